@@ -0,0 +1,22 @@
+#include <stdio.h>
+
+#include <quadrature.h>
+#include <timer.h>
+
+// Prints the position and velocity of the encoder on FTM1 (pins 16 and 17)
+// every 500 ms. The position resets on the index pulse on pin 24.
+int main(void) {
+  int64_t position;
+  int32_t velocity;
+
+  quadrature_reset(0);
+  quadrature_index_reset(0, true);
+
+  while (1) {
+    quadrature_position(0, &position);
+    quadrature_velocity(0, &velocity);
+    printf("Position: %ld, Velocity: %ld counts/s\r\n",
+           (long) position, (long) velocity);
+    delay_ms(500);
+  }
+}
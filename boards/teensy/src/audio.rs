@@ -0,0 +1,268 @@
+//! Lets an app play and record stereo audio over I2S.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! command(AUDIO_DRIVER_NUM, 1, 44100, 16); // 44.1kHz, 16-bit, as master
+//! int block = command(AUDIO_DRIVER_NUM, 6, 0, 0);
+//!
+//! // Playing
+//! allow(AUDIO_DRIVER_NUM, 0, play_ring, 4 * block);
+//! subscribe(AUDIO_DRIVER_NUM, 0, played, NULL);
+//! command(AUDIO_DRIVER_NUM, 2, 0, 0);
+//! // In `played(offset, len, 0, ud)`, refill play_ring[offset..offset+len].
+//!
+//! // Recording
+//! allow(AUDIO_DRIVER_NUM, 1, record_ring, 4 * block);
+//! subscribe(AUDIO_DRIVER_NUM, 1, recorded, NULL);
+//! command(AUDIO_DRIVER_NUM, 4, 0, 0);
+//! // In `recorded(offset, len, 0, ud)`, take record_ring[offset..offset+len].
+//! ```
+//!
+//! Audio moves a block at a time, a block being `BLOCK_FRAMES` frames of
+//! interleaved left and right samples. Samples are 16-bit, or 24 or 32-bit
+//! in 32-bit words, with 24-bit samples in the top three bytes. The rings
+//! are used in whole blocks, from the start when streaming starts. Blocks
+//! must be refilled and taken before the ring wraps around to them again.
+//! Silence is played while there is no playback ring.
+//!
+//! One app at a time may use the interface; it is claimed by configuring
+//! or starting a stream, and can be claimed by another app once both
+//! streams have stopped.
+
+use core::cell::Cell;
+use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
+use mk66::sai::{self, Format, Role, SampleSize, Sai};
+
+pub const DRIVER_NUM: usize = 0x9000C;
+
+const FORMAT_SLAVE: usize = 1 << 8;
+
+#[derive(Default)]
+pub struct App {
+    play_callback: Option<Callback>,
+    record_callback: Option<Callback>,
+    play_buffer: Option<AppSlice<Shared, u8>>,
+    record_buffer: Option<AppSlice<Shared, u8>>,
+}
+
+pub struct Audio {
+    sai: &'static Sai,
+    owner: Cell<Option<AppId>>,
+    // Where the next block goes in each ring.
+    play_offset: Cell<usize>,
+    record_offset: Cell<usize>,
+    apps: Grant<App>,
+}
+
+/// The bytes of a ring used for whole blocks.
+fn ring_len(len: usize, block: usize) -> usize {
+    len / block * block
+}
+
+fn decode_format(sample_rate: usize, format: usize) -> Option<Format> {
+    let sample_size = match format & 0xff {
+        16 => SampleSize::Bits16,
+        24 => SampleSize::Bits24,
+        32 => SampleSize::Bits32,
+        _ => return None,
+    };
+    let role = if format & FORMAT_SLAVE != 0 { Role::Slave } else { Role::Master };
+    Some(Format {
+        sample_rate: sample_rate as u32,
+        sample_size: sample_size,
+        role: role,
+    })
+}
+
+impl Audio {
+    pub fn new(sai: &'static Sai, grant: Grant<App>) -> Audio {
+        Audio {
+            sai: sai,
+            owner: Cell::new(None),
+            play_offset: Cell::new(0),
+            record_offset: Cell::new(0),
+            apps: grant,
+        }
+    }
+
+    /// Makes `appid` the owner, unless another app is streaming.
+    fn claim(&self, appid: AppId) -> bool {
+        let streaming = self.sai.is_playing() || self.sai.is_recording();
+        match self.owner.get() {
+            Some(owner) if streaming && owner != appid => false,
+            _ => {
+                self.owner.set(Some(appid));
+                true
+            }
+        }
+    }
+
+    fn is_owner(&self, appid: AppId) -> bool {
+        self.owner.get().map_or(false, |owner| owner == appid)
+    }
+}
+
+impl sai::Client for Audio {
+    fn play(&self, block: &mut [u8]) {
+        let offset = self.play_offset.get();
+        let filled = self.owner.get().map_or(false, |appid| {
+            self.apps.enter(appid, |app, _| {
+                let start = app.play_buffer.as_ref().and_then(|slice| {
+                    let ring = ring_len(slice.len(), block.len());
+                    if ring == 0 {
+                        return None;
+                    }
+                    let start = offset % ring;
+                    block.copy_from_slice(&slice.as_ref()[start..start + block.len()]);
+                    self.play_offset.set((start + block.len()) % ring);
+                    Some(start)
+                });
+                start.map(|start| {
+                    app.play_callback.map(|mut cb| cb.schedule(start, block.len(), 0));
+                }).is_some()
+            }).unwrap_or(false)
+        });
+        if !filled {
+            for byte in block.iter_mut() {
+                *byte = 0;
+            }
+        }
+    }
+
+    fn recorded(&self, block: &[u8]) {
+        let offset = self.record_offset.get();
+        self.owner.get().map(|appid| {
+            let _ = self.apps.enter(appid, |app, _| {
+                let start = app.record_buffer.as_mut().and_then(|slice| {
+                    let ring = ring_len(slice.len(), block.len());
+                    if ring == 0 {
+                        return None;
+                    }
+                    let start = offset % ring;
+                    slice.as_mut()[start..start + block.len()].copy_from_slice(block);
+                    self.record_offset.set((start + block.len()) % ring);
+                    Some(start)
+                });
+                start.map(|start| {
+                    app.record_callback.map(|mut cb| cb.schedule(start, block.len(), 0));
+                });
+            });
+        });
+    }
+}
+
+impl Driver for Audio {
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: A block of the playback ring was taken and may be refilled.
+    ///        The arguments are its offset and length.
+    /// - `1`: A block of the record ring was filled. The arguments are its
+    ///        offset and length.
+    fn subscribe(&self, subscribe_num: usize, callback: Option<Callback>, app_id: AppId) -> ReturnCode {
+        match subscribe_num {
+            0 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.play_callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            1 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.record_callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Setup rings.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: Playback ring
+    /// - `1`: Record ring
+    fn allow(&self, appid: AppId, allow_num: usize, slice: Option<AppSlice<Shared, u8>>) -> ReturnCode {
+        match allow_num {
+            0 => {
+                self.apps.enter(appid, |app, _| {
+                    app.play_buffer = slice;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            1 => {
+                self.apps.enter(appid, |app, _| {
+                    app.record_buffer = slice;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Control the streams.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Configure `arg1` samples per second, with `arg2` bits per
+    ///        sample (16, 24 or 32) in bits 0-7, and bit 8 set to take the
+    ///        clocks from the codec. Fails while streaming.
+    /// - `2`: Start playing.
+    /// - `3`: Stop playing.
+    /// - `4`: Start recording.
+    /// - `5`: Stop recording.
+    /// - `6`: Return the bytes in a block.
+    fn command(&self, cmd_num: usize, arg1: usize, arg2: usize, appid: AppId) -> ReturnCode {
+        match cmd_num {
+            0 => ReturnCode::SUCCESS,
+            1 => {
+                if !self.claim(appid) {
+                    return ReturnCode::EBUSY;
+                }
+                decode_format(arg1, arg2).map_or(ReturnCode::EINVAL, |format| {
+                    self.sai.configure(format)
+                })
+            },
+            2 => {
+                if !self.claim(appid) {
+                    return ReturnCode::EBUSY;
+                }
+                if !self.sai.is_playing() {
+                    self.play_offset.set(0);
+                }
+                self.sai.start_playback()
+            },
+            3 | 5 => {
+                if !self.is_owner(appid) {
+                    return ReturnCode::EBUSY;
+                }
+                if cmd_num == 3 {
+                    self.sai.stop_playback();
+                } else {
+                    self.sai.stop_recording();
+                }
+                ReturnCode::SUCCESS
+            },
+            4 => {
+                if !self.claim(appid) {
+                    return ReturnCode::EBUSY;
+                }
+                if !self.sai.is_recording() {
+                    self.record_offset.set(0);
+                }
+                self.sai.start_recording()
+            },
+            6 => {
+                self.sai.format().map_or(ReturnCode::EOFF, |format| {
+                    ReturnCode::SuccessWithValue { value: format.block_bytes() }
+                })
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! Lets apps send and receive frames on the CAN bus.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! command(CAN_DRIVER_NUM, 2, 500000, 0); // join the bus at 500kbit/s
+//!
+//! // Sending
+//! subscribe(CAN_DRIVER_NUM, 0, sent, NULL);
+//! allow(CAN_DRIVER_NUM, 0, frame, 16);
+//! command(CAN_DRIVER_NUM, 1, 0, 0);
+//!
+//! // Receiving
+//! allow(CAN_DRIVER_NUM, 1, rx_buffer, 16 * capacity);
+//! subscribe(CAN_DRIVER_NUM, 1, received, NULL);
+//! // In `received(count, 0, 0, ud)`, handle the first `count` frames, then
+//! command(CAN_DRIVER_NUM, 3, count, 0);
+//! ```
+//!
+//! Frames are 16 bytes: the ID as a little-endian word, a flags byte (bit 0
+//! for an extended ID, bit 1 for a remote frame), the data length, two
+//! reserved bytes and eight data bytes. Every app with a receive buffer gets
+//! every frame that passes the acceptance filters. Frames are appended to
+//! the buffer until the app consumes them, and are dropped while it is full.
+//!
+//! Passing 1 as the second argument of command 2 puts the controller in
+//! loopback mode, where sent frames are received back without reaching the
+//! bus.
+
+use core::cell::Cell;
+use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
+use mk66::can::{self, Can, ErrorState, Filter, Frame};
+
+pub const DRIVER_NUM: usize = 0x9000A;
+
+const FRAME_SIZE: usize = 16;
+const FLAG_EXTENDED: u8 = 1 << 0;
+const FLAG_REMOTE: u8 = 1 << 1;
+const FILTER_EXTENDED: usize = 1 << 31;
+
+#[derive(Default)]
+pub struct App {
+    send_callback: Option<Callback>,
+    receive_callback: Option<Callback>,
+    error_callback: Option<Callback>,
+    tx_buffer: Option<AppSlice<Shared, u8>>,
+    rx_buffer: Option<AppSlice<Shared, u8>>,
+    // Bytes of received frames in the receive buffer.
+    rx_len: usize,
+}
+
+pub struct CanBus<'a> {
+    can: &'a Can<'a>,
+    // The app whose frame is being sent.
+    sender: Cell<Option<AppId>>,
+    apps: Grant<App>,
+}
+
+fn encode(frame: &Frame, buf: &mut [u8]) {
+    for i in 0..4 {
+        buf[i] = (frame.id >> (8 * i)) as u8;
+    }
+    buf[4] = if frame.extended { FLAG_EXTENDED } else { 0 } |
+             if frame.remote { FLAG_REMOTE } else { 0 };
+    buf[5] = frame.len;
+    buf[6] = 0;
+    buf[7] = 0;
+    buf[8..16].copy_from_slice(&frame.data);
+}
+
+fn decode(buf: &[u8]) -> Frame {
+    let mut frame = Frame {
+        id: buf[0] as u32 | (buf[1] as u32) << 8 | (buf[2] as u32) << 16 | (buf[3] as u32) << 24,
+        extended: buf[4] & FLAG_EXTENDED != 0,
+        remote: buf[4] & FLAG_REMOTE != 0,
+        len: buf[5],
+        data: [0; 8],
+    };
+    frame.data.copy_from_slice(&buf[8..16]);
+    frame
+}
+
+impl<'a> CanBus<'a> {
+    pub fn new(can: &'a Can<'a>, grant: Grant<App>) -> CanBus<'a> {
+        CanBus {
+            can: can,
+            sender: Cell::new(None),
+            apps: grant,
+        }
+    }
+
+    fn send(&self, app: &mut App, appid: AppId) -> ReturnCode {
+        if self.sender.get().is_some() {
+            return ReturnCode::EBUSY;
+        }
+        let result = app.tx_buffer.as_ref().map_or(ReturnCode::ERESERVE, |slice| {
+            if slice.len() < FRAME_SIZE {
+                return ReturnCode::ESIZE;
+            }
+            self.can.send(&decode(&slice.as_ref()[..FRAME_SIZE]))
+        });
+        if result == ReturnCode::SUCCESS {
+            self.sender.set(Some(appid));
+        }
+        result
+    }
+
+    /// Sets the first free filter, and returns its index.
+    fn add_filter(&self, id: usize, mask: usize) -> ReturnCode {
+        let index = match (0..can::N_FILTERS).find(|&i| self.can.filter(i).is_none()) {
+            Some(index) => index,
+            None => return ReturnCode::ENOMEM,
+        };
+        let filter = Filter {
+            id: (id & !FILTER_EXTENDED) as u32,
+            mask: mask as u32,
+            extended: id & FILTER_EXTENDED != 0,
+        };
+        match self.can.set_filter(index, Some(filter)) {
+            ReturnCode::SUCCESS => ReturnCode::SuccessWithValue { value: index },
+            err => err,
+        }
+    }
+}
+
+impl<'a> can::Client for CanBus<'a> {
+    fn frame_received(&self, frame: &Frame) {
+        self.apps.each(|app| {
+            let rx_len = app.rx_len;
+            let appended = app.rx_buffer.as_mut().map_or(false, |slice| {
+                if rx_len + FRAME_SIZE > slice.len() {
+                    return false;
+                }
+                encode(frame, &mut slice.as_mut()[rx_len..rx_len + FRAME_SIZE]);
+                true
+            });
+            if appended {
+                app.rx_len += FRAME_SIZE;
+                let count = app.rx_len / FRAME_SIZE;
+                app.receive_callback.map(|mut cb| cb.schedule(count, 0, 0));
+            }
+        });
+    }
+
+    fn frame_sent(&self) {
+        self.sender.take().map(|appid| {
+            let _ = self.apps.enter(appid, |app, _| {
+                app.send_callback.map(|mut cb| cb.schedule(0, 0, 0));
+            });
+        });
+    }
+
+    fn error_state_changed(&self, state: ErrorState) {
+        self.apps.each(|app| {
+            app.error_callback.map(|mut cb| cb.schedule(state as usize, 0, 0));
+        });
+    }
+}
+
+impl<'a> Driver for CanBus<'a> {
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: Frame sent
+    /// - `1`: Frames received. The first argument is the number of frames
+    ///        in the receive buffer.
+    /// - `2`: Error state changed. The first argument is 0 for error
+    ///        active, 1 for error passive and 2 for bus off.
+    fn subscribe(&self, subscribe_num: usize, callback: Option<Callback>, app_id: AppId) -> ReturnCode {
+        match subscribe_num {
+            0 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.send_callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            1 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.receive_callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            2 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.error_callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Setup frame buffers.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: Frame to send
+    /// - `1`: Buffer for received frames. Allowing it discards any frames
+    ///        that were not consumed.
+    fn allow(&self, appid: AppId, allow_num: usize, slice: Option<AppSlice<Shared, u8>>) -> ReturnCode {
+        match allow_num {
+            0 => {
+                self.apps.enter(appid, |app, _| {
+                    app.tx_buffer = slice;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            1 => {
+                self.apps.enter(appid, |app, _| {
+                    app.rx_buffer = slice;
+                    app.rx_len = 0;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Use the bus.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Send the frame in the send buffer.
+    /// - `2`: Join the bus at `arg1` bits per second, in loopback mode if
+    ///        `arg2` is 1.
+    /// - `3`: Remove the first `arg1` frames from the receive buffer,
+    ///        moving the rest to its start.
+    /// - `4`: Accept frames whose ID matches `arg1` in the bits set in
+    ///        `arg2`. Bit 31 of `arg1` selects extended IDs. Returns the
+    ///        filter's index.
+    /// - `5`: Remove filter `arg1`. Frames are accepted if they pass any
+    ///        filter, or if there are none.
+    /// - `6`: Return the transmit error counter in bits 0-7, the receive
+    ///        error counter in bits 8-15 and the error state in bits 16-17.
+    /// - `7`: Leave the bus.
+    fn command(&self, cmd_num: usize, arg1: usize, arg2: usize, appid: AppId) -> ReturnCode {
+        match cmd_num {
+            0 => ReturnCode::SUCCESS,
+            1 => {
+                self.apps.enter(appid, |app, _| {
+                    self.send(app, appid)
+                }).unwrap_or_else(|err| err.into())
+            },
+            2 => {
+                self.sender.set(None);
+                self.can.enable(arg1 as u32, arg2 == 1)
+            },
+            3 => {
+                self.apps.enter(appid, |app, _| {
+                    let consumed = arg1 * FRAME_SIZE;
+                    if consumed > app.rx_len {
+                        return ReturnCode::EINVAL;
+                    }
+                    let rx_len = app.rx_len;
+                    app.rx_buffer.as_mut().map(|slice| {
+                        let data = slice.as_mut();
+                        for i in consumed..rx_len {
+                            data[i - consumed] = data[i];
+                        }
+                    });
+                    app.rx_len -= consumed;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            4 => self.add_filter(arg1, arg2),
+            5 => self.can.set_filter(arg1, None),
+            6 => {
+                let (tx_errors, rx_errors) = self.can.error_counters();
+                let value = tx_errors as usize |
+                            (rx_errors as usize) << 8 |
+                            (self.can.error_state() as usize) << 16;
+                ReturnCode::SuccessWithValue { value: value }
+            },
+            7 => {
+                self.sender.set(None);
+                self.can.disable();
+                ReturnCode::SUCCESS
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
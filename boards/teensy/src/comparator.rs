@@ -0,0 +1,188 @@
+//! Lets apps watch analog signals with the comparators.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! // Compare input 3 (pin 36) against the DAC at half of VDD, with the
+//! // lowest hysteresis, unfiltered.
+//! command(COMPARATOR_DRIVER_NUM, 2, 0, 31 | 1 << 8);
+//! command(COMPARATOR_DRIVER_NUM, 1, 0, 3 | 7 << 3);
+//!
+//! subscribe(COMPARATOR_DRIVER_NUM, 0, crossed, NULL);
+//! command(COMPARATOR_DRIVER_NUM, 4, 0, 3); // both edges
+//! // `crossed(comparator, rising, output, ud)` is called on each edge.
+//! ```
+//!
+//! A zero crossing is a comparison against the DAC at the signal's
+//! midpoint with both edges enabled, and a threshold alarm one against the
+//! DAC at the threshold with the rising edge enabled. Comparators are
+//! numbered in the order the board lists them, and are shared: any app may
+//! configure them, and every app is told of the edges it enabled.
+
+use core::cell::Cell;
+use kernel::{AppId, Callback, Driver, Grant, ReturnCode};
+use mk66::cmp::{self, Cmp, Config, DacReference, Edge, Hysteresis, Input};
+
+pub const DRIVER_NUM: usize = 0x9000E;
+
+const EDGE_RISING: u8 = 1 << 0;
+const EDGE_FALLING: u8 = 1 << 1;
+
+const DAC_VDD: usize = 1 << 8;
+
+// Fields of the configuration word.
+const CONFIG_INVERT: usize = 1 << 8;
+const CONFIG_HIGH_SPEED: usize = 1 << 9;
+const CONFIG_WINDOWED: usize = 1 << 10;
+
+#[derive(Default)]
+pub struct App {
+    callback: Option<Callback>,
+    // The edges the app wants from each comparator.
+    edges: [u8; cmp::N_COMPARATORS],
+}
+
+pub struct Comparators<'a> {
+    comparators: &'a [&'a Cmp<'a>],
+    apps: Grant<App>,
+}
+
+/// Bits 0-2 select the plus input and bits 3-5 the minus input, 7 being
+/// the DAC. Bits 6-7 select the hysteresis, bit 8 inverts the output and
+/// bit 9 selects high-speed mode. Bit 10 selects windowed mode. Bits 12-14
+/// are the filter's sample count, 0 for none, and bits 16-23 its period in
+/// bus clock cycles.
+fn decode_config(config: usize) -> Option<Config> {
+    let hysteresis = match (config >> 6) & 0x3 {
+        0 => Hysteresis::Level0,
+        1 => Hysteresis::Level1,
+        2 => Hysteresis::Level2,
+        _ => Hysteresis::Level3,
+    };
+    Some(Config {
+        plus: Input::from_index(config & 0x7)?,
+        minus: Input::from_index((config >> 3) & 0x7)?,
+        hysteresis: hysteresis,
+        invert: config & CONFIG_INVERT != 0,
+        high_speed: config & CONFIG_HIGH_SPEED != 0,
+        filter_count: ((config >> 12) & 0x7) as u8,
+        filter_period: (config >> 16) as u8,
+        windowed: config & CONFIG_WINDOWED != 0,
+    })
+}
+
+impl<'a> Comparators<'a> {
+    pub fn new(comparators: &'a [&'a Cmp<'a>], grant: Grant<App>) -> Comparators<'a> {
+        Comparators {
+            comparators: comparators,
+            apps: grant,
+        }
+    }
+
+    /// Interrupts on every edge some app wants.
+    fn update_interrupts(&self, index: usize) {
+        let edges = Cell::new(0);
+        self.apps.each(|app| edges.set(edges.get() | app.edges[index]));
+        self.comparators[index].enable_interrupts(edges.get() & EDGE_RISING != 0,
+                                                  edges.get() & EDGE_FALLING != 0);
+    }
+}
+
+impl<'a> cmp::Client for Comparators<'a> {
+    fn edge_detected(&self, comparator: usize, edge: Edge) {
+        let index = match self.comparators.iter().position(|c| c.index() == comparator) {
+            Some(index) => index,
+            None => return,
+        };
+        let mask = match edge {
+            Edge::Rising => EDGE_RISING,
+            Edge::Falling => EDGE_FALLING,
+        };
+        let output = self.comparators[index].output();
+        self.apps.each(|app| {
+            if app.edges[index] & mask != 0 {
+                app.callback.map(|mut cb| {
+                    cb.schedule(index, (edge == Edge::Rising) as usize, output as usize)
+                });
+            }
+        });
+    }
+}
+
+impl<'a> Driver for Comparators<'a> {
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: An edge was detected. The arguments are the comparator, 1 for
+    ///        a rising edge or 0 for a falling one, and the output now.
+    fn subscribe(&self, subscribe_num: usize, callback: Option<Callback>, app_id: AppId) -> ReturnCode {
+        match subscribe_num {
+            0 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Use the comparators.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return the number of comparators.
+    /// - `1`: Configure and enable comparator `arg1` as `arg2` describes:
+    ///        see `decode_config`.
+    /// - `2`: Set comparator `arg1`'s DAC to level `arg2` in bits 0-5, of
+    ///        64 steps up to VREF_OUT, or up to VDD if bit 8 is set.
+    /// - `3`: Disable comparator `arg1`'s DAC.
+    /// - `4`: Report rising edges of comparator `arg1` if bit 0 of `arg2`
+    ///        is set, and falling edges if bit 1 is.
+    /// - `5`: Return comparator `arg1`'s output.
+    /// - `6`: Disable comparator `arg1`.
+    fn command(&self, cmd_num: usize, arg1: usize, arg2: usize, appid: AppId) -> ReturnCode {
+        if cmd_num == 0 {
+            return ReturnCode::SuccessWithValue { value: self.comparators.len() };
+        }
+        let comparator = match self.comparators.get(arg1) {
+            Some(comparator) => comparator,
+            None => return ReturnCode::EINVAL,
+        };
+        match cmd_num {
+            1 => decode_config(arg2).map_or(ReturnCode::EINVAL, |config| {
+                comparator.configure(config)
+            }),
+            2 => {
+                let reference = if arg2 & DAC_VDD != 0 {
+                    DacReference::Vdd
+                } else {
+                    DacReference::VrefOut
+                };
+                comparator.set_dac(reference, (arg2 & 0x3F) as u8)
+            },
+            3 => {
+                comparator.disable_dac();
+                ReturnCode::SUCCESS
+            },
+            4 => {
+                let result = self.apps.enter(appid, |app, _| {
+                    app.edges[arg1] = (arg2 & 0x3) as u8;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into());
+                if result == ReturnCode::SUCCESS {
+                    self.update_interrupts(arg1);
+                }
+                result
+            },
+            5 => ReturnCode::SuccessWithValue { value: comparator.output() as usize },
+            6 => {
+                comparator.disable();
+                ReturnCode::SUCCESS
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
@@ -1,28 +1,72 @@
 use mk66;
 use kernel;
-use components::Component;
+use components::{Component, ComponentWithDependency};
 use capsules::alarm::AlarmDriver;
+use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 
-pub struct AlarmComponent;
+type PitMux = MuxAlarm<'static, mk66::pit::Pit<'static>>;
+
+pub struct AlarmMuxComponent;
+
+impl AlarmMuxComponent {
+    pub fn new() -> Self {
+        AlarmMuxComponent {}
+    }
+}
+
+impl Component for AlarmMuxComponent {
+    type Output = &'static PitMux;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        mk66::pit::PIT.init();
+
+        let mux_alarm = static_init!(
+                PitMux,
+                MuxAlarm::new(&mk66::pit::PIT)
+            );
+        mk66::pit::PIT.set_client(mux_alarm);
+        Some(mux_alarm)
+    }
+}
+
+pub struct AlarmComponent {
+    mux: Option<&'static PitMux>
+}
 
 impl AlarmComponent {
     pub fn new() -> Self {
-        AlarmComponent {}
+        AlarmComponent {
+            mux: None
+        }
     }
 }
 
 impl Component for AlarmComponent {
-    type Output = &'static AlarmDriver<'static, mk66::pit::Pit<'static>>;
+    type Output = &'static AlarmDriver<'static, VirtualMuxAlarm<'static, mk66::pit::Pit<'static>>>;
 
     unsafe fn finalize(&mut self) -> Option<Self::Output> {
-        mk66::pit::PIT.init();
+        if self.mux.is_none() {
+            return None;
+        }
 
+        let virtual_alarm = static_init!(
+                VirtualMuxAlarm<'static, mk66::pit::Pit<'static>>,
+                VirtualMuxAlarm::new(self.mux.unwrap())
+            );
         let alarm = static_init!(
-                AlarmDriver<'static, mk66::pit::Pit>,
-                AlarmDriver::new(&mk66::pit::PIT,
+                AlarmDriver<'static, VirtualMuxAlarm<'static, mk66::pit::Pit<'static>>>,
+                AlarmDriver::new(virtual_alarm,
                                  kernel::Grant::create())
             );
-        mk66::pit::PIT.set_client(alarm);
+        virtual_alarm.set_client(alarm);
         Some(alarm)
     }
 }
+
+impl ComponentWithDependency<&'static PitMux> for AlarmComponent {
+    fn dependency(&mut self, mux: &'static PitMux) -> &mut Self {
+        self.mux = Some(mux);
+
+        self
+    }
+}
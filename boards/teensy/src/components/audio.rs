@@ -0,0 +1,29 @@
+use mk66;
+use kernel;
+use audio::Audio;
+use components::Component;
+
+pub struct AudioComponent;
+
+impl AudioComponent {
+    pub fn new() -> Self {
+        AudioComponent {}
+    }
+}
+
+impl Component for AudioComponent {
+    type Output = &'static Audio;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        let audio = static_init!(
+                Audio,
+                Audio::new(&mk66::sai::SAI0, kernel::Grant::create())
+            );
+        // Recording comes in on RXD1, pin 38, where the audio shield's data out
+        // has to be wired.
+        mk66::sai::SAI0.set_receive_line(1);
+        mk66::sai::SAI0.set_client(audio);
+
+        Some(audio)
+    }
+}
@@ -0,0 +1,26 @@
+use mk66;
+use kernel;
+use can::CanBus;
+use components::Component;
+
+pub struct CanComponent;
+
+impl CanComponent {
+    pub fn new() -> Self {
+        CanComponent {}
+    }
+}
+
+impl Component for CanComponent {
+    type Output = &'static CanBus<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        let can = static_init!(
+                CanBus<'static>,
+                CanBus::new(&mk66::can::CAN0, kernel::Grant::create())
+            );
+        mk66::can::CAN0.set_client(can);
+
+        Some(can)
+    }
+}
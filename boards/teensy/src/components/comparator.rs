@@ -0,0 +1,36 @@
+use mk66;
+use kernel;
+use mk66::cmp::Cmp;
+use comparator::Comparators;
+use components::Component;
+
+pub struct ComparatorComponent;
+
+impl ComparatorComponent {
+    pub fn new() -> Self {
+        ComparatorComponent {}
+    }
+}
+
+impl Component for ComparatorComponent {
+    type Output = &'static Comparators<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        // CMP0, with input 3 on pin 36. The other comparators' pin inputs
+        // are claimed by SPI0, I2S0 and CAN0, but their internal inputs
+        // remain.
+        let comparators = static_init!(
+                [&'static Cmp<'static>; 1],
+                [&mk66::cmp::CMP0]
+            );
+        let driver = static_init!(
+                Comparators<'static>,
+                Comparators::new(comparators, kernel::Grant::create())
+            );
+        for comparator in comparators.iter() {
+            comparator.set_client(driver);
+        }
+
+        Some(driver)
+    }
+}
@@ -0,0 +1,25 @@
+use mk66;
+use kernel;
+use crc::Crc;
+use components::Component;
+
+pub struct CrcComponent;
+
+impl CrcComponent {
+    pub fn new() -> Self {
+        CrcComponent {}
+    }
+}
+
+impl Component for CrcComponent {
+    type Output = &'static Crc<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        let crc = static_init!(
+                Crc<'static>,
+                Crc::new(&mk66::crc::CRC, kernel::Grant::create())
+            );
+
+        Some(crc)
+    }
+}
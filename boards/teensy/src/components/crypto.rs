@@ -0,0 +1,24 @@
+use kernel;
+use crypto::Crypto;
+use components::Component;
+
+pub struct CryptoComponent;
+
+impl CryptoComponent {
+    pub fn new() -> Self {
+        CryptoComponent {}
+    }
+}
+
+impl Component for CryptoComponent {
+    type Output = &'static Crypto;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        let crypto = static_init!(
+                Crypto,
+                Crypto::new(kernel::Grant::create())
+            );
+
+        Some(crypto)
+    }
+}
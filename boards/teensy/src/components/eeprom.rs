@@ -0,0 +1,27 @@
+use mk66;
+use eeprom::EepromDriver;
+use components::Component;
+
+pub struct EepromComponent;
+
+impl EepromComponent {
+    pub fn new() -> Self {
+        EepromComponent {}
+    }
+}
+
+impl Component for EepromComponent {
+    type Output = &'static EepromDriver<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        // Apps see the EEPROM as absent if this fails.
+        mk66::eeprom::EEPROM.init();
+
+        let eeprom = static_init!(
+                EepromDriver<'static>,
+                EepromDriver::new(&mk66::eeprom::EEPROM)
+            );
+
+        Some(eeprom)
+    }
+}
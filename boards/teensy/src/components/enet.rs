@@ -0,0 +1,66 @@
+use mk66;
+use kernel::ReturnCode;
+use kernel::hil::gpio::InputMode;
+use mk66::enet::Enet;
+use mk66::gpio::*;
+use mk66::gpio::functions::*;
+use components::Component;
+
+/// The PHY's address on the MDIO bus, set by its strapping pins.
+const PHY_ADDRESS: u8 = 0;
+
+/// The PHY supplies the 50MHz RMII reference clock on pin 24.
+const TIMER_HZ: u32 = 50_000_000;
+
+/// Brings up the Ethernet MAC on the RMII pads. These share pins with CAN0,
+/// SPI0's clock and the FTM1 quadrature decoder, so a board using Ethernet
+/// gives those up.
+pub struct EnetComponent;
+
+impl EnetComponent {
+    pub fn new() -> Self {
+        EnetComponent {}
+    }
+}
+
+impl Component for EnetComponent {
+    type Output = &'static Enet<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        PA05.release_claim();
+        PA12.release_claim();
+        PA13.release_claim();
+        PA14.release_claim();
+        PA15.release_claim();
+        PA16.release_claim();
+        PA17.release_claim();
+        PB00.release_claim();
+        PB01.release_claim();
+        PE26.release_claim();
+        PA05.claim_as(RMII0_RXER);
+        PA12.claim_as(RMII0_RXD1);
+        PA13.claim_as(RMII0_RXD0);
+        PA14.claim_as(RMII0_CRS_DV);
+        PA15.claim_as(RMII0_TXEN);
+        PA16.claim_as(RMII0_TXD0);
+        PA17.claim_as(RMII0_TXD1);
+        PB00.claim_as(RMII0_MDIO);
+        PB01.claim_as(RMII0_MDC);
+        PE26.claim_as(ENET_1588_CLKIN);
+        PB00.set_input_mode(InputMode::PullUp);
+
+        mk66::sim::select_enet_clocks_1588_clkin();
+
+        // A locally administered unicast address, from the chip's unique ID.
+        let id = mk66::sim::unique_id();
+        let mac = [0x02,
+                   (id[2] >> 8) as u8, id[2] as u8,
+                   (id[3] >> 16) as u8, (id[3] >> 8) as u8, id[3] as u8];
+
+        if mk66::enet::ENET.enable(mac, PHY_ADDRESS, TIMER_HZ) != ReturnCode::SUCCESS {
+            return None;
+        }
+
+        Some(&mk66::enet::ENET)
+    }
+}
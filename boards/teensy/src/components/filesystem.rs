@@ -0,0 +1,49 @@
+use mk66;
+use kernel;
+use filesystem::Filesystem;
+use sdcard::SdCard;
+use components::{Component, ComponentWithDependency};
+
+pub struct FilesystemComponent {
+    sdcard: Option<&'static SdCard<'static>>,
+}
+
+impl FilesystemComponent {
+    pub fn new() -> Self {
+        FilesystemComponent {
+            sdcard: None,
+        }
+    }
+}
+
+impl Component for FilesystemComponent {
+    type Output = &'static Filesystem<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        if self.sdcard.is_none() {
+            return None;
+        }
+
+        let filesystem = static_init!(
+                Filesystem<'static>,
+                Filesystem::new(&mk66::sdhc::SDHC, kernel::Grant::create())
+            );
+
+        // The filesystem sees card removals and USB host mounts first, then
+        // passes them on. It is told of raw writes to the card.
+        filesystem.set_client(self.sdcard.unwrap());
+        filesystem.set_msc_client(self.sdcard.unwrap());
+        self.sdcard.unwrap().set_write_client(filesystem);
+        mk66::sdhc::SDHC.set_client(filesystem);
+
+        Some(filesystem)
+    }
+}
+
+impl ComponentWithDependency<&'static SdCard<'static>> for FilesystemComponent {
+    fn dependency(&mut self, sdcard: &'static SdCard<'static>) -> &mut Self {
+        self.sdcard = Some(sdcard);
+
+        self
+    }
+}
@@ -0,0 +1,42 @@
+use kernel;
+use hid::UsbHid;
+use usb::hid::Hid;
+use components::{Component, ComponentWithDependency};
+
+pub struct HidComponent {
+    hid: Option<&'static Hid<'static>>,
+}
+
+impl HidComponent {
+    pub fn new() -> Self {
+        HidComponent {
+            hid: None,
+        }
+    }
+}
+
+impl Component for HidComponent {
+    type Output = &'static UsbHid<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        if self.hid.is_none() {
+            return None;
+        }
+
+        let usb_hid = static_init!(
+                UsbHid<'static>,
+                UsbHid::new(self.hid.unwrap(), kernel::Grant::create())
+            );
+        self.hid.unwrap().set_client(usb_hid);
+
+        Some(usb_hid)
+    }
+}
+
+impl ComponentWithDependency<&'static Hid<'static>> for HidComponent {
+    fn dependency(&mut self, hid: &'static Hid<'static>) -> &mut Self {
+        self.hid = Some(hid);
+
+        self
+    }
+}
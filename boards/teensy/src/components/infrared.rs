@@ -0,0 +1,26 @@
+use mk66;
+use kernel;
+use infrared::Infrared;
+use components::Component;
+
+pub struct InfraredComponent;
+
+impl InfraredComponent {
+    pub fn new() -> Self {
+        InfraredComponent {}
+    }
+}
+
+impl Component for InfraredComponent {
+    type Output = &'static Infrared<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        let infrared = static_init!(
+                Infrared<'static>,
+                Infrared::new(&mk66::cmt::CMT, kernel::Grant::create())
+            );
+        mk66::cmt::CMT.set_client(infrared);
+
+        Some(infrared)
+    }
+}
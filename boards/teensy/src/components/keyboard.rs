@@ -0,0 +1,42 @@
+use kernel;
+use keyboard::UsbKeyboard;
+use usbhost::keyboard::Keyboard;
+use components::{Component, ComponentWithDependency};
+
+pub struct KeyboardComponent {
+    keyboard: Option<&'static Keyboard<'static>>,
+}
+
+impl KeyboardComponent {
+    pub fn new() -> Self {
+        KeyboardComponent {
+            keyboard: None,
+        }
+    }
+}
+
+impl Component for KeyboardComponent {
+    type Output = &'static UsbKeyboard<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        if self.keyboard.is_none() {
+            return None;
+        }
+
+        let usb_keyboard = static_init!(
+                UsbKeyboard<'static>,
+                UsbKeyboard::new(self.keyboard.unwrap(), kernel::Grant::create())
+            );
+        self.keyboard.unwrap().set_client(usb_keyboard);
+
+        Some(usb_keyboard)
+    }
+}
+
+impl ComponentWithDependency<&'static Keyboard<'static>> for KeyboardComponent {
+    fn dependency(&mut self, keyboard: &'static Keyboard<'static>) -> &mut Self {
+        self.keyboard = Some(keyboard);
+
+        self
+    }
+}
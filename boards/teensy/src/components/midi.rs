@@ -0,0 +1,42 @@
+use kernel;
+use midi::UsbMidi;
+use usb::midi::Midi;
+use components::{Component, ComponentWithDependency};
+
+pub struct MidiComponent {
+    midi: Option<&'static Midi<'static>>,
+}
+
+impl MidiComponent {
+    pub fn new() -> Self {
+        MidiComponent {
+            midi: None,
+        }
+    }
+}
+
+impl Component for MidiComponent {
+    type Output = &'static UsbMidi<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        if self.midi.is_none() {
+            return None;
+        }
+
+        let usb_midi = static_init!(
+                UsbMidi<'static>,
+                UsbMidi::new(self.midi.unwrap(), kernel::Grant::create())
+            );
+        self.midi.unwrap().set_client(usb_midi);
+
+        Some(usb_midi)
+    }
+}
+
+impl ComponentWithDependency<&'static Midi<'static>> for MidiComponent {
+    fn dependency(&mut self, midi: &'static Midi<'static>) -> &mut Self {
+        self.midi = Some(midi);
+
+        self
+    }
+}
@@ -15,11 +15,57 @@ mod alarm;
 mod console;
 mod xconsole;
 mod rnga;
+mod quadrature;
+mod uptime;
+mod rtc;
+mod nonvolatile_storage;
+mod eeprom;
+mod sdcard;
+mod filesystem;
+mod usb;
+mod hid;
+mod midi;
+mod usbhost;
+mod keyboard;
+mod usbstorage;
+mod can;
+mod enet;
+mod net;
+mod audio;
+mod touch;
+mod comparator;
+mod infrared;
+mod crc;
+mod crypto;
 
 pub use self::gpio::GpioComponent;
 pub use self::led::LedComponent;
 pub use self::spi::VirtualSpiComponent;
-pub use self::alarm::AlarmComponent;
+pub use self::alarm::{AlarmMuxComponent, AlarmComponent};
 pub use self::console::UartConsoleComponent;
-pub use self::xconsole::XConsoleComponent;
+pub use self::xconsole::{XConsoleComponent, UsbConsoleComponent};
 pub use self::rnga::RngaComponent;
+pub use self::quadrature::QuadratureComponent;
+pub use self::uptime::UptimeComponent;
+pub use self::rtc::RtcComponent;
+pub use self::nonvolatile_storage::NonvolatileStorageComponent;
+pub use self::eeprom::EepromComponent;
+pub use self::sdcard::SdCardComponent;
+pub use self::filesystem::FilesystemComponent;
+pub use self::usb::{UsbSerialComponent, UsbHidComponent, UsbMidiComponent, UsbMscComponent,
+                     UsbDeviceComponent};
+pub use self::hid::HidComponent;
+pub use self::midi::MidiComponent;
+pub use self::usbhost::{HostHubComponent, HostKeyboardComponent, HostStorageComponent,
+                         UsbHostComponent};
+pub use self::keyboard::KeyboardComponent;
+pub use self::usbstorage::UsbStorageComponent;
+pub use self::can::CanComponent;
+pub use self::enet::EnetComponent;
+pub use self::net::{SlipComponent, EthernetLinkComponent, Ipv4Component, UdpComponent};
+pub use self::audio::AudioComponent;
+pub use self::touch::TouchComponent;
+pub use self::comparator::ComparatorComponent;
+pub use self::infrared::InfraredComponent;
+pub use self::crc::CrcComponent;
+pub use self::crypto::CryptoComponent;
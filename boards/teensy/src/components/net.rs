@@ -0,0 +1,192 @@
+use mk66;
+use kernel;
+use kernel::hil::uart::UART;
+use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use mk66::enet::Enet;
+use net::{Config, Link};
+use net::ethernet::{self, EthernetLink};
+use net::ipv4::{self, Ipv4Stack};
+use net::slip::{self, SlipLink};
+use udp::Udp;
+use components::{Component, ComponentWithDependency};
+
+type PitMux = MuxAlarm<'static, mk66::pit::Pit<'static>>;
+type NetAlarm = VirtualMuxAlarm<'static, mk66::pit::Pit<'static>>;
+pub type Stack = Ipv4Stack<'static, NetAlarm>;
+
+const SLIP_BAUD_RATE: u32 = 115200;
+
+/// SLIP links are point to point, so the host end is the router.
+const SLIP_CONFIG: Config = Config {
+    address: [192, 168, 7, 2],
+    netmask: [255, 255, 255, 0],
+    gateway: Some([192, 168, 7, 1]),
+};
+
+pub struct SlipComponent;
+
+impl SlipComponent {
+    pub fn new() -> Self {
+        SlipComponent {}
+    }
+}
+
+impl Component for SlipComponent {
+    type Output = &'static SlipLink<'static, mk66::uart::Uart>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        let link = static_init!(
+                SlipLink<'static, mk66::uart::Uart>,
+                SlipLink::new(&mk66::uart::UART2,
+                              &mut slip::TX_BUF,
+                              &mut slip::RX_BUF,
+                              &mut slip::BYTE_BUF)
+            );
+        mk66::uart::UART2.set_client(link);
+        link.initialize(SLIP_BAUD_RATE);
+
+        Some(link)
+    }
+}
+
+pub struct EthernetLinkComponent {
+    enet: Option<&'static Enet<'static>>,
+}
+
+impl EthernetLinkComponent {
+    pub fn new() -> Self {
+        EthernetLinkComponent {
+            enet: None,
+        }
+    }
+}
+
+impl Component for EthernetLinkComponent {
+    type Output = &'static EthernetLink<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        if self.enet.is_none() {
+            return None;
+        }
+
+        let enet = self.enet.unwrap();
+        let link = static_init!(
+                EthernetLink<'static>,
+                EthernetLink::new(enet,
+                                  &mut ethernet::FRAME_BUF,
+                                  &mut ethernet::PENDING_BUF)
+            );
+        enet.set_client(link);
+
+        Some(link)
+    }
+}
+
+impl ComponentWithDependency<&'static Enet<'static>> for EthernetLinkComponent {
+    fn dependency(&mut self, enet: &'static Enet<'static>) -> &mut Self {
+        self.enet = Some(enet);
+
+        self
+    }
+}
+
+/// Runs the stack over a link. Links with a hardware address are configured
+/// by DHCP, and SLIP links get `SLIP_CONFIG`.
+pub struct Ipv4Component {
+    mux: Option<&'static PitMux>,
+    link: Option<&'static Link<'static>>,
+}
+
+impl Ipv4Component {
+    pub fn new() -> Self {
+        Ipv4Component {
+            mux: None,
+            link: None,
+        }
+    }
+}
+
+impl Component for Ipv4Component {
+    type Output = &'static Stack;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        if self.mux.is_none() || self.link.is_none() {
+            return None;
+        }
+
+        let link = self.link.unwrap();
+        let fixed = match link.hardware_address() {
+            Some(_) => None,
+            None => Some(SLIP_CONFIG),
+        };
+        let alarm = static_init!(
+                NetAlarm,
+                VirtualMuxAlarm::new(self.mux.unwrap())
+            );
+        let stack = static_init!(
+                Stack,
+                Ipv4Stack::new(link, alarm, fixed, &mut ipv4::BUF)
+            );
+        alarm.set_client(stack);
+        link.set_client(stack);
+
+        Some(stack)
+    }
+}
+
+impl ComponentWithDependency<&'static PitMux> for Ipv4Component {
+    fn dependency(&mut self, mux: &'static PitMux) -> &mut Self {
+        self.mux = Some(mux);
+
+        self
+    }
+}
+
+impl ComponentWithDependency<&'static Link<'static>> for Ipv4Component {
+    fn dependency(&mut self, link: &'static Link<'static>) -> &mut Self {
+        self.link = Some(link);
+
+        self
+    }
+}
+
+pub struct UdpComponent {
+    stack: Option<&'static Stack>,
+}
+
+impl UdpComponent {
+    pub fn new() -> Self {
+        UdpComponent {
+            stack: None,
+        }
+    }
+}
+
+impl Component for UdpComponent {
+    type Output = &'static Udp<'static, NetAlarm>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        if self.stack.is_none() {
+            return None;
+        }
+
+        let stack = self.stack.unwrap();
+        let udp = static_init!(
+                Udp<'static, NetAlarm>,
+                Udp::new(stack, kernel::Grant::create())
+            );
+        stack.set_client(udp);
+        // Start once the client is set, so that it hears of the address.
+        stack.start();
+
+        Some(udp)
+    }
+}
+
+impl ComponentWithDependency<&'static Stack> for UdpComponent {
+    fn dependency(&mut self, stack: &'static Stack) -> &mut Self {
+        self.stack = Some(stack);
+
+        self
+    }
+}
@@ -0,0 +1,52 @@
+use mk66;
+use kernel;
+use kernel::hil;
+use capsules::nonvolatile_to_pages::NonvolatileToPages;
+use capsules::nonvolatile_storage_driver::{self, NonvolatileStorage};
+use components::Component;
+
+/// The flash region reserved for storage in `chip_layout.ld`.
+const STORAGE_START: usize = 0x000F_0000;
+const STORAGE_LENGTH: usize = 0x0001_0000;
+
+/// The first half of the region is shared by all apps, and the second half
+/// is kept for the kernel.
+const USER_LENGTH: usize = STORAGE_LENGTH / 2;
+
+static mut PAGE_BUFFER: mk66::flash::Sector = mk66::flash::Sector::new();
+
+pub struct NonvolatileStorageComponent;
+
+impl NonvolatileStorageComponent {
+    pub fn new() -> Self {
+        NonvolatileStorageComponent {}
+    }
+}
+
+impl Component for NonvolatileStorageComponent {
+    type Output = &'static NonvolatileStorage<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        mk66::flash::FLASH.init();
+
+        let nv_to_page = static_init!(
+                NonvolatileToPages<'static, mk66::flash::Flash<'static>>,
+                NonvolatileToPages::new(&mut mk66::flash::FLASH, &mut PAGE_BUFFER)
+            );
+        hil::flash::HasClient::set_client(&mk66::flash::FLASH, nv_to_page);
+
+        let nonvolatile_storage = static_init!(
+                NonvolatileStorage<'static>,
+                NonvolatileStorage::new(nv_to_page,
+                                        kernel::Grant::create(),
+                                        STORAGE_START,
+                                        USER_LENGTH,
+                                        STORAGE_START + USER_LENGTH,
+                                        STORAGE_LENGTH - USER_LENGTH,
+                                        &mut nonvolatile_storage_driver::BUFFER)
+            );
+        hil::nonvolatile_storage::NonvolatileStorage::set_client(nv_to_page, nonvolatile_storage);
+
+        Some(nonvolatile_storage)
+    }
+}
@@ -0,0 +1,59 @@
+use mk66;
+use kernel;
+use quadrature::Quadrature;
+use mk66::ftm::{Ftm, QuadratureMode};
+use components::{Component, ComponentWithDependency};
+
+type PinHandle = &'static mk66::gpio::Gpio<'static>;
+
+pub struct QuadratureComponent {
+    index_pins: Option<&'static [PinHandle]>
+}
+
+impl QuadratureComponent {
+    pub fn new() -> Self {
+        QuadratureComponent {
+            index_pins: None
+        }
+    }
+}
+
+impl Component for QuadratureComponent {
+    type Output = &'static Quadrature<'static, mk66::pit::Pit<'static>>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        let decoders = static_init!(
+                [&'static Ftm<'static>; 2],
+                [&mk66::ftm::FTM1, &mk66::ftm::FTM2]
+            );
+
+        for decoder in decoders.iter() {
+            decoder.enable_quadrature(QuadratureMode::PhaseAB, 2);
+        }
+
+        // Index pins are optional, and are matched to decoders in order.
+        if let Some(pins) = self.index_pins {
+            for (decoder, pin) in decoders.iter().zip(pins.iter()) {
+                pin.set_client(*decoder);
+                decoder.set_index_pin(*pin);
+            }
+        }
+
+        let quadrature = static_init!(
+                Quadrature<'static, mk66::pit::Pit<'static>>,
+                Quadrature::new(decoders,
+                                &mk66::pit::PIT,
+                                kernel::Grant::create())
+            );
+
+        Some(quadrature)
+    }
+}
+
+impl ComponentWithDependency<&'static [PinHandle]> for QuadratureComponent {
+    fn dependency(&mut self, index_pins: &'static [PinHandle]) -> &mut Self {
+        self.index_pins = Some(index_pins);
+
+        self
+    }
+}
@@ -0,0 +1,28 @@
+use mk66;
+use kernel;
+use rtc::RtcDriver;
+use components::Component;
+
+pub struct RtcComponent;
+
+impl RtcComponent {
+    pub fn new() -> Self {
+        RtcComponent {}
+    }
+}
+
+impl Component for RtcComponent {
+    type Output = &'static RtcDriver<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        mk66::rtc::RTC.init();
+
+        let rtc = static_init!(
+                RtcDriver<'static>,
+                RtcDriver::new(&mk66::rtc::RTC, kernel::Grant::create())
+            );
+        mk66::rtc::RTC.set_client(rtc);
+
+        Some(rtc)
+    }
+}
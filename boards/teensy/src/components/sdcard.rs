@@ -0,0 +1,32 @@
+use mk66;
+use kernel;
+use sdcard::{self, SdCard};
+use components::Component;
+
+pub struct SdCardComponent;
+
+impl SdCardComponent {
+    pub fn new() -> Self {
+        SdCardComponent {}
+    }
+}
+
+impl Component for SdCardComponent {
+    type Output = &'static SdCard<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        if mk66::sdhc::SDHC.init() != kernel::ReturnCode::SUCCESS {
+            return None;
+        }
+
+        let sdcard = static_init!(
+                SdCard<'static>,
+                SdCard::new(&mk66::sdhc::SDHC,
+                            &mut sdcard::BUFFER,
+                            kernel::Grant::create())
+            );
+        mk66::sdhc::SDHC.set_client(sdcard);
+
+        Some(sdcard)
+    }
+}
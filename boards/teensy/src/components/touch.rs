@@ -0,0 +1,60 @@
+use mk66;
+use kernel;
+use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use touch::TouchButtons;
+use components::{Component, ComponentWithDependency};
+
+type PitMux = MuxAlarm<'static, mk66::pit::Pit<'static>>;
+type TouchAlarm = VirtualMuxAlarm<'static, mk66::pit::Pit<'static>>;
+
+/// The TSI channel of each button: pin 15. The other touch pins are
+/// claimed by UART0, the quadrature decoders, I2C0 and I2S0.
+const BUTTON_CHANNELS: [usize; 1] = [13];
+
+pub struct TouchComponent {
+    mux: Option<&'static PitMux>,
+}
+
+impl TouchComponent {
+    pub fn new() -> Self {
+        TouchComponent {
+            mux: None,
+        }
+    }
+}
+
+impl Component for TouchComponent {
+    type Output = &'static TouchButtons<'static, TouchAlarm>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        if self.mux.is_none() {
+            return None;
+        }
+
+        let alarm = static_init!(
+                TouchAlarm,
+                VirtualMuxAlarm::new(self.mux.unwrap())
+            );
+        let channels = static_init!([usize; 1], BUTTON_CHANNELS);
+        let touch = static_init!(
+                TouchButtons<'static, TouchAlarm>,
+                TouchButtons::new(&mk66::tsi::TSI,
+                                  alarm,
+                                  channels,
+                                  kernel::Grant::create())
+            );
+        alarm.set_client(touch);
+        mk66::tsi::TSI.set_client(touch);
+        touch.start();
+
+        Some(touch)
+    }
+}
+
+impl ComponentWithDependency<&'static PitMux> for TouchComponent {
+    fn dependency(&mut self, mux: &'static PitMux) -> &mut Self {
+        self.mux = Some(mux);
+
+        self
+    }
+}
@@ -0,0 +1,25 @@
+use mk66;
+use kernel;
+use uptime::UptimeDriver;
+use components::Component;
+
+pub struct UptimeComponent;
+
+impl UptimeComponent {
+    pub fn new() -> Self {
+        UptimeComponent {}
+    }
+}
+
+impl Component for UptimeComponent {
+    type Output = &'static UptimeDriver<'static, mk66::pit::Pit<'static>>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        let uptime = static_init!(
+                UptimeDriver<'static, mk66::pit::Pit<'static>>,
+                UptimeDriver::new(&mk66::pit::PIT, kernel::Grant::create())
+            );
+
+        Some(uptime)
+    }
+}
@@ -0,0 +1,165 @@
+use mk66;
+use kernel::hil::usb::Client;
+use io;
+use usb::{self, cdc, device, hid, msc};
+use usb::cdc::CdcAcm;
+use usb::device::{Function, UsbDevice};
+use usb::hid::Hid;
+use usb::midi::Midi;
+use usb::msc::Msc;
+use filesystem::Filesystem;
+use components::{Component, ComponentWithDependency};
+
+type Functions = &'static [&'static Function<'static>];
+
+pub struct UsbSerialComponent;
+
+impl UsbSerialComponent {
+    pub fn new() -> Self {
+        UsbSerialComponent {}
+    }
+}
+
+impl Component for UsbSerialComponent {
+    type Output = &'static CdcAcm<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        // Interfaces 0 and 1, notifications on endpoint 1, data on 2 and 3.
+        let serial = static_init!(
+                CdcAcm<'static>,
+                CdcAcm::new(&mk66::usb::USB0, 0, 1, 2, 3, &mut cdc::LOG_BUF)
+            );
+        io::USB_SERIAL = Some(serial);
+
+        Some(serial)
+    }
+}
+
+pub struct UsbHidComponent;
+
+impl UsbHidComponent {
+    pub fn new() -> Self {
+        UsbHidComponent {}
+    }
+}
+
+impl Component for UsbHidComponent {
+    type Output = &'static Hid<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        // Interface 2 on endpoint 4, after the serial port.
+        let hid = static_init!(
+                Hid<'static>,
+                Hid::new(&mk66::usb::USB0, 2, 4, &hid::KEYBOARD_MOUSE_GAMEPAD)
+            );
+
+        Some(hid)
+    }
+}
+
+pub struct UsbMidiComponent;
+
+impl UsbMidiComponent {
+    pub fn new() -> Self {
+        UsbMidiComponent {}
+    }
+}
+
+impl Component for UsbMidiComponent {
+    type Output = &'static Midi<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        // Interfaces 3 and 4, with four cables on endpoints 5 and 6.
+        let midi = static_init!(
+                Midi<'static>,
+                Midi::new(&mk66::usb::USB0, 3, 5, 6, 4)
+            );
+
+        Some(midi)
+    }
+}
+
+pub struct UsbMscComponent {
+    filesystem: Option<&'static Filesystem<'static>>,
+}
+
+impl UsbMscComponent {
+    pub fn new() -> Self {
+        UsbMscComponent {
+            filesystem: None,
+        }
+    }
+}
+
+impl Component for UsbMscComponent {
+    type Output = &'static Msc<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        if self.filesystem.is_none() {
+            return None;
+        }
+
+        // Interface 5 on endpoints 7 and 8.
+        let msc = static_init!(
+                Msc<'static>,
+                Msc::new(&mk66::usb::USB0,
+                         &mk66::sdhc::SDHC,
+                         5, 7, 8,
+                         &mut msc::SECTOR_BUF)
+            );
+        msc.set_client(self.filesystem.unwrap());
+
+        Some(msc)
+    }
+}
+
+impl ComponentWithDependency<&'static Filesystem<'static>> for UsbMscComponent {
+    fn dependency(&mut self, filesystem: &'static Filesystem<'static>) -> &mut Self {
+        self.filesystem = Some(filesystem);
+
+        self
+    }
+}
+
+pub struct UsbDeviceComponent {
+    functions: Option<Functions>,
+}
+
+impl UsbDeviceComponent {
+    pub fn new() -> Self {
+        UsbDeviceComponent {
+            functions: None,
+        }
+    }
+}
+
+impl Component for UsbDeviceComponent {
+    type Output = &'static UsbDevice<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        if self.functions.is_none() {
+            return None;
+        }
+
+        let device = static_init!(
+                UsbDevice<'static>,
+                UsbDevice::new(&mk66::usb::USB0,
+                               &usb::SERIAL,
+                               self.functions.unwrap(),
+                               &mut device::CONFIGURATION_BUF)
+            );
+        mk66::usb::USB0.set_client(device);
+        device.enable();
+        device.attach();
+
+        Some(device)
+    }
+}
+
+impl ComponentWithDependency<Functions> for UsbDeviceComponent {
+    fn dependency(&mut self, functions: Functions) -> &mut Self {
+        self.functions = Some(functions);
+
+        self
+    }
+}
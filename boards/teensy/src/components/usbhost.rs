@@ -0,0 +1,183 @@
+use mk66;
+use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use usbhost::{self, ClassDriver, HostAlarm, UsbHost};
+use usbhost::hub::{self, Hub};
+use usbhost::keyboard::{self, Keyboard};
+use usbhost::storage::{self, Storage};
+use components::{Component, ComponentWithDependency};
+
+type PitMux = MuxAlarm<'static, mk66::pit::Pit<'static>>;
+type Drivers = &'static [&'static ClassDriver<'static>];
+
+pub struct HostHubComponent {
+    mux: Option<&'static PitMux>,
+}
+
+impl HostHubComponent {
+    pub fn new() -> Self {
+        HostHubComponent {
+            mux: None,
+        }
+    }
+}
+
+impl Component for HostHubComponent {
+    type Output = &'static Hub<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        if self.mux.is_none() {
+            return None;
+        }
+
+        let alarm = static_init!(
+                HostAlarm,
+                VirtualMuxAlarm::new(self.mux.unwrap())
+            );
+        let hub = static_init!(
+                Hub<'static>,
+                Hub::new(&mk66::usbhs::USBHS,
+                         alarm,
+                         &mut hub::BUFFER)
+            );
+        alarm.set_client(hub);
+
+        Some(hub)
+    }
+}
+
+impl ComponentWithDependency<&'static PitMux> for HostHubComponent {
+    fn dependency(&mut self, mux: &'static PitMux) -> &mut Self {
+        self.mux = Some(mux);
+
+        self
+    }
+}
+
+pub struct HostKeyboardComponent;
+
+impl HostKeyboardComponent {
+    pub fn new() -> Self {
+        HostKeyboardComponent {}
+    }
+}
+
+impl Component for HostKeyboardComponent {
+    type Output = &'static Keyboard<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        let keyboard = static_init!(
+                Keyboard<'static>,
+                Keyboard::new(&mk66::usbhs::USBHS,
+                              &mut keyboard::BUFFER)
+            );
+
+        Some(keyboard)
+    }
+}
+
+pub struct HostStorageComponent {
+    mux: Option<&'static PitMux>,
+}
+
+impl HostStorageComponent {
+    pub fn new() -> Self {
+        HostStorageComponent {
+            mux: None,
+        }
+    }
+}
+
+impl Component for HostStorageComponent {
+    type Output = &'static Storage<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        if self.mux.is_none() {
+            return None;
+        }
+
+        let alarm = static_init!(
+                HostAlarm,
+                VirtualMuxAlarm::new(self.mux.unwrap())
+            );
+        let storage = static_init!(
+                Storage<'static>,
+                Storage::new(&mk66::usbhs::USBHS,
+                             alarm,
+                             &mut storage::COMMAND_BUF)
+            );
+        alarm.set_client(storage);
+
+        Some(storage)
+    }
+}
+
+impl ComponentWithDependency<&'static PitMux> for HostStorageComponent {
+    fn dependency(&mut self, mux: &'static PitMux) -> &mut Self {
+        self.mux = Some(mux);
+
+        self
+    }
+}
+
+pub struct UsbHostComponent {
+    mux: Option<&'static PitMux>,
+    drivers: Option<Drivers>,
+}
+
+impl UsbHostComponent {
+    pub fn new() -> Self {
+        UsbHostComponent {
+            mux: None,
+            drivers: None,
+        }
+    }
+}
+
+impl Component for UsbHostComponent {
+    type Output = &'static UsbHost<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        if self.mux.is_none() || self.drivers.is_none() {
+            return None;
+        }
+
+        let alarm = static_init!(
+                HostAlarm,
+                VirtualMuxAlarm::new(self.mux.unwrap())
+            );
+        let host = static_init!(
+                UsbHost<'static>,
+                UsbHost::new(&mk66::usbhs::USBHS,
+                             alarm,
+                             self.drivers.unwrap(),
+                             &mut usbhost::CONFIGURATION_BUF)
+            );
+        alarm.set_client(host);
+
+        // PTE6 switches power to the host port.
+        let vbus = mk66::gpio::PE06.claim_as_gpio();
+        vbus.enable_output();
+        vbus.set();
+
+        mk66::usbhs::USBHS.set_client(host);
+        mk66::usbhs::USBHS.enable();
+
+        Some(host)
+    }
+}
+
+impl ComponentWithDependency<&'static PitMux> for UsbHostComponent {
+    fn dependency(&mut self, mux: &'static PitMux) -> &mut Self {
+        self.mux = Some(mux);
+
+        self
+    }
+}
+
+impl ComponentWithDependency<Drivers> for UsbHostComponent {
+    fn dependency(&mut self, drivers: Drivers) -> &mut Self {
+        self.drivers = Some(drivers);
+
+        self
+    }
+}
@@ -0,0 +1,44 @@
+use kernel;
+use usbstorage::{self, UsbStorage};
+use usbhost::storage::Storage;
+use components::{Component, ComponentWithDependency};
+
+pub struct UsbStorageComponent {
+    storage: Option<&'static Storage<'static>>,
+}
+
+impl UsbStorageComponent {
+    pub fn new() -> Self {
+        UsbStorageComponent {
+            storage: None,
+        }
+    }
+}
+
+impl Component for UsbStorageComponent {
+    type Output = &'static UsbStorage<'static>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        if self.storage.is_none() {
+            return None;
+        }
+
+        let usb_storage = static_init!(
+                UsbStorage<'static>,
+                UsbStorage::new(self.storage.unwrap(),
+                                &mut usbstorage::BUFFER,
+                                kernel::Grant::create())
+            );
+        self.storage.unwrap().set_client(usb_storage);
+
+        Some(usb_storage)
+    }
+}
+
+impl ComponentWithDependency<&'static Storage<'static>> for UsbStorageComponent {
+    fn dependency(&mut self, storage: &'static Storage<'static>) -> &mut Self {
+        self.storage = Some(storage);
+
+        self
+    }
+}
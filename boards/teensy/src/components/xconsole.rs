@@ -2,7 +2,8 @@ use mk66;
 use kernel;
 use xconsole;
 use kernel::hil::uart::UART;
-use components::Component;
+use usb::cdc::CdcAcm;
+use components::{Component, ComponentWithDependency};
 
 pub struct XConsoleComponent;
 
@@ -39,3 +40,53 @@ impl Component for XConsoleComponent {
         Some(xconsole)
     }
 }
+
+pub struct UsbConsoleComponent {
+    serial: Option<&'static CdcAcm<'static>>,
+}
+
+impl UsbConsoleComponent {
+    pub fn new() -> Self {
+        UsbConsoleComponent {
+            serial: None,
+        }
+    }
+}
+
+impl Component for UsbConsoleComponent {
+    type Output = &'static xconsole::XConsole<'static, CdcAcm<'static>>;
+
+    unsafe fn finalize(&mut self) -> Option<Self::Output> {
+        if self.serial.is_none() {
+            return None;
+        }
+
+        let serial = self.serial.unwrap();
+        let xconsole = static_init!(
+                xconsole::XConsole<CdcAcm>,
+                xconsole::XConsole::new(serial,
+                                        115200,
+                                        &mut xconsole::WRITE_BUF,
+                                        &mut xconsole::READ_BUF,
+                                        kernel::Grant::create())
+            );
+        serial.set_client(xconsole);
+        xconsole.initialize();
+
+        let kc = static_init!(
+                xconsole::App,
+                xconsole::App::default()
+            );
+        kernel::debug::assign_console_driver(Some(xconsole), kc);
+
+        Some(xconsole)
+    }
+}
+
+impl ComponentWithDependency<&'static CdcAcm<'static>> for UsbConsoleComponent {
+    fn dependency(&mut self, serial: &'static CdcAcm<'static>) -> &mut Self {
+        self.serial = Some(serial);
+
+        self
+    }
+}
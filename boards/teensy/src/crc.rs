@@ -0,0 +1,166 @@
+//! Lets apps compute CRCs with the CRC module.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! allow(CRC_DRIVER_NUM, 0, data, sizeof(data));
+//! subscribe(CRC_DRIVER_NUM, 0, done, NULL);
+//! command(CRC_DRIVER_NUM, 1, sizeof(data), 0); // CRC-32
+//! // `done(crc, 0, 0, ud)` is called with the result.
+//! ```
+//!
+//! The CRC is computed before the command returns, but the result comes
+//! back through the callback, as a 32-bit CRC returned from the command
+//! could look like an error code.
+
+use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
+use mk66::crc::{self, Config, Width};
+
+pub const DRIVER_NUM: usize = 0x90010;
+
+// Algorithms.
+const ALG_CRC32: usize = 0;
+const ALG_CRC16_CCITT: usize = 1;
+const ALG_CRC16_XMODEM: usize = 2;
+const ALG_CUSTOM: usize = 3;
+
+// Options of the custom algorithm.
+const OPTION_32_BIT: usize = 1 << 0;
+const OPTION_TRANSPOSE_INPUT: usize = 1 << 1;
+const OPTION_TRANSPOSE_OUTPUT: usize = 1 << 2;
+const OPTION_COMPLEMENT: usize = 1 << 3;
+
+#[derive(Default)]
+pub struct App {
+    callback: Option<Callback>,
+    buffer: Option<AppSlice<Shared, u8>>,
+    // The app's own algorithm.
+    polynomial: u32,
+    seed: u32,
+    options: usize,
+}
+
+impl App {
+    fn custom(&self) -> Config {
+        Config {
+            width: if self.options & OPTION_32_BIT != 0 { Width::Bits32 } else { Width::Bits16 },
+            polynomial: self.polynomial,
+            seed: self.seed,
+            transpose_input: self.options & OPTION_TRANSPOSE_INPUT != 0,
+            transpose_output: self.options & OPTION_TRANSPOSE_OUTPUT != 0,
+            complement: self.options & OPTION_COMPLEMENT != 0,
+        }
+    }
+}
+
+pub struct Crc<'a> {
+    crc: &'a crc::Crc<'a>,
+    apps: Grant<App>,
+}
+
+impl<'a> Crc<'a> {
+    pub fn new(crc: &'a crc::Crc<'a>, grant: Grant<App>) -> Crc<'a> {
+        Crc {
+            crc: crc,
+            apps: grant,
+        }
+    }
+
+    /// Computes the CRC of the first `len` bytes of the app's buffer.
+    fn compute(&self, appid: AppId, len: usize, alg: usize) -> ReturnCode {
+        self.apps.enter(appid, |app, _| {
+            let config = match alg {
+                ALG_CRC32 => crc::CRC32,
+                ALG_CRC16_CCITT => crc::CRC16_CCITT,
+                ALG_CRC16_XMODEM => crc::CRC16_XMODEM,
+                ALG_CUSTOM => app.custom(),
+                _ => return ReturnCode::EINVAL,
+            };
+            let result = match app.buffer {
+                Some(ref buffer) if len <= buffer.len() => {
+                    self.crc.checksum(config, &buffer.as_ref()[..len])
+                },
+                Some(_) => Err(ReturnCode::ESIZE),
+                None => Err(ReturnCode::ERESERVE),
+            };
+            match result {
+                Ok(result) => {
+                    app.callback.map(|mut cb| cb.schedule(result as usize, 0, 0));
+                    ReturnCode::SUCCESS
+                },
+                Err(err) => err,
+            }
+        }).unwrap_or_else(|err| err.into())
+    }
+}
+
+impl<'a> Driver for Crc<'a> {
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: A CRC was computed. The argument is the CRC.
+    fn subscribe(&self, subscribe_num: usize, callback: Option<Callback>, app_id: AppId) -> ReturnCode {
+        match subscribe_num {
+            0 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Setup buffers.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: The data to compute CRCs of.
+    fn allow(&self, appid: AppId, allow_num: usize, slice: Option<AppSlice<Shared, u8>>) -> ReturnCode {
+        match allow_num {
+            0 => {
+                self.apps.enter(appid, |app, _| {
+                    app.buffer = slice;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Compute CRCs.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Compute the CRC of the first `arg1` bytes of the buffer with
+    ///        algorithm `arg2`: 0 for CRC-32, 1 for CRC-16/CCITT-FALSE, 2
+    ///        for the CRC-16 of XMODEM, or 3 for the app's own.
+    /// - `2`: Set the polynomial of the app's algorithm to `arg1` and its
+    ///        seed to `arg2`.
+    /// - `3`: Set the options of the app's algorithm: bit 0 of `arg1`
+    ///        selects a 32-bit CRC over a 16-bit one, bit 1 transposes the
+    ///        input, bit 2 the output, and bit 3 complements the output.
+    fn command(&self, cmd_num: usize, arg1: usize, arg2: usize, appid: AppId) -> ReturnCode {
+        match cmd_num {
+            0 => ReturnCode::SUCCESS,
+            1 => self.compute(appid, arg1, arg2),
+            2 => {
+                self.apps.enter(appid, |app, _| {
+                    app.polynomial = arg1 as u32;
+                    app.seed = arg2 as u32;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            3 => {
+                self.apps.enter(appid, |app, _| {
+                    app.options = arg1;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
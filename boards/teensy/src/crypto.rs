@@ -0,0 +1,151 @@
+//! Lets apps encrypt with AES and hash with SHA-256 and SHA-1 on the MMCAU.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! allow(CRYPTO_DRIVER_NUM, 0, key, 16);     // or 24 or 32 bytes
+//! allow(CRYPTO_DRIVER_NUM, 1, iv, 16);
+//! allow(CRYPTO_DRIVER_NUM, 2, data, sizeof(data));
+//! command(CRYPTO_DRIVER_NUM, 1, 1, sizeof(data)); // encrypt with CBC
+//!
+//! allow(CRYPTO_DRIVER_NUM, 3, digest, 32);
+//! command(CRYPTO_DRIVER_NUM, 2, sizeof(data), 0); // SHA-256
+//! ```
+//!
+//! Everything runs before the command returns, so there are no callbacks.
+//! Data is encrypted or decrypted in place, and the IV buffer is left
+//! holding the IV for the next part of the message, so a long message can
+//! be handled in pieces.
+
+use kernel::{AppId, AppSlice, Driver, Grant, ReturnCode, Shared};
+use mk66::mmcau::{self, AesKey, Digest, Mode, Sha1, Sha256};
+
+pub const DRIVER_NUM: usize = 0x90011;
+
+const DECRYPT: usize = 1 << 8;
+
+#[derive(Default)]
+pub struct App {
+    key: Option<AppSlice<Shared, u8>>,
+    iv: Option<AppSlice<Shared, u8>>,
+    data: Option<AppSlice<Shared, u8>>,
+    digest: Option<AppSlice<Shared, u8>>,
+}
+
+pub struct Crypto {
+    apps: Grant<App>,
+}
+
+impl Crypto {
+    pub fn new(grant: Grant<App>) -> Crypto {
+        Crypto {
+            apps: grant,
+        }
+    }
+
+    fn aes(&self, appid: AppId, mode: usize, len: usize) -> ReturnCode {
+        let encrypting = mode & DECRYPT == 0;
+        let mode = match mode & 0x3 {
+            0 => Mode::Ecb,
+            1 => Mode::Cbc,
+            2 => Mode::Ctr,
+            _ => return ReturnCode::EINVAL,
+        };
+        self.apps.enter(appid, |app, _| {
+            let app: &mut App = &mut **app;
+            let key = match app.key {
+                Some(ref key) => match AesKey::new(key.as_ref()) {
+                    Some(key) => key,
+                    None => return ReturnCode::EINVAL,
+                },
+                None => return ReturnCode::ERESERVE,
+            };
+            let mut iv = [0; mmcau::AES_BLOCK_SIZE];
+            if mode != Mode::Ecb {
+                match app.iv {
+                    Some(ref slice) if slice.len() == iv.len() => iv.copy_from_slice(slice.as_ref()),
+                    Some(_) => return ReturnCode::EINVAL,
+                    None => return ReturnCode::ERESERVE,
+                }
+            }
+            let result = match app.data {
+                Some(ref mut data) => if len > data.len() {
+                    ReturnCode::ESIZE
+                } else {
+                    mmcau::aes_crypt(&key, mode, encrypting, &mut iv, &mut data.as_mut()[..len])
+                },
+                None => ReturnCode::ERESERVE,
+            };
+            if result == ReturnCode::SUCCESS && mode != Mode::Ecb {
+                app.iv.as_mut().map(|slice| slice.as_mut().copy_from_slice(&iv));
+            }
+            result
+        }).unwrap_or_else(|err| err.into())
+    }
+
+    /// Hashes the first `len` bytes of the app's data into its digest
+    /// buffer.
+    fn hash<D: Digest>(&self, appid: AppId, mut digest: D, len: usize) -> ReturnCode {
+        self.apps.enter(appid, |app, _| {
+            let app: &mut App = &mut **app;
+            match app.data {
+                Some(ref data) if len <= data.len() => digest.update(&data.as_ref()[..len]),
+                Some(_) => return ReturnCode::ESIZE,
+                None => return ReturnCode::ERESERVE,
+            }
+            match app.digest {
+                Some(ref mut out) => if out.len() < digest.output_len() {
+                    ReturnCode::ESIZE
+                } else {
+                    digest.finish(out.as_mut());
+                    ReturnCode::SUCCESS
+                },
+                None => ReturnCode::ERESERVE,
+            }
+        }).unwrap_or_else(|err| err.into())
+    }
+}
+
+impl Driver for Crypto {
+    /// Setup buffers.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: The AES key, of 16, 24 or 32 bytes.
+    /// - `1`: The 16-byte IV, or initial counter for CTR.
+    /// - `2`: The data to encrypt, decrypt or hash.
+    /// - `3`: Where hashes go.
+    fn allow(&self, appid: AppId, allow_num: usize, slice: Option<AppSlice<Shared, u8>>) -> ReturnCode {
+        self.apps.enter(appid, |app, _| {
+            match allow_num {
+                0 => app.key = slice,
+                1 => app.iv = slice,
+                2 => app.data = slice,
+                3 => app.digest = slice,
+                _ => return ReturnCode::ENOSUPPORT,
+            }
+            ReturnCode::SUCCESS
+        }).unwrap_or_else(|err| err.into())
+    }
+
+    /// Encrypt and hash.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Encrypt the first `arg2` bytes of the data with AES in mode
+    ///        `arg1`: 0 for ECB, 1 for CBC or 2 for CTR, or decrypt them
+    ///        if bit 8 of `arg1` is set. ECB and CBC take whole blocks.
+    /// - `2`: Hash the first `arg1` bytes of the data with SHA-256.
+    /// - `3`: Hash the first `arg1` bytes of the data with SHA-1.
+    fn command(&self, cmd_num: usize, arg1: usize, arg2: usize, appid: AppId) -> ReturnCode {
+        match cmd_num {
+            0 => ReturnCode::SUCCESS,
+            1 => self.aes(appid, arg1, arg2),
+            2 => self.hash(appid, Sha256::new(), arg1),
+            3 => self.hash(appid, Sha1::new(), arg1),
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
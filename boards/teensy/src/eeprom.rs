@@ -0,0 +1,63 @@
+//! Provides userspace with access to the emulated EEPROM.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! int size = command(EEPROM_DRIVER_NUM, 0, 0, 0);
+//! int value = command(EEPROM_DRIVER_NUM, 1, offset, 0);
+//! command(EEPROM_DRIVER_NUM, 2, offset, value);
+//! ```
+//!
+//! The EEPROM is shared by all apps. Writes finish before the command returns.
+
+use kernel::{AppId, Driver, ReturnCode};
+use mk66::eeprom::{self, Eeprom};
+
+pub const DRIVER_NUM: usize = 0x90003;
+
+pub struct EepromDriver<'a> {
+    eeprom: &'a Eeprom,
+}
+
+impl<'a> EepromDriver<'a> {
+    pub fn new(eeprom: &'a Eeprom) -> EepromDriver<'a> {
+        EepromDriver {
+            eeprom: eeprom,
+        }
+    }
+}
+
+impl<'a> Driver for EepromDriver<'a> {
+    /// Read and write the EEPROM.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check. Returns the EEPROM size in bytes.
+    /// - `1`: Return the byte at offset `arg1`.
+    /// - `2`: Write `arg2` to the byte at offset `arg1`.
+    /// - `3`: Return the 16-bit word at offset `arg1`, which must be even.
+    /// - `4`: Write `arg2` to the 16-bit word at offset `arg1`.
+    fn command(&self, cmd_num: usize, arg1: usize, arg2: usize, _: AppId) -> ReturnCode {
+        match cmd_num {
+            0 /* check if present */ => {
+                if self.eeprom.is_ready() {
+                    ReturnCode::SuccessWithValue { value: eeprom::EEPROM_SIZE }
+                } else {
+                    ReturnCode::EOFF
+                }
+            },
+            1 /* read byte */ => {
+                self.eeprom.read_byte(arg1)
+                    .map_or_else(|err| err, |value| ReturnCode::SuccessWithValue { value: value as usize })
+            },
+            2 /* write byte */ => self.eeprom.write_byte(arg1, arg2 as u8),
+            3 /* read word */ => {
+                self.eeprom.read_word(arg1)
+                    .map_or_else(|err| err, |value| ReturnCode::SuccessWithValue { value: value as usize })
+            },
+            4 /* write word */ => self.eeprom.write_word(arg1, arg2 as u16),
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
@@ -0,0 +1,421 @@
+//! Provides userspace with files on a FAT16 or FAT32 formatted microSD card.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! allow(FS_DRIVER_NUM, 1, "/LOG.TXT", 9);
+//! int handle = command(FS_DRIVER_NUM, 2, FS_APPEND, 0);
+//! allow(FS_DRIVER_NUM, 0, line, len);
+//! command(FS_DRIVER_NUM, 4, handle, len);
+//! command(FS_DRIVER_NUM, 5, handle, 0);
+//! ```
+//!
+//! Paths are 8.3 names separated by `/`, either NUL-terminated or filling
+//! the path buffer. The card is initialized and mounted by the first command
+//! that needs it, and is unmounted when it is removed; handles opened before
+//! a removal are invalid afterwards. All operations complete before the
+//! command returns.
+//!
+//! While a USB host has the card mounted, commands that use the card fail
+//! with `EBUSY`, and handles opened before the host mounted it are invalid.
+//! Handles are likewise invalid after an app writes sectors through the
+//! `sdcard` driver.
+//!
+//! Opening a file fails with `EBUSY` if it would truncate a file that is
+//! open, or write to a file already open for writing, by any app.
+
+use core::cell::Cell;
+use fat::{self, OpenMode, Volume};
+use kernel::{AppId, AppSlice, Driver, Grant, ReturnCode, Shared};
+use kernel::common::cells::MapCell;
+use mk66::sdhc::{self, BlockDevice};
+use sdcard;
+use usb::msc;
+
+pub const DRIVER_NUM: usize = 0x90005;
+
+/// The number of files each app can have open at once.
+pub const MAX_OPEN_FILES: usize = 4;
+
+/// The size of the record `read_dir` writes: the file size (4 bytes, little
+/// endian), 1 if the entry is a directory, and the NUL-terminated name.
+const DIR_RECORD_SIZE: usize = 4 + 1 + 13;
+
+/// Gives the filesystem sector access to the card.
+pub struct Card<'a>(&'a sdhc::Sdhc<'a>);
+
+impl<'a> fat::BlockDevice for Card<'a> {
+    fn read_sector(&self, sector: u32, buf: &mut [u8; fat::SECTOR_SIZE]) -> Result<(), fat::Error> {
+        match self.0.read_blocks(sector, buf) {
+            ReturnCode::SUCCESS => Ok(()),
+            _ => Err(fat::Error::Io),
+        }
+    }
+
+    fn write_sector(&self, sector: u32, buf: &[u8; fat::SECTOR_SIZE]) -> Result<(), fat::Error> {
+        match self.0.write_blocks(sector, buf) {
+            ReturnCode::SUCCESS => Ok(()),
+            _ => Err(fat::Error::Io),
+        }
+    }
+}
+
+fn return_code(err: fat::Error) -> ReturnCode {
+    match err {
+        fat::Error::Io | fat::Error::Corrupt => ReturnCode::FAIL,
+        fat::Error::NotFat | fat::Error::Unsupported => ReturnCode::ENOSUPPORT,
+        fat::Error::NotFound |
+        fat::Error::InvalidName |
+        fat::Error::NotADirectory |
+        fat::Error::IsADirectory |
+        fat::Error::ReadOnly => ReturnCode::EINVAL,
+        fat::Error::DirectoryFull |
+        fat::Error::NoSpace |
+        fat::Error::TooManyOpenFiles => ReturnCode::ENOMEM,
+        fat::Error::FileTooLarge => ReturnCode::ESIZE,
+        fat::Error::InUse => ReturnCode::EBUSY,
+    }
+}
+
+#[derive(Default)]
+pub struct App {
+    buffer: Option<AppSlice<Shared, u8>>,
+    path: Option<AppSlice<Shared, u8>>,
+    files: [Option<fat::File>; MAX_OPEN_FILES],
+    /// The mount the open files belong to.
+    mount: usize,
+}
+
+pub struct Filesystem<'a> {
+    sdhc: &'a sdhc::Sdhc<'a>,
+    volume: MapCell<Volume<Card<'a>>>,
+    /// Counts mounts, so that handles from an earlier card are rejected.
+    mount: Cell<usize>,
+    client: Cell<Option<&'a sdhc::Client>>,
+    host_mounted: Cell<bool>,
+    msc_client: Cell<Option<&'a msc::Client>>,
+    apps: Grant<App>,
+}
+
+impl<'a> Filesystem<'a> {
+    pub fn new(sdhc: &'a sdhc::Sdhc<'a>, grant: Grant<App>) -> Filesystem<'a> {
+        Filesystem {
+            sdhc: sdhc,
+            volume: MapCell::empty(),
+            mount: Cell::new(0),
+            client: Cell::new(None),
+            host_mounted: Cell::new(false),
+            msc_client: Cell::new(None),
+            apps: grant,
+        }
+    }
+
+    /// Card detect events are passed on to `client` after the filesystem
+    /// has seen them.
+    pub fn set_client(&self, client: &'a sdhc::Client) {
+        self.client.set(Some(client));
+    }
+
+    /// USB host mounts are likewise passed on to `client`.
+    pub fn set_msc_client(&self, client: &'a msc::Client) {
+        self.msc_client.set(Some(client));
+    }
+
+    fn mount(&self) -> ReturnCode {
+        if self.host_mounted.get() {
+            return ReturnCode::EBUSY;
+        }
+        if self.volume.is_some() {
+            return ReturnCode::SUCCESS;
+        }
+        if !self.sdhc.is_card_initialized() {
+            let result = self.sdhc.initialize_card();
+            if result != ReturnCode::SUCCESS {
+                return result;
+            }
+        }
+
+        match Volume::mount(Card(self.sdhc)) {
+            Ok(volume) => {
+                self.volume.put(volume);
+                self.mount.set(self.mount.get().wrapping_add(1));
+                ReturnCode::SUCCESS
+            },
+            Err(err) => return_code(err),
+        }
+    }
+
+    fn with_volume<F>(&self, f: F) -> ReturnCode
+        where F: FnOnce(&mut Volume<Card<'a>>) -> ReturnCode
+    {
+        let result = self.mount();
+        if result != ReturnCode::SUCCESS {
+            return result;
+        }
+        self.volume.map_or(ReturnCode::FAIL, f)
+    }
+
+    /// Runs `f` on one of the app's open files.
+    fn with_file<F>(&self, app: &mut App, handle: usize, f: F) -> ReturnCode
+        where F: FnOnce(&mut Volume<Card<'a>>, &mut fat::File, &mut App) -> ReturnCode
+    {
+        if app.mount != self.mount.get() || !self.volume.is_some() {
+            app.files = [None; MAX_OPEN_FILES];
+        }
+        let mut file = match app.files.get(handle).and_then(|file| *file) {
+            Some(file) => file,
+            None => return ReturnCode::EINVAL,
+        };
+
+        let result = self.volume.map_or(ReturnCode::FAIL, |volume| f(volume, &mut file, app));
+        app.files[handle] = Some(file);
+        result
+    }
+
+    /// Rebuilds the volume's record of open files from the apps' handles,
+    /// which disappear without being closed when an app exits.
+    fn track_open_files(&self) {
+        self.volume.map(|volume| volume.forget_open_files());
+        let mount = self.mount.get();
+        self.apps.each(|app| {
+            if app.mount == mount {
+                self.volume.map(|volume| {
+                    // Each handle was recorded when it was opened, so
+                    // they all fit again.
+                    for file in app.files.iter().filter_map(|file| file.as_ref()) {
+                        let _ = volume.track(file);
+                    }
+                });
+            }
+        });
+    }
+
+    fn open(&self, app: &mut App, mode: usize) -> ReturnCode {
+        let mode = match mode {
+            0 => OpenMode::Read,
+            1 => OpenMode::Write,
+            2 => OpenMode::Append,
+            _ => return ReturnCode::EINVAL,
+        };
+
+        // Mount first, so that the new handle belongs to the current mount.
+        let result = self.mount();
+        if result != ReturnCode::SUCCESS {
+            return result;
+        }
+        let mount = self.mount.get();
+        if app.mount != mount {
+            app.files = [None; MAX_OPEN_FILES];
+            app.mount = mount;
+        }
+        let handle = match app.files.iter().position(|file| file.is_none()) {
+            Some(handle) => handle,
+            None => return ReturnCode::ENOMEM,
+        };
+
+        let mut opened = None;
+        let result = match app.path {
+            Some(ref path) => {
+                let path = path.as_ref();
+                let len = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+
+                self.with_volume(|volume| {
+                    match volume.open(&path[..len], mode) {
+                        Ok(file) => {
+                            opened = Some(file);
+                            ReturnCode::SuccessWithValue { value: handle }
+                        },
+                        Err(err) => return_code(err),
+                    }
+                })
+            },
+            None => ReturnCode::ERESERVE,
+        };
+        app.files[handle] = opened;
+        result
+    }
+
+    fn read_dir(&self, app: &mut App, index: usize) -> ReturnCode {
+        let path = match app.path {
+            Some(ref path) => path,
+            None => return ReturnCode::ERESERVE,
+        };
+        let buffer = match app.buffer {
+            Some(ref mut buffer) => buffer,
+            None => return ReturnCode::ERESERVE,
+        };
+        if buffer.len() < DIR_RECORD_SIZE {
+            return ReturnCode::ESIZE;
+        }
+        let path = path.as_ref();
+        let len = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+
+        self.with_volume(|volume| {
+            match volume.read_dir(&path[..len], index) {
+                Ok(Some(entry)) => {
+                    let record = &mut buffer.as_mut()[..DIR_RECORD_SIZE];
+                    for (i, byte) in record[..4].iter_mut().enumerate() {
+                        *byte = (entry.size >> (8 * i)) as u8;
+                    }
+                    record[4] = entry.is_dir as u8;
+                    for byte in record[5..].iter_mut() {
+                        *byte = 0;
+                    }
+                    let name = entry.name();
+                    record[5..5 + name.len()].copy_from_slice(name);
+                    ReturnCode::SuccessWithValue { value: 1 }
+                },
+                Ok(None) => ReturnCode::SuccessWithValue { value: 0 },
+                Err(err) => return_code(err),
+            }
+        })
+    }
+}
+
+impl<'a> sdhc::Client for Filesystem<'a> {
+    fn card_detect_changed(&self, present: bool) {
+        if !present {
+            self.volume.take();
+        }
+        self.client.get().map(|client| client.card_detect_changed(present));
+    }
+}
+
+impl<'a> msc::Client for Filesystem<'a> {
+    fn host_mounted(&self, mounted: bool) {
+        // The host may change anything on the card, so nothing read before
+        // can be trusted afterwards.
+        self.host_mounted.set(mounted);
+        self.volume.take();
+        self.msc_client.get().map(|client| client.host_mounted(mounted));
+    }
+}
+
+impl<'a> sdcard::WriteClient for Filesystem<'a> {
+    fn sectors_written(&self) {
+        // The sectors may hold cached FAT or directory data, so remount
+        // before the next use.
+        self.volume.take();
+    }
+}
+
+impl<'a> Driver for Filesystem<'a> {
+    /// Setup shared buffers.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: Data buffer for reads, writes and directory listings
+    /// - `1`: Path of the file or directory to open or list
+    fn allow(&self, appid: AppId, allow_num: usize, slice: Option<AppSlice<Shared, u8>>) -> ReturnCode {
+        match allow_num {
+            0 | 1 => {
+                self.apps.enter(appid, |app, _| {
+                    if allow_num == 0 {
+                        app.buffer = slice;
+                    } else {
+                        app.path = slice;
+                    }
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Access files.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Mount the card. Other commands mount it as needed, so this is
+    ///        only useful to check for a usable card.
+    /// - `2`: Open the file at the path, with `arg1` 0 to read, 1 to create
+    ///        or truncate, or 2 to append. Returns a handle.
+    /// - `3`: Read up to `arg2` bytes from handle `arg1` into the buffer.
+    ///        Returns the number of bytes read, 0 at the end of the file.
+    /// - `4`: Write `arg2` bytes from the buffer to handle `arg1`. Returns
+    ///        the number of bytes written.
+    /// - `5`: Close handle `arg1`.
+    /// - `6`: Fill the buffer with the `arg1`th entry of the directory at the
+    ///        path. Returns 1, or 0 if there is no such entry.
+    /// - `7`: Return the free space in KB.
+    /// - `8`: Move handle `arg1` to byte `arg2` of its file.
+    /// - `9`: Return the size of the file behind handle `arg1`.
+    fn command(&self, cmd_num: usize, arg1: usize, arg2: usize, appid: AppId) -> ReturnCode {
+        match cmd_num {
+            0 /* check if present */ => ReturnCode::SUCCESS,
+            1 /* mount */ => self.mount(),
+            2 /* open */ => {
+                self.track_open_files();
+                self.apps.enter(appid, |app, _| {
+                    self.open(app, arg1)
+                }).unwrap_or_else(|err| err.into())
+            },
+            3 | 4 /* read, write */ => {
+                self.apps.enter(appid, |app, _| {
+                    self.with_file(app, arg1, |volume, file, app| {
+                        let buffer = match app.buffer {
+                            Some(ref mut buffer) => buffer,
+                            None => return ReturnCode::ERESERVE,
+                        };
+                        if arg2 > buffer.len() {
+                            return ReturnCode::ESIZE;
+                        }
+                        let data = &mut buffer.as_mut()[..arg2];
+
+                        let result = if cmd_num == 3 {
+                            volume.read(file, data)
+                        } else {
+                            volume.write(file, data)
+                        };
+                        match result {
+                            Ok(len) => ReturnCode::SuccessWithValue { value: len },
+                            Err(err) => return_code(err),
+                        }
+                    })
+                }).unwrap_or_else(|err| err.into())
+            },
+            5 /* close */ => {
+                self.apps.enter(appid, |app, _| {
+                    let result = self.with_file(app, arg1, |volume, file, _| {
+                        volume.close(*file).err().map_or(ReturnCode::SUCCESS, return_code)
+                    });
+                    if result != ReturnCode::EINVAL {
+                        app.files[arg1] = None;
+                    }
+                    result
+                }).unwrap_or_else(|err| err.into())
+            },
+            6 /* read directory */ => {
+                self.apps.enter(appid, |app, _| {
+                    self.read_dir(app, arg1)
+                }).unwrap_or_else(|err| err.into())
+            },
+            7 /* free space */ => {
+                self.with_volume(|volume| {
+                    match volume.free_space() {
+                        Ok(bytes) => ReturnCode::SuccessWithValue { value: (bytes / 1024) as usize },
+                        Err(err) => return_code(err),
+                    }
+                })
+            },
+            8 /* seek */ => {
+                self.apps.enter(appid, |app, _| {
+                    self.with_file(app, arg1, |_, file, _| {
+                        file.seek(arg2 as u32);
+                        ReturnCode::SUCCESS
+                    })
+                }).unwrap_or_else(|err| err.into())
+            },
+            9 /* file size */ => {
+                self.apps.enter(appid, |app, _| {
+                    self.with_file(app, arg1, |_, file, _| {
+                        ReturnCode::SuccessWithValue { value: file.size() as usize }
+                    })
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Lets apps act as a USB keyboard, mouse and gamepad.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! subscribe(HID_DRIVER_NUM, 0, report_sent, NULL);
+//! allow(HID_DRIVER_NUM, 0, report, len);
+//! command(HID_DRIVER_NUM, 1, len, 0);
+//! ```
+//!
+//! Reports follow `usb::hid::KEYBOARD_MOUSE_GAMEPAD` and start with their
+//! report ID. The report is copied when the command is made, so the buffer
+//! may be reused at once, but the next report can only be sent after the
+//! callback. The callback's first argument is a return code.
+
+use core::cell::Cell;
+use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
+use usb::hid::{self, Hid};
+
+pub const DRIVER_NUM: usize = 0x90006;
+
+#[derive(Default)]
+pub struct App {
+    sent_callback: Option<Callback>,
+    leds_callback: Option<Callback>,
+    buffer: Option<AppSlice<Shared, u8>>,
+}
+
+pub struct UsbHid<'a> {
+    hid: &'a Hid<'a>,
+    // The app whose report is being sent.
+    sender: Cell<Option<AppId>>,
+    leds: Cell<u8>,
+    apps: Grant<App>,
+}
+
+impl<'a> UsbHid<'a> {
+    pub fn new(hid: &'a Hid<'a>, grant: Grant<App>) -> UsbHid<'a> {
+        UsbHid {
+            hid: hid,
+            sender: Cell::new(None),
+            leds: Cell::new(0),
+            apps: grant,
+        }
+    }
+
+    fn send(&self, app: &mut App, len: usize, appid: AppId) -> ReturnCode {
+        if self.sender.get().is_some() {
+            return ReturnCode::EBUSY;
+        }
+
+        let result = app.buffer.as_ref().map_or(ReturnCode::ERESERVE, |slice| {
+            if len > slice.len() {
+                return ReturnCode::ESIZE;
+            }
+            self.hid.send_report(&slice.as_ref()[..len])
+        });
+        if result == ReturnCode::SUCCESS {
+            self.sender.set(Some(appid));
+        }
+        result
+    }
+}
+
+impl<'a> hid::Client for UsbHid<'a> {
+    fn report_sent(&self, result: ReturnCode) {
+        self.sender.get().map(|appid| {
+            self.sender.set(None);
+            let _ = self.apps.enter(appid, |app, _| {
+                let code = isize::from(result) as usize;
+                app.sent_callback.map(|mut cb| cb.schedule(code, 0, 0));
+            });
+        });
+    }
+
+    fn output_report(&self, report: &[u8]) {
+        if report.len() < 2 || report[0] != hid::REPORT_KEYBOARD {
+            return;
+        }
+        self.leds.set(report[1]);
+        self.apps.each(|app| {
+            app.leds_callback.map(|mut cb| cb.schedule(report[1] as usize, 0, 0));
+        });
+    }
+}
+
+impl<'a> Driver for UsbHid<'a> {
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: Report sent
+    /// - `1`: Keyboard LEDs changed. The first argument holds the LED bits:
+    ///        num lock, caps lock, scroll lock, compose and kana.
+    fn subscribe(&self, subscribe_num: usize, callback: Option<Callback>, app_id: AppId) -> ReturnCode {
+        match subscribe_num {
+            0 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.sent_callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            1 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.leds_callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Setup the report buffer.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: The report to send
+    fn allow(&self, appid: AppId, allow_num: usize, slice: Option<AppSlice<Shared, u8>>) -> ReturnCode {
+        match allow_num {
+            0 => {
+                self.apps.enter(appid, |app, _| {
+                    app.buffer = slice;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Send reports.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Send the first `arg1` bytes of the buffer as a report.
+    /// - `2`: Return the keyboard LED bits last set by the host.
+    /// - `3`: Return 1 if the host has configured the device.
+    fn command(&self, cmd_num: usize, arg1: usize, _: usize, appid: AppId) -> ReturnCode {
+        match cmd_num {
+            0 => ReturnCode::SUCCESS,
+            1 => {
+                self.apps.enter(appid, |app, _| {
+                    self.send(app, arg1, appid)
+                }).unwrap_or_else(|err| err.into())
+            },
+            2 => ReturnCode::SuccessWithValue { value: self.leds.get() as usize },
+            3 => ReturnCode::SuccessWithValue { value: self.hid.is_configured() as usize },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
@@ -0,0 +1,328 @@
+//! Lets apps send IR remote control codes.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! subscribe(INFRARED_DRIVER_NUM, 0, sent, NULL);
+//! command(INFRARED_DRIVER_NUM, 1, 0x04, 0x08); // NEC, address 4, command 8
+//!
+//! // Raw: microseconds of mark and space in turn, at 38kHz.
+//! allow(INFRARED_DRIVER_NUM, 0, timings, sizeof(timings));
+//! command(INFRARED_DRIVER_NUM, 5, count, 0);
+//! ```
+//!
+//! NEC codes go out at 38kHz, RC5 at 36kHz and Sony SIRC at 40kHz, each
+//! with a duty cycle of 33%, unless the app sets another carrier. Codes
+//! are sent one at a time by all apps; `sent(0, 0, 0, ud)` is called
+//! when an app's code is out.
+
+use core::cell::Cell;
+use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
+use mk66::cmt::{self, Carrier, Cmt};
+
+pub const DRIVER_NUM: usize = 0x9000F;
+
+const DUTY_PERCENT: u32 = 33;
+
+const NEC_CARRIER: u32 = 38_000;
+const NEC_UNIT: u16 = 562;
+const NEC_HEADER_MARK: u16 = 16 * NEC_UNIT;
+const NEC_HEADER_SPACE: u16 = 8 * NEC_UNIT;
+const NEC_REPEAT_SPACE: u16 = 4 * NEC_UNIT;
+const NEC_ONE_SPACE: u16 = 3 * NEC_UNIT;
+/// Keeps a repeat from following too closely.
+const NEC_GAP: u16 = 40_000;
+
+const RC5_CARRIER: u32 = 36_000;
+const RC5_HALF_BIT: u16 = 889;
+const RC5_GAP: u16 = 50_000;
+
+const SONY_CARRIER: u32 = 40_000;
+const SONY_UNIT: u16 = 600;
+const SONY_HEADER_MARK: u16 = 4 * SONY_UNIT;
+const SONY_ONE_MARK: u16 = 2 * SONY_UNIT;
+/// Sony frames start every 45ms, and are sent three times.
+const SONY_FRAME: u32 = 45_000;
+const SONY_REPEATS: usize = 3;
+
+#[derive(Default)]
+pub struct App {
+    callback: Option<Callback>,
+    timings: Option<AppSlice<Shared, u8>>,
+}
+
+pub struct Infrared<'a> {
+    cmt: &'a Cmt<'a>,
+    // The carrier set by an app, or `None` for each protocol's own.
+    carrier: Cell<Option<Carrier>>,
+    sender: Cell<Option<AppId>>,
+    apps: Grant<App>,
+}
+
+/// Collects mark and space durations, starting with a mark.
+struct Timings {
+    buf: [u16; cmt::MAX_TIMINGS],
+    len: usize,
+}
+
+impl Timings {
+    fn new() -> Timings {
+        Timings {
+            buf: [0; cmt::MAX_TIMINGS],
+            len: 0,
+        }
+    }
+
+    fn is_mark(&self) -> bool {
+        self.len % 2 == 0
+    }
+
+    /// Adds to the last duration if it was the same level, and drops
+    /// leading spaces.
+    fn push(&mut self, mark: bool, us: u16) {
+        if self.len == 0 && !mark {
+            return;
+        }
+        if mark != self.is_mark() {
+            self.buf[self.len - 1] = self.buf[self.len - 1].saturating_add(us);
+        } else if self.len < self.buf.len() {
+            self.buf[self.len] = us;
+            self.len += 1;
+        }
+    }
+
+    fn mark(&mut self, us: u16) {
+        self.push(true, us);
+    }
+
+    fn space(&mut self, us: u16) {
+        self.push(false, us);
+    }
+
+    fn as_slice(&self) -> &[u16] {
+        &self.buf[..self.len]
+    }
+}
+
+/// An NEC frame: the address, then the command and its inverse, least
+/// significant bits first. Addresses above 0xFF are sent as 16 bits, and
+/// lower ones followed by their inverse.
+fn encode_nec(timings: &mut Timings, address: u16, command: u8) {
+    let address = if address > 0xFF { address } else { address | (!address & 0xFF) << 8 };
+    let bits = address as u32 | (command as u32) << 16 | (!command as u32) << 24;
+
+    timings.mark(NEC_HEADER_MARK);
+    timings.space(NEC_HEADER_SPACE);
+    for i in 0..32 {
+        timings.mark(NEC_UNIT);
+        timings.space(if bits & (1 << i) != 0 { NEC_ONE_SPACE } else { NEC_UNIT });
+    }
+    timings.mark(NEC_UNIT);
+    timings.space(NEC_GAP);
+}
+
+/// The code NEC remotes send while a key is held.
+fn encode_nec_repeat(timings: &mut Timings) {
+    timings.mark(NEC_HEADER_MARK);
+    timings.space(NEC_REPEAT_SPACE);
+    timings.mark(NEC_UNIT);
+    timings.space(NEC_GAP);
+}
+
+/// An RC5 frame of 14 Manchester coded bits, most significant first: two
+/// start bits, the second being the inverse of command bit 6, the toggle
+/// bit, 5 address bits and 6 command bits. A one is a space then a mark.
+fn encode_rc5(timings: &mut Timings, address: u8, command: u8, toggle: bool) {
+    let bits = 1 << 13 |
+               (((command >> 6) & 1) ^ 1) as u16 << 12 |
+               (toggle as u16) << 11 |
+               ((address & 0x1F) as u16) << 6 |
+               (command & 0x3F) as u16;
+    for i in (0..14).rev() {
+        let one = bits & (1 << i) != 0;
+        timings.push(!one, RC5_HALF_BIT);
+        timings.push(one, RC5_HALF_BIT);
+    }
+    timings.space(RC5_GAP);
+}
+
+/// A Sony SIRC frame, sent three times: 7 command bits, then 5, 8 or 13
+/// address bits for 12, 15 or 20 bit codes, least significant first.
+fn encode_sony(timings: &mut Timings, address: u16, command: u8, bits: usize) {
+    let code = (command & 0x7F) as u32 | (address as u32) << 7;
+    for _ in 0..SONY_REPEATS {
+        let mut frame = (SONY_HEADER_MARK + SONY_UNIT) as u32;
+        timings.mark(SONY_HEADER_MARK);
+        timings.space(SONY_UNIT);
+        for i in 0..bits {
+            let mark = if code & (1 << i) != 0 { SONY_ONE_MARK } else { SONY_UNIT };
+            timings.mark(mark);
+            timings.space(SONY_UNIT);
+            frame += (mark + SONY_UNIT) as u32;
+        }
+        // Lengthens the last bit's space to the end of the frame.
+        timings.space((SONY_FRAME - frame) as u16);
+    }
+}
+
+impl<'a> Infrared<'a> {
+    pub fn new(cmt: &'a Cmt<'a>, grant: Grant<App>) -> Infrared<'a> {
+        Infrared {
+            cmt: cmt,
+            carrier: Cell::new(None),
+            sender: Cell::new(None),
+            apps: grant,
+        }
+    }
+
+    fn send(&self, appid: AppId, frequency: u32, timings: &[u16]) -> ReturnCode {
+        if self.sender.get().is_some() {
+            return ReturnCode::EBUSY;
+        }
+        let carrier = self.carrier.get().unwrap_or(Carrier {
+            frequency: frequency,
+            duty_percent: DUTY_PERCENT,
+        });
+        let result = self.cmt.transmit(carrier, timings);
+        if result == ReturnCode::SUCCESS {
+            self.sender.set(Some(appid));
+        }
+        result
+    }
+
+    /// Sends the app's raw timings, as little-endian 16-bit microseconds.
+    fn send_raw(&self, appid: AppId, count: usize) -> ReturnCode {
+        if count == 0 || count > cmt::MAX_TIMINGS {
+            return ReturnCode::ESIZE;
+        }
+        let mut timings = Timings::new();
+        let result = self.apps.enter(appid, |app, _| {
+            app.timings.as_ref().map_or(ReturnCode::ERESERVE, |slice| {
+                if slice.len() < 2 * count {
+                    return ReturnCode::ESIZE;
+                }
+                for pair in slice.as_ref()[..2 * count].chunks(2) {
+                    timings.buf[timings.len] = pair[0] as u16 | (pair[1] as u16) << 8;
+                    timings.len += 1;
+                }
+                ReturnCode::SUCCESS
+            })
+        }).unwrap_or_else(|err| err.into());
+        if result != ReturnCode::SUCCESS {
+            return result;
+        }
+        self.send(appid, NEC_CARRIER, timings.as_slice())
+    }
+}
+
+impl<'a> cmt::Client for Infrared<'a> {
+    fn transmit_done(&self) {
+        self.sender.take().map(|appid| {
+            let _ = self.apps.enter(appid, |app, _| {
+                app.callback.map(|mut cb| cb.schedule(0, 0, 0));
+            });
+        });
+    }
+}
+
+impl<'a> Driver for Infrared<'a> {
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: The app's code was sent.
+    fn subscribe(&self, subscribe_num: usize, callback: Option<Callback>, app_id: AppId) -> ReturnCode {
+        match subscribe_num {
+            0 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Setup buffers.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: Raw timings, as little-endian 16-bit microseconds of mark
+    ///        and space in turn.
+    fn allow(&self, appid: AppId, allow_num: usize, slice: Option<AppSlice<Shared, u8>>) -> ReturnCode {
+        match allow_num {
+            0 => {
+                self.apps.enter(appid, |app, _| {
+                    app.timings = slice;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Send codes.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Send NEC command `arg2` to address `arg1`, which is sent as
+    ///        extended NEC if it is above 0xFF.
+    /// - `2`: Send the NEC repeat code.
+    /// - `3`: Send RC5 command `arg2` (0 to 127) to address `arg1` (0 to
+    ///        31), with the toggle bit from bit 8 of `arg1`.
+    /// - `4`: Send Sony command `arg2` in bits 0-6 to address `arg1`, with
+    ///        12, 15 or 20 bits from bits 8-15 of `arg2`.
+    /// - `5`: Send the first `arg1` raw timings.
+    /// - `6`: Use a carrier of `arg1` Hz with a duty cycle of `arg2`
+    ///        percent, or each protocol's own if `arg1` is 0.
+    fn command(&self, cmd_num: usize, arg1: usize, arg2: usize, appid: AppId) -> ReturnCode {
+        let mut timings = Timings::new();
+        match cmd_num {
+            0 => ReturnCode::SUCCESS,
+            1 => {
+                if arg1 > 0xFFFF || arg2 > 0xFF {
+                    return ReturnCode::EINVAL;
+                }
+                encode_nec(&mut timings, arg1 as u16, arg2 as u8);
+                self.send(appid, NEC_CARRIER, timings.as_slice())
+            },
+            2 => {
+                encode_nec_repeat(&mut timings);
+                self.send(appid, NEC_CARRIER, timings.as_slice())
+            },
+            3 => {
+                if arg1 & 0xFF > 0x1F || arg2 > 0x7F {
+                    return ReturnCode::EINVAL;
+                }
+                encode_rc5(&mut timings, arg1 as u8, arg2 as u8, arg1 & (1 << 8) != 0);
+                self.send(appid, RC5_CARRIER, timings.as_slice())
+            },
+            4 => {
+                let bits = (arg2 >> 8) & 0xFF;
+                if (bits != 12 && bits != 15 && bits != 20) || arg1 >= 1 << (bits - 7) {
+                    return ReturnCode::EINVAL;
+                }
+                encode_sony(&mut timings, arg1 as u16, arg2 as u8, bits);
+                self.send(appid, SONY_CARRIER, timings.as_slice())
+            },
+            5 => self.send_raw(appid, arg1),
+            6 => {
+                if arg1 == 0 {
+                    self.carrier.set(None);
+                    return ReturnCode::SUCCESS;
+                }
+                if arg2 == 0 || arg2 >= 100 {
+                    return ReturnCode::EINVAL;
+                }
+                self.carrier.set(Some(Carrier {
+                    frequency: arg1 as u32,
+                    duty_percent: arg2 as u32,
+                }));
+                ReturnCode::SUCCESS
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
@@ -3,12 +3,17 @@ use kernel::hil::uart::{self, UART};
 use kernel::hil::led;
 use kernel::debug;
 use mk66::{self, gpio};
+use usb::cdc::CdcAcm;
 
 pub struct Writer {
     initialized: bool,
+    panicking: bool,
 }
 
-pub static mut WRITER: Writer = Writer { initialized: false };
+pub static mut WRITER: Writer = Writer { initialized: false, panicking: false };
+
+/// Output is copied to the USB serial port once it exists.
+pub static mut USB_SERIAL: Option<&'static CdcAcm<'static>> = None;
 
 impl Write for Writer {
     fn write_str(&mut self, s: &str) -> ::core::fmt::Result {
@@ -29,6 +34,16 @@ impl Write for Writer {
         }
         while !uart.tx_ready() {}
 
+        unsafe {
+            USB_SERIAL.map(|serial| {
+                serial.write_log(s.as_bytes());
+                // Nothing else will send the log after a panic.
+                if self.panicking {
+                    serial.flush();
+                }
+            });
+        }
+
         Ok(())
     }
 }
@@ -39,6 +54,7 @@ impl Write for Writer {
 #[lang="panic_fmt"]
 pub unsafe extern "C" fn panic_fmt(args: Arguments, file: &'static str, line: u32) -> ! {
     let writer = &mut WRITER;
+    writer.panicking = true;
 
     // blink the panic signal
     gpio::PC05.release_claim();
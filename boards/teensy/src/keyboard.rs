@@ -0,0 +1,96 @@
+//! Passes key presses from a keyboard on the USB host port to apps.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! subscribe(KEYBOARD_DRIVER_NUM, 0, key_changed, NULL);
+//! command(KEYBOARD_DRIVER_NUM, 1, 0, 0); // is a keyboard attached?
+//! ```
+//!
+//! The callback's first argument holds the key's usage ID in its low byte,
+//! and bit 8 is set if the key was pressed rather than released. The
+//! second argument is the modifier bits, and the third the ASCII character
+//! the key types on a US layout, or 0 if it types none. Every subscribed app
+//! receives each key.
+
+use kernel::{AppId, Callback, Driver, Grant, ReturnCode};
+use usbhost::keyboard::{self, Keyboard};
+
+pub const DRIVER_NUM: usize = 0x90008;
+
+#[derive(Default)]
+pub struct App {
+    callback: Option<Callback>,
+}
+
+pub struct UsbKeyboard<'a> {
+    keyboard: &'a Keyboard<'a>,
+    apps: Grant<App>,
+}
+
+impl<'a> UsbKeyboard<'a> {
+    pub fn new(keyboard: &'a Keyboard<'a>, grant: Grant<App>) -> UsbKeyboard<'a> {
+        UsbKeyboard {
+            keyboard: keyboard,
+            apps: grant,
+        }
+    }
+}
+
+impl<'a> keyboard::Client for UsbKeyboard<'a> {
+    fn key_changed(&self, usage: u8, modifiers: u8, pressed: bool) {
+        let character = if pressed {
+            keyboard::ascii(usage, modifiers, self.keyboard.leds()).unwrap_or(0)
+        } else {
+            0
+        };
+        self.apps.each(|app| {
+            app.callback.map(|mut cb| {
+                cb.schedule(usage as usize | (pressed as usize) << 8,
+                            modifiers as usize,
+                            character as usize)
+            });
+        });
+    }
+}
+
+impl<'a> Driver for UsbKeyboard<'a> {
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: Key pressed or released
+    fn subscribe(&self, subscribe_num: usize, callback: Option<Callback>, app_id: AppId) -> ReturnCode {
+        match subscribe_num {
+            0 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Query the keyboard.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Return 1 if a keyboard is attached.
+    /// - `2`: Return the lit lock LEDs: bit 0 for num lock, bit 1 for caps
+    ///        lock.
+    fn command(&self, cmd_num: usize, _arg1: usize, _arg2: usize, _appid: AppId) -> ReturnCode {
+        match cmd_num {
+            0 /* check if present */ => ReturnCode::SUCCESS,
+            1 /* attached */ => {
+                ReturnCode::SuccessWithValue { value: self.keyboard.is_attached() as usize }
+            },
+            2 /* lock LEDs */ => {
+                ReturnCode::SuccessWithValue { value: self.keyboard.leds() as usize }
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
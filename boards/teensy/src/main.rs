@@ -4,6 +4,8 @@
 
 extern crate capsules;
 
+extern crate fat;
+
 #[macro_use(debug, static_init, register_bitfields, register_bitmasks)]
 extern crate kernel;
 
@@ -24,6 +26,48 @@ mod components;
 
 pub mod xconsole;
 
+pub mod quadrature;
+
+pub mod uptime;
+
+pub mod rtc;
+
+pub mod eeprom;
+
+pub mod sdcard;
+
+pub mod filesystem;
+
+pub mod usb;
+
+pub mod hid;
+
+pub mod midi;
+
+pub mod usbhost;
+
+pub mod keyboard;
+
+pub mod usbstorage;
+
+pub mod can;
+
+pub mod net;
+
+pub mod udp;
+
+pub mod audio;
+
+pub mod touch;
+
+pub mod comparator;
+
+pub mod infrared;
+
+pub mod crc;
+
+pub mod crypto;
+
 #[allow(dead_code)]
 mod pins;
 
@@ -31,12 +75,31 @@ use components::*;
 
 #[allow(unused)]
 struct Teensy {
-    xconsole: <XConsoleComponent as Component>::Output,
+    xconsole: <UsbConsoleComponent as Component>::Output,
     gpio: <GpioComponent as Component>::Output,
     led: <LedComponent as Component>::Output,
     alarm: <AlarmComponent as Component>::Output,
     spi: <VirtualSpiComponent as Component>::Output,
     rng: <RngaComponent as Component>::Output,
+    quadrature: <QuadratureComponent as Component>::Output,
+    uptime: <UptimeComponent as Component>::Output,
+    rtc: <RtcComponent as Component>::Output,
+    nonvolatile_storage: <NonvolatileStorageComponent as Component>::Output,
+    eeprom: <EepromComponent as Component>::Output,
+    sdcard: <SdCardComponent as Component>::Output,
+    filesystem: <FilesystemComponent as Component>::Output,
+    hid: <HidComponent as Component>::Output,
+    midi: <MidiComponent as Component>::Output,
+    keyboard: <KeyboardComponent as Component>::Output,
+    usbstorage: <UsbStorageComponent as Component>::Output,
+    can: <CanComponent as Component>::Output,
+    udp: <UdpComponent as Component>::Output,
+    audio: <AudioComponent as Component>::Output,
+    touch: <TouchComponent as Component>::Output,
+    comparator: <ComparatorComponent as Component>::Output,
+    infrared: <InfraredComponent as Component>::Output,
+    crc: <CrcComponent as Component>::Output,
+    crypto: <CryptoComponent as Component>::Output,
     ipc: kernel::ipc::IPC,
 }
 
@@ -55,18 +118,39 @@ impl kernel::Platform for Teensy {
 
             capsules::rng::DRIVER_NUM => f(Some(self.rng)),
 
+            quadrature::DRIVER_NUM => f(Some(self.quadrature)),
+            uptime::DRIVER_NUM => f(Some(self.uptime)),
+            rtc::DRIVER_NUM => f(Some(self.rtc)),
+            capsules::nonvolatile_storage_driver::DRIVER_NUM => f(Some(self.nonvolatile_storage)),
+            eeprom::DRIVER_NUM => f(Some(self.eeprom)),
+            sdcard::DRIVER_NUM => f(Some(self.sdcard)),
+            filesystem::DRIVER_NUM => f(Some(self.filesystem)),
+            hid::DRIVER_NUM => f(Some(self.hid)),
+            midi::DRIVER_NUM => f(Some(self.midi)),
+            keyboard::DRIVER_NUM => f(Some(self.keyboard)),
+            usbstorage::DRIVER_NUM => f(Some(self.usbstorage)),
+            can::DRIVER_NUM => f(Some(self.can)),
+            udp::DRIVER_NUM => f(Some(self.udp)),
+            audio::DRIVER_NUM => f(Some(self.audio)),
+            touch::DRIVER_NUM => f(Some(self.touch)),
+            comparator::DRIVER_NUM => f(Some(self.comparator)),
+            infrared::DRIVER_NUM => f(Some(self.infrared)),
+            crc::DRIVER_NUM => f(Some(self.crc)),
+            crypto::DRIVER_NUM => f(Some(self.crypto)),
+
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
             _ => f(None),
         }
     }
 }
 
+/// Protect the kernel against accidental writes. The Teensy bootloader mass
+/// erases the chip, so it can still replace the kernel.
 #[link_section = ".flashconfig"]
 #[no_mangle]
-pub static FLASH_CONFIG_BYTES: [u8; 16] = [
-    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
-    0xFF, 0xFF, 0xFF, 0xFF, 0xDE, 0xF9, 0xFF, 0xFF,
-];
+pub static FLASH_CONFIG_BYTES: [u8; 16] = mk66::flash_config::FlashConfig::new()
+    .protect_range(0x0000_0000, 0x0002_0000)
+    .bytes();
 
 #[no_mangle]
 pub unsafe fn reset_handler() {
@@ -83,7 +167,7 @@ pub unsafe fn reset_handler() {
     use mk66::sim::Clock;
     mk66::sim::clocks::PORTABCDE.enable();
 
-    let (gpio_pins, led_pins) = pins::configure_all_pins();
+    let (gpio_pins, led_pins, qd_index_pins) = pins::configure_all_pins();
     let gpio = GpioComponent::new()
                              .dependency(gpio_pins)
                              .finalize().unwrap();
@@ -91,9 +175,89 @@ pub unsafe fn reset_handler() {
                            .dependency(led_pins)
                            .finalize().unwrap();
     let spi = VirtualSpiComponent::new().finalize().unwrap();
-    let alarm = AlarmComponent::new().finalize().unwrap();
-    let xconsole = XConsoleComponent::new().finalize().unwrap();
+    let mux_alarm = AlarmMuxComponent::new().finalize().unwrap();
+    let alarm = AlarmComponent::new()
+                               .dependency(mux_alarm)
+                               .finalize().unwrap();
+    let usb_serial = UsbSerialComponent::new().finalize().unwrap();
+    let xconsole = UsbConsoleComponent::new()
+                                      .dependency(usb_serial)
+                                      .finalize().unwrap();
     let rng = RngaComponent::new().finalize().unwrap();
+    let quadrature = QuadratureComponent::new()
+                                         .dependency(qd_index_pins)
+                                         .finalize().unwrap();
+    let uptime = UptimeComponent::new().finalize().unwrap();
+    let rtc = RtcComponent::new().finalize().unwrap();
+    let nonvolatile_storage = NonvolatileStorageComponent::new().finalize().unwrap();
+    let eeprom = EepromComponent::new().finalize().unwrap();
+    let sdcard = SdCardComponent::new().finalize().unwrap();
+    let filesystem = FilesystemComponent::new()
+                                         .dependency(sdcard)
+                                         .finalize().unwrap();
+    let usb_hid = UsbHidComponent::new().finalize().unwrap();
+    let hid = HidComponent::new()
+                           .dependency(usb_hid)
+                           .finalize().unwrap();
+    let usb_midi = UsbMidiComponent::new().finalize().unwrap();
+    let midi = MidiComponent::new()
+                             .dependency(usb_midi)
+                             .finalize().unwrap();
+    let usb_msc = UsbMscComponent::new()
+                                  .dependency(filesystem)
+                                  .finalize().unwrap();
+    let usb_functions = static_init!(
+            [&'static usb::device::Function<'static>; 4],
+            [usb_serial as &usb::device::Function,
+             usb_hid as &usb::device::Function,
+             usb_midi as &usb::device::Function,
+             usb_msc as &usb::device::Function]
+        );
+    UsbDeviceComponent::new()
+                       .dependency(usb_functions)
+                       .finalize().unwrap();
+
+    let host_hub = HostHubComponent::new()
+                                   .dependency(mux_alarm)
+                                   .finalize().unwrap();
+    let host_keyboard = HostKeyboardComponent::new().finalize().unwrap();
+    let host_storage = HostStorageComponent::new()
+                                           .dependency(mux_alarm)
+                                           .finalize().unwrap();
+    let keyboard = KeyboardComponent::new()
+                                     .dependency(host_keyboard)
+                                     .finalize().unwrap();
+    let usbstorage = UsbStorageComponent::new()
+                                         .dependency(host_storage)
+                                         .finalize().unwrap();
+    let host_drivers = static_init!(
+            [&'static usbhost::ClassDriver<'static>; 3],
+            [host_hub as &usbhost::ClassDriver,
+             host_keyboard as &usbhost::ClassDriver,
+             host_storage as &usbhost::ClassDriver]
+        );
+    let host = UsbHostComponent::new()
+                                .dependency(mux_alarm)
+                                .dependency(host_drivers)
+                                .finalize().unwrap();
+    host_hub.set_host(host);
+    let can = CanComponent::new().finalize().unwrap();
+    let slip = SlipComponent::new().finalize().unwrap();
+    let ipv4 = Ipv4Component::new()
+                             .dependency(mux_alarm)
+                             .dependency(slip as &net::Link)
+                             .finalize().unwrap();
+    let udp = UdpComponent::new()
+                           .dependency(ipv4)
+                           .finalize().unwrap();
+    let audio = AudioComponent::new().finalize().unwrap();
+    let touch = TouchComponent::new()
+                               .dependency(mux_alarm)
+                               .finalize().unwrap();
+    let comparator = ComparatorComponent::new().finalize().unwrap();
+    let infrared = InfraredComponent::new().finalize().unwrap();
+    let crc = CrcComponent::new().finalize().unwrap();
+    let crypto = CryptoComponent::new().finalize().unwrap();
 
     let teensy = Teensy {
         xconsole: xconsole,
@@ -102,13 +266,32 @@ pub unsafe fn reset_handler() {
         alarm: alarm,
         spi: spi,
         rng: rng,
+        quadrature: quadrature,
+        uptime: uptime,
+        rtc: rtc,
+        nonvolatile_storage: nonvolatile_storage,
+        eeprom: eeprom,
+        sdcard: sdcard,
+        filesystem: filesystem,
+        hid: hid,
+        midi: midi,
+        keyboard: keyboard,
+        usbstorage: usbstorage,
+        can: can,
+        udp: udp,
+        audio: audio,
+        touch: touch,
+        comparator: comparator,
+        infrared: infrared,
+        crc: crc,
+        crypto: crypto,
         ipc: kernel::ipc::IPC::new(),
     };
 
     let mut chip = mk66::chip::MK66::new();
 
     if tests::TEST {
-        tests::test();
+        tests::test(mux_alarm);
     }
     kernel::kernel_loop(&teensy, &mut chip, load_processes(), Some(&teensy.ipc));
 }
@@ -0,0 +1,230 @@
+//! Lets apps send and receive USB MIDI event packets.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! // Sending
+//! subscribe(MIDI_DRIVER_NUM, 0, send_done, NULL);
+//! allow(MIDI_DRIVER_NUM, 0, packets, 4 * count);
+//! command(MIDI_DRIVER_NUM, 1, count, 0);
+//!
+//! // Receiving
+//! allow(MIDI_DRIVER_NUM, 1, rx_buffer, 4 * capacity);
+//! subscribe(MIDI_DRIVER_NUM, 1, received, NULL);
+//! // In `received(count, 0, 0, ud)`, handle the first `count` packets, then
+//! command(MIDI_DRIVER_NUM, 3, count, 0);
+//! ```
+//!
+//! Event packets are four bytes: the cable number in the top four bits and
+//! the code index in the bottom four bits of the first byte, then the MIDI
+//! message. Every app with a receive buffer gets every packet from the
+//! host, on all cables. Packets are appended to the buffer until the app
+//! consumes them, and are dropped while it is full.
+
+use core::cell::Cell;
+use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
+use usb::midi::{self, Midi, EVENT_PACKET_SIZE};
+
+pub const DRIVER_NUM: usize = 0x90007;
+
+#[derive(Default)]
+pub struct App {
+    send_callback: Option<Callback>,
+    receive_callback: Option<Callback>,
+    tx_buffer: Option<AppSlice<Shared, u8>>,
+    rx_buffer: Option<AppSlice<Shared, u8>>,
+    // Bytes of the transmit buffer to send, and already sent.
+    tx_len: usize,
+    tx_offset: usize,
+    // Bytes of received packets in the receive buffer.
+    rx_len: usize,
+}
+
+pub struct UsbMidi<'a> {
+    midi: &'a Midi<'a>,
+    // The app whose packets are being sent.
+    sender: Cell<Option<AppId>>,
+    apps: Grant<App>,
+}
+
+impl<'a> UsbMidi<'a> {
+    pub fn new(midi: &'a Midi<'a>, grant: Grant<App>) -> UsbMidi<'a> {
+        UsbMidi {
+            midi: midi,
+            sender: Cell::new(None),
+            apps: grant,
+        }
+    }
+
+    /// Passes the next part of the app's packets to the function.
+    fn send_next(&self, app: &mut App) -> ReturnCode {
+        let result = app.tx_buffer.as_ref().map_or(Err(ReturnCode::ERESERVE), |slice| {
+            self.midi.send(&slice.as_ref()[app.tx_offset..app.tx_len])
+        });
+        match result {
+            Ok(count) => {
+                app.tx_offset += count * EVENT_PACKET_SIZE;
+                ReturnCode::SUCCESS
+            },
+            Err(err) => err,
+        }
+    }
+
+    fn send(&self, app: &mut App, count: usize, appid: AppId) -> ReturnCode {
+        if self.sender.get().is_some() {
+            return ReturnCode::EBUSY;
+        }
+        let len = match count.checked_mul(EVENT_PACKET_SIZE) {
+            Some(len) => len,
+            None => return ReturnCode::ESIZE,
+        };
+        if app.tx_buffer.as_ref().map_or(false, |slice| len > slice.len()) {
+            return ReturnCode::ESIZE;
+        }
+
+        app.tx_len = len;
+        app.tx_offset = 0;
+        let result = self.send_next(app);
+        if result == ReturnCode::SUCCESS {
+            self.sender.set(Some(appid));
+        }
+        result
+    }
+}
+
+impl<'a> midi::Client for UsbMidi<'a> {
+    fn packets_received(&self, packets: &[u8]) {
+        self.apps.each(|app| {
+            let rx_len = app.rx_len;
+            let appended = app.rx_buffer.as_mut().map_or(0, |slice| {
+                let space = slice.len() / EVENT_PACKET_SIZE * EVENT_PACKET_SIZE - rx_len;
+                let len = if packets.len() < space { packets.len() } else { space };
+                slice.as_mut()[rx_len..rx_len + len].copy_from_slice(&packets[..len]);
+                len
+            });
+            if appended > 0 {
+                app.rx_len += appended;
+                let count = app.rx_len / EVENT_PACKET_SIZE;
+                app.receive_callback.map(|mut cb| cb.schedule(count, 0, 0));
+            }
+        });
+    }
+
+    fn send_done(&self, result: ReturnCode) {
+        self.sender.take().map(|appid| {
+            let _ = self.apps.enter(appid, |app, _| {
+                let result = if result == ReturnCode::SUCCESS && app.tx_offset < app.tx_len {
+                    let result = self.send_next(app);
+                    if result == ReturnCode::SUCCESS {
+                        self.sender.set(Some(appid));
+                        return;
+                    }
+                    result
+                } else {
+                    result
+                };
+
+                let code = isize::from(result) as usize;
+                app.send_callback.map(|mut cb| cb.schedule(code, 0, 0));
+            });
+        });
+    }
+}
+
+impl<'a> Driver for UsbMidi<'a> {
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: Packets sent. The first argument is a return code.
+    /// - `1`: Packets received. The first argument is the number of packets
+    ///        in the receive buffer.
+    fn subscribe(&self, subscribe_num: usize, callback: Option<Callback>, app_id: AppId) -> ReturnCode {
+        match subscribe_num {
+            0 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.send_callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            1 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.receive_callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Setup packet buffers.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: Packets to send
+    /// - `1`: Buffer for received packets. Allowing it discards any packets
+    ///        that were not consumed.
+    fn allow(&self, appid: AppId, allow_num: usize, slice: Option<AppSlice<Shared, u8>>) -> ReturnCode {
+        match allow_num {
+            0 => {
+                self.apps.enter(appid, |app, _| {
+                    if self.sender.get().map_or(false, |sender| sender == appid) {
+                        return ReturnCode::EBUSY;
+                    }
+                    app.tx_buffer = slice;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            1 => {
+                self.apps.enter(appid, |app, _| {
+                    app.rx_buffer = slice;
+                    app.rx_len = 0;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Exchange packets.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Send the first `arg1` packets of the send buffer.
+    /// - `2`: Return the number of cables.
+    /// - `3`: Remove the first `arg1` packets from the receive buffer,
+    ///        moving the rest to its start.
+    /// - `4`: Return 1 if the host has configured the device.
+    fn command(&self, cmd_num: usize, arg1: usize, _: usize, appid: AppId) -> ReturnCode {
+        match cmd_num {
+            0 => ReturnCode::SUCCESS,
+            1 => {
+                self.apps.enter(appid, |app, _| {
+                    self.send(app, arg1, appid)
+                }).unwrap_or_else(|err| err.into())
+            },
+            2 => ReturnCode::SuccessWithValue { value: self.midi.cables() },
+            3 => {
+                self.apps.enter(appid, |app, _| {
+                    let consumed = match arg1.checked_mul(EVENT_PACKET_SIZE) {
+                        Some(consumed) if consumed <= app.rx_len => consumed,
+                        _ => return ReturnCode::EINVAL,
+                    };
+                    let rx_len = app.rx_len;
+                    app.rx_buffer.as_mut().map(|slice| {
+                        let data = slice.as_mut();
+                        for i in consumed..rx_len {
+                            data[i - consumed] = data[i];
+                        }
+                    });
+                    app.rx_len -= consumed;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            4 => ReturnCode::SuccessWithValue { value: self.midi.is_configured() as usize },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! A DHCP client [RFC 2131], which leases the stack an address, netmask and
+//! router.
+//!
+//! The client is driven by the stack: `tick` every second, and `receive`
+//! with each datagram to the client port. Both build any message to send
+//! in the buffer they are given, and say where to send it.
+
+use core::cell::Cell;
+use net::{self, Config, Ipv4Address, BROADCAST, UNSPECIFIED};
+
+pub const CLIENT_PORT: u16 = 68;
+pub const SERVER_PORT: u16 = 67;
+
+/// Messages are padded to the smallest BOOTP message.
+pub const MESSAGE_SIZE: usize = 300;
+const COOKIE_OFFSET: usize = 236;
+const OPTIONS_OFFSET: usize = 240;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+const BOOTREQUEST: u8 = 1;
+const BOOTREPLY: u8 = 2;
+const FLAG_BROADCAST: u16 = 1 << 15;
+
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_REQUESTED_ADDRESS: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_PARAMETER_LIST: u8 = 55;
+const OPTION_END: u8 = 255;
+
+const DHCPDISCOVER: u8 = 1;
+const DHCPOFFER: u8 = 2;
+const DHCPREQUEST: u8 = 3;
+const DHCPACK: u8 = 5;
+const DHCPNAK: u8 = 6;
+
+/// Seconds before the first retransmission, which doubles up to the most.
+const RETRY_SECONDS: u32 = 4;
+const MAX_RETRY_SECONDS: u32 = 64;
+/// Requests sent for an offer before starting over.
+const REQUEST_TRIES: u32 = 4;
+/// The least time between requests to extend a lease.
+const MIN_RENEW_SECONDS: u32 = 60;
+/// The lease assumed when the server gives none.
+const DEFAULT_LEASE_SECONDS: u32 = 3600;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum State {
+    Stopped,
+    Selecting,
+    Requesting,
+    Bound,
+    Renewing,
+    Rebinding,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Event {
+    None,
+    /// Send the message in the buffer, of length `len`.
+    Send {
+        source: Ipv4Address,
+        destination: Ipv4Address,
+        len: usize,
+    },
+    Bound(Config),
+    /// The lease ran out, or was refused, and the address must not be used.
+    Expired,
+}
+
+#[derive(Default)]
+struct Options {
+    message_type: Option<u8>,
+    netmask: Option<Ipv4Address>,
+    router: Option<Ipv4Address>,
+    lease_time: Option<u32>,
+    server: Option<Ipv4Address>,
+}
+
+fn parse_options(options: &[u8]) -> Options {
+    let mut parsed = Options::default();
+    let mut i = 0;
+    while i < options.len() {
+        let code = options[i];
+        if code == OPTION_END {
+            break;
+        }
+        if code == OPTION_PAD {
+            i += 1;
+            continue;
+        }
+        if i + 1 >= options.len() {
+            break;
+        }
+        let len = options[i + 1] as usize;
+        let start = i + 2;
+        if start + len > options.len() {
+            break;
+        }
+        let value = &options[start..start + len];
+        match (code, len) {
+            (OPTION_MESSAGE_TYPE, 1) => parsed.message_type = Some(value[0]),
+            (OPTION_SUBNET_MASK, 4) => parsed.netmask = Some(net::read_address(value)),
+            // The first router is the preferred one.
+            (OPTION_ROUTER, _) if len >= 4 => parsed.router = Some(net::read_address(value)),
+            (OPTION_LEASE_TIME, 4) => parsed.lease_time = Some(net::read_u32(value)),
+            (OPTION_SERVER_ID, 4) => parsed.server = Some(net::read_address(value)),
+            _ => {},
+        }
+        i = start + len;
+    }
+    parsed
+}
+
+pub struct Dhcp {
+    state: Cell<State>,
+    xid: Cell<u32>,
+    // Seconds until the next message is sent, and the wait after that one.
+    timer: Cell<u32>,
+    interval: Cell<u32>,
+    tries: Cell<u32>,
+    offered: Cell<Ipv4Address>,
+    server: Cell<Ipv4Address>,
+    lease: Cell<Option<Config>>,
+    // Seconds since the lease was granted, and when it is renewed, rebound
+    // and ends.
+    elapsed: Cell<u32>,
+    renew_time: Cell<u32>,
+    rebind_time: Cell<u32>,
+    lease_time: Cell<u32>,
+}
+
+impl Dhcp {
+    pub fn new() -> Dhcp {
+        Dhcp {
+            state: Cell::new(State::Stopped),
+            xid: Cell::new(0),
+            timer: Cell::new(0),
+            interval: Cell::new(RETRY_SECONDS),
+            tries: Cell::new(0),
+            offered: Cell::new(UNSPECIFIED),
+            server: Cell::new(UNSPECIFIED),
+            lease: Cell::new(None),
+            elapsed: Cell::new(0),
+            renew_time: Cell::new(0),
+            rebind_time: Cell::new(0),
+            lease_time: Cell::new(0),
+        }
+    }
+
+    /// Starts looking for a server. The transaction IDs are seeded from
+    /// `mac`, so that clients on one network differ.
+    pub fn start(&self, mac: [u8; 6]) {
+        self.xid.set(net::read_u32(&mac[2..6]) ^ (mac[1] as u32) << 8);
+        self.select();
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.state.get() != State::Stopped
+    }
+
+    /// Sends the next message without waiting, once the link comes up.
+    pub fn link_up(&self) {
+        match self.state.get() {
+            State::Selecting | State::Requesting => {
+                self.timer.set(0);
+                self.interval.set(RETRY_SECONDS);
+            },
+            _ => {},
+        }
+    }
+
+    fn select(&self) {
+        self.state.set(State::Selecting);
+        self.xid.set(self.xid.get().wrapping_add(1));
+        self.timer.set(0);
+        self.interval.set(RETRY_SECONDS);
+        self.lease.set(None);
+    }
+
+    fn send(&self, mac: [u8; 6], buf: &mut [u8]) -> Event {
+        let state = self.state.get();
+        let (message_type, source, destination) = match state {
+            State::Selecting => (DHCPDISCOVER, UNSPECIFIED, BROADCAST),
+            State::Requesting => (DHCPREQUEST, UNSPECIFIED, BROADCAST),
+            State::Renewing => {
+                let address = self.lease.get().map_or(UNSPECIFIED, |lease| lease.address);
+                (DHCPREQUEST, address, self.server.get())
+            },
+            State::Rebinding => {
+                let address = self.lease.get().map_or(UNSPECIFIED, |lease| lease.address);
+                (DHCPREQUEST, address, BROADCAST)
+            },
+            State::Stopped | State::Bound => return Event::None,
+        };
+
+        for byte in buf[..MESSAGE_SIZE].iter_mut() {
+            *byte = 0;
+        }
+        buf[0] = BOOTREQUEST;
+        buf[1] = 1;
+        buf[2] = 6;
+        net::write_u32(&mut buf[4..8], self.xid.get());
+        if source == UNSPECIFIED {
+            // The server cannot reach an address the client does not have.
+            net::write_u16(&mut buf[10..12], FLAG_BROADCAST);
+        }
+        buf[12..16].copy_from_slice(&source);
+        buf[28..34].copy_from_slice(&mac);
+        buf[COOKIE_OFFSET..OPTIONS_OFFSET].copy_from_slice(&MAGIC_COOKIE);
+
+        let mut i = OPTIONS_OFFSET;
+        buf[i..i + 3].copy_from_slice(&[OPTION_MESSAGE_TYPE, 1, message_type]);
+        i += 3;
+        if state == State::Requesting {
+            buf[i..i + 2].copy_from_slice(&[OPTION_REQUESTED_ADDRESS, 4]);
+            buf[i + 2..i + 6].copy_from_slice(&self.offered.get());
+            buf[i + 6..i + 8].copy_from_slice(&[OPTION_SERVER_ID, 4]);
+            buf[i + 8..i + 12].copy_from_slice(&self.server.get());
+            i += 12;
+        }
+        buf[i..i + 5].copy_from_slice(&[OPTION_PARAMETER_LIST, 3,
+                                        OPTION_SUBNET_MASK, OPTION_ROUTER, OPTION_LEASE_TIME]);
+        i += 5;
+        buf[i] = OPTION_END;
+
+        Event::Send {
+            source: source,
+            destination: destination,
+            len: MESSAGE_SIZE,
+        }
+    }
+
+    /// Counts down one second, and builds the next message in `buf` if one
+    /// is due.
+    pub fn tick(&self, mac: [u8; 6], buf: &mut [u8]) -> Event {
+        match self.state.get() {
+            State::Stopped => return Event::None,
+            State::Selecting | State::Requesting => {},
+            State::Bound | State::Renewing | State::Rebinding => {
+                let elapsed = self.elapsed.get().saturating_add(1);
+                self.elapsed.set(elapsed);
+                if elapsed >= self.lease_time.get() {
+                    self.select();
+                    return Event::Expired;
+                }
+                if elapsed >= self.rebind_time.get() && self.state.get() != State::Rebinding {
+                    self.state.set(State::Rebinding);
+                    self.timer.set(0);
+                } else if elapsed >= self.renew_time.get() && self.state.get() == State::Bound {
+                    self.state.set(State::Renewing);
+                    self.timer.set(0);
+                }
+                if self.state.get() == State::Bound {
+                    return Event::None;
+                }
+            },
+        }
+
+        if self.timer.get() > 0 {
+            self.timer.set(self.timer.get() - 1);
+            return Event::None;
+        }
+
+        match self.state.get() {
+            State::Requesting if self.tries.get() == REQUEST_TRIES => self.select(),
+            State::Requesting => self.tries.set(self.tries.get() + 1),
+            _ => {},
+        }
+        match self.state.get() {
+            State::Renewing | State::Rebinding => {
+                // Half the time left, as the RFC suggests.
+                let deadline = if self.state.get() == State::Renewing {
+                    self.rebind_time.get()
+                } else {
+                    self.lease_time.get()
+                };
+                let wait = (deadline - self.elapsed.get()) / 2;
+                self.timer.set(if wait < MIN_RENEW_SECONDS { MIN_RENEW_SECONDS } else { wait });
+            },
+            _ => {
+                self.timer.set(self.interval.get());
+                let interval = self.interval.get() * 2;
+                self.interval.set(if interval > MAX_RETRY_SECONDS { MAX_RETRY_SECONDS } else { interval });
+            },
+        }
+        self.send(mac, buf)
+    }
+
+    /// Handles a message from a server, and builds any reply in `buf`.
+    pub fn receive(&self, mac: [u8; 6], message: &[u8], buf: &mut [u8]) -> Event {
+        if message.len() < OPTIONS_OFFSET ||
+           message[0] != BOOTREPLY ||
+           net::read_u32(&message[4..8]) != self.xid.get() ||
+           message[28..34] != mac ||
+           message[COOKIE_OFFSET..OPTIONS_OFFSET] != MAGIC_COOKIE {
+            return Event::None;
+        }
+        let options = parse_options(&message[OPTIONS_OFFSET..]);
+        let address = net::read_address(&message[16..20]);
+
+        match (self.state.get(), options.message_type) {
+            (State::Selecting, Some(DHCPOFFER)) => {
+                let server = match options.server {
+                    Some(server) => server,
+                    None => return Event::None,
+                };
+                self.offered.set(address);
+                self.server.set(server);
+                self.state.set(State::Requesting);
+                self.tries.set(1);
+                self.timer.set(RETRY_SECONDS);
+                self.interval.set(RETRY_SECONDS * 2);
+                self.send(mac, buf)
+            },
+            (State::Requesting, Some(DHCPACK)) |
+            (State::Renewing, Some(DHCPACK)) |
+            (State::Rebinding, Some(DHCPACK)) => {
+                let lease = Config {
+                    address: address,
+                    netmask: options.netmask.unwrap_or([255, 255, 255, 0]),
+                    gateway: options.router,
+                };
+                let lease_time = options.lease_time.unwrap_or(DEFAULT_LEASE_SECONDS);
+                options.server.map(|server| self.server.set(server));
+                self.lease.set(Some(lease));
+                self.lease_time.set(lease_time);
+                self.renew_time.set(lease_time / 2);
+                self.rebind_time.set(lease_time - lease_time / 8);
+                self.elapsed.set(0);
+                self.state.set(State::Bound);
+                Event::Bound(lease)
+            },
+            (State::Requesting, Some(DHCPNAK)) => {
+                self.select();
+                Event::None
+            },
+            (State::Renewing, Some(DHCPNAK)) |
+            (State::Rebinding, Some(DHCPNAK)) => {
+                self.select();
+                Event::Expired
+            },
+            _ => Event::None,
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Carries IPv4 over the ENET MAC, finding the hardware addresses of hosts
+//! on the network with ARP [RFC 826].
+//!
+//! A packet to a host whose address is not cached waits while its address
+//! is asked for, and only one packet can wait at a time.
+
+use core::cell::Cell;
+use kernel::ReturnCode;
+use kernel::common::cells::TakeCell;
+use mk66::enet::{self, Enet, Timestamp};
+use net::{self, Ipv4Address, Link, LinkClient, BROADCAST, MTU, UNSPECIFIED};
+
+const HEADER_SIZE: usize = 14;
+pub const FRAME_SIZE: usize = HEADER_SIZE + MTU;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const BROADCAST_MAC: [u8; 6] = [0xFF; 6];
+
+const ARP_SIZE: usize = 28;
+const ARP_HARDWARE_ETHERNET: u16 = 1;
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+
+const CACHE_SIZE: usize = 8;
+/// Seconds a cached address is used for.
+const CACHE_LIFETIME: u32 = 300;
+/// Requests sent for a waiting packet's next hop, a second apart, before
+/// the packet is dropped.
+const ARP_TRIES: u32 = 3;
+
+pub static mut FRAME_BUF: [u8; FRAME_SIZE] = [0; FRAME_SIZE];
+pub static mut PENDING_BUF: [u8; FRAME_SIZE] = [0; FRAME_SIZE];
+
+#[derive(Copy, Clone)]
+struct Neighbor {
+    address: Ipv4Address,
+    mac: [u8; 6],
+    age: u32,
+}
+
+pub struct EthernetLink<'a> {
+    enet: &'a Enet<'a>,
+    client: Cell<Option<&'a LinkClient>>,
+    address: Cell<Ipv4Address>,
+    up: Cell<bool>,
+    cache: Cell<[Option<Neighbor>; CACHE_SIZE]>,
+    frame: TakeCell<'static, [u8]>,
+    // The frame waiting for its next hop's hardware address, and its length
+    // or 0.
+    pending: TakeCell<'static, [u8]>,
+    pending_len: Cell<usize>,
+    pending_hop: Cell<Ipv4Address>,
+    pending_tries: Cell<u32>,
+}
+
+impl<'a> EthernetLink<'a> {
+    pub fn new(enet: &'a Enet<'a>,
+               frame: &'static mut [u8],
+               pending: &'static mut [u8])
+               -> EthernetLink<'a> {
+        EthernetLink {
+            enet: enet,
+            client: Cell::new(None),
+            address: Cell::new(UNSPECIFIED),
+            up: Cell::new(false),
+            cache: Cell::new([None; CACHE_SIZE]),
+            frame: TakeCell::new(frame),
+            pending: TakeCell::new(pending),
+            pending_len: Cell::new(0),
+            pending_hop: Cell::new(UNSPECIFIED),
+            pending_tries: Cell::new(0),
+        }
+    }
+
+    fn lookup(&self, address: Ipv4Address) -> Option<[u8; 6]> {
+        self.cache.get().iter()
+            .filter_map(|entry| *entry)
+            .find(|neighbor| neighbor.address == address)
+            .map(|neighbor| neighbor.mac)
+    }
+
+    /// Caches `mac` for `address`, replacing the oldest entry if the cache
+    /// is full. Unless `create` is set, only an existing entry is updated.
+    fn learn(&self, address: Ipv4Address, mac: [u8; 6], create: bool) {
+        let mut cache = self.cache.get();
+        let existing = cache.iter().position(|entry| {
+            entry.map_or(false, |neighbor| neighbor.address == address)
+        });
+        let index = match existing {
+            Some(index) => index,
+            None if create => {
+                match cache.iter().position(|entry| entry.is_none()) {
+                    Some(index) => index,
+                    None => {
+                        (0..CACHE_SIZE).max_by_key(|&i| cache[i].map_or(0, |n| n.age)).unwrap_or(0)
+                    },
+                }
+            },
+            None => return,
+        };
+        cache[index] = Some(Neighbor {
+            address: address,
+            mac: mac,
+            age: 0,
+        });
+        self.cache.set(cache);
+    }
+
+    fn write_header(frame: &mut [u8], destination: [u8; 6], source: [u8; 6], ethertype: u16) {
+        frame[0..6].copy_from_slice(&destination);
+        frame[6..12].copy_from_slice(&source);
+        net::write_u16(&mut frame[12..14], ethertype);
+    }
+
+    fn transmit(&self, destination: [u8; 6], ethertype: u16, payload: &[u8]) -> ReturnCode {
+        let mac = self.enet.mac_address();
+        self.frame.map_or(ReturnCode::EBUSY, |frame| {
+            let len = HEADER_SIZE + payload.len();
+            Self::write_header(frame, destination, mac, ethertype);
+            frame[HEADER_SIZE..len].copy_from_slice(payload);
+            self.enet.transmit(&frame[..len], false)
+        })
+    }
+
+    fn send_arp(&self,
+                operation: u16,
+                destination: [u8; 6],
+                target_mac: [u8; 6],
+                target_address: Ipv4Address)
+                -> ReturnCode {
+        let mut arp = [0; ARP_SIZE];
+        net::write_u16(&mut arp[0..2], ARP_HARDWARE_ETHERNET);
+        net::write_u16(&mut arp[2..4], ETHERTYPE_IPV4);
+        arp[4] = 6;
+        arp[5] = 4;
+        net::write_u16(&mut arp[6..8], operation);
+        arp[8..14].copy_from_slice(&self.enet.mac_address());
+        arp[14..18].copy_from_slice(&self.address.get());
+        arp[18..24].copy_from_slice(&target_mac);
+        arp[24..28].copy_from_slice(&target_address);
+        self.transmit(destination, ETHERTYPE_ARP, &arp)
+    }
+
+    fn request(&self, address: Ipv4Address) -> ReturnCode {
+        self.send_arp(ARP_REQUEST, BROADCAST_MAC, [0; 6], address)
+    }
+
+    fn receive_arp(&self, arp: &[u8]) {
+        if arp.len() < ARP_SIZE ||
+           net::read_u16(&arp[0..2]) != ARP_HARDWARE_ETHERNET ||
+           net::read_u16(&arp[2..4]) != ETHERTYPE_IPV4 ||
+           arp[4] != 6 || arp[5] != 4 {
+            return;
+        }
+        let operation = net::read_u16(&arp[6..8]);
+        let mut sender_mac = [0; 6];
+        sender_mac.copy_from_slice(&arp[8..14]);
+        let sender = net::read_address(&arp[14..18]);
+        let target = net::read_address(&arp[24..28]);
+
+        let address = self.address.get();
+        if address == UNSPECIFIED || sender == UNSPECIFIED {
+            return;
+        }
+        let for_us = target == address;
+        self.learn(sender, sender_mac, for_us);
+        if for_us && operation == ARP_REQUEST {
+            let _ = self.send_arp(ARP_REPLY, sender_mac, sender_mac, sender);
+        }
+
+        if self.pending_len.get() > 0 && self.pending_hop.get() == sender {
+            self.send_pending(sender_mac);
+        }
+    }
+
+    fn send_pending(&self, destination: [u8; 6]) {
+        let len = self.pending_len.get();
+        self.pending_len.set(0);
+        self.pending.map(|frame| {
+            frame[0..6].copy_from_slice(&destination);
+            let _ = self.enet.transmit(&frame[..len], false);
+        });
+    }
+}
+
+impl<'a> Link<'a> for EthernetLink<'a> {
+    fn set_client(&self, client: &'a LinkClient) {
+        self.client.set(Some(client));
+    }
+
+    fn hardware_address(&self) -> Option<[u8; 6]> {
+        Some(self.enet.mac_address())
+    }
+
+    fn is_up(&self) -> bool {
+        self.up.get()
+    }
+
+    fn set_address(&self, address: Ipv4Address) {
+        self.address.set(address);
+        self.cache.set([None; CACHE_SIZE]);
+        // Announce the new address, so that neighbors update their caches.
+        if address != UNSPECIFIED && self.up.get() {
+            let _ = self.request(address);
+        }
+    }
+
+    fn send(&self, next_hop: Ipv4Address, packet: &[u8]) -> ReturnCode {
+        if !self.up.get() {
+            return ReturnCode::EOFF;
+        }
+        if packet.len() > MTU {
+            return ReturnCode::ESIZE;
+        }
+        if next_hop == BROADCAST {
+            return self.transmit(BROADCAST_MAC, ETHERTYPE_IPV4, packet);
+        }
+        if let Some(mac) = self.lookup(next_hop) {
+            return self.transmit(mac, ETHERTYPE_IPV4, packet);
+        }
+
+        if self.pending_len.get() > 0 {
+            return ReturnCode::EBUSY;
+        }
+        let mac = self.enet.mac_address();
+        let queued = self.pending.map(|frame| {
+            let len = HEADER_SIZE + packet.len();
+            Self::write_header(frame, BROADCAST_MAC, mac, ETHERTYPE_IPV4);
+            frame[HEADER_SIZE..len].copy_from_slice(packet);
+            self.pending_len.set(len);
+        });
+        if queued.is_none() {
+            return ReturnCode::EBUSY;
+        }
+        self.pending_hop.set(next_hop);
+        self.pending_tries.set(1);
+        let _ = self.request(next_hop);
+        ReturnCode::SUCCESS
+    }
+
+    fn tick(&self) {
+        let up = self.enet.link_status().is_some();
+        if up != self.up.get() {
+            self.up.set(up);
+            if !up {
+                self.pending_len.set(0);
+            } else if self.address.get() != UNSPECIFIED {
+                let _ = self.request(self.address.get());
+            }
+            self.client.get().map(|client| client.link_changed(up));
+        }
+
+        let mut cache = self.cache.get();
+        for entry in cache.iter_mut() {
+            *entry = entry.and_then(|neighbor| {
+                if neighbor.age + 1 >= CACHE_LIFETIME {
+                    None
+                } else {
+                    Some(Neighbor { age: neighbor.age + 1, ..neighbor })
+                }
+            });
+        }
+        self.cache.set(cache);
+
+        if self.pending_len.get() > 0 {
+            if self.pending_tries.get() == ARP_TRIES {
+                self.pending_len.set(0);
+            } else {
+                self.pending_tries.set(self.pending_tries.get() + 1);
+                let _ = self.request(self.pending_hop.get());
+            }
+        }
+    }
+}
+
+impl<'a> enet::Client for EthernetLink<'a> {
+    fn frame_received(&self, frame: &[u8], _timestamp: Timestamp) {
+        if frame.len() < HEADER_SIZE {
+            return;
+        }
+        let payload = &frame[HEADER_SIZE..];
+        match net::read_u16(&frame[12..14]) {
+            ETHERTYPE_IPV4 => {
+                self.client.get().map(|client| client.packet_received(payload));
+            },
+            ETHERTYPE_ARP => self.receive_arp(payload),
+            _ => {},
+        }
+    }
+
+    fn frame_sent(&self, _result: ReturnCode, _timestamp: Option<Timestamp>) {}
+}
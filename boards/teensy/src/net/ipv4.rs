@@ -0,0 +1,343 @@
+//! IPv4 [RFC 791], with ICMP echo [RFC 792] and UDP [RFC 768].
+//!
+//! Packets are built in one buffer and handed to the link, which copies
+//! them, so sending never waits. Fragments are dropped, and packets sent
+//! are never fragmented.
+
+use core::cell::Cell;
+use kernel::ReturnCode;
+use kernel::common::cells::TakeCell;
+use kernel::hil::time::{self, Alarm, Frequency};
+use net::{self, Config, Ipv4Address, Link, LinkClient, BROADCAST, MTU, UNSPECIFIED};
+use net::dhcp::{self, Dhcp, Event};
+
+const HEADER_SIZE: usize = 20;
+const UDP_HEADER_SIZE: usize = 8;
+const ICMP_HEADER_SIZE: usize = 8;
+
+/// The largest UDP payload that fits in one packet.
+pub const MAX_PAYLOAD: usize = MTU - HEADER_SIZE - UDP_HEADER_SIZE;
+
+const VERSION_IHL: u8 = 0x45;
+const FLAG_DONT_FRAGMENT: u16 = 1 << 14;
+const FRAGMENT_MASK: u16 = 0x3FFF;
+const TTL: u8 = 64;
+
+const PROTOCOL_ICMP: u8 = 1;
+const PROTOCOL_UDP: u8 = 17;
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+
+pub static mut BUF: [u8; MTU] = [0; MTU];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Endpoint {
+    pub address: Ipv4Address,
+    pub port: u16,
+}
+
+pub trait UdpClient {
+    /// Called with each datagram to a local port other than DHCP's.
+    fn datagram_received(&self, source: Endpoint, port: u16, payload: &[u8]);
+
+    /// Called when the stack gains, changes or loses its address.
+    fn config_changed(&self, config: Option<Config>);
+}
+
+fn pseudo_header_sum(source: Ipv4Address, destination: Ipv4Address, protocol: u8, len: usize) -> u32 {
+    let sum = net::checksum_add(0, &source);
+    let sum = net::checksum_add(sum, &destination);
+    sum + protocol as u32 + len as u32
+}
+
+pub struct Ipv4Stack<'a, A: Alarm + 'a> {
+    link: &'a Link<'a>,
+    alarm: &'a A,
+    client: Cell<Option<&'a UdpClient>>,
+    // The address the stack always has, on links without DHCP.
+    fixed: Option<Config>,
+    config: Cell<Option<Config>>,
+    dhcp: Dhcp,
+    buffer: TakeCell<'static, [u8]>,
+    identification: Cell<u16>,
+}
+
+impl<'a, A: Alarm + 'a> Ipv4Stack<'a, A> {
+    pub fn new(link: &'a Link<'a>,
+               alarm: &'a A,
+               fixed: Option<Config>,
+               buffer: &'static mut [u8])
+               -> Ipv4Stack<'a, A> {
+        Ipv4Stack {
+            link: link,
+            alarm: alarm,
+            client: Cell::new(None),
+            fixed: fixed,
+            config: Cell::new(None),
+            dhcp: Dhcp::new(),
+            buffer: TakeCell::new(buffer),
+            identification: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a UdpClient) {
+        self.client.set(Some(client));
+    }
+
+    /// Takes the fixed address, or starts DHCP.
+    pub fn start(&self) {
+        if self.fixed.is_some() {
+            self.set_config(self.fixed);
+        } else if let Some(mac) = self.link.hardware_address() {
+            self.dhcp.start(mac);
+        }
+        self.schedule_tick();
+    }
+
+    pub fn config(&self) -> Option<Config> {
+        self.config.get()
+    }
+
+    fn schedule_tick(&self) {
+        self.alarm.set_alarm(self.alarm.now().wrapping_add(A::Frequency::frequency()));
+    }
+
+    fn set_config(&self, config: Option<Config>) {
+        self.config.set(config);
+        self.link.set_address(config.map_or(UNSPECIFIED, |config| config.address));
+        self.client.get().map(|client| client.config_changed(config));
+    }
+
+    fn next_hop(&self, destination: Ipv4Address) -> Option<Ipv4Address> {
+        if destination == BROADCAST {
+            return Some(BROADCAST);
+        }
+        self.config.get().and_then(|config| {
+            if config.is_broadcast(destination) {
+                Some(BROADCAST)
+            } else if config.is_local(destination) {
+                Some(destination)
+            } else {
+                config.gateway
+            }
+        })
+    }
+
+    /// Whether a packet to `destination` is for this host. Until it has an
+    /// address, everything is, so that DHCP offers arrive.
+    fn accepts(&self, destination: Ipv4Address) -> bool {
+        self.config.get().map_or(true, |config| {
+            destination == config.address || config.is_broadcast(destination)
+        })
+    }
+
+    /// Adds an IPv4 header to the `len` bytes after it in `buf`, and sends
+    /// the packet. Packets with no route fail.
+    fn send_packet(&self,
+                   buf: &mut [u8],
+                   source: Ipv4Address,
+                   destination: Ipv4Address,
+                   protocol: u8,
+                   len: usize)
+                   -> ReturnCode {
+        let next_hop = match self.next_hop(destination) {
+            Some(next_hop) => next_hop,
+            None => return ReturnCode::FAIL,
+        };
+        let total = HEADER_SIZE + len;
+        let identification = self.identification.get();
+        self.identification.set(identification.wrapping_add(1));
+
+        buf[0] = VERSION_IHL;
+        buf[1] = 0;
+        net::write_u16(&mut buf[2..4], total as u16);
+        net::write_u16(&mut buf[4..6], identification);
+        net::write_u16(&mut buf[6..8], FLAG_DONT_FRAGMENT);
+        buf[8] = TTL;
+        buf[9] = protocol;
+        net::write_u16(&mut buf[10..12], 0);
+        buf[12..16].copy_from_slice(&source);
+        buf[16..20].copy_from_slice(&destination);
+        let checksum = net::checksum(&buf[..HEADER_SIZE]);
+        net::write_u16(&mut buf[10..12], checksum);
+
+        self.link.send(next_hop, &buf[..total])
+    }
+
+    /// Sends the `len` byte payload already in `buf` after the headers.
+    fn send_datagram(&self,
+                     buf: &mut [u8],
+                     source: Endpoint,
+                     destination: Endpoint,
+                     len: usize)
+                     -> ReturnCode {
+        let udp_len = UDP_HEADER_SIZE + len;
+        {
+            let udp = &mut buf[HEADER_SIZE..HEADER_SIZE + udp_len];
+            net::write_u16(&mut udp[0..2], source.port);
+            net::write_u16(&mut udp[2..4], destination.port);
+            net::write_u16(&mut udp[4..6], udp_len as u16);
+            net::write_u16(&mut udp[6..8], 0);
+            let sum = pseudo_header_sum(source.address, destination.address, PROTOCOL_UDP, udp_len);
+            let checksum = match net::checksum_finish(net::checksum_add(sum, udp)) {
+                // Zero means no checksum was computed.
+                0 => 0xFFFF,
+                checksum => checksum,
+            };
+            net::write_u16(&mut udp[6..8], checksum);
+        }
+        self.send_packet(buf, source.address, destination.address, PROTOCOL_UDP, udp_len)
+    }
+
+    /// Sends `payload` from local port `port` to `destination`.
+    pub fn send_udp(&self, port: u16, destination: Endpoint, payload: &[u8]) -> ReturnCode {
+        if payload.len() > MAX_PAYLOAD {
+            return ReturnCode::ESIZE;
+        }
+        let address = match self.config.get() {
+            Some(config) => config.address,
+            None => return ReturnCode::EOFF,
+        };
+        self.buffer.map_or(ReturnCode::EBUSY, |buf| {
+            let start = HEADER_SIZE + UDP_HEADER_SIZE;
+            buf[start..start + payload.len()].copy_from_slice(payload);
+            let source = Endpoint {
+                address: address,
+                port: port,
+            };
+            self.send_datagram(buf, source, destination, payload.len())
+        })
+    }
+
+    fn handle_dhcp(&self, event: Event) {
+        match event {
+            Event::None => {},
+            Event::Send { source, destination, len } => {
+                self.buffer.map(|buf| {
+                    let source = Endpoint {
+                        address: source,
+                        port: dhcp::CLIENT_PORT,
+                    };
+                    let destination = Endpoint {
+                        address: destination,
+                        port: dhcp::SERVER_PORT,
+                    };
+                    let _ = self.send_datagram(buf, source, destination, len);
+                });
+            },
+            Event::Bound(config) => {
+                if self.config.get() != Some(config) {
+                    self.set_config(Some(config));
+                }
+            },
+            Event::Expired => self.set_config(None),
+        }
+    }
+
+    /// Runs the DHCP client with the part of the buffer a message goes in.
+    fn run_dhcp<F: FnOnce(&mut [u8]) -> Event>(&self, f: F) {
+        let event = self.buffer.map(|buf| f(&mut buf[HEADER_SIZE + UDP_HEADER_SIZE..]));
+        event.map(|event| self.handle_dhcp(event));
+    }
+
+    fn receive_icmp(&self, source: Ipv4Address, destination: Ipv4Address, message: &[u8]) {
+        let address = match self.config.get() {
+            Some(config) if destination == config.address => config.address,
+            _ => return,
+        };
+        if message.len() < ICMP_HEADER_SIZE || message[0] != ICMP_ECHO_REQUEST ||
+           net::checksum(message) != 0 {
+            return;
+        }
+
+        self.buffer.map(|buf| {
+            {
+                let reply = &mut buf[HEADER_SIZE..HEADER_SIZE + message.len()];
+                reply.copy_from_slice(message);
+                reply[0] = ICMP_ECHO_REPLY;
+                net::write_u16(&mut reply[2..4], 0);
+                let checksum = net::checksum(reply);
+                net::write_u16(&mut reply[2..4], checksum);
+            }
+            let _ = self.send_packet(buf, address, source, PROTOCOL_ICMP, message.len());
+        });
+    }
+
+    fn receive_udp(&self, source: Ipv4Address, destination: Ipv4Address, datagram: &[u8]) {
+        if datagram.len() < UDP_HEADER_SIZE {
+            return;
+        }
+        let len = net::read_u16(&datagram[4..6]) as usize;
+        if len < UDP_HEADER_SIZE || len > datagram.len() {
+            return;
+        }
+        let datagram = &datagram[..len];
+        if net::read_u16(&datagram[6..8]) != 0 {
+            let sum = pseudo_header_sum(source, destination, PROTOCOL_UDP, len);
+            if net::checksum_finish(net::checksum_add(sum, datagram)) != 0 {
+                return;
+            }
+        }
+        let from = Endpoint {
+            address: source,
+            port: net::read_u16(&datagram[0..2]),
+        };
+        let port = net::read_u16(&datagram[2..4]);
+        let payload = &datagram[UDP_HEADER_SIZE..];
+
+        if port == dhcp::CLIENT_PORT && self.dhcp.is_started() {
+            let mac = self.link.hardware_address().unwrap_or([0; 6]);
+            self.run_dhcp(|message| self.dhcp.receive(mac, payload, message));
+        } else if self.config.get().is_some() {
+            self.client.get().map(|client| client.datagram_received(from, port, payload));
+        }
+    }
+}
+
+impl<'a, A: Alarm + 'a> LinkClient for Ipv4Stack<'a, A> {
+    fn packet_received(&self, packet: &[u8]) {
+        if packet.len() < HEADER_SIZE || packet[0] >> 4 != 4 {
+            return;
+        }
+        let header_len = (packet[0] & 0xF) as usize * 4;
+        let total = net::read_u16(&packet[2..4]) as usize;
+        if header_len < HEADER_SIZE || total < header_len || total > packet.len() ||
+           net::checksum(&packet[..header_len]) != 0 {
+            return;
+        }
+        if net::read_u16(&packet[6..8]) & FRAGMENT_MASK != 0 {
+            return;
+        }
+        let source = net::read_address(&packet[12..16]);
+        let destination = net::read_address(&packet[16..20]);
+        if !self.accepts(destination) {
+            return;
+        }
+
+        let payload = &packet[header_len..total];
+        match packet[9] {
+            PROTOCOL_ICMP => self.receive_icmp(source, destination, payload),
+            PROTOCOL_UDP => self.receive_udp(source, destination, payload),
+            _ => {},
+        }
+    }
+
+    fn link_changed(&self, up: bool) {
+        if up {
+            self.dhcp.link_up();
+        }
+    }
+}
+
+impl<'a, A: Alarm + 'a> time::Client for Ipv4Stack<'a, A> {
+    fn fired(&self) {
+        self.link.tick();
+        if self.link.is_up() {
+            if let Some(mac) = self.link.hardware_address() {
+                self.run_dhcp(|message| self.dhcp.tick(mac, message));
+            }
+        }
+        self.schedule_tick();
+    }
+}
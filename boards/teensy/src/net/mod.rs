@@ -0,0 +1,109 @@
+//! A small IPv4 network stack.
+//!
+//! `ipv4` answers pings and carries UDP datagrams over a `Link`, which
+//! moves whole IPv4 packets: `ethernet` runs over the ENET MAC and finds
+//! hardware addresses with ARP, and `slip` runs over a UART. Links with a
+//! hardware address are configured by `dhcp`; the others have a fixed
+//! address. There is no fragmentation, IP options or TCP.
+
+pub mod ethernet;
+pub mod slip;
+pub mod ipv4;
+pub mod dhcp;
+
+use kernel::ReturnCode;
+
+pub type Ipv4Address = [u8; 4];
+
+pub const UNSPECIFIED: Ipv4Address = [0; 4];
+pub const BROADCAST: Ipv4Address = [255; 4];
+
+/// The largest IPv4 packet a link carries.
+pub const MTU: usize = 1500;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Config {
+    pub address: Ipv4Address,
+    pub netmask: Ipv4Address,
+    pub gateway: Option<Ipv4Address>,
+}
+
+impl Config {
+    /// Whether `address` is on the local network, and so reached directly.
+    pub fn is_local(&self, address: Ipv4Address) -> bool {
+        (0..4).all(|i| address[i] & self.netmask[i] == self.address[i] & self.netmask[i])
+    }
+
+    pub fn is_broadcast(&self, address: Ipv4Address) -> bool {
+        address == BROADCAST ||
+            (self.is_local(address) && (0..4).all(|i| address[i] | self.netmask[i] == 0xFF))
+    }
+}
+
+pub trait Link<'a> {
+    fn set_client(&self, client: &'a LinkClient);
+
+    /// The link's hardware address, if it has one. Links with one are
+    /// configured by DHCP.
+    fn hardware_address(&self) -> Option<[u8; 6]>;
+
+    fn is_up(&self) -> bool;
+
+    /// Tells the link the stack's address, or `UNSPECIFIED`.
+    fn set_address(&self, address: Ipv4Address);
+
+    /// Sends an IPv4 packet to `next_hop`, which is a host on the link or
+    /// `BROADCAST`. The packet is copied, or queued while the next hop's
+    /// hardware address is found.
+    fn send(&self, next_hop: Ipv4Address, packet: &[u8]) -> ReturnCode;
+
+    /// Called every second.
+    fn tick(&self);
+}
+
+pub trait LinkClient {
+    fn packet_received(&self, packet: &[u8]);
+    fn link_changed(&self, up: bool);
+}
+
+pub fn read_u16(bytes: &[u8]) -> u16 {
+    (bytes[0] as u16) << 8 | bytes[1] as u16
+}
+
+pub fn write_u16(bytes: &mut [u8], value: u16) {
+    bytes[0] = (value >> 8) as u8;
+    bytes[1] = value as u8;
+}
+
+pub fn read_u32(bytes: &[u8]) -> u32 {
+    (read_u16(bytes) as u32) << 16 | read_u16(&bytes[2..]) as u32
+}
+
+pub fn write_u32(bytes: &mut [u8], value: u32) {
+    write_u16(bytes, (value >> 16) as u16);
+    write_u16(&mut bytes[2..], value as u16);
+}
+
+pub fn read_address(bytes: &[u8]) -> Ipv4Address {
+    [bytes[0], bytes[1], bytes[2], bytes[3]]
+}
+
+/// Adds `data` to a running ones' complement sum [RFC 1071]. Only the last
+/// piece summed may have an odd length.
+pub fn checksum_add(mut sum: u32, data: &[u8]) -> u32 {
+    for pair in data.chunks(2) {
+        sum += if pair.len() == 2 { read_u16(pair) } else { (pair[0] as u16) << 8 } as u32;
+    }
+    sum
+}
+
+pub fn checksum_finish(mut sum: u32) -> u16 {
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+pub fn checksum(data: &[u8]) -> u16 {
+    checksum_finish(checksum_add(0, data))
+}
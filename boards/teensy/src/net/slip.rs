@@ -0,0 +1,163 @@
+//! Carries IPv4 over a serial line with SLIP [RFC 1055], so that the stack
+//! can be reached from a host through `slattach`, without Ethernet.
+
+use core::cell::Cell;
+use kernel::ReturnCode;
+use kernel::common::cells::TakeCell;
+use kernel::hil::uart::{self, UART};
+use net::{Ipv4Address, Link, LinkClient, MTU};
+
+const END: u8 = 0xC0;
+const ESC: u8 = 0xDB;
+const ESC_END: u8 = 0xDC;
+const ESC_ESC: u8 = 0xDD;
+
+/// Every byte may need escaping, between two ENDs.
+pub const TX_SIZE: usize = 2 * MTU + 2;
+
+pub static mut TX_BUF: [u8; TX_SIZE] = [0; TX_SIZE];
+pub static mut RX_BUF: [u8; MTU] = [0; MTU];
+pub static mut BYTE_BUF: [u8; 1] = [0];
+
+pub struct SlipLink<'a, U: UART + 'a> {
+    uart: &'a U,
+    client: Cell<Option<&'a LinkClient>>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    byte_buffer: TakeCell<'static, [u8]>,
+    rx_len: Cell<usize>,
+    escaped: Cell<bool>,
+    // Set when a packet is too long, until its END.
+    overrun: Cell<bool>,
+}
+
+impl<'a, U: UART> SlipLink<'a, U> {
+    pub fn new(uart: &'a U,
+               tx_buffer: &'static mut [u8],
+               rx_buffer: &'static mut [u8],
+               byte_buffer: &'static mut [u8])
+               -> SlipLink<'a, U> {
+        SlipLink {
+            uart: uart,
+            client: Cell::new(None),
+            tx_buffer: TakeCell::new(tx_buffer),
+            rx_buffer: TakeCell::new(rx_buffer),
+            byte_buffer: TakeCell::new(byte_buffer),
+            rx_len: Cell::new(0),
+            escaped: Cell::new(false),
+            overrun: Cell::new(false),
+        }
+    }
+
+    pub fn initialize(&self, baud_rate: u32) {
+        self.uart.init(uart::UARTParams {
+            baud_rate: baud_rate,
+            stop_bits: uart::StopBits::One,
+            parity: uart::Parity::None,
+            hw_flow_control: false,
+        });
+        self.byte_buffer.take().map(|buf| self.uart.receive(buf, 1));
+    }
+
+    fn receive_byte(&self, byte: u8) {
+        let byte = match byte {
+            END => {
+                let len = self.rx_len.get();
+                if len > 0 && !self.overrun.get() {
+                    self.rx_buffer.map(|buf| {
+                        self.client.get().map(|client| client.packet_received(&buf[..len]));
+                    });
+                }
+                self.rx_len.set(0);
+                self.escaped.set(false);
+                self.overrun.set(false);
+                return;
+            },
+            ESC => {
+                self.escaped.set(true);
+                return;
+            },
+            ESC_END if self.escaped.get() => END,
+            ESC_ESC if self.escaped.get() => ESC,
+            byte => byte,
+        };
+        self.escaped.set(false);
+
+        let len = self.rx_len.get();
+        if len == MTU {
+            self.overrun.set(true);
+            return;
+        }
+        self.rx_buffer.map(|buf| buf[len] = byte);
+        self.rx_len.set(len + 1);
+    }
+}
+
+impl<'a, U: UART> Link<'a> for SlipLink<'a, U> {
+    fn set_client(&self, client: &'a LinkClient) {
+        self.client.set(Some(client));
+    }
+
+    fn hardware_address(&self) -> Option<[u8; 6]> {
+        None
+    }
+
+    fn is_up(&self) -> bool {
+        true
+    }
+
+    fn set_address(&self, _address: Ipv4Address) {}
+
+    fn send(&self, _next_hop: Ipv4Address, packet: &[u8]) -> ReturnCode {
+        if packet.len() > MTU {
+            return ReturnCode::ESIZE;
+        }
+        let buf = match self.tx_buffer.take() {
+            Some(buf) => buf,
+            None => return ReturnCode::EBUSY,
+        };
+
+        // Starting with END flushes any noise the peer has received.
+        let mut len = 0;
+        buf[len] = END;
+        len += 1;
+        for &byte in packet {
+            match byte {
+                END => {
+                    buf[len] = ESC;
+                    buf[len + 1] = ESC_END;
+                    len += 2;
+                },
+                ESC => {
+                    buf[len] = ESC;
+                    buf[len + 1] = ESC_ESC;
+                    len += 2;
+                },
+                byte => {
+                    buf[len] = byte;
+                    len += 1;
+                },
+            }
+        }
+        buf[len] = END;
+        len += 1;
+
+        self.uart.transmit(buf, len);
+        ReturnCode::SUCCESS
+    }
+
+    fn tick(&self) {}
+}
+
+impl<'a, U: UART> uart::Client for SlipLink<'a, U> {
+    fn transmit_complete(&self, buffer: &'static mut [u8], _error: uart::Error) {
+        self.tx_buffer.replace(buffer);
+    }
+
+    fn receive_complete(&self, buffer: &'static mut [u8], rx_len: usize, _error: uart::Error) {
+        if rx_len == 1 {
+            self.receive_byte(buffer[0]);
+        }
+        self.uart.receive(buffer, 1);
+    }
+}
@@ -1,10 +1,12 @@
 use capsules::led::ActivationMode;
 use mk66;
+use kernel::hil::gpio::InputMode;
 
 type PinHandle = &'static mk66::gpio::Gpio<'static>;
 
 pub unsafe fn configure_all_pins() -> (&'static [PinHandle],
-                                       &'static [(PinHandle, ActivationMode)]) {
+                                       &'static [(PinHandle, ActivationMode)],
+                                       &'static [PinHandle]) {
     use mk66::gpio::functions::*;
     use mk66::gpio::*;
 
@@ -44,6 +46,12 @@ pub unsafe fn configure_all_pins() -> (&'static [PinHandle],
     PB17.claim_as(UART0_TX);
     PB16.claim_as(UART0_RX);
 
+    // UART2 on pins 7 and 8, for SLIP.
+    PD02.release_claim();
+    PD03.release_claim();
+    PD02.claim_as(UART2_RX);
+    PD03.claim_as(UART2_TX);
+
     // SPI0
     PC04.release_claim();
     PC06.release_claim();
@@ -65,17 +73,77 @@ pub unsafe fn configure_all_pins() -> (&'static [PinHandle],
     PB03.claim_as(I2C0_SDA0);
     PB02.claim_as(I2C0_SCLK0);
 
+    // I2S0: MCLK on pin 35, BCLK on 9, LRCLK on 23, TX on 22 and RX on 38.
+    // Pin 38 is I2C1's SDA, so I2C1 is left out. The audio shield puts MCLK
+    // and its data out on pins 11 and 13 instead, which are SPI0's MOSI and
+    // the LED, so it has to be wired to 35 and 38 by hand.
+    PC08.release_claim();
+    PC03.release_claim();
+    PC02.release_claim();
+    PC01.release_claim();
     PC11.release_claim();
-    PC10.release_claim();
-    PC11.claim_as(I2C1_SDA0);
-    PC10.claim_as(I2C1_SCLK0);
+    PC08.claim_as(I2S0_MCLK);
+    PC03.claim_as(I2S0_TX_BCLK);
+    PC02.claim_as(I2S0_TX_FS);
+    PC01.claim_as(I2S0_TXD0);
+    PC11.claim_as(I2S0_RXD1);
+
+    // Touch button on pin 15.
+    PC00.release_claim();
+    PC00.claim_as(TSI0_CH13);
+
+    // Comparator CMP0's input 3 on pin 36.
+    PC09.release_claim();
+    PC09.claim_as(CMP0_IN3);
 
+    // Infrared LED driver on pin 5.
+    PD07.release_claim();
+    PD07.claim_as(CMT_IRO);
+
+    // CAN0 on pins 3 and 4, which rules out I2C2 there.
+    PA12.release_claim();
     PA13.release_claim();
-    PA14.release_claim();
-    PA13.claim_as(I2C2_SDA0);
-    PA14.claim_as(I2C2_SCLK0);
+    PA12.claim_as(CAN0_TX);
+    PA13.claim_as(CAN0_RX);
+
+    // FTM1 quadrature decoder on pins 16/17, FTM2 on pins 29/30.
+    PB00.release_claim();
+    PB01.release_claim();
+    PB00.claim_as(FTM1_QD_PHA0);
+    PB01.claim_as(FTM1_QD_PHB0);
+
+    PB18.release_claim();
+    PB19.release_claim();
+    PB18.claim_as(FTM2_QD_PHA);
+    PB19.claim_as(FTM2_QD_PHB);
+
+    // SDHC on the microSD socket. DAT3 is pulled down so that the card's
+    // own pull-up signals its presence.
+    PE00.claim_as(SDHC_D1);
+    PE01.claim_as(SDHC_D0);
+    PE02.claim_as(SDHC_DCLK);
+    PE03.claim_as(SDHC_CMD);
+    PE04.claim_as(SDHC_D3);
+    PE05.claim_as(SDHC_D2);
+    PE00.set_input_mode(InputMode::PullUp);
+    PE01.set_input_mode(InputMode::PullUp);
+    PE03.set_input_mode(InputMode::PullUp);
+    PE04.set_input_mode(InputMode::PullDown);
+    PE05.set_input_mode(InputMode::PullUp);
+    PE00.set_high_drive_strength(true);
+    PE01.set_high_drive_strength(true);
+    PE02.set_high_drive_strength(true);
+    PE03.set_high_drive_strength(true);
+    PE04.set_high_drive_strength(true);
+    PE05.set_high_drive_strength(true);
+
+    // Encoder index pulses stay GPIOs: pin 24 for FTM1, pin 25 for FTM2.
+    let qd_index_pins = static_init!(
+            [PinHandle; 2],
+            [gpio_pins[24], gpio_pins[25]]
+        );
 
     // I2C3 appears not to be used at all.
-    (gpio_pins, led_pins)
+    (gpio_pins, led_pins, qd_index_pins)
 }
 
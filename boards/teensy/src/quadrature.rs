@@ -0,0 +1,160 @@
+//! Provides userspace with access to the FTM quadrature decoders.
+//!
+//! Usage
+//! -----
+//!
+//! Positions are 48-bit signed encoder counts, so results are delivered
+//! through a callback rather than a return value:
+//!
+//! ```c
+//! subscribe(QUADRATURE_DRIVER_NUM, 0, callback);
+//! command(QUADRATURE_DRIVER_NUM, 1, decoder, 0); // position
+//! command(QUADRATURE_DRIVER_NUM, 2, decoder, 0); // velocity
+//! ```
+//!
+//! The callback receives the command number as its first argument. For a
+//! position read, the second and third arguments are the low and high 32 bits
+//! of the position. For a velocity read, the second argument is the signed
+//! velocity in counts per second, measured since this app's previous velocity
+//! read of the same decoder.
+
+use kernel::{AppId, Callback, Driver, Grant, ReturnCode};
+use mk66::ftm::Ftm;
+use mk66::pit::Uptime;
+
+pub const DRIVER_NUM: usize = 0x90000;
+
+const MAX_DECODERS: usize = 2;
+
+#[derive(Copy, Clone)]
+struct Sample {
+    position: i64,
+    time_us: u64,
+    valid: bool,
+}
+
+pub struct App {
+    callback: Option<Callback>,
+    samples: [Sample; MAX_DECODERS],
+}
+
+impl Default for App {
+    fn default() -> App {
+        App {
+            callback: None,
+            samples: [Sample { position: 0, time_us: 0, valid: false }; MAX_DECODERS],
+        }
+    }
+}
+
+pub struct Quadrature<'a, U: Uptime + 'a> {
+    decoders: &'a [&'a Ftm<'a>],
+    clock: &'a U,
+    apps: Grant<App>,
+}
+
+impl<'a, U: Uptime> Quadrature<'a, U> {
+    pub fn new(decoders: &'a [&'a Ftm<'a>],
+               clock: &'a U,
+               grant: Grant<App>)
+               -> Quadrature<'a, U> {
+        Quadrature {
+            decoders: decoders,
+            clock: clock,
+            apps: grant,
+        }
+    }
+
+    fn velocity(&self, index: usize, app: &mut App) -> i32 {
+        let now = self.clock.uptime_us();
+        let position = self.decoders[index].position();
+        let sample = &mut app.samples[index];
+
+        let elapsed = now - sample.time_us;
+        let velocity = if sample.valid && elapsed > 0 {
+            let counts = position - sample.position;
+            (counts * 1_000_000 / elapsed as i64) as i32
+        } else {
+            0
+        };
+
+        *sample = Sample { position: position, time_us: now, valid: true };
+        velocity
+    }
+}
+
+impl<'a, U: Uptime> Driver for Quadrature<'a, U> {
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: Position and velocity results
+    fn subscribe(&self, subscribe_num: usize, callback: Option<Callback>, app_id: AppId) -> ReturnCode {
+        match subscribe_num {
+            0 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Read and control the decoders.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check. Returns the number of decoders.
+    /// - `1`: Read the position of decoder `arg1`.
+    /// - `2`: Read the velocity of decoder `arg1`.
+    /// - `3`: Reset the position of decoder `arg1` to zero.
+    /// - `4`: Enable (`arg2` != 0) or disable index-pulse reset on decoder
+    ///        `arg1`.
+    fn command(&self, cmd_num: usize, arg1: usize, arg2: usize, appid: AppId) -> ReturnCode {
+        if cmd_num == 0 {
+            return ReturnCode::SuccessWithValue { value: self.decoders.len() };
+        }
+
+        if arg1 >= self.decoders.len() || arg1 >= MAX_DECODERS {
+            return ReturnCode::EINVAL;
+        }
+        let decoder = self.decoders[arg1];
+
+        match cmd_num {
+            1 /* position */ => {
+                self.apps.enter(appid, |app, _| {
+                    let position = decoder.position() as u64;
+                    app.callback.map(|mut cb| {
+                        cb.schedule(cmd_num, position as u32 as usize, (position >> 32) as u32 as usize);
+                    });
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            2 /* velocity */ => {
+                self.apps.enter(appid, |app, _| {
+                    let velocity = self.velocity(arg1, app);
+                    app.callback.map(|mut cb| {
+                        cb.schedule(cmd_num, velocity as usize, 0);
+                    });
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            3 /* reset */ => {
+                decoder.reset_position();
+                ReturnCode::SUCCESS
+            },
+            4 /* index reset */ => {
+                if arg2 == 0 {
+                    decoder.disable_index_reset();
+                    ReturnCode::SUCCESS
+                } else if decoder.enable_index_reset() {
+                    ReturnCode::SUCCESS
+                } else {
+                    ReturnCode::ENOSUPPORT
+                }
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
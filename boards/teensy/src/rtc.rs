@@ -0,0 +1,200 @@
+//! Provides userspace with wall-clock time from the real-time clock.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! // Seconds since the Unix epoch, as a return value.
+//! int now = command(RTC_DRIVER_NUM, 1, 0, 0);
+//!
+//! // The calendar date and time, packed into two callback arguments.
+//! subscribe(RTC_DRIVER_NUM, 0, callback);
+//! command(RTC_DRIVER_NUM, 3, 0, 0);
+//! ```
+//!
+//! The callback receives the command number as its first argument. Dates are
+//! packed as `year << 16 | month << 8 | day`, and times as
+//! `weekday << 24 | hour << 16 | minute << 8 | second`. All times are UTC.
+
+use core::cell::Cell;
+use kernel::{AppId, Callback, Driver, Grant, ReturnCode};
+use kernel::hil::time::Client;
+use mk66::rtc::{Rtc, DateTime};
+
+pub const DRIVER_NUM: usize = 0x90002;
+
+const CMD_DATETIME: usize = 3;
+const CMD_ALARM: usize = 5;
+
+#[derive(Default)]
+pub struct App {
+    callback: Option<Callback>,
+    alarm: Option<u32>,
+}
+
+pub struct RtcDriver<'a> {
+    rtc: &'a Rtc<'a>,
+    apps: Grant<App>,
+}
+
+fn pack_date(datetime: &DateTime) -> usize {
+    (datetime.year as usize) << 16 | (datetime.month as usize) << 8 | datetime.day as usize
+}
+
+fn pack_time(datetime: &DateTime) -> usize {
+    (datetime.weekday as usize) << 24 | (datetime.hour as usize) << 16 |
+        (datetime.minute as usize) << 8 | datetime.second as usize
+}
+
+fn unpack(date: usize, time: usize) -> DateTime {
+    DateTime {
+        year: (date >> 16) as u16,
+        month: (date >> 8) as u8,
+        day: date as u8,
+        weekday: 0,
+        hour: (time >> 16) as u8,
+        minute: (time >> 8) as u8,
+        second: time as u8,
+    }
+}
+
+impl<'a> RtcDriver<'a> {
+    pub fn new(rtc: &'a Rtc<'a>, grant: Grant<App>) -> RtcDriver<'a> {
+        RtcDriver {
+            rtc: rtc,
+            apps: grant,
+        }
+    }
+
+    // Programs the hardware alarm for the earliest pending app alarm.
+    fn reset_alarm(&self) {
+        let now = self.rtc.seconds();
+        let next: Cell<Option<u32>> = Cell::new(None);
+        self.apps.each(|app| {
+            app.alarm.map(|alarm| {
+                // Alarms in the past fire on the next second.
+                let alarm = if alarm <= now { now.wrapping_add(1) } else { alarm };
+                if next.get().map_or(true, |next| alarm < next) {
+                    next.set(Some(alarm));
+                }
+            });
+        });
+
+        match next.get() {
+            Some(alarm) => self.rtc.set_alarm(alarm),
+            None => self.rtc.disable_alarm(),
+        }
+    }
+}
+
+impl<'a> Client for RtcDriver<'a> {
+    fn fired(&self) {
+        let now = self.rtc.seconds();
+        self.apps.each(|app| {
+            if app.alarm.map_or(false, |alarm| alarm <= now) {
+                app.alarm = None;
+                app.callback.map(|mut cb| cb.schedule(CMD_ALARM, now as usize, 0));
+            }
+        });
+        self.reset_alarm();
+    }
+}
+
+impl<'a> Driver for RtcDriver<'a> {
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: Date reads and alarms
+    fn subscribe(&self, subscribe_num: usize, callback: Option<Callback>, app_id: AppId) -> ReturnCode {
+        match subscribe_num {
+            0 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Read and set the clock.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Return the seconds since the Unix epoch.
+    /// - `2`: Set the time to `arg1` seconds since the Unix epoch.
+    /// - `3`: Schedule a callback with the packed date and time.
+    /// - `4`: Set the time from the packed date `arg1` and time `arg2`.
+    /// - `5`: Schedule a callback when the time reaches `arg1` seconds since
+    ///        the Unix epoch. Replaces any earlier alarm from this app.
+    /// - `6`: Cancel this app's alarm.
+    /// - `7`: Return 1 if the time has been set since VBAT was last lost.
+    /// - `8`: Correct for crystal error: every `arg2` seconds (1-256), one
+    ///        second is `arg1` (-128 to 127) 32kHz cycles shorter.
+    fn command(&self, cmd_num: usize, arg1: usize, arg2: usize, appid: AppId) -> ReturnCode {
+        match cmd_num {
+            0 /* check if present */ => ReturnCode::SUCCESS,
+            1 /* seconds */ => {
+                ReturnCode::SuccessWithValue { value: self.rtc.seconds() as usize }
+            },
+            2 /* set seconds */ => {
+                self.rtc.set_seconds(arg1 as u32);
+                self.reset_alarm();
+                ReturnCode::SUCCESS
+            },
+            CMD_DATETIME => {
+                self.apps.enter(appid, |app, _| {
+                    let datetime = self.rtc.datetime();
+                    app.callback.map(|mut cb| {
+                        cb.schedule(cmd_num, pack_date(&datetime), pack_time(&datetime));
+                    });
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            4 /* set date */ => {
+                let datetime = unpack(arg1, arg2);
+                if !datetime.is_valid() {
+                    return ReturnCode::EINVAL;
+                }
+                self.rtc.set_datetime(&datetime);
+                self.reset_alarm();
+                ReturnCode::SUCCESS
+            },
+            CMD_ALARM => {
+                let result = self.apps.enter(appid, |app, _| {
+                    app.alarm = Some(arg1 as u32);
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into());
+                if result == ReturnCode::SUCCESS {
+                    self.reset_alarm();
+                }
+                result
+            },
+            6 /* cancel alarm */ => {
+                let result = self.apps.enter(appid, |app, _| {
+                    if app.alarm.take().is_some() {
+                        ReturnCode::SUCCESS
+                    } else {
+                        ReturnCode::EALREADY
+                    }
+                }).unwrap_or_else(|err| err.into());
+                self.reset_alarm();
+                result
+            },
+            7 /* time valid */ => {
+                ReturnCode::SuccessWithValue { value: self.rtc.is_time_valid() as usize }
+            },
+            8 /* set compensation */ => {
+                let cycles = arg1 as isize;
+                if cycles < -128 || cycles > 127 || arg2 == 0 || arg2 > 256 {
+                    return ReturnCode::EINVAL;
+                }
+                self.rtc.set_compensation(cycles as i8, arg2 as u32);
+                ReturnCode::SUCCESS
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Provides userspace with sector-level access to the microSD card.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! allow(SDCARD_DRIVER_NUM, 0, buffer, 512 * count);
+//! command(SDCARD_DRIVER_NUM, 1, 0, 0);         // initialize the card
+//! command(SDCARD_DRIVER_NUM, 3, sector, count); // read into buffer
+//! command(SDCARD_DRIVER_NUM, 4, sector, count); // write from buffer
+//! ```
+//!
+//! Transfers complete before the command returns. Apps can subscribe to be
+//! told when a card is inserted or removed; the callback's first argument is
+//! 1 for insertion and 0 for removal.
+//!
+//! Writes fail with `EBUSY` while a USB host has the card mounted. A write
+//! also unmounts the filesystem, which may have the written sectors cached,
+//! so files open through it are invalid afterwards.
+
+use core::cell::Cell;
+use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
+use kernel::common::cells::TakeCell;
+use mk66::sdhc::{self, BlockDevice};
+use usb::msc;
+
+pub const DRIVER_NUM: usize = 0x90004;
+
+/// Transfers go through a kernel buffer, which meets the controller's
+/// alignment requirements whatever the app's buffer alignment.
+#[repr(align(4))]
+pub struct SectorBuffer(pub [u8; sdhc::BLOCK_SIZE]);
+
+pub static mut BUFFER: SectorBuffer = SectorBuffer([0; sdhc::BLOCK_SIZE]);
+
+/// Told when an app writes sectors, so that anything read from the card
+/// before can be dropped.
+pub trait WriteClient {
+    fn sectors_written(&self);
+}
+
+#[derive(Default)]
+pub struct App {
+    callback: Option<Callback>,
+    buffer: Option<AppSlice<Shared, u8>>,
+}
+
+pub struct SdCard<'a> {
+    sdhc: &'a sdhc::Sdhc<'a>,
+    buffer: TakeCell<'static, SectorBuffer>,
+    host_mounted: Cell<bool>,
+    write_client: Cell<Option<&'a WriteClient>>,
+    apps: Grant<App>,
+}
+
+impl<'a> SdCard<'a> {
+    pub fn new(sdhc: &'a sdhc::Sdhc<'a>,
+               buffer: &'static mut SectorBuffer,
+               grant: Grant<App>)
+               -> SdCard<'a> {
+        SdCard {
+            sdhc: sdhc,
+            buffer: TakeCell::new(buffer),
+            host_mounted: Cell::new(false),
+            write_client: Cell::new(None),
+            apps: grant,
+        }
+    }
+
+    pub fn set_write_client(&self, client: &'a WriteClient) {
+        self.write_client.set(Some(client));
+    }
+
+    fn transfer(&self, sector: usize, count: usize, read: bool, app: &mut App) -> ReturnCode {
+        if !read && self.host_mounted.get() {
+            return ReturnCode::EBUSY;
+        }
+        let slice = match app.buffer {
+            Some(ref mut slice) => slice,
+            None => return ReturnCode::ERESERVE,
+        };
+        if count == 0 || count.checked_mul(sdhc::BLOCK_SIZE).map_or(true, |n| n > slice.len()) {
+            return ReturnCode::ESIZE;
+        }
+        if !self.sdhc.is_card_initialized() {
+            return ReturnCode::EOFF;
+        }
+        if sector as u64 + count as u64 > self.sdhc.block_count() as u64 {
+            return ReturnCode::ESIZE;
+        }
+
+        if !read {
+            self.write_client.get().map(|client| client.sectors_written());
+        }
+
+        self.buffer.map_or(ReturnCode::EBUSY, |buffer| {
+            let data = slice.as_mut();
+            for i in 0..count {
+                let block = (sector + i) as u32;
+                let app_block = &mut data[i * sdhc::BLOCK_SIZE..(i + 1) * sdhc::BLOCK_SIZE];
+
+                let result = if read {
+                    let result = self.sdhc.read_blocks(block, &mut buffer.0);
+                    app_block.copy_from_slice(&buffer.0);
+                    result
+                } else {
+                    buffer.0.copy_from_slice(app_block);
+                    self.sdhc.write_blocks(block, &buffer.0)
+                };
+                if result != ReturnCode::SUCCESS {
+                    return result;
+                }
+            }
+            ReturnCode::SUCCESS
+        })
+    }
+}
+
+impl<'a> sdhc::Client for SdCard<'a> {
+    fn card_detect_changed(&self, present: bool) {
+        self.apps.each(|app| {
+            app.callback.map(|mut cb| cb.schedule(present as usize, 0, 0));
+        });
+    }
+}
+
+impl<'a> msc::Client for SdCard<'a> {
+    fn host_mounted(&self, mounted: bool) {
+        self.host_mounted.set(mounted);
+    }
+}
+
+impl<'a> Driver for SdCard<'a> {
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: Card inserted or removed
+    fn subscribe(&self, subscribe_num: usize, callback: Option<Callback>, app_id: AppId) -> ReturnCode {
+        match subscribe_num {
+            0 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Setup the sector buffer.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: Buffer for reads and writes, a multiple of 512 bytes long
+    fn allow(&self, appid: AppId, allow_num: usize, slice: Option<AppSlice<Shared, u8>>) -> ReturnCode {
+        match allow_num {
+            0 => {
+                self.apps.enter(appid, |app, _| {
+                    app.buffer = slice;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Access the card.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Initialize the card. Must be repeated after the card is
+    ///        replaced.
+    /// - `2`: Return the number of sectors on the card.
+    /// - `3`: Read `arg2` sectors starting at sector `arg1` into the buffer.
+    /// - `4`: Write `arg2` sectors starting at sector `arg1` from the buffer.
+    /// - `5`: Return 1 if a card is present.
+    fn command(&self, cmd_num: usize, arg1: usize, arg2: usize, appid: AppId) -> ReturnCode {
+        match cmd_num {
+            0 /* check if present */ => ReturnCode::SUCCESS,
+            1 /* initialize */ => {
+                if self.sdhc.is_card_initialized() {
+                    ReturnCode::SUCCESS
+                } else {
+                    self.sdhc.initialize_card()
+                }
+            },
+            2 /* sector count */ => {
+                if self.sdhc.is_card_initialized() {
+                    ReturnCode::SuccessWithValue { value: self.sdhc.block_count() as usize }
+                } else {
+                    ReturnCode::EOFF
+                }
+            },
+            3 | 4 /* read, write */ => {
+                self.apps.enter(appid, |app, _| {
+                    self.transfer(arg1, arg2, cmd_num == 3, app)
+                }).unwrap_or_else(|err| err.into())
+            },
+            5 /* card present */ => {
+                ReturnCode::SuccessWithValue { value: self.sdhc.is_card_present() as usize }
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
@@ -2,27 +2,45 @@
 
 use mk66::{gpio, clock, pit};
 use kernel::hil::time::{Time, Alarm, Frequency, Client};
+use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use tests::blink;
 
+type TestAlarm = VirtualMuxAlarm<'static, pit::Pit<'static>>;
+
+// Tests take virtual alarms from the board's alarm mux, so they do not steal
+// the PIT from the userspace alarm driver.
+static mut MUX: Option<&'static MuxAlarm<'static, pit::Pit<'static>>> = None;
+
+pub fn set_mux(mux: &'static MuxAlarm<'static, pit::Pit<'static>>) {
+    unsafe { MUX = Some(mux); }
+}
+
+unsafe fn mux() -> &'static MuxAlarm<'static, pit::Pit<'static>> {
+    MUX.expect("Alarm tests need the alarm mux.")
+}
+
 static mut INTERVAL: u32 = 18_000_000;
 static mut UP: bool = false;
 
 static mut LAST_TIME: u32 = 0;
 
+static mut LED_ALARM: Option<&'static TestAlarm> = None;
+
 struct LedClient;
 impl Client for LedClient {
     fn fired(&self) {
         unsafe {
-            let now = pit::PIT.now();
-            let gap = now - LAST_TIME;
-            let wasted = gap - INTERVAL;
+            let alarm = LED_ALARM.unwrap();
+            let now = alarm.now();
+            let gap = now.wrapping_sub(LAST_TIME);
+            let wasted = gap.wrapping_sub(INTERVAL);
             blink::led_toggle();
             if INTERVAL > 18_000_000 || INTERVAL < 4_000_000 {
                 UP = !UP;
             }
             INTERVAL = if UP {INTERVAL + 1_000_000} else { INTERVAL - 1_000_000 };
             LAST_TIME = now;
-            pit::PIT.set_alarm(INTERVAL);
+            alarm.set_alarm(now.wrapping_add(INTERVAL));
             println!("Interval: {}, Time: {}, Gap: {}, Overhead: {}", INTERVAL, now, gap, wasted);
         }
     }
@@ -32,29 +50,43 @@ static LED: LedClient = LedClient;
 
 pub fn alarm_test() {
     unsafe {
-        pit::PIT.set_client(&LED);
-        pit::PIT.set_alarm(pit::PitFrequency::frequency() / 2);
+        let alarm = static_init!(TestAlarm, VirtualMuxAlarm::new(mux()));
+        alarm.set_client(&LED);
+        LED_ALARM = Some(alarm);
+        alarm.set_alarm(alarm.now().wrapping_add(pit::PitFrequency::frequency() / 2));
     }
 }
 
 struct LoopClient {
-    callback: Option<fn()>
+    callback: Option<fn()>,
+    alarm: Option<&'static TestAlarm>,
 }
-static mut LOOP: LoopClient = LoopClient { callback: None };
+static mut LOOP: LoopClient = LoopClient { callback: None, alarm: None };
 
 impl Client for LoopClient {
     fn fired(&self) {
-        unsafe {
-            self.callback.map(|cb| cb() );
-            pit::PIT.set_alarm(pit::PitFrequency::frequency() / 2);
-        }
+        self.callback.map(|cb| cb() );
+        self.alarm.map(|alarm| {
+            alarm.set_alarm(alarm.now().wrapping_add(pit::PitFrequency::frequency() / 2));
+        });
     }
 }
 
 pub fn loop_500ms(client: fn()) {
     unsafe {
+        let alarm = static_init!(TestAlarm, VirtualMuxAlarm::new(mux()));
+        alarm.set_client(&LOOP);
         LOOP.callback = Some(client);
-        pit::PIT.set_client(&LOOP);
-        pit::PIT.set_alarm(pit::PitFrequency::frequency() / 2);
+        LOOP.alarm = Some(alarm);
+        alarm.set_alarm(alarm.now().wrapping_add(pit::PitFrequency::frequency() / 2));
     }
 }
+
+/// Runs two independent virtual alarms at different rates. Both should keep
+/// their own period regardless of the other.
+pub fn multi_alarm_test() {
+    alarm_test();
+    loop_500ms(|| {
+        println!("500ms tick");
+    });
+}
@@ -0,0 +1,52 @@
+//! Checks that LPTMR alarms fire, including alarms set in the past.
+
+use kernel::hil::time::{Alarm, Client};
+use mk66::lptmr::{self, ClockSource};
+use tests::alarm;
+
+struct LptmrTest {
+    fired: bool,
+    // The half-second ticks so far.
+    ticks: usize,
+}
+
+static mut TEST: LptmrTest = LptmrTest { fired: false, ticks: 0 };
+
+struct LptmrClient;
+
+impl Client for LptmrClient {
+    fn fired(&self) {
+        unsafe { TEST.fired = true; }
+    }
+}
+
+static CLIENT: LptmrClient = LptmrClient;
+
+unsafe fn check(name: &str) {
+    println!("{}: {}", name, if TEST.fired { "passed" } else { "FAILED" });
+    TEST.fired = false;
+}
+
+/// Sets a past-due alarm, which should fire at once, and then an alarm 100
+/// ticks ahead, each checked half a second later.
+pub fn lptmr_test() {
+    unsafe {
+        let timer = &lptmr::LPTMR;
+        timer.init(ClockSource::Lpo1kHz);
+        timer.set_client(&CLIENT);
+        timer.set_alarm(timer.now().wrapping_sub(5));
+
+        alarm::loop_500ms(|| {
+            let timer = &lptmr::LPTMR;
+            match TEST.ticks {
+                0 => {
+                    check("Past-due alarm");
+                    timer.set_alarm(timer.now().wrapping_add(100));
+                },
+                1 => check("Alarm 100 ticks ahead"),
+                _ => {},
+            }
+            TEST.ticks += 1;
+        });
+    }
+}
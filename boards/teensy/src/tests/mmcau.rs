@@ -0,0 +1,65 @@
+//! Checks the MMCAU against known answers from FIPS 180-4 and FIPS 197.
+
+use mk66::mmcau::{AesKey, Digest, Sha1, Sha256};
+
+const ABC_SHA256: [u8; 32] = [
+    0xBA, 0x78, 0x16, 0xBF, 0x8F, 0x01, 0xCF, 0xEA, 0x41, 0x41, 0x40, 0xDE, 0x5D, 0xAE, 0x22, 0x23,
+    0xB0, 0x03, 0x61, 0xA3, 0x96, 0x17, 0x7A, 0x9C, 0xB4, 0x10, 0xFF, 0x61, 0xF2, 0x00, 0x15, 0xAD,
+];
+
+const ABC_SHA1: [u8; 20] = [
+    0xA9, 0x99, 0x3E, 0x36, 0x47, 0x06, 0x81, 0x6A, 0xBA, 0x3E,
+    0x25, 0x71, 0x78, 0x50, 0xC2, 0x6C, 0x9C, 0xD0, 0xD8, 0x9D,
+];
+
+// FIPS 197, Appendix C.
+const AES_PLAINTEXT: [u8; 16] = [
+    0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF,
+];
+
+const AES128_CIPHERTEXT: [u8; 16] = [
+    0x69, 0xC4, 0xE0, 0xD8, 0x6A, 0x7B, 0x04, 0x30, 0xD8, 0xCD, 0xB7, 0x80, 0x70, 0xB4, 0xC5, 0x5A,
+];
+
+const AES256_CIPHERTEXT: [u8; 16] = [
+    0x8E, 0xA2, 0xB7, 0xCA, 0x51, 0x67, 0x45, 0xBF, 0xEA, 0xFC, 0x49, 0x90, 0x4B, 0x49, 0x60, 0x89,
+];
+
+fn check(name: &str, result: &[u8], expected: &[u8]) -> bool {
+    let passed = result == expected;
+    println!("{}: {}", name, if passed { "passed" } else { "FAILED" });
+    passed
+}
+
+fn aes_check(name: &str, key_len: usize, expected: &[u8; 16]) -> bool {
+    let mut key = [0; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let key = AesKey::new(&key[..key_len]).unwrap();
+
+    let mut block = AES_PLAINTEXT;
+    key.encrypt_block(&mut block);
+    let encrypted = check(name, &block, expected);
+    key.decrypt_block(&mut block);
+    check(name, &block, &AES_PLAINTEXT) && encrypted
+}
+
+/// Returns whether every known answer matched.
+pub fn mmcau_test() -> bool {
+    let mut hash = [0; 32];
+
+    let mut sha256 = Sha256::new();
+    sha256.update(b"abc");
+    sha256.finish(&mut hash);
+    let mut passed = check("SHA-256", &hash, &ABC_SHA256);
+
+    let mut sha1 = Sha1::new();
+    sha1.update(b"abc");
+    sha1.finish(&mut hash);
+    passed &= check("SHA-1", &hash[..20], &ABC_SHA1);
+
+    passed &= aes_check("AES-128", 16, &AES128_CIPHERTEXT);
+    passed &= aes_check("AES-256", 32, &AES256_CIPHERTEXT);
+    passed
+}
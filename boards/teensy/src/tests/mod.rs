@@ -17,8 +17,19 @@ mod spi;
 #[allow(dead_code)]
 mod rng;
 
+#[allow(dead_code)]
+mod mmcau;
+
+#[allow(dead_code)]
+mod lptmr;
+
+use mk66;
+use capsules::virtual_alarm::MuxAlarm;
+
 // Set this function to run whatever test you desire. Test functions are named XXX_test by convention.
-pub fn test() {
+pub fn test(mux_alarm: &'static MuxAlarm<'static, mk66::pit::Pit<'static>>) {
+    alarm::set_mux(mux_alarm);
+
     spi::spi_test();
 }
 
@@ -0,0 +1,171 @@
+//! Reports touches of capacitive touch buttons to apps.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! int count = command(TOUCH_DRIVER_NUM, 0, 0, 0);
+//! subscribe(TOUCH_DRIVER_NUM, 0, touched, NULL);
+//! command(TOUCH_DRIVER_NUM, 1, 0, 0); // events for button 0
+//! // `touched(button, pressed, 0, ud)` is called on press and release.
+//! ```
+//!
+//! Buttons are the touch pins the board sets up, numbered from 0. They are
+//! scanned 50 times a second, whether or not an app is listening. Each one
+//! takes its baseline from its first scan, so it should not be touched
+//! while the board starts, or it should be recalibrated afterwards.
+
+use core::cell::Cell;
+use kernel::{AppId, Callback, Driver, Grant, ReturnCode};
+use kernel::hil::time::{self, Alarm, Frequency};
+use mk66::tsi::{self, Tsi};
+
+pub const DRIVER_NUM: usize = 0x9000D;
+
+const SCANS_PER_SECOND: u32 = 50;
+
+#[derive(Default)]
+pub struct App {
+    callback: Option<Callback>,
+    // Bit n is set if the app wants events for button n.
+    subscribed: u32,
+}
+
+pub struct TouchButtons<'a, A: Alarm + 'a> {
+    tsi: &'a Tsi<'a>,
+    alarm: &'a A,
+    // The TSI channel of each button.
+    channels: &'a [usize],
+    started: Cell<bool>,
+    apps: Grant<App>,
+}
+
+impl<'a, A: Alarm + 'a> TouchButtons<'a, A> {
+    pub fn new(tsi: &'a Tsi<'a>,
+               alarm: &'a A,
+               channels: &'a [usize],
+               grant: Grant<App>) -> TouchButtons<'a, A> {
+        TouchButtons {
+            tsi: tsi,
+            alarm: alarm,
+            channels: channels,
+            started: Cell::new(false),
+            apps: grant,
+        }
+    }
+
+    /// Enables every button's channel and starts scanning.
+    pub fn start(&self) {
+        if self.started.get() {
+            return;
+        }
+        for &channel in self.channels.iter() {
+            self.tsi.enable_channel(channel, 0);
+        }
+        self.started.set(true);
+        self.tsi.scan();
+    }
+
+    fn schedule_scan(&self) {
+        let interval = A::Frequency::frequency() / SCANS_PER_SECOND;
+        self.alarm.set_alarm(self.alarm.now().wrapping_add(interval));
+    }
+}
+
+impl<'a, A: Alarm + 'a> tsi::Client for TouchButtons<'a, A> {
+    fn touch_changed(&self, channel: usize, touched: bool) {
+        let button = match self.channels.iter().position(|&c| c == channel) {
+            Some(button) => button,
+            None => return,
+        };
+        self.apps.each(|app| {
+            if app.subscribed & (1 << button) != 0 {
+                app.callback.map(|mut cb| cb.schedule(button, touched as usize, 0));
+            }
+        });
+    }
+
+    fn scan_done(&self) {
+        self.schedule_scan();
+    }
+}
+
+impl<'a, A: Alarm + 'a> time::Client for TouchButtons<'a, A> {
+    fn fired(&self) {
+        if self.tsi.scan() != ReturnCode::SUCCESS {
+            self.schedule_scan();
+        }
+    }
+}
+
+impl<'a, A: Alarm + 'a> Driver for TouchButtons<'a, A> {
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: A button was pressed or released. The arguments are the
+    ///        button and 1 for a press or 0 for a release.
+    fn subscribe(&self, subscribe_num: usize, callback: Option<Callback>, app_id: AppId) -> ReturnCode {
+        match subscribe_num {
+            0 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Use the buttons.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return the number of buttons.
+    /// - `1`: Enable events for button `arg1`.
+    /// - `2`: Disable events for button `arg1`.
+    /// - `3`: Return 1 if button `arg1` is touched, else 0.
+    /// - `4`: Return button `arg1`'s last count.
+    /// - `5`: Return button `arg1`'s baseline.
+    /// - `6`: Set button `arg1`'s threshold to `arg2` counts above its
+    ///        baseline, or to 1/8 of its baseline if `arg2` is 0. This
+    ///        recalibrates it.
+    /// - `7`: Recalibrate button `arg1` at its next scan.
+    fn command(&self, cmd_num: usize, arg1: usize, arg2: usize, appid: AppId) -> ReturnCode {
+        if cmd_num == 0 {
+            return ReturnCode::SuccessWithValue { value: self.channels.len() };
+        }
+        let channel = match self.channels.get(arg1) {
+            Some(&channel) => channel,
+            None => return ReturnCode::EINVAL,
+        };
+        match cmd_num {
+            1 => {
+                self.apps.enter(appid, |app, _| {
+                    app.subscribed |= 1 << arg1;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            2 => {
+                self.apps.enter(appid, |app, _| {
+                    app.subscribed &= !(1 << arg1);
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            3 => ReturnCode::SuccessWithValue { value: self.tsi.is_touched(channel) as usize },
+            4 => ReturnCode::SuccessWithValue { value: self.tsi.count(channel) as usize },
+            5 => ReturnCode::SuccessWithValue { value: self.tsi.baseline(channel) as usize },
+            6 => {
+                if arg2 > 0xFFFF {
+                    return ReturnCode::EINVAL;
+                }
+                self.tsi.enable_channel(channel, arg2 as u16)
+            },
+            7 => {
+                self.tsi.recalibrate(channel);
+                ReturnCode::SUCCESS
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
@@ -0,0 +1,274 @@
+//! Lets apps send and receive UDP datagrams.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! command(UDP_DRIVER_NUM, 1, 7000, 0); // bind port 7000
+//!
+//! // Receiving
+//! allow(UDP_DRIVER_NUM, 1, rx_buffer, sizeof(rx_buffer));
+//! subscribe(UDP_DRIVER_NUM, 0, received, NULL);
+//! // In `received(len, address, port, ud)`, handle the datagram, then
+//! command(UDP_DRIVER_NUM, 3, 0, 0);
+//!
+//! // Sending
+//! allow(UDP_DRIVER_NUM, 0, payload, len);
+//! command(UDP_DRIVER_NUM, 2, address, port);
+//! ```
+//!
+//! Each app binds one port, which no other app may have. Addresses are
+//! passed as words holding the address's bytes in memory order, as
+//! `inet_addr` returns them. Datagrams are received one at a time: while
+//! the app has not released the last one, further datagrams to its port
+//! are dropped. Datagrams longer than the receive buffer are truncated.
+
+use core::cell::Cell;
+use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
+use kernel::hil::time::Alarm;
+use net::{Config, Ipv4Address};
+use net::ipv4::{Endpoint, Ipv4Stack, UdpClient};
+
+pub const DRIVER_NUM: usize = 0x9000B;
+
+const EPHEMERAL_PORTS: u16 = 49152;
+
+#[derive(Default)]
+pub struct App {
+    receive_callback: Option<Callback>,
+    config_callback: Option<Callback>,
+    tx_buffer: Option<AppSlice<Shared, u8>>,
+    rx_buffer: Option<AppSlice<Shared, u8>>,
+    port: Option<u16>,
+    // Whether the receive buffer holds a datagram the app has not released.
+    rx_full: bool,
+}
+
+pub struct Udp<'a, A: Alarm + 'a> {
+    stack: &'a Ipv4Stack<'a, A>,
+    next_port: Cell<u16>,
+    apps: Grant<App>,
+}
+
+fn pack(address: Ipv4Address) -> usize {
+    address[0] as usize | (address[1] as usize) << 8 |
+        (address[2] as usize) << 16 | (address[3] as usize) << 24
+}
+
+fn unpack(address: usize) -> Ipv4Address {
+    [address as u8, (address >> 8) as u8, (address >> 16) as u8, (address >> 24) as u8]
+}
+
+impl<'a, A: Alarm + 'a> Udp<'a, A> {
+    pub fn new(stack: &'a Ipv4Stack<'a, A>, grant: Grant<App>) -> Udp<'a, A> {
+        Udp {
+            stack: stack,
+            next_port: Cell::new(EPHEMERAL_PORTS),
+            apps: grant,
+        }
+    }
+
+    fn port_in_use(&self, port: u16) -> bool {
+        let mut in_use = false;
+        self.apps.each(|app| {
+            if app.port == Some(port) {
+                in_use = true;
+            }
+        });
+        in_use
+    }
+
+    /// Picks the next free port from the ephemeral range.
+    fn ephemeral_port(&self) -> Option<u16> {
+        for _ in EPHEMERAL_PORTS as u32..0x10000 {
+            let port = self.next_port.get();
+            self.next_port.set(if port == 0xFFFF { EPHEMERAL_PORTS } else { port + 1 });
+            if !self.port_in_use(port) {
+                return Some(port);
+            }
+        }
+        None
+    }
+
+    fn bind(&self, port: usize, appid: AppId) -> ReturnCode {
+        if port > 0xFFFF {
+            return ReturnCode::EINVAL;
+        }
+        let unbound = self.apps.enter(appid, |app, _| {
+            app.port = None;
+            ReturnCode::SUCCESS
+        }).unwrap_or_else(|err| err.into());
+        if unbound != ReturnCode::SUCCESS {
+            return unbound;
+        }
+
+        let port = match port {
+            0 => match self.ephemeral_port() {
+                Some(port) => port,
+                None => return ReturnCode::ENOMEM,
+            },
+            port if self.port_in_use(port as u16) => return ReturnCode::EBUSY,
+            port => port as u16,
+        };
+        self.apps.enter(appid, |app, _| {
+            app.port = Some(port);
+            ReturnCode::SuccessWithValue { value: port as usize }
+        }).unwrap_or_else(|err| err.into())
+    }
+
+    fn send(&self, app: &mut App, destination: Endpoint) -> ReturnCode {
+        let port = match app.port {
+            Some(port) => port,
+            None => return ReturnCode::ERESERVE,
+        };
+        app.tx_buffer.as_ref().map_or(ReturnCode::ERESERVE, |slice| {
+            self.stack.send_udp(port, destination, slice.as_ref())
+        })
+    }
+}
+
+impl<'a, A: Alarm + 'a> UdpClient for Udp<'a, A> {
+    fn datagram_received(&self, source: Endpoint, port: u16, payload: &[u8]) {
+        self.apps.each(|app| {
+            if app.port != Some(port) || app.rx_full {
+                return;
+            }
+            let copied = app.rx_buffer.as_mut().map(|slice| {
+                let len = if payload.len() < slice.len() { payload.len() } else { slice.len() };
+                slice.as_mut()[..len].copy_from_slice(&payload[..len]);
+                len
+            });
+            if let Some(len) = copied {
+                app.rx_full = true;
+                app.receive_callback.map(|mut cb| {
+                    cb.schedule(len, pack(source.address), source.port as usize)
+                });
+            }
+        });
+    }
+
+    fn config_changed(&self, config: Option<Config>) {
+        self.apps.each(|app| {
+            app.config_callback.map(|mut cb| {
+                match config {
+                    Some(config) => cb.schedule(pack(config.address),
+                                                pack(config.netmask),
+                                                config.gateway.map_or(0, pack)),
+                    None => cb.schedule(0, 0, 0),
+                }
+            });
+        });
+    }
+}
+
+impl<'a, A: Alarm + 'a> Driver for Udp<'a, A> {
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: Datagram received. The arguments are its length in the
+    ///        receive buffer, and its source address and port.
+    /// - `1`: Address changed. The arguments are the address, netmask and
+    ///        router, or 0 while there is no address.
+    fn subscribe(&self, subscribe_num: usize, callback: Option<Callback>, app_id: AppId) -> ReturnCode {
+        match subscribe_num {
+            0 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.receive_callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            1 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.config_callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Setup datagram buffers.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: Payload to send
+    /// - `1`: Buffer for received datagrams. Allowing it releases any
+    ///        datagram in the old one.
+    fn allow(&self, appid: AppId, allow_num: usize, slice: Option<AppSlice<Shared, u8>>) -> ReturnCode {
+        match allow_num {
+            0 => {
+                self.apps.enter(appid, |app, _| {
+                    app.tx_buffer = slice;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            1 => {
+                self.apps.enter(appid, |app, _| {
+                    app.rx_buffer = slice;
+                    app.rx_full = false;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Use the network.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Bind port `arg1`, or a free port if it is 0. Returns the port.
+    /// - `2`: Send the payload buffer to address `arg1`, port `arg2`, from
+    ///        the bound port. Returns `EOFF` while there is no address.
+    /// - `3`: Release the received datagram, so that another can arrive.
+    /// - `4`: Unbind the port.
+    /// - `5`: Call the address callback with the current address.
+    fn command(&self, cmd_num: usize, arg1: usize, arg2: usize, appid: AppId) -> ReturnCode {
+        match cmd_num {
+            0 => ReturnCode::SUCCESS,
+            1 => self.bind(arg1, appid),
+            2 => {
+                if arg2 > 0xFFFF {
+                    return ReturnCode::EINVAL;
+                }
+                let destination = Endpoint {
+                    address: unpack(arg1),
+                    port: arg2 as u16,
+                };
+                self.apps.enter(appid, |app, _| {
+                    self.send(app, destination)
+                }).unwrap_or_else(|err| err.into())
+            },
+            3 => {
+                self.apps.enter(appid, |app, _| {
+                    app.rx_full = false;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            4 => {
+                self.apps.enter(appid, |app, _| {
+                    app.port = None;
+                    app.rx_full = false;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            5 => {
+                let config = self.stack.config();
+                self.apps.enter(appid, |app, _| {
+                    app.config_callback.map_or(ReturnCode::ERESERVE, |mut cb| {
+                        match config {
+                            Some(config) => cb.schedule(pack(config.address),
+                                                        pack(config.netmask),
+                                                        config.gateway.map_or(0, pack)),
+                            None => cb.schedule(0, 0, 0),
+                        };
+                        ReturnCode::SUCCESS
+                    })
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
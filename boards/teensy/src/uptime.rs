@@ -0,0 +1,84 @@
+//! Provides userspace with the time since boot from a 64-bit clock.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! // Whole seconds since boot, as a return value.
+//! int secs = command(UPTIME_DRIVER_NUM, 2, 0, 0);
+//!
+//! // Microseconds since boot, split into two 32-bit callback arguments.
+//! subscribe(UPTIME_DRIVER_NUM, 0, callback);
+//! command(UPTIME_DRIVER_NUM, 1, 0, 0);
+//! ```
+
+use kernel::{AppId, Callback, Driver, Grant, ReturnCode};
+use mk66::pit::Uptime;
+
+pub const DRIVER_NUM: usize = 0x90001;
+
+#[derive(Default)]
+pub struct App {
+    callback: Option<Callback>,
+}
+
+pub struct UptimeDriver<'a, U: Uptime + 'a> {
+    clock: &'a U,
+    apps: Grant<App>,
+}
+
+impl<'a, U: Uptime> UptimeDriver<'a, U> {
+    pub fn new(clock: &'a U, grant: Grant<App>) -> UptimeDriver<'a, U> {
+        UptimeDriver {
+            clock: clock,
+            apps: grant,
+        }
+    }
+}
+
+impl<'a, U: Uptime> Driver for UptimeDriver<'a, U> {
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: Microsecond uptime result
+    fn subscribe(&self, subscribe_num: usize, callback: Option<Callback>, app_id: AppId) -> ReturnCode {
+        match subscribe_num {
+            0 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Read the uptime.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Schedule a callback with the low and high 32 bits of the uptime
+    ///        in microseconds.
+    /// - `2`: Return the uptime in whole seconds.
+    fn command(&self, cmd_num: usize, _: usize, _: usize, appid: AppId) -> ReturnCode {
+        match cmd_num {
+            0 /* check if present */ => ReturnCode::SUCCESS,
+            1 /* microseconds */ => {
+                self.apps.enter(appid, |app, _| {
+                    let us = self.clock.uptime_us();
+                    app.callback.map(|mut cb| {
+                        cb.schedule(us as u32 as usize, (us >> 32) as u32 as usize, 0);
+                    });
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            2 /* seconds */ => {
+                let secs = self.clock.uptime_us() / 1_000_000;
+                ReturnCode::SuccessWithValue { value: secs as usize }
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
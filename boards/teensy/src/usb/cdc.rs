@@ -0,0 +1,405 @@
+//! A CDC-ACM serial port, the USB function hosts expose as a virtual COM
+//! port.
+//!
+//! The port implements `hil::uart::UART`, so the console runs on it as it
+//! would on a hardware UART. Line coding requests are accepted and
+//! ignored, since there is no physical line. Data is only sent while the
+//! host has the port open, as signalled by DTR; writes made while it is
+//! closed complete immediately and are dropped.
+//!
+//! Kernel log output (`print!` and panics) is kept in a separate ring
+//! buffer. It is sent after any pending console data, and is held until the
+//! host opens the port, so messages from boot are not lost.
+
+use core::cell::Cell;
+use core::cmp;
+use kernel::common::cells::{TakeCell, VolatileCell};
+use kernel::hil::uart::{self, UART};
+use kernel::hil::usb::{InResult, OutResult, TransferType, UsbController};
+use mk66::usb::{Usb, MAX_PACKET_SIZE};
+use usb::device::{descriptor_type, Function, Reply, RequestKind, SetupData};
+
+pub static mut LOG_BUF: [u8; 1024] = [0; 1024];
+
+// [USB PSTN Subclass Specification, Table 13]
+const SET_LINE_CODING: u8 = 0x20;
+const GET_LINE_CODING: u8 = 0x21;
+const SET_CONTROL_LINE_STATE: u8 = 0x22;
+const SEND_BREAK: u8 = 0x23;
+
+const CS_INTERFACE: u8 = 0x24;
+
+const DESCRIPTORS_SIZE: usize = 66;
+
+/// How many times `flush` polls the controller before giving up on a host
+/// that is not reading.
+const FLUSH_POLLS: usize = 1_000_000;
+
+pub struct CdcAcm<'a> {
+    usb: &'a Usb<'a>,
+    // The communication interface; the data interface follows it.
+    interface: u8,
+    notify_endpoint: usize,
+    out_endpoint: usize,
+    in_endpoint: usize,
+    in_buffer: [VolatileCell<u8>; MAX_PACKET_SIZE],
+    out_buffer: [VolatileCell<u8>; MAX_PACKET_SIZE],
+    // 115200 baud, 1 stop bit, no parity, 8 data bits.
+    line_coding: Cell<[u8; 7]>,
+    configured: Cell<bool>,
+    connected: Cell<bool>,
+    client: Cell<Option<&'static uart::Client>>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    tx_offset: Cell<usize>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    rx_len: Cell<usize>,
+    rx_offset: Cell<usize>,
+    // The part of the last OUT packet that has not been received yet.
+    out_offset: Cell<usize>,
+    out_len: Cell<usize>,
+    // The OUT endpoint is not re-armed until the packet is consumed.
+    out_held: Cell<bool>,
+    log: TakeCell<'static, [u8]>,
+    log_head: Cell<usize>,
+    log_tail: Cell<usize>,
+    // A transfer that ends with a full packet needs an empty one after it.
+    last_full: Cell<bool>,
+}
+
+impl<'a> CdcAcm<'a> {
+    pub fn new(usb: &'a Usb<'a>,
+               interface: u8,
+               notify_endpoint: usize,
+               out_endpoint: usize,
+               in_endpoint: usize,
+               log: &'static mut [u8])
+               -> CdcAcm<'a> {
+        CdcAcm {
+            usb: usb,
+            interface: interface,
+            notify_endpoint: notify_endpoint,
+            out_endpoint: out_endpoint,
+            in_endpoint: in_endpoint,
+            in_buffer: [VolatileCell::new(0); MAX_PACKET_SIZE],
+            out_buffer: [VolatileCell::new(0); MAX_PACKET_SIZE],
+            line_coding: Cell::new([0x00, 0xC2, 0x01, 0x00, 0, 0, 8]),
+            configured: Cell::new(false),
+            connected: Cell::new(false),
+            client: Cell::new(None),
+            tx_buffer: TakeCell::empty(),
+            tx_len: Cell::new(0),
+            tx_offset: Cell::new(0),
+            rx_buffer: TakeCell::empty(),
+            rx_len: Cell::new(0),
+            rx_offset: Cell::new(0),
+            out_offset: Cell::new(0),
+            out_len: Cell::new(0),
+            out_held: Cell::new(false),
+            log: TakeCell::new(log),
+            log_head: Cell::new(0),
+            log_tail: Cell::new(0),
+            last_full: Cell::new(false),
+        }
+    }
+
+    /// True while the host has the port open.
+    pub fn is_connected(&self) -> bool {
+        self.configured.get() && self.connected.get()
+    }
+
+    /// Queues kernel log output. Bytes that do not fit are dropped.
+    pub fn write_log(&self, bytes: &[u8]) {
+        self.log.map(|log| {
+            let mut head = self.log_head.get();
+            for b in bytes {
+                let next = (head + 1) % log.len();
+                if next == self.log_tail.get() {
+                    break;
+                }
+                log[head] = *b;
+                head = next;
+            }
+            self.log_head.set(head);
+        });
+        if self.configured.get() {
+            self.usb.endpoint_resume_in(self.in_endpoint);
+        }
+    }
+
+    /// Sends the queued log output by polling the controller, for use when
+    /// interrupts are no longer serviced, as after a panic.
+    pub fn flush(&self) {
+        for _ in 0..FLUSH_POLLS {
+            if !self.is_connected() || self.log_head.get() == self.log_tail.get() {
+                return;
+            }
+            self.usb.poll();
+        }
+    }
+
+    fn set_connected(&self, connected: bool) {
+        self.connected.set(connected);
+        if !connected {
+            self.complete_transmit();
+        }
+    }
+
+    fn complete_transmit(&self) {
+        self.tx_buffer.take().map(|buffer| {
+            self.client.get().map(move |client| {
+                client.transmit_complete(buffer, uart::Error::CommandComplete)
+            });
+        });
+    }
+
+    /// Copies as much of the held OUT packet as fits into the receive
+    /// buffer, completing the receive once it is full.
+    fn deliver(&self) {
+        let done = self.rx_buffer.map_or(false, |buffer| {
+            let mut offset = self.rx_offset.get();
+            let mut out_offset = self.out_offset.get();
+            while offset < self.rx_len.get() && out_offset < self.out_len.get() {
+                buffer[offset] = self.out_buffer[out_offset].get();
+                offset += 1;
+                out_offset += 1;
+            }
+            self.rx_offset.set(offset);
+            self.out_offset.set(out_offset);
+            offset == self.rx_len.get()
+        });
+
+        if done {
+            self.rx_buffer.take().map(|buffer| {
+                let len = self.rx_offset.get();
+                self.client.get().map(move |client| {
+                    client.receive_complete(buffer, len, uart::Error::CommandComplete)
+                });
+            });
+        }
+    }
+
+    /// Fills the IN buffer with console data, then log data.
+    fn fill_packet(&self) -> usize {
+        let sent = self.tx_buffer.map_or(0, |buffer| {
+            let offset = self.tx_offset.get();
+            let len = cmp::min(MAX_PACKET_SIZE, self.tx_len.get() - offset);
+            for (dst, src) in self.in_buffer.iter().zip(buffer[offset..offset + len].iter()) {
+                dst.set(*src);
+            }
+            self.tx_offset.set(offset + len);
+            len
+        });
+        if sent > 0 {
+            return sent;
+        }
+
+        self.log.map_or(0, |log| {
+            let mut tail = self.log_tail.get();
+            let mut len = 0;
+            while len < MAX_PACKET_SIZE && tail != self.log_head.get() {
+                self.in_buffer[len].set(log[tail]);
+                tail = (tail + 1) % log.len();
+                len += 1;
+            }
+            self.log_tail.set(tail);
+            len
+        })
+    }
+}
+
+impl<'a> Function<'a> for CdcAcm<'a> {
+    fn descriptors(&self, buf: &mut [u8]) -> usize {
+        let data_interface = self.interface + 1;
+        // [USB CDC Specification, Section 5] and [USB PSTN Subclass
+        // Specification, Section 5.3]
+        let descriptors: [u8; DESCRIPTORS_SIZE] = [
+            // Interface association: both interfaces form one function.
+            8, descriptor_type::INTERFACE_ASSOCIATION,
+            self.interface, 2, 0x02, 0x02, 0x01, 0,
+            // Communication interface, abstract control model, AT commands.
+            9, descriptor_type::INTERFACE,
+            self.interface, 0, 1, 0x02, 0x02, 0x01, 0,
+            // Header, CDC 1.10
+            5, CS_INTERFACE, 0x00, 0x10, 0x01,
+            // Call management over the communication interface.
+            5, CS_INTERFACE, 0x01, 0x00, data_interface,
+            // Abstract control management: line coding and break.
+            4, CS_INTERFACE, 0x02, 0x06,
+            // Union
+            5, CS_INTERFACE, 0x06, self.interface, data_interface,
+            // Notification endpoint: interrupt IN, 16 bytes every 64ms.
+            7, descriptor_type::ENDPOINT,
+            0x80 | self.notify_endpoint as u8, 0x03, 16, 0, 64,
+            // Data interface
+            9, descriptor_type::INTERFACE,
+            data_interface, 0, 2, 0x0A, 0x00, 0x00, 0,
+            // Bulk OUT
+            7, descriptor_type::ENDPOINT,
+            self.out_endpoint as u8, 0x02, MAX_PACKET_SIZE as u8, 0, 0,
+            // Bulk IN
+            7, descriptor_type::ENDPOINT,
+            0x80 | self.in_endpoint as u8, 0x02, MAX_PACKET_SIZE as u8, 0, 0,
+        ];
+        buf[..DESCRIPTORS_SIZE].copy_from_slice(&descriptors);
+        DESCRIPTORS_SIZE
+    }
+
+    fn has_interface(&self, interface: u8) -> bool {
+        interface == self.interface || interface == self.interface + 1
+    }
+
+    fn has_endpoint(&self, endpoint: usize) -> bool {
+        endpoint == self.notify_endpoint || endpoint == self.out_endpoint ||
+        endpoint == self.in_endpoint
+    }
+
+    fn configure(&'a self, configured: bool) {
+        self.configured.set(configured);
+        self.out_offset.set(0);
+        self.out_len.set(0);
+        self.out_held.set(false);
+        self.last_full.set(false);
+
+        if !configured {
+            self.set_connected(false);
+            return;
+        }
+
+        self.usb.endpoint_set_in_buffer(self.in_endpoint, &self.in_buffer);
+        self.usb.endpoint_set_out_buffer(self.out_endpoint, &self.out_buffer);
+        self.usb.endpoint_in_enable(TransferType::Interrupt, self.notify_endpoint);
+        self.usb.endpoint_out_enable(TransferType::Bulk, self.out_endpoint);
+        self.usb.endpoint_in_enable(TransferType::Bulk, self.in_endpoint);
+        self.usb.endpoint_resume_in(self.in_endpoint);
+    }
+
+    fn ctrl_setup(&'a self, setup: &SetupData, buf: &[VolatileCell<u8>]) -> Reply {
+        if setup.kind() != RequestKind::Class {
+            return Reply::Stall;
+        }
+
+        match setup.request {
+            SET_LINE_CODING => Reply::Ok,
+            GET_LINE_CODING => {
+                for (dst, src) in buf.iter().zip(self.line_coding.get().iter()) {
+                    dst.set(*src);
+                }
+                Reply::Buffer(7)
+            },
+            SET_CONTROL_LINE_STATE => {
+                self.set_connected(setup.value & 1 != 0);
+                if self.is_connected() {
+                    self.usb.endpoint_resume_in(self.in_endpoint);
+                }
+                Reply::Ok
+            },
+            SEND_BREAK => Reply::Ok,
+            _ => Reply::Stall,
+        }
+    }
+
+    fn ctrl_out(&'a self, buf: &[VolatileCell<u8>], len: usize) -> bool {
+        if len < 7 {
+            return false;
+        }
+        let mut line_coding = [0; 7];
+        for (dst, src) in line_coding.iter_mut().zip(buf.iter()) {
+            *dst = src.get();
+        }
+        self.line_coding.set(line_coding);
+        true
+    }
+
+    fn packet_in(&'a self, endpoint: usize) -> InResult {
+        // Nothing is sent on the notification endpoint.
+        if endpoint != self.in_endpoint || !self.connected.get() {
+            return InResult::Delay;
+        }
+
+        let len = self.fill_packet();
+        if len > 0 {
+            self.last_full.set(len == MAX_PACKET_SIZE);
+            InResult::Packet(len)
+        } else if self.last_full.get() {
+            self.last_full.set(false);
+            InResult::Packet(0)
+        } else {
+            InResult::Delay
+        }
+    }
+
+    fn packet_out(&'a self, endpoint: usize, len: usize) -> OutResult {
+        if endpoint != self.out_endpoint {
+            return OutResult::Error;
+        }
+
+        self.out_offset.set(0);
+        self.out_len.set(len);
+        self.deliver();
+        if self.out_offset.get() == self.out_len.get() {
+            OutResult::Ok
+        } else {
+            self.out_held.set(true);
+            OutResult::Delay
+        }
+    }
+
+    fn packet_transmitted(&'a self, endpoint: usize) {
+        if endpoint != self.in_endpoint {
+            return;
+        }
+
+        if self.tx_buffer.is_some() && self.tx_offset.get() == self.tx_len.get() {
+            self.complete_transmit();
+        }
+        self.usb.endpoint_resume_in(self.in_endpoint);
+    }
+}
+
+impl<'a> UART for CdcAcm<'a> {
+    fn set_client(&self, client: &'static uart::Client) {
+        self.client.set(Some(client));
+    }
+
+    /// There is no physical line to configure.
+    fn init(&self, _params: uart::UARTParams) {}
+
+    fn transmit(&self, tx_data: &'static mut [u8], tx_len: usize) {
+        if self.tx_buffer.is_some() {
+            self.client.get().map(move |client| {
+                client.transmit_complete(tx_data, uart::Error::RepeatCallError)
+            });
+        } else if !self.is_connected() || tx_len == 0 {
+            self.client.get().map(move |client| {
+                client.transmit_complete(tx_data, uart::Error::CommandComplete)
+            });
+        } else {
+            self.tx_len.set(cmp::min(tx_len, tx_data.len()));
+            self.tx_offset.set(0);
+            self.tx_buffer.replace(tx_data);
+            self.usb.endpoint_resume_in(self.in_endpoint);
+        }
+    }
+
+    fn receive(&self, rx_buffer: &'static mut [u8], rx_len: usize) {
+        self.rx_len.set(cmp::min(rx_len, rx_buffer.len()));
+        self.rx_offset.set(0);
+        self.rx_buffer.replace(rx_buffer);
+
+        self.deliver();
+        if self.out_held.get() && self.out_offset.get() == self.out_len.get() {
+            self.out_held.set(false);
+            self.usb.endpoint_resume_out(self.out_endpoint);
+        }
+    }
+
+    fn abort_receive(&self) {
+        self.rx_buffer.take().map(|buffer| {
+            let len = self.rx_offset.get();
+            self.client.get().map(move |client| {
+                client.receive_complete(buffer, len, uart::Error::CommandComplete)
+            });
+        });
+    }
+}
@@ -0,0 +1,508 @@
+//! A composite USB device assembled from functions.
+//!
+//! The device answers the standard requests on endpoint 0 and builds its
+//! configuration descriptor from the descriptors of each function. Class
+//! requests addressed to a function's interfaces, and all traffic on its
+//! endpoints, are passed to that function.
+
+use core::cell::Cell;
+use core::cmp;
+use kernel::common::cells::VolatileCell;
+use kernel::hil::usb::{Client, CtrlInResult, CtrlOutResult, CtrlSetupResult, DeviceSpeed,
+                       InResult, OutResult, TransferType, UsbController};
+use mk66;
+use mk66::usb::{Usb, MAX_PACKET_SIZE};
+
+// [USB 2.0 Specification, Table 9-4]
+pub mod request {
+    pub const GET_STATUS: u8 = 0;
+    pub const CLEAR_FEATURE: u8 = 1;
+    pub const SET_FEATURE: u8 = 3;
+    pub const SET_ADDRESS: u8 = 5;
+    pub const GET_DESCRIPTOR: u8 = 6;
+    pub const GET_CONFIGURATION: u8 = 8;
+    pub const SET_CONFIGURATION: u8 = 9;
+    pub const GET_INTERFACE: u8 = 10;
+    pub const SET_INTERFACE: u8 = 11;
+}
+
+// [USB 2.0 Specification, Table 9-5]
+pub mod descriptor_type {
+    pub const DEVICE: u8 = 1;
+    pub const CONFIGURATION: u8 = 2;
+    pub const STRING: u8 = 3;
+    pub const INTERFACE: u8 = 4;
+    pub const ENDPOINT: u8 = 5;
+    pub const INTERFACE_ASSOCIATION: u8 = 11;
+}
+
+/// The size of the buffer holding the configuration descriptor.
+pub const CONFIGURATION_SIZE: usize = 512;
+
+pub static mut CONFIGURATION_BUF: [u8; CONFIGURATION_SIZE] = [0; CONFIGURATION_SIZE];
+
+// English (United States)
+static LANGUAGES: [u8; 4] = [4, descriptor_type::STRING, 0x09, 0x04];
+
+const STRING_MANUFACTURER: u8 = 1;
+const STRING_PRODUCT: u8 = 2;
+const STRING_SERIAL: u8 = 3;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum RequestKind {
+    Standard,
+    Class,
+    Vendor,
+    Reserved,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Recipient {
+    Device,
+    Interface,
+    Endpoint,
+    Other,
+}
+
+/// A parsed SETUP packet.
+#[derive(Copy, Clone)]
+pub struct SetupData {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+impl SetupData {
+    pub fn parse(buf: &[VolatileCell<u8>]) -> SetupData {
+        SetupData {
+            request_type: buf[0].get(),
+            request: buf[1].get(),
+            value: buf[2].get() as u16 | (buf[3].get() as u16) << 8,
+            index: buf[4].get() as u16 | (buf[5].get() as u16) << 8,
+            length: buf[6].get() as u16 | (buf[7].get() as u16) << 8,
+        }
+    }
+
+    pub fn is_in(&self) -> bool {
+        self.request_type & 0x80 != 0
+    }
+
+    pub fn kind(&self) -> RequestKind {
+        match (self.request_type >> 5) & 0b11 {
+            0 => RequestKind::Standard,
+            1 => RequestKind::Class,
+            2 => RequestKind::Vendor,
+            _ => RequestKind::Reserved,
+        }
+    }
+
+    pub fn recipient(&self) -> Recipient {
+        match self.request_type & 0b1_1111 {
+            0 => Recipient::Device,
+            1 => Recipient::Interface,
+            2 => Recipient::Endpoint,
+            _ => Recipient::Other,
+        }
+    }
+}
+
+/// How a function answers a control request.
+pub enum Reply {
+    /// Accept the request. If it has an OUT data stage, the data is passed
+    /// to `Function::ctrl_out`.
+    Ok,
+    /// Send the first `n` bytes of the control buffer as the data stage.
+    Buffer(usize),
+    /// Send `data` as the data stage.
+    Static(&'static [u8]),
+    Stall,
+}
+
+/// A USB function: a set of interfaces and endpoints with their own class.
+pub trait Function<'a> {
+    /// Writes the function's interface, class and endpoint descriptors to
+    /// `buf` and returns their length.
+    fn descriptors(&self, buf: &mut [u8]) -> usize;
+
+    fn has_interface(&self, interface: u8) -> bool;
+
+    fn has_endpoint(&self, endpoint: usize) -> bool;
+
+    /// Called when the host selects or clears the configuration. A function
+    /// enables its endpoints here.
+    fn configure(&'a self, configured: bool);
+
+    /// Handles a request addressed to one of the function's interfaces.
+    /// Requests with data carry it in `buf`, which is also where replies
+    /// for `Reply::Buffer` are written.
+    fn ctrl_setup(&'a self, setup: &SetupData, buf: &[VolatileCell<u8>]) -> Reply;
+
+    /// Receives a packet of the OUT data stage of an accepted request.
+    /// Returns false to stall the request.
+    fn ctrl_out(&'a self, _buf: &[VolatileCell<u8>], _len: usize) -> bool {
+        false
+    }
+
+    fn packet_in(&'a self, _endpoint: usize) -> InResult {
+        InResult::Delay
+    }
+
+    fn packet_out(&'a self, _endpoint: usize, _len: usize) -> OutResult {
+        OutResult::Error
+    }
+
+    fn packet_transmitted(&'a self, _endpoint: usize) {}
+}
+
+/// Identifies the device to the host.
+pub struct DeviceInfo {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub manufacturer: &'static str,
+    pub product: &'static str,
+}
+
+#[derive(Copy, Clone)]
+enum Ctrl<'a> {
+    Idle,
+    In { data: &'a [u8], offset: usize, zlp: bool },
+    /// The data is already in the control buffer.
+    InBuffer(usize),
+    Out(&'a Function<'a>),
+    SetAddress,
+}
+
+pub struct UsbDevice<'a> {
+    usb: &'a Usb<'a>,
+    info: &'a DeviceInfo,
+    functions: &'a [&'a Function<'a>],
+    device_descriptor: [u8; 18],
+    configuration: &'a [u8],
+    buffer: [VolatileCell<u8>; MAX_PACKET_SIZE],
+    state: Cell<Ctrl<'a>>,
+    configured: Cell<bool>,
+}
+
+impl<'a> UsbDevice<'a> {
+    pub fn new(usb: &'a Usb<'a>,
+               info: &'a DeviceInfo,
+               functions: &'a [&'a Function<'a>],
+               configuration: &'a mut [u8])
+               -> UsbDevice<'a> {
+        // [USB 2.0 Specification, Table 9-8]. The device class says that
+        // functions may be grouped by interface association descriptors.
+        let device_descriptor = [
+            18, descriptor_type::DEVICE,
+            0x00, 0x02,
+            0xEF, 0x02, 0x01,
+            MAX_PACKET_SIZE as u8,
+            info.vendor_id as u8, (info.vendor_id >> 8) as u8,
+            info.product_id as u8, (info.product_id >> 8) as u8,
+            0x00, 0x01,
+            STRING_MANUFACTURER, STRING_PRODUCT, STRING_SERIAL,
+            1,
+        ];
+
+        // [USB 2.0 Specification, Table 9-10]
+        let mut len = 9;
+        for function in functions.iter() {
+            len += function.descriptors(&mut configuration[len..]);
+        }
+
+        let mut interfaces = 0;
+        let mut i = 9;
+        while i < len {
+            // Count each interface once, not once per alternate setting.
+            if configuration[i + 1] == descriptor_type::INTERFACE && configuration[i + 3] == 0 {
+                interfaces += 1;
+            }
+            i += configuration[i] as usize;
+        }
+
+        configuration[..9].copy_from_slice(&[
+            9, descriptor_type::CONFIGURATION,
+            len as u8, (len >> 8) as u8,
+            interfaces,
+            1,
+            0,
+            // Bus powered, drawing up to 100mA.
+            0x80, 50,
+        ]);
+
+        let configuration: &'a [u8] = configuration;
+        UsbDevice {
+            usb: usb,
+            info: info,
+            functions: functions,
+            device_descriptor: device_descriptor,
+            configuration: &configuration[..len],
+            buffer: [VolatileCell::new(0); MAX_PACKET_SIZE],
+            state: Cell::new(Ctrl::Idle),
+            configured: Cell::new(false),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.configured.get()
+    }
+
+    fn interface_function(&self, interface: u8) -> Option<&'a Function<'a>> {
+        self.functions.iter().find(|f| f.has_interface(interface)).map(|f| *f)
+    }
+
+    fn endpoint_function(&self, endpoint: usize) -> Option<&'a Function<'a>> {
+        self.functions.iter().find(|f| f.has_endpoint(endpoint)).map(|f| *f)
+    }
+
+    fn reply(&'a self, setup: &SetupData, data: &'a [u8]) -> CtrlSetupResult {
+        let len = cmp::min(data.len(), setup.length as usize);
+        // A short reply that ends on a packet boundary needs an empty
+        // packet to tell the host it is over.
+        let zlp = len < setup.length as usize && len % MAX_PACKET_SIZE == 0;
+        self.state.set(Ctrl::In { data: &data[..len], offset: 0, zlp: zlp });
+        CtrlSetupResult::Ok
+    }
+
+    fn reply_buffer(&'a self, setup: &SetupData, len: usize) -> CtrlSetupResult {
+        self.state.set(Ctrl::InBuffer(cmp::min(len, setup.length as usize)));
+        CtrlSetupResult::Ok
+    }
+
+    /// Writes a string descriptor into the control buffer.
+    fn reply_string<I: Iterator<Item = u8>>(&'a self, setup: &SetupData, chars: I) -> CtrlSetupResult {
+        let mut len = 2;
+        for c in chars.take((MAX_PACKET_SIZE - 2) / 2) {
+            self.buffer[len].set(c);
+            self.buffer[len + 1].set(0);
+            len += 2;
+        }
+        self.buffer[0].set(len as u8);
+        self.buffer[1].set(descriptor_type::STRING);
+        self.reply_buffer(setup, len)
+    }
+
+    fn set_configuration(&'a self, configured: bool) {
+        self.configured.set(configured);
+        for function in self.functions.iter() {
+            function.configure(configured);
+        }
+    }
+
+    fn get_descriptor(&'a self, setup: &SetupData) -> CtrlSetupResult {
+        let index = setup.value as u8;
+        match (setup.value >> 8) as u8 {
+            descriptor_type::DEVICE => self.reply(setup, &self.device_descriptor),
+            descriptor_type::CONFIGURATION => self.reply(setup, self.configuration),
+            descriptor_type::STRING => match index {
+                0 => self.reply(setup, &LANGUAGES),
+                STRING_MANUFACTURER => self.reply_string(setup, self.info.manufacturer.bytes()),
+                STRING_PRODUCT => self.reply_string(setup, self.info.product.bytes()),
+                STRING_SERIAL => {
+                    let id = mk66::sim::unique_id();
+                    let serial = (id[2] as u64) << 32 | id[3] as u64;
+                    let digits = (0..16).rev().map(|i| {
+                        b"0123456789ABCDEF"[((serial >> (4 * i)) & 0xF) as usize]
+                    });
+                    self.reply_string(setup, digits)
+                },
+                _ => CtrlSetupResult::ErrInvalidStringIndex,
+            },
+            // Full speed only devices have no device qualifier.
+            _ => CtrlSetupResult::ErrUnrecognizedDescriptorType,
+        }
+    }
+
+    fn device_request(&'a self, setup: &SetupData) -> CtrlSetupResult {
+        match setup.request {
+            request::GET_DESCRIPTOR => self.get_descriptor(setup),
+            request::SET_ADDRESS => {
+                self.usb.set_address(setup.value);
+                self.state.set(Ctrl::SetAddress);
+                CtrlSetupResult::Ok
+            },
+            request::SET_CONFIGURATION => match setup.value {
+                0 | 1 => {
+                    self.set_configuration(setup.value == 1);
+                    CtrlSetupResult::Ok
+                },
+                _ => CtrlSetupResult::ErrInvalidConfigurationIndex,
+            },
+            request::GET_CONFIGURATION => {
+                self.buffer[0].set(self.configured.get() as u8);
+                self.reply_buffer(setup, 1)
+            },
+            request::GET_STATUS => self.reply_status(setup),
+            // Remote wakeup is not supported, but hosts may still ask.
+            request::SET_FEATURE | request::CLEAR_FEATURE => CtrlSetupResult::Ok,
+            _ => CtrlSetupResult::ErrNonstandardRequest,
+        }
+    }
+
+    fn reply_status(&'a self, setup: &SetupData) -> CtrlSetupResult {
+        self.buffer[0].set(0);
+        self.buffer[1].set(0);
+        self.reply_buffer(setup, 2)
+    }
+
+    fn function_request(&'a self, function: &'a Function<'a>, setup: &SetupData) -> CtrlSetupResult {
+        match function.ctrl_setup(setup, &self.buffer) {
+            Reply::Ok => {
+                if !setup.is_in() && setup.length > 0 {
+                    self.state.set(Ctrl::Out(function));
+                }
+                CtrlSetupResult::Ok
+            },
+            Reply::Buffer(len) => self.reply_buffer(setup, len),
+            Reply::Static(data) => self.reply(setup, data),
+            Reply::Stall => CtrlSetupResult::ErrGeneric,
+        }
+    }
+
+    fn interface_request(&'a self, setup: &SetupData) -> CtrlSetupResult {
+        let function = match self.interface_function(setup.index as u8) {
+            Some(function) => function,
+            None => return CtrlSetupResult::ErrInvalidInterfaceIndex,
+        };
+
+        if setup.kind() == RequestKind::Standard {
+            match setup.request {
+                request::GET_STATUS => return self.reply_status(setup),
+                // Functions have a single alternate setting.
+                request::GET_INTERFACE => {
+                    self.buffer[0].set(0);
+                    return self.reply_buffer(setup, 1);
+                },
+                request::SET_INTERFACE => {
+                    return if setup.value == 0 {
+                        CtrlSetupResult::Ok
+                    } else {
+                        CtrlSetupResult::ErrGeneric
+                    };
+                },
+                _ => {},
+            }
+        }
+        self.function_request(function, setup)
+    }
+
+    fn endpoint_request(&'a self, setup: &SetupData) -> CtrlSetupResult {
+        let endpoint = (setup.index & 0xF) as usize;
+        if setup.kind() != RequestKind::Standard {
+            return match self.endpoint_function(endpoint) {
+                Some(function) => self.function_request(function, setup),
+                None => CtrlSetupResult::ErrGeneric,
+            };
+        }
+
+        // [USB 2.0 Specification, Section 9.4.5]. The only endpoint feature
+        // is ENDPOINT_HALT.
+        match setup.request {
+            request::GET_STATUS => self.reply_status(setup),
+            request::CLEAR_FEATURE => {
+                self.usb.endpoint_clear_halt(endpoint);
+                CtrlSetupResult::Ok
+            },
+            request::SET_FEATURE => CtrlSetupResult::Ok,
+            _ => CtrlSetupResult::ErrGeneric,
+        }
+    }
+}
+
+impl<'a> Client<'a> for UsbDevice<'a> {
+    fn enable(&'a self) {
+        self.usb.endpoint_set_ctrl_buffer(&self.buffer);
+        self.usb.enable_as_device(DeviceSpeed::Full);
+    }
+
+    fn attach(&'a self) {
+        self.usb.attach();
+    }
+
+    fn bus_reset(&'a self) {
+        self.state.set(Ctrl::Idle);
+        if self.configured.get() {
+            self.set_configuration(false);
+        }
+    }
+
+    fn ctrl_setup(&'a self, _endpoint: usize) -> CtrlSetupResult {
+        self.state.set(Ctrl::Idle);
+        let setup = SetupData::parse(&self.buffer);
+
+        match setup.recipient() {
+            Recipient::Device if setup.kind() == RequestKind::Standard => {
+                self.device_request(&setup)
+            },
+            Recipient::Interface => self.interface_request(&setup),
+            Recipient::Endpoint => self.endpoint_request(&setup),
+            _ => CtrlSetupResult::ErrUnrecognizedRequestType,
+        }
+    }
+
+    fn ctrl_in(&'a self, _endpoint: usize) -> CtrlInResult {
+        match self.state.get() {
+            Ctrl::In { data, offset, zlp } => {
+                let len = cmp::min(MAX_PACKET_SIZE, data.len() - offset);
+                for (dst, src) in self.buffer.iter().zip(data[offset..offset + len].iter()) {
+                    dst.set(*src);
+                }
+
+                let offset = offset + len;
+                let last = offset == data.len() && !(zlp && len == MAX_PACKET_SIZE);
+                self.state.set(if last {
+                    Ctrl::Idle
+                } else {
+                    Ctrl::In { data: data, offset: offset, zlp: zlp }
+                });
+                CtrlInResult::Packet(len, last)
+            },
+            Ctrl::InBuffer(len) => {
+                self.state.set(Ctrl::Idle);
+                CtrlInResult::Packet(len, true)
+            },
+            _ => CtrlInResult::Error,
+        }
+    }
+
+    fn ctrl_out(&'a self, _endpoint: usize, packet_bytes: u32) -> CtrlOutResult {
+        match self.state.get() {
+            Ctrl::Out(function) => {
+                if function.ctrl_out(&self.buffer, packet_bytes as usize) {
+                    CtrlOutResult::Ok
+                } else {
+                    CtrlOutResult::Halted
+                }
+            },
+            _ => CtrlOutResult::Halted,
+        }
+    }
+
+    fn ctrl_status(&'a self, _endpoint: usize) {}
+
+    fn ctrl_status_complete(&'a self, _endpoint: usize) {
+        if let Ctrl::SetAddress = self.state.get() {
+            self.usb.enable_address();
+        }
+        self.state.set(Ctrl::Idle);
+    }
+
+    fn packet_in(&'a self, _transfer_type: TransferType, endpoint: usize) -> InResult {
+        self.endpoint_function(endpoint).map_or(InResult::Error, |f| f.packet_in(endpoint))
+    }
+
+    fn packet_out(&'a self,
+                  _transfer_type: TransferType,
+                  endpoint: usize,
+                  packet_bytes: u32)
+                  -> OutResult {
+        self.endpoint_function(endpoint).map_or(OutResult::Error, |f| {
+            f.packet_out(endpoint, packet_bytes as usize)
+        })
+    }
+
+    fn packet_transmitted(&'a self, endpoint: usize) {
+        self.endpoint_function(endpoint).map(|f| f.packet_transmitted(endpoint));
+    }
+}
@@ -0,0 +1,345 @@
+//! A USB HID function with a single interrupt IN endpoint.
+//!
+//! The report descriptor is given when the function is created, so the
+//! same function serves keyboards, mice, gamepads or any combination of
+//! them. `KEYBOARD_MOUSE_GAMEPAD` describes all three, told apart by the
+//! first byte of each report. Output reports, such as the keyboard LEDs,
+//! arrive through SET_REPORT requests and are passed to the client.
+
+use core::cell::Cell;
+use core::cmp;
+use kernel::ReturnCode;
+use kernel::common::cells::VolatileCell;
+use kernel::hil::usb::{InResult, TransferType, UsbController};
+use mk66::usb::{Usb, MAX_PACKET_SIZE};
+use usb::device::{descriptor_type, request, Function, Reply, RequestKind, SetupData};
+
+// [HID 1.11 Specification, Section 7.1]
+const HID_DESCRIPTOR: u8 = 0x21;
+const REPORT_DESCRIPTOR: u8 = 0x22;
+
+// [HID 1.11 Specification, Section 7.2]
+const GET_REPORT: u8 = 0x01;
+const GET_IDLE: u8 = 0x02;
+const GET_PROTOCOL: u8 = 0x03;
+const SET_REPORT: u8 = 0x09;
+const SET_IDLE: u8 = 0x0A;
+const SET_PROTOCOL: u8 = 0x0B;
+
+const DESCRIPTORS_SIZE: usize = 25;
+
+pub const REPORT_KEYBOARD: u8 = 1;
+pub const REPORT_MOUSE: u8 = 2;
+pub const REPORT_GAMEPAD: u8 = 3;
+
+/// A keyboard, a mouse and a gamepad.
+///
+/// - Keyboard: modifier bits, a reserved byte and up to six key codes. Its
+///   output report holds the five LED bits.
+/// - Mouse: five button bits, then relative X, Y and wheel.
+/// - Gamepad: sixteen button bits, X, Y, Z and Rz axes from -127 to 127,
+///   and a hat switch, 0 to 7 clockwise from up with 8 for centered.
+pub static KEYBOARD_MOUSE_GAMEPAD: [u8; 191] = [
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x06,       // Usage (Keyboard)
+    0xA1, 0x01,       // Collection (Application)
+    0x85, REPORT_KEYBOARD,
+    0x05, 0x07,       //   Usage Page (Key Codes)
+    0x19, 0xE0,       //   Usage Minimum (Left Control)
+    0x29, 0xE7,       //   Usage Maximum (Right GUI)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x25, 0x01,       //   Logical Maximum (1)
+    0x75, 0x01,       //   Report Size (1)
+    0x95, 0x08,       //   Report Count (8)
+    0x81, 0x02,       //   Input (Data, Variable, Absolute)
+    0x95, 0x01,       //   Report Count (1)
+    0x75, 0x08,       //   Report Size (8)
+    0x81, 0x01,       //   Input (Constant)
+    0x95, 0x05,       //   Report Count (5)
+    0x75, 0x01,       //   Report Size (1)
+    0x05, 0x08,       //   Usage Page (LEDs)
+    0x19, 0x01,       //   Usage Minimum (Num Lock)
+    0x29, 0x05,       //   Usage Maximum (Kana)
+    0x91, 0x02,       //   Output (Data, Variable, Absolute)
+    0x95, 0x01,       //   Report Count (1)
+    0x75, 0x03,       //   Report Size (3)
+    0x91, 0x01,       //   Output (Constant)
+    0x95, 0x06,       //   Report Count (6)
+    0x75, 0x08,       //   Report Size (8)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x25, 0x65,       //   Logical Maximum (101)
+    0x05, 0x07,       //   Usage Page (Key Codes)
+    0x19, 0x00,       //   Usage Minimum (0)
+    0x29, 0x65,       //   Usage Maximum (101)
+    0x81, 0x00,       //   Input (Data, Array)
+    0xC0,             // End Collection
+
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x02,       // Usage (Mouse)
+    0xA1, 0x01,       // Collection (Application)
+    0x85, REPORT_MOUSE,
+    0x09, 0x01,       //   Usage (Pointer)
+    0xA1, 0x00,       //   Collection (Physical)
+    0x05, 0x09,       //     Usage Page (Buttons)
+    0x19, 0x01,       //     Usage Minimum (1)
+    0x29, 0x05,       //     Usage Maximum (5)
+    0x15, 0x00,       //     Logical Minimum (0)
+    0x25, 0x01,       //     Logical Maximum (1)
+    0x95, 0x05,       //     Report Count (5)
+    0x75, 0x01,       //     Report Size (1)
+    0x81, 0x02,       //     Input (Data, Variable, Absolute)
+    0x95, 0x01,       //     Report Count (1)
+    0x75, 0x03,       //     Report Size (3)
+    0x81, 0x01,       //     Input (Constant)
+    0x05, 0x01,       //     Usage Page (Generic Desktop)
+    0x09, 0x30,       //     Usage (X)
+    0x09, 0x31,       //     Usage (Y)
+    0x09, 0x38,       //     Usage (Wheel)
+    0x15, 0x81,       //     Logical Minimum (-127)
+    0x25, 0x7F,       //     Logical Maximum (127)
+    0x75, 0x08,       //     Report Size (8)
+    0x95, 0x03,       //     Report Count (3)
+    0x81, 0x06,       //     Input (Data, Variable, Relative)
+    0xC0,             //   End Collection
+    0xC0,             // End Collection
+
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x05,       // Usage (Gamepad)
+    0xA1, 0x01,       // Collection (Application)
+    0x85, REPORT_GAMEPAD,
+    0x05, 0x09,       //   Usage Page (Buttons)
+    0x19, 0x01,       //   Usage Minimum (1)
+    0x29, 0x10,       //   Usage Maximum (16)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x25, 0x01,       //   Logical Maximum (1)
+    0x75, 0x01,       //   Report Size (1)
+    0x95, 0x10,       //   Report Count (16)
+    0x81, 0x02,       //   Input (Data, Variable, Absolute)
+    0x05, 0x01,       //   Usage Page (Generic Desktop)
+    0x09, 0x30,       //   Usage (X)
+    0x09, 0x31,       //   Usage (Y)
+    0x09, 0x32,       //   Usage (Z)
+    0x09, 0x35,       //   Usage (Rz)
+    0x15, 0x81,       //   Logical Minimum (-127)
+    0x25, 0x7F,       //   Logical Maximum (127)
+    0x75, 0x08,       //   Report Size (8)
+    0x95, 0x04,       //   Report Count (4)
+    0x81, 0x02,       //   Input (Data, Variable, Absolute)
+    0x09, 0x39,       //   Usage (Hat Switch)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x25, 0x07,       //   Logical Maximum (7)
+    0x35, 0x00,       //   Physical Minimum (0)
+    0x46, 0x3B, 0x01, //   Physical Maximum (315)
+    0x65, 0x14,       //   Unit (Degrees)
+    0x75, 0x04,       //   Report Size (4)
+    0x95, 0x01,       //   Report Count (1)
+    0x81, 0x42,       //   Input (Data, Variable, Absolute, Null State)
+    0x65, 0x00,       //   Unit (None)
+    0x75, 0x04,       //   Report Size (4)
+    0x95, 0x01,       //   Report Count (1)
+    0x81, 0x01,       //   Input (Constant)
+    0xC0,             // End Collection
+];
+
+pub trait Client {
+    /// Called when a report given to `send_report` has been sent, or with
+    /// `EOFF` if the host deconfigured the device first.
+    fn report_sent(&self, result: ReturnCode);
+
+    /// Called with each output report from the host. With report IDs in
+    /// use, the first byte is the ID.
+    fn output_report(&self, report: &[u8]);
+}
+
+pub struct Hid<'a> {
+    usb: &'a Usb<'a>,
+    interface: u8,
+    endpoint: usize,
+    report_descriptor: &'static [u8],
+    buffer: [VolatileCell<u8>; MAX_PACKET_SIZE],
+    // The length of the report waiting in `buffer`.
+    pending: Cell<Option<usize>>,
+    in_flight: Cell<bool>,
+    configured: Cell<bool>,
+    idle: Cell<u8>,
+    protocol: Cell<u8>,
+    client: Cell<Option<&'a Client>>,
+}
+
+impl<'a> Hid<'a> {
+    pub fn new(usb: &'a Usb<'a>,
+               interface: u8,
+               endpoint: usize,
+               report_descriptor: &'static [u8])
+               -> Hid<'a> {
+        Hid {
+            usb: usb,
+            interface: interface,
+            endpoint: endpoint,
+            report_descriptor: report_descriptor,
+            buffer: [VolatileCell::new(0); MAX_PACKET_SIZE],
+            pending: Cell::new(None),
+            in_flight: Cell::new(false),
+            configured: Cell::new(false),
+            idle: Cell::new(0),
+            // Report protocol
+            protocol: Cell::new(1),
+            client: Cell::new(None),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a Client) {
+        self.client.set(Some(client));
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.configured.get()
+    }
+
+    /// Queues a report for the host's next poll. Only one report may be
+    /// queued at a time.
+    pub fn send_report(&self, report: &[u8]) -> ReturnCode {
+        if !self.configured.get() {
+            return ReturnCode::EOFF;
+        }
+        if self.pending.get().is_some() {
+            return ReturnCode::EBUSY;
+        }
+        if report.len() == 0 || report.len() > MAX_PACKET_SIZE {
+            return ReturnCode::ESIZE;
+        }
+
+        for (dst, src) in self.buffer.iter().zip(report.iter()) {
+            dst.set(*src);
+        }
+        self.pending.set(Some(report.len()));
+        self.usb.endpoint_resume_in(self.endpoint);
+        ReturnCode::SUCCESS
+    }
+
+    fn write_hid_descriptor(&self, buf: &[VolatileCell<u8>]) -> usize {
+        let len = self.report_descriptor.len();
+        // [HID 1.11 Specification, Section 6.2.1]
+        let descriptor = [
+            9, HID_DESCRIPTOR,
+            0x11, 0x01,
+            0,
+            1, REPORT_DESCRIPTOR, len as u8, (len >> 8) as u8,
+        ];
+        for (dst, src) in buf.iter().zip(descriptor.iter()) {
+            dst.set(*src);
+        }
+        descriptor.len()
+    }
+}
+
+impl<'a> Function<'a> for Hid<'a> {
+    fn descriptors(&self, buf: &mut [u8]) -> usize {
+        let len = self.report_descriptor.len();
+        let descriptors: [u8; DESCRIPTORS_SIZE] = [
+            9, descriptor_type::INTERFACE,
+            self.interface, 0, 1, 0x03, 0x00, 0x00, 0,
+            9, HID_DESCRIPTOR,
+            0x11, 0x01, 0, 1, REPORT_DESCRIPTOR, len as u8, (len >> 8) as u8,
+            // Interrupt IN, polled every millisecond.
+            7, descriptor_type::ENDPOINT,
+            0x80 | self.endpoint as u8, 0x03, MAX_PACKET_SIZE as u8, 0, 1,
+        ];
+        buf[..DESCRIPTORS_SIZE].copy_from_slice(&descriptors);
+        DESCRIPTORS_SIZE
+    }
+
+    fn has_interface(&self, interface: u8) -> bool {
+        interface == self.interface
+    }
+
+    fn has_endpoint(&self, endpoint: usize) -> bool {
+        endpoint == self.endpoint
+    }
+
+    fn configure(&'a self, configured: bool) {
+        self.configured.set(configured);
+        self.in_flight.set(false);
+        self.idle.set(0);
+        self.protocol.set(1);
+
+        if configured {
+            self.usb.endpoint_set_in_buffer(self.endpoint, &self.buffer);
+            self.usb.endpoint_in_enable(TransferType::Interrupt, self.endpoint);
+        } else if self.pending.get().is_some() {
+            self.pending.set(None);
+            self.client.get().map(|client| client.report_sent(ReturnCode::EOFF));
+        }
+    }
+
+    fn ctrl_setup(&'a self, setup: &SetupData, buf: &[VolatileCell<u8>]) -> Reply {
+        match setup.kind() {
+            RequestKind::Standard if setup.request == request::GET_DESCRIPTOR => {
+                match (setup.value >> 8) as u8 {
+                    HID_DESCRIPTOR => Reply::Buffer(self.write_hid_descriptor(buf)),
+                    REPORT_DESCRIPTOR => Reply::Static(self.report_descriptor),
+                    _ => Reply::Stall,
+                }
+            },
+            RequestKind::Class => match setup.request {
+                // Input reports are sent as they happen, so there is no
+                // current state to report; answer with an idle report.
+                GET_REPORT => {
+                    let len = cmp::min(setup.length as usize, buf.len());
+                    for cell in buf[..len].iter() {
+                        cell.set(0);
+                    }
+                    if len > 0 {
+                        buf[0].set(setup.value as u8);
+                    }
+                    Reply::Buffer(len)
+                },
+                SET_REPORT => Reply::Ok,
+                GET_IDLE => {
+                    buf[0].set(self.idle.get());
+                    Reply::Buffer(1)
+                },
+                SET_IDLE => {
+                    self.idle.set((setup.value >> 8) as u8);
+                    Reply::Ok
+                },
+                GET_PROTOCOL => {
+                    buf[0].set(self.protocol.get());
+                    Reply::Buffer(1)
+                },
+                SET_PROTOCOL => {
+                    self.protocol.set(setup.value as u8 & 1);
+                    Reply::Ok
+                },
+                _ => Reply::Stall,
+            },
+            _ => Reply::Stall,
+        }
+    }
+
+    fn ctrl_out(&'a self, buf: &[VolatileCell<u8>], len: usize) -> bool {
+        let mut report = [0; MAX_PACKET_SIZE];
+        let len = cmp::min(len, MAX_PACKET_SIZE);
+        for (dst, src) in report.iter_mut().zip(buf[..len].iter()) {
+            *dst = src.get();
+        }
+        self.client.get().map(|client| client.output_report(&report[..len]));
+        true
+    }
+
+    fn packet_in(&'a self, _endpoint: usize) -> InResult {
+        match self.pending.get() {
+            Some(len) if !self.in_flight.get() => {
+                self.in_flight.set(true);
+                InResult::Packet(len)
+            },
+            _ => InResult::Delay,
+        }
+    }
+
+    fn packet_transmitted(&'a self, _endpoint: usize) {
+        self.in_flight.set(false);
+        self.pending.set(None);
+        self.client.get().map(|client| client.report_sent(ReturnCode::SUCCESS));
+    }
+}
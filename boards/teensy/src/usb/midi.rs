@@ -0,0 +1,244 @@
+//! A USB MIDI function with up to eight virtual cables.
+//!
+//! Each cable shows up on the host as a MIDI port with an input and an
+//! output. MIDI data moves in 4-byte event packets: the cable number and
+//! code index in the first byte, then up to three bytes of the MIDI message
+//! [USB MIDI 1.0 Specification, Section 4]. Up to sixteen packets share a
+//! USB packet in each direction.
+
+use core::cell::Cell;
+use core::cmp;
+use kernel::ReturnCode;
+use kernel::common::cells::VolatileCell;
+use kernel::hil::usb::{InResult, OutResult, TransferType, UsbController};
+use mk66::usb::{Usb, MAX_PACKET_SIZE};
+use usb::device::{descriptor_type, Function, Reply, SetupData};
+
+pub const MAX_CABLES: usize = 8;
+
+pub const EVENT_PACKET_SIZE: usize = 4;
+
+const CS_INTERFACE: u8 = 0x24;
+const CS_ENDPOINT: u8 = 0x25;
+
+// [USB MIDI 1.0 Specification, Appendix A]
+const MS_HEADER: u8 = 0x01;
+const MIDI_IN_JACK: u8 = 0x02;
+const MIDI_OUT_JACK: u8 = 0x03;
+const MS_GENERAL: u8 = 0x01;
+const EMBEDDED: u8 = 0x01;
+const EXTERNAL: u8 = 0x02;
+
+// Each cable has an embedded and an external jack in each direction.
+const JACKS_SIZE: usize = 30;
+
+pub trait Client {
+    /// Called with the event packets of each USB packet from the host,
+    /// without the empty padding packets.
+    fn packets_received(&self, packets: &[u8]);
+
+    /// Called when the packets given to `send` have been read by the host,
+    /// or with `EOFF` if the host deconfigured the device first.
+    fn send_done(&self, result: ReturnCode);
+}
+
+pub struct Midi<'a> {
+    usb: &'a Usb<'a>,
+    // The audio control interface; the MIDI streaming interface follows it.
+    interface: u8,
+    out_endpoint: usize,
+    in_endpoint: usize,
+    cables: usize,
+    in_buffer: [VolatileCell<u8>; MAX_PACKET_SIZE],
+    out_buffer: [VolatileCell<u8>; MAX_PACKET_SIZE],
+    // The length of the packets waiting in `in_buffer`.
+    pending: Cell<Option<usize>>,
+    in_flight: Cell<bool>,
+    configured: Cell<bool>,
+    client: Cell<Option<&'a Client>>,
+}
+
+impl<'a> Midi<'a> {
+    pub fn new(usb: &'a Usb<'a>,
+               interface: u8,
+               out_endpoint: usize,
+               in_endpoint: usize,
+               cables: usize)
+               -> Midi<'a> {
+        if cables == 0 || cables > MAX_CABLES {
+            panic!("USB MIDI supports 1 to {} cables", MAX_CABLES);
+        }
+
+        Midi {
+            usb: usb,
+            interface: interface,
+            out_endpoint: out_endpoint,
+            in_endpoint: in_endpoint,
+            cables: cables,
+            in_buffer: [VolatileCell::new(0); MAX_PACKET_SIZE],
+            out_buffer: [VolatileCell::new(0); MAX_PACKET_SIZE],
+            pending: Cell::new(None),
+            in_flight: Cell::new(false),
+            configured: Cell::new(false),
+            client: Cell::new(None),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a Client) {
+        self.client.set(Some(client));
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.configured.get()
+    }
+
+    pub fn cables(&self) -> usize {
+        self.cables
+    }
+
+    /// Queues event packets for the host's next read and returns how many
+    /// were taken, at most one USB packet's worth.
+    pub fn send(&self, packets: &[u8]) -> Result<usize, ReturnCode> {
+        if !self.configured.get() {
+            return Err(ReturnCode::EOFF);
+        }
+        if self.pending.get().is_some() {
+            return Err(ReturnCode::EBUSY);
+        }
+
+        let len = cmp::min(packets.len(), MAX_PACKET_SIZE) / EVENT_PACKET_SIZE * EVENT_PACKET_SIZE;
+        if len == 0 {
+            return Err(ReturnCode::ESIZE);
+        }
+
+        for (dst, src) in self.in_buffer.iter().zip(packets[..len].iter()) {
+            dst.set(*src);
+        }
+        self.pending.set(Some(len));
+        self.usb.endpoint_resume_in(self.in_endpoint);
+        Ok(len / EVENT_PACKET_SIZE)
+    }
+
+    fn jack_id(cable: usize, jack: usize) -> u8 {
+        (1 + cable * 4 + jack) as u8
+    }
+
+    fn streaming_endpoint(&self, buf: &mut [u8], address: u8, first_jack: usize) -> usize {
+        let header = [
+            9, descriptor_type::ENDPOINT,
+            address, 0x02, MAX_PACKET_SIZE as u8, 0, 0, 0, 0,
+            4 + self.cables as u8, CS_ENDPOINT, MS_GENERAL, self.cables as u8,
+        ];
+        buf[..header.len()].copy_from_slice(&header);
+        for cable in 0..self.cables {
+            buf[header.len() + cable] = Midi::jack_id(cable, first_jack);
+        }
+        header.len() + self.cables
+    }
+}
+
+impl<'a> Function<'a> for Midi<'a> {
+    fn descriptors(&self, buf: &mut [u8]) -> usize {
+        let streaming = self.interface + 1;
+        let streaming_size = 7 + JACKS_SIZE * self.cables + 2 * (13 + self.cables);
+
+        // [USB MIDI 1.0 Specification, Section 6]. The audio control
+        // interface is required, but has nothing to control.
+        let header = [
+            8, descriptor_type::INTERFACE_ASSOCIATION,
+            self.interface, 2, 0x01, 0x01, 0x00, 0,
+            9, descriptor_type::INTERFACE,
+            self.interface, 0, 0, 0x01, 0x01, 0x00, 0,
+            9, CS_INTERFACE, 0x01, 0x00, 0x01, 9, 0, 1, streaming,
+            9, descriptor_type::INTERFACE,
+            streaming, 0, 2, 0x01, 0x03, 0x00, 0,
+            7, CS_INTERFACE, MS_HEADER, 0x00, 0x01,
+            streaming_size as u8, (streaming_size >> 8) as u8,
+        ];
+        buf[..header.len()].copy_from_slice(&header);
+        let mut len = header.len();
+
+        // Data from the host enters through the embedded IN jack and leaves
+        // through the external OUT jack, and the other way around.
+        for cable in 0..self.cables {
+            let id = |jack| Midi::jack_id(cable, jack);
+            let jacks = [
+                6, CS_INTERFACE, MIDI_IN_JACK, EMBEDDED, id(0), 0,
+                6, CS_INTERFACE, MIDI_IN_JACK, EXTERNAL, id(1), 0,
+                9, CS_INTERFACE, MIDI_OUT_JACK, EMBEDDED, id(2), 1, id(1), 1, 0,
+                9, CS_INTERFACE, MIDI_OUT_JACK, EXTERNAL, id(3), 1, id(0), 1, 0,
+            ];
+            buf[len..len + JACKS_SIZE].copy_from_slice(&jacks);
+            len += JACKS_SIZE;
+        }
+
+        len += self.streaming_endpoint(&mut buf[len..], self.out_endpoint as u8, 0);
+        len += self.streaming_endpoint(&mut buf[len..], 0x80 | self.in_endpoint as u8, 2);
+        len
+    }
+
+    fn has_interface(&self, interface: u8) -> bool {
+        interface == self.interface || interface == self.interface + 1
+    }
+
+    fn has_endpoint(&self, endpoint: usize) -> bool {
+        endpoint == self.out_endpoint || endpoint == self.in_endpoint
+    }
+
+    fn configure(&'a self, configured: bool) {
+        self.configured.set(configured);
+        self.in_flight.set(false);
+
+        if configured {
+            self.usb.endpoint_set_in_buffer(self.in_endpoint, &self.in_buffer);
+            self.usb.endpoint_set_out_buffer(self.out_endpoint, &self.out_buffer);
+            self.usb.endpoint_out_enable(TransferType::Bulk, self.out_endpoint);
+            self.usb.endpoint_in_enable(TransferType::Bulk, self.in_endpoint);
+        } else if self.pending.get().is_some() {
+            self.pending.set(None);
+            self.client.get().map(|client| client.send_done(ReturnCode::EOFF));
+        }
+    }
+
+    /// MIDI has no class requests.
+    fn ctrl_setup(&'a self, _setup: &SetupData, _buf: &[VolatileCell<u8>]) -> Reply {
+        Reply::Stall
+    }
+
+    fn packet_in(&'a self, _endpoint: usize) -> InResult {
+        match self.pending.get() {
+            Some(len) if !self.in_flight.get() => {
+                self.in_flight.set(true);
+                InResult::Packet(len)
+            },
+            _ => InResult::Delay,
+        }
+    }
+
+    fn packet_out(&'a self, _endpoint: usize, len: usize) -> OutResult {
+        let mut packets = [0; MAX_PACKET_SIZE];
+        let mut count = 0;
+        let len = cmp::min(len, MAX_PACKET_SIZE);
+        for packet in self.out_buffer[..len].chunks(EVENT_PACKET_SIZE) {
+            // A code index of 0 is reserved, and pads the USB packet.
+            if packet.len() < EVENT_PACKET_SIZE || packet[0].get() & 0x0F == 0 {
+                continue;
+            }
+            for (dst, src) in packets[count..count + EVENT_PACKET_SIZE].iter_mut().zip(packet.iter()) {
+                *dst = src.get();
+            }
+            count += EVENT_PACKET_SIZE;
+        }
+
+        if count > 0 {
+            self.client.get().map(|client| client.packets_received(&packets[..count]));
+        }
+        OutResult::Ok
+    }
+
+    fn packet_transmitted(&'a self, _endpoint: usize) {
+        self.in_flight.set(false);
+        self.pending.set(None);
+        self.client.get().map(|client| client.send_done(ReturnCode::SUCCESS));
+    }
+}
@@ -0,0 +1,21 @@
+//! The Teensy's USB device, built on the K66 full-speed controller.
+//!
+//! `device` implements the device itself and each of the other modules
+//! adds a function to it.
+
+pub mod device;
+pub mod cdc;
+pub mod hid;
+pub mod midi;
+pub mod msc;
+
+use self::device::DeviceInfo;
+
+/// The identifiers of a Teensyduino serial device, so that hosts bind
+/// their serial drivers without extra configuration.
+pub static SERIAL: DeviceInfo = DeviceInfo {
+    vendor_id: 0x16C0,
+    product_id: 0x0483,
+    manufacturer: "Tock",
+    product: "Teensy 3.6 Serial",
+};
@@ -0,0 +1,539 @@
+//! A USB mass storage function serving the microSD card.
+//!
+//! The function implements the bulk-only transport [USB Mass Storage Class
+//! Bulk-Only Transport 1.0] with the SCSI commands hosts use for a
+//! removable disk. Each command arrives in a command block wrapper on the
+//! OUT endpoint, is followed by its data, if any, and is answered with a
+//! command status wrapper on the IN endpoint.
+//!
+//! The host is considered to have the card mounted from when it reads the
+//! card's capacity until it ejects the card or the device is deconfigured.
+//! The client is told when this changes, so that the kernel keeps its own
+//! writes off the card in the meantime.
+
+use core::cell::Cell;
+use core::cmp;
+use kernel::common::cells::{TakeCell, VolatileCell};
+use kernel::hil::usb::{InResult, OutResult, TransferType, UsbController};
+use kernel::ReturnCode;
+use mk66::sdhc::{self, BlockDevice, Sdhc};
+use mk66::usb::{Usb, MAX_PACKET_SIZE};
+use sdcard::SectorBuffer;
+use usb::device::{descriptor_type, Function, Reply, RequestKind, SetupData};
+
+pub static mut SECTOR_BUF: SectorBuffer = SectorBuffer([0; sdhc::BLOCK_SIZE]);
+
+// [Bulk-Only Transport, Section 3]
+const MASS_STORAGE_RESET: u8 = 0xFF;
+const GET_MAX_LUN: u8 = 0xFE;
+
+// [Bulk-Only Transport, Section 5]
+const CBW_SIGNATURE: u32 = 0x4342_5355;
+const CSW_SIGNATURE: u32 = 0x5342_5355;
+const CBW_SIZE: usize = 31;
+const CSW_SIZE: usize = 13;
+
+const STATUS_PASSED: u8 = 0;
+const STATUS_FAILED: u8 = 1;
+
+// [SCSI Primary Commands and SCSI Block Commands]
+const TEST_UNIT_READY: u8 = 0x00;
+const REQUEST_SENSE: u8 = 0x03;
+const INQUIRY: u8 = 0x12;
+const MODE_SENSE_6: u8 = 0x1A;
+const START_STOP_UNIT: u8 = 0x1B;
+const PREVENT_ALLOW_MEDIUM_REMOVAL: u8 = 0x1E;
+const READ_FORMAT_CAPACITIES: u8 = 0x23;
+const READ_CAPACITY_10: u8 = 0x25;
+const READ_10: u8 = 0x28;
+const WRITE_10: u8 = 0x2A;
+const VERIFY_10: u8 = 0x2F;
+const SYNCHRONIZE_CACHE_10: u8 = 0x35;
+const MODE_SENSE_10: u8 = 0x5A;
+
+/// A sense key with its additional sense code and qualifier.
+#[derive(Copy, Clone)]
+struct Sense(u8, u8, u8);
+
+const NO_SENSE: Sense = Sense(0x00, 0x00, 0x00);
+const MEDIUM_NOT_PRESENT: Sense = Sense(0x02, 0x3A, 0x00);
+const MEDIUM_ERROR: Sense = Sense(0x03, 0x11, 0x00);
+const WRITE_ERROR: Sense = Sense(0x03, 0x0C, 0x00);
+const INVALID_COMMAND: Sense = Sense(0x05, 0x20, 0x00);
+const LBA_OUT_OF_RANGE: Sense = Sense(0x05, 0x21, 0x00);
+const MEDIUM_CHANGED: Sense = Sense(0x06, 0x28, 0x00);
+
+static INQUIRY_DATA: [u8; 36] = [
+    0x00, // Direct access block device
+    0x80, // Removable
+    0x04, // SPC-2
+    0x02,
+    31,
+    0, 0, 0,
+    b'T', b'o', b'c', b'k', b' ', b' ', b' ', b' ',
+    b'T', b'e', b'e', b'n', b's', b'y', b' ', b'S',
+    b'D', b' ', b'C', b'a', b'r', b'd', b' ', b' ',
+    b'1', b'.', b'0', b' ',
+];
+
+pub trait Client {
+    /// Called when the host mounts or releases the card.
+    fn host_mounted(&self, mounted: bool);
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum State {
+    /// Waiting for a command block wrapper.
+    Command,
+    /// Sending the first `len` bytes of `reply`, `offset` of which are sent.
+    Reply { len: usize, offset: usize },
+    /// Sending `blocks` sectors starting at `lba`, the first from `offset`.
+    Read { lba: u32, blocks: u32, offset: usize },
+    /// Receiving `blocks` sectors to write starting at `lba`.
+    Write { lba: u32, blocks: u32, offset: usize },
+    /// Receiving data that the command does not use.
+    Discard(usize),
+    /// Ending a data phase shorter than the host asked for.
+    ShortPacket,
+    Status,
+    StatusSent,
+}
+
+pub struct Msc<'a> {
+    usb: &'a Usb<'a>,
+    sdhc: &'a Sdhc<'a>,
+    interface: u8,
+    out_endpoint: usize,
+    in_endpoint: usize,
+    in_buffer: [VolatileCell<u8>; MAX_PACKET_SIZE],
+    out_buffer: [VolatileCell<u8>; MAX_PACKET_SIZE],
+    reply: Cell<[u8; MAX_PACKET_SIZE]>,
+    sector: TakeCell<'static, SectorBuffer>,
+    state: Cell<State>,
+    // From the current command block wrapper.
+    tag: Cell<u32>,
+    data_length: Cell<usize>,
+    data_in: Cell<bool>,
+    transferred: Cell<usize>,
+    status: Cell<u8>,
+    sense: Cell<Sense>,
+    // The card was missing when the host last looked.
+    card_missing: Cell<bool>,
+    ejected: Cell<bool>,
+    mounted: Cell<bool>,
+    client: Cell<Option<&'a Client>>,
+}
+
+impl<'a> Msc<'a> {
+    pub fn new(usb: &'a Usb<'a>,
+               sdhc: &'a Sdhc<'a>,
+               interface: u8,
+               out_endpoint: usize,
+               in_endpoint: usize,
+               sector: &'static mut SectorBuffer)
+               -> Msc<'a> {
+        Msc {
+            usb: usb,
+            sdhc: sdhc,
+            interface: interface,
+            out_endpoint: out_endpoint,
+            in_endpoint: in_endpoint,
+            in_buffer: [VolatileCell::new(0); MAX_PACKET_SIZE],
+            out_buffer: [VolatileCell::new(0); MAX_PACKET_SIZE],
+            reply: Cell::new([0; MAX_PACKET_SIZE]),
+            sector: TakeCell::new(sector),
+            state: Cell::new(State::Command),
+            tag: Cell::new(0),
+            data_length: Cell::new(0),
+            data_in: Cell::new(false),
+            transferred: Cell::new(0),
+            status: Cell::new(STATUS_PASSED),
+            sense: Cell::new(NO_SENSE),
+            card_missing: Cell::new(false),
+            ejected: Cell::new(false),
+            mounted: Cell::new(false),
+            client: Cell::new(None),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a Client) {
+        self.client.set(Some(client));
+    }
+
+    /// True while the host has the card mounted.
+    pub fn is_mounted(&self) -> bool {
+        self.mounted.get()
+    }
+
+    fn set_mounted(&self, mounted: bool) {
+        if self.mounted.get() != mounted {
+            self.mounted.set(mounted);
+            self.client.get().map(|client| client.host_mounted(mounted));
+        }
+    }
+
+    /// Initializes the card if needed, and reports whether it can be used.
+    fn card_ready(&self) -> bool {
+        if self.ejected.get() || !self.sdhc.is_card_present() {
+            return false;
+        }
+        self.sdhc.is_card_initialized() || self.sdhc.initialize_card() == ReturnCode::SUCCESS
+    }
+
+    /// Ends the data phase, which moves no more data.
+    fn finish(&self) {
+        let remaining = self.data_length.get() - self.transferred.get();
+        self.state.set(if remaining == 0 {
+            State::Status
+        } else if self.data_in.get() {
+            State::ShortPacket
+        } else {
+            State::Discard(remaining)
+        });
+    }
+
+    fn pass(&self) {
+        self.status.set(STATUS_PASSED);
+        self.finish();
+    }
+
+    fn fail(&self, sense: Sense) {
+        self.sense.set(sense);
+        self.status.set(STATUS_FAILED);
+        self.finish();
+    }
+
+    fn reply(&self, data: &[u8]) {
+        if !self.data_in.get() {
+            self.fail(INVALID_COMMAND);
+            return;
+        }
+
+        let len = cmp::min(data.len(), self.data_length.get());
+        let mut reply = [0; MAX_PACKET_SIZE];
+        reply[..len].copy_from_slice(&data[..len]);
+        self.reply.set(reply);
+        self.status.set(STATUS_PASSED);
+        if len == 0 {
+            self.finish();
+        } else {
+            self.state.set(State::Reply { len: len, offset: 0 });
+        }
+    }
+
+    /// Starts a read or write of `blocks` sectors from `lba`.
+    fn transfer(&self, lba: u32, blocks: u32, read: bool) {
+        if !self.card_ready() {
+            return self.fail(MEDIUM_NOT_PRESENT);
+        }
+        if lba as u64 + blocks as u64 > self.sdhc.block_count() as u64 {
+            return self.fail(LBA_OUT_OF_RANGE);
+        }
+        // The host must expect exactly the data the command moves.
+        if read != self.data_in.get() ||
+           blocks as usize * sdhc::BLOCK_SIZE != self.data_length.get() {
+            return self.fail(INVALID_COMMAND);
+        }
+
+        // Hosts may read and write without asking for the capacity first,
+        // and the kernel must keep off the card once they do.
+        self.set_mounted(true);
+        self.status.set(STATUS_PASSED);
+        if blocks == 0 {
+            self.finish();
+        } else if read {
+            self.state.set(State::Read { lba: lba, blocks: blocks, offset: 0 });
+        } else {
+            self.state.set(State::Write { lba: lba, blocks: blocks, offset: 0 });
+        }
+    }
+
+    fn capacity(&self) -> Option<u32> {
+        if self.card_ready() {
+            self.card_missing.set(false);
+            Some(self.sdhc.block_count())
+        } else {
+            self.card_missing.set(true);
+            None
+        }
+    }
+
+    fn execute(&self, cb: &[u8]) {
+        let lba = (cb[2] as u32) << 24 | (cb[3] as u32) << 16 | (cb[4] as u32) << 8 | cb[5] as u32;
+        let blocks = (cb[7] as u32) << 8 | cb[8] as u32;
+
+        match cb[0] {
+            TEST_UNIT_READY => {
+                let missing = self.card_missing.get();
+                match self.capacity() {
+                    // Hosts rescan the card after being told it changed.
+                    Some(_) if missing => self.fail(MEDIUM_CHANGED),
+                    Some(_) => self.pass(),
+                    None => self.fail(MEDIUM_NOT_PRESENT),
+                }
+            },
+            REQUEST_SENSE => {
+                let Sense(key, asc, ascq) = self.sense.get();
+                self.sense.set(NO_SENSE);
+                self.reply(&[0x70, 0, key, 0, 0, 0, 0, 10, 0, 0, 0, 0, asc, ascq, 0, 0, 0, 0]);
+            },
+            INQUIRY => self.reply(&INQUIRY_DATA),
+            // No mode pages, and not write protected.
+            MODE_SENSE_6 => self.reply(&[3, 0, 0, 0]),
+            MODE_SENSE_10 => self.reply(&[0, 6, 0, 0, 0, 0, 0, 0]),
+            START_STOP_UNIT => {
+                let load_eject = cb[4] & 0b10 != 0;
+                let start = cb[4] & 0b01 != 0;
+                if load_eject {
+                    self.ejected.set(!start);
+                    if !start {
+                        self.set_mounted(false);
+                    }
+                }
+                self.pass();
+            },
+            // The card can be pulled out whatever the host asks.
+            PREVENT_ALLOW_MEDIUM_REMOVAL | VERIFY_10 | SYNCHRONIZE_CACHE_10 => self.pass(),
+            READ_FORMAT_CAPACITIES => match self.capacity() {
+                Some(count) => self.reply(&[
+                    0, 0, 0, 8,
+                    (count >> 24) as u8, (count >> 16) as u8, (count >> 8) as u8, count as u8,
+                    // Formatted media, 512 byte blocks.
+                    0x02, 0x00, 0x02, 0x00,
+                ]),
+                None => self.fail(MEDIUM_NOT_PRESENT),
+            },
+            READ_CAPACITY_10 => match self.capacity() {
+                // There is no last block to report on an empty card.
+                Some(0) => self.fail(MEDIUM_NOT_PRESENT),
+                Some(count) => {
+                    self.set_mounted(true);
+                    let last = count - 1;
+                    self.reply(&[
+                        (last >> 24) as u8, (last >> 16) as u8, (last >> 8) as u8, last as u8,
+                        0, 0, 0x02, 0x00,
+                    ]);
+                },
+                None => self.fail(MEDIUM_NOT_PRESENT),
+            },
+            READ_10 => self.transfer(lba, blocks, true),
+            WRITE_10 => self.transfer(lba, blocks, false),
+            _ => self.fail(INVALID_COMMAND),
+        }
+    }
+
+    fn command(&self, len: usize) {
+        let cbw = &self.out_buffer;
+        let word = |i: usize| {
+            cbw[i].get() as u32 | (cbw[i + 1].get() as u32) << 8 |
+            (cbw[i + 2].get() as u32) << 16 | (cbw[i + 3].get() as u32) << 24
+        };
+        // Anything but a valid wrapper is ignored until the host resets.
+        if len != CBW_SIZE || word(0) != CBW_SIGNATURE {
+            return;
+        }
+
+        self.tag.set(word(4));
+        self.data_length.set(word(8) as usize);
+        self.data_in.set(cbw[12].get() & 0x80 != 0);
+        self.transferred.set(0);
+
+        let mut cb = [0; 16];
+        for (dst, src) in cb.iter_mut().zip(cbw[15..31].iter()) {
+            *dst = src.get();
+        }
+        // There is a single logical unit.
+        if cbw[13].get() != 0 {
+            self.fail(INVALID_COMMAND);
+        } else {
+            self.execute(&cb);
+        }
+    }
+
+    fn write_packet(&self, lba: u32, blocks: u32, offset: usize, len: usize) {
+        let len = cmp::min(len, sdhc::BLOCK_SIZE - offset);
+        let result = self.sector.map_or(ReturnCode::FAIL, |sector| {
+            for (dst, src) in sector.0[offset..offset + len].iter_mut().zip(self.out_buffer.iter()) {
+                *dst = src.get();
+            }
+            if offset + len == sdhc::BLOCK_SIZE {
+                self.sdhc.write_blocks(lba, &sector.0)
+            } else {
+                ReturnCode::SUCCESS
+            }
+        });
+        self.transferred.set(self.transferred.get() + len);
+
+        if result != ReturnCode::SUCCESS {
+            self.fail(WRITE_ERROR);
+        } else if offset + len < sdhc::BLOCK_SIZE {
+            self.state.set(State::Write { lba: lba, blocks: blocks, offset: offset + len });
+        } else if blocks > 1 {
+            self.state.set(State::Write { lba: lba + 1, blocks: blocks - 1, offset: 0 });
+        } else {
+            self.finish();
+        }
+    }
+
+    fn read_packet(&self, lba: u32, blocks: u32, offset: usize) -> InResult {
+        let result = self.sector.map_or(ReturnCode::FAIL, |sector| {
+            if offset == 0 {
+                let result = self.sdhc.read_blocks(lba, &mut sector.0);
+                if result != ReturnCode::SUCCESS {
+                    return result;
+                }
+            }
+            for (dst, src) in self.in_buffer.iter().zip(sector.0[offset..].iter()) {
+                dst.set(*src);
+            }
+            ReturnCode::SUCCESS
+        });
+        if result != ReturnCode::SUCCESS {
+            self.fail(MEDIUM_ERROR);
+            return self.next_packet();
+        }
+
+        self.transferred.set(self.transferred.get() + MAX_PACKET_SIZE);
+        let offset = offset + MAX_PACKET_SIZE;
+        if offset < sdhc::BLOCK_SIZE {
+            self.state.set(State::Read { lba: lba, blocks: blocks, offset: offset });
+        } else if blocks > 1 {
+            self.state.set(State::Read { lba: lba + 1, blocks: blocks - 1, offset: 0 });
+        } else {
+            self.finish();
+        }
+        InResult::Packet(MAX_PACKET_SIZE)
+    }
+
+    /// Fills the IN buffer with the next packet of the data or status.
+    fn next_packet(&self) -> InResult {
+        match self.state.get() {
+            State::Reply { len, offset } => {
+                let reply = self.reply.get();
+                let n = cmp::min(MAX_PACKET_SIZE, len - offset);
+                for (dst, src) in self.in_buffer.iter().zip(reply[offset..offset + n].iter()) {
+                    dst.set(*src);
+                }
+                self.transferred.set(self.transferred.get() + n);
+                if offset + n < len {
+                    self.state.set(State::Reply { len: len, offset: offset + n });
+                } else if n == MAX_PACKET_SIZE {
+                    self.finish();
+                } else {
+                    // The short packet itself ends the data phase.
+                    self.state.set(State::Status);
+                }
+                InResult::Packet(n)
+            },
+            State::Read { lba, blocks, offset } => self.read_packet(lba, blocks, offset),
+            State::ShortPacket => {
+                self.state.set(State::Status);
+                InResult::Packet(0)
+            },
+            State::Status => {
+                let residue = (self.data_length.get() - self.transferred.get()) as u32;
+                let tag = self.tag.get();
+                let csw = [
+                    CSW_SIGNATURE as u8, (CSW_SIGNATURE >> 8) as u8,
+                    (CSW_SIGNATURE >> 16) as u8, (CSW_SIGNATURE >> 24) as u8,
+                    tag as u8, (tag >> 8) as u8, (tag >> 16) as u8, (tag >> 24) as u8,
+                    residue as u8, (residue >> 8) as u8, (residue >> 16) as u8, (residue >> 24) as u8,
+                    self.status.get(),
+                ];
+                for (dst, src) in self.in_buffer.iter().zip(csw.iter()) {
+                    dst.set(*src);
+                }
+                self.state.set(State::StatusSent);
+                InResult::Packet(CSW_SIZE)
+            },
+            _ => InResult::Delay,
+        }
+    }
+}
+
+impl<'a> Function<'a> for Msc<'a> {
+    fn descriptors(&self, buf: &mut [u8]) -> usize {
+        // SCSI transparent command set over the bulk-only transport.
+        let descriptors = [
+            9, descriptor_type::INTERFACE,
+            self.interface, 0, 2, 0x08, 0x06, 0x50, 0,
+            7, descriptor_type::ENDPOINT,
+            self.out_endpoint as u8, 0x02, MAX_PACKET_SIZE as u8, 0, 0,
+            7, descriptor_type::ENDPOINT,
+            0x80 | self.in_endpoint as u8, 0x02, MAX_PACKET_SIZE as u8, 0, 0,
+        ];
+        buf[..descriptors.len()].copy_from_slice(&descriptors);
+        descriptors.len()
+    }
+
+    fn has_interface(&self, interface: u8) -> bool {
+        interface == self.interface
+    }
+
+    fn has_endpoint(&self, endpoint: usize) -> bool {
+        endpoint == self.out_endpoint || endpoint == self.in_endpoint
+    }
+
+    fn configure(&'a self, configured: bool) {
+        self.state.set(State::Command);
+        self.ejected.set(false);
+
+        if configured {
+            self.usb.endpoint_set_in_buffer(self.in_endpoint, &self.in_buffer);
+            self.usb.endpoint_set_out_buffer(self.out_endpoint, &self.out_buffer);
+            self.usb.endpoint_out_enable(TransferType::Bulk, self.out_endpoint);
+            self.usb.endpoint_in_enable(TransferType::Bulk, self.in_endpoint);
+        } else {
+            self.set_mounted(false);
+        }
+    }
+
+    fn ctrl_setup(&'a self, setup: &SetupData, buf: &[VolatileCell<u8>]) -> Reply {
+        if setup.kind() != RequestKind::Class {
+            return Reply::Stall;
+        }
+
+        match setup.request {
+            MASS_STORAGE_RESET => {
+                self.state.set(State::Command);
+                Reply::Ok
+            },
+            GET_MAX_LUN => {
+                buf[0].set(0);
+                Reply::Buffer(1)
+            },
+            _ => Reply::Stall,
+        }
+    }
+
+    fn packet_in(&'a self, _endpoint: usize) -> InResult {
+        self.next_packet()
+    }
+
+    fn packet_out(&'a self, _endpoint: usize, len: usize) -> OutResult {
+        match self.state.get() {
+            State::Command => self.command(len),
+            State::Write { lba, blocks, offset } => self.write_packet(lba, blocks, offset, len),
+            State::Discard(remaining) => {
+                let remaining = remaining - cmp::min(len, remaining);
+                if remaining == 0 {
+                    self.state.set(State::Status);
+                } else {
+                    self.state.set(State::Discard(remaining));
+                }
+            },
+            // The host should be reading.
+            _ => {},
+        }
+
+        self.usb.endpoint_resume_in(self.in_endpoint);
+        OutResult::Ok
+    }
+
+    fn packet_transmitted(&'a self, _endpoint: usize) {
+        if self.state.get() == State::StatusSent {
+            self.state.set(State::Command);
+        }
+        self.usb.endpoint_resume_in(self.in_endpoint);
+    }
+}
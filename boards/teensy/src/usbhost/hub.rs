@@ -0,0 +1,440 @@
+//! The hub class driver [USB 2.0 Specification, Chapter 11].
+//!
+//! Each hub's ports are powered when it is configured. The hub then reports
+//! port changes on its status change endpoint; each change is read and
+//! acknowledged, and newly connected devices are reset and handed to the
+//! host to enumerate. One hub's setup or port change is handled at a time,
+//! each step starting when the last request or delay ends.
+
+use core::cell::Cell;
+use core::cmp;
+use kernel::common::cells::TakeCell;
+use kernel::hil::time;
+use mk66::usbhs::{PipeType, Speed, TransferError, UsbHs};
+use usb::device::request;
+use usbhost::{self, ClassDriver, Device, HostAlarm, Interface, UsbHost, CONNECT_DEBOUNCE_MS};
+
+pub const MAX_HUBS: usize = 4;
+
+/// Ports past this are ignored.
+const MAX_PORTS: u8 = 15;
+
+const HUB_CLASS: u8 = 0x09;
+
+// [USB 2.0 Specification, Table 11-13]
+const HUB_DESCRIPTOR: u16 = 0x29;
+const HUB_DESCRIPTOR_SIZE: usize = 7;
+
+// Request types of hub class requests.
+const HUB_IN: u8 = 0xA0;
+const PORT_IN: u8 = 0xA3;
+const PORT_OUT: u8 = 0x23;
+
+// [USB 2.0 Specification, Table 11-17]
+const PORT_RESET: u16 = 4;
+const PORT_POWER: u16 = 8;
+const C_PORT_CONNECTION: u16 = 16;
+const C_PORT_ENABLE: u16 = 17;
+const C_PORT_SUSPEND: u16 = 18;
+const C_PORT_OVER_CURRENT: u16 = 19;
+const C_PORT_RESET: u16 = 20;
+
+// [USB 2.0 Specification, Tables 11-21 and 11-22]
+const PORT_STATUS_SIZE: usize = 4;
+const PORT_CONNECTION: u16 = 1 << 0;
+const PORT_LOW_SPEED: u16 = 1 << 9;
+const PORT_HIGH_SPEED: u16 = 1 << 10;
+const PORT_CONNECTION_CHANGE: u16 = 1 << 0;
+const PORT_RESET_CHANGE: u16 = 1 << 4;
+
+/// Each bit of the port change field, and the feature that clears it.
+const PORT_CHANGES: [(u16, u16); 5] = [
+    (PORT_CONNECTION_CHANGE, C_PORT_CONNECTION),
+    (1 << 1, C_PORT_ENABLE),
+    (1 << 2, C_PORT_SUSPEND),
+    (1 << 3, C_PORT_OVER_CURRENT),
+    (PORT_RESET_CHANGE, C_PORT_RESET),
+];
+
+// Hubs drive reset for 10 to 20ms [USB 2.0 Specification, Section 11.5.1.5].
+const RESET_POLL_MS: u32 = 10;
+const RESET_POLLS: usize = 10;
+
+/// How often a hub tries again to claim the host, while it enumerates
+/// another device.
+const CLAIM_RETRY_MS: u32 = 10;
+
+/// Holds hub descriptors and port status.
+pub static mut BUFFER: [u8; 8] = [0; 8];
+
+#[derive(Copy, Clone)]
+struct HubState {
+    device: Device,
+    pipe: usize,
+    ports: u8,
+    power_on_ms: u32,
+    /// Whether the ports have been powered.
+    configured: bool,
+    /// The ports whose changes have yet to be read, as reported on the
+    /// status change endpoint.
+    changed: u16,
+}
+
+/// A step of setting up a hub, or of handling a change on one of its
+/// ports, with the hub's slot and the port.
+#[derive(Copy, Clone, PartialEq)]
+enum Job {
+    Idle,
+    GetDescriptor(usize),
+    PowerPort(usize, u8),
+    PowerOn(usize),
+    GetStatus(usize, u8),
+    // With the changes still to acknowledge, and whether a device has
+    // been connected.
+    ClearChange(usize, u8, u16, bool),
+    Debounce(usize, u8),
+    Claim(usize, u8),
+    // Resetting the port, with the polls left, and then waiting for the
+    // device to recover.
+    Reset(usize, u8),
+    ResetPoll(usize, u8, usize),
+    ResetStatus(usize, u8, usize),
+    ClearReset(usize, u8, Speed),
+    ResetRecovery(usize, u8, Speed),
+}
+
+impl Job {
+    fn slot(&self) -> Option<usize> {
+        match *self {
+            Job::Idle => None,
+            Job::GetDescriptor(slot) | Job::PowerPort(slot, _) | Job::PowerOn(slot) |
+            Job::GetStatus(slot, _) | Job::ClearChange(slot, _, _, _) |
+            Job::Debounce(slot, _) | Job::Claim(slot, _) | Job::Reset(slot, _) |
+            Job::ResetPoll(slot, _, _) | Job::ResetStatus(slot, _, _) |
+            Job::ClearReset(slot, _, _) | Job::ResetRecovery(slot, _, _) => Some(slot),
+        }
+    }
+
+    /// The port the host is claimed for, if any.
+    fn claimed(&self) -> Option<(usize, u8)> {
+        match *self {
+            Job::Reset(slot, port) | Job::ResetPoll(slot, port, _) |
+            Job::ResetStatus(slot, port, _) | Job::ClearReset(slot, port, _) |
+            Job::ResetRecovery(slot, port, _) => Some((slot, port)),
+            _ => None,
+        }
+    }
+}
+
+fn speed(status: u16) -> Speed {
+    if status & PORT_LOW_SPEED != 0 {
+        Speed::Low
+    } else if status & PORT_HIGH_SPEED != 0 {
+        Speed::High
+    } else {
+        Speed::Full
+    }
+}
+
+pub struct Hub<'a> {
+    usbhs: &'a UsbHs<'a>,
+    alarm: &'a HostAlarm,
+    host: Cell<Option<&'a UsbHost<'a>>>,
+    hubs: [Cell<Option<HubState>>; MAX_HUBS],
+    job: Cell<Job>,
+    buffer: TakeCell<'static, [u8]>,
+    // The control pipe of the request in progress.
+    busy: Cell<Option<usize>>,
+}
+
+impl<'a> Hub<'a> {
+    pub fn new(usbhs: &'a UsbHs<'a>, alarm: &'a HostAlarm, buffer: &'static mut [u8]) -> Hub<'a> {
+        Hub {
+            usbhs: usbhs,
+            alarm: alarm,
+            host: Cell::new(None),
+            hubs: [Cell::new(None), Cell::new(None), Cell::new(None), Cell::new(None)],
+            job: Cell::new(Job::Idle),
+            buffer: TakeCell::new(buffer),
+            busy: Cell::new(None),
+        }
+    }
+
+    pub fn set_host(&self, host: &'a UsbHost<'a>) {
+        self.host.set(Some(host));
+    }
+
+    /// Starts the next job, if there is no job in progress.
+    fn next(&self) {
+        if self.job.get() != Job::Idle {
+            return;
+        }
+        for slot in 0..MAX_HUBS {
+            let mut hub = match self.hubs[slot].get() {
+                Some(hub) => hub,
+                None => continue,
+            };
+            if !hub.configured {
+                let value = HUB_DESCRIPTOR << 8;
+                return self.request(slot, HUB_IN, request::GET_DESCRIPTOR, value, 0,
+                                    HUB_DESCRIPTOR_SIZE, Job::GetDescriptor(slot));
+            }
+            if hub.changed != 0 {
+                let port = hub.changed.trailing_zeros() as u8;
+                hub.changed &= !(1 << port);
+                self.hubs[slot].set(Some(hub));
+                return self.request(slot, PORT_IN, request::GET_STATUS, 0, port as u16,
+                                    PORT_STATUS_SIZE, Job::GetStatus(slot, port));
+            }
+        }
+    }
+
+    /// Ends the job in progress, releasing the host if it was claimed, and
+    /// starts the next.
+    fn finish(&self) {
+        let job = self.job.get();
+        self.job.set(Job::Idle);
+        if let Some((slot, port)) = job.claimed() {
+            if let (Some(hub), Some(host)) = (self.hubs[slot].get(), self.host.get()) {
+                host.release(hub.device.address, port);
+            }
+        }
+        self.next();
+    }
+
+    /// Ends a job that failed. A hub that cannot be set up is given up on.
+    fn fail(&self) {
+        match self.job.get() {
+            Job::GetDescriptor(slot) | Job::PowerPort(slot, _) | Job::PowerOn(slot) => {
+                self.hubs[slot].take().map(|hub| self.usbhs.close_pipe(hub.pipe));
+            },
+            _ => {},
+        }
+        self.finish();
+    }
+
+    fn request(&self, slot: usize, request_type: u8, request: u8, value: u16, index: u16, len: usize, job: Job) {
+        self.job.set(job);
+        let hub = match self.hubs[slot].get() {
+            Some(hub) => hub,
+            None => return self.finish(),
+        };
+        let buffer = match self.buffer.take() {
+            Some(buffer) => buffer,
+            None => return self.fail(),
+        };
+        match hub.device.control(self.usbhs, request_type, request, value, index, buffer, len) {
+            Ok(()) => self.busy.set(Some(hub.device.control_pipe())),
+            Err((_, buffer)) => {
+                self.buffer.replace(buffer);
+                self.fail();
+            },
+        }
+    }
+
+    fn port_request(&self, slot: usize, request: u8, feature: u16, port: u8, job: Job) {
+        self.request(slot, PORT_OUT, request, feature, port as u16, 0, job);
+    }
+
+    fn wait(&self, ms: u32, job: Job) {
+        self.job.set(job);
+        usbhost::delay(self.alarm, ms);
+    }
+
+    /// Powers the hub's ports from `port` on, and then waits for the power
+    /// to be good.
+    fn power(&self, slot: usize, hub: &HubState, port: u8) {
+        if port > hub.ports {
+            self.wait(cmp::max(hub.power_on_ms, 1), Job::PowerOn(slot));
+        } else {
+            self.port_request(slot, request::SET_FEATURE, PORT_POWER, port, Job::PowerPort(slot, port));
+        }
+    }
+
+    /// Acknowledges the port's changes one at a time, and then debounces a
+    /// new connection.
+    fn clear_changes(&self, slot: usize, port: u8, changes: u16, connected: bool) {
+        match PORT_CHANGES.iter().find(|&&(bit, _)| changes & bit != 0) {
+            Some(&(bit, feature)) => {
+                let job = Job::ClearChange(slot, port, changes & !bit, connected);
+                self.port_request(slot, request::CLEAR_FEATURE, feature, port, job);
+            },
+            None if connected => self.wait(CONNECT_DEBOUNCE_MS, Job::Debounce(slot, port)),
+            None => self.finish(),
+        }
+    }
+
+    /// Resets the port once the host is free to enumerate the device on it.
+    fn claim(&self, slot: usize, hub: &HubState, port: u8) {
+        let claimed = self.host.get().map_or(false, |host| host.claim(hub.device.address, port));
+        if claimed {
+            self.port_request(slot, request::SET_FEATURE, PORT_RESET, port, Job::Reset(slot, port));
+        } else {
+            self.wait(CLAIM_RETRY_MS, Job::Claim(slot, port));
+        }
+    }
+}
+
+impl<'a> time::Client for Hub<'a> {
+    fn fired(&self) {
+        let job = self.job.get();
+        let hub = match job.slot().and_then(|slot| self.hubs[slot].get()) {
+            Some(hub) => hub,
+            None => return,
+        };
+        match job {
+            Job::PowerOn(slot) => {
+                self.hubs[slot].set(Some(HubState { configured: true, ..hub }));
+                self.usbhs.start_receiving(hub.pipe);
+                self.finish();
+            },
+            Job::Debounce(slot, port) | Job::Claim(slot, port) => self.claim(slot, &hub, port),
+            Job::ResetPoll(slot, port, polls) => {
+                self.request(slot, PORT_IN, request::GET_STATUS, 0, port as u16,
+                             PORT_STATUS_SIZE, Job::ResetStatus(slot, port, polls));
+            },
+            Job::ResetRecovery(_, port, speed) => {
+                // The host now holds the claim.
+                self.job.set(Job::Idle);
+                self.host.get().map(|host| host.attach(hub.device.address, port, speed));
+                self.next();
+            },
+            _ => {},
+        }
+    }
+}
+
+impl<'a> ClassDriver<'a> for Hub<'a> {
+    fn probe(&'a self, device: &Device, interface: &Interface) -> bool {
+        if interface.class != HUB_CLASS {
+            return false;
+        }
+        let slot = match self.hubs.iter().find(|hub| hub.get().is_none()) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        let endpoint = match interface.endpoint(PipeType::Interrupt, true) {
+            Some(endpoint) => endpoint,
+            None => return false,
+        };
+        let pipe = match device.open_pipe(self.usbhs, &endpoint) {
+            Some(pipe) => pipe,
+            None => return false,
+        };
+
+        slot.set(Some(HubState {
+            device: *device,
+            pipe: pipe,
+            ports: 0,
+            power_on_ms: 0,
+            configured: false,
+            changed: 0,
+        }));
+        self.next();
+        true
+    }
+
+    fn disconnected(&'a self, address: u8) {
+        for slot in 0..MAX_HUBS {
+            if !self.hubs[slot].get().map_or(false, |hub| hub.device.address == address) {
+                continue;
+            }
+            self.hubs[slot].take().map(|hub| self.usbhs.close_pipe(hub.pipe));
+
+            // A request in progress ends when its pipe closes.
+            if self.job.get().slot() == Some(slot) && self.busy.get().is_none() {
+                self.finish();
+            }
+        }
+    }
+
+    fn waiting(&self, pipe: usize) -> bool {
+        self.busy.get() == Some(pipe)
+    }
+
+    fn transfer_done(&'a self, _pipe: usize, buffer: &'static mut [u8], result: Result<usize, TransferError>) {
+        let status = buffer[0] as u16 | (buffer[1] as u16) << 8;
+        let change = buffer[2] as u16 | (buffer[3] as u16) << 8;
+        let (ports, power_on_ms) = (buffer[2], buffer[5] as u32 * 2);
+        self.busy.set(None);
+        self.buffer.replace(buffer);
+
+        let job = self.job.get();
+        let hub = match job.slot().and_then(|slot| self.hubs[slot].get()) {
+            Some(hub) => hub,
+            None => return self.finish(),
+        };
+        if result.is_err() {
+            return self.fail();
+        }
+
+        match job {
+            Job::GetDescriptor(slot) => {
+                let hub = HubState {
+                    ports: cmp::min(ports, MAX_PORTS),
+                    power_on_ms: power_on_ms,
+                    ..hub
+                };
+                self.hubs[slot].set(Some(hub));
+                self.power(slot, &hub, 1);
+            },
+            Job::PowerPort(slot, port) => self.power(slot, &hub, port + 1),
+            Job::GetStatus(slot, port) => {
+                let connected = if change & PORT_CONNECTION_CHANGE != 0 {
+                    self.host.get().map(|host| host.detach(hub.device.address, port));
+                    status & PORT_CONNECTION != 0
+                } else {
+                    false
+                };
+                self.clear_changes(slot, port, change, connected);
+            },
+            Job::ClearChange(slot, port, changes, connected) => {
+                self.clear_changes(slot, port, changes, connected);
+            },
+            Job::Reset(slot, port) => {
+                self.wait(RESET_POLL_MS, Job::ResetPoll(slot, port, RESET_POLLS));
+            },
+            Job::ResetStatus(slot, port, polls) => {
+                if status & PORT_CONNECTION == 0 {
+                    self.fail();
+                } else if change & PORT_RESET_CHANGE != 0 {
+                    let job = Job::ClearReset(slot, port, speed(status));
+                    self.port_request(slot, request::CLEAR_FEATURE, C_PORT_RESET, port, job);
+                } else if polls > 1 {
+                    self.wait(RESET_POLL_MS, Job::ResetPoll(slot, port, polls - 1));
+                } else {
+                    self.fail();
+                }
+            },
+            Job::ClearReset(slot, port, speed) => {
+                self.wait(usbhost::RESET_RECOVERY_MS, Job::ResetRecovery(slot, port, speed));
+            },
+            _ => self.finish(),
+        }
+    }
+
+    fn packet_received(&'a self, pipe: usize, result: Result<&[u8], TransferError>) {
+        let slot = match (0..MAX_HUBS).find(|&slot| self.hubs[slot].get().map_or(false, |hub| hub.pipe == pipe)) {
+            Some(slot) => slot,
+            None => return,
+        };
+        let mut hub = match self.hubs[slot].get() {
+            Some(hub) => hub,
+            None => return,
+        };
+
+        // Bit 0 is the hub's own status, and bit n the status of port n.
+        let data = match result {
+            Ok(data) => data,
+            Err(_) => return,
+        };
+        let mut changed = 0;
+        for (i, &byte) in data.iter().take(2).enumerate() {
+            changed |= (byte as u16) << (8 * i);
+        }
+        let ports = ((1u32 << (hub.ports + 1)) - 2) as u16;
+        hub.changed |= changed & ports;
+        self.hubs[slot].set(Some(hub));
+        self.next();
+    }
+}
@@ -0,0 +1,311 @@
+//! A driver for keyboards in the HID boot protocol [HID 1.11, Appendix B].
+//!
+//! Boot keyboards send 8-byte reports: a bitmap of the modifier keys, a
+//! reserved byte and the usage IDs of up to six other pressed keys. Each
+//! report is compared with the last to find the keys pressed and released.
+//! Caps lock and num lock are tracked here, and shown on the keyboard's
+//! LEDs. One keyboard is used at a time.
+//!
+//! A new keyboard is put in the boot protocol before its reports are read.
+//! These requests, and those that update the LEDs, each start when the
+//! last has finished.
+
+use core::cell::Cell;
+use kernel::common::cells::TakeCell;
+use mk66::usbhs::{PipeType, TransferError, UsbHs};
+use usbhost::{ClassDriver, Device, Interface};
+
+const HID_CLASS: u8 = 0x03;
+const BOOT_SUBCLASS: u8 = 0x01;
+const KEYBOARD_PROTOCOL: u8 = 0x01;
+
+// [HID 1.11, Section 7.2]
+const INTERFACE_OUT: u8 = 0x21;
+const SET_REPORT: u8 = 0x09;
+const SET_IDLE: u8 = 0x0A;
+const SET_PROTOCOL: u8 = 0x0B;
+const BOOT_PROTOCOL: u16 = 0;
+const OUTPUT_REPORT: u16 = 2 << 8;
+
+const REPORT_SIZE: usize = 8;
+
+/// Holds the output report.
+pub static mut BUFFER: [u8; 1] = [0; 1];
+
+// Keyboard LEDs, as sent in the output report.
+pub const LED_NUM_LOCK: u8 = 1 << 0;
+pub const LED_CAPS_LOCK: u8 = 1 << 1;
+
+// Modifier bits, left and right.
+pub const MODIFIER_CTRL: u8 = 0x11;
+pub const MODIFIER_SHIFT: u8 = 0x22;
+pub const MODIFIER_ALT: u8 = 0x44;
+pub const MODIFIER_GUI: u8 = 0x88;
+
+// [HID Usage Tables 1.12, Section 10]
+const USAGE_ROLLOVER: u8 = 0x01;
+const USAGE_CAPS_LOCK: u8 = 0x39;
+const USAGE_NUM_LOCK: u8 = 0x53;
+const USAGE_LEFT_CTRL: u8 = 0xE0;
+
+/// The characters of usages 0x1E to 0x38, unshifted and shifted, on a US
+/// keyboard.
+static SYMBOLS: [(u8, u8); 27] = [
+    (b'1', b'!'), (b'2', b'@'), (b'3', b'#'), (b'4', b'$'), (b'5', b'%'),
+    (b'6', b'^'), (b'7', b'&'), (b'8', b'*'), (b'9', b'('), (b'0', b')'),
+    (b'\n', b'\n'), (0x1B, 0x1B), (0x08, 0x08), (b'\t', b'\t'), (b' ', b' '),
+    (b'-', b'_'), (b'=', b'+'), (b'[', b'{'), (b']', b'}'), (b'\\', b'|'),
+    (b'#', b'~'), (b';', b':'), (b'\'', b'"'), (b'`', b'~'), (b',', b'<'),
+    (b'.', b'>'), (b'/', b'?'),
+];
+
+/// The characters of keypad usages 0x54 to 0x63. The digits and point
+/// need num lock.
+static KEYPAD: &'static [u8; 16] = b"/*-+\n1234567890.";
+
+/// The ASCII character a key types on a US keyboard, given the modifiers
+/// and locks, if any.
+pub fn ascii(usage: u8, modifiers: u8, leds: u8) -> Option<u8> {
+    let shift = modifiers & MODIFIER_SHIFT != 0;
+    match usage {
+        0x04...0x1D => {
+            let letter = b'a' + usage - 0x04;
+            if modifiers & MODIFIER_CTRL != 0 {
+                Some(letter & 0x1F)
+            } else if shift != (leds & LED_CAPS_LOCK != 0) {
+                Some(letter.to_ascii_uppercase())
+            } else {
+                Some(letter)
+            }
+        },
+        0x1E...0x38 => {
+            let (plain, shifted) = SYMBOLS[(usage - 0x1E) as usize];
+            Some(if shift { shifted } else { plain })
+        },
+        0x54...0x58 => Some(KEYPAD[(usage - 0x54) as usize]),
+        0x59...0x63 if leds & LED_NUM_LOCK != 0 => Some(KEYPAD[(usage - 0x54) as usize]),
+        _ => None,
+    }
+}
+
+pub trait Client {
+    /// Called when a key is pressed or released, with its usage ID and the
+    /// modifier bits after the change. Modifier keys have usages 0xE0 to
+    /// 0xE7.
+    fn key_changed(&self, usage: u8, modifiers: u8, pressed: bool);
+}
+
+/// The request that sets up a new keyboard next.
+#[derive(Copy, Clone, PartialEq)]
+enum Setup {
+    Protocol,
+    Idle,
+    Done,
+}
+
+pub struct Keyboard<'a> {
+    usbhs: &'a UsbHs<'a>,
+    device: Cell<Option<Device>>,
+    interface: Cell<u8>,
+    pipe: Cell<usize>,
+    report: Cell<[u8; REPORT_SIZE]>,
+    leds: Cell<u8>,
+    setup: Cell<Setup>,
+    // Whether the LEDs have changed since the keyboard was last told.
+    leds_changed: Cell<bool>,
+    buffer: TakeCell<'static, [u8]>,
+    // The control pipe of the request in progress.
+    busy: Cell<Option<usize>>,
+    client: Cell<Option<&'a Client>>,
+}
+
+impl<'a> Keyboard<'a> {
+    pub fn new(usbhs: &'a UsbHs<'a>, buffer: &'static mut [u8]) -> Keyboard<'a> {
+        Keyboard {
+            usbhs: usbhs,
+            device: Cell::new(None),
+            interface: Cell::new(0),
+            pipe: Cell::new(0),
+            report: Cell::new([0; REPORT_SIZE]),
+            leds: Cell::new(0),
+            setup: Cell::new(Setup::Done),
+            leds_changed: Cell::new(false),
+            buffer: TakeCell::new(buffer),
+            busy: Cell::new(None),
+            client: Cell::new(None),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a Client) {
+        self.client.set(Some(client));
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.device.get().is_some()
+    }
+
+    /// The lock LEDs that are lit.
+    pub fn leds(&self) -> u8 {
+        self.leds.get()
+    }
+
+    /// Makes the next request of the keyboard, unless one is in progress.
+    fn run(&self) {
+        let device = match self.device.get() {
+            Some(device) if self.busy.get().is_none() => device,
+            _ => return,
+        };
+        let (request, value, len) = match self.setup.get() {
+            // Keyboards start in the boot protocol, and may not support
+            // these.
+            Setup::Protocol => (SET_PROTOCOL, BOOT_PROTOCOL, 0),
+            Setup::Idle => (SET_IDLE, 0, 0),
+            Setup::Done if self.leds_changed.get() => {
+                self.leds_changed.set(false);
+                (SET_REPORT, OUTPUT_REPORT, 1)
+            },
+            Setup::Done => return,
+        };
+        let buffer = match self.buffer.take() {
+            Some(buffer) => buffer,
+            None => return,
+        };
+        buffer[0] = self.leds.get();
+
+        let interface = self.interface.get() as u16;
+        match device.control(self.usbhs, INTERFACE_OUT, request, value, interface, buffer, len) {
+            Ok(()) => self.busy.set(Some(device.control_pipe())),
+            Err((_, buffer)) => {
+                self.buffer.replace(buffer);
+                self.request_done();
+            },
+        }
+    }
+
+    /// Moves on from the last request, whether or not it succeeded.
+    fn request_done(&self) {
+        match self.setup.get() {
+            Setup::Protocol => self.setup.set(Setup::Idle),
+            Setup::Idle => {
+                self.setup.set(Setup::Done);
+                self.usbhs.start_receiving(self.pipe.get());
+            },
+            Setup::Done => {},
+        }
+        self.run();
+    }
+
+    fn update_leds(&self) {
+        self.leds_changed.set(true);
+        self.run();
+    }
+
+    fn key_changed(&self, usage: u8, modifiers: u8, pressed: bool) {
+        if pressed {
+            let toggled = match usage {
+                USAGE_CAPS_LOCK => LED_CAPS_LOCK,
+                USAGE_NUM_LOCK => LED_NUM_LOCK,
+                _ => 0,
+            };
+            if toggled != 0 {
+                self.leds.set(self.leds.get() ^ toggled);
+                self.update_leds();
+            }
+        }
+        self.client.get().map(|client| client.key_changed(usage, modifiers, pressed));
+    }
+
+    fn report_received(&self, report: &[u8; REPORT_SIZE]) {
+        // Too many keys are down to tell which.
+        if report[2] == USAGE_ROLLOVER {
+            return;
+        }
+        let last = self.report.get();
+        self.report.set(*report);
+
+        let modifiers = report[0];
+        for bit in 0..8 {
+            let mask = 1 << bit;
+            if (last[0] ^ modifiers) & mask != 0 {
+                self.key_changed(USAGE_LEFT_CTRL + bit, modifiers, modifiers & mask != 0);
+            }
+        }
+        for &usage in last[2..].iter() {
+            if usage != 0 && !report[2..].contains(&usage) {
+                self.key_changed(usage, modifiers, false);
+            }
+        }
+        for &usage in report[2..].iter() {
+            if usage != 0 && !last[2..].contains(&usage) {
+                self.key_changed(usage, modifiers, true);
+            }
+        }
+    }
+}
+
+impl<'a> ClassDriver<'a> for Keyboard<'a> {
+    fn probe(&'a self, device: &Device, interface: &Interface) -> bool {
+        if interface.class != HID_CLASS ||
+           interface.subclass != BOOT_SUBCLASS ||
+           interface.protocol != KEYBOARD_PROTOCOL ||
+           self.device.get().is_some() {
+            return false;
+        }
+        let endpoint = match interface.endpoint(PipeType::Interrupt, true) {
+            Some(endpoint) => endpoint,
+            None => return false,
+        };
+
+        let pipe = match device.open_pipe(self.usbhs, &endpoint) {
+            Some(pipe) => pipe,
+            None => return false,
+        };
+        self.device.set(Some(*device));
+        self.interface.set(interface.number);
+        self.pipe.set(pipe);
+        self.report.set([0; REPORT_SIZE]);
+        self.setup.set(Setup::Protocol);
+        self.leds_changed.set(true);
+        self.run();
+        true
+    }
+
+    fn disconnected(&'a self, address: u8) {
+        if self.device.get().map_or(false, |device| device.address == address) {
+            self.device.set(None);
+            self.usbhs.close_pipe(self.pipe.get());
+
+            // Release any keys that were down.
+            self.report_received(&[0; REPORT_SIZE]);
+        }
+    }
+
+    fn waiting(&self, pipe: usize) -> bool {
+        self.busy.get() == Some(pipe)
+    }
+
+    fn transfer_done(&'a self, pipe: usize, buffer: &'static mut [u8], _result: Result<usize, TransferError>) {
+        self.busy.set(None);
+        self.buffer.replace(buffer);
+        // A request of a keyboard since unplugged leaves the next
+        // keyboard's setup where it was.
+        if self.device.get().map_or(false, |device| device.control_pipe() == pipe) {
+            self.request_done();
+        } else {
+            self.run();
+        }
+    }
+
+    fn packet_received(&'a self, pipe: usize, result: Result<&[u8], TransferError>) {
+        if self.device.get().is_none() || pipe != self.pipe.get() {
+            return;
+        }
+        if let Ok(data) = result {
+            if data.len() >= REPORT_SIZE {
+                let mut report = [0; REPORT_SIZE];
+                report.copy_from_slice(&data[..REPORT_SIZE]);
+                self.report_received(&report);
+            }
+        }
+    }
+}
@@ -0,0 +1,635 @@
+//! The Teensy's USB host port, built on the K66 high-speed controller.
+//!
+//! `UsbHost` enumerates devices plugged into the root port, and into the
+//! ports of hubs through `hub`. Each interface of a device's configuration
+//! is offered to the class drivers in turn, and the first that recognizes
+//! it claims it: `hub` for hubs, `keyboard` for boot keyboards and
+//! `storage` for USB sticks.
+//!
+//! Everything is split-phase: enumeration steps through its control
+//! requests as each completes, and waits out the delays USB calls for on a
+//! virtual alarm, as do the class drivers. Only one device at a time is
+//! reset and enumerated, as until it has an address it answers at address
+//! 0; a hub claims the host with `claim` before resetting one of its ports.
+
+pub mod hub;
+pub mod keyboard;
+pub mod storage;
+
+use capsules::virtual_alarm::VirtualMuxAlarm;
+use core::cell::Cell;
+use kernel::ReturnCode;
+use kernel::common::cells::TakeCell;
+use kernel::hil::time::{self, Alarm, Frequency};
+use mk66;
+use mk66::usbhs::{self, Pipe, PipeType, Speed, TransferError, UsbHs};
+use usb::device::{descriptor_type, request};
+
+/// The alarm the host and class drivers time delays with.
+pub type HostAlarm = VirtualMuxAlarm<'static, mk66::pit::Pit<'static>>;
+
+pub const MAX_DEVICES: usize = 8;
+
+/// The most interfaces and endpoints per interface that are offered to the
+/// class drivers.
+pub const MAX_INTERFACES: usize = 8;
+pub const MAX_ENDPOINTS: usize = 4;
+
+/// The size of the buffer holding a device's configuration descriptor.
+/// Interfaces past its end are ignored.
+pub const CONFIGURATION_SIZE: usize = 256;
+
+pub static mut CONFIGURATION_BUF: [u8; CONFIGURATION_SIZE] = [0; CONFIGURATION_SIZE];
+
+/// The hub address of devices on the root port, which is port 1.
+pub const ROOT_HUB: u8 = 0;
+const ROOT_PORT: u8 = 1;
+
+// [USB 2.0 Specification, Sections 7.1.7.3, 7.1.7.5 and 9.2.6.3]
+pub const CONNECT_DEBOUNCE_MS: u32 = 100;
+pub const RESET_RECOVERY_MS: u32 = 10;
+const SET_ADDRESS_RECOVERY_MS: u32 = 2;
+
+// The controller drives the root port's reset for 50ms.
+const RESET_POLL_MS: u32 = 10;
+const RESET_POLLS: usize = 10;
+
+// Request types of standard requests.
+const DEVICE_IN: u8 = 0x80;
+const DEVICE_OUT: u8 = 0x00;
+
+#[derive(Copy, Clone)]
+pub struct Device {
+    pub address: u8,
+    pub speed: Speed,
+    /// The address of the hub the device is plugged into, or `ROOT_HUB`,
+    /// and the port.
+    pub hub: u8,
+    pub port: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    // The high-speed hub and port whose transaction translator reaches a
+    // full or low speed device.
+    tt_hub: u8,
+    tt_port: u8,
+    control_pipe: usize,
+}
+
+impl Device {
+    fn pipe(&self, pipe_type: PipeType, endpoint: u8, max_packet_size: u16) -> Pipe {
+        Pipe {
+            pipe_type: pipe_type,
+            address: self.address,
+            endpoint: endpoint,
+            max_packet_size: max_packet_size,
+            speed: self.speed,
+            tt_hub: self.tt_hub,
+            tt_port: self.tt_port,
+        }
+    }
+
+    /// Starts a control request of the device, moving the first `len`
+    /// bytes of `buffer` in the direction the request type gives. The
+    /// buffer comes back through `transfer_done` on the device's control
+    /// pipe.
+    pub fn control(&self,
+                   usbhs: &UsbHs,
+                   request_type: u8,
+                   request: u8,
+                   value: u16,
+                   index: u16,
+                   buffer: &'static mut [u8],
+                   len: usize)
+                   -> Result<(), (TransferError, &'static mut [u8])> {
+        let length = len as u16;
+        let setup = [
+            request_type, request,
+            value as u8, (value >> 8) as u8,
+            index as u8, (index >> 8) as u8,
+            length as u8, (length >> 8) as u8,
+        ];
+        usbhs.control_transfer(self.control_pipe, &setup, buffer)
+    }
+
+    pub fn control_pipe(&self) -> usize {
+        self.control_pipe
+    }
+
+    /// Opens a pipe to one of the device's bulk or interrupt endpoints.
+    pub fn open_pipe(&self, usbhs: &UsbHs, endpoint: &Endpoint) -> Option<usize> {
+        let pipe_type = endpoint.pipe_type()?;
+        usbhs.open_pipe(self.pipe(pipe_type, endpoint.address, endpoint.max_packet_size))
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Endpoint {
+    pub address: u8,
+    pub attributes: u8,
+    pub max_packet_size: u16,
+    pub interval: u8,
+}
+
+impl Endpoint {
+    pub fn is_in(&self) -> bool {
+        self.address & 0x80 != 0
+    }
+
+    /// The pipe type for the endpoint, or `None` for isochronous endpoints,
+    /// which are not supported.
+    pub fn pipe_type(&self) -> Option<PipeType> {
+        match self.attributes & 0b11 {
+            0 => Some(PipeType::Control),
+            2 => Some(PipeType::Bulk),
+            3 => Some(PipeType::Interrupt),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Interface {
+    pub number: u8,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+    pub endpoints: [Option<Endpoint>; MAX_ENDPOINTS],
+}
+
+impl Interface {
+    /// The interface's first endpoint of the given type and direction.
+    pub fn endpoint(&self, pipe_type: PipeType, is_in: bool) -> Option<Endpoint> {
+        self.endpoints.iter()
+            .filter_map(|endpoint| *endpoint)
+            .find(|endpoint| endpoint.pipe_type() == Some(pipe_type) && endpoint.is_in() == is_in)
+    }
+}
+
+/// Finds the default setting of each interface in a configuration
+/// descriptor, with its endpoints.
+fn parse_interfaces(config: &[u8], interfaces: &mut [Option<Interface>; MAX_INTERFACES]) {
+    let mut offset = 0;
+    let mut count = 0;
+    let mut current = None;
+
+    while offset + 2 <= config.len() {
+        let len = config[offset] as usize;
+        if len < 2 || offset + len > config.len() {
+            break;
+        }
+        let descriptor = &config[offset..offset + len];
+        offset += len;
+
+        match descriptor[1] {
+            descriptor_type::INTERFACE if len >= 9 => {
+                current = None;
+                if descriptor[3] == 0 && count < MAX_INTERFACES {
+                    interfaces[count] = Some(Interface {
+                        number: descriptor[2],
+                        class: descriptor[5],
+                        subclass: descriptor[6],
+                        protocol: descriptor[7],
+                        endpoints: [None; MAX_ENDPOINTS],
+                    });
+                    current = Some(count);
+                    count += 1;
+                }
+            },
+            descriptor_type::ENDPOINT if len >= 7 => {
+                let endpoint = Endpoint {
+                    address: descriptor[2],
+                    attributes: descriptor[3],
+                    max_packet_size: (descriptor[4] as u16 | (descriptor[5] as u16) << 8) & 0x7FF,
+                    interval: descriptor[6],
+                };
+                if let Some(index) = current {
+                    if let Some(ref mut interface) = interfaces[index] {
+                        if let Some(slot) = interface.endpoints.iter_mut().find(|slot| slot.is_none()) {
+                            *slot = Some(endpoint);
+                        }
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+/// Sets `alarm` to fire in `ms` milliseconds.
+pub fn delay(alarm: &HostAlarm, ms: u32) {
+    let ticks = <HostAlarm as Alarm>::Frequency::frequency() / 1000 * ms;
+    alarm.set_alarm(alarm.now().wrapping_add(ticks));
+}
+
+/// A driver for a class of interfaces.
+pub trait ClassDriver<'a> {
+    /// Offered each interface of a newly configured device. Returns true
+    /// if the driver claims the interface, having opened the pipes it needs.
+    /// The driver finishes setting the interface up afterwards.
+    fn probe(&'a self, device: &Device, interface: &Interface) -> bool;
+
+    /// Called when the device at `address` is unplugged. The driver closes
+    /// its pipes to the device.
+    fn disconnected(&'a self, address: u8);
+
+    /// Whether the driver is waiting for a transfer on `pipe` to finish.
+    fn waiting(&self, pipe: usize) -> bool;
+
+    /// Called when the driver's transfer on `pipe` finishes, with its
+    /// buffer.
+    fn transfer_done(&'a self, pipe: usize, buffer: &'static mut [u8], result: Result<usize, TransferError>);
+
+    /// Called with each packet received on an interrupt pipe, which may
+    /// belong to another driver.
+    fn packet_received(&'a self, pipe: usize, result: Result<&[u8], TransferError>);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum State {
+    Idle,
+    // Waiting out a new connection on the root port, its reset, with the
+    // polls left, and the device's recovery from it.
+    Debounce,
+    Resetting(usize),
+    Recovering(Speed),
+    // Held by the hub at the address given, to reset the port given.
+    Claimed(u8, u8),
+    // Enumerating a device. Each state is named for the request in
+    // progress, or for the delay after `SET_ADDRESS`.
+    GetMaxPacketSize,
+    SetAddress,
+    AddressRecovery,
+    GetDeviceDescriptor,
+    GetConfigurationHeader,
+    GetConfiguration,
+    // With the length of the configuration descriptor.
+    SetConfiguration(usize),
+}
+
+pub struct UsbHost<'a> {
+    usbhs: &'a UsbHs<'a>,
+    alarm: &'a HostAlarm,
+    drivers: &'a [&'a ClassDriver<'a>],
+    // Each device's address is its index plus one.
+    devices: [Cell<Option<Device>>; MAX_DEVICES],
+    state: Cell<State>,
+    // The device being enumerated, the address it is to have, and the
+    // packet size of its control endpoint.
+    new_device: Cell<Option<Device>>,
+    new_address: Cell<u8>,
+    max_packet_size: Cell<u16>,
+    configuration: TakeCell<'static, [u8]>,
+    // The pipe of the control transfer holding `configuration`.
+    waiting: Cell<Option<usize>>,
+}
+
+impl<'a> UsbHost<'a> {
+    pub fn new(usbhs: &'a UsbHs<'a>,
+               alarm: &'a HostAlarm,
+               drivers: &'a [&'a ClassDriver<'a>],
+               configuration: &'static mut [u8])
+               -> UsbHost<'a> {
+        UsbHost {
+            usbhs: usbhs,
+            alarm: alarm,
+            drivers: drivers,
+            devices: [
+                Cell::new(None), Cell::new(None), Cell::new(None), Cell::new(None),
+                Cell::new(None), Cell::new(None), Cell::new(None), Cell::new(None),
+            ],
+            state: Cell::new(State::Idle),
+            new_device: Cell::new(None),
+            new_address: Cell::new(0),
+            max_packet_size: Cell::new(0),
+            configuration: TakeCell::new(configuration),
+            waiting: Cell::new(None),
+        }
+    }
+
+    pub fn device(&self, address: u8) -> Option<Device> {
+        if address == 0 || address as usize > MAX_DEVICES {
+            return None;
+        }
+        self.devices[address as usize - 1].get()
+    }
+
+    /// Claims the host for resetting and then enumerating the device on
+    /// `port` of the hub at `hub`. Returns false if the host is busy; the
+    /// hub tries again later. The hub then calls `attach` once the reset
+    /// has ended, or `release` if it failed.
+    pub fn claim(&self, hub: u8, port: u8) -> bool {
+        if self.state.get() != State::Idle || self.waiting.get().is_some() {
+            return false;
+        }
+        self.state.set(State::Claimed(hub, port));
+        true
+    }
+
+    pub fn release(&self, hub: u8, port: u8) {
+        if self.state.get() == State::Claimed(hub, port) {
+            self.state.set(State::Idle);
+        }
+    }
+
+    /// Enumerates a device that has just been reset on `port` of `hub`, and
+    /// offers its interfaces to the class drivers.
+    pub fn attach(&self, hub: u8, port: u8, speed: Speed) {
+        if self.state.get() == State::Claimed(hub, port) {
+            self.enumerate(hub, port, speed);
+        }
+    }
+
+    fn enumerate(&self, hub: u8, port: u8, speed: Speed) {
+        self.state.set(State::Idle);
+        let address = match self.devices.iter().position(|device| device.get().is_none()) {
+            Some(index) => index as u8 + 1,
+            None => return,
+        };
+
+        // Full and low speed devices behind a high-speed hub are reached
+        // through its transaction translator, and otherwise through the
+        // one their hub is reached through.
+        let (tt_hub, tt_port) = match self.device(hub) {
+            Some(parent) if parent.speed == Speed::High => (hub, port),
+            Some(parent) => (parent.tt_hub, parent.tt_port),
+            None => (0, 0),
+        };
+        let mut device = Device {
+            address: 0,
+            speed: speed,
+            hub: hub,
+            port: port,
+            vendor_id: 0,
+            product_id: 0,
+            tt_hub: tt_hub,
+            tt_port: tt_port,
+            control_pipe: 0,
+        };
+
+        // Until it has an address, the device answers at address 0 with
+        // packets of at least 8 bytes.
+        let max_packet_size = if speed == Speed::Low { 8 } else { 64 };
+        match self.usbhs.open_pipe(device.pipe(PipeType::Control, 0, max_packet_size)) {
+            Some(pipe) => device.control_pipe = pipe,
+            None => return,
+        }
+        self.new_device.set(Some(device));
+        self.new_address.set(address);
+
+        let device_descriptor = (descriptor_type::DEVICE as u16) << 8;
+        self.request(DEVICE_IN, request::GET_DESCRIPTOR, device_descriptor, 8, State::GetMaxPacketSize);
+    }
+
+    /// Makes the next request of the device being enumerated, with the
+    /// configuration buffer.
+    fn request(&self, request_type: u8, request: u8, value: u16, len: usize, next: State) {
+        let device = match self.new_device.get() {
+            Some(device) => device,
+            None => return,
+        };
+        let buffer = match self.configuration.take() {
+            Some(buffer) => buffer,
+            None => return self.fail(),
+        };
+        match device.control(self.usbhs, request_type, request, value, 0, buffer, len) {
+            Ok(()) => {
+                self.waiting.set(Some(device.control_pipe));
+                self.state.set(next);
+            },
+            Err((_, buffer)) => {
+                self.configuration.replace(buffer);
+                self.fail();
+            },
+        }
+    }
+
+    /// Gives up on the device being enumerated.
+    fn fail(&self) {
+        self.new_device.take().map(|device| self.usbhs.close_pipe(device.control_pipe));
+        self.state.set(State::Idle);
+    }
+
+    /// Takes the next step of enumeration once a request has finished.
+    fn enumeration_step(&self, buffer: &'static mut [u8], result: Result<usize, TransferError>) {
+        self.waiting.set(None);
+        // A device given up on still has its last request cancelled.
+        if self.new_device.get().is_none() {
+            self.configuration.replace(buffer);
+            return;
+        }
+        let len = match result {
+            Ok(len) => len,
+            Err(_) => {
+                self.configuration.replace(buffer);
+                return self.fail();
+            },
+        };
+
+        let config_descriptor = (descriptor_type::CONFIGURATION as u16) << 8;
+        let state = self.state.get();
+        match state {
+            State::GetMaxPacketSize => {
+                self.max_packet_size.set(buffer[7] as u16);
+                self.configuration.replace(buffer);
+                let address = self.new_address.get() as u16;
+                self.request(DEVICE_OUT, request::SET_ADDRESS, address, 0, State::SetAddress);
+            },
+            State::SetAddress => {
+                self.configuration.replace(buffer);
+                self.state.set(State::AddressRecovery);
+                delay(self.alarm, SET_ADDRESS_RECOVERY_MS);
+            },
+            State::GetDeviceDescriptor => {
+                self.new_device.get().map(|mut device| {
+                    device.vendor_id = buffer[8] as u16 | (buffer[9] as u16) << 8;
+                    device.product_id = buffer[10] as u16 | (buffer[11] as u16) << 8;
+                    self.new_device.set(Some(device));
+                });
+                self.configuration.replace(buffer);
+                self.request(DEVICE_IN, request::GET_DESCRIPTOR, config_descriptor, 9,
+                             State::GetConfigurationHeader);
+            },
+            State::GetConfigurationHeader => {
+                let total = buffer[2] as usize | (buffer[3] as usize) << 8;
+                let len = if total < buffer.len() { total } else { buffer.len() };
+                self.configuration.replace(buffer);
+                self.request(DEVICE_IN, request::GET_DESCRIPTOR, config_descriptor, len,
+                             State::GetConfiguration);
+            },
+            State::GetConfiguration => {
+                let value = buffer[5] as u16;
+                self.configuration.replace(buffer);
+                self.request(DEVICE_OUT, request::SET_CONFIGURATION, value, 0,
+                             State::SetConfiguration(len));
+            },
+            State::SetConfiguration(config_len) => {
+                let mut interfaces = [None; MAX_INTERFACES];
+                parse_interfaces(&buffer[..config_len], &mut interfaces);
+                self.configuration.replace(buffer);
+                self.configured(&interfaces);
+            },
+            _ => {
+                self.configuration.replace(buffer);
+            },
+        }
+    }
+
+    /// Gives the device being enumerated its address, once it has had time
+    /// to take it, and reads its descriptor there.
+    fn address_set(&self) {
+        let mut device = match self.new_device.get() {
+            Some(device) => device,
+            None => return self.fail(),
+        };
+        self.usbhs.close_pipe(device.control_pipe);
+        device.address = self.new_address.get();
+        match self.usbhs.open_pipe(device.pipe(PipeType::Control, 0, self.max_packet_size.get())) {
+            Some(pipe) => device.control_pipe = pipe,
+            None => {
+                self.new_device.set(None);
+                return self.fail();
+            },
+        }
+        self.new_device.set(Some(device));
+
+        let device_descriptor = (descriptor_type::DEVICE as u16) << 8;
+        self.request(DEVICE_IN, request::GET_DESCRIPTOR, device_descriptor, 18,
+                     State::GetDeviceDescriptor);
+    }
+
+    /// Adds a newly configured device, and offers its interfaces to the
+    /// class drivers.
+    fn configured(&self, interfaces: &[Option<Interface>; MAX_INTERFACES]) {
+        self.state.set(State::Idle);
+        let device = match self.new_device.take() {
+            Some(device) => device,
+            None => return,
+        };
+        self.devices[device.address as usize - 1].set(Some(device));
+        for interface in interfaces.iter().filter_map(|interface| *interface) {
+            for driver in self.drivers.iter() {
+                if driver.probe(&device, &interface) {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Stops enumerating or resetting a device if it was on `port` of
+    /// `hub`, or on any port of `hub` if `port` is `None`.
+    fn abort(&self, hub: u8, port: Option<u8>) {
+        let matches = |device_hub: u8, device_port: u8| {
+            device_hub == hub && port.map_or(true, |port| port == device_port)
+        };
+        if let Some(device) = self.new_device.get() {
+            if matches(device.hub, device.port) {
+                self.fail();
+            }
+        }
+        if let State::Claimed(claim_hub, claim_port) = self.state.get() {
+            if matches(claim_hub, claim_port) {
+                self.state.set(State::Idle);
+            }
+        }
+    }
+
+    /// Forgets the device on `port` of `hub`, along with any devices behind
+    /// it.
+    pub fn detach(&self, hub: u8, port: u8) {
+        self.abort(hub, Some(port));
+        let found = self.devices.iter()
+            .filter_map(|device| device.get())
+            .find(|device| device.hub == hub && device.port == port);
+        found.map(|device| self.remove(device.address));
+    }
+
+    fn remove(&self, address: u8) {
+        self.abort(address, None);
+        for child in self.devices.iter().filter_map(|device| device.get()) {
+            if child.hub == address {
+                self.remove(child.address);
+            }
+        }
+
+        for driver in self.drivers.iter() {
+            driver.disconnected(address);
+        }
+        self.devices[address as usize - 1].take().map(|device| {
+            self.usbhs.close_pipe(device.control_pipe);
+        });
+    }
+
+    /// Starts over on the root port, once anything on it has been
+    /// forgotten.
+    fn root_port_changed(&self, connected: bool) {
+        self.detach(ROOT_HUB, ROOT_PORT);
+        self.fail();
+        if connected {
+            self.state.set(State::Debounce);
+            delay(self.alarm, CONNECT_DEBOUNCE_MS);
+        }
+    }
+}
+
+impl<'a> time::Client for UsbHost<'a> {
+    fn fired(&self) {
+        match self.state.get() {
+            State::Debounce => {
+                if self.usbhs.reset_port() == ReturnCode::SUCCESS {
+                    self.state.set(State::Resetting(RESET_POLLS));
+                    delay(self.alarm, RESET_POLL_MS);
+                } else {
+                    self.state.set(State::Idle);
+                }
+            },
+            State::Resetting(polls) => {
+                match self.usbhs.port_enabled() {
+                    Some(speed) => {
+                        self.state.set(State::Recovering(speed));
+                        delay(self.alarm, RESET_RECOVERY_MS);
+                    },
+                    None if polls > 1 => {
+                        self.state.set(State::Resetting(polls - 1));
+                        delay(self.alarm, RESET_POLL_MS);
+                    },
+                    None => self.state.set(State::Idle),
+                }
+            },
+            State::Recovering(speed) => {
+                if self.waiting.get().is_none() {
+                    self.enumerate(ROOT_HUB, ROOT_PORT, speed);
+                } else {
+                    self.state.set(State::Idle);
+                }
+            },
+            State::AddressRecovery => self.address_set(),
+            _ => {},
+        }
+    }
+}
+
+impl<'a> usbhs::Client<'a> for UsbHost<'a> {
+    fn port_changed(&'a self, connected: bool) {
+        self.root_port_changed(connected);
+    }
+
+    fn transfer_done(&'a self, pipe: usize, buffer: &'static mut [u8], result: Result<usize, TransferError>) {
+        if self.waiting.get() == Some(pipe) {
+            return self.enumeration_step(buffer, result);
+        }
+        if let Some(driver) = self.drivers.iter().find(|driver| driver.waiting(pipe)) {
+            driver.transfer_done(pipe, buffer, result);
+        }
+    }
+
+    fn packet_received(&'a self, pipe: usize, result: Result<&[u8], TransferError>) {
+        for driver in self.drivers.iter() {
+            driver.packet_received(pipe, result);
+        }
+    }
+
+    fn host_error(&'a self) {
+        // A device still plugged in is reported again.
+        self.root_port_changed(false);
+    }
+}
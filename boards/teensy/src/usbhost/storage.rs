@@ -0,0 +1,556 @@
+//! A mass storage driver for USB sticks and card readers.
+//!
+//! The driver speaks the bulk-only transport [USB Mass Storage Class
+//! Bulk-Only Transport 1.0] with SCSI block commands, to the first logical
+//! unit of one device at a time. Each command goes out in a command block
+//! wrapper, followed by its data, and the device answers with a command
+//! status wrapper. Each stage starts when the last has finished, and the
+//! client's buffer is handed back through `Client::transfer_done`.
+
+use core::cell::Cell;
+use kernel::ReturnCode;
+use kernel::common::cells::TakeCell;
+use kernel::hil::time;
+use mk66::sdhc::BLOCK_SIZE;
+use mk66::usbhs::{PipeType, TransferError, UsbHs, MAX_TRANSFER_SIZE};
+use usb::device::request;
+use usbhost::{self, ClassDriver, Device, HostAlarm, Interface};
+
+const MASS_STORAGE_CLASS: u8 = 0x08;
+const SCSI_SUBCLASS: u8 = 0x06;
+const BULK_ONLY_PROTOCOL: u8 = 0x50;
+
+// [Bulk-Only Transport, Section 3]
+const INTERFACE_OUT: u8 = 0x21;
+const MASS_STORAGE_RESET: u8 = 0xFF;
+
+// [Bulk-Only Transport, Section 5]
+const CBW_SIGNATURE: u32 = 0x4342_5355;
+const CSW_SIGNATURE: u32 = 0x5342_5355;
+const CBW_SIZE: usize = 31;
+const CSW_SIZE: usize = 13;
+const CBW_DATA_IN: u8 = 0x80;
+
+const STATUS_PASSED: u8 = 0;
+const STATUS_FAILED: u8 = 1;
+
+// [SCSI Primary Commands and SCSI Block Commands]
+const TEST_UNIT_READY: u8 = 0x00;
+const REQUEST_SENSE: u8 = 0x03;
+const READ_CAPACITY_10: u8 = 0x25;
+const READ_10: u8 = 0x28;
+const WRITE_10: u8 = 0x2A;
+
+const SENSE_SIZE: u8 = 18;
+
+// [USB 2.0 Specification, Table 9-6]
+const ENDPOINT_HALT: u16 = 0;
+const ENDPOINT_OUT: u8 = 0x02;
+
+// Sticks may take a few seconds to become ready after they are plugged in.
+const READY_RETRIES: usize = 30;
+const READY_RETRY_MS: u32 = 100;
+
+/// Holds command block and status wrappers, and the data of the commands
+/// that bring a device up.
+pub static mut COMMAND_BUF: [u8; 32] = [0; 32];
+
+pub trait Client {
+    /// Called when a device is attached and ready, or detached.
+    fn device_changed(&self, present: bool);
+
+    /// Called when a read or write ends, with the buffer it was given.
+    /// The result is `EOFF` if the device was detached.
+    fn transfer_done(&self, buffer: &'static mut [u8], result: ReturnCode);
+}
+
+#[derive(Copy, Clone)]
+struct Disk {
+    device: Device,
+    interface: u8,
+    in_endpoint: u8,
+    out_endpoint: u8,
+    in_pipe: usize,
+    out_pipe: usize,
+    blocks: u32,
+}
+
+/// The command in progress. A new device is polled until it is ready,
+/// with the retries left, and then its capacity is read.
+#[derive(Copy, Clone, PartialEq)]
+enum Op {
+    Idle,
+    TestUnitReady(usize),
+    RequestSense(usize),
+    RetryWait(usize),
+    ReadCapacity,
+    Read,
+    Write,
+}
+
+impl Op {
+    /// Whether the command's data goes to the client's buffer, rather
+    /// than the command buffer.
+    fn uses_client_buffer(&self) -> bool {
+        *self == Op::Read || *self == Op::Write
+    }
+}
+
+/// The stage of the command in progress, or of the reset recovery that
+/// follows a failed one.
+#[derive(Copy, Clone, PartialEq)]
+enum Phase {
+    Command,
+    Data,
+    DataClearHalt,
+    // With whether the status has already stalled once.
+    Status(bool),
+    StatusClearHalt,
+    Reset,
+    ResetClearIn,
+    ResetClearOut,
+}
+
+pub struct Storage<'a> {
+    usbhs: &'a UsbHs<'a>,
+    alarm: &'a HostAlarm,
+    disk: Cell<Option<Disk>>,
+    ready: Cell<bool>,
+    tag: Cell<u32>,
+    op: Cell<Op>,
+    phase: Cell<Phase>,
+    // The length and direction of the command's data stage.
+    data_len: Cell<usize>,
+    data_in: Cell<bool>,
+    command: TakeCell<'static, [u8]>,
+    data: TakeCell<'static, [u8]>,
+    // The pipe of the transfer in progress.
+    busy: Cell<Option<usize>>,
+    client: Cell<Option<&'a Client>>,
+}
+
+impl<'a> Storage<'a> {
+    pub fn new(usbhs: &'a UsbHs<'a>, alarm: &'a HostAlarm, command: &'static mut [u8]) -> Storage<'a> {
+        Storage {
+            usbhs: usbhs,
+            alarm: alarm,
+            disk: Cell::new(None),
+            ready: Cell::new(false),
+            tag: Cell::new(0),
+            op: Cell::new(Op::Idle),
+            phase: Cell::new(Phase::Command),
+            data_len: Cell::new(0),
+            data_in: Cell::new(false),
+            command: TakeCell::new(command),
+            data: TakeCell::empty(),
+            busy: Cell::new(None),
+            client: Cell::new(None),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a Client) {
+        self.client.set(Some(client));
+    }
+
+    /// Whether a device is attached and ready.
+    pub fn is_present(&self) -> bool {
+        self.ready.get()
+    }
+
+    pub fn block_count(&self) -> u32 {
+        if self.ready.get() {
+            self.disk.get().map_or(0, |disk| disk.blocks)
+        } else {
+            0
+        }
+    }
+
+    /// Starts reading blocks from `block` on, filling `buffer`, which is
+    /// a whole number of blocks no longer than `MAX_TRANSFER_SIZE`.
+    pub fn read_blocks(&self, block: u32, buffer: &'static mut [u8])
+                       -> Result<(), (ReturnCode, &'static mut [u8])> {
+        self.transfer(block, buffer, Op::Read)
+    }
+
+    /// Starts writing `buffer` to the blocks from `block` on, as
+    /// `read_blocks`.
+    pub fn write_blocks(&self, block: u32, buffer: &'static mut [u8])
+                        -> Result<(), (ReturnCode, &'static mut [u8])> {
+        self.transfer(block, buffer, Op::Write)
+    }
+
+    fn transfer(&self, block: u32, buffer: &'static mut [u8], op: Op)
+                -> Result<(), (ReturnCode, &'static mut [u8])> {
+        if !self.ready.get() {
+            return Err((ReturnCode::EOFF, buffer));
+        }
+        if self.op.get() != Op::Idle {
+            return Err((ReturnCode::EBUSY, buffer));
+        }
+        let len = buffer.len();
+        let count = len / BLOCK_SIZE;
+        if len == 0 || len % BLOCK_SIZE != 0 || len > MAX_TRANSFER_SIZE {
+            return Err((ReturnCode::EINVAL, buffer));
+        }
+        if block as u64 + count as u64 > self.block_count() as u64 {
+            return Err((ReturnCode::EINVAL, buffer));
+        }
+
+        let opcode = if op == Op::Read { READ_10 } else { WRITE_10 };
+        let command = [
+            opcode, 0,
+            (block >> 24) as u8, (block >> 16) as u8, (block >> 8) as u8, block as u8,
+            0, (count >> 8) as u8, count as u8, 0,
+        ];
+        self.data.replace(buffer);
+        self.op.set(op);
+        match self.start_command(&command, len, op == Op::Read) {
+            ReturnCode::SUCCESS => Ok(()),
+            err => {
+                self.op.set(Op::Idle);
+                match self.data.take() {
+                    Some(buffer) => Err((err, buffer)),
+                    None => Ok(()),
+                }
+            },
+        }
+    }
+
+    /// Starts one of the commands that bring a new device up.
+    fn start(&self, op: Op) {
+        self.op.set(op);
+        let result = match op {
+            Op::TestUnitReady(_) => {
+                self.start_command(&[TEST_UNIT_READY, 0, 0, 0, 0, 0], 0, false)
+            },
+            Op::RequestSense(_) => {
+                // Reading the sense data clears the condition, such as a
+                // unit attention after power on.
+                let command = [REQUEST_SENSE, 0, 0, 0, SENSE_SIZE, 0];
+                self.start_command(&command, SENSE_SIZE as usize, true)
+            },
+            Op::ReadCapacity => {
+                let command = [READ_CAPACITY_10, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+                self.start_command(&command, 8, true)
+            },
+            _ => ReturnCode::SUCCESS,
+        };
+        if result != ReturnCode::SUCCESS {
+            self.command_done(result);
+        }
+    }
+
+    /// Starts sending a SCSI command in a command block wrapper. Unlike
+    /// the later stages, a command that cannot be started is not reported
+    /// through `command_done`, so reads and writes never end before they
+    /// have started.
+    fn start_command(&self, block: &[u8], len: usize, data_in: bool) -> ReturnCode {
+        let disk = match self.disk.get() {
+            Some(disk) => disk,
+            None => return ReturnCode::EOFF,
+        };
+        let cbw = match self.command.take() {
+            Some(cbw) => cbw,
+            None => return ReturnCode::EBUSY,
+        };
+        let tag = self.tag.get().wrapping_add(1);
+        self.tag.set(tag);
+        self.data_len.set(len);
+        self.data_in.set(data_in);
+
+        for byte in cbw.iter_mut() {
+            *byte = 0;
+        }
+        cbw[0..4].copy_from_slice(&u32_le(CBW_SIGNATURE));
+        cbw[4..8].copy_from_slice(&u32_le(tag));
+        cbw[8..12].copy_from_slice(&u32_le(len as u32));
+        cbw[12] = if data_in { CBW_DATA_IN } else { 0 };
+        cbw[14] = block.len() as u8;
+        cbw[15..15 + block.len()].copy_from_slice(block);
+
+        self.phase.set(Phase::Command);
+        match self.usbhs.bulk_out(disk.out_pipe, cbw, CBW_SIZE) {
+            Ok(()) => {
+                self.busy.set(Some(disk.out_pipe));
+                ReturnCode::SUCCESS
+            },
+            Err((_, cbw)) => {
+                self.command.replace(cbw);
+                ReturnCode::FAIL
+            },
+        }
+    }
+
+    fn bulk(&self, disk: &Disk, is_in: bool, buffer: &'static mut [u8], len: usize, phase: Phase) {
+        self.phase.set(phase);
+        if is_in {
+            let result = self.usbhs.bulk_in(disk.in_pipe, buffer, len);
+            self.started(disk, disk.in_pipe, result);
+        } else {
+            let result = self.usbhs.bulk_out(disk.out_pipe, buffer, len);
+            self.started(disk, disk.out_pipe, result);
+        }
+    }
+
+    fn request(&self, disk: &Disk, request_type: u8, request: u8, value: u16, index: u16, phase: Phase) {
+        self.phase.set(phase);
+        let buffer = match self.command.take() {
+            Some(buffer) => buffer,
+            None => return self.command_done(ReturnCode::FAIL),
+        };
+        let result = disk.device.control(self.usbhs, request_type, request, value, index, buffer, 0);
+        self.started(disk, disk.device.control_pipe(), result);
+    }
+
+    fn started(&self, disk: &Disk, pipe: usize, result: Result<(), (TransferError, &'static mut [u8])>) {
+        match result {
+            Ok(()) => self.busy.set(Some(pipe)),
+            Err((err, buffer)) => self.step(disk, buffer, Err(err)),
+        }
+    }
+
+    fn data_stage(&self, disk: &Disk) {
+        let buffer = if self.op.get().uses_client_buffer() {
+            self.data.take()
+        } else {
+            self.command.take()
+        };
+        match buffer {
+            Some(buffer) => self.bulk(disk, self.data_in.get(), buffer, self.data_len.get(), Phase::Data),
+            None => self.command_done(ReturnCode::FAIL),
+        }
+    }
+
+    fn status_stage(&self, disk: &Disk, retried: bool) {
+        match self.command.take() {
+            Some(csw) => self.bulk(disk, true, csw, CSW_SIZE, Phase::Status(retried)),
+            None => self.command_done(ReturnCode::FAIL),
+        }
+    }
+
+    fn clear_halt(&self, disk: &Disk, endpoint: u8, phase: Phase) {
+        self.request(disk, ENDPOINT_OUT, request::CLEAR_FEATURE, ENDPOINT_HALT, endpoint as u16, phase);
+    }
+
+    /// Brings the device back in step after a failed transfer
+    /// [Bulk-Only Transport, Section 5.3.4].
+    fn reset_recovery(&self, disk: &Disk) {
+        self.request(disk, INTERFACE_OUT, MASS_STORAGE_RESET, 0, disk.interface as u16, Phase::Reset);
+    }
+
+    /// Takes the next stage of the command once a transfer has ended. A
+    /// stalled data stage ends the data early, but the command's status
+    /// decides the result.
+    fn step(&self, disk: &Disk, buffer: &'static mut [u8], result: Result<usize, TransferError>) {
+        match self.phase.get() {
+            Phase::Command => {
+                self.command.replace(buffer);
+                match result {
+                    Ok(_) if self.data_len.get() > 0 => self.data_stage(disk),
+                    Ok(_) => self.status_stage(disk, false),
+                    Err(_) => self.reset_recovery(disk),
+                }
+            },
+            Phase::Data => {
+                if self.op.get().uses_client_buffer() {
+                    self.data.replace(buffer);
+                } else {
+                    if result.is_ok() && self.op.get() == Op::ReadCapacity {
+                        self.capacity_read(&buffer[..8]);
+                    }
+                    self.command.replace(buffer);
+                }
+                match result {
+                    Ok(_) => self.status_stage(disk, false),
+                    Err(TransferError::Stall) => {
+                        let endpoint = if self.data_in.get() { disk.in_endpoint } else { disk.out_endpoint };
+                        self.clear_halt(disk, endpoint, Phase::DataClearHalt);
+                    },
+                    Err(_) => self.reset_recovery(disk),
+                }
+            },
+            Phase::DataClearHalt => {
+                self.command.replace(buffer);
+                self.usbhs.reset_pipe(if self.data_in.get() { disk.in_pipe } else { disk.out_pipe });
+                self.status_stage(disk, false);
+            },
+            Phase::Status(retried) => {
+                let valid = result == Ok(CSW_SIZE) &&
+                            buffer[0..4] == u32_le(CSW_SIGNATURE) &&
+                            buffer[4..8] == u32_le(self.tag.get());
+                let status = buffer[12];
+                self.command.replace(buffer);
+                match status {
+                    _ if result == Err(TransferError::Stall) && !retried => {
+                        self.clear_halt(disk, disk.in_endpoint, Phase::StatusClearHalt);
+                    },
+                    STATUS_PASSED if valid => self.command_done(ReturnCode::SUCCESS),
+                    STATUS_FAILED if valid => self.command_done(ReturnCode::FAIL),
+                    _ => self.reset_recovery(disk),
+                }
+            },
+            Phase::StatusClearHalt => {
+                self.command.replace(buffer);
+                self.usbhs.reset_pipe(disk.in_pipe);
+                self.status_stage(disk, true);
+            },
+            Phase::Reset => {
+                self.command.replace(buffer);
+                self.clear_halt(disk, disk.in_endpoint, Phase::ResetClearIn);
+            },
+            Phase::ResetClearIn => {
+                self.command.replace(buffer);
+                self.usbhs.reset_pipe(disk.in_pipe);
+                self.clear_halt(disk, disk.out_endpoint, Phase::ResetClearOut);
+            },
+            Phase::ResetClearOut => {
+                self.command.replace(buffer);
+                self.usbhs.reset_pipe(disk.out_pipe);
+                self.command_done(ReturnCode::FAIL);
+            },
+        }
+    }
+
+    fn capacity_read(&self, capacity: &[u8]) {
+        let last = u32_be(&capacity[0..4]);
+        let block_size = u32_be(&capacity[4..8]);
+        self.disk.get().map(|mut disk| {
+            disk.blocks = if block_size == BLOCK_SIZE as u32 { last.wrapping_add(1) } else { 0 };
+            self.disk.set(Some(disk));
+        });
+    }
+
+    fn command_done(&self, result: ReturnCode) {
+        match self.op.get() {
+            Op::TestUnitReady(_) if result == ReturnCode::SUCCESS => self.start(Op::ReadCapacity),
+            Op::TestUnitReady(retries) => self.start(Op::RequestSense(retries)),
+            Op::RequestSense(retries) if retries > 1 => {
+                self.op.set(Op::RetryWait(retries - 1));
+                usbhost::delay(self.alarm, READY_RETRY_MS);
+            },
+            Op::ReadCapacity => {
+                self.op.set(Op::Idle);
+                if result == ReturnCode::SUCCESS && self.disk.get().map_or(false, |disk| disk.blocks != 0) {
+                    self.ready.set(true);
+                    self.client.get().map(|client| client.device_changed(true));
+                }
+            },
+            Op::Read | Op::Write => {
+                self.op.set(Op::Idle);
+                self.data.take().map(|buffer| {
+                    self.client.get().map(move |client| client.transfer_done(buffer, result));
+                });
+            },
+            // A device that never becomes ready is left unused.
+            _ => self.op.set(Op::Idle),
+        }
+    }
+
+    /// Ends the command of a device that has been detached, once its
+    /// transfers have ended.
+    fn abandon(&self) {
+        self.op.set(Op::Idle);
+        self.data.take().map(|buffer| {
+            self.client.get().map(move |client| client.transfer_done(buffer, ReturnCode::EOFF));
+        });
+    }
+}
+
+fn u32_le(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+fn u32_be(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32
+}
+
+impl<'a> time::Client for Storage<'a> {
+    fn fired(&self) {
+        if let Op::RetryWait(retries) = self.op.get() {
+            self.start(Op::TestUnitReady(retries));
+        }
+    }
+}
+
+impl<'a> ClassDriver<'a> for Storage<'a> {
+    fn probe(&'a self, device: &Device, interface: &Interface) -> bool {
+        if interface.class != MASS_STORAGE_CLASS ||
+           interface.subclass != SCSI_SUBCLASS ||
+           interface.protocol != BULK_ONLY_PROTOCOL ||
+           self.disk.get().is_some() ||
+           self.busy.get().is_some() {
+            return false;
+        }
+        let (in_endpoint, out_endpoint) = match (interface.endpoint(PipeType::Bulk, true),
+                                                 interface.endpoint(PipeType::Bulk, false)) {
+            (Some(in_endpoint), Some(out_endpoint)) => (in_endpoint, out_endpoint),
+            _ => return false,
+        };
+
+        let in_pipe = match device.open_pipe(self.usbhs, &in_endpoint) {
+            Some(pipe) => pipe,
+            None => return false,
+        };
+        let out_pipe = match device.open_pipe(self.usbhs, &out_endpoint) {
+            Some(pipe) => pipe,
+            None => {
+                self.usbhs.close_pipe(in_pipe);
+                return false;
+            },
+        };
+
+        self.disk.set(Some(Disk {
+            device: *device,
+            interface: interface.number,
+            in_endpoint: in_endpoint.address,
+            out_endpoint: out_endpoint.address,
+            in_pipe: in_pipe,
+            out_pipe: out_pipe,
+            blocks: 0,
+        }));
+        self.ready.set(false);
+        self.start(Op::TestUnitReady(READY_RETRIES));
+        true
+    }
+
+    fn disconnected(&'a self, address: u8) {
+        if let Some(disk) = self.disk.get() {
+            if disk.device.address == address {
+                let was_ready = self.ready.get();
+                self.disk.set(None);
+                self.ready.set(false);
+                self.usbhs.close_pipe(disk.in_pipe);
+                self.usbhs.close_pipe(disk.out_pipe);
+
+                // A transfer in progress ends when its pipe closes.
+                if self.busy.get().is_none() {
+                    self.abandon();
+                }
+                if was_ready {
+                    self.client.get().map(|client| client.device_changed(false));
+                }
+            }
+        }
+    }
+
+    fn waiting(&self, pipe: usize) -> bool {
+        self.busy.get() == Some(pipe)
+    }
+
+    fn transfer_done(&'a self, _pipe: usize, buffer: &'static mut [u8], result: Result<usize, TransferError>) {
+        self.busy.set(None);
+        match self.disk.get() {
+            Some(disk) => self.step(&disk, buffer, result),
+            None => {
+                if self.phase.get() == Phase::Data && self.op.get().uses_client_buffer() {
+                    self.data.replace(buffer);
+                } else {
+                    self.command.replace(buffer);
+                }
+                self.abandon();
+            },
+        }
+    }
+
+    fn packet_received(&'a self, _pipe: usize, _result: Result<&[u8], TransferError>) {}
+}
@@ -0,0 +1,267 @@
+//! Provides userspace with sector-level access to a USB stick on the host
+//! port.
+//!
+//! Usage
+//! -----
+//!
+//! ```c
+//! allow(USBSTORAGE_DRIVER_NUM, 0, buffer, 512 * count);
+//! subscribe(USBSTORAGE_DRIVER_NUM, 1, transfer_done, NULL);
+//! command(USBSTORAGE_DRIVER_NUM, 1, 0, 0);         // is a stick attached?
+//! command(USBSTORAGE_DRIVER_NUM, 3, sector, count); // read into buffer
+//! command(USBSTORAGE_DRIVER_NUM, 4, sector, count); // write from buffer
+//! ```
+//!
+//! Reads and writes run a sector at a time, and one app's at a time. When
+//! one ends, the first argument of the transfer callback is its result, and
+//! is `EOFF` if the stick was detached. The buffer must stay allowed until
+//! then. Apps can also subscribe to be told when a stick is attached or
+//! detached; the callback's first argument is 1 when attached and 0 when
+//! detached.
+
+use core::cell::Cell;
+use kernel::{AppId, AppSlice, Callback, Driver, Grant, ReturnCode, Shared};
+use kernel::common::cells::TakeCell;
+use mk66::sdhc::BLOCK_SIZE;
+use usbhost::storage::{self, Storage};
+
+pub const DRIVER_NUM: usize = 0x90009;
+
+pub static mut BUFFER: [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
+
+#[derive(Default)]
+pub struct App {
+    callback: Option<Callback>,
+    transfer_callback: Option<Callback>,
+    buffer: Option<AppSlice<Shared, u8>>,
+}
+
+/// A read or write in progress.
+#[derive(Copy, Clone)]
+struct Transfer {
+    appid: AppId,
+    read: bool,
+    sector: usize,
+    count: usize,
+    // Sectors moved so far.
+    done: usize,
+}
+
+pub struct UsbStorage<'a> {
+    storage: &'a Storage<'a>,
+    buffer: TakeCell<'static, [u8]>,
+    transfer: Cell<Option<Transfer>>,
+    apps: Grant<App>,
+}
+
+impl<'a> UsbStorage<'a> {
+    pub fn new(storage: &'a Storage<'a>,
+               buffer: &'static mut [u8],
+               grant: Grant<App>)
+               -> UsbStorage<'a> {
+        UsbStorage {
+            storage: storage,
+            buffer: TakeCell::new(buffer),
+            transfer: Cell::new(None),
+            apps: grant,
+        }
+    }
+
+    fn transfer(&self, sector: usize, count: usize, read: bool, app: &mut App, appid: AppId) -> ReturnCode {
+        if self.transfer.get().is_some() {
+            return ReturnCode::EBUSY;
+        }
+        let len = match app.buffer {
+            Some(ref slice) => slice.len(),
+            None => return ReturnCode::ERESERVE,
+        };
+        if count == 0 || count.checked_mul(BLOCK_SIZE).map_or(true, |n| n > len) {
+            return ReturnCode::ESIZE;
+        }
+        if !self.storage.is_present() {
+            return ReturnCode::EOFF;
+        }
+
+        let transfer = Transfer {
+            appid: appid,
+            read: read,
+            sector: sector,
+            count: count,
+            done: 0,
+        };
+        let result = self.start_sector(&transfer, app);
+        if result == ReturnCode::SUCCESS {
+            self.transfer.set(Some(transfer));
+        }
+        result
+    }
+
+    /// Starts moving the next sector of a transfer.
+    fn start_sector(&self, transfer: &Transfer, app: &mut App) -> ReturnCode {
+        let buffer = match self.buffer.take() {
+            Some(buffer) => buffer,
+            None => return ReturnCode::EBUSY,
+        };
+        let offset = transfer.done * BLOCK_SIZE;
+        let block = match transfer.sector.checked_add(transfer.done) {
+            Some(block) if block <= u32::max_value() as usize => block as u32,
+            _ => {
+                self.buffer.replace(buffer);
+                return ReturnCode::EINVAL;
+            },
+        };
+
+        let started = if transfer.read {
+            self.storage.read_blocks(block, buffer)
+        } else {
+            match app.buffer {
+                Some(ref slice) if slice.len() >= offset + BLOCK_SIZE => {
+                    buffer.copy_from_slice(&slice.as_ref()[offset..offset + BLOCK_SIZE]);
+                    self.storage.write_blocks(block, buffer)
+                },
+                _ => Err((ReturnCode::ESIZE, buffer)),
+            }
+        };
+        match started {
+            Ok(()) => ReturnCode::SUCCESS,
+            Err((err, buffer)) => {
+                self.buffer.replace(buffer);
+                err
+            },
+        }
+    }
+
+    /// Moves on from a sector that has been read or written, and returns
+    /// the result once the transfer is over.
+    fn sector_done(&self, transfer: &mut Transfer, app: &mut App, result: ReturnCode) -> Option<ReturnCode> {
+        if result != ReturnCode::SUCCESS {
+            return Some(result);
+        }
+        if transfer.read {
+            let offset = transfer.done * BLOCK_SIZE;
+            let copied = match app.buffer {
+                Some(ref mut slice) if slice.len() >= offset + BLOCK_SIZE => {
+                    self.buffer.map(|buffer| {
+                        slice.as_mut()[offset..offset + BLOCK_SIZE].copy_from_slice(buffer);
+                    });
+                    true
+                },
+                _ => false,
+            };
+            if !copied {
+                return Some(ReturnCode::ESIZE);
+            }
+        }
+
+        transfer.done += 1;
+        if transfer.done == transfer.count {
+            return Some(ReturnCode::SUCCESS);
+        }
+        match self.start_sector(transfer, app) {
+            ReturnCode::SUCCESS => None,
+            err => Some(err),
+        }
+    }
+}
+
+impl<'a> storage::Client for UsbStorage<'a> {
+    fn device_changed(&self, present: bool) {
+        self.apps.each(|app| {
+            app.callback.map(|mut cb| cb.schedule(present as usize, 0, 0));
+        });
+    }
+
+    fn transfer_done(&self, buffer: &'static mut [u8], result: ReturnCode) {
+        self.buffer.replace(buffer);
+        let mut transfer = match self.transfer.take() {
+            Some(transfer) => transfer,
+            None => return,
+        };
+
+        let _ = self.apps.enter(transfer.appid, |app, _| {
+            match self.sector_done(&mut transfer, app, result) {
+                Some(result) => {
+                    let code = isize::from(result) as usize;
+                    app.transfer_callback.map(|mut cb| cb.schedule(code, 0, 0));
+                },
+                None => self.transfer.set(Some(transfer)),
+            }
+        });
+    }
+}
+
+impl<'a> Driver for UsbStorage<'a> {
+    /// Setup callbacks.
+    ///
+    /// ### `subscribe_num`
+    ///
+    /// - `0`: Stick attached or detached
+    /// - `1`: Read or write done. The first argument is the result.
+    fn subscribe(&self, subscribe_num: usize, callback: Option<Callback>, app_id: AppId) -> ReturnCode {
+        match subscribe_num {
+            0 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            1 => {
+                self.apps.enter(app_id, |app, _| {
+                    app.transfer_callback = callback;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Setup the sector buffer.
+    ///
+    /// ### `allow_num`
+    ///
+    /// - `0`: Buffer for reads and writes, a multiple of 512 bytes long
+    fn allow(&self, appid: AppId, allow_num: usize, slice: Option<AppSlice<Shared, u8>>) -> ReturnCode {
+        match allow_num {
+            0 => {
+                self.apps.enter(appid, |app, _| {
+                    app.buffer = slice;
+                    ReturnCode::SUCCESS
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+
+    /// Access the stick.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver check.
+    /// - `1`: Return 1 if a stick is attached.
+    /// - `2`: Return the number of sectors on the stick.
+    /// - `3`: Start reading `arg2` sectors starting at sector `arg1` into the
+    ///        buffer.
+    /// - `4`: Start writing `arg2` sectors starting at sector `arg1` from the
+    ///        buffer.
+    fn command(&self, cmd_num: usize, arg1: usize, arg2: usize, appid: AppId) -> ReturnCode {
+        match cmd_num {
+            0 /* check if present */ => ReturnCode::SUCCESS,
+            1 /* attached */ => {
+                ReturnCode::SuccessWithValue { value: self.storage.is_present() as usize }
+            },
+            2 /* sector count */ => {
+                if self.storage.is_present() {
+                    ReturnCode::SuccessWithValue { value: self.storage.block_count() as usize }
+                } else {
+                    ReturnCode::EOFF
+                }
+            },
+            3 | 4 /* read, write */ => {
+                self.apps.enter(appid, |app, _| {
+                    self.transfer(arg1, arg2, cmd_num == 3, app, appid)
+                }).unwrap_or_else(|err| err.into())
+            },
+            _ => ReturnCode::ENOSUPPORT
+        }
+    }
+}
@@ -0,0 +1,502 @@
+//! Implementation of the MK66 FlexCAN controllers.
+//!
+//! Received frames pass through the six-deep RX FIFO, which is filtered by
+//! eight acceptance filters, each matching a standard or extended ID under a
+//! mask. With no filters set, every frame is accepted. The eight message
+//! buffers after the FIFO and its filter table are transmit mailboxes, and
+//! the controller sends the pending frame with the highest priority ID
+//! first.
+//!
+//! The controller leaves the bus after too many transmit errors, and rejoins
+//! it by itself once it has seen 128 occurrences of 11 recessive bits. There
+//! is no interrupt for rejoining, so the return to error active is reported
+//! with the next frame sent or received.
+
+use core::cell::Cell;
+use core::mem;
+use kernel::ReturnCode;
+use kernel::common::regs::{FieldValue, ReadWrite};
+use clock;
+use nvic::{self, NvicIdx};
+use regs::can::*;
+
+pub const N_FILTERS: usize = 8;
+pub const MAX_BITRATE: u32 = 1_000_000;
+
+/// Message buffers 0 to 5 hold the RX FIFO, and 6 and 7 its filter table.
+const FIRST_FILTER_MB: usize = 6;
+const FIRST_TX_MB: usize = 8;
+const LAST_MB: usize = 15;
+
+// RX FIFO flags in IFLAG1 [K66 Reference Manual, Section 44.4.10].
+const FIFO_AVAILABLE: u32 = 1 << 5;
+const FIFO_WARNING: u32 = 1 << 6;
+const FIFO_OVERFLOW: u32 = 1 << 7;
+const TX_MBS: u32 = 0xFF << FIRST_TX_MB;
+
+// Filter table elements in format A [K66 Reference Manual, Section 44.4.43].
+const FILTER_IDE: u32 = 1 << 30;
+const FILTER_EXT_SHIFT: u32 = 1;
+const FILTER_STD_SHIFT: u32 = 19;
+
+pub const MAX_STANDARD_ID: u32 = 0x7FF;
+pub const MAX_EXTENDED_ID: u32 = 0x1FFF_FFFF;
+
+// Polling bound for mode changes, which take a few bit times.
+const MODE_TIMEOUT: usize = 1_000_000;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Frame {
+    pub id: u32,
+    pub extended: bool,
+    pub remote: bool,
+    pub len: u8,
+    pub data: [u8; 8],
+}
+
+/// Accepts frames whose ID matches `id` in the bits set in `mask`.
+#[derive(Copy, Clone, Debug)]
+pub struct Filter {
+    pub id: u32,
+    pub mask: u32,
+    pub extended: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ErrorState {
+    Active,
+    Passive,
+    BusOff,
+}
+
+pub trait Client {
+    fn frame_received(&self, frame: &Frame);
+    fn frame_sent(&self);
+    fn error_state_changed(&self, state: ErrorState);
+}
+
+/// Segment lengths of one bit, in time quanta.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BitTiming {
+    pub prescaler: u32,
+    pub propagation: u32,
+    pub phase1: u32,
+    pub phase2: u32,
+    pub jump_width: u32,
+}
+
+/// Finds the bit timing for `bitrate` with the most time quanta per bit,
+/// sampling at about 87.5% of the bit. Returns `None` if the clock cannot be
+/// divided down to the bitrate exactly.
+pub fn bit_timing(clock_hz: u32, bitrate: u32) -> Option<BitTiming> {
+    if bitrate == 0 || bitrate > MAX_BITRATE {
+        return None;
+    }
+    for quanta in (8..26).rev() {
+        let quantum_hz = bitrate * quanta;
+        if clock_hz % quantum_hz != 0 {
+            continue;
+        }
+        let prescaler = clock_hz / quantum_hz;
+        if prescaler < 1 || prescaler > 256 {
+            continue;
+        }
+
+        // One quantum is the sync segment.
+        let phase2 = if (quanta + 4) / 8 < 2 { 2 } else { (quanta + 4) / 8 };
+        let before_sample = quanta - 1 - phase2;
+        if before_sample > 16 {
+            continue;
+        }
+        let propagation = before_sample / 2;
+        let phase1 = before_sample - propagation;
+        return Some(BitTiming {
+            prescaler: prescaler,
+            propagation: propagation,
+            phase1: phase1,
+            phase2: phase2,
+            jump_width: if phase2 < 4 { phase2 } else { 4 },
+        });
+    }
+    None
+}
+
+pub struct Can<'a> {
+    index: usize,
+    registers: *mut Registers,
+    client: Cell<Option<&'a Client>>,
+    enabled: Cell<bool>,
+    filters: Cell<[Option<Filter>; N_FILTERS]>,
+    // Transmit mailboxes holding a frame, by IFLAG1 bit.
+    tx_pending: Cell<u32>,
+    error_state: Cell<ErrorState>,
+    rx_overflows: Cell<usize>,
+}
+
+pub static mut CAN0: Can<'static> = Can::new(0);
+pub static mut CAN1: Can<'static> = Can::new(1);
+
+impl<'a> Can<'a> {
+    const fn new(index: usize) -> Can<'a> {
+        Can {
+            index: index,
+            registers: CAN_BASE_ADDRS[index],
+            client: Cell::new(None),
+            enabled: Cell::new(false),
+            filters: Cell::new([None; N_FILTERS]),
+            tx_pending: Cell::new(0),
+            error_state: Cell::new(ErrorState::Active),
+            rx_overflows: Cell::new(0),
+        }
+    }
+
+    fn regs(&self) -> &mut Registers {
+        unsafe { mem::transmute(self.registers) }
+    }
+
+    pub fn set_client(&self, client: &'a Client) {
+        self.client.set(Some(client));
+    }
+
+    fn enable_clock(&self) {
+        use sim::{clocks, Clock};
+        match self.index {
+            0 => clocks::FLEXCAN0.enable(),
+            1 => clocks::FLEXCAN1.enable(),
+            _ => unreachable!()
+        };
+    }
+
+    fn enable_interrupts(&self) {
+        let irqs = match self.index {
+            0 => [NvicIdx::CAN0_MSGBUF, NvicIdx::CAN0_BUSOFF, NvicIdx::CAN0_ERR,
+                  NvicIdx::CAN0_TX, NvicIdx::CAN0_RX],
+            1 => [NvicIdx::CAN1_MSBBUF, NvicIdx::CAN1_BUSOFF, NvicIdx::CAN1_ERR,
+                  NvicIdx::CAN1_TX, NvicIdx::CAN1_RX],
+            _ => unreachable!()
+        };
+        for &irq in irqs.iter() {
+            unsafe { nvic::enable(irq) };
+        }
+    }
+
+    fn wait_for<F: Fn(&Registers) -> bool>(&self, done: F) -> ReturnCode {
+        let regs = self.regs();
+        for _ in 0..MODE_TIMEOUT {
+            if done(regs) {
+                return ReturnCode::SUCCESS;
+            }
+        }
+        ReturnCode::FAIL
+    }
+
+    fn freeze(&self) -> ReturnCode {
+        self.regs().mcr.modify(ModuleConfiguration::FRZ::SET + ModuleConfiguration::HALT::SET);
+        self.wait_for(|regs| regs.mcr.is_set(ModuleConfiguration::FRZACK))
+    }
+
+    fn unfreeze(&self) -> ReturnCode {
+        self.regs().mcr.modify(ModuleConfiguration::HALT::CLEAR);
+        self.wait_for(|regs| !regs.mcr.is_set(ModuleConfiguration::FRZACK))
+    }
+
+    /// Writes the filter table and masks. Only possible while frozen.
+    fn write_filters(&self) {
+        let regs = self.regs();
+        let table: &[ReadWrite<u32>; N_FILTERS] = unsafe { mem::transmute(&regs.mb[FIRST_FILTER_MB]) };
+
+        let filters = self.filters.get();
+        let mut active = [None; N_FILTERS];
+        let mut count = 0;
+        for filter in filters.iter().filter_map(|filter| *filter) {
+            active[count] = Some(filter);
+            count += 1;
+        }
+
+        for i in 0..N_FILTERS {
+            // Spare elements repeat the filters that are set, so they accept
+            // nothing else.
+            let (element, mask) = if count == 0 {
+                (0, 0)
+            } else {
+                let filter = active[i % count].unwrap();
+                if filter.extended {
+                    (FILTER_IDE | filter.id << FILTER_EXT_SHIFT,
+                     FILTER_IDE | filter.mask << FILTER_EXT_SHIFT)
+                } else {
+                    (filter.id << FILTER_STD_SHIFT,
+                     FILTER_IDE | filter.mask << FILTER_STD_SHIFT)
+                }
+            };
+            table[i].set(element);
+            regs.rximr[i].set(mask);
+        }
+    }
+
+    /// Joins the bus at `bitrate`. In loopback mode, sent frames are
+    /// received back without going out on the bus, so no transceiver is
+    /// needed.
+    pub fn enable(&self, bitrate: u32, loopback: bool) -> ReturnCode {
+        let timing = match bit_timing(clock::bus_clock_hz(), bitrate) {
+            Some(timing) => timing,
+            None => return ReturnCode::EINVAL,
+        };
+        let regs = self.regs();
+        self.enable_clock();
+
+        // The clock source can only be chosen while the module is disabled.
+        regs.mcr.modify(ModuleConfiguration::MDIS::SET);
+        if self.wait_for(|regs| regs.mcr.is_set(ModuleConfiguration::LPMACK)) != ReturnCode::SUCCESS {
+            return ReturnCode::FAIL;
+        }
+        regs.ctrl1.modify(Control1::CLKSRC::PeripheralClock);
+        regs.mcr.modify(ModuleConfiguration::MDIS::CLEAR);
+        if self.wait_for(|regs| !regs.mcr.is_set(ModuleConfiguration::LPMACK)) != ReturnCode::SUCCESS {
+            return ReturnCode::FAIL;
+        }
+
+        regs.mcr.modify(ModuleConfiguration::SOFTRST::SET);
+        if self.wait_for(|regs| !regs.mcr.is_set(ModuleConfiguration::SOFTRST)) != ReturnCode::SUCCESS {
+            return ReturnCode::FAIL;
+        }
+        if self.freeze() != ReturnCode::SUCCESS {
+            return ReturnCode::FAIL;
+        }
+
+        let self_reception = if loopback {
+            ModuleConfiguration::SRXDIS::CLEAR
+        } else {
+            ModuleConfiguration::SRXDIS::SET
+        };
+        regs.mcr.write(ModuleConfiguration::FRZ::SET +
+                       ModuleConfiguration::HALT::SET +
+                       ModuleConfiguration::RFEN::SET +
+                       ModuleConfiguration::WRNEN::SET +
+                       ModuleConfiguration::IRMQ::SET +
+                       ModuleConfiguration::IDAM::OneFullId +
+                       ModuleConfiguration::MAXMB.val(LAST_MB as u32) +
+                       self_reception);
+        regs.ctrl1.write(Control1::PRESDIV.val(timing.prescaler - 1) +
+                         Control1::RJW.val(timing.jump_width - 1) +
+                         Control1::PSEG1.val(timing.phase1 - 1) +
+                         Control1::PSEG2.val(timing.phase2 - 1) +
+                         Control1::PROPSEG.val(timing.propagation - 1) +
+                         Control1::CLKSRC::PeripheralClock +
+                         Control1::LPB.val(loopback as u32) +
+                         Control1::BOFFMSK::SET +
+                         Control1::ERRMSK::SET +
+                         Control1::TWRNMSK::SET +
+                         Control1::RWRNMSK::SET);
+        regs.ctrl2.modify(Control2::RFFN.val(0) + Control2::RRS::SET + Control2::MRP::CLEAR);
+
+        for mb in regs.mb[FIRST_TX_MB..].iter() {
+            mb.cs.write(ControlStatus::CODE::TxInactive);
+        }
+        self.write_filters();
+
+        regs.iflag1.set(0xFFFF_FFFF);
+        regs.esr1.write(error_interrupts());
+        regs.imask1.set(FIFO_AVAILABLE | FIFO_OVERFLOW | TX_MBS);
+        self.tx_pending.set(0);
+        self.error_state.set(ErrorState::Active);
+        self.enable_interrupts();
+
+        if self.unfreeze() != ReturnCode::SUCCESS {
+            return ReturnCode::FAIL;
+        }
+        self.enabled.set(true);
+        ReturnCode::SUCCESS
+    }
+
+    /// Leaves the bus, dropping any frames waiting to be sent.
+    pub fn disable(&self) {
+        if !self.enabled.get() {
+            return;
+        }
+        let regs = self.regs();
+        regs.imask1.set(0);
+        regs.ctrl1.modify(Control1::BOFFMSK::CLEAR + Control1::ERRMSK::CLEAR +
+                          Control1::TWRNMSK::CLEAR + Control1::RWRNMSK::CLEAR);
+        regs.mcr.modify(ModuleConfiguration::MDIS::SET);
+        self.tx_pending.set(0);
+        self.enabled.set(false);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.get()
+    }
+
+    /// Sets or clears acceptance filter `index`. Frames are received if they
+    /// match any filter that is set.
+    pub fn set_filter(&self, index: usize, filter: Option<Filter>) -> ReturnCode {
+        if index >= N_FILTERS {
+            return ReturnCode::EINVAL;
+        }
+        if let Some(filter) = filter {
+            let max = if filter.extended { MAX_EXTENDED_ID } else { MAX_STANDARD_ID };
+            if filter.id > max || filter.mask > max {
+                return ReturnCode::EINVAL;
+            }
+        }
+        let mut filters = self.filters.get();
+        filters[index] = filter;
+        self.filters.set(filters);
+
+        if !self.enabled.get() {
+            return ReturnCode::SUCCESS;
+        }
+        if self.freeze() != ReturnCode::SUCCESS {
+            return ReturnCode::FAIL;
+        }
+        self.write_filters();
+        self.unfreeze()
+    }
+
+    pub fn filter(&self, index: usize) -> Option<Filter> {
+        if index < N_FILTERS { self.filters.get()[index] } else { None }
+    }
+
+    /// Queues a frame in a free mailbox. `frame_sent` is called once it has
+    /// been sent.
+    pub fn send(&self, frame: &Frame) -> ReturnCode {
+        if !self.enabled.get() {
+            return ReturnCode::EOFF;
+        }
+        let max = if frame.extended { MAX_EXTENDED_ID } else { MAX_STANDARD_ID };
+        if frame.id > max || frame.len > 8 {
+            return ReturnCode::EINVAL;
+        }
+        let pending = self.tx_pending.get();
+        let index = match (FIRST_TX_MB..LAST_MB + 1).find(|&i| pending & (1 << i) == 0) {
+            Some(index) => index,
+            None => return ReturnCode::EBUSY,
+        };
+        self.tx_pending.set(pending | 1 << index);
+
+        let mb = &self.regs().mb[index];
+        mb.cs.write(ControlStatus::CODE::TxInactive);
+        if frame.extended {
+            mb.id.write(Identifier::EXT.val(frame.id));
+        } else {
+            mb.id.write(Identifier::STD.val(frame.id));
+        }
+        mb.word0.set(be_word(&frame.data[0..4]));
+        mb.word1.set(be_word(&frame.data[4..8]));
+        mb.cs.write(ControlStatus::CODE::TxData +
+                    ControlStatus::SRR.val(frame.extended as u32) +
+                    ControlStatus::IDE.val(frame.extended as u32) +
+                    ControlStatus::RTR.val(frame.remote as u32) +
+                    ControlStatus::DLC.val(frame.len as u32));
+        ReturnCode::SUCCESS
+    }
+
+    /// Returns the transmit and receive error counters.
+    pub fn error_counters(&self) -> (u8, u8) {
+        if !self.enabled.get() {
+            return (0, 0);
+        }
+        let regs = self.regs();
+        (regs.ecr.read(ErrorCounter::TXERRCNT) as u8,
+         regs.ecr.read(ErrorCounter::RXERRCNT) as u8)
+    }
+
+    pub fn error_state(&self) -> ErrorState {
+        if !self.enabled.get() {
+            return ErrorState::Active;
+        }
+        match self.regs().esr1.read(ErrorStatus1::FLTCONF) {
+            0 => ErrorState::Active,
+            1 => ErrorState::Passive,
+            _ => ErrorState::BusOff,
+        }
+    }
+
+    /// The number of times received frames were lost because the FIFO was
+    /// full.
+    pub fn rx_overflows(&self) -> usize {
+        self.rx_overflows.get()
+    }
+
+    fn check_error_state(&self) {
+        let state = self.error_state();
+        if state != self.error_state.get() {
+            self.error_state.set(state);
+            self.client.get().map(|client| client.error_state_changed(state));
+        }
+    }
+
+    fn receive(&self) -> Frame {
+        let regs = self.regs();
+        let mb = &regs.mb[0];
+        let extended = mb.cs.is_set(ControlStatus::IDE);
+        let id = if extended {
+            mb.id.read(Identifier::EXT)
+        } else {
+            mb.id.read(Identifier::STD)
+        };
+        let len = mb.cs.read(ControlStatus::DLC) as u8;
+
+        let mut frame = Frame {
+            id: id,
+            extended: extended,
+            remote: mb.cs.is_set(ControlStatus::RTR),
+            len: if len > 8 { 8 } else { len },
+            data: [0; 8],
+        };
+        let (word0, word1) = (mb.word0.get(), mb.word1.get());
+        for i in 0..4 {
+            frame.data[i] = (word0 >> (24 - 8 * i)) as u8;
+            frame.data[i + 4] = (word1 >> (24 - 8 * i)) as u8;
+        }
+
+        // Reading the timer releases the message buffer lock.
+        regs.timer.get();
+        regs.iflag1.set(FIFO_AVAILABLE);
+        frame
+    }
+
+    pub fn handle_interrupt(&self) {
+        let regs = self.regs();
+        let flags = regs.iflag1.get() & regs.imask1.get();
+
+        if flags & (FIFO_OVERFLOW | FIFO_WARNING) != 0 {
+            regs.iflag1.set(flags & (FIFO_OVERFLOW | FIFO_WARNING));
+            if flags & FIFO_OVERFLOW != 0 {
+                self.rx_overflows.set(self.rx_overflows.get() + 1);
+            }
+        }
+        while regs.iflag1.get() & FIFO_AVAILABLE != 0 {
+            let frame = self.receive();
+            self.client.get().map(|client| client.frame_received(&frame));
+        }
+
+        let sent = flags & TX_MBS;
+        if sent != 0 {
+            regs.iflag1.set(sent);
+            self.tx_pending.set(self.tx_pending.get() & !sent);
+            for _ in 0..sent.count_ones() {
+                self.client.get().map(|client| client.frame_sent());
+            }
+        }
+
+        self.check_error_state();
+    }
+
+    /// Handles the bus off, error and warning interrupts.
+    pub fn handle_error_interrupt(&self) {
+        let regs = self.regs();
+        let status = regs.esr1.get();
+        regs.esr1.set(status & error_interrupts().mask);
+        self.check_error_state();
+    }
+}
+
+/// The interrupt flags in ESR1, which are cleared by writing ones.
+fn error_interrupts() -> FieldValue<u32, ErrorStatus1::Register> {
+    ErrorStatus1::TWRNINT::SET + ErrorStatus1::RWRNINT::SET + ErrorStatus1::BOFFINT::SET +
+    ErrorStatus1::ERRINT::SET + ErrorStatus1::WAKINT::SET
+}
+
+fn be_word(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32
+}
@@ -4,6 +4,21 @@ use pit;
 use spi;
 use gpio;
 use uart;
+use ftm;
+use lptmr;
+use llwu;
+use rtc;
+use flash;
+use sdhc;
+use usb;
+use usbhs;
+use can;
+use enet;
+use dma;
+use tsi;
+use cmp;
+use cmt;
+use deferred;
 
 pub struct MK66 {
     pub mpu: (),
@@ -37,11 +52,34 @@ impl Chip for MK66 {
                     PCMD => gpio::PD.handle_interrupt(),
                     PCME => gpio::PE.handle_interrupt(),
                     PIT2 => pit::PIT.handle_interrupt(),
+                    LOWPOWERTIER => lptmr::LPTMR.handle_interrupt(),
+                    LLWU => llwu::handle_interrupt(),
+                    RTC_ALARM => rtc::RTC.handle_alarm_interrupt(),
+                    FLASHCC => flash::FLASH.handle_interrupt(),
+                    SDHC => sdhc::SDHC.handle_interrupt(),
+                    USBFS_OTG => usb::USB0.handle_interrupt(),
+                    USBHS => usbhs::USBHS.handle_interrupt(),
+                    CAN0_MSGBUF => can::CAN0.handle_interrupt(),
+                    CAN0_BUSOFF | CAN0_ERR | CAN0_TX | CAN0_RX => can::CAN0.handle_error_interrupt(),
+                    CAN1_MSBBUF => can::CAN1.handle_interrupt(),
+                    CAN1_BUSOFF | CAN1_ERR | CAN1_TX | CAN1_RX => can::CAN1.handle_error_interrupt(),
+                    DMA0...DMA15 => dma::DMA_CHANNELS[interrupt as usize].handle_interrupt(),
+                    EMAC_TIMER | EMAC_TX | EMAC_RX | EMAC_ERR => enet::ENET.handle_interrupt(),
+                    TSI0 => tsi::TSI.handle_interrupt(),
+                    CMP0 => cmp::CMP0.handle_interrupt(),
+                    CMP1 => cmp::CMP1.handle_interrupt(),
+                    CMP2 => cmp::CMP2.handle_interrupt(),
+                    CMP3 => cmp::CMP3.handle_interrupt(),
+                    CMT => cmt::CMT.handle_interrupt(),
                     SPI0 => spi::SPI0.handle_interrupt(),
                     SPI1 => spi::SPI1.handle_interrupt(),
                     SPI2 => spi::SPI2.handle_interrupt(),
                     UART0 => uart::UART0.handle_interrupt(),
                     UART1 => uart::UART1.handle_interrupt(),
+                    UART2 => uart::UART2.handle_interrupt(),
+                    FTM1 => ftm::FTM1.handle_interrupt(),
+                    FTM2 => ftm::FTM2.handle_interrupt(),
+                    SOFTWARE => deferred::handle_interrupt(),
                     _ => {}
                 }
 
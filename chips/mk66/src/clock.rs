@@ -6,6 +6,9 @@ static mut CORECLK: u32 = 20_480_000;
 static mut BUSCLK: u32 = 20_480_000;
 static mut FLASHCLK: u32 = 10_240_000;
 
+// MCGPLLCLK, which is 0 until the PLL is running.
+static mut PLLCLK: u32 = 0;
+
 use osc;
 use mcg;
 use sim;
@@ -26,6 +29,10 @@ pub fn core_clock_hz() -> u32 {
     unsafe { CORECLK }
 }
 
+pub fn pll_clock_hz() -> u32 {
+    unsafe { PLLCLK }
+}
+
 #[allow(non_upper_case_globals)]
 const MHz: u32 = 1_000_000;
 
@@ -85,6 +92,7 @@ pub fn configure(core_freq: u32) {
         unsafe {
             MCGOUTCLK = core_freq * MHz;
             CORECLK = core_freq * MHz;
+            PLLCLK = core_freq * MHz;
             BUSCLK = (core_freq * MHz) / bus_div; 
             FLASHCLK = (core_freq * MHz) / flash_div;
         }
@@ -0,0 +1,235 @@
+//! Implementation of the MK66 analog comparators (CMP).
+//!
+//! Each comparator picks its plus and minus inputs from eight: pins, the
+//! 12-bit DAC, VREF_OUT, the bandgap, and its own 6-bit DAC, which is
+//! always input 7 [K66 Reference Manual, Section 3.7.2]. The 6-bit DAC
+//! divides VREF_OUT or VDD into 64 steps, which makes it a cheap threshold
+//! to compare a pin against.
+//!
+//! In continuous mode the output follows the inputs, with only the chosen
+//! hysteresis. With a filter, the inputs are sampled every `filter_period`
+//! bus clock cycles and the output only changes once `filter_count`
+//! samples in a row agree, which rejects glitches. In windowed mode the
+//! output only follows the inputs while the PDB's window signal is high.
+//! Rising and falling edges of the output raise interrupts.
+
+use core::cell::Cell;
+use core::mem;
+use kernel::ReturnCode;
+use nvic::{self, NvicIdx};
+use regs::cmp::*;
+
+pub const N_COMPARATORS: usize = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Input {
+    In0 = 0,
+    In1 = 1,
+    In2 = 2,
+    In3 = 3,
+    In4 = 4,
+    In5 = 5,
+    In6 = 6,
+    Dac = 7,
+}
+
+impl Input {
+    pub fn from_index(index: usize) -> Option<Input> {
+        match index {
+            0 => Some(Input::In0),
+            1 => Some(Input::In1),
+            2 => Some(Input::In2),
+            3 => Some(Input::In3),
+            4 => Some(Input::In4),
+            5 => Some(Input::In5),
+            6 => Some(Input::In6),
+            7 => Some(Input::Dac),
+            _ => None,
+        }
+    }
+}
+
+/// Hysteresis levels, from about 5mV at level 0 to about 30mV at level 3
+/// in high-speed mode, and less in low-speed mode.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Hysteresis {
+    Level0 = 0,
+    Level1 = 1,
+    Level2 = 2,
+    Level3 = 3,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DacReference {
+    VrefOut,
+    Vdd,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Config {
+    pub plus: Input,
+    pub minus: Input,
+    pub hysteresis: Hysteresis,
+    pub invert: bool,
+    /// Faster, at the cost of more current.
+    pub high_speed: bool,
+    /// Samples that must agree before the output changes, up to 7, or 0
+    /// for continuous mode.
+    pub filter_count: u8,
+    /// Bus clock cycles between samples, when filtering.
+    pub filter_period: u8,
+    pub windowed: bool,
+}
+
+pub trait Client {
+    fn edge_detected(&self, comparator: usize, edge: Edge);
+}
+
+pub static mut CMP0: Cmp<'static> = Cmp::new(0);
+pub static mut CMP1: Cmp<'static> = Cmp::new(1);
+pub static mut CMP2: Cmp<'static> = Cmp::new(2);
+pub static mut CMP3: Cmp<'static> = Cmp::new(3);
+
+pub struct Cmp<'a> {
+    index: usize,
+    registers: *mut Registers,
+    client: Cell<Option<&'a Client>>,
+}
+
+impl<'a> Cmp<'a> {
+    const fn new(index: usize) -> Cmp<'a> {
+        Cmp {
+            index: index,
+            registers: CMP_BASE_ADDRS[index],
+            client: Cell::new(None),
+        }
+    }
+
+    fn regs(&self) -> &mut Registers {
+        unsafe { mem::transmute(self.registers) }
+    }
+
+    /// All four comparators share one clock gate, which must be open for
+    /// any register access.
+    fn enable_clock(&self) {
+        use sim::{clocks, Clock};
+        clocks::CMP.enable();
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn set_client(&self, client: &'a Client) {
+        self.client.set(Some(client));
+    }
+
+    fn interrupt(&self) -> NvicIdx {
+        match self.index {
+            0 => NvicIdx::CMP0,
+            1 => NvicIdx::CMP1,
+            2 => NvicIdx::CMP2,
+            3 => NvicIdx::CMP3,
+            _ => unreachable!()
+        }
+    }
+
+    /// Selects the inputs and mode, and enables the comparator.
+    pub fn configure(&self, config: Config) -> ReturnCode {
+        if config.filter_count > 7 || (config.filter_count > 0 && config.filter_period == 0) {
+            return ReturnCode::EINVAL;
+        }
+
+        self.enable_clock();
+        let regs = self.regs();
+        regs.cr1.write(Control1::EN::CLEAR);
+        regs.cr0.write(Control0::FILTER_CNT.val(config.filter_count) +
+                       Control0::HYSTCTR.val(config.hysteresis as u8));
+        regs.fpr.set(if config.filter_count > 0 { config.filter_period } else { 0 });
+        regs.muxcr.write(MuxControl::PSEL.val(config.plus as u8) +
+                         MuxControl::MSEL.val(config.minus as u8));
+        regs.cr1.write(Control1::WE.val(config.windowed as u8) +
+                       Control1::PMODE.val(config.high_speed as u8) +
+                       Control1::INV.val(config.invert as u8) +
+                       Control1::EN::SET);
+        ReturnCode::SUCCESS
+    }
+
+    /// Sets the 6-bit DAC to `reference` * (`level` + 1) / 64.
+    pub fn set_dac(&self, reference: DacReference, level: u8) -> ReturnCode {
+        if level > 63 {
+            return ReturnCode::EINVAL;
+        }
+        self.enable_clock();
+
+        let vrsel = match reference {
+            DacReference::VrefOut => DacControl::VRSEL::Vin1,
+            DacReference::Vdd => DacControl::VRSEL::Vin2,
+        };
+        self.regs().daccr.write(DacControl::DACEN::SET + vrsel + DacControl::VOSEL.val(level));
+        ReturnCode::SUCCESS
+    }
+
+    pub fn disable_dac(&self) {
+        self.enable_clock();
+        self.regs().daccr.set(0);
+    }
+
+    /// Interrupts on the chosen edges of the output, or on none.
+    pub fn enable_interrupts(&self, rising: bool, falling: bool) {
+        self.enable_clock();
+        let regs = self.regs();
+        regs.scr.write(StatusControl::CFR::SET +
+                       StatusControl::CFF::SET +
+                       StatusControl::IER.val(rising as u8) +
+                       StatusControl::IEF.val(falling as u8));
+        if rising || falling {
+            unsafe { nvic::enable(self.interrupt()) };
+        }
+    }
+
+    pub fn output(&self) -> bool {
+        self.enable_clock();
+        self.regs().scr.is_set(StatusControl::COUT)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enable_clock();
+        self.regs().cr1.is_set(Control1::EN)
+    }
+
+    pub fn disable(&self) {
+        self.enable_clock();
+        let regs = self.regs();
+        regs.scr.write(StatusControl::CFR::SET + StatusControl::CFF::SET);
+        regs.cr1.set(0);
+    }
+
+    pub fn handle_interrupt(&self) {
+        let regs = self.regs();
+        let rising = regs.scr.is_set(StatusControl::CFR);
+        let falling = regs.scr.is_set(StatusControl::CFF);
+        // Writing the flags back clears them.
+        regs.scr.modify(StatusControl::DMAEN::CLEAR);
+
+        // If both edges came, the output shows which was last.
+        let edges = if self.output() {
+            [(falling, Edge::Falling), (rising, Edge::Rising)]
+        } else {
+            [(rising, Edge::Rising), (falling, Edge::Falling)]
+        };
+        self.client.get().map(|client| {
+            for &(seen, edge) in edges.iter() {
+                if seen {
+                    client.edge_detected(self.index, edge);
+                }
+            }
+        });
+    }
+}
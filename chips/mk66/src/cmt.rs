@@ -0,0 +1,186 @@
+//! Implementation of the MK66 Carrier Modulator Transmitter (CMT).
+//!
+//! In time mode, the CMT drives its IRO pin with bursts of carrier, called
+//! marks, separated by silence, called spaces, which is how IR remote
+//! controls signal. The carrier and the modulator run from an intermediate
+//! clock of about 8MHz divided from the bus clock; the modulator counts it
+//! in eighths, so marks and spaces are timed to about a microsecond.
+//!
+//! A message is a list of durations in microseconds, alternating mark and
+//! space and starting with a mark. Each modulation cycle sends one mark and
+//! the space after it. The modulator reloads its counters from the data
+//! registers as each cycle ends, so the registers are written a cycle
+//! ahead. After the last cycle, extended space mode keeps the output quiet
+//! until the modulator is stopped.
+
+use core::cell::Cell;
+use core::mem;
+use kernel::ReturnCode;
+use clock;
+use nvic;
+use regs::cmt::*;
+
+/// The most durations in a message.
+pub const MAX_TIMINGS: usize = 256;
+
+const INTERMEDIATE_HZ: u32 = 8_000_000;
+/// The modulator counts the intermediate clock divided by this.
+const MODULATOR_DIVIDER: u32 = 8;
+
+static mut TIMINGS: [u16; MAX_TIMINGS] = [0; MAX_TIMINGS];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Carrier {
+    pub frequency: u32,
+    /// The share of each carrier period that is high, from 1 to 99.
+    pub duty_percent: u32,
+}
+
+pub trait Client {
+    fn transmit_done(&self);
+}
+
+pub static mut CMT: Cmt<'static> = Cmt::new();
+
+pub struct Cmt<'a> {
+    client: Cell<Option<&'a Client>>,
+    intermediate_hz: Cell<u32>,
+    // Mark and space pairs in the message.
+    cycles: Cell<usize>,
+    // The next cycle to write to the data registers.
+    next: Cell<usize>,
+    completed: Cell<usize>,
+    busy: Cell<bool>,
+}
+
+impl<'a> Cmt<'a> {
+    const fn new() -> Cmt<'a> {
+        Cmt {
+            client: Cell::new(None),
+            intermediate_hz: Cell::new(INTERMEDIATE_HZ),
+            cycles: Cell::new(0),
+            next: Cell::new(0),
+            completed: Cell::new(0),
+            busy: Cell::new(false),
+        }
+    }
+
+    fn regs(&self) -> &mut Registers {
+        unsafe { mem::transmute(CMT_BASE) }
+    }
+
+    pub fn set_client(&self, client: &'a Client) {
+        self.client.set(Some(client));
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.busy.get()
+    }
+
+    /// Sets the carrier's high and low times, in intermediate clock cycles.
+    fn set_carrier(&self, carrier: Carrier) -> ReturnCode {
+        if carrier.frequency == 0 || carrier.duty_percent == 0 || carrier.duty_percent >= 100 {
+            return ReturnCode::EINVAL;
+        }
+        let period = (self.intermediate_hz.get() + carrier.frequency / 2) / carrier.frequency;
+        let high = (period * carrier.duty_percent + 50) / 100;
+        let low = period.saturating_sub(high);
+        if high == 0 || high > 0xFF || low == 0 || low > 0xFF {
+            return ReturnCode::EINVAL;
+        }
+        let regs = self.regs();
+        regs.cgh1.set(high as u8);
+        regs.cgl1.set(low as u8);
+        ReturnCode::SUCCESS
+    }
+
+    fn ticks(&self, us: u16) -> u32 {
+        let hz = (self.intermediate_hz.get() / MODULATOR_DIVIDER) as u64;
+        ((us as u64 * hz + 500_000) / 1_000_000) as u32
+    }
+
+    /// Writes cycle `next` to the data registers, or enters extended space
+    /// if the message has no more cycles. Writing CMD2 clears EOCF.
+    fn load_next(&self) {
+        let regs = self.regs();
+        let cycle = self.next.get();
+        if cycle < self.cycles.get() {
+            let (mark, space) = unsafe { (TIMINGS[2 * cycle], TIMINGS[2 * cycle + 1]) };
+            // The mark lasts one tick more than its count.
+            let mark = self.ticks(mark).max(1).min(0x1_0000) - 1;
+            let space = self.ticks(space).min(0xFFFF);
+            regs.cmd1.set((mark >> 8) as u8);
+            regs.cmd2.set(mark as u8);
+            regs.cmd3.set((space >> 8) as u8);
+            regs.cmd4.set(space as u8);
+        } else {
+            regs.msc.modify(ModulatorStatus::EXSPC::SET);
+            regs.cmd2.set(regs.cmd2.get());
+        }
+        self.next.set(cycle + 1);
+    }
+
+    /// Sends `timings`, microseconds of mark and space in turn, on
+    /// `carrier`. An odd count leaves the last mark without a space.
+    pub fn transmit(&self, carrier: Carrier, timings: &[u16]) -> ReturnCode {
+        if self.busy.get() {
+            return ReturnCode::EBUSY;
+        }
+        if timings.is_empty() || timings.len() > MAX_TIMINGS {
+            return ReturnCode::ESIZE;
+        }
+
+        use sim::{clocks, Clock};
+        clocks::CMT.enable();
+
+        // The intermediate clock is the bus clock divided by 1 to 16.
+        let divider = ((clock::bus_clock_hz() + INTERMEDIATE_HZ / 2) / INTERMEDIATE_HZ).max(1).min(16);
+        self.intermediate_hz.set(clock::bus_clock_hz() / divider);
+        let regs = self.regs();
+        regs.pps.write(PrimaryPrescaler::PPSDIV.val(divider as u8 - 1));
+
+        let result = self.set_carrier(carrier);
+        if result != ReturnCode::SUCCESS {
+            return result;
+        }
+
+        let cycles = (timings.len() + 1) / 2;
+        unsafe {
+            TIMINGS[..timings.len()].copy_from_slice(timings);
+            if timings.len() % 2 == 1 {
+                TIMINGS[timings.len()] = 0;
+            }
+        }
+        self.cycles.set(cycles);
+        self.next.set(0);
+        self.completed.set(0);
+        self.busy.set(true);
+
+        regs.oc.write(OutputControl::CMTPOL::SET + OutputControl::IROPEN::SET);
+        regs.msc.write(ModulatorStatus::CMTDIV.val(0));
+        self.load_next();
+        unsafe { nvic::enable(nvic::NvicIdx::CMT) };
+        regs.msc.write(ModulatorStatus::EOCIE::SET + ModulatorStatus::MCGEN::SET);
+        self.load_next();
+        ReturnCode::SUCCESS
+    }
+
+    pub fn handle_interrupt(&self) {
+        let regs = self.regs();
+        if !regs.msc.is_set(ModulatorStatus::EOCF) {
+            return;
+        }
+        self.completed.set(self.completed.get() + 1);
+        if self.completed.get() < self.cycles.get() {
+            self.load_next();
+            return;
+        }
+
+        // The output is in extended space, so it can stop here.
+        regs.cmd2.set(regs.cmd2.get());
+        regs.msc.write(ModulatorStatus::CMTDIV.val(0));
+        regs.oc.write(OutputControl::IROPEN::CLEAR);
+        self.busy.set(false);
+        self.client.get().map(|client| client.transmit_done());
+    }
+}
@@ -0,0 +1,245 @@
+//! Implementation of the MK66 cyclic redundancy check (CRC) module.
+//!
+//! The CRC module computes a 16- or 32-bit CRC with any polynomial as data
+//! is written to it, a word or a byte at a time, so the result is ready as
+//! soon as the last byte is written. Input bits can be transposed, so that
+//! each byte is consumed least significant bit first, and the result can be
+//! transposed and complemented, which covers the common CRCs: see
+//! `CRC16_CCITT`, `CRC16_XMODEM` and `CRC32`.
+//!
+//! There is one module, so a CRC is computed by `start`, any number of
+//! `update`s and then `result`, with no other user starting in between.
+//! `checksum` does all three for data in one piece.
+
+use core::cell::Cell;
+use core::mem;
+use kernel::ReturnCode;
+use kernel::common::regs::ReadWrite;
+use kernel::hil::crc::{self, CrcAlg};
+use deferred::{self, Task};
+use regs::crc::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Width {
+    Bits16,
+    Bits32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Config {
+    pub width: Width,
+    /// The polynomial without its highest term, such as 0x1021 for
+    /// x^16 + x^12 + x^5 + 1.
+    pub polynomial: u32,
+    pub seed: u32,
+    /// Consumes each byte least significant bit first, as reflected CRCs
+    /// do.
+    pub transpose_input: bool,
+    /// Reverses the bits of the result.
+    pub transpose_output: bool,
+    /// Complements the result, after any transposition.
+    pub complement: bool,
+}
+
+/// CRC-16/CCITT-FALSE, whose check value is 0x29B1.
+pub const CRC16_CCITT: Config = Config {
+    width: Width::Bits16,
+    polynomial: 0x1021,
+    seed: 0xFFFF,
+    transpose_input: false,
+    transpose_output: false,
+    complement: false,
+};
+
+/// The CRC-16 of XMODEM, whose check value is 0x31C3.
+pub const CRC16_XMODEM: Config = Config {
+    width: Width::Bits16,
+    polynomial: 0x1021,
+    seed: 0,
+    transpose_input: false,
+    transpose_output: false,
+    complement: false,
+};
+
+/// The CRC-32 of Ethernet and zlib, whose check value is 0xCBF43926.
+pub const CRC32: Config = Config {
+    width: Width::Bits32,
+    polynomial: 0x04C1_1DB7,
+    seed: 0xFFFF_FFFF,
+    transpose_input: true,
+    transpose_output: true,
+    complement: true,
+};
+
+const CRC32C_POLYNOMIAL: u32 = 0x1EDC_6F41;
+
+pub static mut CRC: Crc<'static> = Crc::new();
+
+pub struct Crc<'a> {
+    client: Cell<Option<&'a crc::Client>>,
+    width: Cell<Width>,
+    // The result of `compute`, waiting to be passed to the client.
+    pending: Cell<Option<u32>>,
+}
+
+impl<'a> Crc<'a> {
+    const fn new() -> Crc<'a> {
+        Crc {
+            client: Cell::new(None),
+            width: Cell::new(Width::Bits32),
+            pending: Cell::new(None),
+        }
+    }
+
+    fn regs(&self) -> &mut Registers {
+        unsafe { mem::transmute(CRC_BASE) }
+    }
+
+    /// The low byte of the data register, for feeding single bytes.
+    fn data_byte(&self) -> &ReadWrite<u8> {
+        unsafe { mem::transmute(CRC_BASE) }
+    }
+
+    fn enable_clock(&self) {
+        use sim::{clocks, Clock};
+        clocks::CRC.enable();
+    }
+
+    pub fn set_client(&self, client: &'a crc::Client) {
+        self.client.set(Some(client));
+    }
+
+    /// Starts a CRC, discarding any in progress.
+    pub fn start(&self, config: Config) -> ReturnCode {
+        if config.width == Width::Bits16 && (config.polynomial > 0xFFFF || config.seed > 0xFFFF) {
+            return ReturnCode::EINVAL;
+        }
+        self.enable_clock();
+
+        let width = match config.width {
+            Width::Bits16 => Control::TCRC::Bits16,
+            Width::Bits32 => Control::TCRC::Bits32,
+        };
+        let input = if config.transpose_input { Control::TOT::Bits } else { Control::TOT::None };
+        // Transposing the bytes as well reverses the whole result.
+        let output = if config.transpose_output {
+            Control::TOTR::BitsAndBytes
+        } else {
+            Control::TOTR::None
+        };
+
+        let regs = self.regs();
+        regs.gpoly.set(config.polynomial);
+        // The seed is written before transposition is set, so it is taken
+        // as it is.
+        regs.ctrl.write(width + Control::WAS::SET);
+        regs.data.set(config.seed);
+        regs.ctrl.write(width + input + output + Control::FXOR.val(config.complement as u32));
+        self.width.set(config.width);
+        ReturnCode::SUCCESS
+    }
+
+    /// Feeds `data` into the CRC in progress.
+    pub fn update(&self, data: &[u8]) {
+        let regs = self.regs();
+        for chunk in data.chunks(4) {
+            if chunk.len() == 4 {
+                // The module consumes words from the top byte down.
+                regs.data.set((chunk[0] as u32) << 24 |
+                              (chunk[1] as u32) << 16 |
+                              (chunk[2] as u32) << 8 |
+                              chunk[3] as u32);
+            } else {
+                for &byte in chunk.iter() {
+                    self.data_byte().set(byte);
+                }
+            }
+        }
+    }
+
+    /// The CRC of the data fed so far.
+    pub fn result(&self) -> u32 {
+        let regs = self.regs();
+        let value = regs.data.get();
+        match self.width.get() {
+            Width::Bits32 => value,
+            // Transposing the bytes moves a 16-bit result to the top half.
+            Width::Bits16 => if regs.ctrl.matches_all(Control::TOTR::BitsAndBytes) {
+                value >> 16
+            } else {
+                value & 0xFFFF
+            },
+        }
+    }
+
+    pub fn checksum(&self, config: Config, data: &[u8]) -> Result<u32, ReturnCode> {
+        let result = self.start(config);
+        if result != ReturnCode::SUCCESS {
+            return Err(result);
+        }
+        self.update(data);
+        Ok(self.result())
+    }
+
+    /// Passes the result of `compute` to the client.
+    pub fn handle_deferred(&self) {
+        self.pending.take().map(|result| {
+            self.client.get().map(|client| client.receive_result(result));
+        });
+    }
+}
+
+impl<'a> crc::CRC for Crc<'a> {
+    fn init(&self) -> ReturnCode {
+        self.enable_clock();
+        ReturnCode::SUCCESS
+    }
+
+    /// The module has no version register.
+    fn get_version(&self) -> u32 {
+        0
+    }
+
+    /// The result is ready at once, but the client receives it from the
+    /// kernel loop, after this returns.
+    fn compute(&self, data: &[u8], alg: CrcAlg) -> ReturnCode {
+        if self.pending.get().is_some() {
+            return ReturnCode::EBUSY;
+        }
+        // Every algorithm consumes bytes least significant bit first.
+        let config = match alg {
+            CrcAlg::Crc32 => CRC32,
+            CrcAlg::Crc32C => Config { polynomial: CRC32C_POLYNOMIAL, ..CRC32 },
+            CrcAlg::Sam4L16 => Config {
+                width: Width::Bits16,
+                polynomial: 0x1021,
+                seed: 0xFFFF,
+                transpose_input: true,
+                transpose_output: false,
+                complement: false,
+            },
+            CrcAlg::Sam4L32 => Config {
+                transpose_output: false,
+                complement: false,
+                ..CRC32
+            },
+            CrcAlg::Sam4L32C => Config {
+                polynomial: CRC32C_POLYNOMIAL,
+                transpose_output: false,
+                complement: false,
+                ..CRC32
+            },
+        };
+        match self.checksum(config, data) {
+            Ok(result) => {
+                self.pending.set(Some(result));
+                deferred::defer(Task::Crc);
+                ReturnCode::SUCCESS
+            },
+            Err(err) => err,
+        }
+    }
+
+    /// The module is idle between CRCs, so there is nothing to stop.
+    fn disable(&self) {}
+}
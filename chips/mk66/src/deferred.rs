@@ -0,0 +1,39 @@
+//! Callbacks that drivers owe their clients but must not make from inside
+//! the call that started the work.
+//!
+//! Some modules finish at once, but their HILs promise a callback, which
+//! clients expect to come after the call returns. Such a driver keeps its
+//! result, calls `defer` with its task, and is called back from the kernel
+//! loop through the otherwise unused software interrupt.
+
+use nvic::{self, NvicIdx};
+use crc;
+use mmcau;
+
+#[derive(Copy, Clone)]
+pub enum Task {
+    Crc = 0,
+    Mmcau = 1,
+}
+
+static mut PENDING: u32 = 0;
+
+/// Runs `task` the next time the kernel services interrupts.
+pub fn defer(task: Task) {
+    unsafe {
+        PENDING |= 1 << task as u32;
+        nvic::set_pending(NvicIdx::SOFTWARE);
+    }
+}
+
+pub fn handle_interrupt() {
+    let pending = unsafe { PENDING };
+    unsafe { PENDING = 0 };
+
+    if pending & 1 << Task::Crc as u32 != 0 {
+        unsafe { crc::CRC.handle_deferred() };
+    }
+    if pending & 1 << Task::Mmcau as u32 != 0 {
+        unsafe { mmcau::MMCAU.handle_deferred() };
+    }
+}
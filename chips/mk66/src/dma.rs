@@ -0,0 +1,181 @@
+//! Implementation of the MK66 eDMA controller and its request multiplexer.
+//!
+//! Only circular transfers between a peripheral register and a buffer are
+//! supported, which is what ping-pong streaming needs: the channel runs
+//! until stopped, and its client is told each time it finishes a half of
+//! the buffer. Channels 0 to 15 are available, each with its own interrupt.
+
+use core::cell::Cell;
+use core::mem;
+use kernel::common::regs::ReadWrite;
+use nvic::{self, NvicIdx};
+use regs::dma::*;
+
+pub const N_CHANNELS: usize = 16;
+
+/// Request sources [K66 Reference Manual, Table 3-27].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DmaPeripheral {
+    I2s0Rx = 12,
+    I2s0Tx = 13,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Width {
+    Byte = 0,
+    HalfWord = 1,
+    Word = 2,
+}
+
+impl Width {
+    fn bytes(self) -> usize {
+        1 << self as usize
+    }
+}
+
+pub trait DmaClient {
+    /// Called when `channel` finishes half 0 or half 1 of its buffer.
+    fn buffer_done(&self, channel: usize, half: usize);
+}
+
+pub static mut DMA_CHANNELS: [DmaChannel; N_CHANNELS] = [
+    DmaChannel::new(0), DmaChannel::new(1), DmaChannel::new(2), DmaChannel::new(3),
+    DmaChannel::new(4), DmaChannel::new(5), DmaChannel::new(6), DmaChannel::new(7),
+    DmaChannel::new(8), DmaChannel::new(9), DmaChannel::new(10), DmaChannel::new(11),
+    DmaChannel::new(12), DmaChannel::new(13), DmaChannel::new(14), DmaChannel::new(15),
+];
+
+const INTERRUPTS: [NvicIdx; N_CHANNELS] = [
+    NvicIdx::DMA0, NvicIdx::DMA1, NvicIdx::DMA2, NvicIdx::DMA3,
+    NvicIdx::DMA4, NvicIdx::DMA5, NvicIdx::DMA6, NvicIdx::DMA7,
+    NvicIdx::DMA8, NvicIdx::DMA9, NvicIdx::DMA10, NvicIdx::DMA11,
+    NvicIdx::DMA12, NvicIdx::DMA13, NvicIdx::DMA14, NvicIdx::DMA15,
+];
+
+fn regs() -> &'static mut Registers {
+    unsafe { mem::transmute(DMA_BASE) }
+}
+
+fn enable_clocks() {
+    use sim::{clocks, Clock};
+    clocks::DMA.enable();
+    clocks::DMAMUX.enable();
+}
+
+pub struct DmaChannel {
+    index: usize,
+    client: Cell<Option<&'static DmaClient>>,
+    // The controller's clocks are gated until a channel is first started.
+    active: Cell<bool>,
+}
+
+impl DmaChannel {
+    const fn new(index: usize) -> DmaChannel {
+        DmaChannel {
+            index: index,
+            client: Cell::new(None),
+            active: Cell::new(false),
+        }
+    }
+
+    fn tcd(&self) -> &mut TransferControl {
+        let tcds: &mut [TransferControl; 32] = unsafe { mem::transmute(TCD_BASE) };
+        &mut tcds[self.index]
+    }
+
+    fn mux(&self) -> &mut ReadWrite<u8, ChannelConfig::Register> {
+        let mux: &mut [ReadWrite<u8, ChannelConfig::Register>; 32] =
+            unsafe { mem::transmute(DMAMUX_BASE) };
+        &mut mux[self.index]
+    }
+
+    pub fn set_client(&self, client: &'static DmaClient) {
+        self.client.set(Some(client));
+    }
+
+    /// Plays `len` bytes from `buffer` into the peripheral register at
+    /// `register`, one `width` word per request, over and over.
+    pub fn circular_to_peripheral(&self,
+                                  peripheral: DmaPeripheral,
+                                  buffer: *const u8,
+                                  len: usize,
+                                  register: u32,
+                                  width: Width) {
+        let tcd = self.tcd();
+        self.configure(peripheral, len, width);
+        tcd.saddr.set(buffer as u32);
+        tcd.soff.set(width.bytes() as u16);
+        tcd.slast.set((len as i32).wrapping_neg() as u32);
+        tcd.daddr.set(register);
+        tcd.doff.set(0);
+        tcd.dlast_sga.set(0);
+        self.start();
+    }
+
+    /// Fills `len` bytes of `buffer` from the peripheral register at
+    /// `register`, one `width` word per request, over and over.
+    pub fn circular_from_peripheral(&self,
+                                    peripheral: DmaPeripheral,
+                                    register: u32,
+                                    buffer: *mut u8,
+                                    len: usize,
+                                    width: Width) {
+        let tcd = self.tcd();
+        self.configure(peripheral, len, width);
+        tcd.saddr.set(register);
+        tcd.soff.set(0);
+        tcd.slast.set(0);
+        tcd.daddr.set(buffer as u32);
+        tcd.doff.set(width.bytes() as u16);
+        tcd.dlast_sga.set((len as i32).wrapping_neg() as u32);
+        self.start();
+    }
+
+    fn configure(&self, peripheral: DmaPeripheral, len: usize, width: Width) {
+        enable_clocks();
+        self.stop();
+
+        let tcd = self.tcd();
+        let count = (len / width.bytes()) as u16;
+        tcd.attr.write(Attributes::SSIZE.val(width as u16) + Attributes::DSIZE.val(width as u16));
+        tcd.nbytes.set(width.bytes() as u32);
+        tcd.citer.set(count);
+        tcd.biter.set(count);
+        tcd.csr.write(ControlStatus::INTHALF::SET + ControlStatus::INTMAJOR::SET);
+
+        self.mux().write(ChannelConfig::ENBL::SET +
+                         ChannelConfig::SOURCE.val(peripheral as u8));
+    }
+
+    fn start(&self) {
+        unsafe { nvic::enable(INTERRUPTS[self.index]) };
+        regs().serq.set(self.index as u8);
+        self.active.set(true);
+    }
+
+    pub fn stop(&self) {
+        if !self.active.get() {
+            return;
+        }
+        self.active.set(false);
+        let regs = regs();
+        regs.cerq.set(self.index as u8);
+        regs.cint.set(self.index as u8);
+        self.mux().set(0);
+    }
+
+    pub fn handle_interrupt(&self) {
+        let regs = regs();
+        regs.cint.set(self.index as u8);
+
+        // The count runs down from `biter` through each pass, and reloads
+        // when the major loop completes. By the time this runs the channel
+        // has moved on, so the count is in the first half of a pass just
+        // after the second half finished, and in the second half just after
+        // the first finished. This holds as long as the interrupt is taken
+        // within half a buffer.
+        let tcd = self.tcd();
+        let half = if tcd.citer.get() > tcd.biter.get() / 2 { 1 } else { 0 };
+        self.client.get().map(|client| client.buffer_done(self.index, half));
+    }
+}
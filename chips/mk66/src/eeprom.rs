@@ -0,0 +1,153 @@
+//! Implementation of FlexNVM EEPROM emulation.
+//!
+//! The MK66FX1M0 can back its 4KB FlexRAM with part of FlexNVM, so that every
+//! write to FlexRAM is saved to flash by the controller. Wear is spread over
+//! the whole backup region, which makes it suitable for settings that change
+//! often.
+//!
+//! Partitioning is permanent until a mass erase. `init` uses the same layout
+//! as Teensyduino, so EEPROM contents are shared with Arduino sketches.
+
+use core::ptr;
+use core::mem;
+use kernel::ReturnCode;
+use flash;
+use regs::flash::*;
+
+pub const EEPROM_SIZE: usize = 4096;
+
+const FLEXRAM: usize = 0x1400_0000;
+
+const PROGRAM_PARTITION: u8 = 0x80;
+
+/// 4KB of EEPROM, split evenly between the two FlexRAM subsystems.
+const EEPROM_DATA_SIZE: u8 = 0x32;
+
+/// 32KB of FlexNVM for EEPROM backup, with the rest left as data flash.
+const FLEXNVM_PARTITION: u8 = 0x05;
+
+// Bounds the wait for the controller to load EEPROM contents into FlexRAM.
+const READY_TIMEOUT: usize = 1_000_000;
+
+pub static mut EEPROM: Eeprom = Eeprom::new();
+
+pub struct Eeprom {
+    regs: *mut Registers,
+}
+
+impl Eeprom {
+    const fn new() -> Eeprom {
+        Eeprom {
+            regs: FTFE,
+        }
+    }
+
+    fn regs(&self) -> &mut Registers {
+        unsafe { mem::transmute(self.regs) }
+    }
+
+    /// Partitions FlexNVM on first use, then waits for the EEPROM to become
+    /// available.
+    pub fn init(&self) -> ReturnCode {
+        use sim::{clocks, Clock};
+        clocks::FTF.enable();
+
+        let regs = self.regs();
+
+        // FlexRAM is plain RAM until FlexNVM has been partitioned.
+        if regs.fcnfg.is_set(FlashConfig::RAMRDY) {
+            // PGMPART takes the data size in FCCOB4 and the FlexNVM partition
+            // code in FCCOB5.
+            let data = [0, 0, FLEXNVM_PARTITION, EEPROM_DATA_SIZE];
+            if !unsafe { flash::run_command(PROGRAM_PARTITION, 0, &data) } {
+                return ReturnCode::FAIL;
+            }
+        }
+
+        for _ in 0..READY_TIMEOUT {
+            if self.is_ready() {
+                return ReturnCode::SUCCESS;
+            }
+        }
+        ReturnCode::FAIL
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.regs().fcnfg.is_set(FlashConfig::EEERDY)
+    }
+
+    fn check(&self, offset: usize, len: usize) -> ReturnCode {
+        if !self.is_ready() {
+            ReturnCode::EOFF
+        } else if offset % len != 0 || offset >= EEPROM_SIZE || len > EEPROM_SIZE - offset {
+            ReturnCode::EINVAL
+        } else {
+            ReturnCode::SUCCESS
+        }
+    }
+
+    // Each write starts a backup to FlexNVM, and FlexRAM cannot be written
+    // again until it finishes.
+    fn wait_for_write(&self) {
+        while !self.regs().fstat.is_set(FlashStatus::CCIF) {}
+    }
+
+    fn read<T: Copy>(&self, offset: usize) -> Result<T, ReturnCode> {
+        match self.check(offset, mem::size_of::<T>()) {
+            ReturnCode::SUCCESS => {
+                self.wait_for_write();
+                Ok(unsafe { ptr::read_volatile((FLEXRAM + offset) as *const T) })
+            },
+            err => Err(err),
+        }
+    }
+
+    fn write<T: Copy + PartialEq>(&self, offset: usize, value: T) -> ReturnCode {
+        let current: T = match self.read(offset) {
+            Ok(current) => current,
+            Err(err) => return err,
+        };
+
+        // Skip writes that would not change anything, to save wear.
+        if current != value {
+            // The error flags are sticky, so clear any left by an earlier
+            // command before judging this write by them.
+            self.regs().fstat.write(FlashStatus::ACCERR::SET + FlashStatus::FPVIOL::SET);
+
+            unsafe { ptr::write_volatile((FLEXRAM + offset) as *mut T, value); }
+            self.wait_for_write();
+
+            let fstat = &self.regs().fstat;
+            if fstat.is_set(FlashStatus::ACCERR) || fstat.is_set(FlashStatus::FPVIOL) {
+                return ReturnCode::FAIL;
+            }
+        }
+        ReturnCode::SUCCESS
+    }
+
+    pub fn read_byte(&self, offset: usize) -> Result<u8, ReturnCode> {
+        self.read(offset)
+    }
+
+    pub fn write_byte(&self, offset: usize, value: u8) -> ReturnCode {
+        self.write(offset, value)
+    }
+
+    /// Reads a 16-bit word. `offset` must be 2-byte aligned.
+    pub fn read_word(&self, offset: usize) -> Result<u16, ReturnCode> {
+        self.read(offset)
+    }
+
+    pub fn write_word(&self, offset: usize, value: u16) -> ReturnCode {
+        self.write(offset, value)
+    }
+
+    /// Reads a 32-bit word. `offset` must be 4-byte aligned.
+    pub fn read_dword(&self, offset: usize) -> Result<u32, ReturnCode> {
+        self.read(offset)
+    }
+
+    pub fn write_dword(&self, offset: usize, value: u32) -> ReturnCode {
+        self.write(offset, value)
+    }
+}
@@ -0,0 +1,608 @@
+//! Implementation of the MK66 Ethernet MAC (ENET).
+//!
+//! The MAC talks to an external PHY over RMII, and manages it over MDIO.
+//! Frames move through rings of enhanced buffer descriptors: eight receive
+//! buffers, handed to the client as frames arrive and then recycled, and
+//! four transmit buffers, which frames are copied into. Frames are whole
+//! Ethernet frames from the destination address up to the end of the
+//! payload; the MAC adds padding and the frame check sequence, and strips
+//! the check sequence from received frames.
+//!
+//! The IEEE 1588 timer counts nanoseconds, wrapping every second, and the
+//! driver keeps the seconds. Every received frame is timestamped with the
+//! time its start arrived, and sent frames can be timestamped with the time
+//! they left. The timer's rate can be trimmed in parts per billion, to
+//! follow a PTP master.
+//!
+//! The PHY has no interrupt line to the MAC, so the client polls
+//! `link_status`, which also matches the MAC's speed and duplex to the
+//! negotiated link.
+
+use core::cell::Cell;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{compiler_fence, Ordering};
+use kernel::ReturnCode;
+use clock;
+use nvic::{self, NvicIdx};
+use regs::enet::*;
+
+/// The largest frame, without its check sequence.
+pub const MAX_FRAME_SIZE: usize = 1514;
+
+const BUFFER_SIZE: usize = 1536;
+const N_RX: usize = 8;
+const N_TX: usize = 4;
+
+const NANOS_PER_SECOND: u32 = 1_000_000_000;
+const MDC_MAX_HZ: u32 = 2_500_000;
+
+// Polling bounds for resets and MDIO transfers, which take microseconds.
+const RESET_TIMEOUT: usize = 100_000;
+const MDIO_TIMEOUT: usize = 100_000;
+const PHY_RESET_POLLS: usize = 10_000;
+
+// Receive descriptor bits [K66 Reference Manual, Section 45.4.2].
+const RX_EMPTY: u16 = 1 << 15;
+const RX_WRAP: u16 = 1 << 13;
+const RX_LAST: u16 = 1 << 11;
+const RX_ERRORS: u16 = 0b11_0111;
+const RX_INTERRUPT: u16 = 1 << 7;
+
+// Transmit descriptor bits [K66 Reference Manual, Section 45.4.3].
+const TX_READY: u16 = 1 << 15;
+const TX_WRAP: u16 = 1 << 13;
+const TX_LAST: u16 = 1 << 11;
+const TX_CRC: u16 = 1 << 10;
+const TX_INTERRUPT: u16 = 1 << 14;
+const TX_TIMESTAMP: u16 = 1 << 13;
+const TX_ERROR: u16 = 1 << 15;
+
+// [IEEE 802.3, Clause 22.2.4]
+const PHY_BMCR: u8 = 0;
+const PHY_BMSR: u8 = 1;
+const PHY_ANAR: u8 = 4;
+const PHY_ANLPAR: u8 = 5;
+const BMCR_RESET: u16 = 1 << 15;
+const BMCR_AUTONEG: u16 = 1 << 12;
+const BMCR_RESTART_AUTONEG: u16 = 1 << 9;
+const BMSR_AUTONEG_DONE: u16 = 1 << 5;
+const BMSR_LINK: u16 = 1 << 2;
+const AN_100_FULL: u16 = 1 << 8;
+const AN_100_HALF: u16 = 1 << 7;
+const AN_10_FULL: u16 = 1 << 6;
+const AN_10_HALF: u16 = 1 << 5;
+const AN_IEEE_802_3: u16 = 1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct Timestamp {
+    pub seconds: u32,
+    pub nanoseconds: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Speed {
+    TenMbps,
+    HundredMbps,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Link {
+    pub speed: Speed,
+    pub full_duplex: bool,
+}
+
+pub trait Client {
+    /// Called with each frame received, and the time its start arrived.
+    fn frame_received(&self, frame: &[u8], timestamp: Timestamp);
+
+    /// Called for each frame passed to `transmit`, in order. The timestamp
+    /// is when it left, if one was asked for.
+    fn frame_sent(&self, result: ReturnCode, timestamp: Option<Timestamp>);
+}
+
+#[repr(C, align(8))]
+#[derive(Copy, Clone)]
+struct BufferDescriptor {
+    length: u16,
+    control: u16,
+    buffer: u32,
+    ext0: u16,
+    ext1: u16,
+    _checksum: u16,
+    _header: u16,
+    _reserved0: u16,
+    _ext2: u16,
+    timestamp: u32,
+    _reserved1: [u16; 4],
+}
+
+const EMPTY_DESCRIPTOR: BufferDescriptor = BufferDescriptor {
+    length: 0,
+    control: 0,
+    buffer: 0,
+    ext0: 0,
+    ext1: 0,
+    _checksum: 0,
+    _header: 0,
+    _reserved0: 0,
+    _ext2: 0,
+    timestamp: 0,
+    _reserved1: [0; 4],
+};
+
+/// Receive buffers must be 16-byte aligned.
+#[repr(C, align(16))]
+struct RxBuffers([[u8; BUFFER_SIZE]; N_RX]);
+
+#[repr(C, align(16))]
+struct TxBuffers([[u8; BUFFER_SIZE]; N_TX]);
+
+static mut RX_RING: [BufferDescriptor; N_RX] = [EMPTY_DESCRIPTOR; N_RX];
+static mut TX_RING: [BufferDescriptor; N_TX] = [EMPTY_DESCRIPTOR; N_TX];
+static mut RX_BUFFERS: RxBuffers = RxBuffers([[0; BUFFER_SIZE]; N_RX]);
+static mut TX_BUFFERS: TxBuffers = TxBuffers([[0; BUFFER_SIZE]; N_TX]);
+
+fn get16(field: &u16) -> u16 {
+    unsafe { ptr::read_volatile(field) }
+}
+
+fn set16(field: &mut u16, value: u16) {
+    unsafe { ptr::write_volatile(field, value) }
+}
+
+fn get32(field: &u32) -> u32 {
+    unsafe { ptr::read_volatile(field) }
+}
+
+fn set32(field: &mut u32, value: u32) {
+    unsafe { ptr::write_volatile(field, value) }
+}
+
+fn rx_descriptor(index: usize) -> &'static mut BufferDescriptor {
+    unsafe { &mut RX_RING[index] }
+}
+
+fn tx_descriptor(index: usize) -> &'static mut BufferDescriptor {
+    unsafe { &mut TX_RING[index] }
+}
+
+fn wrap(index: usize, len: usize, bit: u16) -> u16 {
+    if index == len - 1 { bit } else { 0 }
+}
+
+pub static mut ENET: Enet<'static> = Enet::new();
+
+pub struct Enet<'a> {
+    client: Cell<Option<&'a Client>>,
+    enabled: Cell<bool>,
+    mac_address: Cell<[u8; 6]>,
+    phy_address: Cell<u8>,
+    link: Cell<Option<Link>>,
+    rx_next: Cell<usize>,
+    // The next transmit descriptor to fill, and the oldest in flight.
+    tx_next: Cell<usize>,
+    tx_oldest: Cell<usize>,
+    tx_count: Cell<usize>,
+    // Transmit descriptors whose frames are being timestamped, by bit.
+    tx_timestamped: Cell<u32>,
+    timer_hz: Cell<u32>,
+    seconds: Cell<u32>,
+    rx_errors: Cell<usize>,
+}
+
+impl<'a> Enet<'a> {
+    const fn new() -> Enet<'a> {
+        Enet {
+            client: Cell::new(None),
+            enabled: Cell::new(false),
+            mac_address: Cell::new([0; 6]),
+            phy_address: Cell::new(0),
+            link: Cell::new(None),
+            rx_next: Cell::new(0),
+            tx_next: Cell::new(0),
+            tx_oldest: Cell::new(0),
+            tx_count: Cell::new(0),
+            tx_timestamped: Cell::new(0),
+            timer_hz: Cell::new(0),
+            seconds: Cell::new(0),
+            rx_errors: Cell::new(0),
+        }
+    }
+
+    fn regs(&self) -> &mut Registers {
+        unsafe { mem::transmute(ENET_BASE) }
+    }
+
+    pub fn set_client(&self, client: &'a Client) {
+        self.client.set(Some(client));
+    }
+
+    pub fn mac_address(&self) -> [u8; 6] {
+        self.mac_address.get()
+    }
+
+    /// The number of frames dropped because they were damaged or too long.
+    pub fn rx_errors(&self) -> usize {
+        self.rx_errors.get()
+    }
+
+    /// Resets the MAC and the PHY at `phy_address`, and starts
+    /// autonegotiation. `timer_hz` is the frequency of the 1588 timer's
+    /// clock, selected in the SIM.
+    pub fn enable(&self, mac_address: [u8; 6], phy_address: u8, timer_hz: u32) -> ReturnCode {
+        // Each timer tick must add a whole number of nanoseconds below 128.
+        if timer_hz < NANOS_PER_SECOND / 127 || timer_hz > NANOS_PER_SECOND {
+            return ReturnCode::EINVAL;
+        }
+        use sim::{clocks, Clock};
+        clocks::ENET.enable();
+
+        let regs = self.regs();
+        regs.ecr.write(EthernetControl::RESET::SET);
+        if !(0..RESET_TIMEOUT).any(|_| !regs.ecr.is_set(EthernetControl::RESET)) {
+            return ReturnCode::FAIL;
+        }
+
+        // MDC runs at most at 2.5MHz, from the system clock.
+        let mii_speed = (clock::core_clock_hz() + 2 * MDC_MAX_HZ - 1) / (2 * MDC_MAX_HZ);
+        regs.mscr.write(MiiSpeedControl::MII_SPEED.val(mii_speed) +
+                        MiiSpeedControl::HOLDTIME.val(2));
+        regs.eimr.set(0);
+        regs.eir.set(0xFFFF_FFFF);
+
+        self.mac_address.set(mac_address);
+        self.phy_address.set(phy_address);
+        let mac = mac_address;
+        regs.palr.set((mac[0] as u32) << 24 | (mac[1] as u32) << 16 |
+                      (mac[2] as u32) << 8 | mac[3] as u32);
+        regs.paur.set((mac[4] as u32) << 24 | (mac[5] as u32) << 16 | 0x8808);
+        regs.iaur.set(0);
+        regs.ialr.set(0);
+        regs.gaur.set(0);
+        regs.galr.set(0);
+
+        regs.rcr.write(ReceiveControl::MAX_FL.val(MAX_FRAME_SIZE as u32 + 4) +
+                       ReceiveControl::CRCFWD::SET +
+                       ReceiveControl::RMII_MODE::SET +
+                       ReceiveControl::MII_MODE::SET);
+        regs.tcr.write(TransmitControl::FDEN::SET);
+        regs.tfwr.write(TransmitFifoWatermark::STRFWD::SET);
+        regs.mrbr.set(BUFFER_SIZE as u32);
+        regs.rdsr.set(unsafe { &RX_RING as *const _ as u32 });
+        regs.tdsr.set(unsafe { &TX_RING as *const _ as u32 });
+        self.init_rings();
+
+        self.timer_hz.set(timer_hz);
+        self.seconds.set(0);
+        regs.atper.set(NANOS_PER_SECOND);
+        self.set_rate(0);
+        regs.atcr.write(TimerControl::RESTART::SET);
+        regs.atcr.write(TimerControl::EN::SET + TimerControl::PEREN::SET);
+
+        regs.eimr.write(Interrupt::TXF::SET + Interrupt::RXF::SET + Interrupt::TS_TIMER::SET +
+                        Interrupt::EBERR::SET + Interrupt::BABR::SET + Interrupt::BABT::SET);
+        for &irq in [NvicIdx::EMAC_TIMER, NvicIdx::EMAC_TX, NvicIdx::EMAC_RX, NvicIdx::EMAC_ERR].iter() {
+            unsafe { nvic::enable(irq) };
+        }
+        self.start();
+        self.enabled.set(true);
+
+        if self.mdio_write(PHY_BMCR, BMCR_RESET) != ReturnCode::SUCCESS {
+            return ReturnCode::FAIL;
+        }
+        let reset_done = (0..PHY_RESET_POLLS).any(|_| {
+            self.mdio_read(PHY_BMCR).map_or(false, |bmcr| bmcr & BMCR_RESET == 0)
+        });
+        if !reset_done {
+            return ReturnCode::FAIL;
+        }
+        let _ = self.mdio_write(PHY_ANAR, AN_100_FULL | AN_100_HALF | AN_10_FULL | AN_10_HALF |
+                                          AN_IEEE_802_3);
+        self.mdio_write(PHY_BMCR, BMCR_AUTONEG | BMCR_RESTART_AUTONEG)
+    }
+
+    fn init_rings(&self) {
+        for i in 0..N_RX {
+            let bd = rx_descriptor(i);
+            *bd = EMPTY_DESCRIPTOR;
+            bd.buffer = unsafe { RX_BUFFERS.0[i].as_ptr() as u32 };
+            bd.ext1 = RX_INTERRUPT;
+            bd.control = RX_EMPTY | wrap(i, N_RX, RX_WRAP);
+        }
+        for i in 0..N_TX {
+            let bd = tx_descriptor(i);
+            *bd = EMPTY_DESCRIPTOR;
+            bd.buffer = unsafe { TX_BUFFERS.0[i].as_ptr() as u32 };
+            bd.control = wrap(i, N_TX, TX_WRAP);
+        }
+        compiler_fence(Ordering::SeqCst);
+        self.rx_next.set(0);
+        self.tx_next.set(0);
+        self.tx_oldest.set(0);
+        self.tx_count.set(0);
+        self.tx_timestamped.set(0);
+    }
+
+    fn start(&self) {
+        let regs = self.regs();
+        regs.ecr.write(EthernetControl::ETHEREN::SET +
+                       EthernetControl::EN1588::SET +
+                       EthernetControl::DBSWP::SET);
+        regs.rdar.write(DescriptorActive::ACTIVE::SET);
+    }
+
+    fn mdio_wait(&self) -> ReturnCode {
+        let regs = self.regs();
+        for _ in 0..MDIO_TIMEOUT {
+            if regs.eir.is_set(Interrupt::MII) {
+                regs.eir.write(Interrupt::MII::SET);
+                return ReturnCode::SUCCESS;
+            }
+        }
+        ReturnCode::FAIL
+    }
+
+    /// Reads a PHY register over MDIO.
+    pub fn mdio_read(&self, register: u8) -> Result<u16, ReturnCode> {
+        let regs = self.regs();
+        regs.mmfr.write(MiiFrame::ST.val(1) +
+                        MiiFrame::OP::Read +
+                        MiiFrame::PA.val(self.phy_address.get() as u32) +
+                        MiiFrame::RA.val(register as u32) +
+                        MiiFrame::TA.val(2));
+        match self.mdio_wait() {
+            ReturnCode::SUCCESS => Ok(regs.mmfr.read(MiiFrame::DATA) as u16),
+            err => Err(err),
+        }
+    }
+
+    /// Writes a PHY register over MDIO.
+    pub fn mdio_write(&self, register: u8, value: u16) -> ReturnCode {
+        self.regs().mmfr.write(MiiFrame::ST.val(1) +
+                               MiiFrame::OP::Write +
+                               MiiFrame::PA.val(self.phy_address.get() as u32) +
+                               MiiFrame::RA.val(register as u32) +
+                               MiiFrame::TA.val(2) +
+                               MiiFrame::DATA.val(value as u32));
+        self.mdio_wait()
+    }
+
+    /// Reads the link state from the PHY. When a new link comes up, the
+    /// MAC is restarted at its speed and duplex, and frames waiting to be
+    /// sent are dropped.
+    pub fn link_status(&self) -> Option<Link> {
+        if !self.enabled.get() {
+            return None;
+        }
+        // The link bit latches low, so the first read reports past drops.
+        let _ = self.mdio_read(PHY_BMSR);
+        let link = match self.mdio_read(PHY_BMSR) {
+            Ok(bmsr) if bmsr & BMSR_LINK != 0 && bmsr & BMSR_AUTONEG_DONE != 0 => {
+                let advertised = self.mdio_read(PHY_ANAR).unwrap_or(0);
+                let partner = self.mdio_read(PHY_ANLPAR).unwrap_or(0);
+                let common = advertised & partner;
+                Some(if common & AN_100_FULL != 0 {
+                    Link { speed: Speed::HundredMbps, full_duplex: true }
+                } else if common & AN_100_HALF != 0 {
+                    Link { speed: Speed::HundredMbps, full_duplex: false }
+                } else if common & AN_10_FULL != 0 {
+                    Link { speed: Speed::TenMbps, full_duplex: true }
+                } else {
+                    Link { speed: Speed::TenMbps, full_duplex: false }
+                })
+            },
+            _ => None,
+        };
+
+        if link != self.link.get() {
+            self.link.set(link);
+            if let Some(link) = link {
+                self.restart(link);
+            }
+        }
+        link
+    }
+
+    /// Duplex can only be changed while the MAC is stopped, which also
+    /// rewinds the descriptor rings.
+    fn restart(&self, link: Link) {
+        let regs = self.regs();
+        regs.ecr.modify(EthernetControl::ETHEREN::CLEAR);
+        let dropped = self.tx_count.get();
+
+        regs.tcr.modify(TransmitControl::FDEN.val(link.full_duplex as u32));
+        regs.rcr.modify(ReceiveControl::RMII_10T.val((link.speed == Speed::TenMbps) as u32) +
+                        ReceiveControl::DRT.val(!link.full_duplex as u32));
+        self.init_rings();
+        self.start();
+
+        // The client may send again from the callback, into the new rings.
+        for _ in 0..dropped {
+            self.client.get().map(|client| client.frame_sent(ReturnCode::FAIL, None));
+        }
+    }
+
+    /// Queues a frame to be sent. If `timestamp` is true, `frame_sent` is
+    /// told when it left.
+    pub fn transmit(&self, frame: &[u8], timestamp: bool) -> ReturnCode {
+        if !self.enabled.get() || self.link.get().is_none() {
+            return ReturnCode::EOFF;
+        }
+        if frame.len() > MAX_FRAME_SIZE {
+            return ReturnCode::ESIZE;
+        }
+        if self.tx_count.get() == N_TX {
+            return ReturnCode::EBUSY;
+        }
+
+        let index = self.tx_next.get();
+        unsafe { TX_BUFFERS.0[index][..frame.len()].copy_from_slice(frame) };
+        let bd = tx_descriptor(index);
+        set16(&mut bd.length, frame.len() as u16);
+        set16(&mut bd.ext0, 0);
+        set16(&mut bd.ext1, TX_INTERRUPT | if timestamp { TX_TIMESTAMP } else { 0 });
+        compiler_fence(Ordering::SeqCst);
+        set16(&mut bd.control, TX_READY | TX_LAST | TX_CRC | wrap(index, N_TX, TX_WRAP));
+
+        let timestamped = self.tx_timestamped.get() & !(1 << index);
+        self.tx_timestamped.set(timestamped | (timestamp as u32) << index);
+        self.tx_next.set((index + 1) % N_TX);
+        self.tx_count.set(self.tx_count.get() + 1);
+        self.regs().tdar.write(DescriptorActive::ACTIVE::SET);
+        ReturnCode::SUCCESS
+    }
+
+    /// The current time of the 1588 timer.
+    pub fn time(&self) -> Timestamp {
+        let regs = self.regs();
+        regs.atcr.modify(TimerControl::CAPTURE::SET);
+        while regs.atcr.is_set(TimerControl::CAPTURE) {}
+        let nanoseconds = regs.atvr.get();
+
+        // The timer may have wrapped since the last interrupt.
+        let mut seconds = self.seconds.get();
+        if regs.eir.is_set(Interrupt::TS_TIMER) && nanoseconds < NANOS_PER_SECOND / 2 {
+            seconds = seconds.wrapping_add(1);
+        }
+        Timestamp {
+            seconds: seconds,
+            nanoseconds: nanoseconds,
+        }
+    }
+
+    pub fn set_time(&self, time: Timestamp) {
+        let regs = self.regs();
+        regs.eir.write(Interrupt::TS_TIMER::SET);
+        regs.atvr.set(time.nanoseconds);
+        self.seconds.set(time.seconds);
+    }
+
+    /// Steps the time by `offset` nanoseconds.
+    pub fn adjust_time(&self, offset: i64) {
+        let now = self.time();
+        let total = now.seconds as i64 * NANOS_PER_SECOND as i64 + now.nanoseconds as i64 + offset;
+        let total = if total < 0 { 0 } else { total };
+        self.set_time(Timestamp {
+            seconds: (total / NANOS_PER_SECOND as i64) as u32,
+            nanoseconds: (total % NANOS_PER_SECOND as i64) as u32,
+        });
+    }
+
+    /// Runs the timer fast or slow by `ppb` parts per billion.
+    pub fn adjust_frequency(&self, ppb: i32) {
+        if self.enabled.get() {
+            self.set_rate(ppb);
+        }
+    }
+
+    /// Each tick adds the nanoseconds per tick, rounded down. The timer makes
+    /// up the remainder, and any adjustment, by adding one more or one less
+    /// every so many ticks.
+    fn set_rate(&self, ppb: i32) {
+        let regs = self.regs();
+        let hz = self.timer_hz.get();
+        let increment = NANOS_PER_SECOND / hz;
+        let correction = (NANOS_PER_SECOND % hz) as i64 + ppb as i64;
+
+        if correction == 0 {
+            regs.atcor.set(0);
+            regs.atinc.write(TimerIncrement::INC.val(increment) +
+                             TimerIncrement::INC_CORR.val(increment));
+            return;
+        }
+        let corrected = if correction > 0 { increment + 1 } else { increment - 1 };
+        let period = hz as i64 / correction.abs();
+        regs.atcor.set(if period < 1 { 1 } else { period as u32 });
+        regs.atinc.write(TimerIncrement::INC.val(increment) +
+                         TimerIncrement::INC_CORR.val(corrected));
+    }
+
+    /// Turns a timestamp from a descriptor, which has only nanoseconds, into
+    /// a full time. The event must be less than a second old.
+    fn complete(&self, nanoseconds: u32) -> Timestamp {
+        let now = self.time();
+        let seconds = if nanoseconds > now.nanoseconds {
+            now.seconds.wrapping_sub(1)
+        } else {
+            now.seconds
+        };
+        Timestamp {
+            seconds: seconds,
+            nanoseconds: nanoseconds,
+        }
+    }
+
+    fn receive(&self) {
+        loop {
+            let index = self.rx_next.get();
+            let bd = rx_descriptor(index);
+            let control = get16(&bd.control);
+            if control & RX_EMPTY != 0 {
+                break;
+            }
+
+            if control & RX_LAST != 0 && control & RX_ERRORS == 0 {
+                let len = get16(&bd.length) as usize;
+                let timestamp = self.complete(get32(&bd.timestamp));
+                let frame = unsafe { &RX_BUFFERS.0[index][..len] };
+                self.client.get().map(|client| client.frame_received(frame, timestamp));
+            } else {
+                self.rx_errors.set(self.rx_errors.get() + 1);
+            }
+
+            set16(&mut bd.ext1, RX_INTERRUPT);
+            set32(&mut bd.timestamp, 0);
+            compiler_fence(Ordering::SeqCst);
+            set16(&mut bd.control, RX_EMPTY | wrap(index, N_RX, RX_WRAP));
+            self.rx_next.set((index + 1) % N_RX);
+        }
+        self.regs().rdar.write(DescriptorActive::ACTIVE::SET);
+    }
+
+    fn transmit_done(&self) {
+        while self.tx_count.get() > 0 {
+            let index = self.tx_oldest.get();
+            let bd = tx_descriptor(index);
+            if get16(&bd.control) & TX_READY != 0 {
+                break;
+            }
+
+            let result = if get16(&bd.ext0) & TX_ERROR != 0 {
+                ReturnCode::FAIL
+            } else {
+                ReturnCode::SUCCESS
+            };
+            let timestamp = if self.tx_timestamped.get() & (1 << index) != 0 {
+                Some(self.complete(get32(&bd.timestamp)))
+            } else {
+                None
+            };
+            self.tx_oldest.set((index + 1) % N_TX);
+            self.tx_count.set(self.tx_count.get() - 1);
+            self.client.get().map(|client| client.frame_sent(result, timestamp));
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        let regs = self.regs();
+        let events = regs.eir.get() & regs.eimr.get();
+        // The timer flag is cleared when the seconds are counted, so that
+        // `time` sees an uncounted wrap.
+        regs.eir.set(events & !(Interrupt::TS_TIMER::SET).mask);
+
+        if regs.eir.is_set(Interrupt::TS_TIMER) {
+            regs.eir.write(Interrupt::TS_TIMER::SET);
+            self.seconds.set(self.seconds.get().wrapping_add(1));
+        }
+        if events & (Interrupt::RXF::SET).mask != 0 {
+            self.receive();
+        }
+        if events & (Interrupt::TXF::SET).mask != 0 {
+            self.transmit_done();
+        }
+        if events & (Interrupt::BABR::SET + Interrupt::EBERR::SET).mask != 0 {
+            self.rx_errors.set(self.rx_errors.get() + 1);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+//! Implementation of the MK66 program flash controller (FTFE).
+//!
+//! Program flash is erased in 4KB sectors and programmed in 8-byte phrases.
+//! The flash cannot be read while a command runs, so each command is launched
+//! and waited on from a function in RAM with interrupts disabled. Commands
+//! are issued one at a time, and the command complete interrupt steps the
+//! driver to the next one, so a page write does not hold off interrupts for
+//! its full duration.
+//!
+//! A page in `hil::flash` terms is one sector. Sector 0 holds the vector
+//! table and flash configuration field and is never erased or written.
+
+use core::cell::Cell;
+use core::{mem, ptr, slice};
+use kernel::ReturnCode;
+use kernel::common::cells::TakeCell;
+use kernel::hil;
+use nvic::{self, NvicIdx};
+use regs::flash::*;
+
+pub const SECTOR_SIZE: usize = 4096;
+pub const PHRASE_SIZE: usize = 8;
+pub const PROGRAM_FLASH_SIZE: usize = 0x10_0000;
+
+const NUM_SECTORS: usize = PROGRAM_FLASH_SIZE / SECTOR_SIZE;
+
+const PROGRAM_PHRASE: u8 = 0x07;
+const ERASE_SECTOR: u8 = 0x09;
+
+pub struct Sector(pub [u8; SECTOR_SIZE]);
+
+impl Sector {
+    pub const fn new() -> Sector {
+        Sector([0; SECTOR_SIZE])
+    }
+}
+
+impl Default for Sector {
+    fn default() -> Sector {
+        Sector::new()
+    }
+}
+
+impl AsMut<[u8]> for Sector {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum State {
+    Idle,
+    Read,
+    Erase,
+    // Erasing before a write.
+    WriteErase,
+    // Programming the phrase at the given offset into the sector.
+    Write(usize),
+}
+
+pub struct Flash<'a> {
+    client: Cell<Option<&'a hil::flash::Client<Flash<'a>>>>,
+    buffer: TakeCell<'static, Sector>,
+    state: Cell<State>,
+    sector: Cell<usize>,
+    error: Cell<bool>,
+}
+
+pub static mut FLASH: Flash<'static> = Flash::new();
+
+// Launches the loaded command and waits for it to finish. This must not touch
+// flash, so it lives in RAM and is always called through a pointer: a direct
+// call from flash cannot reach RAM.
+#[link_section = ".ramfunc"]
+#[inline(never)]
+unsafe extern "C" fn launch_command(fstat: *mut u8) {
+    let primask: u32;
+    asm!("mrs $0, primask
+          cpsid i"
+         : "=r"(primask) : : "memory" : "volatile");
+
+    // Writing CCIF launches the command.
+    ptr::write_volatile(fstat, 0x80);
+    while ptr::read_volatile(fstat) & 0x80 == 0 {}
+
+    if primask & 1 == 0 {
+        asm!("cpsie i" : : : "memory" : "volatile");
+    }
+}
+
+static LAUNCH_COMMAND: unsafe extern "C" fn(*mut u8) = launch_command;
+
+/// Runs one FTFE command to completion, and returns false if the controller
+/// reported an error. `data` is copied to FCCOB4 onwards in memory order,
+/// i.e. starting from FCCOB7.
+///
+/// The caller must not run commands from an interrupt while another command
+/// is being prepared.
+pub unsafe fn run_command(cmd: u8, address: usize, data: &[u8]) -> bool {
+    let regs: &mut Registers = mem::transmute(FTFE);
+
+    // An EEPROM write may still be in progress.
+    while !regs.fstat.is_set(FlashStatus::CCIF) {}
+
+    // Clear errors from the previous command, which would otherwise block
+    // this one.
+    regs.fstat.write(FlashStatus::ACCERR::SET + FlashStatus::FPVIOL::SET +
+                     FlashStatus::RDCOLERR::SET);
+
+    regs.fccob[0].set(address as u8);
+    regs.fccob[1].set((address >> 8) as u8);
+    regs.fccob[2].set((address >> 16) as u8);
+    regs.fccob[3].set(cmd);
+    for (i, byte) in data.iter().enumerate() {
+        regs.fccob[4 + i].set(*byte);
+    }
+
+    let launch = ptr::read_volatile(&LAUNCH_COMMAND);
+    launch(&regs.fstat as *const _ as *mut u8);
+    ptr::write_volatile(FMC_PFB01CR, ptr::read_volatile(FMC_PFB01CR) | FMC_CACHE_INVALIDATE);
+
+    !(regs.fstat.is_set(FlashStatus::ACCERR) ||
+      regs.fstat.is_set(FlashStatus::FPVIOL) ||
+      regs.fstat.is_set(FlashStatus::MGSTAT0))
+}
+
+fn sector_address(sector: usize) -> usize {
+    sector * SECTOR_SIZE
+}
+
+fn sector_memory(sector: usize) -> &'static [u8] {
+    unsafe { slice::from_raw_parts(sector_address(sector) as *const u8, SECTOR_SIZE) }
+}
+
+impl<'a> Flash<'a> {
+    const fn new() -> Flash<'a> {
+        Flash {
+            client: Cell::new(None),
+            buffer: TakeCell::empty(),
+            state: Cell::new(State::Idle),
+            sector: Cell::new(0),
+            error: Cell::new(false),
+        }
+    }
+
+    fn regs(&self) -> &mut Registers {
+        unsafe { mem::transmute(FTFE) }
+    }
+
+    pub fn init(&self) {
+        use sim::{clocks, Clock};
+        clocks::FTF.enable();
+        unsafe { nvic::enable(NvicIdx::FLASHCC); }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.state.get() != State::Idle
+    }
+
+    fn command(&self, cmd: u8, address: usize, data: &[u8]) -> bool {
+        unsafe { run_command(cmd, address, data) }
+    }
+
+    // The command has already finished, so enabling the interrupt makes it
+    // pend immediately. The next step runs from the interrupt handler.
+    fn schedule_step(&self) {
+        self.regs().fcnfg.modify(FlashConfig::CCIE::SET);
+    }
+
+    fn erase(&self, sector: usize) -> bool {
+        self.command(ERASE_SECTOR, sector_address(sector), &[]) &&
+            sector_memory(sector).iter().all(|byte| *byte == 0xFF)
+    }
+
+    // Programs phrases from `offset` until one is written, and returns the
+    // offset of the next phrase. Erased phrases are skipped.
+    fn program_next(&self, sector: usize, offset: usize) -> Result<usize, ()> {
+        self.buffer.map_or(Err(()), |buffer| {
+            let mut offset = offset;
+            while offset < SECTOR_SIZE {
+                let phrase = &buffer.0[offset..offset + PHRASE_SIZE];
+                let next = offset + PHRASE_SIZE;
+                if phrase.iter().all(|byte| *byte == 0xFF) {
+                    offset = next;
+                    continue;
+                }
+
+                let address = sector_address(sector) + offset;
+                if !self.command(PROGRAM_PHRASE, address, phrase) {
+                    return Err(());
+                }
+                return Ok(next);
+            }
+            Ok(offset)
+        })
+    }
+
+    fn verify(&self, sector: usize) -> bool {
+        self.buffer.map_or(false, |buffer| &buffer.0[..] == sector_memory(sector))
+    }
+
+    fn check_sector(&self, sector: usize) -> ReturnCode {
+        if self.is_busy() {
+            ReturnCode::EBUSY
+        } else if sector == 0 || sector >= NUM_SECTORS {
+            ReturnCode::EINVAL
+        } else {
+            ReturnCode::SUCCESS
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        self.regs().fcnfg.modify(FlashConfig::CCIE::CLEAR);
+
+        let sector = self.sector.get();
+        match self.state.get() {
+            State::Idle => {},
+            State::Read => {
+                self.state.set(State::Idle);
+                self.client.get().map(|client| {
+                    self.buffer.take().map(|buffer| {
+                        client.read_complete(buffer, hil::flash::Error::CommandComplete);
+                    });
+                });
+            },
+            State::Erase => {
+                self.state.set(State::Idle);
+                let error = if self.error.get() {
+                    hil::flash::Error::FlashError
+                } else {
+                    hil::flash::Error::CommandComplete
+                };
+                self.client.get().map(|client| client.erase_complete(error));
+            },
+            State::WriteErase | State::Write(_) => {
+                let offset = match self.state.get() {
+                    State::Write(offset) => offset,
+                    _ => 0,
+                };
+
+                let done = if self.error.get() {
+                    true
+                } else if offset < SECTOR_SIZE {
+                    match self.program_next(sector, offset) {
+                        Ok(next) => {
+                            self.state.set(State::Write(next));
+                            next >= SECTOR_SIZE
+                        },
+                        Err(()) => {
+                            self.error.set(true);
+                            true
+                        },
+                    }
+                } else {
+                    true
+                };
+
+                if !done {
+                    self.schedule_step();
+                    return;
+                }
+
+                if !self.error.get() && !self.verify(sector) {
+                    self.error.set(true);
+                }
+
+                self.state.set(State::Idle);
+                let error = if self.error.get() {
+                    hil::flash::Error::FlashError
+                } else {
+                    hil::flash::Error::CommandComplete
+                };
+                self.client.get().map(|client| {
+                    self.buffer.take().map(|buffer| client.write_complete(buffer, error));
+                });
+            },
+        }
+    }
+}
+
+impl<'a, C: hil::flash::Client<Self>> hil::flash::HasClient<'a, C> for Flash<'a> {
+    fn set_client(&'a self, client: &'a C) {
+        self.client.set(Some(client));
+    }
+}
+
+impl<'a> hil::flash::Flash for Flash<'a> {
+    type Page = Sector;
+
+    fn read_page(&self, page_number: usize, buf: &'static mut Self::Page) -> ReturnCode {
+        let result = self.check_sector(page_number);
+        if result != ReturnCode::SUCCESS {
+            return result;
+        }
+
+        buf.0.copy_from_slice(sector_memory(page_number));
+        self.buffer.replace(buf);
+        self.state.set(State::Read);
+        self.schedule_step();
+        ReturnCode::SUCCESS
+    }
+
+    fn write_page(&self, page_number: usize, buf: &'static mut Self::Page) -> ReturnCode {
+        let result = self.check_sector(page_number);
+        if result != ReturnCode::SUCCESS {
+            return result;
+        }
+
+        self.buffer.replace(buf);
+        self.sector.set(page_number);
+        self.state.set(State::WriteErase);
+        self.error.set(!self.erase(page_number));
+        self.schedule_step();
+        ReturnCode::SUCCESS
+    }
+
+    fn erase_page(&self, page_number: usize) -> ReturnCode {
+        let result = self.check_sector(page_number);
+        if result != ReturnCode::SUCCESS {
+            return result;
+        }
+
+        self.sector.set(page_number);
+        self.state.set(State::Erase);
+        self.error.set(!self.erase(page_number));
+        self.schedule_step();
+        ReturnCode::SUCCESS
+    }
+}
@@ -0,0 +1,179 @@
+//! Builder for the flash configuration field.
+//!
+//! The 16 bytes at 0x400 are loaded into the flash controller at reset. They
+//! hold the backdoor key, the program flash protection bits, the security
+//! byte (FSEC), boot options (FOPT), and the EEPROM and data flash protection
+//! bits. A bad value can permanently lock the chip, so boards build the field
+//! with `FlashConfig` in a static initializer instead of writing raw bytes:
+//!
+//! ```rust,ignore
+//! #[link_section = ".flashconfig"]
+//! #[no_mangle]
+//! pub static FLASH_CONFIG_BYTES: [u8; 16] = FlashConfig::new()
+//!     .protect_range(0x0000_0000, 0x0002_0000)
+//!     .bytes();
+//! ```
+//!
+//! Securing the chip while also disabling mass erase cannot be undone, so
+//! `bytes` refuses to build such a configuration: evaluating it in a static
+//! fails to compile.
+//!
+//! [Kinetis K66 Sub-Family Reference Manual Section 29.3.1]
+
+use flash::PROGRAM_FLASH_SIZE;
+
+/// Program flash is protected in 32 equal regions.
+pub const PROTECTION_REGION_SIZE: usize = PROGRAM_FLASH_SIZE / 32;
+
+// Two-bit FSEC fields use 0b10 for one setting and any other value for the
+// other.
+const FSEC_SEC_UNSECURE: u8 = 0b10 << 0;
+const FSEC_FSLACC_DENIED: u8 = 0b10 << 2;
+const FSEC_MEEN_DISABLED: u8 = 0b10 << 4;
+const FSEC_KEYEN_ENABLED: u8 = 0b10 << 6;
+
+const FOPT_NMI_ENABLE: u8 = 1 << 2;
+
+#[derive(Copy, Clone)]
+pub struct FlashConfig {
+    backdoor_key: [u8; 8],
+    // A set bit leaves the region unprotected.
+    fprot: u32,
+    fsec: u8,
+    fopt: u8,
+    feprot: u8,
+    fdprot: u8,
+}
+
+impl FlashConfig {
+    /// The Teensyduino defaults: unsecured, nothing protected, NMI disabled,
+    /// and the boot source chosen by the bootloader.
+    pub const fn new() -> FlashConfig {
+        FlashConfig {
+            backdoor_key: [0xFF; 8],
+            fprot: 0xFFFF_FFFF,
+            fsec: 0xDE,
+            fopt: 0xF9,
+            feprot: 0xFF,
+            fdprot: 0xFF,
+        }
+    }
+
+    /// Protects the program flash region with the given index against
+    /// programming and erasing.
+    pub const fn protect_region(self, region: usize) -> FlashConfig {
+        FlashConfig {
+            fprot: self.fprot & !(1 << region),
+            ..self
+        }
+    }
+
+    /// Protects every region that overlaps the addresses from `start` up to,
+    /// but not including, `end`.
+    pub const fn protect_range(self, start: usize, end: usize) -> FlashConfig {
+        FlashConfig {
+            fprot: self.fprot & !FlashConfig::region_mask(start / PROTECTION_REGION_SIZE,
+                                                          (end - 1) / PROTECTION_REGION_SIZE),
+            ..self
+        }
+    }
+
+    // Bits `first` to `last` inclusive.
+    const fn region_mask(first: usize, last: usize) -> u32 {
+        (((1u64 << (last + 1)) - 1) as u32) & !((1u32 << first) - 1)
+    }
+
+    /// Protects the FlexRAM EEPROM regions with bits clear in `unprotected`.
+    pub const fn eeprom_protection(self, unprotected: u8) -> FlashConfig {
+        FlashConfig {
+            feprot: unprotected,
+            ..self
+        }
+    }
+
+    /// Protects the data flash regions with bits clear in `unprotected`.
+    pub const fn data_flash_protection(self, unprotected: u8) -> FlashConfig {
+        FlashConfig {
+            fdprot: unprotected,
+            ..self
+        }
+    }
+
+    /// Secures the chip, which blocks debugger access to flash and RAM.
+    pub const fn secure(self, secure: bool) -> FlashConfig {
+        FlashConfig {
+            fsec: (self.fsec & !0b11) | [FSEC_SEC_UNSECURE, 0][secure as usize],
+            ..self
+        }
+    }
+
+    /// Allows or denies factory access to a secured chip.
+    pub const fn factory_access(self, allowed: bool) -> FlashConfig {
+        FlashConfig {
+            fsec: (self.fsec & !(0b11 << 2)) | [FSEC_FSLACC_DENIED, 0b11 << 2][allowed as usize],
+            ..self
+        }
+    }
+
+    /// Allows or denies mass erase, which is how a secured chip is recovered
+    /// and how the Teensy bootloader reprograms the chip.
+    pub const fn mass_erase(self, enabled: bool) -> FlashConfig {
+        FlashConfig {
+            fsec: (self.fsec & !(0b11 << 4)) | [FSEC_MEEN_DISABLED, 0b01 << 4][enabled as usize],
+            ..self
+        }
+    }
+
+    /// Sets the key that unsecures the chip until the next reset when
+    /// written with the Verify Backdoor Access Key command, and enables
+    /// backdoor access.
+    pub const fn backdoor_key(self, key: [u8; 8]) -> FlashConfig {
+        FlashConfig {
+            backdoor_key: key,
+            fsec: (self.fsec & !(0b11 << 6)) | FSEC_KEYEN_ENABLED,
+            ..self
+        }
+    }
+
+    /// Enables or disables the NMI pin.
+    pub const fn nmi(self, enabled: bool) -> FlashConfig {
+        FlashConfig {
+            fopt: (self.fopt & !FOPT_NMI_ENABLE) | [0, FOPT_NMI_ENABLE][enabled as usize],
+            ..self
+        }
+    }
+
+    /// Sets the raw boot options byte.
+    /// [Kinetis K66 Sub-Family Reference Manual Section 6.3.3]
+    pub const fn boot_options(self, fopt: u8) -> FlashConfig {
+        FlashConfig {
+            fopt: fopt,
+            ..self
+        }
+    }
+
+    const fn is_secure(&self) -> bool {
+        self.fsec & 0b11 != FSEC_SEC_UNSECURE
+    }
+
+    const fn is_mass_erase_disabled(&self) -> bool {
+        self.fsec & (0b11 << 4) == FSEC_MEEN_DISABLED
+    }
+
+    /// Returns the 16 bytes for the flash configuration field.
+    pub const fn bytes(self) -> [u8; 16] {
+        [
+            self.backdoor_key[0], self.backdoor_key[1], self.backdoor_key[2], self.backdoor_key[3],
+            self.backdoor_key[4], self.backdoor_key[5], self.backdoor_key[6], self.backdoor_key[7],
+            // FPROT3 (regions 0-7) comes first.
+            self.fprot as u8, (self.fprot >> 8) as u8, (self.fprot >> 16) as u8, (self.fprot >> 24) as u8,
+            // A secured chip that cannot be mass erased can never be
+            // reprogrammed. Indexing past the end makes such a configuration
+            // fail to evaluate.
+            [self.fsec][(self.is_secure() && self.is_mass_erase_disabled()) as usize],
+            self.fopt,
+            self.feprot,
+            self.fdprot,
+        ]
+    }
+}
@@ -0,0 +1,225 @@
+//! Implementation of the MK66 FlexTimer (FTM) quadrature decoder mode.
+//!
+//! FTM1 and FTM2 have a hardware quadrature decoder. The 16-bit FTM counter is
+//! extended in software: each counter overflow or underflow adjusts a signed
+//! revolution count, so `position` returns a 48-bit signed count that fits in
+//! an `i64`.
+//!
+//! An optional index pin (any GPIO) resets the position on its rising edge.
+
+use core::cell::Cell;
+use core::mem;
+use kernel::hil;
+use gpio::Gpio;
+use nvic::{self, NvicIdx};
+use regs::ftm::*;
+
+/// How the decoder interprets its phase A and phase B inputs.
+#[derive(Copy, Clone, PartialEq)]
+pub enum QuadratureMode {
+    /// Standard quadrature encoder with two signals 90 degrees out of phase.
+    PhaseAB,
+    /// Phase A is a count signal and phase B selects the count direction.
+    CountDirection,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Direction {
+    Increasing,
+    Decreasing,
+}
+
+pub struct Ftm<'a> {
+    regs: *mut Registers,
+    index: usize,
+    overflows: Cell<i32>,
+    index_pin: Cell<Option<&'a Gpio<'a>>>,
+    index_reset: Cell<bool>,
+}
+
+pub static mut FTM1: Ftm<'static> = Ftm::new(1);
+pub static mut FTM2: Ftm<'static> = Ftm::new(2);
+
+impl<'a> Ftm<'a> {
+    const fn new(index: usize) -> Ftm<'a> {
+        Ftm {
+            regs: FTM_ADDRS[index],
+            index: index,
+            overflows: Cell::new(0),
+            index_pin: Cell::new(None),
+            index_reset: Cell::new(false),
+        }
+    }
+
+    fn regs(&self) -> &mut Registers {
+        unsafe { mem::transmute(self.regs) }
+    }
+
+    fn enable_clock(&self) {
+        use sim::{clocks, Clock};
+        match self.index {
+            0 => clocks::FTM0.enable(),
+            1 => clocks::FTM1.enable(),
+            2 => clocks::FTM2.enable(),
+            3 => clocks::FTM3.enable(),
+            _ => unreachable!()
+        };
+    }
+
+    fn enable_interrupt(&self) {
+        let idx = match self.index {
+            0 => NvicIdx::FTM0,
+            1 => NvicIdx::FTM1,
+            2 => NvicIdx::FTM2,
+            3 => NvicIdx::FTM3,
+            _ => unreachable!()
+        };
+
+        unsafe { nvic::enable(idx); }
+        self.regs().sc.modify(StatusAndControl::TOIE::SET);
+    }
+
+    /// Configures the FTM as a quadrature decoder and starts counting from
+    /// zero.
+    ///
+    /// `filter` is the input filter length applied to both phases, in units
+    /// of four bus clock cycles. A value of zero disables the filter.
+    pub fn enable_quadrature(&self, mode: QuadratureMode, filter: u32) {
+        // The quadrature decoder only exists on FTM1 and FTM2.
+        if self.index != 1 && self.index != 2 {
+            return;
+        }
+
+        self.enable_clock();
+
+        let regs = self.regs();
+
+        // Disable write protection and enable the full FTM feature set.
+        regs.mode.modify(FeaturesMode::WPDIS::SET);
+        regs.mode.modify(FeaturesMode::FTMEN::SET);
+
+        // Count through the full 16-bit range.
+        regs.sc.write(StatusAndControl::CLKS::NoClock);
+        regs.cntin.set(0);
+        regs.modulo.set(0xFFFF);
+        regs.cnt.set(0);
+
+        let quadmode = match mode {
+            QuadratureMode::PhaseAB => QuadratureDecoderControl::QUADMODE::PhaseAB,
+            QuadratureMode::CountDirection => QuadratureDecoderControl::QUADMODE::CountDirection,
+        };
+
+        let filter = filter & 0xF;
+        regs.filter.modify(InputCaptureFilter::CH0FVAL.val(filter) +
+                           InputCaptureFilter::CH1FVAL.val(filter));
+        let filter_enable = if filter > 0 {
+            QuadratureDecoderControl::PHAFLTREN::SET + QuadratureDecoderControl::PHBFLTREN::SET
+        } else {
+            QuadratureDecoderControl::PHAFLTREN::CLEAR + QuadratureDecoderControl::PHBFLTREN::CLEAR
+        };
+
+        regs.qdctrl.write(quadmode + filter_enable + QuadratureDecoderControl::QUADEN::SET);
+
+        // The counter is clocked by the phase inputs, but the FTM still
+        // requires a clock source to be selected.
+        regs.sc.write(StatusAndControl::CLKS::SystemClock + StatusAndControl::PS.val(0));
+
+        self.overflows.set(0);
+        self.enable_interrupt();
+    }
+
+    pub fn disable_quadrature(&self) {
+        self.regs().sc.write(StatusAndControl::CLKS::NoClock);
+        self.regs().qdctrl.modify(QuadratureDecoderControl::QUADEN::CLEAR);
+    }
+
+    pub fn counter(&self) -> u16 {
+        self.regs().cnt.get() as u16
+    }
+
+    pub fn direction(&self) -> Direction {
+        if self.regs().qdctrl.is_set(QuadratureDecoderControl::QUADIR) {
+            Direction::Increasing
+        } else {
+            Direction::Decreasing
+        }
+    }
+
+    // Folds a pending counter wrap into the revolution count. The overflow
+    // interrupt is serviced from the kernel loop, so readers call this to
+    // avoid returning a position that is off by a full revolution.
+    fn update_overflows(&self) {
+        let regs = self.regs();
+        if regs.sc.is_set(StatusAndControl::TOF) {
+            // TOFDIR is set when the counter wrapped from MOD to CNTIN, i.e.
+            // while counting up.
+            if regs.qdctrl.is_set(QuadratureDecoderControl::TOFDIR) {
+                self.overflows.set(self.overflows.get().wrapping_add(1));
+            } else {
+                self.overflows.set(self.overflows.get().wrapping_sub(1));
+            }
+
+            // TOF is cleared by reading it while set, then writing a zero.
+            regs.sc.modify(StatusAndControl::TOF::CLEAR);
+        }
+    }
+
+    /// Returns the signed position in encoder counts since the last reset.
+    pub fn position(&self) -> i64 {
+        loop {
+            self.update_overflows();
+            let revolutions = self.overflows.get() as i64;
+            let count = self.counter() as i64;
+
+            // If the counter wrapped while we were reading it, we cannot tell
+            // which side of the wrap `count` came from, so try again.
+            if !self.regs().sc.is_set(StatusAndControl::TOF) {
+                return (revolutions << 16) + count;
+            }
+        }
+    }
+
+    /// Resets the position to zero.
+    pub fn reset_position(&self) {
+        // Writing any value to CNT loads it with CNTIN.
+        self.regs().cnt.set(0);
+        self.regs().sc.modify(StatusAndControl::TOF::CLEAR);
+        self.overflows.set(0);
+    }
+
+    /// Sets the GPIO pin that carries the encoder's index pulse. The caller is
+    /// responsible for making this FTM the pin's interrupt client.
+    pub fn set_index_pin(&self, pin: &'a Gpio<'a>) {
+        hil::gpio::Pin::make_input(pin);
+        self.index_pin.set(Some(pin));
+    }
+
+    pub fn has_index_pin(&self) -> bool {
+        self.index_pin.get().is_some()
+    }
+
+    pub fn enable_index_reset(&self) -> bool {
+        self.index_pin.get().map_or(false, |pin| {
+            hil::gpio::Pin::enable_interrupt(pin, self.index, hil::gpio::InterruptMode::RisingEdge);
+            self.index_reset.set(true);
+            true
+        })
+    }
+
+    pub fn disable_index_reset(&self) {
+        self.index_reset.set(false);
+        self.index_pin.get().map(|pin| hil::gpio::Pin::disable_interrupt(pin));
+    }
+
+    pub fn handle_interrupt(&self) {
+        self.update_overflows();
+    }
+}
+
+impl<'a> hil::gpio::Client for Ftm<'a> {
+    fn fired(&self, _: usize) {
+        if self.index_reset.get() {
+            self.reset_position();
+        }
+    }
+}
@@ -218,6 +218,17 @@ impl<'a, P: PinNum> Pin<'a, P> {
         port.pcr[self.gpio.index()].modify(PinControl::MUX.val(function as u32));
     }
 
+    pub fn set_input_mode(&self, mode: hil::gpio::InputMode) {
+        self.gpio.set_input_mode(mode);
+    }
+
+    pub fn set_high_drive_strength(&self, high: bool) {
+        let port = self.gpio.port.regs();
+        let dse = if high { PinControl::DSE::SET } else { PinControl::DSE::CLEAR };
+
+        port.pcr[self.gpio.index()].modify(dse);
+    }
+
     pub fn release_claim(&self) {
         self.gpio.clear_client();
         self.gpio.set_client_data(0);
@@ -479,6 +490,10 @@ pub mod functions {
     pub const UART0_RX: Function<PinB16> = Function::new(Alt3);
     pub const UART0_TX: Function<PinB17> = Function::new(Alt3);
 
+    // UART2: PD02, PD03
+    pub const UART2_RX: Function<PinD02> = Function::new(Alt3);
+    pub const UART2_TX: Function<PinD03> = Function::new(Alt3);
+
     // SPI0
     pub const SPI0_MOSI: Function<PinC06> = Function::new(Alt2);
     pub const SPI0_MISO: Function<PinC07> = Function::new(Alt2);
@@ -489,6 +504,73 @@ pub mod functions {
     pub const SPI1_MOSI: Function<PinD06> = Function::new(Alt7);
     pub const SPI1_SCK: Function<PinD05> = Function::new(Alt7);
 
+    // FTM quadrature decoder inputs
+    pub const FTM1_QD_PHA0: Function<PinB00> = Function::new(Alt6);
+    pub const FTM1_QD_PHB0: Function<PinB01> = Function::new(Alt6);
+    pub const FTM1_QD_PHA1: Function<PinA12> = Function::new(Alt7);
+    pub const FTM1_QD_PHB1: Function<PinA13> = Function::new(Alt7);
+    pub const FTM2_QD_PHA: Function<PinB18> = Function::new(Alt6);
+    pub const FTM2_QD_PHB: Function<PinB19> = Function::new(Alt6);
+
+    // SDHC, wired to the Teensy 3.6 microSD socket
+    pub const SDHC_D0: Function<PinE01> = Function::new(Alt4);
+    pub const SDHC_D1: Function<PinE00> = Function::new(Alt4);
+    pub const SDHC_D2: Function<PinE05> = Function::new(Alt4);
+    pub const SDHC_D3: Function<PinE04> = Function::new(Alt4);
+    pub const SDHC_CMD: Function<PinE03> = Function::new(Alt4);
+    pub const SDHC_DCLK: Function<PinE02> = Function::new(Alt4);
+
+    // FlexCAN, CAN0 on Teensy pins 3 and 4, CAN1 on pins 33 and 34
+    pub const CAN0_TX: Function<PinA12> = Function::new(Alt2);
+    pub const CAN0_RX: Function<PinA13> = Function::new(Alt2);
+    pub const CAN1_TX: Function<PinE24> = Function::new(Alt2);
+    pub const CAN1_RX: Function<PinE25> = Function::new(Alt2);
+
+    // ENET RMII, with the 50MHz reference clock in on ENET_1588_CLKIN
+    pub const RMII0_MDIO: Function<PinB00> = Function::new(Alt4);
+    pub const RMII0_MDC: Function<PinB01> = Function::new(Alt4);
+    pub const RMII0_RXER: Function<PinA05> = Function::new(Alt4);
+    pub const RMII0_RXD1: Function<PinA12> = Function::new(Alt4);
+    pub const RMII0_RXD0: Function<PinA13> = Function::new(Alt4);
+    pub const RMII0_CRS_DV: Function<PinA14> = Function::new(Alt4);
+    pub const RMII0_TXEN: Function<PinA15> = Function::new(Alt4);
+    pub const RMII0_TXD0: Function<PinA16> = Function::new(Alt4);
+    pub const RMII0_TXD1: Function<PinA17> = Function::new(Alt4);
+    pub const ENET_1588_CLKIN: Function<PinE26> = Function::new(Alt2);
+
+    // I2S0, with the receiver following the transmitter's clocks
+    pub const I2S0_MCLK: Function<PinC08> = Function::new(Alt4);
+    pub const I2S0_TX_BCLK: Function<PinC03> = Function::new(Alt6);
+    pub const I2S0_TX_FS: Function<PinC02> = Function::new(Alt6);
+    pub const I2S0_TXD0: Function<PinC01> = Function::new(Alt6);
+    pub const I2S0_RXD1: Function<PinC11> = Function::new(Alt4);
+
+    // TSI0 electrodes, numbered by channel
+    pub const TSI0_CH0: Function<PinB00> = Function::new(Alt0);
+    pub const TSI0_CH6: Function<PinB01> = Function::new(Alt0);
+    pub const TSI0_CH7: Function<PinB02> = Function::new(Alt0);
+    pub const TSI0_CH8: Function<PinB03> = Function::new(Alt0);
+    pub const TSI0_CH9: Function<PinB16> = Function::new(Alt0);
+    pub const TSI0_CH10: Function<PinB17> = Function::new(Alt0);
+    pub const TSI0_CH11: Function<PinB18> = Function::new(Alt0);
+    pub const TSI0_CH12: Function<PinB19> = Function::new(Alt0);
+    pub const TSI0_CH13: Function<PinC00> = Function::new(Alt0);
+    pub const TSI0_CH14: Function<PinC01> = Function::new(Alt0);
+    pub const TSI0_CH15: Function<PinC02> = Function::new(Alt0);
+
+    // Analog comparator inputs
+    pub const CMP0_IN0: Function<PinC06> = Function::new(Alt0);
+    pub const CMP0_IN1: Function<PinC07> = Function::new(Alt0);
+    pub const CMP0_IN2: Function<PinC08> = Function::new(Alt0);
+    pub const CMP0_IN3: Function<PinC09> = Function::new(Alt0);
+    pub const CMP1_IN0: Function<PinC02> = Function::new(Alt0);
+    pub const CMP1_IN1: Function<PinC03> = Function::new(Alt0);
+    pub const CMP2_IN0: Function<PinA12> = Function::new(Alt0);
+    pub const CMP2_IN1: Function<PinA13> = Function::new(Alt0);
+
+    // CMT infrared output on Teensy pin 5
+    pub const CMT_IRO: Function<PinD07> = Function::new(Alt2);
+
     // The physical i2c ports
     // In most cases there is more than one bus per i2c
     // controller. Which are used is selected on a per-board
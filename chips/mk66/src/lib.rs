@@ -19,6 +19,7 @@ extern crate block_cipher_trait;
 
 pub mod chip;
 pub mod nvic;
+pub mod deferred;
 pub mod wdog;
 pub mod gpio;
 pub mod sim;
@@ -28,6 +29,26 @@ pub mod uart;
 pub mod clock;
 pub mod pit;
 pub mod spi;
+pub mod ftm;
+pub mod lptmr;
+pub mod llwu;
+pub mod rtc;
+pub mod flash;
+pub mod eeprom;
+pub mod flash_config;
+pub mod sdhc;
+pub mod sysmpu;
+pub mod usb;
+pub mod usbhs;
+pub mod can;
+pub mod enet;
+pub mod dma;
+pub mod sai;
+pub mod tsi;
+pub mod cmp;
+pub mod cmt;
+pub mod crc;
+pub mod mmcau;
 
 #[allow(while_true)]
 pub mod rnga;
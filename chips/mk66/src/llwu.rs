@@ -0,0 +1,49 @@
+//! Implementation of the MK66 Low-Leakage Wakeup Unit (LLWU).
+//!
+//! The LLWU selects which pins and internal modules can bring the chip out of
+//! the LLS and VLLS low-power modes. Module wakeup flags are cleared by
+//! clearing the flag in the source module itself.
+
+use core::mem;
+use nvic::{self, NvicIdx};
+use regs::llwu::*;
+
+/// Internal modules that can act as wakeup sources.
+/// [Kinetis K66 Sub-Family Reference Manual Section 3.7.1.2]
+#[derive(Copy, Clone)]
+pub enum WakeupModule {
+    Lptmr0 = 0,
+    Cmp0 = 1,
+    Cmp1 = 2,
+    Cmp2Cmp3 = 3,
+    Tsi0 = 4,
+    RtcAlarm = 5,
+    RtcSeconds = 7,
+}
+
+fn regs() -> &'static mut Registers {
+    unsafe { mem::transmute(LLWU) }
+}
+
+pub fn enable_module(module: WakeupModule) {
+    let me = regs().me.get();
+    regs().me.set(me | (1 << module as u8));
+    unsafe { nvic::enable(NvicIdx::LLWU); }
+}
+
+pub fn disable_module(module: WakeupModule) {
+    let me = regs().me.get();
+    regs().me.set(me & !(1 << module as u8));
+}
+
+pub fn module_woke(module: WakeupModule) -> bool {
+    regs().mf5.get() & (1 << module as u8) != 0
+}
+
+pub fn handle_interrupt() {
+    // Pin wakeup flags are write-one-to-clear. Module flags clear themselves
+    // once the module handles its own interrupt.
+    for pf in regs().pf.iter() {
+        pf.set(0xFF);
+    }
+}
@@ -0,0 +1,222 @@
+//! Implementation of the MK66 Low-Power Timer (LPTMR).
+//!
+//! Unlike the PIT, the LPTMR keeps counting in every low-power mode, including
+//! VLPS and LLS, and can wake the chip through the LLWU. It is clocked from
+//! either the 1kHz LPO or the 32.768kHz RTC oscillator.
+//!
+//! The hardware counter is only 16 bits, so the timer runs in reset-on-compare
+//! mode and every compare match adds the elapsed period to a 32-bit software
+//! epoch. When no alarm is set the compare value is left at its maximum, so
+//! the epoch keeps advancing. Long alarms are reached in several compare
+//! periods.
+
+use core::cell::Cell;
+use core::mem;
+use kernel::hil::time::{Client, Time, Alarm, Frequency};
+use llwu;
+use nvic;
+use regs::lptmr::*;
+use sim;
+
+const MAX_PERIOD: u32 = 0x1_0000;
+
+// Whether an alarm is due at `now`: alarms up to half the counter's range in
+// the past are treated as due rather than as far in the future.
+fn is_due(alarm: u32, now: u32) -> bool {
+    let remaining = alarm.wrapping_sub(now);
+    remaining == 0 || remaining > (1 << 31)
+}
+
+static mut LPTMR_HZ: u32 = 1_000;
+
+pub static mut LPTMR: Lptmr<'static> = Lptmr::new();
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ClockSource {
+    /// The 1kHz low-power oscillator, which is always running.
+    Lpo1kHz,
+    /// The 32.768kHz RTC oscillator. The RTC oscillator must be enabled.
+    Rtc32kHz,
+}
+
+pub struct Lptmr<'a> {
+    client: Cell<Option<&'a Client>>,
+    epoch: Cell<u32>,
+    alarm: Cell<u32>,
+    armed: Cell<bool>,
+}
+
+impl<'a> Lptmr<'a> {
+    const fn new() -> Lptmr<'a> {
+        Lptmr {
+            client: Cell::new(None),
+            epoch: Cell::new(0),
+            alarm: Cell::new(0),
+            armed: Cell::new(false),
+        }
+    }
+
+    fn regs(&self) -> &mut Registers {
+        unsafe { mem::transmute(LPTMR_BASE) }
+    }
+
+    pub fn init(&self, source: ClockSource) {
+        use sim::{clocks, Clock};
+        clocks::LPTMR.enable();
+
+        let regs = self.regs();
+        regs.csr.write(ControlStatus::TEN::CLEAR);
+
+        let (pcs, hz) = match source {
+            ClockSource::Lpo1kHz => (Prescale::PCS::Lpo, 1_000),
+            ClockSource::Rtc32kHz => {
+                sim::set_osc32k_source(sim::Osc32kSource::Rtc32kHz);
+                (Prescale::PCS::Erclk32k, 32_768)
+            }
+        };
+        unsafe { LPTMR_HZ = hz; }
+
+        regs.psr.write(pcs + Prescale::PBYP::SET);
+        regs.cmr.set(MAX_PERIOD - 1);
+        self.epoch.set(0);
+
+        unsafe { nvic::enable(nvic::NvicIdx::LOWPOWERTIMER); }
+        regs.csr.write(ControlStatus::TMS::TimeCounter +
+                       ControlStatus::TFC::ResetOnCompare +
+                       ControlStatus::TIE::SET +
+                       ControlStatus::TEN::SET);
+    }
+
+    /// Allows an LPTMR compare match to wake the chip from LLS or VLLS.
+    pub fn enable_wakeup(&self) {
+        llwu::enable_module(llwu::WakeupModule::Lptmr0);
+    }
+
+    pub fn disable_wakeup(&self) {
+        llwu::disable_module(llwu::WakeupModule::Lptmr0);
+    }
+
+    pub fn set_client(&self, client: &'a Client) {
+        self.client.set(Some(client));
+    }
+
+    fn counter(&self) -> u32 {
+        // The counter must be written before each read to latch its value.
+        self.regs().cnr.set(0);
+        self.regs().cnr.get() & 0xFFFF
+    }
+
+    fn period(&self) -> u32 {
+        self.regs().cmr.get() + 1
+    }
+
+    // The time at which the current compare period ends.
+    fn period_end(&self) -> u32 {
+        self.epoch.get().wrapping_add(self.period())
+    }
+
+    // The number of ticks from the start of a compare period until the
+    // alarm, limited to what fits in the compare register.
+    fn ticks_until_alarm(&self, from: u32) -> u32 {
+        if !self.armed.get() {
+            return MAX_PERIOD;
+        }
+
+        let remaining = self.alarm.get().wrapping_sub(from);
+        if is_due(self.alarm.get(), from) {
+            // Already due: match as soon as possible.
+            1
+        } else if remaining > MAX_PERIOD {
+            MAX_PERIOD
+        } else {
+            remaining
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        let regs = self.regs();
+        if !regs.csr.is_set(ControlStatus::TCF) {
+            return;
+        }
+
+        // The counter reset when the compare matched, so a full period has
+        // elapsed.
+        let now = self.period_end();
+        self.epoch.set(now);
+
+        // An alarm set in the past is caught by the next match.
+        let fired = self.armed.get() && is_due(self.alarm.get(), now);
+        if fired {
+            self.armed.set(false);
+        }
+
+        // CMR may only be changed while TCF is set, so pick the next period
+        // before acknowledging the match.
+        regs.cmr.set(self.ticks_until_alarm(now) - 1);
+        regs.csr.modify(ControlStatus::TCF::SET);
+
+        if fired {
+            self.client.get().map(|client| client.fired());
+        }
+    }
+}
+
+pub struct LptmrFrequency;
+impl Frequency for LptmrFrequency {
+    fn frequency() -> u32 {
+        unsafe { LPTMR_HZ }
+    }
+}
+
+impl<'a> Time for Lptmr<'a> {
+    type Frequency = LptmrFrequency;
+
+    fn disable(&self) {
+        // Leave the counter running so that `now` keeps advancing.
+        self.armed.set(false);
+    }
+
+    fn is_armed(&self) -> bool {
+        self.armed.get()
+    }
+}
+
+impl<'a> Alarm for Lptmr<'a> {
+    fn now(&self) -> u32 {
+        if self.regs().csr.is_set(ControlStatus::TCF) {
+            // A compare match is waiting to be handled, and the counter has
+            // already restarted from zero.
+            self.period_end().wrapping_add(self.counter())
+        } else {
+            self.epoch.get().wrapping_add(self.counter())
+        }
+    }
+
+    fn set_alarm(&self, ticks: u32) {
+        self.alarm.set(ticks);
+        self.armed.set(true);
+
+        let regs = self.regs();
+        if regs.csr.is_set(ControlStatus::TCF) {
+            // The interrupt handler will choose the next period.
+            return;
+        }
+
+        // If the current period ends before the alarm, the interrupt handler
+        // will extend it. Otherwise, or if the alarm is already due, CMR
+        // cannot be moved earlier while the timer runs, so restart the period
+        // from now. This drops any partial tick, which is at most one tick of
+        // drift per restart.
+        let now = self.now();
+        if is_due(ticks, now) || ticks.wrapping_sub(now) < self.period_end().wrapping_sub(now) {
+            self.epoch.set(now);
+            regs.csr.modify(ControlStatus::TEN::CLEAR);
+            regs.cmr.set(self.ticks_until_alarm(now) - 1);
+            regs.csr.modify(ControlStatus::TEN::SET);
+        }
+    }
+
+    fn get_alarm(&self) -> u32 {
+        self.alarm.get()
+    }
+}
@@ -56,6 +56,15 @@ pub fn state() -> State {
     }
 }
 
+/// Enables MCGIRCLK from the fast internal reference, for peripherals that
+/// need a clock independent of the system clock mode.
+pub fn enable_fast_irclk() {
+    let mcg: &mut Registers = unsafe { mem::transmute(MCG) };
+
+    mcg.c2.modify(Control2::IRCS::FastInternal);
+    mcg.c1.modify(Control1::IRCLKEN::Active);
+}
+
 pub struct Xtal {
     pub range: OscRange,
     pub frdiv: Frdiv,
@@ -0,0 +1,577 @@
+//! Implementation of the MK66 memory-mapped cryptographic acceleration unit
+//! (MMCAU).
+//!
+//! The MMCAU is a coprocessor with nine 32-bit registers, CA0 to CA8, and
+//! an accumulator, CAA. It runs the rounds of AES and the compression
+//! functions of SHA-1 and SHA-256, a command at a time, while key
+//! schedules, message schedules, padding and block cipher modes stay in
+//! software. Commands finish before a register can be read back, so there
+//! is never anything to wait for.
+//!
+//! AES-128, AES-192 and AES-256 are available in ECB, CBC and CTR modes
+//! through `AesKey` and `aes_crypt`, and AES-128 in CBC and CTR modes
+//! through the `symmetric_encryption` HIL on `MMCAU`. `Sha256` and `Sha1`
+//! hash data given in any number of pieces.
+//!
+//! AES keeps its state as four big-endian words in CA0 to CA3, SHA-256 its
+//! eight in CA0 to CA7 with CA8 to spare, and SHA-1 its five in CA0 to CA4.
+
+use core::cell::Cell;
+use core::ptr;
+use deferred::{self, Task};
+use kernel::ReturnCode;
+use kernel::common::cells::TakeCell;
+use kernel::hil::symmetric_encryption::{self, AES128, AES128CBC, AES128Ctr,
+                                        AES128_BLOCK_SIZE, AES128_KEY_SIZE};
+use regs::mmcau::*;
+
+pub const AES_BLOCK_SIZE: usize = 16;
+
+const MAX_ROUNDS: usize = 14;
+
+const SHA256_K: [u32; 64] = [
+    0x428A_2F98, 0x7137_4491, 0xB5C0_FBCF, 0xE9B5_DBA5,
+    0x3956_C25B, 0x59F1_11F1, 0x923F_82A4, 0xAB1C_5ED5,
+    0xD807_AA98, 0x1283_5B01, 0x2431_85BE, 0x550C_7DC3,
+    0x72BE_5D74, 0x80DE_B1FE, 0x9BDC_06A7, 0xC19B_F174,
+    0xE49B_69C1, 0xEFBE_4786, 0x0FC1_9DC6, 0x240C_A1CC,
+    0x2DE9_2C6F, 0x4A74_84AA, 0x5CB0_A9DC, 0x76F9_88DA,
+    0x983E_5152, 0xA831_C66D, 0xB003_27C8, 0xBF59_7FC7,
+    0xC6E0_0BF3, 0xD5A7_9147, 0x06CA_6351, 0x1429_2967,
+    0x27B7_0A85, 0x2E1B_2138, 0x4D2C_6DFC, 0x5338_0D13,
+    0x650A_7354, 0x766A_0ABB, 0x81C2_C92E, 0x9272_2C85,
+    0xA2BF_E8A1, 0xA81A_664B, 0xC24B_8B70, 0xC76C_51A3,
+    0xD192_E819, 0xD699_0624, 0xF40E_3585, 0x106A_A070,
+    0x19A4_C116, 0x1E37_6C08, 0x2748_774C, 0x34B0_BCB5,
+    0x391C_0CB3, 0x4ED8_AA4A, 0x5B9C_CA4F, 0x682E_6FF3,
+    0x748F_82EE, 0x78A5_636F, 0x84C8_7814, 0x8CC7_0208,
+    0x90BE_FFFA, 0xA450_6CEB, 0xBEF9_A3F7, 0xC671_78F2,
+];
+
+const SHA256_INIT: [u32; 8] = [
+    0x6A09_E667, 0xBB67_AE85, 0x3C6E_F372, 0xA54F_F53A,
+    0x510E_527F, 0x9B05_688C, 0x1F83_D9AB, 0x5BE0_CD19,
+];
+
+const SHA1_INIT: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+
+fn command(cmd: u32) {
+    unsafe { ptr::write_volatile(MMCAU_DIRECT, ONE_COMMAND | cmd << 22) }
+}
+
+fn commands2(first: u32, second: u32) {
+    unsafe { ptr::write_volatile(MMCAU_DIRECT, TWO_COMMANDS | first << 22 | second << 11) }
+}
+
+fn commands3(first: u32, second: u32, third: u32) {
+    unsafe { ptr::write_volatile(MMCAU_DIRECT, THREE_COMMANDS | first << 22 | second << 11 | third) }
+}
+
+/// Combines `register` with `value` by `op`: `LDR`, `ADR`, `XOR`, and so
+/// on.
+fn write(op: u32, register: u32, value: u32) {
+    let address = MMCAU_INDIRECT + ((op + register) << 2) as usize;
+    unsafe { ptr::write_volatile(address as *mut u32, value) }
+}
+
+fn read(register: u32) -> u32 {
+    let address = MMCAU_INDIRECT + ((STR + register) << 2) as usize;
+    unsafe { ptr::read_volatile(address as *const u32) }
+}
+
+fn get_word(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32
+}
+
+fn put_word(bytes: &mut [u8], word: u32) {
+    bytes[0] = (word >> 24) as u8;
+    bytes[1] = (word >> 16) as u8;
+    bytes[2] = (word >> 8) as u8;
+    bytes[3] = word as u8;
+}
+
+fn xor(bytes: &mut [u8], other: &[u8]) {
+    for (byte, other) in bytes.iter_mut().zip(other.iter()) {
+        *byte ^= *other;
+    }
+}
+
+/// Applies the AES S-box to each byte of `word`.
+fn sub_word(word: u32) -> u32 {
+    write(LDR, CAA, word);
+    command(AESS + CAA);
+    read(CAA)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mode {
+    Ecb,
+    Cbc,
+    Ctr,
+}
+
+/// An expanded AES key.
+#[derive(Copy, Clone)]
+pub struct AesKey {
+    schedule: [u32; 4 * (MAX_ROUNDS + 1)],
+    // 0 for no key.
+    rounds: usize,
+}
+
+impl AesKey {
+    const fn empty() -> AesKey {
+        AesKey {
+            schedule: [0; 4 * (MAX_ROUNDS + 1)],
+            rounds: 0,
+        }
+    }
+
+    /// Expands a 16, 24 or 32 byte key.
+    pub fn new(key: &[u8]) -> Option<AesKey> {
+        let words = match key.len() {
+            16 | 24 | 32 => key.len() / 4,
+            _ => return None,
+        };
+        let mut aes_key = AesKey::empty();
+        aes_key.rounds = words + 6;
+        {
+            let schedule = &mut aes_key.schedule;
+            for i in 0..words {
+                schedule[i] = get_word(&key[4 * i..]);
+            }
+            let mut rcon = 1u32;
+            for i in words..4 * (words + 7) {
+                let mut word = schedule[i - 1];
+                if i % words == 0 {
+                    word = sub_word(word.rotate_left(8)) ^ rcon << 24;
+                    rcon = if rcon & 0x80 != 0 { (rcon << 1) ^ 0x11B } else { rcon << 1 };
+                } else if words > 6 && i % words == 4 {
+                    word = sub_word(word);
+                }
+                schedule[i] = schedule[i - words] ^ word;
+            }
+        }
+        Some(aes_key)
+    }
+
+    fn load(&self, block: &[u8], round_key: usize) {
+        for i in 0..4 {
+            write(LDR, CA0 + i as u32, get_word(&block[4 * i..]));
+            write(XOR, CA0 + i as u32, self.schedule[4 * round_key + i]);
+        }
+    }
+
+    fn store(&self, block: &mut [u8]) {
+        for i in 0..4 {
+            put_word(&mut block[4 * i..], read(CA0 + i as u32));
+        }
+    }
+
+    pub fn encrypt_block(&self, block: &mut [u8]) {
+        self.load(block, 0);
+        for round in 1..self.rounds + 1 {
+            commands3(AESS + CA0, AESS + CA1, AESS + CA2);
+            commands2(AESS + CA3, AESR);
+            // Every round but the last mixes the columns as it adds the
+            // round key.
+            let op = if round < self.rounds { AESC } else { XOR };
+            for i in 0..4 {
+                write(op, CA0 + i as u32, self.schedule[4 * round + i]);
+            }
+        }
+        self.store(block);
+    }
+
+    pub fn decrypt_block(&self, block: &mut [u8]) {
+        self.load(block, self.rounds);
+        for round in (0..self.rounds).rev() {
+            commands3(AESIR, AESIS + CA3, AESIS + CA2);
+            commands2(AESIS + CA1, AESIS + CA0);
+            let op = if round > 0 { AESIC } else { XOR };
+            for i in 0..4 {
+                write(op, CA0 + i as u32, self.schedule[4 * round + i]);
+            }
+        }
+        self.store(block);
+    }
+}
+
+/// Encrypts or decrypts `data` in place, chaining from `iv`, which is left
+/// ready for the next part of the message. ECB ignores `iv`, and CTR uses
+/// it as a big-endian counter and works on any length, though a partial
+/// block must end the message.
+pub fn aes_crypt(key: &AesKey,
+                 mode: Mode,
+                 encrypting: bool,
+                 iv: &mut [u8; AES_BLOCK_SIZE],
+                 data: &mut [u8]) -> ReturnCode {
+    if key.rounds == 0 {
+        return ReturnCode::ERESERVE;
+    }
+    if mode != Mode::Ctr && data.len() % AES_BLOCK_SIZE != 0 {
+        return ReturnCode::ESIZE;
+    }
+    for block in data.chunks_mut(AES_BLOCK_SIZE) {
+        match mode {
+            Mode::Ecb => if encrypting {
+                key.encrypt_block(block);
+            } else {
+                key.decrypt_block(block);
+            },
+            Mode::Cbc => if encrypting {
+                xor(block, &iv[..]);
+                key.encrypt_block(block);
+                iv.copy_from_slice(block);
+            } else {
+                let mut next = [0; AES_BLOCK_SIZE];
+                next.copy_from_slice(block);
+                key.decrypt_block(block);
+                xor(block, &iv[..]);
+                *iv = next;
+            },
+            Mode::Ctr => {
+                let mut stream = *iv;
+                key.encrypt_block(&mut stream);
+                xor(block, &stream);
+                for byte in iv.iter_mut().rev() {
+                    *byte = byte.wrapping_add(1);
+                    if *byte != 0 {
+                        break;
+                    }
+                }
+            },
+        }
+    }
+    ReturnCode::SUCCESS
+}
+
+/// Hashes data given in any number of pieces.
+pub trait Digest {
+    /// The length of the hash, in bytes.
+    fn output_len(&self) -> usize;
+
+    fn update(&mut self, data: &[u8]);
+
+    /// Writes the hash of the data so far to `out`, which must be at least
+    /// `output_len` long, and starts over.
+    fn finish(&mut self, out: &mut [u8]);
+}
+
+/// Splits a message into 64-byte blocks, and pads the last with the
+/// message's length in bits, big-endian, as SHA-1 and SHA-256 both do.
+struct Blocks {
+    buffer: [u8; 64],
+    used: usize,
+    length: u64,
+}
+
+impl Blocks {
+    fn new() -> Blocks {
+        Blocks {
+            buffer: [0; 64],
+            used: 0,
+            length: 0,
+        }
+    }
+
+    fn update<F: FnMut(&[u8])>(&mut self, mut data: &[u8], mut compress: F) {
+        self.length += data.len() as u64;
+        if self.used > 0 {
+            let count = data.len().min(64 - self.used);
+            self.buffer[self.used..self.used + count].copy_from_slice(&data[..count]);
+            self.used += count;
+            data = &data[count..];
+            if self.used < 64 {
+                return;
+            }
+            compress(&self.buffer[..]);
+            self.used = 0;
+        }
+        while data.len() >= 64 {
+            compress(&data[..64]);
+            data = &data[64..];
+        }
+        self.buffer[..data.len()].copy_from_slice(data);
+        self.used = data.len();
+    }
+
+    fn finish<F: FnMut(&[u8])>(&mut self, mut compress: F) {
+        let bits = self.length * 8;
+        self.buffer[self.used] = 0x80;
+        for byte in self.buffer[self.used + 1..].iter_mut() {
+            *byte = 0;
+        }
+        if self.used >= 56 {
+            compress(&self.buffer[..]);
+            self.buffer = [0; 64];
+        }
+        for i in 0..8 {
+            self.buffer[56 + i] = (bits >> (56 - 8 * i)) as u8;
+        }
+        compress(&self.buffer[..]);
+        self.used = 0;
+        self.length = 0;
+    }
+}
+
+pub struct Sha256 {
+    state: [u32; 8],
+    blocks: Blocks,
+}
+
+impl Sha256 {
+    pub fn new() -> Sha256 {
+        Sha256 {
+            state: SHA256_INIT,
+            blocks: Blocks::new(),
+        }
+    }
+}
+
+fn sha256_compress(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    for i in 0..16 {
+        w[i] = get_word(&block[4 * i..]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+
+    for i in 0..8 {
+        write(LDR, CA0 + i as u32, state[i]);
+    }
+    for i in 0..64 {
+        // CAA = h + Σ1(e) + Ch(e, f, g) + K + W, which SHS2 adds to d and
+        // keeps in CA8, then plus Σ0(a) + Maj(a, b, c) for the new a.
+        commands3(MVRA + CA7, HASH + HF2T, HASH + HF2C);
+        write(ADR, CAA, SHA256_K[i]);
+        write(ADR, CAA, w[i]);
+        commands3(MVAR + CA8, HASH + HF2S, HASH + HF2M);
+        command(SHS2);
+    }
+    for i in 0..8 {
+        write(ADR, CA0 + i as u32, state[i]);
+        state[i] = read(CA0 + i as u32);
+    }
+}
+
+impl Digest for Sha256 {
+    fn output_len(&self) -> usize {
+        32
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        let Sha256 { ref mut state, ref mut blocks } = *self;
+        blocks.update(data, |block| sha256_compress(state, block));
+    }
+
+    fn finish(&mut self, out: &mut [u8]) {
+        {
+            let Sha256 { ref mut state, ref mut blocks } = *self;
+            blocks.finish(|block| sha256_compress(state, block));
+        }
+        for i in 0..8 {
+            put_word(&mut out[4 * i..], self.state[i]);
+        }
+        self.state = SHA256_INIT;
+    }
+}
+
+pub struct Sha1 {
+    state: [u32; 5],
+    blocks: Blocks,
+}
+
+impl Sha1 {
+    pub fn new() -> Sha1 {
+        Sha1 {
+            state: SHA1_INIT,
+            blocks: Blocks::new(),
+        }
+    }
+}
+
+fn sha1_compress(state: &mut [u32; 5], block: &[u8]) {
+    let mut w = [0u32; 80];
+    for i in 0..16 {
+        w[i] = get_word(&block[4 * i..]);
+    }
+    for i in 16..80 {
+        w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+
+    for i in 0..5 {
+        write(LDR, CA0 + i as u32, state[i]);
+    }
+    for i in 0..80 {
+        let (function, k) = match i {
+            0...19 => (HFC, 0x5A82_7999),
+            20...39 => (HFP, 0x6ED9_EBA1),
+            40...59 => (HFM, 0x8F1B_BCDC),
+            _ => (HFP, 0xCA62_C1D6),
+        };
+        // CAA = rotl(a, 5) + f(b, c, d) + e + K + W, which SHS makes the
+        // new a.
+        command(MVRA + CA0);
+        write(ROTL, CAA, 5);
+        commands2(HASH + function, ADRA + CA4);
+        write(ADR, CAA, k);
+        write(ADR, CAA, w[i]);
+        command(SHS);
+    }
+    for i in 0..5 {
+        write(ADR, CA0 + i as u32, state[i]);
+        state[i] = read(CA0 + i as u32);
+    }
+}
+
+impl Digest for Sha1 {
+    fn output_len(&self) -> usize {
+        20
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        let Sha1 { ref mut state, ref mut blocks } = *self;
+        blocks.update(data, |block| sha1_compress(state, block));
+    }
+
+    fn finish(&mut self, out: &mut [u8]) {
+        {
+            let Sha1 { ref mut state, ref mut blocks } = *self;
+            blocks.finish(|block| sha1_compress(state, block));
+        }
+        for i in 0..5 {
+            put_word(&mut out[4 * i..], self.state[i]);
+        }
+        self.state = SHA1_INIT;
+    }
+}
+
+pub static mut MMCAU: Mmcau<'static> = Mmcau::new();
+
+/// AES-128 for the `symmetric_encryption` HIL.
+pub struct Mmcau<'a> {
+    client: Cell<Option<&'a symmetric_encryption::Client<'a>>>,
+    key: Cell<AesKey>,
+    iv: Cell<[u8; AES_BLOCK_SIZE]>,
+    // Where the message in progress continues from.
+    chain: Cell<[u8; AES_BLOCK_SIZE]>,
+    mode: Cell<Mode>,
+    encrypting: Cell<bool>,
+    // The buffers of the finished `crypt`, waiting to go back to the client.
+    source: TakeCell<'a, [u8]>,
+    dest: TakeCell<'a, [u8]>,
+}
+
+impl<'a> Mmcau<'a> {
+    const fn new() -> Mmcau<'a> {
+        Mmcau {
+            client: Cell::new(None),
+            key: Cell::new(AesKey::empty()),
+            iv: Cell::new([0; AES_BLOCK_SIZE]),
+            chain: Cell::new([0; AES_BLOCK_SIZE]),
+            mode: Cell::new(Mode::Ctr),
+            encrypting: Cell::new(true),
+            source: TakeCell::empty(),
+            dest: TakeCell::empty(),
+        }
+    }
+
+    /// Returns the buffers of `crypt` to the client.
+    pub fn handle_deferred(&'a self) {
+        self.dest.take().map(|dest| {
+            let source = self.source.take();
+            self.client.get().map(move |client| client.crypt_done(source, dest));
+        });
+    }
+}
+
+impl<'a> AES128<'a> for Mmcau<'a> {
+    /// The MMCAU is always on.
+    fn enable(&self) {}
+
+    fn disable(&self) {}
+
+    fn set_client(&'a self, client: &'a symmetric_encryption::Client<'a>) {
+        self.client.set(Some(client));
+    }
+
+    fn set_key(&self, key: &[u8]) -> ReturnCode {
+        if key.len() != AES128_KEY_SIZE {
+            return ReturnCode::EINVAL;
+        }
+        AesKey::new(key).map_or(ReturnCode::EINVAL, |key| {
+            self.key.set(key);
+            ReturnCode::SUCCESS
+        })
+    }
+
+    fn set_iv(&self, iv: &[u8]) -> ReturnCode {
+        if iv.len() != AES128_BLOCK_SIZE {
+            return ReturnCode::EINVAL;
+        }
+        let mut block = [0; AES_BLOCK_SIZE];
+        block.copy_from_slice(iv);
+        self.iv.set(block);
+        self.chain.set(block);
+        ReturnCode::SUCCESS
+    }
+
+    fn start_message(&self) {
+        self.chain.set(self.iv.get());
+    }
+
+    /// The result is ready at once, but the client receives it from the
+    /// kernel loop, after this returns.
+    fn crypt(&'a self,
+             source: Option<&'a mut [u8]>,
+             dest: &'a mut [u8],
+             start_index: usize,
+             stop_index: usize)
+             -> Option<(ReturnCode, Option<&'a mut [u8]>, &'a mut [u8])> {
+        let source_len = source.as_ref().map(|source| source.len());
+        if start_index > stop_index || stop_index > dest.len() ||
+           (stop_index - start_index) % AES128_BLOCK_SIZE != 0 ||
+           source_len.map_or(false, |len| len != stop_index - start_index) {
+            return Some((ReturnCode::EINVAL, source, dest));
+        }
+        if self.dest.is_some() {
+            return Some((ReturnCode::EBUSY, source, dest));
+        }
+
+        let result = {
+            let data = &mut dest[start_index..stop_index];
+            if let Some(ref source) = source {
+                data.copy_from_slice(source);
+            }
+            let mut chain = self.chain.get();
+            let result = aes_crypt(&self.key.get(), self.mode.get(), self.encrypting.get(),
+                                   &mut chain, data);
+            self.chain.set(chain);
+            result
+        };
+        if result != ReturnCode::SUCCESS {
+            return Some((result, source, dest));
+        }
+        self.source.put(source);
+        self.dest.replace(dest);
+        deferred::defer(Task::Mmcau);
+        None
+    }
+}
+
+impl<'a> AES128Ctr for Mmcau<'a> {
+    fn set_mode_aes128ctr(&self, encrypting: bool) {
+        self.mode.set(Mode::Ctr);
+        self.encrypting.set(encrypting);
+    }
+}
+
+impl<'a> AES128CBC for Mmcau<'a> {
+    fn set_mode_aes128cbc(&self, encrypting: bool) {
+        self.mode.set(Mode::Cbc);
+        self.encrypting.set(encrypting);
+    }
+}
@@ -250,6 +250,13 @@ pub unsafe fn disable(signal: NvicIdx) {
     nvic.icer[interrupt / 32].set(1 << (interrupt & 31));
 }
 
+pub unsafe fn set_pending(signal: NvicIdx) {
+    let nvic: &mut Nvic = intrinsics::transmute(BASE_ADDRESS);
+    let interrupt = signal as usize;
+
+    nvic.ispr[interrupt / 32].set(1 << (interrupt & 31));
+}
+
 pub unsafe fn clear_pending(signal: NvicIdx) {
     let nvic: &mut Nvic = intrinsics::transmute(BASE_ADDRESS);
     let interrupt = signal as usize;
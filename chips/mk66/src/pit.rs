@@ -111,16 +111,47 @@ impl<'a> Time for Pit<'a> {
     }
 }
 
+/// A 64-bit monotonic clock that, unlike `Alarm::now`, does not wrap in
+/// practice.
+pub trait Uptime {
+    /// Ticks of the bus clock since the lifetime timer was started.
+    fn now_ticks64(&self) -> u64;
+
+    /// Microseconds since the lifetime timer was started.
+    fn uptime_us(&self) -> u64;
+}
+
+impl<'a> Uptime for Pit<'a> {
+    fn now_ticks64(&self) -> u64 {
+        // Reading LTMR64H latches the value of LTMR64L, so the two halves
+        // are consistent as long as they are read in this order.
+        let high = self.regs().ltmr64h.get() as u64;
+        let low = self.regs().ltmr64l.get() as u64;
+
+        // The chained timers count down from the maximum value.
+        !((high << 32) | low)
+    }
+
+    fn uptime_us(&self) -> u64 {
+        let ticks = self.now_ticks64();
+        let hz = PitFrequency::frequency() as u64;
+
+        // Split the conversion to avoid overflowing the multiplication.
+        (ticks / hz) * 1_000_000 + ((ticks % hz) * 1_000_000) / hz
+    }
+}
+
 impl<'a> Alarm for Pit<'a> {
     fn now(&self) -> u32 {
-        self.regs().ltmr64h.get();
-        ::core::u32::MAX - self.regs().ltmr64l.get()
+        self.now_ticks64() as u32
     }
 
     fn set_alarm(&self, ticks: u32) {
         Time::disable(self);
-        self.alarm.set(ticks.wrapping_sub(self.now()));
-        self.set_counter(self.alarm.get());
+        // `ticks` is an absolute time, but PIT2 counts down from its load
+        // value. Keep the absolute time so `get_alarm` can report it.
+        self.alarm.set(ticks);
+        self.set_counter(ticks.wrapping_sub(self.now()));
         self.enable_interrupt();
         self.enable();
     }
@@ -0,0 +1,146 @@
+use kernel::common::regs::{ReadWrite, ReadOnly};
+
+#[repr(C)]
+pub struct MessageBuffer {
+    pub cs: ReadWrite<u32, ControlStatus::Register>,
+    pub id: ReadWrite<u32, Identifier::Register>,
+    pub word0: ReadWrite<u32>,
+    pub word1: ReadWrite<u32>,
+}
+
+#[repr(C)]
+pub struct Registers {
+    pub mcr: ReadWrite<u32, ModuleConfiguration::Register>,
+    pub ctrl1: ReadWrite<u32, Control1::Register>,
+    pub timer: ReadWrite<u32>,
+    _reserved0: u32,
+    pub rxmgmask: ReadWrite<u32>,
+    pub rx14mask: ReadWrite<u32>,
+    pub rx15mask: ReadWrite<u32>,
+    pub ecr: ReadWrite<u32, ErrorCounter::Register>,
+    pub esr1: ReadWrite<u32, ErrorStatus1::Register>,
+    _reserved1: u32,
+    pub imask1: ReadWrite<u32>,
+    _reserved2: u32,
+    pub iflag1: ReadWrite<u32>,
+    pub ctrl2: ReadWrite<u32, Control2::Register>,
+    pub esr2: ReadOnly<u32>,
+    _reserved3: [u32; 2],
+    pub crcr: ReadOnly<u32>,
+    pub rxfgmask: ReadWrite<u32>,
+    pub rxfir: ReadOnly<u32>,
+    _reserved4: [u32; 12],
+    pub mb: [MessageBuffer; 16],
+    _reserved5: [u32; 448],
+    pub rximr: [ReadWrite<u32>; 16],
+}
+
+pub const CAN_BASE_ADDRS: [*mut Registers; 2] = [0x4002_4000 as *mut Registers,
+                                                 0x400A_4000 as *mut Registers];
+
+register_bitfields![u32,
+    ModuleConfiguration [
+        MDIS OFFSET(31) NUMBITS(1) [],
+        FRZ OFFSET(30) NUMBITS(1) [],
+        RFEN OFFSET(29) NUMBITS(1) [],
+        HALT OFFSET(28) NUMBITS(1) [],
+        NOTRDY OFFSET(27) NUMBITS(1) [],
+        WAKMSK OFFSET(26) NUMBITS(1) [],
+        SOFTRST OFFSET(25) NUMBITS(1) [],
+        FRZACK OFFSET(24) NUMBITS(1) [],
+        SUPV OFFSET(23) NUMBITS(1) [],
+        SLFWAK OFFSET(22) NUMBITS(1) [],
+        WRNEN OFFSET(21) NUMBITS(1) [],
+        LPMACK OFFSET(20) NUMBITS(1) [],
+        WAKSRC OFFSET(19) NUMBITS(1) [],
+        SRXDIS OFFSET(17) NUMBITS(1) [],
+        IRMQ OFFSET(16) NUMBITS(1) [],
+        LPRIOEN OFFSET(13) NUMBITS(1) [],
+        AEN OFFSET(12) NUMBITS(1) [],
+        IDAM OFFSET(8) NUMBITS(2) [
+            OneFullId = 0,
+            TwoPartialIds = 1,
+            FourPartialIds = 2,
+            AllRejected = 3
+        ],
+        MAXMB OFFSET(0) NUMBITS(7) []
+    ],
+    Control1 [
+        PRESDIV OFFSET(24) NUMBITS(8) [],
+        RJW OFFSET(22) NUMBITS(2) [],
+        PSEG1 OFFSET(19) NUMBITS(3) [],
+        PSEG2 OFFSET(16) NUMBITS(3) [],
+        BOFFMSK OFFSET(15) NUMBITS(1) [],
+        ERRMSK OFFSET(14) NUMBITS(1) [],
+        CLKSRC OFFSET(13) NUMBITS(1) [
+            Oscillator = 0,
+            PeripheralClock = 1
+        ],
+        LPB OFFSET(12) NUMBITS(1) [],
+        TWRNMSK OFFSET(11) NUMBITS(1) [],
+        RWRNMSK OFFSET(10) NUMBITS(1) [],
+        SMP OFFSET(7) NUMBITS(1) [],
+        BOFFREC OFFSET(6) NUMBITS(1) [],
+        TSYN OFFSET(5) NUMBITS(1) [],
+        LBUF OFFSET(4) NUMBITS(1) [],
+        LOM OFFSET(3) NUMBITS(1) [],
+        PROPSEG OFFSET(0) NUMBITS(3) []
+    ],
+    ErrorCounter [
+        RXERRCNT OFFSET(8) NUMBITS(8) [],
+        TXERRCNT OFFSET(0) NUMBITS(8) []
+    ],
+    ErrorStatus1 [
+        SYNCH OFFSET(18) NUMBITS(1) [],
+        TWRNINT OFFSET(17) NUMBITS(1) [],
+        RWRNINT OFFSET(16) NUMBITS(1) [],
+        BIT1ERR OFFSET(15) NUMBITS(1) [],
+        BIT0ERR OFFSET(14) NUMBITS(1) [],
+        ACKERR OFFSET(13) NUMBITS(1) [],
+        CRCERR OFFSET(12) NUMBITS(1) [],
+        FRMERR OFFSET(11) NUMBITS(1) [],
+        STFERR OFFSET(10) NUMBITS(1) [],
+        TXWRN OFFSET(9) NUMBITS(1) [],
+        RXWRN OFFSET(8) NUMBITS(1) [],
+        IDLE OFFSET(7) NUMBITS(1) [],
+        TX OFFSET(6) NUMBITS(1) [],
+        FLTCONF OFFSET(4) NUMBITS(2) [
+            ErrorActive = 0,
+            ErrorPassive = 1,
+            BusOff = 2
+        ],
+        RX OFFSET(3) NUMBITS(1) [],
+        BOFFINT OFFSET(2) NUMBITS(1) [],
+        ERRINT OFFSET(1) NUMBITS(1) [],
+        WAKINT OFFSET(0) NUMBITS(1) []
+    ],
+    Control2 [
+        WRMFRZ OFFSET(28) NUMBITS(1) [],
+        RFFN OFFSET(24) NUMBITS(4) [],
+        TASD OFFSET(19) NUMBITS(5) [],
+        MRP OFFSET(18) NUMBITS(1) [],
+        RRS OFFSET(17) NUMBITS(1) [],
+        EACEN OFFSET(16) NUMBITS(1) []
+    ],
+    ControlStatus [
+        CODE OFFSET(24) NUMBITS(4) [
+            RxInactive = 0b0000,
+            RxFull = 0b0010,
+            RxEmpty = 0b0100,
+            RxOverrun = 0b0110,
+            TxInactive = 0b1000,
+            TxAbort = 0b1001,
+            TxData = 0b1100
+        ],
+        SRR OFFSET(22) NUMBITS(1) [],
+        IDE OFFSET(21) NUMBITS(1) [],
+        RTR OFFSET(20) NUMBITS(1) [],
+        DLC OFFSET(16) NUMBITS(4) [],
+        TIMESTAMP OFFSET(0) NUMBITS(16) []
+    ],
+    Identifier [
+        PRIO OFFSET(29) NUMBITS(3) [],
+        STD OFFSET(18) NUMBITS(11) [],
+        EXT OFFSET(0) NUMBITS(29) []
+    ]
+];
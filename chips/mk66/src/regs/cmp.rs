@@ -0,0 +1,54 @@
+use kernel::common::regs::ReadWrite;
+
+#[repr(C)]
+pub struct Registers {
+    pub cr0: ReadWrite<u8, Control0::Register>,
+    pub cr1: ReadWrite<u8, Control1::Register>,
+    pub fpr: ReadWrite<u8>,
+    pub scr: ReadWrite<u8, StatusControl::Register>,
+    pub daccr: ReadWrite<u8, DacControl::Register>,
+    pub muxcr: ReadWrite<u8, MuxControl::Register>,
+}
+
+pub const CMP_BASE_ADDRS: [*mut Registers; 4] = [0x4007_3000 as *mut Registers,
+                                                 0x4007_3008 as *mut Registers,
+                                                 0x4007_3010 as *mut Registers,
+                                                 0x4007_3018 as *mut Registers];
+
+register_bitfields![u8,
+    Control0 [
+        FILTER_CNT OFFSET(4) NUMBITS(3) [],
+        HYSTCTR OFFSET(0) NUMBITS(2) []
+    ],
+    Control1 [
+        SE OFFSET(7) NUMBITS(1) [],
+        WE OFFSET(6) NUMBITS(1) [],
+        TRIGM OFFSET(5) NUMBITS(1) [],
+        PMODE OFFSET(4) NUMBITS(1) [],
+        INV OFFSET(3) NUMBITS(1) [],
+        COS OFFSET(2) NUMBITS(1) [],
+        OPE OFFSET(1) NUMBITS(1) [],
+        EN OFFSET(0) NUMBITS(1) []
+    ],
+    StatusControl [
+        DMAEN OFFSET(6) NUMBITS(1) [],
+        IER OFFSET(4) NUMBITS(1) [],
+        IEF OFFSET(3) NUMBITS(1) [],
+        CFR OFFSET(2) NUMBITS(1) [],
+        CFF OFFSET(1) NUMBITS(1) [],
+        COUT OFFSET(0) NUMBITS(1) []
+    ],
+    DacControl [
+        DACEN OFFSET(7) NUMBITS(1) [],
+        VRSEL OFFSET(6) NUMBITS(1) [
+            Vin1 = 0,
+            Vin2 = 1
+        ],
+        VOSEL OFFSET(0) NUMBITS(6) []
+    ],
+    MuxControl [
+        PSTM OFFSET(7) NUMBITS(1) [],
+        PSEL OFFSET(3) NUMBITS(3) [],
+        MSEL OFFSET(0) NUMBITS(3) []
+    ]
+];
@@ -0,0 +1,39 @@
+use kernel::common::regs::ReadWrite;
+
+#[repr(C)]
+pub struct Registers {
+    pub cgh1: ReadWrite<u8>,
+    pub cgl1: ReadWrite<u8>,
+    pub cgh2: ReadWrite<u8>,
+    pub cgl2: ReadWrite<u8>,
+    pub oc: ReadWrite<u8, OutputControl::Register>,
+    pub msc: ReadWrite<u8, ModulatorStatus::Register>,
+    pub cmd1: ReadWrite<u8>,
+    pub cmd2: ReadWrite<u8>,
+    pub cmd3: ReadWrite<u8>,
+    pub cmd4: ReadWrite<u8>,
+    pub pps: ReadWrite<u8, PrimaryPrescaler::Register>,
+    pub dma: ReadWrite<u8>,
+}
+
+pub const CMT_BASE: *mut Registers = 0x4006_2000 as *mut Registers;
+
+register_bitfields![u8,
+    OutputControl [
+        IROL OFFSET(7) NUMBITS(1) [],
+        CMTPOL OFFSET(6) NUMBITS(1) [],
+        IROPEN OFFSET(5) NUMBITS(1) []
+    ],
+    ModulatorStatus [
+        EOCF OFFSET(7) NUMBITS(1) [],
+        CMTDIV OFFSET(5) NUMBITS(2) [],
+        EXSPC OFFSET(4) NUMBITS(1) [],
+        BASE OFFSET(3) NUMBITS(1) [],
+        FSK OFFSET(2) NUMBITS(1) [],
+        EOCIE OFFSET(1) NUMBITS(1) [],
+        MCGEN OFFSET(0) NUMBITS(1) []
+    ],
+    PrimaryPrescaler [
+        PPSDIV OFFSET(0) NUMBITS(4) []
+    ]
+];
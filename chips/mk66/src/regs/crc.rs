@@ -0,0 +1,35 @@
+use kernel::common::regs::ReadWrite;
+
+#[repr(C)]
+pub struct Registers {
+    /// The seed while `Control::WAS` is set, else the data and the result.
+    /// Byte writes to its low byte feed the CRC one byte at a time.
+    pub data: ReadWrite<u32>,
+    pub gpoly: ReadWrite<u32>,
+    pub ctrl: ReadWrite<u32, Control::Register>,
+}
+
+pub const CRC_BASE: *mut Registers = 0x4003_2000 as *mut Registers;
+
+register_bitfields![u32,
+    Control [
+        TOT OFFSET(30) NUMBITS(2) [
+            None = 0,
+            Bits = 1,
+            BitsAndBytes = 2,
+            Bytes = 3
+        ],
+        TOTR OFFSET(28) NUMBITS(2) [
+            None = 0,
+            Bits = 1,
+            BitsAndBytes = 2,
+            Bytes = 3
+        ],
+        FXOR OFFSET(26) NUMBITS(1) [],
+        WAS OFFSET(25) NUMBITS(1) [],
+        TCRC OFFSET(24) NUMBITS(1) [
+            Bits16 = 0,
+            Bits32 = 1
+        ]
+    ]
+];
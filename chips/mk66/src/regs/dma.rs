@@ -0,0 +1,97 @@
+use kernel::common::regs::{ReadWrite, ReadOnly};
+
+#[repr(C)]
+pub struct Registers {
+    pub cr: ReadWrite<u32, Control::Register>,
+    pub es: ReadOnly<u32>,
+    _reserved0: u32,
+    pub erq: ReadWrite<u32>,
+    _reserved1: u32,
+    pub eei: ReadWrite<u32>,
+    pub ceei: ReadWrite<u8>,
+    pub seei: ReadWrite<u8>,
+    pub cerq: ReadWrite<u8>,
+    pub serq: ReadWrite<u8>,
+    pub cdne: ReadWrite<u8>,
+    pub ssrt: ReadWrite<u8>,
+    pub cerr: ReadWrite<u8>,
+    pub cint: ReadWrite<u8>,
+    _reserved2: u32,
+    pub int: ReadWrite<u32>,
+    _reserved3: u32,
+    pub err: ReadWrite<u32>,
+    _reserved4: u32,
+    pub hrs: ReadOnly<u32>,
+}
+
+/// A channel's transfer control descriptor.
+#[repr(C)]
+pub struct TransferControl {
+    pub saddr: ReadWrite<u32>,
+    pub soff: ReadWrite<u16>,
+    pub attr: ReadWrite<u16, Attributes::Register>,
+    pub nbytes: ReadWrite<u32>,
+    pub slast: ReadWrite<u32>,
+    pub daddr: ReadWrite<u32>,
+    pub doff: ReadWrite<u16>,
+    pub citer: ReadWrite<u16>,
+    pub dlast_sga: ReadWrite<u32>,
+    pub csr: ReadWrite<u16, ControlStatus::Register>,
+    pub biter: ReadWrite<u16>,
+}
+
+pub const DMA_BASE: *mut Registers = 0x4000_8000 as *mut Registers;
+pub const TCD_BASE: *mut [TransferControl; 32] = 0x4000_9000 as *mut [TransferControl; 32];
+pub const DMAMUX_BASE: *mut [ReadWrite<u8, ChannelConfig::Register>; 32] =
+    0x4002_1000 as *mut [ReadWrite<u8, ChannelConfig::Register>; 32];
+
+register_bitfields![u32,
+    Control [
+        CX OFFSET(17) NUMBITS(1) [],
+        ECX OFFSET(16) NUMBITS(1) [],
+        EMLM OFFSET(7) NUMBITS(1) [],
+        CLM OFFSET(6) NUMBITS(1) [],
+        HALT OFFSET(5) NUMBITS(1) [],
+        HOE OFFSET(4) NUMBITS(1) [],
+        ERGA OFFSET(3) NUMBITS(1) [],
+        ERCA OFFSET(2) NUMBITS(1) [],
+        EDBG OFFSET(1) NUMBITS(1) []
+    ]
+];
+
+register_bitfields![u16,
+    Attributes [
+        SMOD OFFSET(11) NUMBITS(5) [],
+        SSIZE OFFSET(8) NUMBITS(3) [
+            Byte = 0,
+            HalfWord = 1,
+            Word = 2
+        ],
+        DMOD OFFSET(3) NUMBITS(5) [],
+        DSIZE OFFSET(0) NUMBITS(3) [
+            Byte = 0,
+            HalfWord = 1,
+            Word = 2
+        ]
+    ],
+    ControlStatus [
+        BWC OFFSET(14) NUMBITS(2) [],
+        MAJORLINKCH OFFSET(8) NUMBITS(5) [],
+        DONE OFFSET(7) NUMBITS(1) [],
+        ACTIVE OFFSET(6) NUMBITS(1) [],
+        MAJORELINK OFFSET(5) NUMBITS(1) [],
+        ESG OFFSET(4) NUMBITS(1) [],
+        DREQ OFFSET(3) NUMBITS(1) [],
+        INTHALF OFFSET(2) NUMBITS(1) [],
+        INTMAJOR OFFSET(1) NUMBITS(1) [],
+        START OFFSET(0) NUMBITS(1) []
+    ]
+];
+
+register_bitfields![u8,
+    ChannelConfig [
+        ENBL OFFSET(7) NUMBITS(1) [],
+        TRIG OFFSET(6) NUMBITS(1) [],
+        SOURCE OFFSET(0) NUMBITS(6) []
+    ]
+];
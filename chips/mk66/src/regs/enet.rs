@@ -0,0 +1,163 @@
+use kernel::common::regs::{ReadWrite, ReadOnly};
+
+#[repr(C)]
+pub struct TimerChannel {
+    pub tcsr: ReadWrite<u32>,
+    pub tccr: ReadWrite<u32>,
+}
+
+#[repr(C)]
+pub struct Registers {
+    _reserved0: u32,
+    pub eir: ReadWrite<u32, Interrupt::Register>,
+    pub eimr: ReadWrite<u32, Interrupt::Register>,
+    _reserved1: u32,
+    pub rdar: ReadWrite<u32, DescriptorActive::Register>,
+    pub tdar: ReadWrite<u32, DescriptorActive::Register>,
+    _reserved2: [u32; 3],
+    pub ecr: ReadWrite<u32, EthernetControl::Register>,
+    _reserved3: [u32; 6],
+    pub mmfr: ReadWrite<u32, MiiFrame::Register>,
+    pub mscr: ReadWrite<u32, MiiSpeedControl::Register>,
+    _reserved4: [u32; 7],
+    pub mibc: ReadWrite<u32>,
+    _reserved5: [u32; 7],
+    pub rcr: ReadWrite<u32, ReceiveControl::Register>,
+    _reserved6: [u32; 15],
+    pub tcr: ReadWrite<u32, TransmitControl::Register>,
+    _reserved7: [u32; 7],
+    pub palr: ReadWrite<u32>,
+    pub paur: ReadWrite<u32>,
+    pub opd: ReadWrite<u32>,
+    _reserved8: [u32; 10],
+    pub iaur: ReadWrite<u32>,
+    pub ialr: ReadWrite<u32>,
+    pub gaur: ReadWrite<u32>,
+    pub galr: ReadWrite<u32>,
+    _reserved9: [u32; 7],
+    pub tfwr: ReadWrite<u32, TransmitFifoWatermark::Register>,
+    _reserved10: [u32; 14],
+    pub rdsr: ReadWrite<u32>,
+    pub tdsr: ReadWrite<u32>,
+    pub mrbr: ReadWrite<u32>,
+    _reserved11: u32,
+    pub rsfl: ReadWrite<u32>,
+    pub rsem: ReadWrite<u32>,
+    pub raem: ReadWrite<u32>,
+    pub rafl: ReadWrite<u32>,
+    pub tsem: ReadWrite<u32>,
+    pub taem: ReadWrite<u32>,
+    pub tafl: ReadWrite<u32>,
+    pub tipg: ReadWrite<u32>,
+    pub ftrl: ReadWrite<u32>,
+    _reserved12: [u32; 3],
+    pub tacc: ReadWrite<u32>,
+    pub racc: ReadWrite<u32>,
+    _reserved13: [u32; 142],
+    pub atcr: ReadWrite<u32, TimerControl::Register>,
+    pub atvr: ReadWrite<u32>,
+    pub atoff: ReadWrite<u32>,
+    pub atper: ReadWrite<u32>,
+    pub atcor: ReadWrite<u32>,
+    pub atinc: ReadWrite<u32, TimerIncrement::Register>,
+    pub atstmp: ReadOnly<u32>,
+    _reserved14: [u32; 122],
+    pub tgsr: ReadWrite<u32>,
+    pub channels: [TimerChannel; 4],
+}
+
+pub const ENET_BASE: *mut Registers = 0x400C_0000 as *mut Registers;
+
+register_bitfields![u32,
+    Interrupt [
+        BABR OFFSET(30) NUMBITS(1) [],
+        BABT OFFSET(29) NUMBITS(1) [],
+        GRA OFFSET(28) NUMBITS(1) [],
+        TXF OFFSET(27) NUMBITS(1) [],
+        TXB OFFSET(26) NUMBITS(1) [],
+        RXF OFFSET(25) NUMBITS(1) [],
+        RXB OFFSET(24) NUMBITS(1) [],
+        MII OFFSET(23) NUMBITS(1) [],
+        EBERR OFFSET(22) NUMBITS(1) [],
+        LC OFFSET(21) NUMBITS(1) [],
+        RL OFFSET(20) NUMBITS(1) [],
+        UN OFFSET(19) NUMBITS(1) [],
+        PLR OFFSET(18) NUMBITS(1) [],
+        WAKEUP OFFSET(17) NUMBITS(1) [],
+        TS_AVAIL OFFSET(16) NUMBITS(1) [],
+        TS_TIMER OFFSET(15) NUMBITS(1) []
+    ],
+    DescriptorActive [
+        ACTIVE OFFSET(24) NUMBITS(1) []
+    ],
+    EthernetControl [
+        DBSWP OFFSET(8) NUMBITS(1) [],
+        STOPEN OFFSET(7) NUMBITS(1) [],
+        DBGEN OFFSET(6) NUMBITS(1) [],
+        EN1588 OFFSET(4) NUMBITS(1) [],
+        SLEEP OFFSET(3) NUMBITS(1) [],
+        MAGICEN OFFSET(2) NUMBITS(1) [],
+        ETHEREN OFFSET(1) NUMBITS(1) [],
+        RESET OFFSET(0) NUMBITS(1) []
+    ],
+    MiiFrame [
+        ST OFFSET(30) NUMBITS(2) [],
+        OP OFFSET(28) NUMBITS(2) [
+            Write = 1,
+            Read = 2
+        ],
+        PA OFFSET(23) NUMBITS(5) [],
+        RA OFFSET(18) NUMBITS(5) [],
+        TA OFFSET(16) NUMBITS(2) [],
+        DATA OFFSET(0) NUMBITS(16) []
+    ],
+    MiiSpeedControl [
+        HOLDTIME OFFSET(8) NUMBITS(3) [],
+        DIS_PRE OFFSET(7) NUMBITS(1) [],
+        MII_SPEED OFFSET(1) NUMBITS(6) []
+    ],
+    ReceiveControl [
+        GRS OFFSET(31) NUMBITS(1) [],
+        NLC OFFSET(30) NUMBITS(1) [],
+        MAX_FL OFFSET(16) NUMBITS(14) [],
+        CFEN OFFSET(15) NUMBITS(1) [],
+        CRCFWD OFFSET(14) NUMBITS(1) [],
+        PAUFWD OFFSET(13) NUMBITS(1) [],
+        PADEN OFFSET(12) NUMBITS(1) [],
+        RMII_10T OFFSET(9) NUMBITS(1) [],
+        RMII_MODE OFFSET(8) NUMBITS(1) [],
+        FCE OFFSET(5) NUMBITS(1) [],
+        BC_REJ OFFSET(4) NUMBITS(1) [],
+        PROM OFFSET(3) NUMBITS(1) [],
+        MII_MODE OFFSET(2) NUMBITS(1) [],
+        DRT OFFSET(1) NUMBITS(1) [],
+        LOOP OFFSET(0) NUMBITS(1) []
+    ],
+    TransmitControl [
+        CRCFWD OFFSET(9) NUMBITS(1) [],
+        ADDINS OFFSET(8) NUMBITS(1) [],
+        ADDSEL OFFSET(5) NUMBITS(3) [],
+        RFC_PAUSE OFFSET(4) NUMBITS(1) [],
+        TFC_PAUSE OFFSET(3) NUMBITS(1) [],
+        FDEN OFFSET(2) NUMBITS(1) [],
+        GTS OFFSET(0) NUMBITS(1) []
+    ],
+    TransmitFifoWatermark [
+        STRFWD OFFSET(8) NUMBITS(1) [],
+        TFWR OFFSET(0) NUMBITS(6) []
+    ],
+    TimerControl [
+        SLAVE OFFSET(13) NUMBITS(1) [],
+        CAPTURE OFFSET(11) NUMBITS(1) [],
+        RESTART OFFSET(9) NUMBITS(1) [],
+        PINPER OFFSET(7) NUMBITS(1) [],
+        PEREN OFFSET(4) NUMBITS(1) [],
+        OFFRST OFFSET(3) NUMBITS(1) [],
+        OFFEN OFFSET(2) NUMBITS(1) [],
+        EN OFFSET(0) NUMBITS(1) []
+    ],
+    TimerIncrement [
+        INC_CORR OFFSET(8) NUMBITS(7) [],
+        INC OFFSET(0) NUMBITS(7) []
+    ]
+];
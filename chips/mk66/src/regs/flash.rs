@@ -0,0 +1,45 @@
+use kernel::common::regs::{ReadWrite, ReadOnly};
+
+#[repr(C)]
+pub struct Registers {
+    pub fstat: ReadWrite<u8, FlashStatus::Register>,
+    pub fcnfg: ReadWrite<u8, FlashConfig::Register>,
+    pub fsec: ReadOnly<u8>,
+    pub fopt: ReadOnly<u8>,
+    // The command registers are big-endian within each word: offset 0 is
+    // FCCOB3, offset 3 is FCCOB0, offset 4 is FCCOB7, and so on.
+    pub fccob: [ReadWrite<u8>; 12],
+    pub fprot: [ReadWrite<u8>; 4],
+    _reserved0: [u8; 2],
+    pub feprot: ReadWrite<u8>,
+    pub fdprot: ReadWrite<u8>,
+}
+
+register_bitfields![u8,
+    FlashStatus [
+        CCIF OFFSET(7) NUMBITS(1) [],
+        RDCOLERR OFFSET(6) NUMBITS(1) [],
+        ACCERR OFFSET(5) NUMBITS(1) [],
+        FPVIOL OFFSET(4) NUMBITS(1) [],
+        MGSTAT0 OFFSET(0) NUMBITS(1) []
+    ],
+    FlashConfig [
+        CCIE OFFSET(7) NUMBITS(1) [],
+        RDCOLLIE OFFSET(6) NUMBITS(1) [],
+        ERSAREQ OFFSET(5) NUMBITS(1) [],
+        ERSSUSP OFFSET(4) NUMBITS(1) [],
+        SWAP OFFSET(3) NUMBITS(1) [],
+        PFLSH OFFSET(2) NUMBITS(1) [],
+        RAMRDY OFFSET(1) NUMBITS(1) [],
+        EEERDY OFFSET(0) NUMBITS(1) []
+    ]
+];
+
+pub const FTFE: *mut Registers = 0x4002_0000 as *mut Registers;
+
+/// Flash Memory Controller cache control register.
+pub const FMC_PFB01CR: *mut u32 = 0x4001_F004 as *mut u32;
+
+/// Invalidates every way of the FMC cache and the prefetch speculation
+/// buffer.
+pub const FMC_CACHE_INVALIDATE: u32 = 0xF << 20 | 1 << 19;
@@ -0,0 +1,88 @@
+use kernel::common::regs::ReadWrite;
+
+#[repr(C)]
+pub struct Registers {
+    pub sc: ReadWrite<u32, StatusAndControl::Register>,
+    pub cnt: ReadWrite<u32>,
+    pub modulo: ReadWrite<u32>,
+    pub channels: [ChannelRegisters; 8],
+    pub cntin: ReadWrite<u32>,
+    pub status: ReadWrite<u32>,
+    pub mode: ReadWrite<u32, FeaturesMode::Register>,
+    pub sync: ReadWrite<u32>,
+    pub outinit: ReadWrite<u32>,
+    pub outmask: ReadWrite<u32>,
+    pub combine: ReadWrite<u32>,
+    pub deadtime: ReadWrite<u32>,
+    pub exttrig: ReadWrite<u32>,
+    pub pol: ReadWrite<u32>,
+    pub fms: ReadWrite<u32, FaultModeStatus::Register>,
+    pub filter: ReadWrite<u32, InputCaptureFilter::Register>,
+    pub fltctrl: ReadWrite<u32>,
+    pub qdctrl: ReadWrite<u32, QuadratureDecoderControl::Register>,
+    pub conf: ReadWrite<u32>,
+    pub fltpol: ReadWrite<u32>,
+    pub synconf: ReadWrite<u32>,
+    pub invctrl: ReadWrite<u32>,
+    pub swoctrl: ReadWrite<u32>,
+    pub pwmload: ReadWrite<u32>,
+}
+
+#[repr(C)]
+pub struct ChannelRegisters {
+    pub csc: ReadWrite<u32>,
+    pub cv: ReadWrite<u32>,
+}
+
+register_bitfields![u32,
+    StatusAndControl [
+        TOF OFFSET(7) NUMBITS(1) [],
+        TOIE OFFSET(6) NUMBITS(1) [],
+        CPWMS OFFSET(5) NUMBITS(1) [],
+        CLKS OFFSET(3) NUMBITS(2) [
+            NoClock = 0,
+            SystemClock = 1,
+            FixedFrequencyClock = 2,
+            ExternalClock = 3
+        ],
+        PS OFFSET(0) NUMBITS(3) []
+    ],
+    FeaturesMode [
+        FAULTIE OFFSET(7) NUMBITS(1) [],
+        FAULTM OFFSET(5) NUMBITS(2) [],
+        CAPTEST OFFSET(4) NUMBITS(1) [],
+        PWMSYNC OFFSET(3) NUMBITS(1) [],
+        WPDIS OFFSET(2) NUMBITS(1) [],
+        INIT OFFSET(1) NUMBITS(1) [],
+        FTMEN OFFSET(0) NUMBITS(1) []
+    ],
+    FaultModeStatus [
+        FAULTF OFFSET(7) NUMBITS(1) [],
+        WPEN OFFSET(6) NUMBITS(1) [],
+        FAULTIN OFFSET(5) NUMBITS(1) []
+    ],
+    InputCaptureFilter [
+        CH3FVAL OFFSET(12) NUMBITS(4) [],
+        CH2FVAL OFFSET(8) NUMBITS(4) [],
+        CH1FVAL OFFSET(4) NUMBITS(4) [],
+        CH0FVAL OFFSET(0) NUMBITS(4) []
+    ],
+    QuadratureDecoderControl [
+        PHAFLTREN OFFSET(7) NUMBITS(1) [],
+        PHBFLTREN OFFSET(6) NUMBITS(1) [],
+        PHAPOL OFFSET(5) NUMBITS(1) [],
+        PHBPOL OFFSET(4) NUMBITS(1) [],
+        QUADMODE OFFSET(3) NUMBITS(1) [
+            PhaseAB = 0,
+            CountDirection = 1
+        ],
+        QUADIR OFFSET(2) NUMBITS(1) [],
+        TOFDIR OFFSET(1) NUMBITS(1) [],
+        QUADEN OFFSET(0) NUMBITS(1) []
+    ]
+];
+
+pub const FTM_ADDRS: [*mut Registers; 4] = [0x4003_8000 as *mut Registers,
+                                            0x4003_9000 as *mut Registers,
+                                            0x400B_8000 as *mut Registers,
+                                            0x400B_9000 as *mut Registers];
@@ -0,0 +1,13 @@
+use kernel::common::regs::{ReadWrite, ReadOnly};
+
+#[repr(C)]
+pub struct Registers {
+    pub pe: [ReadWrite<u8>; 8],
+    pub me: ReadWrite<u8>,
+    pub pf: [ReadWrite<u8>; 4],
+    pub mf5: ReadOnly<u8>,
+    pub filt1: ReadWrite<u8>,
+    pub filt2: ReadWrite<u8>,
+}
+
+pub const LLWU: *mut Registers = 0x4007_C000 as *mut Registers;
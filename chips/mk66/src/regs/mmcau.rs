@@ -0,0 +1,65 @@
+//! The MMCAU has no registers of its own in the usual sense: commands are
+//! written to the direct space, and each indirect address combines a
+//! register with the data written, or reads it, as `(command + register)
+//! << 2` from `MMCAU_INDIRECT` [K66 Reference Manual, Section 33.3].
+
+/// Commands written here run in order; a word holds up to three.
+pub const MMCAU_DIRECT: *mut u32 = 0xE008_1000 as *mut u32;
+pub const MMCAU_INDIRECT: usize = 0xE008_1800;
+
+/// Marks the first, second and third commands of a direct word as valid.
+pub const ONE_COMMAND: u32 = 0x8000_0000;
+pub const TWO_COMMANDS: u32 = 0x8010_0000;
+pub const THREE_COMMANDS: u32 = 0x8010_0200;
+
+// Commands, to which a register or hash function is added.
+pub const LDR: u32 = 0x010;
+pub const STR: u32 = 0x020;
+pub const ADR: u32 = 0x030;
+pub const RADR: u32 = 0x040;
+pub const ADRA: u32 = 0x050;
+pub const XOR: u32 = 0x060;
+pub const ROTL: u32 = 0x070;
+pub const MVRA: u32 = 0x080;
+pub const MVAR: u32 = 0x090;
+pub const AESS: u32 = 0x0A0;
+pub const AESIS: u32 = 0x0B0;
+pub const AESC: u32 = 0x0C0;
+pub const AESIC: u32 = 0x0D0;
+pub const AESR: u32 = 0x0E0;
+pub const AESIR: u32 = 0x0F0;
+pub const DESR: u32 = 0x100;
+pub const DESK: u32 = 0x110;
+pub const HASH: u32 = 0x120;
+pub const SHS: u32 = 0x130;
+pub const MDS: u32 = 0x140;
+pub const SHS2: u32 = 0x150;
+
+// Registers.
+pub const CASR: u32 = 0;
+pub const CAA: u32 = 1;
+pub const CA0: u32 = 2;
+pub const CA1: u32 = 3;
+pub const CA2: u32 = 4;
+pub const CA3: u32 = 5;
+pub const CA4: u32 = 6;
+pub const CA5: u32 = 7;
+pub const CA6: u32 = 8;
+pub const CA7: u32 = 9;
+pub const CA8: u32 = 10;
+
+// Hash functions, added to CAA by `HASH`.
+/// SHA-1 Ch(CA1, CA2, CA3).
+pub const HFC: u32 = 4;
+/// SHA-1 Parity(CA1, CA2, CA3).
+pub const HFP: u32 = 2;
+/// SHA-1 Maj(CA1, CA2, CA3).
+pub const HFM: u32 = 5;
+/// SHA-256 Ch(CA4, CA5, CA6).
+pub const HF2C: u32 = 6;
+/// SHA-256 Maj(CA0, CA1, CA2).
+pub const HF2M: u32 = 7;
+/// SHA-256 Σ0(CA0).
+pub const HF2S: u32 = 8;
+/// SHA-256 Σ1(CA4).
+pub const HF2T: u32 = 9;
@@ -5,3 +5,21 @@ pub mod uart;
 pub mod wdog;
 pub mod pit;
 pub mod spi;
+pub mod ftm;
+pub mod lptmr;
+pub mod llwu;
+pub mod rtc;
+pub mod flash;
+pub mod sdhc;
+pub mod usb;
+pub mod usbhs;
+pub mod can;
+pub mod enet;
+pub mod dma;
+pub mod sai;
+pub mod tsi;
+pub mod cmp;
+pub mod cmt;
+pub mod crc;
+pub mod mmcau;
+pub mod sysmpu;
@@ -0,0 +1,50 @@
+use kernel::common::regs::ReadWrite;
+
+#[repr(C)]
+pub struct Registers {
+    pub tsr: ReadWrite<u32>,
+    pub tpr: ReadWrite<u32>,
+    pub tar: ReadWrite<u32>,
+    pub tcr: ReadWrite<u32, TimeCompensation::Register>,
+    pub cr: ReadWrite<u32, Control::Register>,
+    pub sr: ReadWrite<u32, Status::Register>,
+    pub lr: ReadWrite<u32>,
+    pub ier: ReadWrite<u32, InterruptEnable::Register>,
+}
+
+register_bitfields![u32,
+    TimeCompensation [
+        CIC OFFSET(24) NUMBITS(8) [],
+        TCV OFFSET(16) NUMBITS(8) [],
+        CIR OFFSET(8) NUMBITS(8) [],
+        TCR OFFSET(0) NUMBITS(8) []
+    ],
+    Control [
+        SC2P OFFSET(13) NUMBITS(1) [],
+        SC4P OFFSET(12) NUMBITS(1) [],
+        SC8P OFFSET(11) NUMBITS(1) [],
+        SC16P OFFSET(10) NUMBITS(1) [],
+        CLKO OFFSET(9) NUMBITS(1) [],
+        OSCE OFFSET(8) NUMBITS(1) [],
+        WPS OFFSET(4) NUMBITS(1) [],
+        UM OFFSET(3) NUMBITS(1) [],
+        SUP OFFSET(2) NUMBITS(1) [],
+        WPE OFFSET(1) NUMBITS(1) [],
+        SWR OFFSET(0) NUMBITS(1) []
+    ],
+    Status [
+        TCE OFFSET(4) NUMBITS(1) [],
+        TAF OFFSET(2) NUMBITS(1) [],
+        TOF OFFSET(1) NUMBITS(1) [],
+        TIF OFFSET(0) NUMBITS(1) []
+    ],
+    InterruptEnable [
+        WPON OFFSET(7) NUMBITS(1) [],
+        TSIE OFFSET(4) NUMBITS(1) [],
+        TAIE OFFSET(2) NUMBITS(1) [],
+        TOIE OFFSET(1) NUMBITS(1) [],
+        TIIE OFFSET(0) NUMBITS(1) []
+    ]
+];
+
+pub const RTC_BASE: *mut Registers = 0x4003_D000 as *mut Registers;
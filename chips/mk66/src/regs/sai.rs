@@ -0,0 +1,104 @@
+use kernel::common::regs::{ReadWrite, ReadOnly};
+
+/// The transmitter and receiver have the same registers.
+#[repr(C)]
+pub struct Direction {
+    pub csr: ReadWrite<u32, ControlStatus::Register>,
+    pub cr1: ReadWrite<u32, Config1::Register>,
+    pub cr2: ReadWrite<u32, Config2::Register>,
+    pub cr3: ReadWrite<u32, Config3::Register>,
+    pub cr4: ReadWrite<u32, Config4::Register>,
+    pub cr5: ReadWrite<u32, Config5::Register>,
+    _reserved0: [u32; 2],
+    pub dr: [ReadWrite<u32>; 2],
+    _reserved1: [u32; 6],
+    pub fr: [ReadOnly<u32>; 2],
+    _reserved2: [u32; 6],
+    pub mr: ReadWrite<u32>,
+    _reserved3: [u32; 7],
+}
+
+#[repr(C)]
+pub struct Registers {
+    pub tx: Direction,
+    pub rx: Direction,
+    pub mcr: ReadWrite<u32, MclkControl::Register>,
+    pub mdr: ReadWrite<u32, MclkDivide::Register>,
+}
+
+pub const I2S0_BASE: *mut Registers = 0x4002_F000 as *mut Registers;
+
+register_bitfields![u32,
+    ControlStatus [
+        E OFFSET(31) NUMBITS(1) [],
+        STOPE OFFSET(30) NUMBITS(1) [],
+        DBGE OFFSET(29) NUMBITS(1) [],
+        BCE OFFSET(28) NUMBITS(1) [],
+        FR OFFSET(25) NUMBITS(1) [],
+        SR OFFSET(24) NUMBITS(1) [],
+        WSF OFFSET(20) NUMBITS(1) [],
+        SEF OFFSET(19) NUMBITS(1) [],
+        FEF OFFSET(18) NUMBITS(1) [],
+        FWF OFFSET(17) NUMBITS(1) [],
+        FRF OFFSET(16) NUMBITS(1) [],
+        WSIE OFFSET(12) NUMBITS(1) [],
+        SEIE OFFSET(11) NUMBITS(1) [],
+        FEIE OFFSET(10) NUMBITS(1) [],
+        FWIE OFFSET(9) NUMBITS(1) [],
+        FRIE OFFSET(8) NUMBITS(1) [],
+        FWDE OFFSET(1) NUMBITS(1) [],
+        FRDE OFFSET(0) NUMBITS(1) []
+    ],
+    Config1 [
+        FW OFFSET(0) NUMBITS(3) []
+    ],
+    Config2 [
+        SYNC OFFSET(30) NUMBITS(2) [
+            Asynchronous = 0,
+            Synchronous = 1
+        ],
+        BCS OFFSET(29) NUMBITS(1) [],
+        BCI OFFSET(28) NUMBITS(1) [],
+        MSEL OFFSET(26) NUMBITS(2) [
+            BusClock = 0,
+            Mclk = 1
+        ],
+        BCP OFFSET(25) NUMBITS(1) [],
+        BCD OFFSET(24) NUMBITS(1) [],
+        DIV OFFSET(0) NUMBITS(8) []
+    ],
+    Config3 [
+        CE OFFSET(16) NUMBITS(2) [],
+        WDFL OFFSET(0) NUMBITS(5) []
+    ],
+    Config4 [
+        FCONT OFFSET(28) NUMBITS(1) [],
+        FCOMB OFFSET(26) NUMBITS(2) [],
+        FPACK OFFSET(24) NUMBITS(2) [],
+        FRSZ OFFSET(16) NUMBITS(5) [],
+        SYWD OFFSET(8) NUMBITS(5) [],
+        MF OFFSET(4) NUMBITS(1) [],
+        FSE OFFSET(3) NUMBITS(1) [],
+        ONDEM OFFSET(2) NUMBITS(1) [],
+        FSP OFFSET(1) NUMBITS(1) [],
+        FSD OFFSET(0) NUMBITS(1) []
+    ],
+    Config5 [
+        WNW OFFSET(24) NUMBITS(5) [],
+        W0W OFFSET(16) NUMBITS(5) [],
+        FBT OFFSET(8) NUMBITS(5) []
+    ],
+    MclkControl [
+        DUF OFFSET(31) NUMBITS(1) [],
+        MOE OFFSET(30) NUMBITS(1) [],
+        MICS OFFSET(24) NUMBITS(2) [
+            SystemClock = 0,
+            OscErClk = 1,
+            McgPllClk = 3
+        ]
+    ],
+    MclkDivide [
+        FRACT OFFSET(12) NUMBITS(8) [],
+        DIVIDE OFFSET(0) NUMBITS(12) []
+    ]
+];
@@ -0,0 +1,142 @@
+use kernel::common::regs::{ReadWrite, ReadOnly};
+
+#[repr(C)]
+pub struct Registers {
+    pub dsaddr: ReadWrite<u32>,
+    pub blkattr: ReadWrite<u32, BlockAttributes::Register>,
+    pub cmdarg: ReadWrite<u32>,
+    pub xfertyp: ReadWrite<u32, TransferType::Register>,
+    pub cmdrsp: [ReadOnly<u32>; 4],
+    pub datport: ReadWrite<u32>,
+    pub prsstat: ReadOnly<u32, PresentState::Register>,
+    pub proctl: ReadWrite<u32, ProtocolControl::Register>,
+    pub sysctl: ReadWrite<u32, SystemControl::Register>,
+    pub irqstat: ReadWrite<u32, Interrupt::Register>,
+    pub irqstaten: ReadWrite<u32, Interrupt::Register>,
+    pub irqsigen: ReadWrite<u32, Interrupt::Register>,
+    pub ac12err: ReadOnly<u32>,
+    pub htcapblt: ReadOnly<u32>,
+    pub wml: ReadWrite<u32>,
+    _reserved0: [u32; 2],
+    pub fevt: ReadWrite<u32>,
+    pub admaes: ReadOnly<u32>,
+    pub adsaddr: ReadWrite<u32>,
+    _reserved1: [u32; 25],
+    pub vendor: ReadWrite<u32>,
+    pub mmcboot: ReadWrite<u32>,
+    _reserved2: [u32; 13],
+    pub hostver: ReadOnly<u32>,
+}
+
+register_bitfields![u32,
+    BlockAttributes [
+        BLKCNT OFFSET(16) NUMBITS(16) [],
+        BLKSIZE OFFSET(0) NUMBITS(13) []
+    ],
+    TransferType [
+        CMDINX OFFSET(24) NUMBITS(6) [],
+        CMDTYP OFFSET(22) NUMBITS(2) [],
+        DPSEL OFFSET(21) NUMBITS(1) [],
+        CICEN OFFSET(20) NUMBITS(1) [],
+        CCCEN OFFSET(19) NUMBITS(1) [],
+        RSPTYP OFFSET(16) NUMBITS(2) [
+            None = 0,
+            Length136 = 1,
+            Length48 = 2,
+            Length48Busy = 3
+        ],
+        MSBSEL OFFSET(5) NUMBITS(1) [],
+        DTDSEL OFFSET(4) NUMBITS(1) [
+            Write = 0,
+            Read = 1
+        ],
+        AC12EN OFFSET(2) NUMBITS(1) [],
+        BCEN OFFSET(1) NUMBITS(1) [],
+        DMAEN OFFSET(0) NUMBITS(1) []
+    ],
+    PresentState [
+        DLSL OFFSET(24) NUMBITS(8) [],
+        CLSL OFFSET(23) NUMBITS(1) [],
+        CINST OFFSET(16) NUMBITS(1) [],
+        BREN OFFSET(11) NUMBITS(1) [],
+        BWEN OFFSET(10) NUMBITS(1) [],
+        RTA OFFSET(9) NUMBITS(1) [],
+        WTA OFFSET(8) NUMBITS(1) [],
+        SDOFF OFFSET(7) NUMBITS(1) [],
+        PEROFF OFFSET(6) NUMBITS(1) [],
+        HCKOFF OFFSET(5) NUMBITS(1) [],
+        IPGOFF OFFSET(4) NUMBITS(1) [],
+        SDSTB OFFSET(3) NUMBITS(1) [],
+        DLA OFFSET(2) NUMBITS(1) [],
+        CDIHB OFFSET(1) NUMBITS(1) [],
+        CIHB OFFSET(0) NUMBITS(1) []
+    ],
+    ProtocolControl [
+        WECRM OFFSET(26) NUMBITS(1) [],
+        WECINS OFFSET(25) NUMBITS(1) [],
+        WECINT OFFSET(24) NUMBITS(1) [],
+        IABG OFFSET(19) NUMBITS(1) [],
+        RWCTL OFFSET(18) NUMBITS(1) [],
+        CREQ OFFSET(17) NUMBITS(1) [],
+        SABGREQ OFFSET(16) NUMBITS(1) [],
+        DMAS OFFSET(8) NUMBITS(2) [
+            NoDma = 0,
+            Adma1 = 1,
+            Adma2 = 2
+        ],
+        CDSS OFFSET(7) NUMBITS(1) [],
+        CDTL OFFSET(6) NUMBITS(1) [],
+        EMODE OFFSET(4) NUMBITS(2) [
+            BigEndian = 0,
+            HalfWordBigEndian = 1,
+            LittleEndian = 2
+        ],
+        D3CD OFFSET(3) NUMBITS(1) [],
+        DTW OFFSET(1) NUMBITS(2) [
+            OneBit = 0,
+            FourBit = 1,
+            EightBit = 2
+        ],
+        LCTL OFFSET(0) NUMBITS(1) []
+    ],
+    SystemControl [
+        INITA OFFSET(27) NUMBITS(1) [],
+        RSTD OFFSET(26) NUMBITS(1) [],
+        RSTC OFFSET(25) NUMBITS(1) [],
+        RSTA OFFSET(24) NUMBITS(1) [],
+        DTOCV OFFSET(16) NUMBITS(4) [],
+        SDCLKFS OFFSET(8) NUMBITS(8) [],
+        DVS OFFSET(4) NUMBITS(4) [],
+        SDCLKEN OFFSET(3) NUMBITS(1) [],
+        PEREN OFFSET(2) NUMBITS(1) [],
+        HCKEN OFFSET(1) NUMBITS(1) [],
+        IPGEN OFFSET(0) NUMBITS(1) []
+    ],
+    Interrupt [
+        DMAE OFFSET(28) NUMBITS(1) [],
+        AC12E OFFSET(24) NUMBITS(1) [],
+        DEBE OFFSET(22) NUMBITS(1) [],
+        DCE OFFSET(21) NUMBITS(1) [],
+        DTOE OFFSET(20) NUMBITS(1) [],
+        CIE OFFSET(19) NUMBITS(1) [],
+        CEBE OFFSET(18) NUMBITS(1) [],
+        CCE OFFSET(17) NUMBITS(1) [],
+        CTOE OFFSET(16) NUMBITS(1) [],
+        CINT OFFSET(8) NUMBITS(1) [],
+        CRM OFFSET(7) NUMBITS(1) [],
+        CINS OFFSET(6) NUMBITS(1) [],
+        BRR OFFSET(5) NUMBITS(1) [],
+        BWR OFFSET(4) NUMBITS(1) [],
+        DINT OFFSET(3) NUMBITS(1) [],
+        BGE OFFSET(2) NUMBITS(1) [],
+        TC OFFSET(1) NUMBITS(1) [],
+        CC OFFSET(0) NUMBITS(1) []
+    ]
+];
+
+/// Command errors in the interrupt status register.
+pub const COMMAND_ERRORS: u32 = 0x000F_0000;
+/// Data and DMA errors in the interrupt status register.
+pub const DATA_ERRORS: u32 = 0x1170_0000;
+
+pub const SDHC_BASE: *mut Registers = 0x400B_1000 as *mut Registers;
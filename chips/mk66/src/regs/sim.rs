@@ -2,7 +2,7 @@ use kernel::common::regs::{ReadWrite, ReadOnly};
 
 #[repr(C)]
 pub struct Registers {
-    pub sopt2: ReadWrite<u32>,
+    pub sopt2: ReadWrite<u32, SystemOptions2::Register>,
     _reserved0: ReadWrite<u32>,
     pub sopt4: ReadWrite<u32>,
     pub sopt5: ReadWrite<u32>,
@@ -32,7 +32,48 @@ pub struct Registers {
 
 pub const SIM: *mut Registers = 0x40048004 as *mut Registers;
 
+// SOPT1 is separated from the rest of the SIM registers.
+pub const SOPT1: *mut ReadWrite<u32, SystemOptions1::Register> = 0x40047000 as *mut ReadWrite<u32, SystemOptions1::Register>;
+
 register_bitfields![u32,
+    SystemOptions1 [
+        USBREGEN OFFSET(31) NUMBITS(1) [],
+        USBSSTBY OFFSET(30) NUMBITS(1) [],
+        USBVSTBY OFFSET(29) NUMBITS(1) [],
+        OSC32KSEL OFFSET(18) NUMBITS(2) [
+            SystemOscillator = 0,
+            Rtc32kHz = 2,
+            Lpo1kHz = 3
+        ],
+        RAMSIZE OFFSET(12) NUMBITS(4) []
+    ],
+    SystemOptions2 [
+        TIMESRC OFFSET(20) NUMBITS(2) [
+            CoreClock = 0,
+            PllFllSel = 1,
+            OscErClk = 2,
+            Enet1588ClkIn = 3
+        ],
+        RMIISRC OFFSET(19) NUMBITS(1) [
+            Extal = 0,
+            Enet1588ClkIn = 1
+        ],
+        USBSRC OFFSET(18) NUMBITS(1) [
+            UsbClkin = 0,
+            Divided = 1
+        ],
+        PLLFLLSEL OFFSET(16) NUMBITS(2) [
+            Fll = 0,
+            Pll = 1,
+            UsbPfd = 2,
+            Irc48M = 3
+        ],
+        USBREGEN OFFSET(11) NUMBITS(1) [],
+        USBSLSRC OFFSET(10) NUMBITS(1) [
+            McgIrClk = 0,
+            RtcClk = 1
+        ]
+    ],
     SystemClockGatingControl1 [
         UART4 10,
         I2C3 7,
@@ -0,0 +1,12 @@
+use kernel::common::regs::ReadWrite;
+
+#[repr(C)]
+pub struct Registers {
+    pub cesr: ReadWrite<u32>,
+    _reserved0: [u32; 511],
+    /// Alternate access control views of the region descriptors. Writing
+    /// one changes a region's permissions without invalidating it.
+    pub rgdaac: [ReadWrite<u32>; 12],
+}
+
+pub const SYSMPU: *mut Registers = 0x4000_D000 as *mut Registers;
@@ -0,0 +1,46 @@
+use kernel::common::regs::ReadWrite;
+
+#[repr(C)]
+pub struct Registers {
+    pub gencs: ReadWrite<u32, GeneralControl::Register>,
+    pub data: ReadWrite<u32, Data::Register>,
+    pub tshd: ReadWrite<u32, Threshold::Register>,
+}
+
+pub const TSI0_BASE: *mut Registers = 0x4004_5000 as *mut Registers;
+
+register_bitfields![u32,
+    GeneralControl [
+        OUTRGF OFFSET(31) NUMBITS(1) [],
+        ESOR OFFSET(28) NUMBITS(1) [
+            OutOfRange = 0,
+            EndOfScan = 1
+        ],
+        MODE OFFSET(24) NUMBITS(4) [],
+        REFCHRG OFFSET(21) NUMBITS(3) [],
+        DVOLT OFFSET(19) NUMBITS(2) [],
+        EXTCHRG OFFSET(16) NUMBITS(3) [],
+        PS OFFSET(13) NUMBITS(3) [],
+        NSCN OFFSET(8) NUMBITS(5) [],
+        TSIEN OFFSET(7) NUMBITS(1) [],
+        TSIIEN OFFSET(6) NUMBITS(1) [],
+        STPE OFFSET(5) NUMBITS(1) [],
+        STM OFFSET(4) NUMBITS(1) [
+            Software = 0,
+            Hardware = 1
+        ],
+        SCNIP OFFSET(3) NUMBITS(1) [],
+        EOSF OFFSET(2) NUMBITS(1) [],
+        CURSW OFFSET(1) NUMBITS(1) []
+    ],
+    Data [
+        TSICH OFFSET(28) NUMBITS(4) [],
+        DMAEN OFFSET(23) NUMBITS(1) [],
+        SWTS OFFSET(22) NUMBITS(1) [],
+        TSICNT OFFSET(0) NUMBITS(16) []
+    ],
+    Threshold [
+        THRESH OFFSET(16) NUMBITS(16) [],
+        THRESL OFFSET(0) NUMBITS(16) []
+    ]
+];
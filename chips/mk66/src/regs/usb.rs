@@ -0,0 +1,139 @@
+use kernel::common::regs::{ReadWrite, ReadOnly};
+
+// The registers are 8 bits wide, but are spaced 4 bytes apart.
+#[repr(C)]
+pub struct Registers {
+    pub perid: ReadOnly<u8>,
+    _reserved0: [u8; 3],
+    pub idcomp: ReadOnly<u8>,
+    _reserved1: [u8; 3],
+    pub rev: ReadOnly<u8>,
+    _reserved2: [u8; 3],
+    pub addinfo: ReadOnly<u8>,
+    _reserved3: [u8; 3],
+    pub otgistat: ReadWrite<u8>,
+    _reserved4: [u8; 3],
+    pub otgicr: ReadWrite<u8>,
+    _reserved5: [u8; 3],
+    pub otgstat: ReadWrite<u8>,
+    _reserved6: [u8; 3],
+    pub otgctl: ReadWrite<u8>,
+    _reserved7: [u8; 99],
+    pub istat: ReadWrite<u8, Interrupt::Register>,
+    _reserved8: [u8; 3],
+    pub inten: ReadWrite<u8, Interrupt::Register>,
+    _reserved9: [u8; 3],
+    pub errstat: ReadWrite<u8>,
+    _reserved10: [u8; 3],
+    pub erren: ReadWrite<u8>,
+    _reserved11: [u8; 3],
+    pub stat: ReadOnly<u8, Status::Register>,
+    _reserved12: [u8; 3],
+    pub ctl: ReadWrite<u8, Control::Register>,
+    _reserved13: [u8; 3],
+    pub addr: ReadWrite<u8, Address::Register>,
+    _reserved14: [u8; 3],
+    pub bdtpage1: ReadWrite<u8>,
+    _reserved15: [u8; 3],
+    pub frmnuml: ReadOnly<u8>,
+    _reserved16: [u8; 3],
+    pub frmnumh: ReadOnly<u8>,
+    _reserved17: [u8; 3],
+    pub token: ReadWrite<u8>,
+    _reserved18: [u8; 3],
+    pub softhld: ReadWrite<u8>,
+    _reserved19: [u8; 3],
+    pub bdtpage2: ReadWrite<u8>,
+    _reserved20: [u8; 3],
+    pub bdtpage3: ReadWrite<u8>,
+    _reserved21: [u8; 11],
+    pub endpt: [EndpointRegister; 16],
+    pub usbctrl: ReadWrite<u8, UsbControl::Register>,
+    _reserved22: [u8; 3],
+    pub observe: ReadOnly<u8>,
+    _reserved23: [u8; 3],
+    pub control: ReadWrite<u8, OtgControl::Register>,
+    _reserved24: [u8; 3],
+    pub usbtrc0: ReadWrite<u8, TransceiverControl::Register>,
+    _reserved25: [u8; 7],
+    pub usbfrmadjust: ReadWrite<u8>,
+    _reserved26: [u8; 43],
+    pub clk_recover_ctrl: ReadWrite<u8, ClockRecoveryControl::Register>,
+    _reserved27: [u8; 3],
+    pub clk_recover_irc_en: ReadWrite<u8, IrcEnable::Register>,
+    _reserved28: [u8; 15],
+    pub clk_recover_int_en: ReadWrite<u8>,
+    _reserved29: [u8; 7],
+    pub clk_recover_int_status: ReadWrite<u8>,
+}
+
+#[repr(C)]
+pub struct EndpointRegister {
+    pub ctl: ReadWrite<u8, EndpointControl::Register>,
+    _reserved: [u8; 3],
+}
+
+register_bitfields![u8,
+    Interrupt [
+        STALL 7,
+        ATTACH 6,
+        RESUME 5,
+        SLEEP 4,
+        TOKDNE 3,
+        SOFTOK 2,
+        ERROR 1,
+        USBRST 0
+    ],
+    Status [
+        ENDP OFFSET(4) NUMBITS(4) [],
+        TX OFFSET(3) NUMBITS(1) [],
+        ODD OFFSET(2) NUMBITS(1) []
+    ],
+    Control [
+        JSTATE 7,
+        SE0 6,
+        TXSUSPENDTOKENBUSY 5,
+        RESET 4,
+        HOSTMODEEN 3,
+        RESUME 2,
+        ODDRST 1,
+        USBENSOFEN 0
+    ],
+    Address [
+        LSEN OFFSET(7) NUMBITS(1) [],
+        ADDR OFFSET(0) NUMBITS(7) []
+    ],
+    EndpointControl [
+        HOSTWOHUB 7,
+        RETRYDIS 6,
+        EPCTLDIS 4,
+        EPRXEN 3,
+        EPTXEN 2,
+        EPSTALL 1,
+        EPHSHK 0
+    ],
+    UsbControl [
+        SUSP 7,
+        PDE 6
+    ],
+    OtgControl [
+        DPPULLUPNONOTG 4
+    ],
+    TransceiverControl [
+        USBRESET 7,
+        USBRESMEN 5,
+        SYNC_DET 1,
+        USB_RESUME_INT 0
+    ],
+    ClockRecoveryControl [
+        CLOCK_RECOVER_EN 7,
+        RESET_RESUME_ROUGH_EN 6,
+        RESTART_IFRTRIM_EN 5
+    ],
+    IrcEnable [
+        IRC_EN 1,
+        REG_EN 0
+    ]
+];
+
+pub const USB0_BASE: *mut Registers = 0x4007_2000 as *mut Registers;
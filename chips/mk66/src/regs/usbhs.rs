@@ -0,0 +1,160 @@
+use kernel::common::regs::{ReadWrite, ReadOnly, RegisterLongName};
+
+/// The USB-HS controller. In host mode it is EHCI compatible, with the
+/// operational registers at offset 0x140.
+#[repr(C)]
+pub struct Registers {
+    pub id: ReadOnly<u32>,
+    pub hwgeneral: ReadOnly<u32>,
+    pub hwhost: ReadOnly<u32>,
+    pub hwdevice: ReadOnly<u32>,
+    pub hwtxbuf: ReadOnly<u32>,
+    pub hwrxbuf: ReadOnly<u32>,
+    _reserved0: [u32; 26],
+    pub gptimer0ld: ReadWrite<u32>,
+    pub gptimer0ctl: ReadWrite<u32, GpTimerControl::Register>,
+    pub gptimer1ld: ReadWrite<u32>,
+    pub gptimer1ctl: ReadWrite<u32>,
+    pub usb_sbuscfg: ReadWrite<u32>,
+    _reserved1: [u32; 27],
+    pub hciversion: ReadOnly<u32>,
+    pub hcsparams: ReadOnly<u32>,
+    pub hccparams: ReadOnly<u32>,
+    _reserved2: [u32; 5],
+    pub dciversion: ReadOnly<u32>,
+    pub dccparams: ReadOnly<u32>,
+    _reserved3: [u32; 6],
+    pub usbcmd: ReadWrite<u32, UsbCommand::Register>,
+    pub usbsts: ReadWrite<u32, UsbStatus::Register>,
+    pub usbintr: ReadWrite<u32, UsbStatus::Register>,
+    pub frindex: ReadWrite<u32>,
+    _reserved4: u32,
+    pub periodiclistbase: ReadWrite<u32>,
+    pub asynclistaddr: ReadWrite<u32>,
+    pub ttctrl: ReadWrite<u32>,
+    pub burstsize: ReadWrite<u32>,
+    pub txfilltuning: ReadWrite<u32>,
+    _reserved5: [u32; 6],
+    pub configflag: ReadOnly<u32>,
+    pub portsc1: ReadWrite<u32, PortStatusControl::Register>,
+    _reserved6: [u32; 7],
+    pub otgsc: ReadWrite<u32>,
+    pub usbmode: ReadWrite<u32, UsbMode::Register>,
+}
+
+/// A PHY register, followed by aliases that set, clear and toggle the bits
+/// written to them.
+#[repr(C)]
+pub struct SetClearRegister<R: RegisterLongName = ()> {
+    pub value: ReadWrite<u32, R>,
+    pub set: ReadWrite<u32, R>,
+    pub clr: ReadWrite<u32, R>,
+    pub tog: ReadWrite<u32, R>,
+}
+
+#[repr(C)]
+pub struct PhyRegisters {
+    pub pwd: SetClearRegister,
+    pub tx: SetClearRegister,
+    pub rx: SetClearRegister,
+    pub ctrl: SetClearRegister<PhyControl::Register>,
+    _reserved0: [u32; 24],
+    pub pll_sic: SetClearRegister<PllControl::Register>,
+}
+
+register_bitfields![u32,
+    UsbCommand [
+        ITC OFFSET(16) NUMBITS(8) [],
+        FS2 OFFSET(15) NUMBITS(1) [],
+        ASPE OFFSET(11) NUMBITS(1) [],
+        ASP OFFSET(8) NUMBITS(2) [],
+        IAA OFFSET(6) NUMBITS(1) [],
+        ASE OFFSET(5) NUMBITS(1) [],
+        PSE OFFSET(4) NUMBITS(1) [],
+        FS OFFSET(2) NUMBITS(2) [],
+        RST OFFSET(1) NUMBITS(1) [],
+        RS OFFSET(0) NUMBITS(1) []
+    ],
+    UsbStatus [
+        TI1 25,
+        TI0 24,
+        UPI 19,
+        UAI 18,
+        NAKI 16,
+        AS 15,
+        PS 14,
+        RCL 13,
+        HCH 12,
+        SLI 8,
+        SRI 7,
+        URI 6,
+        AAI 5,
+        SEI 4,
+        FRI 3,
+        PCI 2,
+        UEI 1,
+        UI 0
+    ],
+    GpTimerControl [
+        GPTRUN OFFSET(31) NUMBITS(1) [],
+        GPTRST OFFSET(30) NUMBITS(1) [],
+        GPTMODE OFFSET(24) NUMBITS(1) [
+            OneShot = 0,
+            Repeat = 1
+        ],
+        GPTCNT OFFSET(0) NUMBITS(24) []
+    ],
+    PortStatusControl [
+        PSPD OFFSET(26) NUMBITS(2) [
+            Full = 0,
+            Low = 1,
+            High = 2
+        ],
+        PHCD OFFSET(23) NUMBITS(1) [],
+        PTC OFFSET(16) NUMBITS(4) [],
+        PP OFFSET(12) NUMBITS(1) [],
+        LS OFFSET(10) NUMBITS(2) [],
+        HSP OFFSET(9) NUMBITS(1) [],
+        PR OFFSET(8) NUMBITS(1) [],
+        SUSP OFFSET(7) NUMBITS(1) [],
+        FPR OFFSET(6) NUMBITS(1) [],
+        OCC OFFSET(5) NUMBITS(1) [],
+        OCA OFFSET(4) NUMBITS(1) [],
+        PEC OFFSET(3) NUMBITS(1) [],
+        PE OFFSET(2) NUMBITS(1) [],
+        CSC OFFSET(1) NUMBITS(1) [],
+        CCS OFFSET(0) NUMBITS(1) []
+    ],
+    UsbMode [
+        SDIS OFFSET(4) NUMBITS(1) [],
+        SLOM OFFSET(3) NUMBITS(1) [],
+        ES OFFSET(2) NUMBITS(1) [],
+        CM OFFSET(0) NUMBITS(2) [
+            Idle = 0,
+            Device = 2,
+            Host = 3
+        ]
+    ],
+    PhyControl [
+        SFTRST 31,
+        CLKGATE 30,
+        ENUTMILEVEL3 15,
+        ENUTMILEVEL2 14,
+        ENHOSTDISCONDETECT 1
+    ],
+    PllControl [
+        PLL_LOCK OFFSET(31) NUMBITS(1) [],
+        PLL_BYPASS OFFSET(16) NUMBITS(1) [],
+        PLL_ENABLE OFFSET(13) NUMBITS(1) [],
+        PLL_POWER OFFSET(12) NUMBITS(1) [],
+        PLL_EN_USB_CLKS OFFSET(6) NUMBITS(1) [],
+        PLL_DIV_SEL OFFSET(0) NUMBITS(2) [
+            Ref24MHz = 0,
+            Ref16MHz = 1,
+            Ref12MHz = 2
+        ]
+    ]
+];
+
+pub const USBHS_BASE: *mut Registers = 0x400A_1000 as *mut Registers;
+pub const USBPHY_BASE: *mut PhyRegisters = 0x400A_2000 as *mut PhyRegisters;
@@ -4,9 +4,17 @@
 //! 32-bit numbers with 1-2 bits of entropy each, and uses SHA-256 to hash this
 //! data into a 256-bit key for the Twofish block cipher in counter mode.
 //!
+//! After every request, the key is replaced with fresh cipher output, so
+//! reading the key out of RAM reveals nothing about earlier output. Every
+//! `RESEED_INTERVAL` requests, or whenever `reseed` is called, fresh RNGA
+//! output is hashed into the key along with the old key.
+//!
 //! - Author: Conor McAvity <cmcavity@stanford.edu>
 
 use core::cell::Cell;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{self, Ordering};
 use kernel::common::regs::{ReadWrite, WriteOnly, ReadOnly};
 use kernel::hil::rng::{self, Continue};
 use sha2::{Sha256, Digest};
@@ -45,11 +53,29 @@ register_bitfields! [
 
 const BASE_ADDRESS: *const RngaRegisters = 0x40029000 as *const RngaRegisters;
 
+/// RNGA words hashed into the key at each reseed.
+const ENTROPY_WORDS: usize = 256;
+
+/// Requests served between reseeds from the RNGA.
+pub const RESEED_INTERVAL: u32 = 1024;
+
+/// Overwrites a copy of key material with zeroes. The writes are volatile so
+/// that they are not optimized away as dead stores.
+fn wipe<T>(value: &mut T) {
+    let bytes = value as *mut T as *mut u8;
+    for i in 0..mem::size_of::<T>() {
+        unsafe { ptr::write_volatile(bytes.offset(i as isize), 0) };
+    }
+    atomic::compiler_fence(Ordering::SeqCst);
+}
+
 pub struct Rnga<'a> {
     regs: *const RngaRegisters,
     client: Cell<Option<&'a rng::Client>>,
     key: Cell<[u8; 32]>,
     counter: Cell<u128>,
+    // Requests served since the last reseed.
+    reseed_counter: Cell<u32>,
 }
 
 pub static mut RNGA: Rnga<'static> = Rnga::new();
@@ -61,6 +87,7 @@ impl<'a> Rnga<'a> {
             client: Cell::new(None),
             key: Cell::new([0; 32]),
             counter: Cell::new(0),
+            reseed_counter: Cell::new(0),
         }
     }
 
@@ -68,53 +95,60 @@ impl<'a> Rnga<'a> {
         self.client.set(Some(client));
     }
 
-    pub fn init(&mut self) {
+    pub fn init(&self) {
         // set clock gate
         use regs::sim::*;
         let sim = unsafe { &*SIM };
         sim.scgc6.modify(SystemClockGatingControl6::RNGA::SET);
 
+        self.reseed();
+    }
+
+    /// Replaces the key with the hash of the old key and fresh RNGA output,
+    /// so that the new key is at least as unpredictable as either.
+    pub fn reseed(&self) {
+        let mut sha = Sha256::new();
+        let mut old_key = self.key.get();
+        sha.input(&old_key);
+        wipe(&mut old_key);
+
         // start rnga
         let regs = unsafe { &*self.regs };
         regs.control.modify(Control::SLP::CLEAR);
         regs.control.modify(Control::INTM::SET + Control::HA::SET + Control::GO::SET);
 
-        let mut msg: [u8; 1024] = [0; 1024];
-
         // collect data from rnga
-        for i in 0..256 {
-            while true {
-                if regs.reg_level.get() != 1 {
-                    continue
-                }
-
-                let rn = regs.output.get();
+        for _ in 0..ENTROPY_WORDS {
+            while regs.reg_level.get() != 1 {}
 
-                let j = 4 * i;
-                msg[j] = (rn >> 24) as u8;
-                msg[j + 1] = (rn >> 16) as u8;
-                msg[j + 2] = (rn >> 8) as u8;
-                msg[j + 3] = rn as u8;
-
-                break;
-            }
-        }
-
-        let hash = Sha256::digest(&msg);
-
-        let key = self.key.get_mut();
-
-        for i in 0..32 {
-            key[i] = hash[i];
+            let rn = regs.output.get();
+            sha.input(&[(rn >> 24) as u8, (rn >> 16) as u8, (rn >> 8) as u8, rn as u8]);
         }
 
         // stop rnga
         regs.control.modify(Control::SLP::SET);
+
+        let mut digest = sha.result();
+        let mut key = [0; 32];
+        key.copy_from_slice(&digest);
+        self.key.set(key);
+        wipe(&mut digest);
+        wipe(&mut key);
+        self.counter.set(0);
+        self.reseed_counter.set(0);
     }
 
+    fn cipher(&self) -> Twofish {
+        let mut key = self.key.get();
+        let mut array = GenericArray::clone_from_slice(&key);
+        let cipher = BlockCipher::new(&array);
+        wipe(&mut key);
+        wipe(&mut array);
+        cipher
+    }
 
-    pub fn get_number(&self) -> Option<u32> {
-        let key = GenericArray::clone_from_slice(&self.key.get());
+    /// Encrypts the next counter value.
+    fn next_block(&self, cipher: &Twofish) -> [u8; 16] {
         let counter = self.counter.replace(self.counter.get() + 1);
 
         let mut block: [u8; 16] = [0; 16];
@@ -125,40 +159,70 @@ impl<'a> Rnga<'a> {
         }
 
         let mut block = GenericArray::clone_from_slice(&block);
-
-        let cipher: Twofish = BlockCipher::new(&key);
         cipher.encrypt_block(&mut block);
 
-        let mut num = 0u32;
-
-        // keeps the 32 least significant bits
-        for i in 0..4 {
-            let byte = block[15 - i] as u32;
-            num |= byte << (8 * i);
-        }
+        let mut output = [0; 16];
+        output.copy_from_slice(&block);
+        wipe(&mut block);
+        output
+    }
 
-        Some(num)
+    /// Replaces the key with output no one has seen, so that the key that
+    /// produced earlier output is gone, along with the cipher's copy of it.
+    fn rekey(&self, cipher: &mut Twofish) {
+        let mut first = self.next_block(cipher);
+        let mut second = self.next_block(cipher);
+        let mut key = [0; 32];
+        key[..16].copy_from_slice(&first);
+        key[16..].copy_from_slice(&second);
+        self.key.set(key);
+        self.counter.set(0);
+
+        wipe(&mut first);
+        wipe(&mut second);
+        wipe(&mut key);
+        wipe(cipher);
     }
 }
 
-struct RngaIter<'a, 'b: 'a>(&'a Rnga<'b>);
+struct RngaIter<'a, 'b: 'a> {
+    rnga: &'a Rnga<'b>,
+    cipher: &'a Twofish,
+}
 
 impl<'a, 'b> Iterator for RngaIter<'a, 'b> {
     type Item = u32;
 
     fn next(&mut self) -> Option<u32> {
-        self.0.get_number()
+        let block = self.rnga.next_block(self.cipher);
+
+        let mut num = 0u32;
+
+        // keeps the 32 least significant bits
+        for i in 0..4 {
+            let byte = block[15 - i] as u32;
+            num |= byte << (8 * i);
+        }
+
+        Some(num)
     }
 }
 
 impl<'a> rng::RNG for Rnga<'a> {
     fn get(&self) {
+        if self.reseed_counter.get() >= RESEED_INTERVAL {
+            self.reseed();
+        }
+        self.reseed_counter.set(self.reseed_counter.get() + 1);
+
+        let mut cipher = self.cipher();
         while true {
             let result = self.client.get().unwrap()
-                .randomness_available(&mut RngaIter(self));
+                .randomness_available(&mut RngaIter { rnga: self, cipher: &cipher });
             if let Continue::Done = result {
                 break;
             }
         }
+        self.rekey(&mut cipher);
     }
 }
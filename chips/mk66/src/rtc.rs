@@ -0,0 +1,250 @@
+//! Implementation of the MK66 Real-Time Clock (RTC).
+//!
+//! The RTC counts seconds from the 32.768kHz crystal and is powered from VBAT,
+//! so the time survives resets and power loss as long as a battery is fitted.
+//! Time is kept as seconds since the Unix epoch; `DateTime` converts to and
+//! from calendar dates.
+
+use core::cell::Cell;
+use core::mem;
+use kernel::hil::time::Client;
+use llwu;
+use nvic::{self, NvicIdx};
+use regs::rtc::*;
+
+pub static mut RTC: Rtc<'static> = Rtc::new();
+
+pub struct Rtc<'a> {
+    client: Cell<Option<&'a Client>>,
+    // Whether the time has survived, or been set since it was lost. Resetting
+    // the counter in `init` clears TIF, so the flag alone cannot tell.
+    time_valid: Cell<bool>,
+}
+
+impl<'a> Rtc<'a> {
+    const fn new() -> Rtc<'a> {
+        Rtc {
+            client: Cell::new(None),
+            time_valid: Cell::new(false),
+        }
+    }
+
+    fn regs(&self) -> &mut Registers {
+        unsafe { mem::transmute(RTC_BASE) }
+    }
+
+    /// Starts the RTC oscillator if it is not already running. A time that
+    /// was kept through a reset is left untouched; an invalid time is reset to
+    /// the epoch.
+    pub fn init(&self) {
+        use sim::{clocks, Clock};
+        clocks::RTC.enable();
+
+        let regs = self.regs();
+        if !regs.cr.is_set(Control::OSCE) {
+            // The Teensy 3.6 crystal needs 20pF of load capacitance.
+            regs.sr.set(0);
+            regs.cr.write(Control::SC16P::SET + Control::SC4P::SET + Control::OSCE::SET);
+        }
+
+        if regs.sr.is_set(Status::TIF) {
+            self.write_seconds(0);
+            self.time_valid.set(false);
+        } else {
+            if !regs.sr.is_set(Status::TCE) {
+                regs.sr.write(Status::TCE::SET);
+            }
+            self.time_valid.set(true);
+        }
+    }
+
+    /// Returns false if the time was lost, for example because VBAT was
+    /// removed, and has not been set since.
+    pub fn is_time_valid(&self) -> bool {
+        self.time_valid.get() && !self.regs().sr.is_set(Status::TIF)
+    }
+
+    /// Returns the number of seconds since the Unix epoch.
+    pub fn seconds(&self) -> u32 {
+        // The counter may increment between reads, so wait for two reads to
+        // agree.
+        let regs = self.regs();
+        loop {
+            let seconds = regs.tsr.get();
+            if seconds == regs.tsr.get() {
+                return seconds;
+            }
+        }
+    }
+
+    pub fn set_seconds(&self, seconds: u32) {
+        self.write_seconds(seconds);
+        self.time_valid.set(true);
+    }
+
+    fn write_seconds(&self, seconds: u32) {
+        let regs = self.regs();
+        regs.sr.write(Status::TCE::CLEAR);
+
+        // Writing TSR clears the invalid and overflow flags.
+        regs.tpr.set(0);
+        regs.tsr.set(seconds);
+        regs.sr.write(Status::TCE::SET);
+    }
+
+    pub fn datetime(&self) -> DateTime {
+        DateTime::from_unix(self.seconds())
+    }
+
+    pub fn set_datetime(&self, datetime: &DateTime) {
+        self.set_seconds(datetime.to_unix());
+    }
+
+    /// Corrects for crystal error by shortening one second out of every
+    /// `interval` seconds by `cycles` 32kHz cycles. A negative `cycles`
+    /// lengthens the second instead. `interval` is limited to 1-256
+    /// seconds, so the finest correction is about 0.12ppm.
+    pub fn set_compensation(&self, cycles: i8, interval: u32) {
+        let interval = if interval == 0 {
+            1
+        } else if interval > 256 {
+            256
+        } else {
+            interval
+        };
+
+        self.regs().tcr.write(TimeCompensation::CIR.val(interval - 1) +
+                              TimeCompensation::TCR.val(cycles as u8 as u32));
+    }
+
+    pub fn set_client(&self, client: &'a Client) {
+        self.client.set(Some(client));
+    }
+
+    /// Raises an alarm interrupt when the time reaches `seconds`.
+    pub fn set_alarm(&self, seconds: u32) {
+        let regs = self.regs();
+
+        // The alarm flag is set when TSR increments past TAR, so an alarm for
+        // second `n` compares against `n - 1`. Writing TAR clears the flag.
+        regs.tar.set(seconds.wrapping_sub(1));
+        regs.ier.modify(InterruptEnable::TAIE::SET);
+        unsafe { nvic::enable(NvicIdx::RTC_ALARM); }
+    }
+
+    pub fn get_alarm(&self) -> u32 {
+        self.regs().tar.get().wrapping_add(1)
+    }
+
+    pub fn disable_alarm(&self) {
+        self.regs().ier.modify(InterruptEnable::TAIE::CLEAR);
+    }
+
+    pub fn is_alarm_enabled(&self) -> bool {
+        self.regs().ier.is_set(InterruptEnable::TAIE)
+    }
+
+    /// Allows the RTC alarm to wake the chip from LLS or VLLS.
+    pub fn enable_wakeup(&self) {
+        llwu::enable_module(llwu::WakeupModule::RtcAlarm);
+    }
+
+    pub fn disable_wakeup(&self) {
+        llwu::disable_module(llwu::WakeupModule::RtcAlarm);
+    }
+
+    pub fn handle_alarm_interrupt(&self) {
+        let regs = self.regs();
+        if !regs.sr.is_set(Status::TAF) {
+            return;
+        }
+
+        // The alarm is one-shot.
+        let tar = regs.tar.get();
+        regs.tar.set(tar);
+        self.disable_alarm();
+
+        self.client.get().map(|client| client.fired());
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DateTime {
+    pub year: u16,
+    /// 1-12
+    pub month: u8,
+    /// 1-31
+    pub day: u8,
+    /// 0 is Sunday.
+    pub weekday: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Converts seconds since the Unix epoch to a UTC date and time.
+    pub fn from_unix(seconds: u32) -> DateTime {
+        let days = seconds / 86_400;
+        let secs = seconds % 86_400;
+
+        // Shift the epoch to 0000-03-01 so that leap days fall at the end of
+        // each year. [Howard Hinnant, "chrono-Compatible Low-Level Date
+        // Algorithms"]
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        DateTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            // 1970-01-01 was a Thursday.
+            weekday: ((days + 4) % 7) as u8,
+            hour: (secs / 3600) as u8,
+            minute: (secs / 60 % 60) as u8,
+            second: (secs % 60) as u8,
+        }
+    }
+
+    /// Converts a UTC date and time to seconds since the Unix epoch. Dates
+    /// outside 1970-2105 do not fit and wrap. The weekday is ignored.
+    pub fn to_unix(&self) -> u32 {
+        let month = self.month as u32;
+        let year = self.year as u32 - if month <= 2 { 1 } else { 0 };
+        let era = year / 400;
+        let yoe = year - era * 400;
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + self.day as u32 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = (era * 146_097 + doe).wrapping_sub(719_468);
+
+        days.wrapping_mul(86_400)
+            .wrapping_add(self.hour as u32 * 3600)
+            .wrapping_add(self.minute as u32 * 60)
+            .wrapping_add(self.second as u32)
+    }
+
+    /// Returns true if every field is within its calendar range.
+    pub fn is_valid(&self) -> bool {
+        let days_in_month = match self.month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 => {
+                let y = self.year;
+                if (y % 4 == 0 && y % 100 != 0) || y % 400 == 0 { 29 } else { 28 }
+            },
+            _ => return false,
+        };
+
+        self.year >= 1970 && self.year <= 2105 &&
+            self.day >= 1 && self.day <= days_in_month &&
+            self.hour < 24 && self.minute < 60 && self.second < 60
+    }
+}
@@ -0,0 +1,343 @@
+//! Implementation of the MK66 Synchronous Audio Interface (I2S0).
+//!
+//! The interface speaks stereo I2S: frames of two 32-bit slots, left then
+//! right, with the frame sync one bit early and low for the left slot. As
+//! master it drives the bit clock and frame sync; as slave they come from
+//! the codec. Either way it drives a master clock of 256 times the sample
+//! rate, divided from the PLL, for codecs such as the SGTL5000 that need
+//! one. The receiver runs from the transmitter's clocks, so recording keeps
+//! the transmitter enabled.
+//!
+//! Samples stream through eDMA ping-pong buffers: while one half of a
+//! buffer is moved, the client fills or reads the other. In memory,
+//! 16-bit samples take two bytes, and 24 and 32-bit samples take four,
+//! with 24-bit samples in the top three bytes. Left and right alternate.
+
+use core::cell::Cell;
+use core::mem;
+use kernel::ReturnCode;
+use kernel::common::regs::{FieldValue, ReadWrite};
+use clock;
+use dma::{self, DmaClient, DmaPeripheral, Width};
+use regs::sai::*;
+
+/// The frames in each half of a ping-pong buffer.
+pub const BLOCK_FRAMES: usize = 128;
+const MAX_FRAME_BYTES: usize = 8;
+const BUFFER_SIZE: usize = 2 * BLOCK_FRAMES * MAX_FRAME_BYTES;
+
+const TX_CHANNEL: usize = 0;
+const RX_CHANNEL: usize = 1;
+
+const MCLK_RATIO: u32 = 256;
+const SLOT_BITS: u32 = 32;
+/// The master clock is divided by (DIV + 1) * 2 to the bit clock, which
+/// runs at 64 times the sample rate.
+const BCLK_DIV: u32 = MCLK_RATIO / (2 * SLOT_BITS) / 2 - 1;
+/// The transmitter asks for data while its 8-word FIFO holds this many
+/// words or fewer.
+const TX_WATERMARK: u32 = 4;
+
+#[repr(C, align(4))]
+struct AudioBuffer([u8; BUFFER_SIZE]);
+
+static mut TX_BUF: AudioBuffer = AudioBuffer([0; BUFFER_SIZE]);
+static mut RX_BUF: AudioBuffer = AudioBuffer([0; BUFFER_SIZE]);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SampleSize {
+    Bits16,
+    Bits24,
+    Bits32,
+}
+
+impl SampleSize {
+    pub fn bytes(self) -> usize {
+        match self {
+            SampleSize::Bits16 => 2,
+            SampleSize::Bits24 | SampleSize::Bits32 => 4,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Role {
+    Master,
+    Slave,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Format {
+    pub sample_rate: u32,
+    pub sample_size: SampleSize,
+    pub role: Role,
+}
+
+impl Format {
+    pub fn frame_bytes(&self) -> usize {
+        2 * self.sample_size.bytes()
+    }
+
+    /// The bytes in each half of a ping-pong buffer.
+    pub fn block_bytes(&self) -> usize {
+        BLOCK_FRAMES * self.frame_bytes()
+    }
+}
+
+pub trait Client {
+    /// Called for each block of frames to play, which the client fills.
+    fn play(&self, block: &mut [u8]);
+
+    /// Called with each block of frames recorded.
+    fn recorded(&self, block: &[u8]);
+}
+
+/// Finds the fraction FRACT+1 / DIVIDE+1, with FRACT below 256 and DIVIDE
+/// below 4096, that brings `input` closest to `mclk`. Returns the register
+/// values, if the result is within 1%.
+fn mclk_divider(input: u32, mclk: u32) -> Option<(u32, u32)> {
+    let mut best: Option<(u32, u32, u64)> = None;
+    for multiplier in 1..257u64 {
+        let divider = (input as u64 * multiplier + mclk as u64 / 2) / mclk as u64;
+        if divider < multiplier || divider > 4096 {
+            continue;
+        }
+        let output = input as u64 * multiplier / divider;
+        let error = if output > mclk as u64 { output - mclk as u64 } else { mclk as u64 - output };
+        if best.map_or(true, |(_, _, best_error)| error < best_error) {
+            best = Some((multiplier as u32 - 1, divider as u32 - 1, error));
+        }
+    }
+    best.and_then(|(fract, divide, error)| {
+        if error * 100 <= mclk as u64 { Some((fract, divide)) } else { None }
+    })
+}
+
+pub static mut SAI0: Sai = Sai::new();
+
+pub struct Sai {
+    client: Cell<Option<&'static Client>>,
+    format: Cell<Option<Format>>,
+    rx_line: Cell<usize>,
+    playing: Cell<bool>,
+    recording: Cell<bool>,
+}
+
+impl Sai {
+    const fn new() -> Sai {
+        Sai {
+            client: Cell::new(None),
+            format: Cell::new(None),
+            rx_line: Cell::new(0),
+            playing: Cell::new(false),
+            recording: Cell::new(false),
+        }
+    }
+
+    fn regs(&self) -> &mut Registers {
+        unsafe { mem::transmute(I2S0_BASE) }
+    }
+
+    pub fn set_client(&self, client: &'static Client) {
+        self.client.set(Some(client));
+    }
+
+    /// Selects which of the two receive data pins, RXD0 or RXD1, is used.
+    pub fn set_receive_line(&self, line: usize) {
+        self.rx_line.set(line & 1);
+    }
+
+    pub fn format(&self) -> Option<Format> {
+        self.format.get()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing.get()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.get()
+    }
+
+    /// Sets up the clocks and frames. Fails with `EBUSY` while streaming,
+    /// and with `EINVAL` if the PLL cannot make the master clock.
+    pub fn configure(&self, format: Format) -> ReturnCode {
+        if self.playing.get() || self.recording.get() {
+            return ReturnCode::EBUSY;
+        }
+        let mclk = match format.sample_rate.checked_mul(MCLK_RATIO) {
+            Some(mclk) if mclk > 0 => mclk,
+            _ => return ReturnCode::EINVAL,
+        };
+        let (fract, divide) = match mclk_divider(clock::pll_clock_hz(), mclk) {
+            Some(divider) => divider,
+            None => return ReturnCode::EINVAL,
+        };
+
+        use sim::{clocks, Clock};
+        clocks::I2S.enable();
+        let regs = self.regs();
+
+        regs.mcr.write(MclkControl::MICS::McgPllClk + MclkControl::MOE::SET);
+        regs.mdr.write(MclkDivide::FRACT.val(fract) + MclkDivide::DIVIDE.val(divide));
+        while regs.mcr.is_set(MclkControl::DUF) {}
+
+        let master = format.role == Role::Master;
+        self.configure_direction(&regs.tx, Config2::SYNC::Asynchronous, master);
+        self.configure_direction(&regs.rx, Config2::SYNC::Synchronous, master);
+        regs.tx.cr1.write(Config1::FW.val(TX_WATERMARK));
+        regs.rx.cr1.write(Config1::FW.val(0));
+        regs.tx.cr3.write(Config3::CE.val(1));
+        regs.rx.cr3.write(Config3::CE.val(1 << self.rx_line.get()));
+
+        self.format.set(Some(format));
+        ReturnCode::SUCCESS
+    }
+
+    /// Resets one direction and sets up its I2S frames.
+    fn configure_direction(&self,
+                           direction: &Direction,
+                           sync: FieldValue<u32, Config2::Register>,
+                           master: bool) {
+        direction.csr.write(ControlStatus::SR::SET);
+        direction.csr.write(ControlStatus::FR::SET);
+        direction.cr2.write(sync +
+                            Config2::BCP::SET +
+                            Config2::MSEL::Mclk +
+                            Config2::BCD.val(master as u32) +
+                            Config2::DIV.val(BCLK_DIV));
+        direction.cr4.write(Config4::FRSZ.val(1) +
+                            Config4::SYWD.val(SLOT_BITS - 1) +
+                            Config4::MF::SET +
+                            Config4::FSE::SET +
+                            Config4::FSP::SET +
+                            Config4::FSD.val(master as u32));
+        direction.cr5.write(Config5::WNW.val(SLOT_BITS - 1) +
+                            Config5::W0W.val(SLOT_BITS - 1) +
+                            Config5::FBT.val(SLOT_BITS - 1));
+    }
+
+    /// The DMA width and data register address for a sample, which the
+    /// top half of the register holds if it is 16 bits.
+    fn data_register(&self, register: &ReadWrite<u32>, format: Format) -> (Width, u32) {
+        let address = register as *const ReadWrite<u32> as u32;
+        match format.sample_size {
+            SampleSize::Bits16 => (Width::HalfWord, address + 2),
+            _ => (Width::Word, address),
+        }
+    }
+
+    /// Starts playing, first asking the client for two blocks.
+    pub fn start_playback(&'static self) -> ReturnCode {
+        let format = match self.format.get() {
+            Some(format) => format,
+            None => return ReturnCode::EOFF,
+        };
+        if self.playing.get() {
+            return ReturnCode::EALREADY;
+        }
+        let len = 2 * format.block_bytes();
+        let buffer = unsafe { &mut TX_BUF.0[..len] };
+        self.client.get().map(|client| {
+            let (first, second) = buffer.split_at_mut(len / 2);
+            client.play(first);
+            client.play(second);
+        });
+
+        let regs = self.regs();
+        let (width, register) = self.data_register(&regs.tx.dr[0], format);
+        let channel = unsafe { &dma::DMA_CHANNELS[TX_CHANNEL] };
+        channel.set_client(self);
+        channel.circular_to_peripheral(DmaPeripheral::I2s0Tx, buffer.as_ptr(), len, register, width);
+
+        if self.recording.get() {
+            regs.tx.csr.modify(ControlStatus::FWDE::SET);
+        } else {
+            regs.tx.csr.write(ControlStatus::E::SET +
+                              ControlStatus::BCE::SET +
+                              ControlStatus::FWDE::SET);
+        }
+        self.playing.set(true);
+        ReturnCode::SUCCESS
+    }
+
+    pub fn stop_playback(&self) {
+        if !self.playing.get() {
+            return;
+        }
+        self.playing.set(false);
+        unsafe { dma::DMA_CHANNELS[TX_CHANNEL].stop() };
+        if self.recording.get() {
+            self.regs().tx.csr.modify(ControlStatus::FWDE::CLEAR);
+        } else {
+            self.disable_transmitter();
+        }
+    }
+
+    /// Starts recording. The receiver must be enabled before the
+    /// transmitter it follows.
+    pub fn start_recording(&'static self) -> ReturnCode {
+        let format = match self.format.get() {
+            Some(format) => format,
+            None => return ReturnCode::EOFF,
+        };
+        if self.recording.get() {
+            return ReturnCode::EALREADY;
+        }
+        let len = 2 * format.block_bytes();
+        let buffer = unsafe { &mut RX_BUF.0[..len] };
+
+        let regs = self.regs();
+        let (width, register) = self.data_register(&regs.rx.dr[self.rx_line.get()], format);
+        let channel = unsafe { &dma::DMA_CHANNELS[RX_CHANNEL] };
+        channel.set_client(self);
+        channel.circular_from_peripheral(DmaPeripheral::I2s0Rx, register, buffer.as_mut_ptr(), len, width);
+
+        regs.rx.csr.write(ControlStatus::E::SET +
+                          ControlStatus::BCE::SET +
+                          ControlStatus::FRDE::SET);
+        if !self.playing.get() {
+            regs.tx.csr.write(ControlStatus::E::SET + ControlStatus::BCE::SET);
+        }
+        self.recording.set(true);
+        ReturnCode::SUCCESS
+    }
+
+    pub fn stop_recording(&self) {
+        if !self.recording.get() {
+            return;
+        }
+        self.recording.set(false);
+        let regs = self.regs();
+        regs.rx.csr.write(ControlStatus::FR::SET);
+        while regs.rx.csr.is_set(ControlStatus::E) {}
+        unsafe { dma::DMA_CHANNELS[RX_CHANNEL].stop() };
+        if !self.playing.get() {
+            self.disable_transmitter();
+        }
+    }
+
+    /// The transmitter finishes its frame before it stops.
+    fn disable_transmitter(&self) {
+        let regs = self.regs();
+        regs.tx.csr.write(ControlStatus::FR::SET);
+        while regs.tx.csr.is_set(ControlStatus::E) {}
+    }
+}
+
+impl DmaClient for Sai {
+    fn buffer_done(&self, channel: usize, half: usize) {
+        let block = match self.format.get() {
+            Some(format) => format.block_bytes(),
+            None => return,
+        };
+        let range = half * block..(half + 1) * block;
+        self.client.get().map(|client| {
+            if channel == TX_CHANNEL {
+                client.play(unsafe { &mut TX_BUF.0[range] });
+            } else {
+                client.recorded(unsafe { &RX_BUF.0[range] });
+            }
+        });
+    }
+}
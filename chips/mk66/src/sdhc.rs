@@ -0,0 +1,474 @@
+//! Implementation of the MK66 SD Host Controller (SDHC).
+//!
+//! Supports SD and SDHC/SDXC memory cards in 4-bit mode at 25MHz. Block
+//! transfers use the ADMA2 engine and run to completion before returning, so
+//! callers see a simple synchronous block device. Card initialization happens
+//! on demand, and again after the card is replaced.
+//!
+//! The Teensy 3.6 socket has no card detect switch, so insertion and removal
+//! are detected from the card's pull-up on DAT3. The DAT3 pin must be
+//! configured with a pull-down for this to work.
+
+use core::cell::Cell;
+use core::mem;
+use core::sync::atomic::{compiler_fence, Ordering};
+use kernel::ReturnCode;
+use kernel::common::regs::FieldValue;
+use clock;
+use nvic::{self, NvicIdx};
+use regs::sdhc::*;
+use sysmpu;
+
+pub const BLOCK_SIZE: usize = 512;
+
+/// A single ADMA2 descriptor moves at most 64KB, so this bounds the length of
+/// one transfer.
+const MAX_DESCRIPTORS: usize = 4;
+const MAX_DESCRIPTOR_LENGTH: usize = 0x1_0000;
+
+// Rough iteration bounds for polling loops. ACMD41 may take up to a second
+// to report that the card is ready.
+const COMMAND_TIMEOUT: usize = 1_000_000;
+const TRANSFER_TIMEOUT: usize = 50_000_000;
+const OP_COND_RETRIES: usize = 10_000;
+
+const IDENTIFICATION_HZ: u32 = 400_000;
+const TRANSFER_HZ: u32 = 25_000_000;
+
+// ACMD41 argument: host supports high capacity cards, 3.2-3.4V.
+const OCR_HCS: u32 = 1 << 30;
+const OCR_VOLTAGE: u32 = 0x0030_0000;
+const OCR_READY: u32 = 1 << 31;
+
+/// A block device with fixed 512-byte blocks.
+pub trait BlockDevice {
+    /// The number of blocks on the device, or zero if it is not available.
+    fn block_count(&self) -> u32;
+
+    /// Reads `buf.len() / BLOCK_SIZE` blocks starting at `block`.
+    fn read_blocks(&self, block: u32, buf: &mut [u8]) -> ReturnCode;
+
+    /// Writes `buf.len() / BLOCK_SIZE` blocks starting at `block`.
+    fn write_blocks(&self, block: u32, buf: &[u8]) -> ReturnCode;
+}
+
+pub trait Client {
+    fn card_detect_changed(&self, present: bool);
+}
+
+#[derive(Copy, Clone)]
+enum Response {
+    None,
+    R1,
+    R1b,
+    R2,
+    R3,
+    R6,
+    R7,
+}
+
+#[derive(Copy, Clone)]
+struct Card {
+    rca: u32,
+    high_capacity: bool,
+    blocks: u32,
+}
+
+// [SD Physical Layer Simplified Specification Section 4.2.3]
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct AdmaDescriptor {
+    attribute: u16,
+    length: u16,
+    address: u32,
+}
+
+const ADMA_VALID: u16 = 1 << 0;
+const ADMA_END: u16 = 1 << 1;
+const ADMA_TRANSFER: u16 = 0b10 << 4;
+
+static mut ADMA_TABLE: [AdmaDescriptor; MAX_DESCRIPTORS] =
+    [AdmaDescriptor { attribute: 0, length: 0, address: 0 }; MAX_DESCRIPTORS];
+
+pub static mut SDHC: Sdhc<'static> = Sdhc::new();
+
+pub struct Sdhc<'a> {
+    client: Cell<Option<&'a Client>>,
+    card: Cell<Option<Card>>,
+}
+
+// Extracts CSD bits `msb` to `lsb`. The controller strips the CRC, so the
+// response is the CSD shifted down by 8 bits.
+fn csd_bits(rsp: &[u32; 4], msb: usize, lsb: usize) -> u32 {
+    let mut value = 0;
+    for bit in (lsb..msb + 1).rev() {
+        let n = bit - 8;
+        value = (value << 1) | ((rsp[n / 32] >> (n % 32)) & 1);
+    }
+    value
+}
+
+// [SD Physical Layer Simplified Specification Section 5.3]
+fn csd_block_count(rsp: &[u32; 4]) -> u32 {
+    match csd_bits(rsp, 127, 126) {
+        0 => {
+            let c_size = csd_bits(rsp, 73, 62);
+            let c_size_mult = csd_bits(rsp, 49, 47);
+            let read_bl_len = csd_bits(rsp, 83, 80);
+            ((c_size + 1) << (c_size_mult + 2 + read_bl_len)) / BLOCK_SIZE as u32
+        },
+        _ => (csd_bits(rsp, 69, 48) + 1) * 1024,
+    }
+}
+
+impl<'a> Sdhc<'a> {
+    const fn new() -> Sdhc<'a> {
+        Sdhc {
+            client: Cell::new(None),
+            card: Cell::new(None),
+        }
+    }
+
+    fn regs(&self) -> &mut Registers {
+        unsafe { mem::transmute(SDHC_BASE) }
+    }
+
+    /// Resets the controller and enables card detection. The SD pins must
+    /// already be configured.
+    pub fn init(&self) -> ReturnCode {
+        use sim::{clocks, Clock};
+        clocks::SDHC.enable();
+        sysmpu::grant_access(sysmpu::Master::Sdhc);
+
+        let regs = self.regs();
+        regs.sysctl.modify(SystemControl::RSTA::SET);
+        if self.wait_while(|| regs.sysctl.is_set(SystemControl::RSTA), COMMAND_TIMEOUT) !=
+           ReturnCode::SUCCESS {
+            return ReturnCode::FAIL;
+        }
+
+        if self.set_clock(IDENTIFICATION_HZ) != ReturnCode::SUCCESS {
+            return ReturnCode::FAIL;
+        }
+        regs.proctl.write(ProtocolControl::EMODE::LittleEndian +
+                          ProtocolControl::DMAS::Adma2 +
+                          ProtocolControl::D3CD::SET);
+        regs.irqstaten.set(!0);
+        regs.irqstat.set(!0);
+
+        regs.irqsigen.write(Interrupt::CINS::SET + Interrupt::CRM::SET);
+        unsafe { nvic::enable(NvicIdx::SDHC); }
+        ReturnCode::SUCCESS
+    }
+
+    pub fn set_client(&self, client: &'a Client) {
+        self.client.set(Some(client));
+    }
+
+    pub fn is_card_present(&self) -> bool {
+        self.regs().prsstat.is_set(PresentState::CINST)
+    }
+
+    pub fn is_card_initialized(&self) -> bool {
+        self.card.get().is_some()
+    }
+
+    fn set_clock(&self, hz: u32) -> ReturnCode {
+        let base = clock::core_clock_hz();
+
+        // SD clock = base / prescaler / divisor, where the prescaler is a
+        // power of two up to 256 and the divisor is 1-16.
+        let mut prescaler = 1;
+        while prescaler < 256 && base / (prescaler * 16) > hz {
+            prescaler *= 2;
+        }
+        let mut divisor = 1;
+        while divisor < 16 && base / (prescaler * divisor) > hz {
+            divisor += 1;
+        }
+
+        let regs = self.regs();
+        regs.sysctl.modify(SystemControl::SDCLKEN::CLEAR);
+        regs.sysctl.modify(SystemControl::SDCLKFS.val(prescaler >> 1) +
+                           SystemControl::DVS.val(divisor - 1) +
+                           SystemControl::DTOCV.val(0xE));
+        let result = self.wait_while(|| !regs.prsstat.is_set(PresentState::SDSTB),
+                                     COMMAND_TIMEOUT);
+        regs.sysctl.modify(SystemControl::SDCLKEN::SET);
+        result
+    }
+
+    fn response_type(response: Response) -> FieldValue<u32, TransferType::Register> {
+        match response {
+            Response::None => TransferType::RSPTYP::None,
+            Response::R2 => TransferType::RSPTYP::Length136 + TransferType::CCCEN::SET,
+            Response::R3 => TransferType::RSPTYP::Length48,
+            Response::R1b => TransferType::RSPTYP::Length48Busy +
+                             TransferType::CCCEN::SET + TransferType::CICEN::SET,
+            Response::R1 | Response::R6 | Response::R7 => {
+                TransferType::RSPTYP::Length48 + TransferType::CCCEN::SET + TransferType::CICEN::SET
+            },
+        }
+    }
+
+    // Waits for `busy` to become false, for at most `timeout` polls. A card
+    // pulled out mid-transfer can leave the controller busy for good.
+    fn wait_while<F: Fn() -> bool>(&self, busy: F, timeout: usize) -> ReturnCode {
+        for _ in 0..timeout {
+            if !busy() {
+                return ReturnCode::SUCCESS;
+            }
+        }
+        ReturnCode::FAIL
+    }
+
+    // Waits for one of `flags` or an error, and clears them.
+    fn wait_for(&self, flags: u32, errors: u32, timeout: usize) -> ReturnCode {
+        let regs = self.regs();
+        for _ in 0..timeout {
+            let status = regs.irqstat.get();
+            if status & errors != 0 {
+                regs.irqstat.set(status & (errors | flags));
+                return ReturnCode::FAIL;
+            }
+            if status & flags == flags {
+                regs.irqstat.set(flags);
+                return ReturnCode::SUCCESS;
+            }
+        }
+        ReturnCode::EBUSY
+    }
+
+    fn send(&self,
+            index: u32,
+            arg: u32,
+            response: Response,
+            data: FieldValue<u32, TransferType::Register>)
+            -> ReturnCode {
+        let regs = self.regs();
+        let inhibited = || {
+            regs.prsstat.is_set(PresentState::CIHB) || regs.prsstat.is_set(PresentState::CDIHB)
+        };
+        if self.wait_while(inhibited, COMMAND_TIMEOUT) != ReturnCode::SUCCESS {
+            return ReturnCode::FAIL;
+        }
+
+        regs.irqstat.set(COMMAND_ERRORS | DATA_ERRORS |
+                         (Interrupt::CC::SET + Interrupt::TC::SET).value);
+        regs.cmdarg.set(arg);
+        regs.xfertyp.write(TransferType::CMDINX.val(index) + Sdhc::response_type(response) + data);
+
+        let mut result = self.wait_for(Interrupt::CC::SET.value, COMMAND_ERRORS, COMMAND_TIMEOUT);
+        if result == ReturnCode::SUCCESS {
+            if let Response::R1b = response {
+                result = self.wait_while(|| regs.prsstat.is_set(PresentState::DLA),
+                                         TRANSFER_TIMEOUT);
+            }
+        }
+        if result != ReturnCode::SUCCESS {
+            // The command line must be reset after an error.
+            regs.sysctl.modify(SystemControl::RSTC::SET);
+            if self.wait_while(|| regs.sysctl.is_set(SystemControl::RSTC), COMMAND_TIMEOUT) !=
+               ReturnCode::SUCCESS {
+                return ReturnCode::FAIL;
+            }
+        }
+        result
+    }
+
+    fn command(&self, index: u32, arg: u32, response: Response) -> ReturnCode {
+        self.send(index, arg, response, TransferType::DPSEL::CLEAR)
+    }
+
+    fn app_command(&self, index: u32, arg: u32, response: Response) -> ReturnCode {
+        let rca = self.card.get().map_or(0, |card| card.rca);
+        match self.command(55, rca << 16, Response::R1) {
+            ReturnCode::SUCCESS => self.command(index, arg, response),
+            err => err,
+        }
+    }
+
+    fn response(&self) -> [u32; 4] {
+        let rsp = &self.regs().cmdrsp;
+        [rsp[0].get(), rsp[1].get(), rsp[2].get(), rsp[3].get()]
+    }
+
+    /// Identifies the card and switches it to 4-bit transfer mode.
+    pub fn initialize_card(&self) -> ReturnCode {
+        self.card.set(None);
+        if !self.is_card_present() {
+            return ReturnCode::ENODEVICE;
+        }
+
+        macro_rules! try_cmd {
+            ($e:expr) => {
+                match $e {
+                    ReturnCode::SUCCESS => {},
+                    err => return err,
+                }
+            }
+        }
+
+        let regs = self.regs();
+        try_cmd!(self.set_clock(IDENTIFICATION_HZ));
+        regs.proctl.modify(ProtocolControl::DTW::OneBit);
+
+        // Send the 80 clocks the card needs after power up.
+        regs.sysctl.modify(SystemControl::INITA::SET);
+        try_cmd!(self.wait_while(|| regs.sysctl.is_set(SystemControl::INITA), COMMAND_TIMEOUT));
+
+        try_cmd!(self.command(0, 0, Response::None));
+
+        // Only version 2 cards answer CMD8, and only they may be high
+        // capacity.
+        let v2 = self.command(8, 0x1AA, Response::R7) == ReturnCode::SUCCESS &&
+                 self.response()[0] & 0xFFF == 0x1AA;
+        let hcs = if v2 { OCR_HCS } else { 0 };
+
+        let mut ocr = 0;
+        for _ in 0..OP_COND_RETRIES {
+            try_cmd!(self.app_command(41, hcs | OCR_VOLTAGE, Response::R3));
+            ocr = self.response()[0];
+            if ocr & OCR_READY != 0 {
+                break;
+            }
+        }
+        if ocr & OCR_READY == 0 {
+            return ReturnCode::FAIL;
+        }
+
+        try_cmd!(self.command(2, 0, Response::R2));
+        try_cmd!(self.command(3, 0, Response::R6));
+        let rca = self.response()[0] >> 16;
+
+        try_cmd!(self.command(9, rca << 16, Response::R2));
+        let blocks = csd_block_count(&self.response());
+
+        try_cmd!(self.command(7, rca << 16, Response::R1b));
+
+        self.card.set(Some(Card {
+            rca: rca,
+            high_capacity: ocr & OCR_HCS != 0,
+            blocks: blocks,
+        }));
+
+        try_cmd!(self.app_command(6, 2, Response::R1));
+        regs.proctl.modify(ProtocolControl::DTW::FourBit);
+        try_cmd!(self.command(16, BLOCK_SIZE as u32, Response::R1));
+
+        self.set_clock(TRANSFER_HZ)
+    }
+
+    fn transfer(&self, block: u32, address: usize, len: usize, read: bool) -> ReturnCode {
+        let card = match self.card.get() {
+            Some(card) => card,
+            None => return ReturnCode::EOFF,
+        };
+
+        let count = len / BLOCK_SIZE;
+        if len == 0 || len % BLOCK_SIZE != 0 || address % 4 != 0 ||
+           len > MAX_DESCRIPTORS * MAX_DESCRIPTOR_LENGTH {
+            return ReturnCode::EINVAL;
+        }
+        if block as u64 + count as u64 > card.blocks as u64 {
+            return ReturnCode::ESIZE;
+        }
+
+        unsafe {
+            let descriptors = (len + MAX_DESCRIPTOR_LENGTH - 1) / MAX_DESCRIPTOR_LENGTH;
+            for i in 0..descriptors {
+                let offset = i * MAX_DESCRIPTOR_LENGTH;
+                let length = if len - offset > MAX_DESCRIPTOR_LENGTH {
+                    MAX_DESCRIPTOR_LENGTH
+                } else {
+                    len - offset
+                };
+                let end = if i == descriptors - 1 { ADMA_END } else { 0 };
+                ADMA_TABLE[i] = AdmaDescriptor {
+                    attribute: ADMA_VALID | ADMA_TRANSFER | end,
+                    // A length of zero means 64KB.
+                    length: length as u16,
+                    address: (address + offset) as u32,
+                };
+            }
+            compiler_fence(Ordering::SeqCst);
+            self.regs().adsaddr.set(&ADMA_TABLE as *const _ as u32);
+        }
+
+        let regs = self.regs();
+        regs.blkattr.write(BlockAttributes::BLKSIZE.val(BLOCK_SIZE as u32) +
+                           BlockAttributes::BLKCNT.val(count as u32));
+
+        // DAT3 toggles during transfers, which looks like card removal.
+        regs.irqsigen.modify(Interrupt::CINS::CLEAR + Interrupt::CRM::CLEAR);
+
+        let arg = if card.high_capacity { block } else { block * BLOCK_SIZE as u32 };
+        let multiple = count > 1;
+        let index = match (read, multiple) {
+            (true, false) => 17,
+            (true, true) => 18,
+            (false, false) => 24,
+            (false, true) => 25,
+        };
+        let direction = if read { TransferType::DTDSEL::Read } else { TransferType::DTDSEL::Write };
+        let blocks = if multiple {
+            TransferType::MSBSEL::SET + TransferType::BCEN::SET + TransferType::AC12EN::SET
+        } else {
+            TransferType::MSBSEL::CLEAR
+        };
+
+        let mut result = self.send(index, arg, Response::R1,
+                                   TransferType::DPSEL::SET + TransferType::DMAEN::SET +
+                                   direction + blocks);
+        if result == ReturnCode::SUCCESS {
+            result = self.wait_for(Interrupt::TC::SET.value, DATA_ERRORS, TRANSFER_TIMEOUT);
+        }
+        if result == ReturnCode::SUCCESS {
+            // Writes finish when the card releases DAT0.
+            result = self.wait_while(|| regs.prsstat.is_set(PresentState::DLA), TRANSFER_TIMEOUT);
+        }
+        if result != ReturnCode::SUCCESS {
+            // Abort the transfer and reset the data path.
+            self.command(12, 0, Response::R1b);
+            regs.sysctl.modify(SystemControl::RSTD::SET);
+            if self.wait_while(|| regs.sysctl.is_set(SystemControl::RSTD), COMMAND_TIMEOUT) !=
+               ReturnCode::SUCCESS {
+                result = ReturnCode::FAIL;
+            }
+        }
+
+        regs.irqstat.write(Interrupt::CINS::SET + Interrupt::CRM::SET);
+        regs.irqsigen.modify(Interrupt::CINS::SET + Interrupt::CRM::SET);
+        result
+    }
+
+    pub fn handle_interrupt(&self) {
+        let regs = self.regs();
+        let status = regs.irqstat.get();
+        let changed = Interrupt::CINS::SET.value | Interrupt::CRM::SET.value;
+        if status & changed == 0 {
+            return;
+        }
+        regs.irqstat.set(status & changed);
+
+        let present = self.is_card_present();
+        if !present {
+            self.card.set(None);
+        }
+        self.client.get().map(|client| client.card_detect_changed(present));
+    }
+}
+
+impl<'a> BlockDevice for Sdhc<'a> {
+    fn block_count(&self) -> u32 {
+        self.card.get().map_or(0, |card| card.blocks)
+    }
+
+    fn read_blocks(&self, block: u32, buf: &mut [u8]) -> ReturnCode {
+        self.transfer(block, buf.as_mut_ptr() as usize, buf.len(), true)
+    }
+
+    fn write_blocks(&self, block: u32, buf: &[u8]) -> ReturnCode {
+        self.transfer(block, buf.as_ptr() as usize, buf.len(), false)
+    }
+}
@@ -2,7 +2,9 @@
 
 use core::mem;
 use regs::sim::*;
-use kernel::common::regs::FieldValue;
+use kernel::common::regs::{FieldValue, ReadWrite};
+
+pub use regs::sim::SystemOptions1::OSC32KSEL::Value as Osc32kSource;
 
 pub type Clock1 = FieldValue<u32, SystemClockGatingControl1::Register>;
 pub type Clock2 = FieldValue<u32, SystemClockGatingControl2::Register>;
@@ -145,3 +147,46 @@ pub fn set_dividers(core: u32, bus: u32, flash: u32) {
                         ClockDivider1::FlexBus.val(bus - 1) +
                         ClockDivider1::Flash.val(flash - 1));
 }
+
+/// Selects the source of ERCLK32K, the 32kHz clock used by the LPTMR and
+/// other low-power peripherals.
+pub fn set_osc32k_source(source: Osc32kSource) {
+    let sopt1: &mut ReadWrite<u32, SystemOptions1::Register> = unsafe { mem::transmute(SOPT1) };
+    sopt1.modify(SystemOptions1::OSC32KSEL.val(source as u32));
+}
+
+/// Clocks the USB-FS controller from the 48MHz internal reference, through
+/// the divider in CLKDIV2. The USB controller's clock recovery keeps the
+/// reference locked to the host's frame timing.
+pub fn select_usb_clock_irc48m() {
+    let regs: &mut Registers = unsafe { mem::transmute(SIM) };
+
+    regs.clkdiv2.set(0);
+    regs.sopt2.modify(SystemOptions2::USBSRC::Divided +
+                      SystemOptions2::PLLFLLSEL::Irc48M);
+}
+
+/// Powers the USB-HS PHY's PLL regulator, and clocks its slow logic from
+/// MCGIRCLK, which must be running.
+pub fn enable_usbhs_phy_regulator() {
+    let regs: &mut Registers = unsafe { mem::transmute(SIM) };
+
+    regs.sopt2.modify(SystemOptions2::USBREGEN::SET +
+                      SystemOptions2::USBSLSRC::McgIrClk);
+}
+
+/// Clocks the ENET RMII interface and its IEEE 1588 timer from the 50MHz
+/// reference on ENET_1588_CLKIN.
+pub fn select_enet_clocks_1588_clkin() {
+    let regs: &mut Registers = unsafe { mem::transmute(SIM) };
+
+    regs.sopt2.modify(SystemOptions2::RMIISRC::Enet1588ClkIn +
+                      SystemOptions2::TIMESRC::Enet1588ClkIn);
+}
+
+/// The chip's 128-bit unique identification number, most significant word
+/// first.
+pub fn unique_id() -> [u32; 4] {
+    let regs: &mut Registers = unsafe { mem::transmute(SIM) };
+    [regs.uidh.get(), regs.uidmh.get(), regs.uidml.get(), regs.uidl.get()]
+}
@@ -0,0 +1,34 @@
+//! Bus master permissions in the MK66 system memory protection unit.
+//!
+//! Out of reset the MPU's background region grants full access to the core,
+//! debugger, DMA and ENET masters, but denies the USB and SDHC controllers
+//! access to memory. Drivers for those controllers must grant their master
+//! access before starting DMA.
+
+use core::mem;
+use regs::sysmpu::*;
+
+#[derive(Copy, Clone)]
+pub enum Master {
+    Enet = 3,
+    UsbFs = 4,
+    Sdhc = 5,
+    UsbHs = 6,
+}
+
+/// Allows `master` to read and write all of memory.
+pub fn grant_access(master: Master) {
+    let regs: &mut Registers = unsafe { mem::transmute(SYSMPU) };
+
+    // Masters 0-3 have separate supervisor and user permissions, while
+    // masters 4-7 only have read and write enables.
+    let master = master as u32;
+    let bits = if master < 4 {
+        0x1F << (6 * master)
+    } else {
+        0b11 << (24 + 2 * (master - 4))
+    };
+
+    let access = regs.rgdaac[0].get();
+    regs.rgdaac[0].set(access | bits);
+}
@@ -0,0 +1,258 @@
+//! Implementation of the MK66 Touch Sensing Input (TSI).
+//!
+//! A scan charges and discharges an electrode a number of times, counting
+//! cycles of a reference oscillator; a finger adds capacitance and raises
+//! the count. Enabled channels are scanned one after another by software
+//! trigger. Each channel tracks a baseline, the count it settles at when
+//! untouched, which drifts with temperature and humidity. The hardware
+//! flags a count more than the channel's threshold above its baseline as
+//! out of range, which makes the channel touched. While it is touched, its
+//! baseline is frozen, and it is released once its count falls below half
+//! the threshold above the baseline.
+
+use core::cell::Cell;
+use core::mem;
+use kernel::ReturnCode;
+use nvic;
+use regs::tsi::*;
+
+pub const N_CHANNELS: usize = 16;
+
+/// An untouched baseline moves 1/2^n of the way to each new count.
+const BASELINE_SHIFT: i32 = 4;
+/// A threshold of 0 means 1/2^n of the baseline.
+const DEFAULT_THRESHOLD_SHIFT: u16 = 3;
+
+/// Charge currents are 500nA times 2^n, for n from 0 to 7.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScanConfig {
+    pub reference_current: u8,
+    pub electrode_current: u8,
+    /// Divides the electrode oscillator by 2^n, for n from 0 to 7.
+    pub prescaler: u8,
+    /// Electrode oscillations per scan, from 1 to 32.
+    pub scans: u8,
+}
+
+/// The settings Teensyduino's `touchRead` uses.
+pub const DEFAULT_CONFIG: ScanConfig = ScanConfig {
+    reference_current: 4,
+    electrode_current: 3,
+    prescaler: 2,
+    scans: 10,
+};
+
+pub trait Client {
+    fn touch_changed(&self, channel: usize, touched: bool);
+
+    /// Called when every enabled channel has been scanned once.
+    fn scan_done(&self);
+}
+
+struct Channel {
+    enabled: Cell<bool>,
+    threshold: Cell<u16>,
+    // 0 until the channel's first scan.
+    baseline: Cell<u16>,
+    count: Cell<u16>,
+    touched: Cell<bool>,
+}
+
+impl Channel {
+    const fn new() -> Channel {
+        Channel {
+            enabled: Cell::new(false),
+            threshold: Cell::new(0),
+            baseline: Cell::new(0),
+            count: Cell::new(0),
+            touched: Cell::new(false),
+        }
+    }
+}
+
+pub static mut TSI: Tsi<'static> = Tsi::new();
+
+pub struct Tsi<'a> {
+    client: Cell<Option<&'a Client>>,
+    config: Cell<ScanConfig>,
+    channels: [Channel; N_CHANNELS],
+    // The channel being scanned.
+    scanning: Cell<Option<usize>>,
+}
+
+impl<'a> Tsi<'a> {
+    const fn new() -> Tsi<'a> {
+        Tsi {
+            client: Cell::new(None),
+            config: Cell::new(DEFAULT_CONFIG),
+            channels: [
+                Channel::new(), Channel::new(), Channel::new(), Channel::new(),
+                Channel::new(), Channel::new(), Channel::new(), Channel::new(),
+                Channel::new(), Channel::new(), Channel::new(), Channel::new(),
+                Channel::new(), Channel::new(), Channel::new(), Channel::new(),
+            ],
+            scanning: Cell::new(None),
+        }
+    }
+
+    fn regs(&self) -> &mut Registers {
+        unsafe { mem::transmute(TSI0_BASE) }
+    }
+
+    pub fn set_client(&self, client: &'a Client) {
+        self.client.set(Some(client));
+    }
+
+    /// Takes effect from the next scan. Fails with `EBUSY` while scanning.
+    pub fn configure(&self, config: ScanConfig) -> ReturnCode {
+        if self.scanning.get().is_some() {
+            return ReturnCode::EBUSY;
+        }
+        if config.reference_current > 7 || config.electrode_current > 7 ||
+           config.prescaler > 7 || config.scans == 0 || config.scans > 32 {
+            return ReturnCode::EINVAL;
+        }
+        self.config.set(config);
+        ReturnCode::SUCCESS
+    }
+
+    /// Scans `channel` from now on. It is touched when its count is more
+    /// than `threshold` above its baseline, or 1/8 above if `threshold`
+    /// is 0. Its baseline is taken from its first scan.
+    pub fn enable_channel(&self, channel: usize, threshold: u16) -> ReturnCode {
+        if channel >= N_CHANNELS {
+            return ReturnCode::EINVAL;
+        }
+        let ch = &self.channels[channel];
+        ch.threshold.set(threshold);
+        ch.baseline.set(0);
+        ch.touched.set(false);
+        ch.enabled.set(true);
+        ReturnCode::SUCCESS
+    }
+
+    pub fn disable_channel(&self, channel: usize) -> ReturnCode {
+        if channel >= N_CHANNELS {
+            return ReturnCode::EINVAL;
+        }
+        self.channels[channel].enabled.set(false);
+        ReturnCode::SUCCESS
+    }
+
+    /// Retakes the baseline at the channel's next scan, which must be
+    /// untouched.
+    pub fn recalibrate(&self, channel: usize) {
+        if channel < N_CHANNELS {
+            let ch = &self.channels[channel];
+            ch.baseline.set(0);
+            ch.touched.set(false);
+        }
+    }
+
+    pub fn count(&self, channel: usize) -> u16 {
+        self.channels[channel].count.get()
+    }
+
+    pub fn baseline(&self, channel: usize) -> u16 {
+        self.channels[channel].baseline.get()
+    }
+
+    pub fn is_touched(&self, channel: usize) -> bool {
+        self.channels[channel].touched.get()
+    }
+
+    fn next_enabled(&self, from: usize) -> Option<usize> {
+        (from..N_CHANNELS).find(|&i| self.channels[i].enabled.get())
+    }
+
+    /// Scans each enabled channel once. Fails with `EBUSY` while
+    /// scanning, and with `EOFF` if no channel is enabled.
+    pub fn scan(&self) -> ReturnCode {
+        if self.scanning.get().is_some() {
+            return ReturnCode::EBUSY;
+        }
+        let first = match self.next_enabled(0) {
+            Some(channel) => channel,
+            None => return ReturnCode::EOFF,
+        };
+
+        use sim::{clocks, Clock};
+        clocks::TSI.enable();
+
+        let config = self.config.get();
+        self.regs().gencs.write(GeneralControl::OUTRGF::SET +
+                                GeneralControl::ESOR::EndOfScan +
+                                GeneralControl::REFCHRG.val(config.reference_current as u32) +
+                                GeneralControl::EXTCHRG.val(config.electrode_current as u32) +
+                                GeneralControl::PS.val(config.prescaler as u32) +
+                                GeneralControl::NSCN.val(config.scans as u32 - 1) +
+                                GeneralControl::TSIEN::SET +
+                                GeneralControl::TSIIEN::SET +
+                                GeneralControl::STM::Software +
+                                GeneralControl::EOSF::SET);
+        unsafe { nvic::enable(nvic::NvicIdx::TSI0) };
+
+        self.start_channel(first);
+        ReturnCode::SUCCESS
+    }
+
+    fn start_channel(&self, channel: usize) {
+        self.scanning.set(Some(channel));
+
+        let ch = &self.channels[channel];
+        let baseline = ch.baseline.get();
+        let upper = if baseline == 0 {
+            // Nothing counts as a touch while the baseline is taken.
+            0xFFFF
+        } else {
+            let threshold = match ch.threshold.get() {
+                0 => baseline >> DEFAULT_THRESHOLD_SHIFT,
+                threshold => threshold,
+            };
+            let margin = if ch.touched.get() { threshold / 2 } else { threshold };
+            baseline.saturating_add(margin)
+        };
+
+        let regs = self.regs();
+        regs.tshd.write(Threshold::THRESH.val(upper as u32) + Threshold::THRESL.val(0));
+        regs.data.write(Data::TSICH.val(channel as u32) + Data::SWTS::SET);
+    }
+
+    pub fn handle_interrupt(&self) {
+        let regs = self.regs();
+        let out_of_range = regs.gencs.is_set(GeneralControl::OUTRGF);
+        // Writing the flags back clears them.
+        regs.gencs.modify(GeneralControl::EOSF::SET);
+        let count = regs.data.read(Data::TSICNT) as u16;
+
+        let channel = match self.scanning.get() {
+            Some(channel) => channel,
+            None => return,
+        };
+        let ch = &self.channels[channel];
+        ch.count.set(count);
+
+        let baseline = ch.baseline.get();
+        if baseline == 0 {
+            ch.baseline.set(count);
+        } else {
+            if !out_of_range {
+                let delta = (count as i32 - baseline as i32) >> BASELINE_SHIFT;
+                ch.baseline.set((baseline as i32 + delta) as u16);
+            }
+            if out_of_range != ch.touched.get() {
+                ch.touched.set(out_of_range);
+                self.client.get().map(|client| client.touch_changed(channel, out_of_range));
+            }
+        }
+
+        match self.next_enabled(channel + 1) {
+            Some(next) => self.start_channel(next),
+            None => {
+                self.scanning.set(None);
+                regs.gencs.modify(GeneralControl::TSIEN::CLEAR);
+                self.client.get().map(|client| client.scan_done());
+            }
+        }
+    }
+}
@@ -0,0 +1,613 @@
+//! Implementation of the MK66 USB full-speed controller in device mode.
+//!
+//! The controller moves packets by DMA, following a buffer descriptor table
+//! (BDT) in RAM with an even and odd descriptor for each direction of each
+//! endpoint. Endpoint 0 uses buffers owned by this driver, and its packets
+//! are copied to and from the client's control buffer. Other endpoints
+//! transfer directly from the client's buffers, one packet at a time.
+//!
+//! The controller runs from the 48MHz internal reference, trimmed to the
+//! host's start of frame packets, so USB works whatever the core clock.
+
+use core::cell::Cell;
+use core::cmp;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{compiler_fence, Ordering};
+use kernel::common::cells::VolatileCell;
+use kernel::hil::usb::*;
+use nvic::{self, NvicIdx};
+use regs::usb::*;
+use sim;
+use sysmpu;
+
+pub const N_ENDPOINTS: usize = 16;
+
+/// The maximum packet size of a full-speed control or bulk endpoint.
+pub const MAX_PACKET_SIZE: usize = 64;
+
+// Buffer descriptor bits [K66 Sub-Family Reference Manual, Section 41.4.3]
+const BD_OWN: u32 = 1 << 7;
+const BD_DATA1: u32 = 1 << 6;
+const BD_DTS: u32 = 1 << 3;
+
+// Packet identifiers reported in the descriptor after a transaction.
+const PID_OUT: u32 = 0x1;
+const PID_IN: u32 = 0x9;
+const PID_SETUP: u32 = 0xD;
+
+// [USB 2.0 Specification, Section 9.3]
+const SETUP_SIZE: usize = 8;
+const REQUEST_DIRECTION_IN: u8 = 1 << 7;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct BufferDescriptor {
+    desc: u32,
+    addr: u32,
+}
+
+impl BufferDescriptor {
+    fn give(&mut self, addr: u32, len: usize, data1: bool) {
+        let data1 = if data1 { BD_DATA1 } else { 0 };
+        unsafe {
+            ptr::write_volatile(&mut self.addr, addr);
+            compiler_fence(Ordering::SeqCst);
+            ptr::write_volatile(&mut self.desc, ((len as u32) << 16) | BD_OWN | BD_DTS | data1);
+        }
+    }
+
+    fn release(&mut self) {
+        unsafe { ptr::write_volatile(&mut self.desc, 0); }
+    }
+
+    fn pid(&self) -> u32 {
+        (unsafe { ptr::read_volatile(&self.desc) } >> 2) & 0xF
+    }
+
+    fn byte_count(&self) -> usize {
+        ((unsafe { ptr::read_volatile(&self.desc) } >> 16) & 0x3FF) as usize
+    }
+}
+
+/// The table must be 512-byte aligned, as the controller only takes the
+/// upper 23 bits of its address.
+#[repr(C, align(512))]
+struct BufferDescriptorTable([BufferDescriptor; N_ENDPOINTS * 4]);
+
+static mut BDT: BufferDescriptorTable =
+    BufferDescriptorTable([BufferDescriptor { desc: 0, addr: 0 }; N_ENDPOINTS * 4]);
+
+fn bd(endpoint: usize, tx: bool, odd: bool) -> &'static mut BufferDescriptor {
+    unsafe { &mut BDT.0[(endpoint << 2) | ((tx as usize) << 1) | odd as usize] }
+}
+
+#[repr(C, align(4))]
+struct Ep0Buffers {
+    rx: [[u8; MAX_PACKET_SIZE]; 2],
+    tx: [[u8; MAX_PACKET_SIZE]; 2],
+}
+
+static mut EP0_BUFFERS: Ep0Buffers = Ep0Buffers {
+    rx: [[0; MAX_PACKET_SIZE]; 2],
+    tx: [[0; MAX_PACKET_SIZE]; 2],
+};
+
+/// The stage of the current control transfer on endpoint 0.
+#[derive(Copy, Clone, PartialEq)]
+enum Ep0State {
+    Idle,
+    /// Sending the data stage; more packets follow the one in flight.
+    DataIn,
+    /// The last packet of the data stage is in flight.
+    LastIn,
+    /// Waiting for the host's empty status packet.
+    StatusOut,
+    /// Receiving `remaining` more bytes of data stage.
+    DataOut(usize),
+    /// Our empty status packet is in flight.
+    StatusIn,
+}
+
+struct Endpoint<'a> {
+    transfer_type: Cell<Option<TransferType>>,
+    in_buffer: Cell<Option<&'a [VolatileCell<u8>]>>,
+    out_buffer: Cell<Option<&'a [VolatileCell<u8>]>>,
+    // The bank and data toggle of the next packet in each direction.
+    in_odd: Cell<bool>,
+    in_data1: Cell<bool>,
+    out_odd: Cell<bool>,
+    out_data1: Cell<bool>,
+    in_busy: Cell<bool>,
+    out_armed: Cell<bool>,
+}
+
+impl<'a> Endpoint<'a> {
+    const fn new() -> Endpoint<'a> {
+        Endpoint {
+            transfer_type: Cell::new(None),
+            in_buffer: Cell::new(None),
+            out_buffer: Cell::new(None),
+            in_odd: Cell::new(false),
+            in_data1: Cell::new(false),
+            out_odd: Cell::new(false),
+            out_data1: Cell::new(false),
+            in_busy: Cell::new(false),
+            out_armed: Cell::new(false),
+        }
+    }
+
+    fn reset(&self) {
+        self.in_odd.set(false);
+        self.in_data1.set(false);
+        self.out_odd.set(false);
+        self.out_data1.set(false);
+        self.in_busy.set(false);
+        self.out_armed.set(false);
+    }
+}
+
+pub static mut USB0: Usb<'static> = Usb::new();
+
+pub struct Usb<'a> {
+    client: Cell<Option<&'a Client<'a>>>,
+    ctrl_buffer: Cell<Option<&'a [VolatileCell<u8>]>>,
+    ep0_state: Cell<Ep0State>,
+    ep0_tx_odd: Cell<bool>,
+    ep0_tx_data1: Cell<bool>,
+    address: Cell<u8>,
+    endpoints: [Endpoint<'a>; N_ENDPOINTS],
+}
+
+impl<'a> Usb<'a> {
+    const fn new() -> Usb<'a> {
+        Usb {
+            client: Cell::new(None),
+            ctrl_buffer: Cell::new(None),
+            ep0_state: Cell::new(Ep0State::Idle),
+            ep0_tx_odd: Cell::new(false),
+            ep0_tx_data1: Cell::new(false),
+            address: Cell::new(0),
+            endpoints: [
+                Endpoint::new(), Endpoint::new(), Endpoint::new(), Endpoint::new(),
+                Endpoint::new(), Endpoint::new(), Endpoint::new(), Endpoint::new(),
+                Endpoint::new(), Endpoint::new(), Endpoint::new(), Endpoint::new(),
+                Endpoint::new(), Endpoint::new(), Endpoint::new(), Endpoint::new(),
+            ],
+        }
+    }
+
+    fn regs(&self) -> &mut Registers {
+        unsafe { mem::transmute(USB0_BASE) }
+    }
+
+    pub fn set_client(&self, client: &'a Client<'a>) {
+        self.client.set(Some(client));
+    }
+
+    /// True once the host has given the device an address.
+    pub fn is_addressed(&self) -> bool {
+        self.regs().addr.read(Address::ADDR) != 0
+    }
+
+    /// Handles pending controller events without relying on the interrupt,
+    /// for use when interrupts are disabled.
+    pub fn poll(&self) {
+        if self.regs().istat.get() & self.regs().inten.get() != 0 {
+            self.handle_interrupt();
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        let regs = self.regs();
+
+        loop {
+            let status = regs.istat.get() & regs.inten.get();
+
+            if status & Interrupt::USBRST::SET.value != 0 {
+                self.bus_reset();
+                return;
+            }
+
+            if status & Interrupt::STALL::SET.value != 0 {
+                // A stall on endpoint 0 lasts until the host acknowledges it.
+                regs.endpt[0].ctl.modify(EndpointControl::EPSTALL::CLEAR);
+                regs.istat.write(Interrupt::STALL::SET);
+            }
+
+            if status & Interrupt::ERROR::SET.value != 0 {
+                regs.errstat.set(0xFF);
+                regs.istat.write(Interrupt::ERROR::SET);
+            }
+
+            if status & Interrupt::SLEEP::SET.value != 0 {
+                regs.istat.write(Interrupt::SLEEP::SET);
+            }
+
+            if status & Interrupt::TOKDNE::SET.value == 0 {
+                return;
+            }
+
+            // STAT describes the oldest completed token, and clearing TOKDNE
+            // advances to the next one.
+            let endpoint = regs.stat.read(Status::ENDP) as usize;
+            let tx = regs.stat.is_set(Status::TX);
+            let odd = regs.stat.is_set(Status::ODD);
+            regs.istat.write(Interrupt::TOKDNE::SET);
+
+            if endpoint == 0 {
+                self.ep0_token(tx, odd);
+            } else if tx {
+                self.in_complete(endpoint);
+            } else {
+                self.out_complete(endpoint, bd(endpoint, false, odd).byte_count());
+            }
+        }
+    }
+
+    fn bus_reset(&self) {
+        let regs = self.regs();
+
+        regs.ctl.modify(Control::ODDRST::SET);
+        for endpoint in 1..N_ENDPOINTS {
+            regs.endpt[endpoint].ctl.set(0);
+            self.endpoints[endpoint].reset();
+            for &(tx, odd) in [(false, false), (false, true), (true, false), (true, true)].iter() {
+                bd(endpoint, tx, odd).release();
+            }
+        }
+
+        let rx = unsafe { &EP0_BUFFERS.rx };
+        bd(0, false, false).give(rx[0].as_ptr() as u32, MAX_PACKET_SIZE, false);
+        bd(0, false, true).give(rx[1].as_ptr() as u32, MAX_PACKET_SIZE, false);
+        bd(0, true, false).release();
+        bd(0, true, true).release();
+        self.ep0_tx_odd.set(false);
+        self.ep0_state.set(Ep0State::Idle);
+        regs.endpt[0].ctl.write(EndpointControl::EPRXEN::SET +
+                                EndpointControl::EPTXEN::SET +
+                                EndpointControl::EPHSHK::SET);
+
+        regs.errstat.set(0xFF);
+        regs.istat.set(0xFF);
+        regs.addr.set(0);
+        regs.erren.set(0xFF);
+        regs.inten.write(Interrupt::USBRST::SET +
+                         Interrupt::ERROR::SET +
+                         Interrupt::TOKDNE::SET +
+                         Interrupt::SLEEP::SET +
+                         Interrupt::STALL::SET);
+        regs.ctl.write(Control::USBENSOFEN::SET);
+
+        self.client.get().map(|client| client.bus_reset());
+    }
+
+    fn ep0_stall(&self) {
+        self.ep0_state.set(Ep0State::Idle);
+        self.regs().endpt[0].ctl.modify(EndpointControl::EPSTALL::SET);
+    }
+
+    /// Queues a packet of `len` bytes from the control buffer on endpoint 0.
+    fn ep0_send(&self, len: usize) {
+        let odd = self.ep0_tx_odd.get();
+        let tx = unsafe { &mut EP0_BUFFERS.tx[odd as usize] };
+        self.ctrl_buffer.get().map(|buf| {
+            for (dst, src) in tx.iter_mut().zip(buf.iter()).take(len) {
+                *dst = src.get();
+            }
+        });
+
+        bd(0, true, odd).give(tx.as_ptr() as u32, len, self.ep0_tx_data1.get());
+        self.ep0_tx_odd.set(!odd);
+        self.ep0_tx_data1.set(!self.ep0_tx_data1.get());
+    }
+
+    fn ep0_status_in(&self) {
+        self.client.get().map(|client| client.ctrl_status(0));
+        self.ep0_state.set(Ep0State::StatusIn);
+        self.ep0_send(0);
+    }
+
+    fn ep0_data_in(&self) {
+        let result = self.client.get().map_or(CtrlInResult::Error, |client| client.ctrl_in(0));
+        match result {
+            CtrlInResult::Packet(len, last) => {
+                self.ep0_state.set(if last { Ep0State::LastIn } else { Ep0State::DataIn });
+                self.ep0_send(cmp::min(len, MAX_PACKET_SIZE));
+            },
+            _ => self.ep0_stall(),
+        }
+    }
+
+    fn ep0_token(&self, tx: bool, odd: bool) {
+        if tx {
+            match self.ep0_state.get() {
+                Ep0State::DataIn => self.ep0_data_in(),
+                Ep0State::LastIn => self.ep0_state.set(Ep0State::StatusOut),
+                Ep0State::StatusIn => {
+                    self.ep0_state.set(Ep0State::Idle);
+                    self.client.get().map(|client| client.ctrl_status_complete(0));
+                },
+                _ => {},
+            }
+            return;
+        }
+
+        let descriptor = bd(0, false, odd);
+        let pid = descriptor.pid();
+        let len = cmp::min(descriptor.byte_count(), MAX_PACKET_SIZE);
+        let rx = unsafe { &EP0_BUFFERS.rx[odd as usize] };
+
+        if pid == PID_SETUP || (pid == PID_OUT && len > 0) {
+            self.ctrl_buffer.get().map(|buf| {
+                for (dst, src) in buf.iter().zip(rx.iter()).take(len) {
+                    dst.set(*src);
+                }
+            });
+        }
+
+        // The next packet in this bank follows a SETUP or an OUT, both of
+        // which are followed by DATA1.
+        descriptor.give(rx.as_ptr() as u32, MAX_PACKET_SIZE, true);
+
+        if pid == PID_SETUP {
+            self.ep0_setup(len);
+        } else if pid == PID_OUT {
+            self.ep0_out(len);
+        }
+    }
+
+    fn ep0_setup(&self, len: usize) {
+        // A SETUP cancels whatever remained of the previous transfer.
+        bd(0, true, false).release();
+        bd(0, true, true).release();
+        self.ep0_tx_data1.set(true);
+
+        let mut request = [0; SETUP_SIZE];
+        self.ctrl_buffer.get().map(|buf| {
+            for (dst, src) in request.iter_mut().zip(buf.iter()) {
+                *dst = src.get();
+            }
+        });
+
+        let result = if len == SETUP_SIZE {
+            self.client.get().map_or(CtrlSetupResult::ErrGeneric, |client| client.ctrl_setup(0))
+        } else {
+            CtrlSetupResult::ErrBadLength
+        };
+
+        match result {
+            CtrlSetupResult::Ok | CtrlSetupResult::OkSetAddress => {
+                let length = request[6] as usize | (request[7] as usize) << 8;
+                if length == 0 {
+                    self.ep0_status_in();
+                } else if request[0] & REQUEST_DIRECTION_IN != 0 {
+                    self.ep0_data_in();
+                } else {
+                    self.ep0_state.set(Ep0State::DataOut(length));
+                }
+            },
+            _ => self.ep0_stall(),
+        }
+
+        // Receiving a SETUP suspends token processing until it is cleared.
+        self.regs().ctl.modify(Control::TXSUSPENDTOKENBUSY::CLEAR);
+    }
+
+    fn ep0_out(&self, len: usize) {
+        match self.ep0_state.get() {
+            Ep0State::StatusOut => {
+                self.ep0_state.set(Ep0State::Idle);
+                self.client.get().map(|client| {
+                    client.ctrl_status(0);
+                    client.ctrl_status_complete(0);
+                });
+            },
+            Ep0State::DataOut(remaining) => {
+                let result = self.client.get().map_or(CtrlOutResult::Halted, |client| {
+                    client.ctrl_out(0, len as u32)
+                });
+                match result {
+                    CtrlOutResult::Ok => {
+                        if len >= remaining || len < MAX_PACKET_SIZE {
+                            self.ep0_status_in();
+                        } else {
+                            self.ep0_state.set(Ep0State::DataOut(remaining - len));
+                        }
+                    },
+                    _ => self.ep0_stall(),
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn arm_out(&self, endpoint: usize) {
+        let ep = &self.endpoints[endpoint];
+        ep.out_buffer.get().map(|buf| {
+            let len = cmp::min(buf.len(), MAX_PACKET_SIZE);
+            bd(endpoint, false, ep.out_odd.get()).give(buf.as_ptr() as u32, len, ep.out_data1.get());
+            ep.out_armed.set(true);
+        });
+    }
+
+    fn out_complete(&self, endpoint: usize, len: usize) {
+        let ep = &self.endpoints[endpoint];
+        ep.out_odd.set(!ep.out_odd.get());
+        ep.out_data1.set(!ep.out_data1.get());
+        ep.out_armed.set(false);
+
+        let transfer_type = ep.transfer_type.get().unwrap_or(TransferType::Bulk);
+        let result = self.client.get().map_or(OutResult::Error, |client| {
+            client.packet_out(transfer_type, endpoint, len as u32)
+        });
+        match result {
+            OutResult::Ok => self.arm_out(endpoint),
+            OutResult::Delay => {},
+            OutResult::Error => self.stall(endpoint),
+        }
+    }
+
+    fn in_complete(&self, endpoint: usize) {
+        let ep = &self.endpoints[endpoint];
+        ep.in_odd.set(!ep.in_odd.get());
+        ep.in_data1.set(!ep.in_data1.get());
+        ep.in_busy.set(false);
+
+        self.client.get().map(|client| client.packet_transmitted(endpoint));
+    }
+
+    fn stall(&self, endpoint: usize) {
+        self.regs().endpt[endpoint].ctl.modify(EndpointControl::EPSTALL::SET);
+    }
+
+    /// Clears a halt set by the host or by a client error, resetting the
+    /// endpoint's data toggles as the host expects.
+    pub fn endpoint_clear_halt(&self, endpoint: usize) {
+        if endpoint == 0 || endpoint >= N_ENDPOINTS {
+            return;
+        }
+        self.regs().endpt[endpoint].ctl.modify(EndpointControl::EPSTALL::CLEAR);
+        let ep = &self.endpoints[endpoint];
+        ep.in_data1.set(false);
+        ep.out_data1.set(false);
+        if ep.out_armed.get() {
+            // Re-arm with the reset toggle.
+            self.arm_out(endpoint);
+        }
+    }
+
+    fn endpoint_enable(&self, transfer_type: TransferType, endpoint: usize, tx: bool, rx: bool) {
+        let ep = &self.endpoints[endpoint];
+        ep.transfer_type.set(Some(transfer_type));
+        ep.reset();
+
+        let mut ctl = EndpointControl::EPCTLDIS::SET;
+        if transfer_type != TransferType::Isochronous {
+            ctl = ctl + EndpointControl::EPHSHK::SET;
+        }
+        if tx {
+            ctl = ctl + EndpointControl::EPTXEN::SET;
+        }
+        if rx {
+            ctl = ctl + EndpointControl::EPRXEN::SET;
+        }
+        self.regs().endpt[endpoint].ctl.modify(ctl);
+
+        if rx {
+            self.arm_out(endpoint);
+        }
+    }
+}
+
+impl<'a> UsbController<'a> for Usb<'a> {
+    fn endpoint_set_ctrl_buffer(&self, buf: &'a [VolatileCell<u8>]) {
+        if buf.len() < MAX_PACKET_SIZE {
+            panic!("USB control buffer is shorter than a packet");
+        }
+        self.ctrl_buffer.set(Some(buf));
+    }
+
+    fn endpoint_set_in_buffer(&self, endpoint: usize, buf: &'a [VolatileCell<u8>]) {
+        self.endpoints[endpoint].in_buffer.set(Some(buf));
+    }
+
+    fn endpoint_set_out_buffer(&self, endpoint: usize, buf: &'a [VolatileCell<u8>]) {
+        self.endpoints[endpoint].out_buffer.set(Some(buf));
+    }
+
+    /// The controller only runs at full speed, so `speed` is ignored.
+    fn enable_as_device(&self, _speed: DeviceSpeed) {
+        use sim::{clocks, Clock};
+
+        sim::select_usb_clock_irc48m();
+        clocks::USBOTG.enable();
+        sysmpu::grant_access(sysmpu::Master::UsbFs);
+
+        let regs = self.regs();
+        regs.usbtrc0.write(TransceiverControl::USBRESET::SET);
+        while regs.usbtrc0.is_set(TransceiverControl::USBRESET) {}
+
+        let bdt = unsafe { &BDT as *const BufferDescriptorTable as u32 };
+        regs.bdtpage1.set((bdt >> 8) as u8);
+        regs.bdtpage2.set((bdt >> 16) as u8);
+        regs.bdtpage3.set((bdt >> 24) as u8);
+
+        regs.istat.set(0xFF);
+        regs.errstat.set(0xFF);
+        regs.otgistat.set(0xFF);
+
+        // Bit 6 is undocumented, but the controller does not work without
+        // it.
+        regs.usbtrc0.set(regs.usbtrc0.get() | 0x40);
+
+        regs.clk_recover_irc_en.write(IrcEnable::IRC_EN::SET + IrcEnable::REG_EN::SET);
+        regs.clk_recover_ctrl.write(ClockRecoveryControl::CLOCK_RECOVER_EN::SET +
+                                    ClockRecoveryControl::RESTART_IFRTRIM_EN::SET);
+
+        regs.ctl.write(Control::USBENSOFEN::SET);
+        regs.usbctrl.set(0);
+        regs.inten.write(Interrupt::USBRST::SET);
+        unsafe { nvic::enable(NvicIdx::USBFS_OTG); }
+    }
+
+    fn attach(&self) {
+        self.regs().control.write(OtgControl::DPPULLUPNONOTG::SET);
+    }
+
+    fn detach(&self) {
+        self.regs().control.set(0);
+    }
+
+    /// The address takes effect when `enable_address` is called, which must
+    /// wait until the status stage of SET_ADDRESS is complete.
+    fn set_address(&self, addr: u16) {
+        self.address.set(addr as u8);
+    }
+
+    fn enable_address(&self) {
+        self.regs().addr.write(Address::ADDR.val(self.address.get()));
+    }
+
+    fn endpoint_in_enable(&self, transfer_type: TransferType, endpoint: usize) {
+        self.endpoint_enable(transfer_type, endpoint, true, false);
+    }
+
+    fn endpoint_out_enable(&self, transfer_type: TransferType, endpoint: usize) {
+        self.endpoint_enable(transfer_type, endpoint, false, true);
+    }
+
+    fn endpoint_in_out_enable(&self, transfer_type: TransferType, endpoint: usize) {
+        self.endpoint_enable(transfer_type, endpoint, true, true);
+    }
+
+    fn endpoint_resume_in(&self, endpoint: usize) {
+        let ep = &self.endpoints[endpoint];
+        if ep.in_busy.get() || ep.transfer_type.get().is_none() {
+            return;
+        }
+
+        let transfer_type = ep.transfer_type.get().unwrap_or(TransferType::Bulk);
+        let result = self.client.get().map_or(InResult::Delay, |client| {
+            client.packet_in(transfer_type, endpoint)
+        });
+        match result {
+            InResult::Packet(len) => {
+                ep.in_buffer.get().map(|buf| {
+                    let len = cmp::min(len, cmp::min(buf.len(), MAX_PACKET_SIZE));
+                    bd(endpoint, true, ep.in_odd.get()).give(buf.as_ptr() as u32, len, ep.in_data1.get());
+                    ep.in_busy.set(true);
+                });
+            },
+            InResult::Delay => {},
+            InResult::Error => self.stall(endpoint),
+        }
+    }
+
+    fn endpoint_resume_out(&self, endpoint: usize) {
+        let ep = &self.endpoints[endpoint];
+        if !ep.out_armed.get() && ep.transfer_type.get().is_some() {
+            self.arm_out(endpoint);
+        }
+    }
+}
@@ -0,0 +1,983 @@
+//! Implementation of the MK66 USB high-speed controller in host mode.
+//!
+//! The controller follows the EHCI model: it walks lists of queue heads in
+//! RAM, one for each open pipe, and runs the transfer descriptors queued on
+//! them. Control and bulk pipes sit on the circular asynchronous schedule,
+//! and interrupt pipes on the periodic schedule, which polls each of them
+//! once a frame. Full and low speed devices are reached with split
+//! transactions, through the controller's embedded transaction translator
+//! on the root port, or a high-speed hub's.
+//!
+//! Control and bulk transfers are split-phase: each pipe runs one at a
+//! time, holding the caller's buffer until the completion interrupt hands
+//! it back to the client with the result. The controller's first general
+//! purpose timer ticks while transfers are running, to time out those that
+//! take too long. Interrupt pipes receive in the background, and report
+//! each packet to the client.
+//!
+//! A queue head taken off a schedule may still be in use by the controller
+//! until it has moved on, so pipes that are closed, or cancelled after a
+//! timeout, are only retired once the controller has answered the async
+//! advance doorbell, or for interrupt pipes, once a tick has passed.
+
+use core::cell::Cell;
+use core::cmp;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{compiler_fence, Ordering};
+use kernel::ReturnCode;
+use kernel::common::cells::TakeCell;
+use kernel::common::regs::FieldValue;
+use mcg;
+use nvic::{self, NvicIdx};
+use regs::usbhs::*;
+use sim;
+use sysmpu;
+
+pub const N_PIPES: usize = 12;
+
+/// The largest packet an interrupt pipe receives. Hubs and boot keyboards
+/// send far less.
+pub const MAX_INTERRUPT_PACKET_SIZE: usize = 64;
+
+/// A transfer descriptor spans five 4KB pages, so a transfer of this length
+/// fits whatever the buffer's alignment.
+pub const MAX_TRANSFER_SIZE: usize = 16 * 1024;
+
+const PERIODIC_LIST_LEN: usize = 32;
+
+// Time limits, in milliseconds, and the timer tick that measures them.
+const CONTROL_TIMEOUT_MS: u32 = 500;
+const BULK_TIMEOUT_MS: u32 = 5000;
+const TICK_MS: u32 = 10;
+
+// Link pointer bits [EHCI Specification, Section 3.1]
+const LINK_TERMINATE: u32 = 1 << 0;
+const LINK_QH: u32 = 1 << 1;
+
+// Queue head endpoint characteristics [EHCI Specification, Section 3.6.2]
+const QH_NAK_RELOAD: u32 = 15 << 28;
+const QH_CONTROL_ENDPOINT: u32 = 1 << 27;
+const QH_HEAD: u32 = 1 << 15;
+const QH_TOGGLE_FROM_QTD: u32 = 1 << 14;
+
+// Queue head endpoint capabilities: one transaction per microframe, and
+// for interrupt pipes, the microframes that start and complete them.
+const QH_MULT_ONE: u32 = 1 << 30;
+const QH_START_MASK: u32 = 0x01;
+const QH_COMPLETE_MASK: u32 = 0x1C << 8;
+
+// Transfer descriptor token [EHCI Specification, Section 3.5.3]
+const TOKEN_TOGGLE: u32 = 1 << 31;
+const TOKEN_IOC: u32 = 1 << 15;
+const TOKEN_CERR: u32 = 3 << 10;
+const TOKEN_PID_OUT: u32 = 0 << 8;
+const TOKEN_PID_IN: u32 = 1 << 8;
+const TOKEN_PID_SETUP: u32 = 2 << 8;
+const TOKEN_ACTIVE: u32 = 1 << 7;
+const TOKEN_HALTED: u32 = 1 << 6;
+const TOKEN_BUFFER_ERROR: u32 = 1 << 5;
+const TOKEN_BABBLE: u32 = 1 << 4;
+const TOKEN_TRANSACTION_ERROR: u32 = 1 << 3;
+
+const SETUP_STAGE: usize = 0;
+const DATA_STAGE: usize = 1;
+const STATUS_STAGE: usize = 2;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Speed {
+    Full = 0,
+    Low = 1,
+    High = 2,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PipeType {
+    Control,
+    Bulk,
+    Interrupt,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TransferError {
+    /// The device stalled the endpoint.
+    Stall,
+    /// The device did not finish the transfer in time.
+    Timeout,
+    /// The device stopped responding, or sent corrupt or excess data.
+    Failed,
+    /// The pipe is not open, or is of the wrong type.
+    Invalid,
+    /// The pipe is already running a transfer.
+    Busy,
+    /// The pipe was closed, or the controller reset, before the transfer
+    /// finished.
+    Cancelled,
+}
+
+#[derive(Copy, Clone)]
+pub struct Pipe {
+    pub pipe_type: PipeType,
+    pub address: u8,
+    /// The endpoint address, with the direction in the top bit for bulk
+    /// and interrupt pipes.
+    pub endpoint: u8,
+    pub max_packet_size: u16,
+    pub speed: Speed,
+    /// For full and low speed devices, the high-speed hub whose transaction
+    /// translator reaches the device, and the hub port it is behind. Both
+    /// are zero on the root port.
+    pub tt_hub: u8,
+    pub tt_port: u8,
+}
+
+pub trait Client<'a> {
+    /// Called when a device is plugged into or unplugged from the root
+    /// port.
+    fn port_changed(&'a self, connected: bool);
+
+    /// Called when a control or bulk transfer finishes, with its buffer and
+    /// the number of bytes moved.
+    fn transfer_done(&'a self, pipe: usize, buffer: &'static mut [u8], result: Result<usize, TransferError>);
+
+    /// Called with each packet received on an interrupt pipe, or with the
+    /// error that stopped the pipe.
+    fn packet_received(&'a self, pipe: usize, result: Result<&[u8], TransferError>);
+
+    /// Called when the controller stopped on a bus error and was reset.
+    /// Every pipe has been closed, and a device still plugged in is
+    /// reported again through `port_changed`.
+    fn host_error(&'a self);
+}
+
+#[repr(C, align(32))]
+#[derive(Copy, Clone)]
+struct QueueHead {
+    horizontal: u32,
+    characteristics: u32,
+    capabilities: u32,
+    current: u32,
+    // The transfer overlay, the controller's working copy of the current
+    // transfer descriptor.
+    next: u32,
+    alt_next: u32,
+    token: u32,
+    buffers: [u32; 5],
+}
+
+#[repr(C, align(32))]
+#[derive(Copy, Clone)]
+struct TransferDescriptor {
+    next: u32,
+    alt_next: u32,
+    token: u32,
+    buffers: [u32; 5],
+}
+
+#[repr(C, align(4))]
+#[derive(Copy, Clone)]
+struct PipeMemory {
+    qh: QueueHead,
+    // The stages of a control transfer. Other transfers use the first.
+    qtds: [TransferDescriptor; 3],
+    setup: [u8; 8],
+    // Where interrupt pipes receive.
+    buffer: [u8; MAX_INTERRUPT_PACKET_SIZE],
+}
+
+const EMPTY_QH: QueueHead = QueueHead {
+    horizontal: LINK_TERMINATE,
+    characteristics: 0,
+    capabilities: 0,
+    current: 0,
+    next: LINK_TERMINATE,
+    alt_next: LINK_TERMINATE,
+    token: 0,
+    buffers: [0; 5],
+};
+
+const EMPTY_QTD: TransferDescriptor = TransferDescriptor {
+    next: LINK_TERMINATE,
+    alt_next: LINK_TERMINATE,
+    token: 0,
+    buffers: [0; 5],
+};
+
+static mut PIPE_MEMORY: [PipeMemory; N_PIPES] = [PipeMemory {
+    qh: EMPTY_QH,
+    qtds: [EMPTY_QTD; 3],
+    setup: [0; 8],
+    buffer: [0; MAX_INTERRUPT_PACKET_SIZE],
+}; N_PIPES];
+
+/// The permanent head of the asynchronous schedule, which never runs
+/// transfers.
+static mut ASYNC_HEAD: QueueHead = EMPTY_QH;
+
+/// The controller takes the frame list's address in 4KB units. Every entry
+/// points to the first interrupt pipe, so each is polled every frame.
+#[repr(C, align(4096))]
+struct PeriodicList([u32; PERIODIC_LIST_LEN]);
+
+static mut PERIODIC_LIST: PeriodicList = PeriodicList([LINK_TERMINATE; PERIODIC_LIST_LEN]);
+
+fn get(field: &u32) -> u32 {
+    unsafe { ptr::read_volatile(field) }
+}
+
+fn set(field: &mut u32, value: u32) {
+    unsafe { ptr::write_volatile(field, value) }
+}
+
+fn qh(pipe: usize) -> &'static mut QueueHead {
+    unsafe { &mut PIPE_MEMORY[pipe].qh }
+}
+
+fn qtd(pipe: usize, stage: usize) -> &'static mut TransferDescriptor {
+    unsafe { &mut PIPE_MEMORY[pipe].qtds[stage] }
+}
+
+fn qh_link(pipe: usize) -> u32 {
+    qh(pipe) as *const QueueHead as u32 | LINK_QH
+}
+
+fn async_head_link() -> u32 {
+    unsafe { &ASYNC_HEAD as *const QueueHead as u32 | LINK_QH }
+}
+
+/// Fills in a transfer descriptor for `len` bytes at `addr`, activating it
+/// last.
+fn prepare(qtd: &mut TransferDescriptor, next: u32, token: u32, addr: u32, len: usize) {
+    set(&mut qtd.next, next);
+    set(&mut qtd.alt_next, LINK_TERMINATE);
+    let page = addr & !0xFFF;
+    set(&mut qtd.buffers[0], addr);
+    for i in 1..5 {
+        set(&mut qtd.buffers[i], page + 0x1000 * i as u32);
+    }
+    compiler_fence(Ordering::SeqCst);
+    set(&mut qtd.token, (len as u32) << 16 | token | TOKEN_CERR | TOKEN_ACTIVE);
+}
+
+/// The number of bytes a completed descriptor moved, out of `len`.
+fn transferred(qtd: &TransferDescriptor, len: usize) -> usize {
+    len - cmp::min(((get(&qtd.token) >> 16) & 0x7FFF) as usize, len)
+}
+
+fn error(token: u32) -> TransferError {
+    if token & (TOKEN_BUFFER_ERROR | TOKEN_BABBLE | TOKEN_TRANSACTION_ERROR) != 0 {
+        TransferError::Failed
+    } else {
+        TransferError::Stall
+    }
+}
+
+/// A control or bulk transfer in progress: the descriptor that ends it, and
+/// the one that moves its data, with the data's length.
+#[derive(Copy, Clone)]
+struct Transfer {
+    last: usize,
+    data: usize,
+    len: usize,
+}
+
+/// A pipe taken off its schedule, waiting for the controller to let go of
+/// it.
+#[derive(Copy, Clone)]
+struct Retire {
+    pipe_type: PipeType,
+    // Whether the pipe goes back on the schedule afterwards, after a
+    // timeout, rather than being closed.
+    relink: bool,
+    // Whether the doorbell has been rung, or for interrupt pipes a tick
+    // has passed, since the pipe was taken off.
+    waited: bool,
+}
+
+struct PipeState {
+    config: Cell<Option<Pipe>>,
+    // The next pipe on the same schedule.
+    next: Cell<Option<usize>>,
+    receiving: Cell<bool>,
+    transfer: Cell<Option<Transfer>>,
+    buffer: TakeCell<'static, [u8]>,
+    // Ticks left before the transfer in progress times out.
+    ticks: Cell<u32>,
+    retire: Cell<Option<Retire>>,
+}
+
+impl PipeState {
+    const fn new() -> PipeState {
+        PipeState {
+            config: Cell::new(None),
+            next: Cell::new(None),
+            receiving: Cell::new(false),
+            transfer: Cell::new(None),
+            buffer: TakeCell::empty(),
+            ticks: Cell::new(0),
+            retire: Cell::new(None),
+        }
+    }
+}
+
+pub static mut USBHS: UsbHs<'static> = UsbHs::new();
+
+pub struct UsbHs<'a> {
+    client: Cell<Option<&'a Client<'a>>>,
+    pipes: [PipeState; N_PIPES],
+    first_async: Cell<Option<usize>>,
+    first_periodic: Cell<Option<usize>>,
+    // Whether the async advance doorbell has been rung and not answered.
+    doorbell: Cell<bool>,
+}
+
+impl<'a> UsbHs<'a> {
+    const fn new() -> UsbHs<'a> {
+        UsbHs {
+            client: Cell::new(None),
+            pipes: [
+                PipeState::new(), PipeState::new(), PipeState::new(), PipeState::new(),
+                PipeState::new(), PipeState::new(), PipeState::new(), PipeState::new(),
+                PipeState::new(), PipeState::new(), PipeState::new(), PipeState::new(),
+            ],
+            first_async: Cell::new(None),
+            first_periodic: Cell::new(None),
+            doorbell: Cell::new(false),
+        }
+    }
+
+    fn regs(&self) -> &mut Registers {
+        unsafe { mem::transmute(USBHS_BASE) }
+    }
+
+    fn phy(&self) -> &mut PhyRegisters {
+        unsafe { mem::transmute(USBPHY_BASE) }
+    }
+
+    pub fn set_client(&self, client: &'a Client<'a>) {
+        self.client.set(Some(client));
+    }
+
+    /// Powers up the PHY and starts the controller as a host. VBUS must be
+    /// switched on separately.
+    pub fn enable(&self) {
+        use sim::{clocks, Clock};
+
+        mcg::enable_fast_irclk();
+        sim::enable_usbhs_phy_regulator();
+        clocks::USBHSDCD.enable();
+        clocks::USBHSPHY.enable();
+        clocks::USBHS.enable();
+        sysmpu::grant_access(sysmpu::Master::UsbHs);
+
+        // The PHY's PLL multiplies the 16MHz crystal up to 480MHz.
+        let phy = self.phy();
+        phy.ctrl.clr.write(PhyControl::SFTRST::SET + PhyControl::CLKGATE::SET);
+        phy.ctrl.set.write(PhyControl::ENUTMILEVEL2::SET + PhyControl::ENUTMILEVEL3::SET);
+        phy.pll_sic.value.write(PllControl::PLL_POWER::SET +
+                                PllControl::PLL_ENABLE::SET +
+                                PllControl::PLL_EN_USB_CLKS::SET +
+                                PllControl::PLL_DIV_SEL::Ref16MHz);
+        while !phy.pll_sic.value.is_set(PllControl::PLL_LOCK) {}
+        phy.pwd.value.set(0);
+
+        self.start_controller();
+        unsafe { nvic::enable(NvicIdx::USBHS); }
+    }
+
+    /// Resets the controller and starts it with empty schedules.
+    fn start_controller(&self) {
+        let regs = self.regs();
+        regs.usbcmd.write(UsbCommand::RST::SET);
+        while regs.usbcmd.is_set(UsbCommand::RST) {}
+        regs.usbmode.write(UsbMode::CM::Host);
+        regs.usbintr.set(0);
+        self.doorbell.set(false);
+
+        unsafe {
+            let head = &mut ASYNC_HEAD;
+            set(&mut head.horizontal, async_head_link());
+            set(&mut head.characteristics, QH_HEAD);
+            set(&mut head.token, TOKEN_HALTED);
+            regs.asynclistaddr.set(head as *const QueueHead as u32);
+            for entry in PERIODIC_LIST.0.iter_mut() {
+                set(entry, LINK_TERMINATE);
+            }
+            regs.periodiclistbase.set(&PERIODIC_LIST as *const PeriodicList as u32);
+        }
+        regs.frindex.set(0);
+
+        // FS2 and FS select a 32 entry frame list.
+        regs.usbcmd.write(UsbCommand::ITC.val(1) +
+                          UsbCommand::FS2::SET +
+                          UsbCommand::FS.val(1) +
+                          UsbCommand::ASE::SET +
+                          UsbCommand::PSE::SET +
+                          UsbCommand::RS::SET);
+        self.modify_port(PortStatusControl::PP::SET);
+
+        // The timer ticks every `TICK_MS` while it runs.
+        regs.gptimer0ld.set(TICK_MS * 1000 - 1);
+
+        regs.usbsts.set(regs.usbsts.get());
+        regs.usbintr.write(UsbStatus::TI0::SET +
+                           UsbStatus::AAI::SET +
+                           UsbStatus::PCI::SET +
+                           UsbStatus::UI::SET +
+                           UsbStatus::UEI::SET +
+                           UsbStatus::SEI::SET);
+    }
+
+    /// Writes PORTSC1 without clearing its write-one-to-clear change bits,
+    /// other than those in `field`.
+    fn modify_port(&self, field: FieldValue<u32, PortStatusControl::Register>) {
+        let regs = self.regs();
+        let changes = PortStatusControl::CSC::SET.value |
+                      PortStatusControl::PEC::SET.value |
+                      PortStatusControl::OCC::SET.value;
+        let value = regs.portsc1.get() & !changes & !field.mask;
+        regs.portsc1.set(value | field.value);
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.regs().portsc1.is_set(PortStatusControl::CCS)
+    }
+
+    /// Starts resetting the device on the root port. The controller times
+    /// the reset, and `port_enabled` tells when it has ended.
+    pub fn reset_port(&self) -> ReturnCode {
+        if !self.is_connected() {
+            return ReturnCode::EOFF;
+        }
+        self.modify_port(PortStatusControl::PR::SET);
+        ReturnCode::SUCCESS
+    }
+
+    /// Returns the speed of the device on the root port once its reset has
+    /// ended and the port is enabled.
+    pub fn port_enabled(&self) -> Option<Speed> {
+        let regs = self.regs();
+        if regs.portsc1.is_set(PortStatusControl::PR) || !regs.portsc1.is_set(PortStatusControl::PE) {
+            return None;
+        }
+
+        Some(match regs.portsc1.read(PortStatusControl::PSPD) {
+            1 => Speed::Low,
+            2 => {
+                // Only the PHY can tell that a high-speed device has gone.
+                self.phy().ctrl.set.write(PhyControl::ENHOSTDISCONDETECT::SET);
+                Speed::High
+            },
+            _ => Speed::Full,
+        })
+    }
+
+    /// Opens a pipe to a device's endpoint, returning its number, or `None`
+    /// if all pipes are in use.
+    pub fn open_pipe(&self, pipe: Pipe) -> Option<usize> {
+        let index = self.pipes.iter().position(|state| {
+            state.config.get().is_none() && state.retire.get().is_none()
+        })?;
+
+        let mut characteristics = (pipe.max_packet_size as u32 & 0x7FF) << 16 |
+                                  (pipe.speed as u32) << 12 |
+                                  (pipe.endpoint as u32 & 0xF) << 8 |
+                                  pipe.address as u32;
+        let mut capabilities = QH_MULT_ONE |
+                               (pipe.tt_port as u32) << 23 |
+                               (pipe.tt_hub as u32) << 16;
+        match pipe.pipe_type {
+            PipeType::Control => {
+                characteristics |= QH_NAK_RELOAD | QH_TOGGLE_FROM_QTD;
+                if pipe.speed != Speed::High {
+                    characteristics |= QH_CONTROL_ENDPOINT;
+                }
+            },
+            PipeType::Bulk => characteristics |= QH_NAK_RELOAD,
+            PipeType::Interrupt => {
+                capabilities |= QH_START_MASK;
+                if pipe.speed != Speed::High {
+                    capabilities |= QH_COMPLETE_MASK;
+                }
+            },
+        }
+
+        let qh = qh(index);
+        set(&mut qh.characteristics, characteristics);
+        set(&mut qh.capabilities, capabilities);
+        set(&mut qh.current, 0);
+        set(&mut qh.next, LINK_TERMINATE);
+        set(&mut qh.alt_next, LINK_TERMINATE);
+        set(&mut qh.token, 0);
+
+        let state = &self.pipes[index];
+        state.config.set(Some(pipe));
+        state.receiving.set(false);
+        self.link(index, pipe.pipe_type);
+        Some(index)
+    }
+
+    /// Closes a pipe. An interrupt pipe stops receiving, and a transfer in
+    /// progress ends with `TransferError::Cancelled` once the controller has
+    /// let go of the pipe.
+    pub fn close_pipe(&self, index: usize) {
+        let pipe = match self.pipes.get(index).and_then(|state| state.config.get()) {
+            Some(pipe) => pipe,
+            None => return,
+        };
+        let state = &self.pipes[index];
+        state.config.set(None);
+        state.receiving.set(false);
+
+        match state.retire.get() {
+            // Already off the schedule after a timeout.
+            Some(retire) => state.retire.set(Some(Retire { relink: false, ..retire })),
+            None => {
+                self.unlink(index, pipe.pipe_type);
+                self.retire(index, pipe.pipe_type, false);
+            },
+        }
+    }
+
+    // Pipes are added at the front of their schedule, after the
+    // asynchronous schedule's head.
+    fn link(&self, index: usize, pipe_type: PipeType) {
+        let first = self.first(pipe_type);
+        let next = first.get().map_or(self.end_link(pipe_type), qh_link);
+        set(&mut qh(index).horizontal, next);
+        self.pipes[index].next.set(first.get());
+        compiler_fence(Ordering::SeqCst);
+
+        self.set_first_link(pipe_type, qh_link(index));
+        first.set(Some(index));
+    }
+
+    fn unlink(&self, index: usize, pipe_type: PipeType) {
+        let first = self.first(pipe_type);
+        let next = self.pipes[index].next.get();
+        let next_link = next.map_or(self.end_link(pipe_type), qh_link);
+
+        if first.get() == Some(index) {
+            first.set(next);
+            self.set_first_link(pipe_type, next_link);
+        } else if let Some(prev) = (0..N_PIPES).find(|&p| self.pipes[p].next.get() == Some(index)) {
+            self.pipes[prev].next.set(next);
+            set(&mut qh(prev).horizontal, next_link);
+        }
+        self.pipes[index].next.set(None);
+    }
+
+    /// Waits for the controller to let go of a pipe that has just been
+    /// unlinked, until the doorbell is answered, or for interrupt pipes,
+    /// until the frame has ended.
+    fn retire(&self, index: usize, pipe_type: PipeType, relink: bool) {
+        self.pipes[index].retire.set(Some(Retire {
+            pipe_type: pipe_type,
+            relink: relink,
+            waited: false,
+        }));
+        match pipe_type {
+            PipeType::Interrupt => self.start_timer(),
+            _ => self.ring_doorbell(),
+        }
+    }
+
+    /// Asks the controller to signal once it has moved past every queue
+    /// head unlinked so far.
+    fn ring_doorbell(&self) {
+        if self.doorbell.get() {
+            return;
+        }
+        let mut waiting = false;
+        for state in self.pipes.iter() {
+            if let Some(retire) = state.retire.get() {
+                if retire.pipe_type != PipeType::Interrupt && !retire.waited {
+                    state.retire.set(Some(Retire { waited: true, ..retire }));
+                    waiting = true;
+                }
+            }
+        }
+        if waiting {
+            self.doorbell.set(true);
+            self.regs().usbcmd.modify(UsbCommand::IAA::SET);
+        }
+    }
+
+    /// Finishes retiring a pipe the controller no longer holds. A cancelled
+    /// transfer's pipe goes back on the schedule, and the transfer's buffer
+    /// goes back to the client.
+    fn retired(&self, index: usize, retire: Retire) {
+        let state = &self.pipes[index];
+        state.retire.set(None);
+        for stage in 0..3 {
+            set(&mut qtd(index, stage).token, 0);
+        }
+        let qh = qh(index);
+        set(&mut qh.next, LINK_TERMINATE);
+        let toggle = get(&qh.token) & TOKEN_TOGGLE;
+        set(&mut qh.token, toggle);
+
+        let err = if retire.relink {
+            self.link(index, retire.pipe_type);
+            TransferError::Timeout
+        } else {
+            TransferError::Cancelled
+        };
+        state.transfer.set(None);
+        state.buffer.take().map(|buffer| {
+            self.client.get().map(move |client| client.transfer_done(index, buffer, Err(err)));
+        });
+    }
+
+    fn first(&self, pipe_type: PipeType) -> &Cell<Option<usize>> {
+        match pipe_type {
+            PipeType::Interrupt => &self.first_periodic,
+            _ => &self.first_async,
+        }
+    }
+
+    // The asynchronous schedule is a ring back to its head, while the
+    // periodic schedule ends.
+    fn end_link(&self, pipe_type: PipeType) -> u32 {
+        match pipe_type {
+            PipeType::Interrupt => LINK_TERMINATE,
+            _ => async_head_link(),
+        }
+    }
+
+    fn set_first_link(&self, pipe_type: PipeType, link: u32) {
+        match pipe_type {
+            PipeType::Interrupt => {
+                for entry in unsafe { PERIODIC_LIST.0.iter_mut() } {
+                    set(entry, link);
+                }
+            },
+            _ => set(unsafe { &mut ASYNC_HEAD.horizontal }, link),
+        }
+    }
+
+    fn check(&self, index: usize, pipe_type: PipeType) -> Result<Pipe, TransferError> {
+        let state = match self.pipes.get(index) {
+            Some(state) => state,
+            None => return Err(TransferError::Invalid),
+        };
+        let pipe = match state.config.get() {
+            Some(pipe) if pipe.pipe_type == pipe_type => pipe,
+            _ => return Err(TransferError::Invalid),
+        };
+        if state.transfer.get().is_some() || state.retire.get().is_some() {
+            return Err(TransferError::Busy);
+        }
+        Ok(pipe)
+    }
+
+    /// Points an idle queue head at its first transfer descriptor, and
+    /// clears any halt, keeping the data toggle.
+    fn start(&self, index: usize) {
+        let qh = qh(index);
+        compiler_fence(Ordering::SeqCst);
+        set(&mut qh.next, &*qtd(index, 0) as *const TransferDescriptor as u32);
+        set(&mut qh.alt_next, LINK_TERMINATE);
+        let toggle = get(&qh.token) & TOKEN_TOGGLE;
+        set(&mut qh.token, toggle);
+    }
+
+    /// Starts a transfer, keeping its buffer until it finishes.
+    fn run(&self, index: usize, transfer: Transfer, buffer: &'static mut [u8], timeout_ms: u32) {
+        let state = &self.pipes[index];
+        state.transfer.set(Some(transfer));
+        state.buffer.replace(buffer);
+        // One tick more, as the first may come at once.
+        state.ticks.set(timeout_ms / TICK_MS + 1);
+        self.start(index);
+        self.start_timer();
+    }
+
+    /// Starts a control request on a control pipe, moving at most
+    /// `buffer.len()` bytes in the direction the request says. The number
+    /// of bytes moved is reported to the client's `transfer_done`.
+    pub fn control_transfer(&self,
+                            index: usize,
+                            setup: &[u8; 8],
+                            buffer: &'static mut [u8])
+                            -> Result<(), (TransferError, &'static mut [u8])> {
+        if let Err(err) = self.check(index, PipeType::Control) {
+            return Err((err, buffer));
+        }
+
+        let requested = setup[6] as usize | (setup[7] as usize) << 8;
+        let len = cmp::min(cmp::min(requested, buffer.len()), MAX_TRANSFER_SIZE);
+        let data_in = setup[0] & 0x80 != 0;
+        let status_link = &*qtd(index, STATUS_STAGE) as *const TransferDescriptor as u32;
+        let data_link = &*qtd(index, DATA_STAGE) as *const TransferDescriptor as u32;
+        let setup_addr = unsafe {
+            PIPE_MEMORY[index].setup = *setup;
+            PIPE_MEMORY[index].setup.as_ptr() as u32
+        };
+
+        // The status stage runs opposite to the data, and always uses
+        // DATA1.
+        let status_pid = if data_in && len > 0 { TOKEN_PID_OUT } else { TOKEN_PID_IN };
+        prepare(qtd(index, STATUS_STAGE), LINK_TERMINATE, status_pid | TOKEN_TOGGLE | TOKEN_IOC, 0, 0);
+        if len > 0 {
+            let pid = if data_in { TOKEN_PID_IN } else { TOKEN_PID_OUT };
+            prepare(qtd(index, DATA_STAGE), status_link, pid | TOKEN_TOGGLE, buffer.as_mut_ptr() as u32, len);
+        }
+        let next = if len > 0 { data_link } else { status_link };
+        prepare(qtd(index, SETUP_STAGE), next, TOKEN_PID_SETUP, setup_addr, setup.len());
+
+        let transfer = Transfer {
+            last: STATUS_STAGE,
+            data: if len > 0 { DATA_STAGE } else { STATUS_STAGE },
+            len: len,
+        };
+        self.run(index, transfer, buffer, CONTROL_TIMEOUT_MS);
+        Ok(())
+    }
+
+    fn bulk_transfer(&self,
+                     index: usize,
+                     buffer: &'static mut [u8],
+                     len: usize,
+                     data_in: bool)
+                     -> Result<(), (TransferError, &'static mut [u8])> {
+        let pipe = match self.check(index, PipeType::Bulk) {
+            Ok(pipe) => pipe,
+            Err(err) => return Err((err, buffer)),
+        };
+        if (pipe.endpoint & 0x80 != 0) != data_in {
+            return Err((TransferError::Invalid, buffer));
+        }
+
+        let len = cmp::min(cmp::min(len, buffer.len()), MAX_TRANSFER_SIZE);
+        let pid = if data_in { TOKEN_PID_IN } else { TOKEN_PID_OUT };
+        prepare(qtd(index, 0), LINK_TERMINATE, pid | TOKEN_IOC, buffer.as_mut_ptr() as u32, len);
+        self.run(index, Transfer { last: 0, data: 0, len: len }, buffer, BULK_TIMEOUT_MS);
+        Ok(())
+    }
+
+    /// Starts receiving up to `len` bytes, at most `MAX_TRANSFER_SIZE`, on a
+    /// bulk IN pipe, ending early at a short packet.
+    pub fn bulk_in(&self, index: usize, buffer: &'static mut [u8], len: usize)
+                   -> Result<(), (TransferError, &'static mut [u8])> {
+        self.bulk_transfer(index, buffer, len, true)
+    }
+
+    /// Starts sending up to `len` bytes, at most `MAX_TRANSFER_SIZE`, on a
+    /// bulk OUT pipe.
+    pub fn bulk_out(&self, index: usize, buffer: &'static mut [u8], len: usize)
+                    -> Result<(), (TransferError, &'static mut [u8])> {
+        self.bulk_transfer(index, buffer, len, false)
+    }
+
+    /// Clears a stalled bulk or interrupt pipe and resets its data toggle,
+    /// once the device's endpoint halt has been cleared.
+    pub fn reset_pipe(&self, index: usize) {
+        if self.pipes.get(index).map_or(false, |state| state.config.get().is_some()) {
+            set(&mut qh(index).token, 0);
+        }
+    }
+
+    /// Reports a control or bulk transfer that has ended.
+    fn transfer_finished(&self, index: usize) {
+        let state = &self.pipes[index];
+        let transfer = match state.transfer.get() {
+            Some(transfer) if state.retire.get().is_none() => transfer,
+            _ => return,
+        };
+        let token = get(&qh(index).token);
+        let halted = token & TOKEN_HALTED != 0;
+        if !halted && get(&qtd(index, transfer.last).token) & TOKEN_ACTIVE != 0 {
+            return;
+        }
+        compiler_fence(Ordering::SeqCst);
+
+        state.transfer.set(None);
+        let result = if halted {
+            Err(error(token))
+        } else {
+            Ok(transferred(qtd(index, transfer.data), transfer.len))
+        };
+        state.buffer.take().map(|buffer| {
+            self.client.get().map(move |client| client.transfer_done(index, buffer, result));
+        });
+    }
+
+    /// Takes a transfer that has run out of time off the schedule, to be
+    /// reported once the controller has let go of it.
+    fn cancel(&self, index: usize) {
+        let pipe_type = match self.pipes[index].config.get() {
+            Some(pipe) => pipe.pipe_type,
+            None => return,
+        };
+        self.unlink(index, pipe_type);
+        self.retire(index, pipe_type, true);
+    }
+
+    fn start_timer(&self) {
+        let regs = self.regs();
+        if !regs.gptimer0ctl.is_set(GpTimerControl::GPTRUN) {
+            regs.gptimer0ctl.write(GpTimerControl::GPTRUN::SET +
+                                   GpTimerControl::GPTRST::SET +
+                                   GpTimerControl::GPTMODE::Repeat);
+        }
+    }
+
+    fn tick(&self) {
+        let mut busy = false;
+        for index in 0..N_PIPES {
+            let state = &self.pipes[index];
+            match state.retire.get() {
+                Some(retire) if retire.pipe_type == PipeType::Interrupt => {
+                    if retire.waited {
+                        self.retired(index, retire);
+                    } else {
+                        state.retire.set(Some(Retire { waited: true, ..retire }));
+                        busy = true;
+                    }
+                },
+                Some(_) => busy = true,
+                None => if state.transfer.get().is_some() {
+                    let ticks = state.ticks.get().saturating_sub(1);
+                    state.ticks.set(ticks);
+                    if ticks == 0 {
+                        self.cancel(index);
+                    }
+                    busy = true;
+                },
+            }
+        }
+        if !busy {
+            self.regs().gptimer0ctl.set(0);
+        }
+    }
+
+    fn doorbell_answered(&self) {
+        self.doorbell.set(false);
+        for index in 0..N_PIPES {
+            if let Some(retire) = self.pipes[index].retire.get() {
+                if retire.pipe_type != PipeType::Interrupt && retire.waited {
+                    self.retired(index, retire);
+                }
+            }
+        }
+        // Pipes unlinked since the doorbell was rung need another.
+        self.ring_doorbell();
+    }
+
+    /// Starts receiving packets on an interrupt IN pipe. The pipe keeps
+    /// receiving until it fails or is closed.
+    pub fn start_receiving(&self, index: usize) -> ReturnCode {
+        let pipe = match self.check(index, PipeType::Interrupt) {
+            Ok(pipe) => pipe,
+            Err(_) => return ReturnCode::EINVAL,
+        };
+        if pipe.endpoint & 0x80 == 0 {
+            return ReturnCode::EINVAL;
+        }
+        if !self.pipes[index].receiving.get() {
+            self.receive(index, pipe);
+        }
+        ReturnCode::SUCCESS
+    }
+
+    fn receive(&self, index: usize, pipe: Pipe) {
+        let buffer = unsafe { &PIPE_MEMORY[index].buffer };
+        let len = cmp::min(pipe.max_packet_size as usize, MAX_INTERRUPT_PACKET_SIZE);
+        prepare(qtd(index, 0), LINK_TERMINATE, TOKEN_PID_IN | TOKEN_IOC, buffer.as_ptr() as u32, len);
+        self.pipes[index].receiving.set(true);
+        self.start(index);
+    }
+
+    fn packet_received(&self, index: usize) {
+        let state = &self.pipes[index];
+        let pipe = match state.config.get() {
+            Some(pipe) if state.receiving.get() => pipe,
+            _ => return,
+        };
+        let token = get(&qtd(index, 0).token);
+        if token & TOKEN_ACTIVE != 0 {
+            return;
+        }
+        compiler_fence(Ordering::SeqCst);
+        state.receiving.set(false);
+
+        let len = cmp::min(pipe.max_packet_size as usize, MAX_INTERRUPT_PACKET_SIZE);
+        let ok = token & TOKEN_HALTED == 0;
+        self.client.get().map(|client| {
+            let result = if ok {
+                let buffer = unsafe { &PIPE_MEMORY[index].buffer };
+                Ok(&buffer[..transferred(qtd(index, 0), len)])
+            } else {
+                Err(error(token))
+            };
+            client.packet_received(index, result);
+        });
+
+        // The client may have closed or restarted the pipe.
+        if ok && state.config.get().is_some() && !state.receiving.get() {
+            self.receive(index, pipe);
+        }
+    }
+
+    /// Recovers from a bus error, which halts the controller: the
+    /// controller is reset with every pipe closed, and transfers in
+    /// progress end with `TransferError::Cancelled`.
+    fn recover(&self) {
+        self.regs().gptimer0ctl.set(0);
+        self.first_async.set(None);
+        self.first_periodic.set(None);
+        self.start_controller();
+
+        for index in 0..N_PIPES {
+            let state = &self.pipes[index];
+            state.config.set(None);
+            state.next.set(None);
+            state.receiving.set(false);
+            state.transfer.set(None);
+            state.retire.set(None);
+            state.buffer.take().map(|buffer| {
+                self.client.get().map(move |client| {
+                    client.transfer_done(index, buffer, Err(TransferError::Cancelled))
+                });
+            });
+        }
+        self.phy().ctrl.clr.write(PhyControl::ENHOSTDISCONDETECT::SET);
+        self.client.get().map(|client| client.host_error());
+    }
+
+    pub fn handle_interrupt(&self) {
+        let regs = self.regs();
+        let status = regs.usbsts.get() & regs.usbintr.get();
+        regs.usbsts.set(status);
+
+        if status & UsbStatus::SEI::SET.value != 0 {
+            self.recover();
+            return;
+        }
+
+        if status & UsbStatus::PCI::SET.value != 0 && regs.portsc1.is_set(PortStatusControl::CSC) {
+            self.modify_port(PortStatusControl::CSC::SET);
+            let connected = self.is_connected();
+            if !connected {
+                self.phy().ctrl.clr.write(PhyControl::ENHOSTDISCONDETECT::SET);
+            }
+            self.client.get().map(|client| client.port_changed(connected));
+        }
+
+        if status & (UsbStatus::UI::SET.value | UsbStatus::UEI::SET.value) != 0 {
+            for index in 0..N_PIPES {
+                self.transfer_finished(index);
+                self.packet_received(index);
+            }
+        }
+
+        if status & UsbStatus::AAI::SET.value != 0 {
+            self.doorbell_answered();
+        }
+
+        if status & UsbStatus::TI0::SET.value != 0 {
+            self.tick();
+        }
+    }
+}
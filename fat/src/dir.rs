@@ -0,0 +1,144 @@
+//! Directory entries and 8.3 names.
+
+use Error;
+
+pub const ENTRY_SIZE: usize = 32;
+
+pub const ATTR_VOLUME_ID: u8 = 0x08;
+pub const ATTR_DIRECTORY: u8 = 0x10;
+pub const ATTR_ARCHIVE: u8 = 0x20;
+pub const ATTR_LONG_NAME: u8 = 0x0F;
+
+pub const END_OF_DIRECTORY: u8 = 0x00;
+pub const DELETED: u8 = 0xE5;
+
+/// A file or directory, as returned by `Volume::read_dir`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DirEntry {
+    name: [u8; 12],
+    name_len: usize,
+    pub size: u32,
+    pub is_dir: bool,
+}
+
+impl DirEntry {
+    pub fn parse(raw: &[u8]) -> DirEntry {
+        let mut name = [0; 12];
+        let mut len = 0;
+        for &c in raw[0..8].iter().filter(|&&c| c != b' ') {
+            name[len] = c;
+            len += 1;
+        }
+        if raw[8] != b' ' {
+            name[len] = b'.';
+            len += 1;
+            for &c in raw[8..11].iter().filter(|&&c| c != b' ') {
+                name[len] = c;
+                len += 1;
+            }
+        }
+
+        DirEntry {
+            name: name,
+            name_len: len,
+            size: read_u32(raw, 28),
+            is_dir: raw[11] & ATTR_DIRECTORY != 0,
+        }
+    }
+
+    /// The name in `NAME.EXT` form.
+    pub fn name(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+}
+
+/// Returns true for entries that name a file or directory.
+pub fn is_visible(raw: &[u8]) -> bool {
+    raw[0] != END_OF_DIRECTORY && raw[0] != DELETED &&
+        raw[11] & ATTR_LONG_NAME != ATTR_LONG_NAME &&
+        raw[11] & ATTR_VOLUME_ID == 0
+}
+
+pub fn is_dot(raw: &[u8]) -> bool {
+    raw[0] == b'.'
+}
+
+pub fn first_cluster(raw: &[u8]) -> u32 {
+    (read_u16(raw, 20) as u32) << 16 | read_u16(raw, 26) as u32
+}
+
+pub fn set_first_cluster(raw: &mut [u8], cluster: u32) {
+    write_u16(raw, 20, (cluster >> 16) as u16);
+    write_u16(raw, 26, cluster as u16);
+}
+
+pub fn set_size(raw: &mut [u8], size: u32) {
+    write_u32(raw, 28, size);
+}
+
+/// Fills in a new, empty file entry.
+pub fn init_file(raw: &mut [u8], name: &[u8; 11]) {
+    for b in raw[..ENTRY_SIZE].iter_mut() {
+        *b = 0;
+    }
+    raw[0..11].copy_from_slice(name);
+    raw[11] = ATTR_ARCHIVE;
+    // 1980-01-01, the earliest date FAT can store.
+    write_u16(raw, 16, 0x21);
+    write_u16(raw, 24, 0x21);
+}
+
+fn is_valid_char(c: u8) -> bool {
+    match c {
+        b'A'...b'Z' | b'0'...b'9' => true,
+        b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'(' | b')' |
+        b'-' | b'@' | b'^' | b'_' | b'`' | b'{' | b'}' | b'~' => true,
+        _ => false,
+    }
+}
+
+/// Converts a path component such as `log.txt` to the space-padded,
+/// upper-case form stored on disk.
+pub fn short_name(component: &[u8]) -> Result<[u8; 11], Error> {
+    let mut name = [b' '; 11];
+    if component == b"." || component == b".." {
+        name[..component.len()].copy_from_slice(component);
+        return Ok(name);
+    }
+
+    let (base, ext) = match component.iter().rposition(|&c| c == b'.') {
+        Some(dot) => (&component[..dot], &component[dot + 1..]),
+        None => (component, &component[component.len()..]),
+    };
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+        return Err(Error::InvalidName);
+    }
+
+    for (i, &c) in base.iter().chain(ext.iter()).enumerate() {
+        let c = c.to_ascii_uppercase();
+        if !is_valid_char(c) {
+            return Err(Error::InvalidName);
+        }
+        let index = if i < base.len() { i } else { 8 + i - base.len() };
+        name[index] = c;
+    }
+    Ok(name)
+}
+
+pub fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    buf[offset] as u16 | (buf[offset + 1] as u16) << 8
+}
+
+pub fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    read_u16(buf, offset) as u32 | (read_u16(buf, offset + 2) as u32) << 16
+}
+
+pub fn write_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset] = value as u8;
+    buf[offset + 1] = (value >> 8) as u8;
+}
+
+pub fn write_u32(buf: &mut [u8], offset: usize, value: u32) {
+    write_u16(buf, offset, value as u16);
+    write_u16(buf, offset + 2, (value >> 16) as u16);
+}
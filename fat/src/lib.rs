@@ -0,0 +1,56 @@
+//! A small FAT16/FAT32 filesystem.
+//!
+//! The filesystem is independent of Tock and of any particular storage
+//! hardware: it reads and writes 512-byte sectors through the `BlockDevice`
+//! trait. On the board this is the SD card, and on a development machine it
+//! can be an image file, which is how the tests in `tests/` exercise it.
+//!
+//! Only 8.3 short names are supported. Long file name entries written by
+//! other systems are skipped, so those files are visible under their short
+//! alias. All writes go straight to the device, and a file's directory entry
+//! is updated after every write, so a logger that loses power loses at most
+//! the data of the interrupted write.
+
+#![no_std]
+
+mod dir;
+mod volume;
+
+pub use dir::DirEntry;
+pub use volume::{FatType, File, OpenMode, Volume, MAX_OPEN_ENTRIES};
+
+pub const SECTOR_SIZE: usize = 512;
+
+/// A storage device made of 512-byte sectors.
+pub trait BlockDevice {
+    fn read_sector(&self, sector: u32, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), Error>;
+    fn write_sector(&self, sector: u32, buf: &[u8; SECTOR_SIZE]) -> Result<(), Error>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The block device reported an error.
+    Io,
+    /// The device does not hold a FAT volume.
+    NotFat,
+    /// The volume is FAT12 or uses sectors other than 512 bytes.
+    Unsupported,
+    /// The volume's structures are inconsistent.
+    Corrupt,
+    NotFound,
+    InvalidName,
+    NotADirectory,
+    IsADirectory,
+    /// The file was not opened for writing.
+    ReadOnly,
+    /// The fixed-size FAT16 root directory has no free entries.
+    DirectoryFull,
+    NoSpace,
+    /// Files are limited to 4GB.
+    FileTooLarge,
+    /// The file is open, and cannot be truncated or written to by another
+    /// open file.
+    InUse,
+    /// `MAX_OPEN_ENTRIES` files are already open.
+    TooManyOpenFiles,
+}
@@ -0,0 +1,834 @@
+//! Mounted volumes and open files.
+
+use dir::{self, DirEntry, ENTRY_SIZE};
+use {BlockDevice, Error, SECTOR_SIZE};
+
+const ENTRIES_PER_SECTOR: usize = SECTOR_SIZE / ENTRY_SIZE;
+
+// [Microsoft FAT Specification, Section 3.5]
+const MAX_FAT12_CLUSTERS: u32 = 4084;
+const MAX_FAT16_CLUSTERS: u32 = 65524;
+
+// [Microsoft FAT Specification, Section 6]
+const MAX_DIR_ENTRIES: usize = 65536;
+
+/// The number of distinct files that can be open at once. Any number of
+/// files may share an entry.
+pub const MAX_OPEN_ENTRIES: usize = 16;
+
+// MBR partition types that hold FAT16 or FAT32 volumes.
+const PARTITION_TYPES: [u8; 5] = [0x04, 0x06, 0x0B, 0x0C, 0x0E];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FatType {
+    Fat16,
+    Fat32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OpenMode {
+    /// Read an existing file.
+    Read,
+    /// Create the file, or truncate it if it exists.
+    Write,
+    /// Create the file if needed, and write to its end.
+    Append,
+}
+
+/// An open file. The volume records the file as open until it is passed to
+/// `Volume::close`.
+#[derive(Copy, Clone, Debug)]
+pub struct File {
+    entry_sector: u32,
+    entry_offset: usize,
+    start_cluster: u32,
+    size: u32,
+    position: u32,
+    mode: OpenMode,
+    // The cluster holding byte `cluster_index * cluster size`, to avoid
+    // walking the chain from the start on every access.
+    cluster: u32,
+    cluster_index: u32,
+}
+
+impl File {
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn position(&self) -> u32 {
+        self.position
+    }
+
+    /// Moves the read position, limited to the end of the file. Appends
+    /// always write at the end.
+    pub fn seek(&mut self, position: u32) {
+        self.position = if position > self.size { self.size } else { position };
+    }
+}
+
+// A directory entry with files open on it. Each file keeps its own copy of
+// the entry's start cluster and size, so a second writer, or truncating a
+// file someone is reading, would leave the copies disagreeing.
+#[derive(Copy, Clone)]
+struct OpenEntry {
+    sector: u32,
+    offset: usize,
+    files: usize,
+    writing: bool,
+}
+
+#[derive(Copy, Clone)]
+enum Dir {
+    // The FAT16 root directory, which has a fixed location and size.
+    FixedRoot,
+    Chain(u32),
+}
+
+/// The DMA engines that feed block devices often need aligned buffers.
+#[repr(align(4))]
+struct Sector([u8; SECTOR_SIZE]);
+
+pub struct Volume<D: BlockDevice> {
+    device: D,
+    fat_type: FatType,
+    sectors_per_cluster: u32,
+    fat_start: u32,
+    fat_size: u32,
+    num_fats: u32,
+    root_dir_start: u32,
+    root_dir_sectors: u32,
+    root_cluster: u32,
+    data_start: u32,
+    cluster_count: u32,
+    next_free: u32,
+    free_clusters: Option<u32>,
+    cache: Sector,
+    cached: Option<u32>,
+    open_entries: [Option<OpenEntry>; MAX_OPEN_ENTRIES],
+}
+
+impl<D: BlockDevice> Volume<D> {
+    /// Mounts the volume at the start of the device, or in the device's
+    /// first MBR partition.
+    pub fn mount(device: D) -> Result<Volume<D>, Error> {
+        let mut volume = Volume {
+            device: device,
+            fat_type: FatType::Fat16,
+            sectors_per_cluster: 1,
+            fat_start: 0,
+            fat_size: 0,
+            num_fats: 0,
+            root_dir_start: 0,
+            root_dir_sectors: 0,
+            root_cluster: 0,
+            data_start: 0,
+            cluster_count: 0,
+            next_free: 2,
+            free_clusters: None,
+            cache: Sector([0; SECTOR_SIZE]),
+            cached: None,
+            open_entries: [None; MAX_OPEN_ENTRIES],
+        };
+
+        volume.load(0)?;
+        if dir::read_u16(&volume.cache.0, 510) != 0xAA55 {
+            return Err(Error::NotFat);
+        }
+
+        let mut start = 0;
+        if !volume.is_boot_sector() {
+            // The first MBR partition entry.
+            if !PARTITION_TYPES.contains(&volume.cache.0[0x1C2]) {
+                return Err(Error::NotFat);
+            }
+            start = dir::read_u32(&volume.cache.0, 0x1C6);
+            volume.load(start)?;
+            if !volume.is_boot_sector() {
+                return Err(Error::NotFat);
+            }
+        }
+
+        volume.parse_bpb(start)?;
+        Ok(volume)
+    }
+
+    /// Returns the block device, for example to mount it again after the
+    /// medium was replaced.
+    pub fn unmount(self) -> D {
+        self.device
+    }
+
+    fn is_boot_sector(&self) -> bool {
+        let bpb = &self.cache.0;
+        (bpb[0] == 0xEB || bpb[0] == 0xE9) && dir::read_u16(bpb, 11) as usize == SECTOR_SIZE
+    }
+
+    // [Microsoft FAT Specification, Section 3.1]
+    fn parse_bpb(&mut self, start: u32) -> Result<(), Error> {
+        let (sectors_per_cluster, reserved, num_fats, root_entries, total, fat_size, root_cluster) = {
+            let bpb = &self.cache.0;
+            let total16 = dir::read_u16(bpb, 19) as u32;
+            let fat_size16 = dir::read_u16(bpb, 22) as u32;
+            (bpb[13] as u32,
+             dir::read_u16(bpb, 14) as u32,
+             bpb[16] as u32,
+             dir::read_u16(bpb, 17) as u32,
+             if total16 != 0 { total16 } else { dir::read_u32(bpb, 32) },
+             if fat_size16 != 0 { fat_size16 } else { dir::read_u32(bpb, 36) },
+             dir::read_u32(bpb, 44))
+        };
+
+        if sectors_per_cluster == 0 || !sectors_per_cluster.is_power_of_two() ||
+           num_fats == 0 || fat_size == 0 {
+            return Err(Error::NotFat);
+        }
+
+        self.sectors_per_cluster = sectors_per_cluster;
+        self.num_fats = num_fats;
+        self.fat_size = fat_size;
+        self.fat_start = start + reserved;
+        self.root_dir_start = self.fat_start + num_fats * fat_size;
+        self.root_dir_sectors = (root_entries * ENTRY_SIZE as u32 + SECTOR_SIZE as u32 - 1) /
+                                SECTOR_SIZE as u32;
+        self.data_start = self.root_dir_start + self.root_dir_sectors;
+
+        let used = self.data_start - start;
+        if total <= used {
+            return Err(Error::Corrupt);
+        }
+        self.cluster_count = (total - used) / sectors_per_cluster;
+
+        if self.cluster_count <= MAX_FAT12_CLUSTERS {
+            return Err(Error::Unsupported);
+        } else if self.cluster_count <= MAX_FAT16_CLUSTERS {
+            self.fat_type = FatType::Fat16;
+        } else {
+            self.fat_type = FatType::Fat32;
+            self.root_cluster = root_cluster;
+        }
+
+        // The FAT must have an entry for every cluster.
+        let entry_size = match self.fat_type { FatType::Fat16 => 2, FatType::Fat32 => 4 };
+        if (self.cluster_count + 2) * entry_size > fat_size * SECTOR_SIZE as u32 {
+            return Err(Error::Corrupt);
+        }
+        Ok(())
+    }
+
+    pub fn fat_type(&self) -> FatType {
+        self.fat_type
+    }
+
+    pub fn cluster_size(&self) -> u32 {
+        self.sectors_per_cluster * SECTOR_SIZE as u32
+    }
+
+    pub fn total_space(&self) -> u64 {
+        self.cluster_count as u64 * self.cluster_size() as u64
+    }
+
+    /// Returns the free space in bytes. The first call scans the whole FAT.
+    pub fn free_space(&mut self) -> Result<u64, Error> {
+        let free = match self.free_clusters {
+            Some(free) => free,
+            None => {
+                let mut free = 0;
+                for cluster in 2..self.cluster_count + 2 {
+                    if self.read_fat(cluster)? == 0 {
+                        free += 1;
+                    }
+                }
+                self.free_clusters = Some(free);
+                free
+            }
+        };
+        Ok(free as u64 * self.cluster_size() as u64)
+    }
+
+    // Sector cache
+
+    fn load(&mut self, sector: u32) -> Result<(), Error> {
+        if self.cached != Some(sector) {
+            self.cached = None;
+            self.device.read_sector(sector, &mut self.cache.0)?;
+            self.cached = Some(sector);
+        }
+        Ok(())
+    }
+
+    fn store(&mut self) -> Result<(), Error> {
+        match self.cached {
+            Some(sector) => self.device.write_sector(sector, &self.cache.0),
+            None => Ok(()),
+        }
+    }
+
+    // Replaces the cache with a zeroed sector, whose bytes are about to be
+    // overwritten or are past the end of a file.
+    fn overwrite(&mut self, sector: u32) {
+        for b in self.cache.0.iter_mut() {
+            *b = 0;
+        }
+        self.cached = Some(sector);
+    }
+
+    // File allocation table
+
+    // Data clusters are numbered from 2. Cluster numbers read from
+    // directory entries are checked here before they are used.
+    fn check_cluster(&self, cluster: u32) -> Result<(), Error> {
+        if cluster < 2 || cluster >= self.cluster_count + 2 {
+            Err(Error::Corrupt)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn cluster_sector(&self, cluster: u32) -> Result<u32, Error> {
+        self.check_cluster(cluster)?;
+        Ok(self.data_start + (cluster - 2) * self.sectors_per_cluster)
+    }
+
+    fn fat_location(&self, cluster: u32) -> (u32, usize) {
+        let offset = match self.fat_type {
+            FatType::Fat16 => cluster * 2,
+            FatType::Fat32 => cluster * 4,
+        };
+        (self.fat_start + offset / SECTOR_SIZE as u32, (offset % SECTOR_SIZE as u32) as usize)
+    }
+
+    fn read_fat(&mut self, cluster: u32) -> Result<u32, Error> {
+        self.check_cluster(cluster)?;
+        let (sector, offset) = self.fat_location(cluster);
+        self.load(sector)?;
+        Ok(match self.fat_type {
+            FatType::Fat16 => dir::read_u16(&self.cache.0, offset) as u32,
+            FatType::Fat32 => dir::read_u32(&self.cache.0, offset) & 0x0FFF_FFFF,
+        })
+    }
+
+    fn write_fat(&mut self, cluster: u32, value: u32) -> Result<(), Error> {
+        self.check_cluster(cluster)?;
+        let (sector, offset) = self.fat_location(cluster);
+        let fat_size = self.fat_size;
+        for i in 0..self.num_fats {
+            self.load(sector + i * fat_size)?;
+            match self.fat_type {
+                FatType::Fat16 => dir::write_u16(&mut self.cache.0, offset, value as u16),
+                FatType::Fat32 => {
+                    // The top four bits are reserved and must be preserved.
+                    let old = dir::read_u32(&self.cache.0, offset);
+                    dir::write_u32(&mut self.cache.0, offset, (old & 0xF000_0000) | value);
+                },
+            }
+            self.store()?;
+        }
+        Ok(())
+    }
+
+    fn end_of_chain(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFF_FFFF,
+        }
+    }
+
+    fn next_cluster(&mut self, cluster: u32) -> Result<Option<u32>, Error> {
+        let next = self.read_fat(cluster)?;
+        let eoc = match self.fat_type {
+            FatType::Fat16 => 0xFFF8,
+            FatType::Fat32 => 0x0FFF_FFF8,
+        };
+
+        if next >= eoc {
+            Ok(None)
+        } else {
+            self.check_cluster(next)?;
+            Ok(Some(next))
+        }
+    }
+
+    // Allocates a free cluster and links it after `previous`.
+    fn allocate_cluster(&mut self, previous: Option<u32>) -> Result<u32, Error> {
+        let first = self.next_free;
+        let mut cluster = first;
+        loop {
+            if self.read_fat(cluster)? == 0 {
+                break;
+            }
+            cluster = if cluster + 1 >= self.cluster_count + 2 { 2 } else { cluster + 1 };
+            if cluster == first {
+                return Err(Error::NoSpace);
+            }
+        }
+
+        let eoc = self.end_of_chain();
+        self.write_fat(cluster, eoc)?;
+        if let Some(previous) = previous {
+            self.write_fat(previous, cluster)?;
+        }
+
+        self.next_free = cluster;
+        self.free_clusters = self.free_clusters.map(|free| free - 1);
+        Ok(cluster)
+    }
+
+    // Chains longer than the volume loop back on themselves, so walks give
+    // up after `cluster_count` clusters.
+
+    fn free_chain(&mut self, start: u32) -> Result<(), Error> {
+        let mut cluster = Some(start);
+        let mut steps = 0;
+        while let Some(current) = cluster {
+            steps += 1;
+            if steps > self.cluster_count {
+                return Err(Error::Corrupt);
+            }
+            cluster = self.next_cluster(current)?;
+            self.write_fat(current, 0)?;
+            self.free_clusters = self.free_clusters.map(|free| free + 1);
+        }
+        Ok(())
+    }
+
+    fn last_cluster(&mut self, start: u32) -> Result<u32, Error> {
+        let mut cluster = start;
+        let mut steps = 0;
+        while let Some(next) = self.next_cluster(cluster)? {
+            steps += 1;
+            if steps >= self.cluster_count {
+                return Err(Error::Corrupt);
+            }
+            cluster = next;
+        }
+        Ok(cluster)
+    }
+
+    fn zero_cluster(&mut self, cluster: u32) -> Result<(), Error> {
+        let first = self.cluster_sector(cluster)?;
+        for sector in first..first + self.sectors_per_cluster {
+            self.overwrite(sector);
+            self.store()?;
+        }
+        Ok(())
+    }
+
+    // Directories
+
+    fn root(&self) -> Dir {
+        match self.fat_type {
+            FatType::Fat16 => Dir::FixedRoot,
+            FatType::Fat32 => Dir::Chain(self.root_cluster),
+        }
+    }
+
+    // Returns the sector number of the `index`th sector of a directory. A
+    // directory chain that runs past the largest a directory may be, as a
+    // cyclic one does, is corrupt.
+    fn dir_sector(&mut self, dir: Dir, index: u32) -> Result<Option<u32>, Error> {
+        match dir {
+            Dir::FixedRoot => {
+                if index < self.root_dir_sectors {
+                    Ok(Some(self.root_dir_start + index))
+                } else {
+                    Ok(None)
+                }
+            },
+            Dir::Chain(start) => {
+                let mut cluster = start;
+                for _ in 0..index / self.sectors_per_cluster {
+                    cluster = match self.next_cluster(cluster)? {
+                        Some(next) => next,
+                        None => return Ok(None),
+                    };
+                }
+                if index as usize * ENTRIES_PER_SECTOR >= MAX_DIR_ENTRIES {
+                    return Err(Error::Corrupt);
+                }
+                Ok(Some(self.cluster_sector(cluster)? + index % self.sectors_per_cluster))
+            },
+        }
+    }
+
+    // Calls `f` with the sector and offset of each visible entry until it
+    // returns true. Returns the location `f` stopped at.
+    fn find_entry<F>(&mut self, dir: Dir, mut f: F) -> Result<Option<(u32, usize)>, Error>
+        where F: FnMut(&[u8]) -> bool
+    {
+        let mut index = 0;
+        while let Some(sector) = self.dir_sector(dir, index)? {
+            self.load(sector)?;
+            for slot in 0..ENTRIES_PER_SECTOR {
+                let offset = slot * ENTRY_SIZE;
+                let raw = &self.cache.0[offset..offset + ENTRY_SIZE];
+                if raw[0] == dir::END_OF_DIRECTORY {
+                    return Ok(None);
+                }
+                if dir::is_visible(raw) && f(raw) {
+                    return Ok(Some((sector, offset)));
+                }
+            }
+            index += 1;
+        }
+        Ok(None)
+    }
+
+    fn lookup(&mut self, dir: Dir, name: &[u8; 11]) -> Result<Option<(u32, usize)>, Error> {
+        self.find_entry(dir, |raw| &raw[0..11] == &name[..])
+    }
+
+    // Finds a free slot, growing the directory if needed, and writes a new
+    // file entry to it.
+    fn create_entry(&mut self, dir: Dir, name: &[u8; 11]) -> Result<(u32, usize), Error> {
+        let mut index = 0;
+        loop {
+            match self.dir_sector(dir, index)? {
+                Some(sector) => {
+                    self.load(sector)?;
+                    for slot in 0..ENTRIES_PER_SECTOR {
+                        let offset = slot * ENTRY_SIZE;
+                        let first = self.cache.0[offset];
+                        if first == dir::END_OF_DIRECTORY || first == dir::DELETED {
+                            dir::init_file(&mut self.cache.0[offset..offset + ENTRY_SIZE], name);
+                            self.store()?;
+                            return Ok((sector, offset));
+                        }
+                    }
+                    index += 1;
+                },
+                None => match dir {
+                    Dir::FixedRoot => return Err(Error::DirectoryFull),
+                    Dir::Chain(_) if index as usize * ENTRIES_PER_SECTOR >= MAX_DIR_ENTRIES => {
+                        return Err(Error::DirectoryFull);
+                    },
+                    Dir::Chain(start) => {
+                        let last = self.last_cluster(start)?;
+                        let cluster = self.allocate_cluster(Some(last))?;
+                        self.zero_cluster(cluster)?;
+                    },
+                },
+            }
+        }
+    }
+
+    fn entry_dir(&self, raw: &[u8]) -> Result<Dir, Error> {
+        if raw[11] & dir::ATTR_DIRECTORY == 0 {
+            return Err(Error::NotADirectory);
+        }
+        // A `..` entry that points at the root holds cluster zero.
+        match dir::first_cluster(raw) {
+            0 => Ok(self.root()),
+            cluster => Ok(Dir::Chain(cluster)),
+        }
+    }
+
+    // Follows every component of `path` as a directory.
+    fn resolve_dir(&mut self, path: &[u8]) -> Result<Dir, Error> {
+        let mut dir = self.root();
+        for component in path.split(|&c| c == b'/').filter(|c| !c.is_empty()) {
+            let name = dir::short_name(component)?;
+            let (sector, offset) = self.lookup(dir, &name)?.ok_or(Error::NotFound)?;
+            self.load(sector)?;
+            dir = {
+                let raw = &self.cache.0[offset..offset + ENTRY_SIZE];
+                self.entry_dir(raw)?
+            };
+        }
+        Ok(dir)
+    }
+
+    // Splits `path` into its parent directory and final name.
+    fn resolve_parent(&mut self, path: &[u8]) -> Result<(Dir, [u8; 11]), Error> {
+        let path = match path.iter().rposition(|&c| c != b'/') {
+            Some(end) => &path[..end + 1],
+            None => return Err(Error::InvalidName),
+        };
+        let (parent, name) = match path.iter().rposition(|&c| c == b'/') {
+            Some(slash) => (&path[..slash], &path[slash + 1..]),
+            None => (&path[..0], path),
+        };
+        Ok((self.resolve_dir(parent)?, dir::short_name(name)?))
+    }
+
+    /// Returns the `index`th entry of the directory at `path`, or `None`
+    /// past the last entry. The `.` and `..` entries are skipped.
+    pub fn read_dir(&mut self, path: &[u8], index: usize) -> Result<Option<DirEntry>, Error> {
+        let dir = self.resolve_dir(path)?;
+        let mut count = 0;
+        let found = self.find_entry(dir, |raw| {
+            if dir::is_dot(raw) {
+                return false;
+            }
+            count += 1;
+            count > index
+        })?;
+
+        Ok(found.map(|(_, offset)| DirEntry::parse(&self.cache.0[offset..offset + ENTRY_SIZE])))
+    }
+
+    // Open files
+
+    // Records a file opened on the entry at `sector` and `offset`, unless it
+    // would truncate a file that is open, or write to a file already open
+    // for writing.
+    fn claim(&mut self, sector: u32, offset: usize, mode: OpenMode) -> Result<(), Error> {
+        let writing = mode != OpenMode::Read;
+        for open in self.open_entries.iter_mut() {
+            if let Some(ref mut open) = *open {
+                if open.sector == sector && open.offset == offset {
+                    if mode == OpenMode::Write || (writing && open.writing) {
+                        return Err(Error::InUse);
+                    }
+                    open.files += 1;
+                    open.writing |= writing;
+                    return Ok(());
+                }
+            }
+        }
+
+        match self.open_entries.iter_mut().find(|open| open.is_none()) {
+            Some(slot) => {
+                *slot = Some(OpenEntry {
+                    sector: sector,
+                    offset: offset,
+                    files: 1,
+                    writing: writing,
+                });
+                Ok(())
+            },
+            None => Err(Error::TooManyOpenFiles),
+        }
+    }
+
+    fn release(&mut self, file: &File) {
+        for slot in self.open_entries.iter_mut() {
+            let closed = match *slot {
+                Some(ref mut open) if open.sector == file.entry_sector &&
+                                      open.offset == file.entry_offset => {
+                    open.files -= 1;
+                    if file.mode != OpenMode::Read {
+                        open.writing = false;
+                    }
+                    open.files == 0
+                },
+                _ => false,
+            };
+            if closed {
+                *slot = None;
+            }
+        }
+    }
+
+    /// Forgets every open file. Together with `track`, this lets a caller
+    /// whose files can be dropped without being closed rebuild the record
+    /// from the files it still has.
+    pub fn forget_open_files(&mut self) {
+        self.open_entries = [None; MAX_OPEN_ENTRIES];
+    }
+
+    /// Records `file` as open again after `forget_open_files`.
+    pub fn track(&mut self, file: &File) -> Result<(), Error> {
+        self.claim(file.entry_sector, file.entry_offset, file.mode)
+    }
+
+    // Files
+
+    /// Opens the file at `path`. Fails with `InUse` to truncate a file that
+    /// is open, or to write to a file already open for writing.
+    pub fn open(&mut self, path: &[u8], mode: OpenMode) -> Result<File, Error> {
+        let (parent, name) = self.resolve_parent(path)?;
+
+        let (entry_sector, entry_offset) = match self.lookup(parent, &name)? {
+            Some(location) => location,
+            None if mode == OpenMode::Read => return Err(Error::NotFound),
+            None => self.create_entry(parent, &name)?,
+        };
+
+        self.load(entry_sector)?;
+        let (start_cluster, size) = {
+            let raw = &self.cache.0[entry_offset..entry_offset + ENTRY_SIZE];
+            if raw[11] & dir::ATTR_DIRECTORY != 0 {
+                return Err(Error::IsADirectory);
+            }
+            (dir::first_cluster(raw), dir::read_u32(raw, 28))
+        };
+
+        let mut file = File {
+            entry_sector: entry_sector,
+            entry_offset: entry_offset,
+            start_cluster: start_cluster,
+            size: size,
+            position: 0,
+            mode: mode,
+            cluster: start_cluster,
+            cluster_index: 0,
+        };
+
+        self.claim(entry_sector, entry_offset, mode)?;
+        match mode {
+            OpenMode::Read => {},
+            OpenMode::Write => {
+                if let Err(err) = self.truncate(&mut file) {
+                    self.release(&file);
+                    return Err(err);
+                }
+            },
+            OpenMode::Append => file.position = file.size,
+        }
+        Ok(file)
+    }
+
+    fn truncate(&mut self, file: &mut File) -> Result<(), Error> {
+        if file.start_cluster != 0 {
+            self.free_chain(file.start_cluster)?;
+        }
+        file.start_cluster = 0;
+        file.cluster = 0;
+        file.size = 0;
+        self.update_entry(file)
+    }
+
+    fn update_entry(&mut self, file: &File) -> Result<(), Error> {
+        self.load(file.entry_sector)?;
+        {
+            let raw = &mut self.cache.0[file.entry_offset..file.entry_offset + ENTRY_SIZE];
+            dir::set_first_cluster(raw, file.start_cluster);
+            dir::set_size(raw, file.size);
+        }
+        self.store()
+    }
+
+    // Returns the `index`th cluster of the file, extending the file's chain
+    // if `allocate` is set.
+    fn file_cluster(&mut self, file: &mut File, index: u32, allocate: bool) -> Result<u32, Error> {
+        if file.start_cluster == 0 {
+            if !allocate {
+                return Err(Error::Corrupt);
+            }
+            file.start_cluster = self.allocate_cluster(None)?;
+            file.cluster = file.start_cluster;
+            file.cluster_index = 0;
+        }
+
+        if index < file.cluster_index {
+            file.cluster = file.start_cluster;
+            file.cluster_index = 0;
+        }
+
+        while file.cluster_index < index {
+            file.cluster = match self.next_cluster(file.cluster)? {
+                Some(next) => next,
+                None if allocate => self.allocate_cluster(Some(file.cluster))?,
+                None => return Err(Error::Corrupt),
+            };
+            file.cluster_index += 1;
+        }
+        Ok(file.cluster)
+    }
+
+    // Returns the sector holding the byte at `position`, and the offset of
+    // that byte in the sector.
+    fn file_sector(&mut self, file: &mut File, position: u32, allocate: bool)
+                   -> Result<(u32, usize), Error> {
+        let cluster_size = self.cluster_size();
+        let cluster = self.file_cluster(file, position / cluster_size, allocate)?;
+        let offset = position % cluster_size;
+        Ok((self.cluster_sector(cluster)? + offset / SECTOR_SIZE as u32,
+            (offset % SECTOR_SIZE as u32) as usize))
+    }
+
+    /// Reads from the current position, and returns the number of bytes
+    /// read. Returns zero at the end of the file.
+    pub fn read(&mut self, file: &mut File, buf: &mut [u8]) -> Result<usize, Error> {
+        let remaining = (file.size - file.position) as usize;
+        let len = if buf.len() < remaining { buf.len() } else { remaining };
+
+        let mut done = 0;
+        while done < len {
+            let position = file.position;
+            let (sector, offset) = self.file_sector(file, position, false)?;
+            let chunk = if len - done < SECTOR_SIZE - offset { len - done } else { SECTOR_SIZE - offset };
+
+            self.load(sector)?;
+            buf[done..done + chunk].copy_from_slice(&self.cache.0[offset..offset + chunk]);
+            done += chunk;
+            file.position += chunk as u32;
+        }
+        Ok(done)
+    }
+
+    /// Writes at the current position, or at the end of the file if it was
+    /// opened for appending.
+    pub fn write(&mut self, file: &mut File, data: &[u8]) -> Result<usize, Error> {
+        if file.mode == OpenMode::Read {
+            return Err(Error::ReadOnly);
+        }
+        if file.mode == OpenMode::Append {
+            file.position = file.size;
+        }
+        if data.len() as u64 + file.position as u64 > u32::max_value() as u64 {
+            return Err(Error::FileTooLarge);
+        }
+
+        let mut done = 0;
+        let mut result = Ok(());
+        while done < data.len() {
+            let position = file.position;
+            let (sector, offset) = match self.file_sector(file, position, true) {
+                Ok(location) => location,
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            };
+            let chunk = if data.len() - done < SECTOR_SIZE - offset {
+                data.len() - done
+            } else {
+                SECTOR_SIZE - offset
+            };
+
+            // A sector past the end of the file holds no data worth reading.
+            let past_end = offset == 0 && file.position >= file.size;
+            if chunk == SECTOR_SIZE || past_end {
+                self.overwrite(sector);
+            } else if let Err(err) = self.load(sector) {
+                result = Err(err);
+                break;
+            }
+
+            self.cache.0[offset..offset + chunk].copy_from_slice(&data[done..done + chunk]);
+            if let Err(err) = self.store() {
+                self.cached = None;
+                result = Err(err);
+                break;
+            }
+
+            done += chunk;
+            file.position += chunk as u32;
+            if file.position > file.size {
+                file.size = file.position;
+            }
+        }
+
+        // Record whatever was written, even if the write stopped early.
+        self.update_entry(file)?;
+        match result {
+            Ok(()) => Ok(done),
+            Err(_) if done > 0 => Ok(done),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Finishes with a file. Every write already updates the file's
+    /// directory entry, so this cannot lose data, but it reports a failure
+    /// to record the final size. The file is closed either way.
+    pub fn close(&mut self, file: File) -> Result<(), Error> {
+        self.release(&file);
+        if file.mode == OpenMode::Read {
+            Ok(())
+        } else {
+            self.update_entry(&file)
+        }
+    }
+}
@@ -0,0 +1,311 @@
+//! Exercises the filesystem against FAT images stored in temporary files.
+
+extern crate fat;
+
+use std::cell::RefCell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use fat::{BlockDevice, Error, FatType, OpenMode, Volume, MAX_OPEN_ENTRIES, SECTOR_SIZE};
+
+struct ImageFile {
+    file: RefCell<File>,
+}
+
+impl BlockDevice for ImageFile {
+    fn read_sector(&self, sector: u32, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), Error> {
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(sector as u64 * SECTOR_SIZE as u64)).map_err(|_| Error::Io)?;
+        file.read_exact(buf).map_err(|_| Error::Io)
+    }
+
+    fn write_sector(&self, sector: u32, buf: &[u8; SECTOR_SIZE]) -> Result<(), Error> {
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(sector as u64 * SECTOR_SIZE as u64)).map_err(|_| Error::Io)?;
+        file.write_all(buf).map_err(|_| Error::Io)
+    }
+}
+
+struct Image {
+    path: PathBuf,
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn put_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset] = value as u8;
+    buf[offset + 1] = (value >> 8) as u8;
+}
+
+fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+    put_u16(buf, offset, value as u16);
+    put_u16(buf, offset + 2, (value >> 16) as u16);
+}
+
+/// Writes a freshly formatted volume, laid out the way `mkfs.fat` would.
+fn format(name: &str, fat_type: FatType) -> Image {
+    let path = std::env::temp_dir().join(format!("fat-test-{}-{}.img", name, std::process::id()));
+    let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(true)
+                                     .open(&path).unwrap();
+
+    // (total sectors, sectors per cluster, reserved sectors, root entries,
+    // FAT size in sectors)
+    let (total, spc, reserved, root_entries, fat_size) = match fat_type {
+        FatType::Fat16 => (32768u32, 4u8, 1u16, 512u16, 32u32),
+        FatType::Fat32 => (70000u32, 1u8, 32u16, 0u16, 540u32),
+    };
+    file.set_len(total as u64 * SECTOR_SIZE as u64).unwrap();
+
+    let mut boot = [0u8; SECTOR_SIZE];
+    boot[0] = 0xEB;
+    boot[1] = 0x3C;
+    boot[2] = 0x90;
+    boot[3..11].copy_from_slice(b"MKFS.FAT");
+    put_u16(&mut boot, 11, SECTOR_SIZE as u16);
+    boot[13] = spc;
+    put_u16(&mut boot, 14, reserved);
+    boot[16] = 2;
+    put_u16(&mut boot, 17, root_entries);
+    boot[21] = 0xF8;
+    match fat_type {
+        FatType::Fat16 => {
+            put_u16(&mut boot, 19, total as u16);
+            put_u16(&mut boot, 22, fat_size as u16);
+        },
+        FatType::Fat32 => {
+            put_u32(&mut boot, 32, total);
+            put_u32(&mut boot, 36, fat_size);
+            put_u32(&mut boot, 44, 2);
+        },
+    }
+    put_u16(&mut boot, 510, 0xAA55);
+    file.write_all(&boot).unwrap();
+
+    let mut fat = [0u8; SECTOR_SIZE];
+    match fat_type {
+        FatType::Fat16 => {
+            put_u16(&mut fat, 0, 0xFFF8);
+            put_u16(&mut fat, 2, 0xFFFF);
+        },
+        FatType::Fat32 => {
+            put_u32(&mut fat, 0, 0x0FFF_FFF8);
+            put_u32(&mut fat, 4, 0x0FFF_FFFF);
+            // The root directory occupies cluster 2.
+            put_u32(&mut fat, 8, 0x0FFF_FFFF);
+        },
+    }
+    for i in 0..2 {
+        let sector = reserved as u64 + i * fat_size as u64;
+        file.seek(SeekFrom::Start(sector * SECTOR_SIZE as u64)).unwrap();
+        file.write_all(&fat).unwrap();
+    }
+
+    Image { path: path }
+}
+
+fn mount(image: &Image) -> Volume<ImageFile> {
+    let file = OpenOptions::new().read(true).write(true).open(&image.path).unwrap();
+    Volume::mount(ImageFile { file: RefCell::new(file) }).unwrap()
+}
+
+fn read_all(volume: &mut Volume<ImageFile>, path: &[u8]) -> Vec<u8> {
+    let mut file = volume.open(path, OpenMode::Read).unwrap();
+    let mut data = vec![0; file.size() as usize];
+    assert_eq!(volume.read(&mut file, &mut data).unwrap(), data.len());
+    volume.close(file).unwrap();
+    data
+}
+
+fn round_trip(fat_type: FatType, name: &str) {
+    let image = format(name, fat_type);
+    let mut volume = mount(&image);
+    assert_eq!(volume.fat_type(), fat_type);
+
+    let free = volume.free_space().unwrap();
+
+    // Spans several clusters and ends mid-sector.
+    let data: Vec<u8> = (0..10_000u32).map(|i| (i * 7) as u8).collect();
+    let mut file = volume.open(b"/data.bin", OpenMode::Write).unwrap();
+    assert_eq!(volume.write(&mut file, &data).unwrap(), data.len());
+    volume.close(file).unwrap();
+
+    let mut file = volume.open(b"log.txt", OpenMode::Append).unwrap();
+    volume.write(&mut file, b"first\n").unwrap();
+    volume.close(file).unwrap();
+    let mut file = volume.open(b"LOG.TXT", OpenMode::Append).unwrap();
+    volume.write(&mut file, b"second\n").unwrap();
+    volume.close(file).unwrap();
+
+    let used = volume.free_space().unwrap();
+    assert!(used < free);
+
+    // Everything must be readable after mounting again.
+    drop(volume);
+    let mut volume = mount(&image);
+    assert_eq!(read_all(&mut volume, b"DATA.BIN"), data);
+    assert_eq!(read_all(&mut volume, b"log.txt"), b"first\nsecond\n".to_vec());
+    assert_eq!(volume.free_space().unwrap(), used);
+
+    let first = volume.read_dir(b"/", 0).unwrap().unwrap();
+    assert_eq!(first.name(), b"DATA.BIN");
+    assert_eq!(first.size, data.len() as u32);
+    assert!(!first.is_dir);
+    let second = volume.read_dir(b"", 1).unwrap().unwrap();
+    assert_eq!(second.name(), b"LOG.TXT");
+    assert_eq!(volume.read_dir(b"/", 2).unwrap(), None);
+
+    // Seeking and partial reads.
+    let mut file = volume.open(b"data.bin", OpenMode::Read).unwrap();
+    file.seek(5000);
+    let mut buf = [0; 100];
+    assert_eq!(volume.read(&mut file, &mut buf).unwrap(), 100);
+    assert_eq!(&buf[..], &data[5000..5100]);
+    volume.close(file).unwrap();
+
+    // Truncating releases the old clusters.
+    let mut file = volume.open(b"data.bin", OpenMode::Write).unwrap();
+    volume.write(&mut file, b"short").unwrap();
+    volume.close(file).unwrap();
+    assert_eq!(read_all(&mut volume, b"data.bin"), b"short".to_vec());
+    assert!(volume.free_space().unwrap() > used);
+
+    assert_eq!(volume.open(b"missing.txt", OpenMode::Read).unwrap_err(), Error::NotFound);
+    assert_eq!(volume.open(b"toolongname.txt", OpenMode::Write).unwrap_err(), Error::InvalidName);
+    let mut file = volume.open(b"log.txt", OpenMode::Read).unwrap();
+    assert_eq!(volume.write(&mut file, b"x").unwrap_err(), Error::ReadOnly);
+    volume.close(file).unwrap();
+}
+
+#[test]
+fn fat16_round_trip() {
+    round_trip(FatType::Fat16, "fat16");
+}
+
+#[test]
+fn fat32_round_trip() {
+    round_trip(FatType::Fat32, "fat32");
+}
+
+#[test]
+fn fat32_directory_grows() {
+    let image = format("grow", FatType::Fat32);
+    let mut volume = mount(&image);
+
+    // One-sector clusters hold 16 entries, so this needs several clusters.
+    for i in 0..40 {
+        let name = format!("file{}.txt", i);
+        let mut file = volume.open(name.as_bytes(), OpenMode::Write).unwrap();
+        volume.write(&mut file, name.as_bytes()).unwrap();
+        volume.close(file).unwrap();
+    }
+
+    for i in 0..40 {
+        let name = format!("FILE{}.TXT", i);
+        assert_eq!(volume.read_dir(b"/", i).unwrap().unwrap().name(), name.as_bytes());
+        assert_eq!(read_all(&mut volume, name.as_bytes()), name.to_lowercase().into_bytes());
+    }
+}
+
+#[test]
+fn fat32_shared_file() {
+    let image = format("shared", FatType::Fat32);
+    let mut volume = mount(&image);
+
+    // Any number of readers, but only one writer, and no truncating a file
+    // that is open.
+    let mut writer = volume.open(b"log.txt", OpenMode::Append).unwrap();
+    let reader = volume.open(b"LOG.TXT", OpenMode::Read).unwrap();
+    assert_eq!(volume.open(b"log.txt", OpenMode::Append).unwrap_err(), Error::InUse);
+    assert_eq!(volume.open(b"log.txt", OpenMode::Write).unwrap_err(), Error::InUse);
+    volume.write(&mut writer, b"line\n").unwrap();
+    volume.close(writer).unwrap();
+
+    let writer = volume.open(b"log.txt", OpenMode::Append).unwrap();
+    volume.close(writer).unwrap();
+    assert_eq!(volume.open(b"log.txt", OpenMode::Write).unwrap_err(), Error::InUse);
+    volume.close(reader).unwrap();
+    let writer = volume.open(b"log.txt", OpenMode::Write).unwrap();
+    volume.close(writer).unwrap();
+
+    // Forgetting the open files and tracking the ones still held restores
+    // the record.
+    let reader = volume.open(b"log.txt", OpenMode::Read).unwrap();
+    volume.forget_open_files();
+    volume.track(&reader).unwrap();
+    assert_eq!(volume.open(b"log.txt", OpenMode::Write).unwrap_err(), Error::InUse);
+    volume.close(reader).unwrap();
+
+    let mut files = Vec::new();
+    for i in 0..MAX_OPEN_ENTRIES {
+        let name = format!("f{}", i);
+        files.push(volume.open(name.as_bytes(), OpenMode::Write).unwrap());
+    }
+    assert_eq!(volume.open(b"extra", OpenMode::Write).unwrap_err(), Error::TooManyOpenFiles);
+    volume.close(files.pop().unwrap()).unwrap();
+    volume.open(b"extra", OpenMode::Write).unwrap();
+}
+
+#[test]
+fn fat16_root_directory_fills() {
+    let image = format("full", FatType::Fat16);
+    let mut volume = mount(&image);
+
+    for i in 0..512 {
+        let name = format!("f{}", i);
+        let file = volume.open(name.as_bytes(), OpenMode::Write).unwrap();
+        volume.close(file).unwrap();
+    }
+    assert_eq!(volume.open(b"extra", OpenMode::Write).unwrap_err(), Error::DirectoryFull);
+}
+
+/// Patches the image directly, bypassing the volume.
+fn patch(image: &Image, offset: u64, bytes: &[u8]) {
+    let mut file = OpenOptions::new().write(true).open(&image.path).unwrap();
+    file.seek(SeekFrom::Start(offset)).unwrap();
+    file.write_all(bytes).unwrap();
+}
+
+// Where `format` puts the first FAT and cluster 2 of a FAT32 volume.
+const FAT32_FAT_START: u64 = 32 * SECTOR_SIZE as u64;
+const FAT32_DATA_START: u64 = (32 + 2 * 540) * SECTOR_SIZE as u64;
+
+#[test]
+fn fat32_cyclic_directory() {
+    let image = format("cycle", FatType::Fat32);
+    // The root directory's cluster links back to itself.
+    let mut entry = [0; 4];
+    put_u32(&mut entry, 0, 2);
+    patch(&image, FAT32_FAT_START + 8, &entry);
+    let mut volume = mount(&image);
+
+    // The first cluster's 16 entries fill, and then the walk must give up
+    // rather than go round forever.
+    for i in 0..16 {
+        let name = format!("f{}", i);
+        let file = volume.open(name.as_bytes(), OpenMode::Write).unwrap();
+        volume.close(file).unwrap();
+    }
+    assert_eq!(volume.open(b"extra", OpenMode::Write).unwrap_err(), Error::Corrupt);
+}
+
+#[test]
+fn fat32_bad_start_cluster() {
+    let image = format("badcluster", FatType::Fat32);
+    let mut volume = mount(&image);
+    let mut file = volume.open(b"data.bin", OpenMode::Write).unwrap();
+    volume.write(&mut file, b"data").unwrap();
+    volume.close(file).unwrap();
+    drop(volume);
+
+    // The high word of the file's first cluster, past the end of the volume.
+    patch(&image, FAT32_DATA_START + 20, &[0xFF, 0x0F]);
+    let mut volume = mount(&image);
+    let mut file = volume.open(b"data.bin", OpenMode::Read).unwrap();
+    let mut buf = [0; 4];
+    assert_eq!(volume.read(&mut file, &mut buf).unwrap_err(), Error::Corrupt);
+}
@@ -0,0 +1,55 @@
+#include "tock.h"
+#include "audio.h"
+
+struct audio_stream {
+  uint8_t *ring;
+  audio_block_fn *callback;
+  void *ud;
+};
+
+static struct audio_stream play_stream;
+static struct audio_stream record_stream;
+
+static void audio_block_cb(int offset, int len,
+                           __attribute__ ((unused)) int unused,
+                           void* ud) {
+  struct audio_stream* stream = (struct audio_stream*) ud;
+  stream->callback(stream->ring + offset, len, stream->ud);
+}
+
+static int audio_start(struct audio_stream* stream, int num,
+                       uint8_t *ring, size_t len, audio_block_fn callback, void *ud) {
+  stream->ring = ring;
+  stream->callback = callback;
+  stream->ud = ud;
+
+  int err = allow(DRIVER_NUM_AUDIO, num, ring, len);
+  if (err < 0) return err;
+  err = subscribe(DRIVER_NUM_AUDIO, num, audio_block_cb, stream);
+  if (err < 0) return err;
+  return command(DRIVER_NUM_AUDIO, num == 0 ? 2 : 4, 0, 0);
+}
+
+int audio_configure(uint32_t sample_rate, int bits, bool slave) {
+  return command(DRIVER_NUM_AUDIO, 1, sample_rate, bits | (slave ? 1 << 8 : 0));
+}
+
+int audio_block_size(void) {
+  return command(DRIVER_NUM_AUDIO, 6, 0, 0);
+}
+
+int audio_play(uint8_t *ring, size_t len, audio_block_fn callback, void *ud) {
+  return audio_start(&play_stream, 0, ring, len, callback, ud);
+}
+
+int audio_stop_playing(void) {
+  return command(DRIVER_NUM_AUDIO, 3, 0, 0);
+}
+
+int audio_record(uint8_t *ring, size_t len, audio_block_fn callback, void *ud) {
+  return audio_start(&record_stream, 1, ring, len, callback, ud);
+}
+
+int audio_stop_recording(void) {
+  return command(DRIVER_NUM_AUDIO, 5, 0, 0);
+}
@@ -0,0 +1,51 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#include "tock.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_AUDIO 0x9000C
+
+/**
+ * Called with a block of the playback ring to refill, or a block of the
+ * record ring to take. Samples are interleaved left and right.
+ */
+typedef void (audio_block_fn)(uint8_t *block, size_t len, void *ud);
+
+/**
+ * Sets the sample rate and the bits per sample: 16, or 24 or 32 in 32-bit
+ * words. As slave, the codec drives the bit clock and frame sync.
+ */
+int audio_configure(uint32_t sample_rate, int bits, bool slave);
+
+/**
+ * Returns the bytes in a block for the configured format. Rings should
+ * hold a whole number of blocks, at least two.
+ */
+int audio_block_size(void);
+
+/**
+ * Plays `ring` over and over, calling `callback` with each block as it is
+ * taken so that the app can refill it.
+ */
+int audio_play(uint8_t *ring, size_t len, audio_block_fn callback, void *ud);
+
+int audio_stop_playing(void);
+
+/**
+ * Records into `ring` over and over, calling `callback` with each block as
+ * it is filled.
+ */
+int audio_record(uint8_t *ring, size_t len, audio_block_fn callback, void *ud);
+
+int audio_stop_recording(void);
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,84 @@
+#include "tock.h"
+#include "can.h"
+
+struct can_data {
+  bool fired;
+};
+
+static struct can_data result = { .fired = false };
+
+static can_frame_t *rx_buffer;
+static can_receive_fn *rx_callback;
+static void *rx_ud;
+
+static void can_send_cb(__attribute__ ((unused)) int arg1,
+                        __attribute__ ((unused)) int arg2,
+                        __attribute__ ((unused)) int arg3,
+                        void* ud) {
+  struct can_data* data = (struct can_data*) ud;
+  data->fired = true;
+}
+
+static void can_receive_cb(int count,
+                           __attribute__ ((unused)) int arg2,
+                           __attribute__ ((unused)) int arg3,
+                           __attribute__ ((unused)) void* ud) {
+  // More frames may arrive before this runs, so consume only those the
+  // callback is told about.
+  rx_callback(rx_buffer, count, rx_ud);
+  command(DRIVER_NUM_CAN, 3, count, 0);
+}
+
+int can_enable(uint32_t bitrate, bool loopback) {
+  return command(DRIVER_NUM_CAN, 2, bitrate, loopback ? 1 : 0);
+}
+
+int can_disable(void) {
+  return command(DRIVER_NUM_CAN, 7, 0, 0);
+}
+
+int can_send(const can_frame_t *frame) {
+  int err = subscribe(DRIVER_NUM_CAN, 0, can_send_cb, &result);
+  if (err < 0) return err;
+  err = allow(DRIVER_NUM_CAN, 0, (void *) frame, sizeof(can_frame_t));
+  if (err < 0) return err;
+
+  result.fired = false;
+  err = command(DRIVER_NUM_CAN, 1, 0, 0);
+  if (err == TOCK_SUCCESS) {
+    yield_for(&result.fired);
+  }
+  allow(DRIVER_NUM_CAN, 0, NULL, 0);
+  return err;
+}
+
+int can_add_filter(uint32_t id, uint32_t mask, bool extended) {
+  return command(DRIVER_NUM_CAN, 4, id | (extended ? 1u << 31 : 0), mask);
+}
+
+int can_remove_filter(int index) {
+  return command(DRIVER_NUM_CAN, 5, index, 0);
+}
+
+int can_errors(uint8_t *tx_errors, uint8_t *rx_errors, int *state) {
+  int value = command(DRIVER_NUM_CAN, 6, 0, 0);
+  if (value < 0) return value;
+  *tx_errors = value & 0xFF;
+  *rx_errors = (value >> 8) & 0xFF;
+  *state = (value >> 16) & 0x3;
+  return TOCK_SUCCESS;
+}
+
+int can_receive(can_frame_t *buffer, int capacity, can_receive_fn callback, void *ud) {
+  rx_buffer = buffer;
+  rx_callback = callback;
+  rx_ud = ud;
+
+  int err = allow(DRIVER_NUM_CAN, 1, buffer, capacity * sizeof(can_frame_t));
+  if (err < 0) return err;
+  return subscribe(DRIVER_NUM_CAN, 1, can_receive_cb, NULL);
+}
+
+int can_set_error_callback(subscribe_cb callback, void *ud) {
+  return subscribe(DRIVER_NUM_CAN, 2, callback, ud);
+}
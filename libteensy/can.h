@@ -0,0 +1,72 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#include "tock.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_CAN 0x9000A
+
+#define CAN_FLAG_EXTENDED 0x01
+#define CAN_FLAG_REMOTE   0x02
+
+#define CAN_ERROR_ACTIVE  0
+#define CAN_ERROR_PASSIVE 1
+#define CAN_BUS_OFF       2
+
+typedef struct {
+  uint32_t id;
+  uint8_t flags;
+  uint8_t len;
+  uint8_t reserved[2];
+  uint8_t data[8];
+} can_frame_t;
+
+typedef void (can_receive_fn)(const can_frame_t *frames, int count, void *ud);
+
+/**
+ * Joins the bus at `bitrate` bits per second. In loopback mode, sent frames
+ * are received back and nothing reaches the bus.
+ */
+int can_enable(uint32_t bitrate, bool loopback);
+
+int can_disable(void);
+
+/**
+ * Sends a frame and waits until it is on the bus.
+ */
+int can_send(const can_frame_t *frame);
+
+/**
+ * Accepts frames whose ID matches `id` in the bits set in `mask`. Returns
+ * the filter's index, or a negative error code. With no filters, every
+ * frame is accepted.
+ */
+int can_add_filter(uint32_t id, uint32_t mask, bool extended);
+
+int can_remove_filter(int index);
+
+/**
+ * Reads the error counters and the error state, one of the CAN_ERROR_ and
+ * CAN_BUS_OFF values.
+ */
+int can_errors(uint8_t *tx_errors, uint8_t *rx_errors, int *state);
+
+/**
+ * Starts receiving frames into `buffer`. `callback` is called with the
+ * frames received since the last call.
+ */
+int can_receive(can_frame_t *buffer, int capacity, can_receive_fn callback, void *ud);
+
+/**
+ * Calls `callback` with the new error state when it changes.
+ */
+int can_set_error_callback(subscribe_cb callback, void *ud);
+
+#ifdef __cplusplus
+}
+#endif
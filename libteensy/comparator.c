@@ -0,0 +1,42 @@
+#include "tock.h"
+#include "comparator.h"
+
+int comparator_count(void) {
+  return command(DRIVER_NUM_COMPARATOR, 0, 0, 0);
+}
+
+int comparator_configure(int comparator, const comparator_config_t *config) {
+  int word = (config->plus & 0x7) |
+             (config->minus & 0x7) << 3 |
+             (config->hysteresis & 0x3) << 6 |
+             (config->invert ? 1 << 8 : 0) |
+             (config->high_speed ? 1 << 9 : 0) |
+             (config->windowed ? 1 << 10 : 0) |
+             (config->filter_count & 0x7) << 12 |
+             config->filter_period << 16;
+  return command(DRIVER_NUM_COMPARATOR, 1, comparator, word);
+}
+
+int comparator_set_dac(int comparator, int level, bool vdd) {
+  return command(DRIVER_NUM_COMPARATOR, 2, comparator, (level & 0x3F) | (vdd ? 1 << 8 : 0));
+}
+
+int comparator_disable_dac(int comparator) {
+  return command(DRIVER_NUM_COMPARATOR, 3, comparator, 0);
+}
+
+int comparator_subscribe(subscribe_cb callback, void *ud) {
+  return subscribe(DRIVER_NUM_COMPARATOR, 0, callback, ud);
+}
+
+int comparator_enable_edges(int comparator, int edges) {
+  return command(DRIVER_NUM_COMPARATOR, 4, comparator, edges);
+}
+
+int comparator_read(int comparator) {
+  return command(DRIVER_NUM_COMPARATOR, 5, comparator, 0);
+}
+
+int comparator_disable(int comparator) {
+  return command(DRIVER_NUM_COMPARATOR, 6, comparator, 0);
+}
@@ -0,0 +1,75 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#include "tock.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_COMPARATOR 0x9000E
+
+/* Inputs. Input 3 of comparator 0 is pin 36. */
+#define COMPARATOR_IN_DAC 7
+
+#define COMPARATOR_RISING  0x01
+#define COMPARATOR_FALLING 0x02
+
+typedef struct {
+  uint8_t plus;
+  uint8_t minus;
+  /* 0 to 3, from about 5mV to about 30mV. */
+  uint8_t hysteresis;
+  bool invert;
+  bool high_speed;
+  /* Samples that must agree before the output changes, up to 7, or 0 for
+   * none. */
+  uint8_t filter_count;
+  /* Bus clock cycles between samples, when filtering. */
+  uint8_t filter_period;
+  /* Follow the inputs only while the PDB's window signal is high. */
+  bool windowed;
+} comparator_config_t;
+
+/**
+ * Returns the number of comparators.
+ */
+int comparator_count(void);
+
+/**
+ * Configures and enables a comparator.
+ */
+int comparator_configure(int comparator, const comparator_config_t *config);
+
+/**
+ * Sets the comparator's DAC to `level` + 1 64ths of VREF_OUT, or of VDD if
+ * `vdd` is set.
+ */
+int comparator_set_dac(int comparator, int level, bool vdd);
+
+int comparator_disable_dac(int comparator);
+
+/**
+ * Sets the callback for edges, which is called with the comparator, 1 for
+ * a rising edge or 0 for a falling one, and the output now.
+ */
+int comparator_subscribe(subscribe_cb callback, void *ud);
+
+/**
+ * Reports the `edges` of the comparator's output, a mask of
+ * COMPARATOR_RISING and COMPARATOR_FALLING.
+ */
+int comparator_enable_edges(int comparator, int edges);
+
+/**
+ * Returns the comparator's output, or a negative error code.
+ */
+int comparator_read(int comparator);
+
+int comparator_disable(int comparator);
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,53 @@
+#include "tock.h"
+#include "crc.h"
+
+struct crc_data {
+  bool fired;
+  uint32_t crc;
+};
+
+static struct crc_data result = { .fired = false };
+
+static void crc_cb(int crc,
+                   __attribute__ ((unused)) int unused1,
+                   __attribute__ ((unused)) int unused2,
+                   void* ud) {
+  struct crc_data* data = (struct crc_data*) ud;
+  data->crc = (uint32_t) crc;
+  data->fired = true;
+}
+
+int crc_set_buffer(const uint8_t *buffer, int len) {
+  return allow(DRIVER_NUM_CRC, 0, (void *) buffer, len);
+}
+
+int crc_subscribe(subscribe_cb callback, void *ud) {
+  return subscribe(DRIVER_NUM_CRC, 0, callback, ud);
+}
+
+int crc_compute(int len, int algorithm) {
+  return command(DRIVER_NUM_CRC, 1, len, algorithm);
+}
+
+int crc_set_custom(uint32_t polynomial, uint32_t seed, int options) {
+  int err = command(DRIVER_NUM_CRC, 2, (int) polynomial, (int) seed);
+  if (err < 0) return err;
+
+  return command(DRIVER_NUM_CRC, 3, options, 0);
+}
+
+int crc_compute_sync(const uint8_t *data, int len, int algorithm, uint32_t *crc) {
+  int err = crc_set_buffer(data, len);
+  if (err < 0) return err;
+
+  err = crc_subscribe(crc_cb, &result);
+  if (err < 0) return err;
+
+  result.fired = false;
+  err = crc_compute(len, algorithm);
+  if (err < 0) return err;
+
+  yield_for(&result.fired);
+  *crc = result.crc;
+  return TOCK_SUCCESS;
+}
@@ -0,0 +1,55 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#include "tock.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_CRC 0x90010
+
+/* Algorithms. */
+#define CRC_32           0
+#define CRC_16_CCITT     1
+#define CRC_16_XMODEM    2
+#define CRC_CUSTOM       3
+
+/* Options of the custom algorithm. */
+#define CRC_OPTION_32_BIT           0x01
+#define CRC_OPTION_TRANSPOSE_INPUT  0x02
+#define CRC_OPTION_TRANSPOSE_OUTPUT 0x04
+#define CRC_OPTION_COMPLEMENT       0x08
+
+/**
+ * Sets the buffer that CRCs are computed over.
+ */
+int crc_set_buffer(const uint8_t *buffer, int len);
+
+/**
+ * Sets the callback for a computed CRC, which is called with the CRC.
+ */
+int crc_subscribe(subscribe_cb callback, void *ud);
+
+/**
+ * Computes the CRC of the first `len` bytes of the buffer with `algorithm`.
+ */
+int crc_compute(int len, int algorithm);
+
+/**
+ * Sets up CRC_CUSTOM: `polynomial` without its highest term, the `seed`,
+ * and a mask of CRC_OPTION_*. Without CRC_OPTION_32_BIT, the polynomial
+ * and seed must fit in 16 bits.
+ */
+int crc_set_custom(uint32_t polynomial, uint32_t seed, int options);
+
+/**
+ * Computes the CRC of `len` bytes of `data` and waits for it.
+ */
+int crc_compute_sync(const uint8_t *data, int len, int algorithm, uint32_t *crc);
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,45 @@
+#include "tock.h"
+#include "crypto.h"
+
+#define DECRYPT (1 << 8)
+
+int aes_set_key(const uint8_t *key, size_t len) {
+  return allow(DRIVER_NUM_CRYPTO, 0, (void *) key, len);
+}
+
+int aes_set_iv(uint8_t iv[AES_BLOCK_SIZE]) {
+  return allow(DRIVER_NUM_CRYPTO, 1, (void *) iv, AES_BLOCK_SIZE);
+}
+
+static int aes_crypt(int mode, uint8_t *data, size_t len) {
+  int err = allow(DRIVER_NUM_CRYPTO, 2, (void *) data, len);
+  if (err < 0) return err;
+
+  return command(DRIVER_NUM_CRYPTO, 1, mode, len);
+}
+
+int aes_encrypt(aes_mode_t mode, uint8_t *data, size_t len) {
+  return aes_crypt(mode, data, len);
+}
+
+int aes_decrypt(aes_mode_t mode, uint8_t *data, size_t len) {
+  return aes_crypt(mode | DECRYPT, data, len);
+}
+
+static int hash(int command_num, const uint8_t *data, size_t len, uint8_t *digest, size_t size) {
+  int err = allow(DRIVER_NUM_CRYPTO, 2, (void *) data, len);
+  if (err < 0) return err;
+
+  err = allow(DRIVER_NUM_CRYPTO, 3, (void *) digest, size);
+  if (err < 0) return err;
+
+  return command(DRIVER_NUM_CRYPTO, command_num, len, 0);
+}
+
+int sha256(const uint8_t *data, size_t len, uint8_t digest[SHA256_SIZE]) {
+  return hash(2, data, len, digest, SHA256_SIZE);
+}
+
+int sha1(const uint8_t *data, size_t len, uint8_t digest[SHA1_SIZE]) {
+  return hash(3, data, len, digest, SHA1_SIZE);
+}
@@ -0,0 +1,50 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#include "tock.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_CRYPTO 0x90011
+
+#define AES_BLOCK_SIZE 16
+#define SHA256_SIZE 32
+#define SHA1_SIZE 20
+
+typedef enum {
+  AES_ECB = 0,
+  AES_CBC = 1,
+  AES_CTR = 2,
+} aes_mode_t;
+
+/**
+ * Sets the AES key, of 16, 24 or 32 bytes. The buffer is read at each
+ * call, so it must stay valid.
+ */
+int aes_set_key(const uint8_t *key, size_t len);
+
+/**
+ * Sets the IV, or the initial counter for CTR. Each call leaves it ready
+ * for the next part of the message.
+ */
+int aes_set_iv(uint8_t iv[AES_BLOCK_SIZE]);
+
+/**
+ * Encrypts `len` bytes of `data` in place. ECB and CBC take whole blocks.
+ */
+int aes_encrypt(aes_mode_t mode, uint8_t *data, size_t len);
+
+int aes_decrypt(aes_mode_t mode, uint8_t *data, size_t len);
+
+int sha256(const uint8_t *data, size_t len, uint8_t digest[SHA256_SIZE]);
+
+int sha1(const uint8_t *data, size_t len, uint8_t digest[SHA1_SIZE]);
+
+#ifdef __cplusplus
+}
+#endif
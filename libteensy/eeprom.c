@@ -0,0 +1,22 @@
+#include "tock.h"
+#include "eeprom.h"
+
+int eeprom_size(void) {
+  return command(DRIVER_NUM_EEPROM, 0, 0, 0);
+}
+
+int eeprom_read_byte(int offset) {
+  return command(DRIVER_NUM_EEPROM, 1, offset, 0);
+}
+
+int eeprom_write_byte(int offset, uint8_t value) {
+  return command(DRIVER_NUM_EEPROM, 2, offset, value);
+}
+
+int eeprom_read_word(int offset) {
+  return command(DRIVER_NUM_EEPROM, 3, offset, 0);
+}
+
+int eeprom_write_word(int offset, uint16_t value) {
+  return command(DRIVER_NUM_EEPROM, 4, offset, value);
+}
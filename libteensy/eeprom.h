@@ -0,0 +1,34 @@
+#pragma once
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_EEPROM 0x90003
+
+/**
+ * Returns the EEPROM size in bytes, or a negative error code if the EEPROM is
+ * not available.
+ */
+int eeprom_size(void);
+
+/**
+ * Returns the byte at `offset`, or a negative error code.
+ */
+int eeprom_read_byte(int offset);
+
+int eeprom_write_byte(int offset, uint8_t value);
+
+/**
+ * Returns the 16-bit word at `offset`, which must be even, or a negative
+ * error code.
+ */
+int eeprom_read_word(int offset);
+
+int eeprom_write_word(int offset, uint16_t value);
+
+#ifdef __cplusplus
+}
+#endif
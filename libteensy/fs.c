@@ -0,0 +1,70 @@
+#include <string.h>
+
+#include "tock.h"
+#include "fs.h"
+
+int fs_mount(void) {
+  return command(DRIVER_NUM_FS, 1, 0, 0);
+}
+
+int fs_open(const char *path, int mode) {
+  int err = allow(DRIVER_NUM_FS, 1, (void *) path, strlen(path));
+  if (err < 0) return err;
+
+  err = command(DRIVER_NUM_FS, 2, mode, 0);
+  allow(DRIVER_NUM_FS, 1, NULL, 0);
+  return err;
+}
+
+static int fs_transfer(int cmd, int handle, void *buffer, size_t len) {
+  int err = allow(DRIVER_NUM_FS, 0, buffer, len);
+  if (err < 0) return err;
+
+  err = command(DRIVER_NUM_FS, cmd, handle, len);
+  allow(DRIVER_NUM_FS, 0, NULL, 0);
+  return err;
+}
+
+int fs_read(int handle, void *buffer, size_t len) {
+  return fs_transfer(3, handle, buffer, len);
+}
+
+int fs_write(int handle, const void *buffer, size_t len) {
+  return fs_transfer(4, handle, (void *) buffer, len);
+}
+
+int fs_seek(int handle, uint32_t position) {
+  return command(DRIVER_NUM_FS, 8, handle, position);
+}
+
+int fs_size(int handle) {
+  return command(DRIVER_NUM_FS, 9, handle, 0);
+}
+
+int fs_close(int handle) {
+  return command(DRIVER_NUM_FS, 5, handle, 0);
+}
+
+int fs_read_dir(const char *path, int index, fs_dirent_t *entry) {
+  uint8_t record[18];
+
+  int err = allow(DRIVER_NUM_FS, 1, (void *) path, strlen(path));
+  if (err < 0) return err;
+  err = allow(DRIVER_NUM_FS, 0, record, sizeof(record));
+  if (err < 0) return err;
+
+  err = command(DRIVER_NUM_FS, 6, index, 0);
+  allow(DRIVER_NUM_FS, 0, NULL, 0);
+  allow(DRIVER_NUM_FS, 1, NULL, 0);
+
+  if (err == 1) {
+    entry->size = record[0] | (record[1] << 8) | (record[2] << 16) | ((uint32_t) record[3] << 24);
+    entry->is_dir = record[4];
+    memcpy(entry->name, &record[5], sizeof(entry->name));
+  }
+  return err;
+}
+
+int fs_free_space(void) {
+  return command(DRIVER_NUM_FS, 7, 0, 0);
+}
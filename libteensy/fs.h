@@ -0,0 +1,67 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#include "tock.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_FS 0x90005
+
+#define FS_READ   0
+#define FS_WRITE  1
+#define FS_APPEND 2
+
+typedef struct {
+  uint32_t size;
+  bool is_dir;
+  char name[13];
+} fs_dirent_t;
+
+/**
+ * Mounts the card. Other calls mount it as needed, so this is only useful to
+ * check that a formatted card is present.
+ */
+int fs_mount(void);
+
+/**
+ * Opens the file at `path` with one of the FS_ modes, and returns a handle
+ * or a negative error code. FS_WRITE creates or truncates the file, and
+ * FS_APPEND creates it if needed. Fails with TOCK_EBUSY if the file is
+ * already open for writing, or is open at all for FS_WRITE.
+ */
+int fs_open(const char *path, int mode);
+
+/**
+ * Returns the number of bytes read, 0 at the end of the file.
+ */
+int fs_read(int handle, void *buffer, size_t len);
+
+/**
+ * Returns the number of bytes written.
+ */
+int fs_write(int handle, const void *buffer, size_t len);
+
+int fs_seek(int handle, uint32_t position);
+
+int fs_size(int handle);
+
+int fs_close(int handle);
+
+/**
+ * Reads the `index`th entry of the directory at `path`. Returns 1 if
+ * `entry` was filled in, and 0 after the last entry.
+ */
+int fs_read_dir(const char *path, int index, fs_dirent_t *entry);
+
+/**
+ * Returns the free space on the card in KB.
+ */
+int fs_free_space(void);
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,130 @@
+#include "tock.h"
+#include "hid.h"
+
+#define REPORT_KEYBOARD 1
+#define REPORT_MOUSE    2
+#define REPORT_GAMEPAD  3
+
+#define KEY_ENTER 40
+#define KEY_TAB   43
+#define KEY_SPACE 44
+
+struct hid_data {
+  bool fired;
+  int result;
+};
+
+static struct hid_data result = { .fired = false };
+
+static void hid_cb(int code,
+                   __attribute__ ((unused)) int arg2,
+                   __attribute__ ((unused)) int arg3,
+                   void* ud) {
+  struct hid_data* data = (struct hid_data*) ud;
+  data->result = code;
+  data->fired = true;
+}
+
+bool hid_is_configured(void) {
+  return command(DRIVER_NUM_HID, 3, 0, 0) == 1;
+}
+
+int hid_send_report(const uint8_t *report, size_t len) {
+  int err = subscribe(DRIVER_NUM_HID, 0, hid_cb, &result);
+  if (err < 0) return err;
+  err = allow(DRIVER_NUM_HID, 0, (void *) report, len);
+  if (err < 0) return err;
+
+  result.fired = false;
+  err = command(DRIVER_NUM_HID, 1, len, 0);
+  allow(DRIVER_NUM_HID, 0, NULL, 0);
+  if (err < 0) return err;
+
+  yield_for(&result.fired);
+  return result.result;
+}
+
+int hid_keyboard_press(uint8_t modifiers, const uint8_t keys[6]) {
+  uint8_t report[9] = { REPORT_KEYBOARD, modifiers, 0 };
+  for (int i = 0; i < 6; i++) {
+    report[3 + i] = keys[i];
+  }
+  return hid_send_report(report, sizeof(report));
+}
+
+int hid_keyboard_release_all(void) {
+  uint8_t report[9] = { REPORT_KEYBOARD };
+  return hid_send_report(report, sizeof(report));
+}
+
+// The key codes of the punctuation from '!' to '~' on a US layout, with the
+// top bit set when shift is needed. [HID Usage Tables, Section 10]
+static const uint8_t bang_keys[] = {
+  0x80 | 30, 0x80 | 52, 0x80 | 32, 0x80 | 33, 0x80 | 34, 0x80 | 36, 52,  // !"#$%&'
+  0x80 | 38, 0x80 | 39, 0x80 | 37, 0x80 | 46, 54, 45, 55, 56,          // ()*+,-./
+};
+
+static const uint8_t colon_keys[] = {
+  0x80 | 51, 51, 0x80 | 54, 46, 0x80 | 55, 0x80 | 56, 0x80 | 31,       // :;<=>?@
+};
+
+static const uint8_t bracket_keys[] = {
+  47, 49, 48, 0x80 | 35, 0x80 | 45, 53,                                // [\]^_`
+};
+
+static const uint8_t brace_keys[] = {
+  0x80 | 47, 0x80 | 49, 0x80 | 48, 0x80 | 53,                          // {|}~
+};
+
+// Returns the key code with the shift flag in the top bit, or 0.
+static uint8_t ascii_key(char c) {
+  if (c >= 'a' && c <= 'z') return 4 + (c - 'a');
+  if (c >= 'A' && c <= 'Z') return 0x80 | (4 + (c - 'A'));
+  if (c >= '1' && c <= '9') return 30 + (c - '1');
+  if (c == '0') return 39;
+  if (c == ' ') return KEY_SPACE;
+  if (c == '\n') return KEY_ENTER;
+  if (c == '\t') return KEY_TAB;
+  if (c >= '!' && c <= '/') return bang_keys[c - '!'];
+  if (c >= ':' && c <= '@') return colon_keys[c - ':'];
+  if (c >= '[' && c <= '`') return bracket_keys[c - '['];
+  if (c >= '{' && c <= '~') return brace_keys[c - '{'];
+  return 0;
+}
+
+int hid_keyboard_print(const char *text) {
+  for (; *text; text++) {
+    uint8_t key = ascii_key(*text);
+    if (key == 0) continue;
+
+    uint8_t keys[6] = { key & 0x7F };
+    int err = hid_keyboard_press((key & 0x80) ? HID_MOD_SHIFT : 0, keys);
+    if (err < 0) return err;
+    err = hid_keyboard_release_all();
+    if (err < 0) return err;
+  }
+  return TOCK_SUCCESS;
+}
+
+int hid_keyboard_leds(void) {
+  return command(DRIVER_NUM_HID, 2, 0, 0);
+}
+
+int hid_set_leds_callback(subscribe_cb callback, void *ud) {
+  return subscribe(DRIVER_NUM_HID, 1, callback, ud);
+}
+
+int hid_mouse_move(uint8_t buttons, int8_t x, int8_t y, int8_t wheel) {
+  uint8_t report[5] = { REPORT_MOUSE, buttons, (uint8_t) x, (uint8_t) y, (uint8_t) wheel };
+  return hid_send_report(report, sizeof(report));
+}
+
+int hid_gamepad_send(uint16_t buttons, int8_t x, int8_t y, int8_t z, int8_t rz, uint8_t hat) {
+  uint8_t report[8] = {
+    REPORT_GAMEPAD,
+    buttons & 0xFF, buttons >> 8,
+    (uint8_t) x, (uint8_t) y, (uint8_t) z, (uint8_t) rz,
+    hat & 0x0F,
+  };
+  return hid_send_report(report, sizeof(report));
+}
@@ -0,0 +1,75 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#include "tock.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_HID 0x90006
+
+#define HID_MOD_CTRL  0x01
+#define HID_MOD_SHIFT 0x02
+#define HID_MOD_ALT   0x04
+#define HID_MOD_GUI   0x08
+
+#define HID_MOUSE_LEFT   0x01
+#define HID_MOUSE_RIGHT  0x02
+#define HID_MOUSE_MIDDLE 0x04
+
+#define HID_HAT_CENTERED 8
+
+#define HID_LED_NUM_LOCK    0x01
+#define HID_LED_CAPS_LOCK   0x02
+#define HID_LED_SCROLL_LOCK 0x04
+
+/**
+ * Returns true once the host has configured the device.
+ */
+bool hid_is_configured(void);
+
+/**
+ * Sends a raw report, starting with its report ID, and waits until the host
+ * has read it.
+ */
+int hid_send_report(const uint8_t *report, size_t len);
+
+/**
+ * Reports the keys currently held: HID_MOD_ bits and up to six key codes.
+ * Unused key slots are 0.
+ */
+int hid_keyboard_press(uint8_t modifiers, const uint8_t keys[6]);
+
+int hid_keyboard_release_all(void);
+
+/**
+ * Types an ASCII string on a US layout, pressing and releasing each key.
+ * Characters that have no key are skipped.
+ */
+int hid_keyboard_print(const char *text);
+
+/**
+ * Returns the HID_LED_ bits set by the host.
+ */
+int hid_keyboard_leds(void);
+
+int hid_set_leds_callback(subscribe_cb callback, void *ud);
+
+/**
+ * Moves the pointer and wheel by relative amounts with the given
+ * HID_MOUSE_ buttons held.
+ */
+int hid_mouse_move(uint8_t buttons, int8_t x, int8_t y, int8_t wheel);
+
+/**
+ * Reports the state of a gamepad: a bit per button, four axes from -127 to
+ * 127 and a hat direction, 0 to 7 clockwise from up or HID_HAT_CENTERED.
+ */
+int hid_gamepad_send(uint16_t buttons, int8_t x, int8_t y, int8_t z, int8_t rz, uint8_t hat);
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,34 @@
+#include "tock.h"
+#include "infrared.h"
+
+int infrared_subscribe(subscribe_cb callback, void *ud) {
+  return subscribe(DRIVER_NUM_INFRARED, 0, callback, ud);
+}
+
+int infrared_send_nec(uint16_t address, uint8_t code) {
+  return command(DRIVER_NUM_INFRARED, 1, address, code);
+}
+
+int infrared_send_nec_repeat(void) {
+  return command(DRIVER_NUM_INFRARED, 2, 0, 0);
+}
+
+int infrared_send_rc5(uint8_t address, uint8_t code, bool toggle) {
+  return command(DRIVER_NUM_INFRARED, 3, (address & 0x1F) | (toggle ? 1 << 8 : 0), code);
+}
+
+int infrared_send_sony(uint16_t address, uint8_t code, int bits) {
+  return command(DRIVER_NUM_INFRARED, 4, address, (code & 0x7F) | bits << 8);
+}
+
+int infrared_send_raw(const uint16_t *timings, int count) {
+  int err = allow(DRIVER_NUM_INFRARED, 0, (void *) timings, count * sizeof(uint16_t));
+  if (err < 0) {
+    return err;
+  }
+  return command(DRIVER_NUM_INFRARED, 5, count, 0);
+}
+
+int infrared_set_carrier(int frequency, int duty_percent) {
+  return command(DRIVER_NUM_INFRARED, 6, frequency, duty_percent);
+}
@@ -0,0 +1,59 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#include "tock.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_INFRARED 0x9000F
+
+/* The most raw timings in one code. */
+#define INFRARED_MAX_TIMINGS 256
+
+/**
+ * Sets the callback for a sent code. Only one code is sent at a time, and
+ * the others return EBUSY until it is out.
+ */
+int infrared_subscribe(subscribe_cb callback, void *ud);
+
+/**
+ * Sends an NEC code, as extended NEC if `address` is above 0xFF.
+ */
+int infrared_send_nec(uint16_t address, uint8_t code);
+
+/**
+ * Sends the NEC repeat code, as a held key does.
+ */
+int infrared_send_nec_repeat(void);
+
+/**
+ * Sends an RC5 code: `address` is 0 to 31 and `code` 0 to 127. Flip
+ * `toggle` on each new key press.
+ */
+int infrared_send_rc5(uint8_t address, uint8_t code, bool toggle);
+
+/**
+ * Sends a Sony SIRC code of 12, 15 or 20 `bits`, with 5, 8 or 13 address
+ * bits.
+ */
+int infrared_send_sony(uint16_t address, uint8_t code, int bits);
+
+/**
+ * Sends `count` raw timings, microseconds of mark and space in turn,
+ * starting with a mark.
+ */
+int infrared_send_raw(const uint16_t *timings, int count);
+
+/**
+ * Uses a carrier of `frequency` Hz with a duty cycle of `duty_percent`
+ * for every code, or each protocol's own if `frequency` is 0.
+ */
+int infrared_set_carrier(int frequency, int duty_percent);
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,51 @@
+#include "tock.h"
+#include "keyboard.h"
+
+static keyboard_key_fn *key_callback;
+static void *key_ud;
+
+static void keyboard_upcall(int key,
+                            int modifiers,
+                            int ascii,
+                            __attribute__ ((unused)) void* ud) {
+  key_callback(key & 0xFF, (key >> 8) & 1, modifiers, ascii, key_ud);
+}
+
+bool keyboard_is_attached(void) {
+  return command(DRIVER_NUM_KEYBOARD, 1, 0, 0) == 1;
+}
+
+int keyboard_leds(void) {
+  return command(DRIVER_NUM_KEYBOARD, 2, 0, 0);
+}
+
+int keyboard_set_callback(keyboard_key_fn callback, void *ud) {
+  key_callback = callback;
+  key_ud = ud;
+  return subscribe(DRIVER_NUM_KEYBOARD, 0, callback ? keyboard_upcall : NULL, NULL);
+}
+
+struct getch_data {
+  bool fired;
+  char ascii;
+};
+
+static void getch_callback(__attribute__ ((unused)) uint8_t usage,
+                           bool pressed,
+                           __attribute__ ((unused)) uint8_t modifiers,
+                           char ascii,
+                           void* ud) {
+  struct getch_data* data = (struct getch_data*) ud;
+  if (pressed && ascii != 0) {
+    data->ascii = ascii;
+    data->fired = true;
+  }
+}
+
+char keyboard_getch(void) {
+  struct getch_data data = { false, 0 };
+  keyboard_set_callback(getch_callback, &data);
+  yield_for(&data.fired);
+  keyboard_set_callback(NULL, NULL);
+  return data.ascii;
+}
@@ -0,0 +1,48 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#include "tock.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_KEYBOARD 0x90008
+
+#define KEYBOARD_MODIFIER_CTRL  0x11
+#define KEYBOARD_MODIFIER_SHIFT 0x22
+#define KEYBOARD_MODIFIER_ALT   0x44
+#define KEYBOARD_MODIFIER_GUI   0x88
+
+#define KEYBOARD_LED_NUM_LOCK  0x01
+#define KEYBOARD_LED_CAPS_LOCK 0x02
+
+/**
+ * Called when a key is pressed or released. `usage` is the key's HID usage
+ * ID, and `ascii` the character it types on a US layout, or 0 if it types
+ * none or was released.
+ */
+typedef void (keyboard_key_fn)(uint8_t usage, bool pressed, uint8_t modifiers, char ascii, void *ud);
+
+/**
+ * Returns true if a keyboard is plugged into the USB host port.
+ */
+bool keyboard_is_attached(void);
+
+/**
+ * Returns the lit lock LEDs.
+ */
+int keyboard_leds(void);
+
+int keyboard_set_callback(keyboard_key_fn callback, void *ud);
+
+/**
+ * Waits for a key that types a character, and returns it.
+ */
+char keyboard_getch(void);
+
+#ifdef __cplusplus
+}
+#endif
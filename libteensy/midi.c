@@ -0,0 +1,112 @@
+#include "tock.h"
+#include "midi.h"
+
+struct midi_data {
+  bool fired;
+  int result;
+};
+
+static struct midi_data result = { .fired = false };
+
+static midi_packet_t *rx_buffer;
+static midi_receive_fn *rx_callback;
+static void *rx_ud;
+
+static void midi_send_cb(int code,
+                         __attribute__ ((unused)) int arg2,
+                         __attribute__ ((unused)) int arg3,
+                         void* ud) {
+  struct midi_data* data = (struct midi_data*) ud;
+  data->result = code;
+  data->fired = true;
+}
+
+static void midi_receive_cb(int count,
+                             __attribute__ ((unused)) int arg2,
+                             __attribute__ ((unused)) int arg3,
+                             __attribute__ ((unused)) void* ud) {
+  // More packets may arrive before this runs, so consume only those the
+  // callback is told about.
+  rx_callback(rx_buffer, count, rx_ud);
+  command(DRIVER_NUM_MIDI, 3, count, 0);
+}
+
+bool midi_is_configured(void) {
+  return command(DRIVER_NUM_MIDI, 4, 0, 0) == 1;
+}
+
+int midi_cables(void) {
+  return command(DRIVER_NUM_MIDI, 2, 0, 0);
+}
+
+int midi_send_packets(const midi_packet_t *packets, int count) {
+  int err = subscribe(DRIVER_NUM_MIDI, 0, midi_send_cb, &result);
+  if (err < 0) return err;
+  err = allow(DRIVER_NUM_MIDI, 0, (void *) packets, count * sizeof(midi_packet_t));
+  if (err < 0) return err;
+
+  result.fired = false;
+  err = command(DRIVER_NUM_MIDI, 1, count, 0);
+  if (err == TOCK_SUCCESS) {
+    yield_for(&result.fired);
+    err = result.result;
+  }
+  allow(DRIVER_NUM_MIDI, 0, NULL, 0);
+  return err;
+}
+
+// The code index of a message, from its status byte.
+// [USB MIDI 1.0 Specification, Table 4-1]
+static uint8_t code_index(uint8_t status) {
+  if (status < 0xF0) return status >> 4;
+  switch (status) {
+    case 0xF1:
+    case 0xF3:
+      return 0x2;
+    case 0xF2:
+      return 0x3;
+    case 0xF6:
+      return 0x5;
+    default:
+      return 0xF;
+  }
+}
+
+int midi_send(int cable, uint8_t status, uint8_t data1, uint8_t data2) {
+  midi_packet_t packet = {
+    .header = (cable << 4) | code_index(status),
+    .data = { status, data1, data2 },
+  };
+  return midi_send_packets(&packet, 1);
+}
+
+int midi_note_on(int cable, int channel, uint8_t note, uint8_t velocity) {
+  return midi_send(cable, 0x90 | (channel & 0xF), note & 0x7F, velocity & 0x7F);
+}
+
+int midi_note_off(int cable, int channel, uint8_t note, uint8_t velocity) {
+  return midi_send(cable, 0x80 | (channel & 0xF), note & 0x7F, velocity & 0x7F);
+}
+
+int midi_control_change(int cable, int channel, uint8_t control, uint8_t value) {
+  return midi_send(cable, 0xB0 | (channel & 0xF), control & 0x7F, value & 0x7F);
+}
+
+int midi_program_change(int cable, int channel, uint8_t program) {
+  return midi_send(cable, 0xC0 | (channel & 0xF), program & 0x7F, 0);
+}
+
+int midi_pitch_bend(int cable, int channel, int value) {
+  int bend = value + 8192;
+  return midi_send(cable, 0xE0 | (channel & 0xF), bend & 0x7F, (bend >> 7) & 0x7F);
+}
+
+int midi_receive(midi_packet_t *buffer, int capacity, midi_receive_fn callback, void *ud) {
+  rx_buffer = buffer;
+  rx_callback = callback;
+  rx_ud = ud;
+
+  int err = allow(DRIVER_NUM_MIDI, 1, buffer, capacity * sizeof(midi_packet_t));
+  if (err < 0) return err;
+  return subscribe(DRIVER_NUM_MIDI, 1, midi_receive_cb, NULL);
+}
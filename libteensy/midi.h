@@ -0,0 +1,67 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#include "tock.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_MIDI 0x90007
+
+/**
+ * A USB MIDI event packet. The header holds the cable number in its top
+ * four bits and the code index, the kind of message, in its bottom four.
+ */
+typedef struct {
+  uint8_t header;
+  uint8_t data[3];
+} midi_packet_t;
+
+#define MIDI_PACKET_CABLE(p) ((p).header >> 4)
+
+typedef void (midi_receive_fn)(const midi_packet_t *packets, int count, void *ud);
+
+bool midi_is_configured(void);
+
+/**
+ * Returns the number of virtual cables.
+ */
+int midi_cables(void);
+
+/**
+ * Sends raw event packets and waits until the host has read them.
+ */
+int midi_send_packets(const midi_packet_t *packets, int count);
+
+/**
+ * Sends a channel or system message of up to three bytes on `cable`, with
+ * the code index worked out from the status byte. System exclusive
+ * messages must be sent as raw packets.
+ */
+int midi_send(int cable, uint8_t status, uint8_t data1, uint8_t data2);
+
+int midi_note_on(int cable, int channel, uint8_t note, uint8_t velocity);
+
+int midi_note_off(int cable, int channel, uint8_t note, uint8_t velocity);
+
+int midi_control_change(int cable, int channel, uint8_t control, uint8_t value);
+
+int midi_program_change(int cable, int channel, uint8_t program);
+
+/**
+ * `value` ranges from -8192 to 8191, 0 being centered.
+ */
+int midi_pitch_bend(int cable, int channel, int value);
+
+/**
+ * Starts receiving packets into `buffer`. `callback` is called with the
+ * packets received since the last call, from all cables.
+ */
+int midi_receive(midi_packet_t *buffer, int capacity, midi_receive_fn callback, void *ud);
+
+#ifdef __cplusplus
+}
+#endif
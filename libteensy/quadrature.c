@@ -0,0 +1,59 @@
+#include "tock.h"
+#include "quadrature.h"
+
+struct quadrature_data {
+  bool fired;
+  int kind;
+  uint32_t lo;
+  uint32_t hi;
+};
+
+static struct quadrature_data result = { .fired = false };
+
+static void quadrature_cb(int kind, int lo, int hi, void* ud) {
+  struct quadrature_data* data = (struct quadrature_data*) ud;
+  data->kind = kind;
+  data->lo = (uint32_t) lo;
+  data->hi = (uint32_t) hi;
+  data->fired = true;
+}
+
+static int quadrature_read(int command_num, int decoder) {
+  int err = subscribe(DRIVER_NUM_QUADRATURE, 0, quadrature_cb, &result);
+  if (err < 0) return err;
+
+  result.fired = false;
+  err = command(DRIVER_NUM_QUADRATURE, command_num, decoder, 0);
+  if (err < 0) return err;
+
+  yield_for(&result.fired);
+  return TOCK_SUCCESS;
+}
+
+int quadrature_count(void) {
+  return command(DRIVER_NUM_QUADRATURE, 0, 0, 0);
+}
+
+int quadrature_position(int decoder, int64_t *position) {
+  int err = quadrature_read(1, decoder);
+  if (err < 0) return err;
+
+  *position = (int64_t) (((uint64_t) result.hi << 32) | result.lo);
+  return TOCK_SUCCESS;
+}
+
+int quadrature_velocity(int decoder, int32_t *velocity) {
+  int err = quadrature_read(2, decoder);
+  if (err < 0) return err;
+
+  *velocity = (int32_t) result.lo;
+  return TOCK_SUCCESS;
+}
+
+int quadrature_reset(int decoder) {
+  return command(DRIVER_NUM_QUADRATURE, 3, decoder, 0);
+}
+
+int quadrature_index_reset(int decoder, bool enable) {
+  return command(DRIVER_NUM_QUADRATURE, 4, decoder, enable);
+}
@@ -0,0 +1,40 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_QUADRATURE 0x90000
+
+/**
+ * Returns the number of quadrature decoders, or a negative error code.
+ */
+int quadrature_count(void);
+
+/**
+ * Reads the signed position of a decoder, in encoder counts.
+ */
+int quadrature_position(int decoder, int64_t *position);
+
+/**
+ * Reads the velocity of a decoder in counts per second, measured since the
+ * previous call to this function for the same decoder.
+ */
+int quadrature_velocity(int decoder, int32_t *velocity);
+
+/**
+ * Resets the position of a decoder to zero.
+ */
+int quadrature_reset(int decoder);
+
+/**
+ * Enables or disables resetting the position on the encoder's index pulse.
+ */
+int quadrature_index_reset(int decoder, bool enable);
+
+#ifdef __cplusplus
+}
+#endif
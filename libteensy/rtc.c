@@ -0,0 +1,74 @@
+#include "tock.h"
+#include "rtc.h"
+
+struct rtc_data {
+  bool fired;
+  int arg1;
+  int arg2;
+};
+
+static struct rtc_data result = { .fired = false };
+
+static void rtc_cb(__attribute__ ((unused)) int cmd,
+                   int arg1,
+                   int arg2,
+                   void* ud) {
+  struct rtc_data* data = (struct rtc_data*) ud;
+  data->arg1 = arg1;
+  data->arg2 = arg2;
+  data->fired = true;
+}
+
+static int rtc_command_sync(int cmd, int arg1) {
+  int err = subscribe(DRIVER_NUM_RTC, 0, rtc_cb, &result);
+  if (err < 0) return err;
+
+  result.fired = false;
+  err = command(DRIVER_NUM_RTC, cmd, arg1, 0);
+  if (err < 0) return err;
+
+  yield_for(&result.fired);
+  return TOCK_SUCCESS;
+}
+
+uint32_t rtc_seconds(void) {
+  return (uint32_t) command(DRIVER_NUM_RTC, 1, 0, 0);
+}
+
+int rtc_set_seconds(uint32_t seconds) {
+  return command(DRIVER_NUM_RTC, 2, (int) seconds, 0);
+}
+
+int rtc_get_datetime(rtc_datetime_t *datetime) {
+  int err = rtc_command_sync(3, 0);
+  if (err < 0) return err;
+
+  uint32_t date = (uint32_t) result.arg1;
+  uint32_t time = (uint32_t) result.arg2;
+  datetime->year    = date >> 16;
+  datetime->month   = (date >> 8) & 0xFF;
+  datetime->day     = date & 0xFF;
+  datetime->weekday = time >> 24;
+  datetime->hour    = (time >> 16) & 0xFF;
+  datetime->minute  = (time >> 8) & 0xFF;
+  datetime->second  = time & 0xFF;
+  return TOCK_SUCCESS;
+}
+
+int rtc_set_datetime(const rtc_datetime_t *datetime) {
+  int date = (datetime->year << 16) | (datetime->month << 8) | datetime->day;
+  int time = (datetime->hour << 16) | (datetime->minute << 8) | datetime->second;
+  return command(DRIVER_NUM_RTC, 4, date, time);
+}
+
+bool rtc_is_valid(void) {
+  return command(DRIVER_NUM_RTC, 7, 0, 0) == 1;
+}
+
+int rtc_set_compensation(int8_t cycles, uint32_t interval) {
+  return command(DRIVER_NUM_RTC, 8, cycles, (int) interval);
+}
+
+int rtc_sleep_until(uint32_t seconds) {
+  return rtc_command_sync(5, (int) seconds);
+}
@@ -0,0 +1,58 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_RTC 0x90002
+
+typedef struct {
+  uint16_t year;
+  uint8_t month;   // 1-12
+  uint8_t day;     // 1-31
+  uint8_t weekday; // 0 is Sunday
+  uint8_t hour;
+  uint8_t minute;
+  uint8_t second;
+} rtc_datetime_t;
+
+/**
+ * Returns the number of seconds since the Unix epoch.
+ */
+uint32_t rtc_seconds(void);
+
+int rtc_set_seconds(uint32_t seconds);
+
+/**
+ * Reads the current UTC date and time.
+ */
+int rtc_get_datetime(rtc_datetime_t *datetime);
+
+/**
+ * Sets the clock from a UTC date and time. The weekday is ignored.
+ */
+int rtc_set_datetime(const rtc_datetime_t *datetime);
+
+/**
+ * Returns true if the clock has been set since its battery was last removed.
+ */
+bool rtc_is_valid(void);
+
+/**
+ * Corrects for crystal error: one second out of every `interval` seconds
+ * (1-256) is made `cycles` 32kHz cycles shorter, or longer if `cycles` is
+ * negative.
+ */
+int rtc_set_compensation(int8_t cycles, uint32_t interval);
+
+/**
+ * Blocks until the clock reaches `seconds` since the Unix epoch.
+ */
+int rtc_sleep_until(uint32_t seconds);
+
+#ifdef __cplusplus
+}
+#endif
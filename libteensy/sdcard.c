@@ -0,0 +1,35 @@
+#include "tock.h"
+#include "sdcard.h"
+
+bool sdcard_is_present(void) {
+  return command(DRIVER_NUM_SDCARD, 5, 0, 0) == 1;
+}
+
+int sdcard_init(void) {
+  return command(DRIVER_NUM_SDCARD, 1, 0, 0);
+}
+
+int sdcard_sector_count(void) {
+  return command(DRIVER_NUM_SDCARD, 2, 0, 0);
+}
+
+static int sdcard_transfer(int cmd, uint32_t sector, uint8_t *buffer, uint32_t count) {
+  int err = allow(DRIVER_NUM_SDCARD, 0, buffer, count * SDCARD_SECTOR_SIZE);
+  if (err < 0) return err;
+
+  err = command(DRIVER_NUM_SDCARD, cmd, sector, count);
+  allow(DRIVER_NUM_SDCARD, 0, NULL, 0);
+  return err;
+}
+
+int sdcard_read(uint32_t sector, uint8_t *buffer, uint32_t count) {
+  return sdcard_transfer(3, sector, buffer, count);
+}
+
+int sdcard_write(uint32_t sector, const uint8_t *buffer, uint32_t count) {
+  return sdcard_transfer(4, sector, (uint8_t *) buffer, count);
+}
+
+int sdcard_set_detect_callback(subscribe_cb callback, void *ud) {
+  return subscribe(DRIVER_NUM_SDCARD, 0, callback, ud);
+}
@@ -0,0 +1,45 @@
+#pragma once
+
+#include <stdint.h>
+
+#include "tock.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_SDCARD 0x90004
+
+#define SDCARD_SECTOR_SIZE 512
+
+/**
+ * Returns true if a card is in the socket.
+ */
+bool sdcard_is_present(void);
+
+/**
+ * Initializes the card. This must be called again after a card is replaced.
+ */
+int sdcard_init(void);
+
+/**
+ * Returns the number of sectors on the card, or a negative error code.
+ */
+int sdcard_sector_count(void);
+
+/**
+ * Reads `count` sectors starting at `sector`. `buffer` must hold at least
+ * `count * SDCARD_SECTOR_SIZE` bytes.
+ */
+int sdcard_read(uint32_t sector, uint8_t *buffer, uint32_t count);
+
+int sdcard_write(uint32_t sector, const uint8_t *buffer, uint32_t count);
+
+/**
+ * Calls `callback` with 1 when a card is inserted and 0 when it is removed.
+ */
+int sdcard_set_detect_callback(subscribe_cb callback, void *ud);
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,40 @@
+#include "tock.h"
+#include "touch.h"
+
+int touch_count(void) {
+  return command(DRIVER_NUM_TOUCH, 0, 0, 0);
+}
+
+int touch_subscribe(subscribe_cb callback, void *ud) {
+  return subscribe(DRIVER_NUM_TOUCH, 0, callback, ud);
+}
+
+int touch_enable_events(int button) {
+  return command(DRIVER_NUM_TOUCH, 1, button, 0);
+}
+
+int touch_disable_events(int button) {
+  return command(DRIVER_NUM_TOUCH, 2, button, 0);
+}
+
+int touch_read(int button) {
+  return command(DRIVER_NUM_TOUCH, 3, button, 0);
+}
+
+int touch_read_raw(int button, uint16_t *count, uint16_t *baseline) {
+  int value = command(DRIVER_NUM_TOUCH, 4, button, 0);
+  if (value < 0) return value;
+  *count = value;
+  value = command(DRIVER_NUM_TOUCH, 5, button, 0);
+  if (value < 0) return value;
+  *baseline = value;
+  return TOCK_SUCCESS;
+}
+
+int touch_set_threshold(int button, uint16_t threshold) {
+  return command(DRIVER_NUM_TOUCH, 6, button, threshold);
+}
+
+int touch_recalibrate(int button) {
+  return command(DRIVER_NUM_TOUCH, 7, button, 0);
+}
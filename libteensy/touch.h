@@ -0,0 +1,53 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#include "tock.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_TOUCH 0x9000D
+
+/**
+ * Returns the number of touch buttons.
+ */
+int touch_count(void);
+
+/**
+ * Sets the callback for presses and releases, which is called with the
+ * button and 1 for a press or 0 for a release.
+ */
+int touch_subscribe(subscribe_cb callback, void *ud);
+
+int touch_enable_events(int button);
+
+int touch_disable_events(int button);
+
+/**
+ * Returns 1 if `button` is touched, 0 if not, or a negative error code.
+ */
+int touch_read(int button);
+
+/**
+ * Reads the button's last count and its untouched baseline.
+ */
+int touch_read_raw(int button, uint16_t *count, uint16_t *baseline);
+
+/**
+ * Sets how many counts above its baseline make the button touched, or
+ * 1/8 of its baseline if `threshold` is 0. This recalibrates the button.
+ */
+int touch_set_threshold(int button, uint16_t threshold);
+
+/**
+ * Retakes the button's baseline at its next scan. It should not be
+ * touched meanwhile.
+ */
+int touch_recalibrate(int button);
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,75 @@
+#include "tock.h"
+#include "udp.h"
+
+struct udp_config_data {
+  bool fired;
+  udp_addr_t address;
+  udp_addr_t netmask;
+  udp_addr_t router;
+};
+
+static uint8_t *rx_buffer;
+static udp_receive_fn *rx_callback;
+static void *rx_ud;
+
+static void udp_receive_cb(int len, int address, int port,
+                           __attribute__ ((unused)) void* ud) {
+  rx_callback(rx_buffer, len, (udp_addr_t) address, port, rx_ud);
+  command(DRIVER_NUM_UDP, 3, 0, 0);
+}
+
+static void udp_config_cb(int address, int netmask, int router, void* ud) {
+  struct udp_config_data* data = (struct udp_config_data*) ud;
+  data->address = address;
+  data->netmask = netmask;
+  data->router = router;
+  data->fired = true;
+}
+
+int udp_bind(uint16_t port) {
+  return command(DRIVER_NUM_UDP, 1, port, 0);
+}
+
+int udp_unbind(void) {
+  return command(DRIVER_NUM_UDP, 4, 0, 0);
+}
+
+int udp_send(udp_addr_t address, uint16_t port, const void *payload, size_t len) {
+  int err = allow(DRIVER_NUM_UDP, 0, (void *) payload, len);
+  if (err < 0) return err;
+  err = command(DRIVER_NUM_UDP, 2, address, port);
+  allow(DRIVER_NUM_UDP, 0, NULL, 0);
+  return err;
+}
+
+int udp_receive(uint8_t *buffer, size_t len, udp_receive_fn callback, void *ud) {
+  rx_buffer = buffer;
+  rx_callback = callback;
+  rx_ud = ud;
+
+  int err = allow(DRIVER_NUM_UDP, 1, buffer, len);
+  if (err < 0) return err;
+  return subscribe(DRIVER_NUM_UDP, 0, udp_receive_cb, NULL);
+}
+
+int udp_config(udp_addr_t *address, udp_addr_t *netmask, udp_addr_t *router) {
+  struct udp_config_data data = { .fired = false };
+  int err = subscribe(DRIVER_NUM_UDP, 1, udp_config_cb, &data);
+  if (err < 0) return err;
+  err = command(DRIVER_NUM_UDP, 5, 0, 0);
+  if (err == TOCK_SUCCESS) {
+    yield_for(&data.fired);
+  }
+  subscribe(DRIVER_NUM_UDP, 1, NULL, NULL);
+  if (err < 0) return err;
+  if (data.address == 0) return TOCK_EOFF;
+
+  *address = data.address;
+  *netmask = data.netmask;
+  *router = data.router;
+  return TOCK_SUCCESS;
+}
+
+int udp_set_config_callback(subscribe_cb callback, void *ud) {
+  return subscribe(DRIVER_NUM_UDP, 1, callback, ud);
+}
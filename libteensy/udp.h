@@ -0,0 +1,60 @@
+#pragma once
+
+#include <stddef.h>
+#include <stdint.h>
+
+#include "tock.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_UDP 0x9000B
+
+/**
+ * Addresses are in network byte order, as `inet_addr` returns them.
+ */
+typedef uint32_t udp_addr_t;
+
+#define UDP_ADDR(a, b, c, d) \
+  ((udp_addr_t) (a) | (udp_addr_t) (b) << 8 | (udp_addr_t) (c) << 16 | (udp_addr_t) (d) << 24)
+
+#define UDP_BROADCAST UDP_ADDR(255, 255, 255, 255)
+
+typedef void (udp_receive_fn)(const uint8_t *payload, size_t len,
+                              udp_addr_t address, uint16_t port, void *ud);
+
+/**
+ * Binds `port`, or a free port if it is 0. Returns the port, or a negative
+ * error code.
+ */
+int udp_bind(uint16_t port);
+
+int udp_unbind(void);
+
+/**
+ * Sends `len` bytes from the bound port to `address` and `port`.
+ */
+int udp_send(udp_addr_t address, uint16_t port, const void *payload, size_t len);
+
+/**
+ * Starts receiving datagrams to the bound port into `buffer`. `callback` is
+ * called with each one, after which the buffer is free for the next.
+ */
+int udp_receive(uint8_t *buffer, size_t len, udp_receive_fn callback, void *ud);
+
+/**
+ * Reads the board's address, netmask and router. Returns `TOCK_EOFF` while
+ * it has no address. This removes any config callback.
+ */
+int udp_config(udp_addr_t *address, udp_addr_t *netmask, udp_addr_t *router);
+
+/**
+ * Calls `callback` with the address, netmask and router when the address
+ * changes, or with zeroes when it is lost.
+ */
+int udp_set_config_callback(subscribe_cb callback, void *ud);
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,36 @@
+#include "tock.h"
+#include "uptime.h"
+
+struct uptime_data {
+  bool fired;
+  uint32_t lo;
+  uint32_t hi;
+};
+
+static struct uptime_data result = { .fired = false };
+
+static void uptime_cb(int lo, int hi,
+                      __attribute__ ((unused)) int unused,
+                      void* ud) {
+  struct uptime_data* data = (struct uptime_data*) ud;
+  data->lo = (uint32_t) lo;
+  data->hi = (uint32_t) hi;
+  data->fired = true;
+}
+
+int uptime_seconds(void) {
+  return command(DRIVER_NUM_UPTIME, 2, 0, 0);
+}
+
+int uptime_us(uint64_t *us) {
+  int err = subscribe(DRIVER_NUM_UPTIME, 0, uptime_cb, &result);
+  if (err < 0) return err;
+
+  result.fired = false;
+  err = command(DRIVER_NUM_UPTIME, 1, 0, 0);
+  if (err < 0) return err;
+
+  yield_for(&result.fired);
+  *us = ((uint64_t) result.hi << 32) | result.lo;
+  return TOCK_SUCCESS;
+}
@@ -0,0 +1,23 @@
+#pragma once
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_UPTIME 0x90001
+
+/**
+ * Returns the number of whole seconds since boot, or a negative error code.
+ */
+int uptime_seconds(void);
+
+/**
+ * Reads the number of microseconds since boot. This does not wrap.
+ */
+int uptime_us(uint64_t *us);
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,55 @@
+#include "tock.h"
+#include "usbstorage.h"
+
+bool usbstorage_is_present(void) {
+  return command(DRIVER_NUM_USBSTORAGE, 1, 0, 0) == 1;
+}
+
+int usbstorage_sector_count(void) {
+  return command(DRIVER_NUM_USBSTORAGE, 2, 0, 0);
+}
+
+struct usbstorage_data {
+  bool fired;
+  int result;
+};
+
+static struct usbstorage_data result = { .fired = false };
+
+static void usbstorage_cb(int code,
+                          __attribute__ ((unused)) int unused1,
+                          __attribute__ ((unused)) int unused2,
+                          void* ud) {
+  struct usbstorage_data* data = (struct usbstorage_data*) ud;
+  data->result = code;
+  data->fired = true;
+}
+
+static int usbstorage_transfer(int cmd, uint32_t sector, uint8_t *buffer, uint32_t count) {
+  int err = subscribe(DRIVER_NUM_USBSTORAGE, 1, usbstorage_cb, &result);
+  if (err < 0) return err;
+
+  err = allow(DRIVER_NUM_USBSTORAGE, 0, buffer, count * USBSTORAGE_SECTOR_SIZE);
+  if (err < 0) return err;
+
+  result.fired = false;
+  err = command(DRIVER_NUM_USBSTORAGE, cmd, sector, count);
+  if (err == TOCK_SUCCESS) {
+    yield_for(&result.fired);
+    err = result.result;
+  }
+  allow(DRIVER_NUM_USBSTORAGE, 0, NULL, 0);
+  return err;
+}
+
+int usbstorage_read(uint32_t sector, uint8_t *buffer, uint32_t count) {
+  return usbstorage_transfer(3, sector, buffer, count);
+}
+
+int usbstorage_write(uint32_t sector, const uint8_t *buffer, uint32_t count) {
+  return usbstorage_transfer(4, sector, (uint8_t *) buffer, count);
+}
+
+int usbstorage_set_detect_callback(subscribe_cb callback, void *ud) {
+  return subscribe(DRIVER_NUM_USBSTORAGE, 0, callback, ud);
+}
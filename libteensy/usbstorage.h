@@ -0,0 +1,42 @@
+#pragma once
+
+#include <stdint.h>
+
+#include "tock.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DRIVER_NUM_USBSTORAGE 0x90009
+
+#define USBSTORAGE_SECTOR_SIZE 512
+
+/**
+ * Returns true if a USB stick is plugged into the USB host port and ready.
+ */
+bool usbstorage_is_present(void);
+
+/**
+ * Returns the number of sectors on the stick, or a negative error code.
+ */
+int usbstorage_sector_count(void);
+
+/**
+ * Reads `count` sectors starting at `sector`, waiting until they have been
+ * read. `buffer` must hold at least `count * USBSTORAGE_SECTOR_SIZE` bytes.
+ * Returns TOCK_EOFF if the stick is detached meanwhile.
+ */
+int usbstorage_read(uint32_t sector, uint8_t *buffer, uint32_t count);
+
+int usbstorage_write(uint32_t sector, const uint8_t *buffer, uint32_t count);
+
+/**
+ * Calls `callback` with 1 when a stick is attached and 0 when it is
+ * detached.
+ */
+int usbstorage_set_detect_callback(subscribe_cb callback, void *ud);
+
+#ifdef __cplusplus
+}
+#endif